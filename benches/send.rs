@@ -0,0 +1,79 @@
+//! Compares the CPU time spent per frame when copying channels behind a start
+//! code with sending them through a vectored write.
+//!
+//! Frames are sent to a pseudo terminal, so the timing of breaks and of the
+//! output itself does not reflect real hardware. Run with `cargo bench`.
+
+#[cfg(unix)]
+fn main() {
+    use std::ffi::CStr;
+    use std::{ptr, thread, time};
+
+    use dmx::DmxTransmitter;
+
+    const FRAMES: u32 = 1000;
+    const UNIVERSES: usize = 4;
+
+    // thread CPU time, excluding the time spent sleeping between frames
+    fn cpu_time() -> time::Duration {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) };
+        time::Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+    }
+
+    let mut ports = Vec::new();
+    for _ in 0..UNIVERSES {
+        let (mut master, mut slave) = (0, 0);
+        let mut name = [0 as libc::c_char; 64];
+        let rv = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                name.as_mut_ptr(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(rv, 0, "could not open pseudo terminal");
+
+        // discard everything written to the terminal
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            while unsafe { libc::read(master, buf.as_mut_ptr() as *mut _, buf.len()) } > 0 {}
+        });
+
+        let name = unsafe { CStr::from_ptr(name.as_ptr()) }.to_str().unwrap();
+        ports.push(dmx::open_serial(name).unwrap());
+    }
+
+    let channels = [0x80; 512];
+    let mut run = |label: &str, vectored: bool| {
+        let start = cpu_time();
+
+        for _ in 0..FRAMES {
+            for port in ports.iter_mut() {
+                if vectored {
+                    port.send_dmx_packet(&channels).unwrap();
+                } else {
+                    let mut packet = [0; 513];
+                    packet[1..].copy_from_slice(&channels);
+                    port.send_raw_dmx_packet(&packet).unwrap();
+                }
+            }
+        }
+
+        let frames = FRAMES * UNIVERSES as u32;
+        println!("{:>8}: {:?} CPU time per frame", label, (cpu_time() - start) / frames);
+    };
+
+    run("copy", false);
+    run("vectored", true);
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("this benchmark requires pseudo terminals");
+}
@@ -0,0 +1,115 @@
+#ifndef DMX_H
+#define DMX_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef struct DmxPort DmxPort;
+typedef struct DmxRefresher DmxRefresher;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Returns a message describing the last failure on the calling thread.
+ *
+ * Returns null if nothing failed yet. The message stays valid until the
+ * next failure on the same thread.
+ */
+const char *dmx_last_error(void);
+
+/**
+ * Opens a serial port for sending DMX.
+ *
+ * Returns null on failure.
+ *
+ * # Safety
+ *
+ * `path` must be null or a NUL-terminated string.
+ */
+DmxPort *dmx_open(const char *path);
+
+/**
+ * Sends a packet with the default start code, followed by `len` channel
+ * values.
+ *
+ * # Safety
+ *
+ * `port` must be null or returned by `dmx_open`, and not be used by another
+ * thread at the same time. `data` must point to `len` bytes.
+ */
+int dmx_send_packet(DmxPort *port, const uint8_t *data, size_t len);
+
+/**
+ * Closes a port. Does nothing if `port` is null.
+ *
+ * # Safety
+ *
+ * `port` must be null or returned by `dmx_open`, and not be used afterwards.
+ */
+void dmx_close(DmxPort *port);
+
+/**
+ * Starts sending all 512 channels through `port` from a background thread,
+ * `fps` times per second.
+ *
+ * Takes ownership of the port, even if starting fails. Channels start out
+ * at zero. Returns null on failure.
+ *
+ * # Safety
+ *
+ * `port` must be null or returned by `dmx_open`, and not be used afterwards.
+ */
+DmxRefresher *dmx_refresher_start(DmxPort *port, float fps);
+
+/**
+ * Sets channel `channel`, from 1 to 512, to `value`.
+ *
+ * May be called from any thread.
+ *
+ * # Safety
+ *
+ * `refresher` must be null or returned by `dmx_refresher_start`, and not be
+ * stopped yet.
+ */
+int dmx_refresher_set_channel(const DmxRefresher *refresher, uint16_t channel, uint8_t value);
+
+/**
+ * Sets `len` consecutive channels, starting at channel `start`.
+ *
+ * Values that would end up beyond channel 512 are ignored. May be called
+ * from any thread.
+ *
+ * # Safety
+ *
+ * `refresher` must be null or returned by `dmx_refresher_start`, and not be
+ * stopped yet. `values` must point to `len` bytes.
+ */
+int dmx_refresher_set_channels(const DmxRefresher *refresher,
+                               uint16_t start,
+                               const uint8_t *values,
+                               size_t len);
+
+/**
+ * Stops refreshing and closes the port.
+ *
+ * Fails if sending failed in the meantime, which also stopped refreshing.
+ *
+ * # Safety
+ *
+ * `refresher` must be null or returned by `dmx_refresher_start`, and not be
+ * used afterwards.
+ */
+int dmx_refresher_stop(DmxRefresher *refresher);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* DMX_H */
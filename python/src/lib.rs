@@ -0,0 +1,281 @@
+//! Python bindings.
+//!
+//! Builds the `dmx` Python module, e.g. through `maturin develop` or
+//! `pip install ./python`:
+//!
+//! ```python
+//! import dmx
+//!
+//! port = dmx.DmxPort("/dev/ttyUSB0")
+//! port.send_packet([0xff, 0x80, 0x00])
+//!
+//! # keep sending the universe from a background thread
+//! refresher = dmx.Refresher(port, fps=40.0)
+//! refresher[1] = 255
+//! refresher.set_channels(2, b"\x80\x00")
+//! refresher.stop()
+//! ```
+//!
+//! Channels are numbered from 1 to 512. The GIL is released while sending
+//! and while waiting for a refresher to stop, so other Python threads keep
+//! running. Refreshers send from a thread of their own, which never needs
+//! the GIL. I/O errors are raised as `OSError`, invalid channels and values
+//! as `ValueError`, other failures as `dmx.DmxError`.
+
+use std::convert::TryFrom;
+use std::sync::Mutex;
+use std::time;
+
+use dmx_rs::{DmxAddress, DmxPort, DmxRefresher, DmxTransmitter, DmxUniverse, Error};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyOSError, PyTimeoutError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+create_exception!(dmx, DmxError, PyException, "Failure sending DMX.");
+
+fn to_py(e: Error) -> PyErr {
+    match e {
+        Error::Io(e) => PyOSError::new_err(e.to_string()),
+        Error::Timeout => PyTimeoutError::new_err(e.to_string()),
+        Error::InvalidParameter(_) => PyValueError::new_err(e.to_string()),
+        e => DmxError::new_err(e.to_string()),
+    }
+}
+
+fn address(channel: u16) -> PyResult<DmxAddress> {
+    DmxAddress::try_from(channel).map_err(|e| to_py(e.into()))
+}
+
+fn closed() -> PyErr {
+    PyValueError::new_err("port is closed or used by a refresher")
+}
+
+fn duration(seconds: f64) -> PyResult<time::Duration> {
+    time::Duration::try_from_secs_f64(seconds)
+        .map_err(|_| PyValueError::new_err("duration must not be negative"))
+}
+
+/// A serial port with DMX support.
+#[pyclass(name = "DmxPort", module = "dmx")]
+struct PyDmxPort {
+    // taken by a refresher; only accessed through `get_mut`, the mutex
+    // makes the class shareable between Python threads
+    port: Mutex<Option<DmxPort>>,
+}
+
+impl PyDmxPort {
+    fn port(&mut self) -> PyResult<&mut DmxPort> {
+        self.port_mut().as_mut().ok_or_else(closed)
+    }
+
+    fn port_mut(&mut self) -> &mut Option<DmxPort> {
+        self.port.get_mut().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[pymethods]
+impl PyDmxPort {
+    /// Opens the serial port at `path`.
+    #[new]
+    fn new(path: &str) -> PyResult<PyDmxPort> {
+        let port = dmx_rs::open_serial(path).map_err(to_py)?;
+
+        Ok(PyDmxPort {
+            port: Mutex::new(Some(port)),
+        })
+    }
+
+    /// Sends a packet with the default start code, followed by the channel
+    /// values in `data`.
+    fn send_packet(&mut self, py: Python<'_>, data: Vec<u8>) -> PyResult<()> {
+        let port = self.port()?;
+
+        py.allow_threads(|| port.send_dmx_packet(&data))
+            .map_err(to_py)
+    }
+
+    /// Sends all channels of `universe`.
+    fn send_universe(&mut self, py: Python<'_>, universe: &PyUniverse) -> PyResult<()> {
+        let port = self.port()?;
+        let universe = &universe.universe;
+
+        py.allow_threads(|| port.send_universe(universe))
+            .map_err(to_py)
+    }
+
+    /// Closes the port.
+    fn close(&mut self) {
+        *self.port_mut() = None;
+    }
+
+    fn __repr__(&mut self) -> &'static str {
+        match self.port_mut() {
+            Some(_) => "<dmx.DmxPort>",
+            None => "<dmx.DmxPort (closed)>",
+        }
+    }
+}
+
+/// The values of all 512 channels, with fades.
+#[pyclass(name = "Universe", module = "dmx")]
+#[derive(Clone)]
+struct PyUniverse {
+    universe: DmxUniverse,
+}
+
+#[pymethods]
+impl PyUniverse {
+    /// Create a universe with all channels set to zero.
+    #[new]
+    fn new() -> PyUniverse {
+        PyUniverse {
+            universe: DmxUniverse::new(),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.universe.channels().len()
+    }
+
+    fn __getitem__(&self, channel: u16) -> PyResult<u8> {
+        Ok(self.universe.get(address(channel)?))
+    }
+
+    fn __setitem__(&mut self, channel: u16, value: u8) -> PyResult<()> {
+        self.universe.set(address(channel)?, value);
+        Ok(())
+    }
+
+    /// Sets consecutive channels, starting at channel `start`.
+    fn set_range(&mut self, start: u16, values: Vec<u8>) -> PyResult<()> {
+        self.universe.set_range(address(start)?, &values);
+        Ok(())
+    }
+
+    /// Sets all channels to `value`.
+    fn fill(&mut self, value: u8) {
+        self.universe.fill(value);
+    }
+
+    /// Sets all channels to zero.
+    fn blackout(&mut self) {
+        self.universe.blackout();
+    }
+
+    /// Fades a channel to `value` over `seconds`, advanced by `tick`.
+    fn fade_channel(&mut self, channel: u16, value: u8, seconds: f64) -> PyResult<()> {
+        self.universe
+            .fade_channel(address(channel)?, value, duration(seconds)?);
+        Ok(())
+    }
+
+    /// Advances fades in progress by `seconds`.
+    fn tick(&mut self, seconds: f64) -> PyResult<()> {
+        self.universe.tick(duration(seconds)?);
+        Ok(())
+    }
+
+    /// Returns whether any fades are in progress.
+    fn is_fading(&self) -> bool {
+        self.universe.is_fading()
+    }
+
+    /// Returns the values of all channels.
+    fn channels<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.universe.channels())
+    }
+}
+
+/// Sends a universe from a background thread at a fixed frame rate.
+#[pyclass(name = "Refresher", module = "dmx")]
+struct PyRefresher {
+    // none once stopped
+    refresher: Option<DmxRefresher>,
+}
+
+impl PyRefresher {
+    fn refresher(&self) -> PyResult<&DmxRefresher> {
+        self.refresher
+            .as_ref()
+            .ok_or_else(|| PyValueError::new_err("refresher is stopped"))
+    }
+}
+
+#[pymethods]
+impl PyRefresher {
+    /// Starts sending through `port`, `fps` times per second.
+    ///
+    /// The port is closed afterwards, it is only used by the refresher.
+    #[new]
+    #[pyo3(signature = (port, fps = 40.0))]
+    fn new(mut port: PyRefMut<'_, PyDmxPort>, fps: f32) -> PyResult<PyRefresher> {
+        if fps.is_nan() || fps <= 0.0 {
+            return Err(PyValueError::new_err("frame rate must be positive"));
+        }
+        let port = port.port_mut().take().ok_or_else(closed)?;
+
+        Ok(PyRefresher {
+            refresher: Some(DmxRefresher::with_frame_rate(port, fps)),
+        })
+    }
+
+    fn __getitem__(&self, channel: u16) -> PyResult<u8> {
+        let n = address(channel)?;
+        Ok(self.refresher()?.handle().snapshot()[usize::from(n.get() - 1)])
+    }
+
+    fn __setitem__(&self, channel: u16, value: u8) -> PyResult<()> {
+        self.refresher()?.set_channel(address(channel)?, value);
+        Ok(())
+    }
+
+    /// Sets consecutive channels, starting at channel `start`.
+    ///
+    /// Values that would end up beyond channel 512 are ignored.
+    fn set_channels(&self, start: u16, values: Vec<u8>) -> PyResult<()> {
+        self.refresher()?.set_channels(address(start)?, &values);
+        Ok(())
+    }
+
+    /// Returns a copy of the universe being sent.
+    fn universe(&self) -> PyResult<PyUniverse> {
+        Ok(PyUniverse {
+            universe: self.refresher()?.handle().universe(),
+        })
+    }
+
+    /// Replaces the universe being sent, including fades in progress.
+    fn set_universe(&self, universe: &PyUniverse) -> PyResult<()> {
+        let universe = universe.universe.clone();
+        self.refresher()?.handle().update(|u| *u = universe);
+        Ok(())
+    }
+
+    /// Returns whether the background thread is still sending.
+    ///
+    /// Sending stops early if an error occurs, which is raised by `stop`.
+    fn is_running(&self) -> bool {
+        self.refresher.as_ref().is_some_and(DmxRefresher::is_running)
+    }
+
+    /// Stops refreshing and closes the port.
+    ///
+    /// Raises the error that stopped sending early, if any. Does nothing if
+    /// already stopped.
+    fn stop(&mut self, py: Python<'_>) -> PyResult<()> {
+        match self.refresher.take() {
+            Some(refresher) => py.allow_threads(|| refresher.stop()).map_err(to_py),
+            None => Ok(()),
+        }
+    }
+}
+
+#[pymodule]
+fn dmx(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("DmxError", m.py().get_type::<DmxError>())?;
+    m.add_class::<PyDmxPort>()?;
+    m.add_class::<PyUniverse>()?;
+    m.add_class::<PyRefresher>()?;
+    Ok(())
+}
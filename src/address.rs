@@ -0,0 +1,135 @@
+//! Channel addresses.
+
+use core::convert::TryFrom;
+use core::fmt;
+
+use crate::packet::MAX_CHANNELS;
+
+/// A DMX address, i.e. the number of a channel.
+///
+/// Addresses range from 1 to 512, as printed on fixtures and consoles. The
+/// zero-based offset into channel buffers is available through `index`.
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use dmx::DmxAddress;
+///
+/// let address = DmxAddress::try_from(17u16).unwrap();
+/// assert_eq!(address.index(), 16);
+/// assert!(DmxAddress::new(0).is_none());
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "u16", into = "u16")
+)]
+pub struct DmxAddress(u16);
+
+impl DmxAddress {
+    /// The first address, channel 1.
+    pub const MIN: DmxAddress = DmxAddress(1);
+
+    /// The last address, channel 512.
+    pub const MAX: DmxAddress = DmxAddress(MAX_CHANNELS as u16);
+
+    /// Create an address from a channel number.
+    ///
+    /// Returns `None` if `n` is not in the range of 1 to 512.
+    #[inline]
+    pub const fn new(n: u16) -> Option<DmxAddress> {
+        if n == 0 || n > MAX_CHANNELS as u16 {
+            return None;
+        }
+
+        Some(DmxAddress(n))
+    }
+
+    /// Create an address from a zero-based buffer index.
+    ///
+    /// Returns `None` if `index` is 512 or above.
+    #[inline]
+    pub const fn from_index(index: usize) -> Option<DmxAddress> {
+        if index >= MAX_CHANNELS {
+            return None;
+        }
+
+        Some(DmxAddress(index as u16 + 1))
+    }
+
+    /// Returns the channel number.
+    #[inline]
+    pub const fn get(self) -> u16 {
+        self.0
+    }
+
+    /// Returns the zero-based buffer index.
+    #[inline]
+    pub const fn index(self) -> usize {
+        self.0 as usize - 1
+    }
+
+    /// Returns the address `n` channels further, e.g. to address a channel
+    /// relative to a fixture's start address.
+    ///
+    /// Returns `None` if the result would be beyond channel 512.
+    #[inline]
+    pub fn checked_add(self, n: u16) -> Option<DmxAddress> {
+        self.0.checked_add(n).and_then(DmxAddress::new)
+    }
+}
+
+impl TryFrom<u16> for DmxAddress {
+    type Error = AddressError;
+
+    #[inline]
+    fn try_from(n: u16) -> Result<DmxAddress, AddressError> {
+        DmxAddress::new(n).ok_or(AddressError(n.into()))
+    }
+}
+
+impl TryFrom<usize> for DmxAddress {
+    type Error = AddressError;
+
+    #[inline]
+    fn try_from(n: usize) -> Result<DmxAddress, AddressError> {
+        if n > usize::from(u16::MAX) {
+            return Err(AddressError(n));
+        }
+
+        DmxAddress::try_from(n as u16)
+    }
+}
+
+impl From<DmxAddress> for u16 {
+    #[inline]
+    fn from(address: DmxAddress) -> u16 {
+        address.0
+    }
+}
+
+impl From<DmxAddress> for usize {
+    #[inline]
+    fn from(address: DmxAddress) -> usize {
+        address.0.into()
+    }
+}
+
+impl fmt::Display for DmxAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A channel number is outside the range of 1 to 512; holds the number.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AddressError(pub usize);
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "channel {} out of range 1-512", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AddressError {}
@@ -0,0 +1,267 @@
+//! Art-Net input.
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::mpsc;
+use std::{cmp, io, thread};
+
+use super::{opcode, ArtNetNode, PortAddress, ARTNET_PORT, OP_DMX};
+use crate::{DmxReceiver, Error, Result};
+
+// ID, opcode, version, sequence, physical, port-address, length
+const DMX_HEADER_LEN: usize = 18;
+
+// sequence numbers up to this far behind the previous one are considered
+// late, larger jumps backwards a restarted sender
+const SEQUENCE_WINDOW: i8 = 20;
+
+/// A decoded `ArtDmx` packet.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ArtDmx<'a> {
+    /// Port-address the data is sent to.
+    pub address: PortAddress,
+    /// Sequence number, zero if sequencing is disabled.
+    pub sequence: u8,
+    /// Physical input port of the sender.
+    pub physical: u8,
+    /// Channel data, starting at channel 1.
+    pub channels: &'a [u8],
+}
+
+/// Decodes an `ArtDmx` packet.
+///
+/// Returns `None` if the packet is not a valid `ArtDmx` packet.
+pub fn decode_dmx(packet: &[u8]) -> Option<ArtDmx<'_>> {
+    if opcode(packet) != Some(OP_DMX) || packet.len() < DMX_HEADER_LEN {
+        return None;
+    }
+
+    let address = PortAddress::from_u16(u16::from(packet[15]) << 8 | u16::from(packet[14]))?;
+    let len = usize::from(u16::from_be_bytes([packet[16], packet[17]]));
+    let channels = &packet[DMX_HEADER_LEN..];
+
+    Some(ArtDmx {
+        address,
+        sequence: packet[12],
+        physical: packet[13],
+        channels: &channels[..cmp::min(len, cmp::min(channels.len(), 512))],
+    })
+}
+
+/// A frame received by an `ArtNetReceiver`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArtDmxFrame {
+    /// Address of the sender.
+    pub source: SocketAddr,
+    /// Port-address the data was sent to.
+    pub address: PortAddress,
+    /// Channel data, starting at channel 1.
+    pub channels: Vec<u8>,
+}
+
+/// Art-Net DMX receiver.
+///
+/// Listens for `ArtDmx` packets on the Art-Net port and returns those sent
+/// to the port-addresses subscribed to. Packets arriving out of order are
+/// dropped, based on their sequence numbers.
+///
+/// As a `DmxReceiver`, frames are returned as packets with the default start
+/// code, regardless of their port-address; subscribing to a single
+/// port-address forwards exactly one universe.
+///
+/// Given an `ArtNetNode` through `set_node`, the receiver answers polls and
+/// applies `ArtAddress` packets while receiving. The port-addresses of the
+/// node's outputs are subscribed to, following changes made by consoles.
+///
+/// ## Example
+///
+/// ```no_run
+/// use dmx::{DmxReceiver, DmxTransmitter};
+/// use dmx::artnet::{ArtNetReceiver, PortAddress};
+///
+/// let mut input = ArtNetReceiver::new(&[PortAddress::new(0, 0, 1).unwrap()]).unwrap();
+/// let mut dmx_port = dmx::open_serial("/dev/ttyUSB0").unwrap();
+///
+/// loop {
+///     let packet = input.recv_dmx_packet().unwrap();
+///     dmx_port.send_raw_dmx_packet(&packet).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ArtNetReceiver {
+    socket: UdpSocket,
+    addresses: Vec<PortAddress>,
+    // last sequence number by sender and port-address
+    sequences: BTreeMap<(IpAddr, PortAddress), u8>,
+    node: Option<ArtNetNode>,
+    buf: [u8; DMX_HEADER_LEN + 512],
+}
+
+impl ArtNetReceiver {
+    /// Create a receiver listening on the Art-Net port of all interfaces.
+    ///
+    /// Only frames sent to one of `addresses` are received; an empty slice
+    /// receives all frames.
+    #[inline]
+    pub fn new(addresses: &[PortAddress]) -> io::Result<ArtNetReceiver> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, ARTNET_PORT))?;
+
+        Ok(ArtNetReceiver::with_socket(socket, addresses))
+    }
+
+    /// Create a receiver from a bound socket.
+    ///
+    /// See `new`.
+    pub fn with_socket(socket: UdpSocket, addresses: &[PortAddress]) -> ArtNetReceiver {
+        ArtNetReceiver {
+            socket,
+            addresses: addresses.to_vec(),
+            sequences: BTreeMap::new(),
+            node: None,
+            buf: [0; DMX_HEADER_LEN + 512],
+        }
+    }
+
+    /// Subscribes to another port-address.
+    ///
+    /// Has no effect if the receiver receives all frames.
+    #[inline]
+    pub fn subscribe(&mut self, address: PortAddress) {
+        if !self.addresses.is_empty() && !self.addresses.contains(&address) {
+            self.addresses.push(address);
+        }
+    }
+
+    /// Makes the receiver act as `node`, subscribing to its outputs.
+    ///
+    /// Replaces the node set before, if any.
+    pub fn set_node(&mut self, node: ArtNetNode) {
+        for &address in &node.config().outputs {
+            self.subscribe(address);
+        }
+        self.node = Some(node);
+    }
+
+    /// Returns the node the receiver acts as.
+    #[inline]
+    pub fn node(&self) -> Option<&ArtNetNode> {
+        self.node.as_ref()
+    }
+
+    /// Returns the node the receiver acts as, for changing it.
+    #[inline]
+    pub fn node_mut(&mut self) -> Option<&mut ArtNetNode> {
+        self.node.as_mut()
+    }
+
+    /// Returns the underlying socket, e.g. to set a read timeout.
+    #[inline]
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Blocking receive the next frame.
+    pub fn recv_frame(&mut self) -> Result<ArtDmxFrame> {
+        loop {
+            let (len, source) = self.socket.recv_from(&mut self.buf).map_err(Error::from_read)?;
+
+            let dmx = match decode_dmx(&self.buf[..len]) {
+                Some(dmx) => dmx,
+                None => {
+                    self.handle_node_packet(len, source)?;
+                    continue;
+                }
+            };
+
+            if !self.addresses.is_empty() && !self.addresses.contains(&dmx.address) {
+                continue;
+            }
+
+            if dmx.sequence != 0 {
+                let key = (source.ip(), dmx.address);
+
+                // wrapping from 255 to 1 yields a small positive difference
+                if let Some(&last) = self.sequences.get(&key) {
+                    let diff = dmx.sequence.wrapping_sub(last) as i8;
+                    if diff <= 0 && diff > -SEQUENCE_WINDOW {
+                        continue;
+                    }
+                }
+                self.sequences.insert(key, dmx.sequence);
+            }
+
+            return Ok(ArtDmxFrame {
+                source,
+                address: dmx.address,
+                channels: dmx.channels.to_vec(),
+            });
+        }
+    }
+
+    /// Hands a packet other than `ArtDmx` to the node, if any.
+    fn handle_node_packet(&mut self, len: usize, source: SocketAddr) -> Result<()> {
+        let node = match self.node {
+            Some(ref mut node) => node,
+            None => return Ok(()),
+        };
+
+        let before = node.config().outputs.clone();
+        node.handle(&self.socket, &self.buf[..len], source)?;
+
+        let after = &node.config().outputs;
+        if !self.addresses.is_empty() && after != &before {
+            self.addresses.retain(|a| !before.contains(a));
+            for &address in after {
+                if !self.addresses.contains(&address) {
+                    self.addresses.push(address);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Receives frames, calling `f` for each one.
+    ///
+    /// Only returns if receiving fails.
+    pub fn run<F: FnMut(ArtDmxFrame)>(&mut self, mut f: F) -> Result<()> {
+        loop {
+            f(self.recv_frame()?);
+        }
+    }
+
+    /// Moves the receiver into a background thread, delivering frames
+    /// through a channel.
+    ///
+    /// The thread exits once the returned receiver is dropped, or if
+    /// receiving fails.
+    pub fn into_channel(mut self) -> mpsc::Receiver<ArtDmxFrame> {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            while let Ok(frame) = self.recv_frame() {
+                if tx.send(frame).is_err() {
+                    break;
+                }
+            }
+        });
+
+        rx
+    }
+}
+
+impl DmxReceiver for ArtNetReceiver {
+    fn recv_dmx_packet_into(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let frame = self.recv_frame()?;
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let len = cmp::min(frame.channels.len(), buf.len() - 1);
+        buf[0] = 0x00;
+        buf[1..=len].copy_from_slice(&frame.channels[..len]);
+
+        Ok(len + 1)
+    }
+}
@@ -0,0 +1,454 @@
+//! Art-Net support.
+//!
+//! [Art-Net](https://art-net.org.uk) transports DMX data over UDP. Each
+//! packet starts with the `Art-Net` identifier followed by an opcode; DMX
+//! data is carried by `ArtDmx` packets, addressed to one of 32,768 universes
+//! using a 15-bit *port-address* made up of a net, sub-net and universe
+//! number.
+//!
+//! Nodes on the network can be found through `discover`, which broadcasts an
+//! `ArtPoll` and collects the nodes' replies. Received DMX data is available
+//! through `ArtNetReceiver`, which can also act as an `ArtNetNode`, letting
+//! consoles find it, rename it and move its ports through `ArtAddress`.
+//!
+//! Several universes making up a single surface, such as a pixel-mapped
+//! wall, are sent through `ArtNetSource`, which follows each frame with an
+//! `ArtSync` so nodes output them simultaneously.
+//!
+//! `ArtRdmGateway` makes the RDM devices on a DMX line accessible to
+//! consoles on the network, answering their requests for the table of
+//! devices and tunneling `ArtRdm` packets to and from the line.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use dmx::DmxTransmitter;
+//! use dmx::artnet::{ArtNetTransmitter, PortAddress};
+//!
+//! let address = PortAddress::new(0, 0, 1).unwrap();
+//! let mut node = ArtNetTransmitter::new("10.0.0.20:6454", address).unwrap();
+//!
+//! node.send_dmx_packet(&[0xff, 0x00, 0x80]).unwrap();
+//! ```
+
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::{cmp, fmt, io};
+
+use crate::{DmxTransmitter, Error, Result, SocketOptions};
+
+mod input;
+mod node;
+mod poll;
+mod rdm;
+mod sync;
+
+pub use self::input::{decode_dmx, ArtDmx, ArtDmxFrame, ArtNetReceiver};
+pub use self::node::{
+    decode_address, encode_address, AddressCommand, ArtAddress, ArtNetNode, Indicators,
+    NodeConfig, SwitchChange, OP_ADDRESS,
+};
+pub use self::poll::{
+    decode_poll_reply, discover, discover_on, encode_poll, encode_poll_reply, ArtNode, OP_POLL,
+    OP_POLL_REPLY,
+};
+pub use self::rdm::{
+    decode_rdm, decode_tod_data, decode_tod_request, encode_rdm, encode_tod_data, ArtRdm,
+    ArtRdmGateway, ArtTodData, MAX_TOD_UIDS, OP_RDM, OP_TOD_CONTROL, OP_TOD_DATA, OP_TOD_REQUEST,
+};
+pub use self::sync::{encode_sync, ArtNetSource, OP_SYNC};
+
+/// UDP port used by Art-Net.
+pub const ARTNET_PORT: u16 = 6454;
+
+/// Art-Net protocol revision implemented.
+pub const PROTOCOL_VERSION: u16 = 14;
+
+/// Packet identifier, present at the start of every Art-Net packet.
+pub const ID: &[u8; 8] = b"Art-Net\0";
+
+/// Opcode of `ArtDmx` packets.
+pub const OP_DMX: u16 = 0x5000;
+
+// ID, opcode, version, sequence, physical, port-address, length
+const DMX_HEADER_LEN: usize = 18;
+
+/// An Art-Net port-address.
+///
+/// Consists of a 7-bit net, a 4-bit sub-net and a 4-bit universe number.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct PortAddress(u16);
+
+impl PortAddress {
+    /// Create a port-address from its components.
+    ///
+    /// Returns `None` if any component is out of range.
+    #[inline]
+    pub fn new(net: u8, subnet: u8, universe: u8) -> Option<PortAddress> {
+        if net > 0x7f || subnet > 0x0f || universe > 0x0f {
+            return None;
+        }
+
+        Some(PortAddress(
+            u16::from(net) << 8 | u16::from(subnet) << 4 | u16::from(universe),
+        ))
+    }
+
+    /// Create a port-address from its 15-bit numeric representation.
+    ///
+    /// Returns `None` if `value` exceeds 15 bits.
+    #[inline]
+    pub fn from_u16(value: u16) -> Option<PortAddress> {
+        if value > 0x7fff {
+            return None;
+        }
+
+        Some(PortAddress(value))
+    }
+
+    /// Returns the 15-bit numeric representation.
+    #[inline]
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    /// Returns the net.
+    #[inline]
+    pub fn net(&self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    /// Returns the sub-net.
+    #[inline]
+    pub fn subnet(&self) -> u8 {
+        (self.0 >> 4) as u8 & 0x0f
+    }
+
+    /// Returns the universe.
+    #[inline]
+    pub fn universe(&self) -> u8 {
+        self.0 as u8 & 0x0f
+    }
+
+    /// Returns the low byte, combining sub-net and universe.
+    #[inline]
+    pub fn sub_uni(&self) -> u8 {
+        self.0 as u8
+    }
+}
+
+impl fmt::Display for PortAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.net(), self.subnet(), self.universe())
+    }
+}
+
+/// Writes the common header of all Art-Net packets into `buf`.
+///
+/// Returns the number of bytes written.
+pub(crate) fn write_header(buf: &mut [u8], opcode: u16) -> usize {
+    buf[..8].copy_from_slice(ID);
+    buf[8..10].copy_from_slice(&opcode.to_le_bytes());
+    10
+}
+
+/// Returns the sequence number following `sequence`.
+///
+/// Sequence numbers wrap from 255 to 1, zero means sequencing is disabled.
+#[inline]
+fn next_sequence(sequence: u8) -> u8 {
+    match sequence {
+        0 => 0,
+        0xff => 1,
+        n => n + 1,
+    }
+}
+
+/// Returns the opcode of a packet, if it is a valid Art-Net packet.
+pub fn opcode(packet: &[u8]) -> Option<u16> {
+    if packet.len() < 10 || &packet[..8] != ID {
+        return None;
+    }
+
+    Some(u16::from_le_bytes([packet[8], packet[9]]))
+}
+
+/// Encodes an `ArtDmx` packet into `buf`.
+///
+/// `channels` is padded to an even length of at least two, as required by
+/// the protocol, and truncated to 512 channels. Returns the length of the
+/// packet; `buf` must hold at least 530 bytes.
+pub fn encode_dmx(
+    buf: &mut [u8],
+    address: PortAddress,
+    sequence: u8,
+    physical: u8,
+    channels: &[u8],
+) -> usize {
+    let count = cmp::min(channels.len(), 512);
+    let len = cmp::max(count + (count & 1), 2);
+
+    write_header(buf, OP_DMX);
+    buf[10..12].copy_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+    buf[12] = sequence;
+    buf[13] = physical;
+    buf[14] = address.sub_uni();
+    buf[15] = address.net();
+    buf[16..18].copy_from_slice(&(len as u16).to_be_bytes());
+
+    buf[DMX_HEADER_LEN..(DMX_HEADER_LEN + count)].copy_from_slice(&channels[..count]);
+    for v in &mut buf[(DMX_HEADER_LEN + count)..(DMX_HEADER_LEN + len)] {
+        *v = 0;
+    }
+
+    DMX_HEADER_LEN + len
+}
+
+/// Art-Net DMX transmitter.
+///
+/// Sends `ArtDmx` packets to a node via UDP. As Art-Net has no notion of
+/// breaks, `send_break` does nothing and only complete packets with the
+/// default start code can be sent.
+#[derive(Debug)]
+pub struct ArtNetTransmitter {
+    socket: UdpSocket,
+    target: SocketAddr,
+    address: PortAddress,
+    physical: u8,
+    // next sequence number, zero if sequencing is disabled
+    sequence: u8,
+    buf: [u8; DMX_HEADER_LEN + 512],
+}
+
+impl ArtNetTransmitter {
+    /// Create a transmitter sending to a specific node.
+    ///
+    /// `target` usually is the node's IP address with port 6454. Broadcast
+    /// addresses are allowed.
+    #[inline]
+    pub fn new<A: ToSocketAddrs>(target: A, address: PortAddress) -> io::Result<ArtNetTransmitter> {
+        ArtNetTransmitter::with_options(target, address, &SocketOptions::default())
+    }
+
+    /// Create a transmitter sending to a node through a specific interface.
+    ///
+    /// See `SocketOptions` for how packets are kept on that interface.
+    pub fn with_options<A: ToSocketAddrs>(
+        target: A,
+        address: PortAddress,
+        options: &SocketOptions,
+    ) -> io::Result<ArtNetTransmitter> {
+        let target = target
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no target address"))?;
+
+        let socket = options.bind()?;
+
+        Ok(ArtNetTransmitter {
+            socket,
+            target,
+            address,
+            physical: 0,
+            sequence: 1,
+            buf: [0; DMX_HEADER_LEN + 512],
+        })
+    }
+
+    /// Create a transmitter broadcasting to all nodes on the local network.
+    #[inline]
+    pub fn broadcast(address: PortAddress) -> io::Result<ArtNetTransmitter> {
+        ArtNetTransmitter::new((Ipv4Addr::BROADCAST, ARTNET_PORT), address)
+    }
+
+    /// Returns the port-address packets are sent to.
+    #[inline]
+    pub fn address(&self) -> PortAddress {
+        self.address
+    }
+
+    /// Sets the port-address packets are sent to.
+    #[inline]
+    pub fn set_address(&mut self, address: PortAddress) {
+        self.address = address;
+    }
+
+    /// Returns the target socket address.
+    #[inline]
+    pub fn target(&self) -> SocketAddr {
+        self.target
+    }
+
+    /// Sets the physical input port reported in packets.
+    ///
+    /// This is informational only and defaults to zero.
+    #[inline]
+    pub fn set_physical(&mut self, physical: u8) {
+        self.physical = physical;
+    }
+
+    /// Enables or disables sequence numbers.
+    ///
+    /// Sequence numbers allow receivers to reorder packets and are enabled
+    /// by default.
+    #[inline]
+    pub fn set_sequence_enabled(&mut self, enabled: bool) {
+        self.sequence = if enabled { 1 } else { 0 };
+    }
+
+    /// Sends channel data as an `ArtDmx` packet.
+    pub fn send_channels(&mut self, channels: &[u8]) -> Result<()> {
+        let len = encode_dmx(
+            &mut self.buf,
+            self.address,
+            self.sequence,
+            self.physical,
+            channels,
+        );
+        self.socket.send_to(&self.buf[..len], self.target)?;
+        self.sequence = next_sequence(self.sequence);
+
+        Ok(())
+    }
+
+    /// Sends an `ArtSync` packet to the target.
+    ///
+    /// When several transmitters send to the same node, sending an `ArtSync`
+    /// through one of them after all have sent their universes makes the
+    /// node output them simultaneously; see `ArtNetSource`.
+    pub fn send_sync(&mut self) -> Result<()> {
+        let len = encode_sync(&mut self.buf);
+        self.socket.send_to(&self.buf[..len], self.target)?;
+        Ok(())
+    }
+}
+
+impl DmxTransmitter for ArtNetTransmitter {
+    type Error = Error;
+
+    #[inline]
+    fn send_break(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn send_raw_data(&mut self, _data: &[u8]) -> Result<()> {
+        Err(Error::Unsupported("Art-Net can only transmit complete packets"))
+    }
+
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<()> {
+        match data.first() {
+            Some(&0x00) => self.send_channels(&data[1..]),
+            Some(&code) => Err(Error::UnsupportedStartCode(code)),
+            None => Err(Error::EmptyPacket),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dmx_packet_layout() {
+        let address = PortAddress::new(0x12, 0x3, 0x4).unwrap();
+        let mut buf = [0; 530];
+        let len = encode_dmx(&mut buf, address, 7, 2, &[0xff, 0x80, 0x40, 0x20]);
+        assert_eq!(len, 22);
+
+        // ID, opcode low byte first, version 14, sequence, physical,
+        // sub-net and universe, net, length high byte first, channels
+        let mut expected = b"Art-Net\0".to_vec();
+        expected.extend_from_slice(&[0x00, 0x50, 0x00, 0x0e, 7, 2, 0x34, 0x12, 0x00, 0x04]);
+        expected.extend_from_slice(&[0xff, 0x80, 0x40, 0x20]);
+
+        assert_eq!(buf[..len], expected[..]);
+    }
+
+    #[test]
+    fn dmx_data_is_padded_to_an_even_length() {
+        let mut buf = [0xaa; 530];
+        let len = encode_dmx(&mut buf, PortAddress::default(), 0, 0, &[1, 2, 3]);
+        assert_eq!(len, 22);
+        assert_eq!(buf[16..len], [0x00, 0x04, 1, 2, 3, 0]);
+
+        let len = encode_dmx(&mut buf, PortAddress::default(), 0, 0, &[]);
+        assert_eq!(len, 20);
+        assert_eq!(buf[16..len], [0x00, 0x02, 0, 0]);
+    }
+
+    #[test]
+    fn dmx_data_is_truncated_to_512_channels() {
+        let mut buf = [0; 530];
+        let len = encode_dmx(&mut buf, PortAddress::default(), 0, 0, &[0x55; 600]);
+        assert_eq!(len, 530);
+        assert_eq!(buf[16..18], [0x02, 0x00]);
+    }
+
+    #[test]
+    fn dmx_packet_round_trip() {
+        let address = PortAddress::new(0x7f, 0xf, 0xf).unwrap();
+        let channels: Vec<u8> = (0..512).map(|i| i as u8).collect();
+        let mut buf = [0; 530];
+        let len = encode_dmx(&mut buf, address, 0xff, 3, &channels);
+
+        let packet = decode_dmx(&buf[..len]).unwrap();
+        assert_eq!(packet.address, address);
+        assert_eq!(packet.sequence, 0xff);
+        assert_eq!(packet.physical, 3);
+        assert_eq!(packet.channels, &channels[..]);
+    }
+
+    #[test]
+    fn decoding_honors_the_length_field() {
+        let mut buf = [0; 530];
+        let len = encode_dmx(&mut buf, PortAddress::default(), 0, 0, &[1, 2, 3, 4]);
+        buf[17] = 2;
+
+        assert_eq!(decode_dmx(&buf[..len]).unwrap().channels, [1, 2]);
+        // a length beyond the packet is cut at its end
+        buf[17] = 6;
+        assert_eq!(decode_dmx(&buf[..len]).unwrap().channels, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn invalid_packets_are_rejected() {
+        let mut buf = [0; 530];
+        let len = encode_dmx(&mut buf, PortAddress::default(), 0, 0, &[1, 2]);
+
+        assert!(decode_dmx(&buf[..17]).is_none());
+        assert_eq!(opcode(&buf[..9]), None);
+
+        let mut packet = buf[..len].to_vec();
+        packet[7] = b'X';
+        assert_eq!(opcode(&packet), None);
+
+        // an ArtPoll is not an ArtDmx
+        let mut packet = buf[..len].to_vec();
+        packet[9] = 0x20;
+        assert_eq!(opcode(&packet), Some(OP_POLL));
+        assert!(decode_dmx(&packet).is_none());
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_to_one() {
+        assert_eq!(next_sequence(1), 2);
+        assert_eq!(next_sequence(0xfe), 0xff);
+        assert_eq!(next_sequence(0xff), 1);
+        // zero disables sequencing
+        assert_eq!(next_sequence(0), 0);
+    }
+
+    #[test]
+    fn port_address_components() {
+        let address = PortAddress::new(0x12, 0x3, 0x4).unwrap();
+        assert_eq!(address.as_u16(), 0x1234);
+        assert_eq!((address.net(), address.subnet(), address.universe()), (0x12, 3, 4));
+        assert_eq!(address.sub_uni(), 0x34);
+        assert_eq!(address.to_string(), "18:3:4");
+
+        assert_eq!(PortAddress::new(0x80, 0, 0), None);
+        assert_eq!(PortAddress::new(0, 0x10, 0), None);
+        assert_eq!(PortAddress::new(0, 0, 0x10), None);
+        assert_eq!(PortAddress::from_u16(0x8000), None);
+    }
+}
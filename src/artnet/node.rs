@@ -0,0 +1,470 @@
+//! Remote configuration of nodes.
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+
+use super::poll::{c_string, write_c_string, write_poll_reply, POLL_REPLY_LEN, STATUS_DEFAULT};
+use super::{opcode, write_header, ArtNode, PortAddress, OP_POLL, PROTOCOL_VERSION};
+use crate::Result;
+
+/// Opcode of `ArtAddress` packets.
+pub const OP_ADDRESS: u16 = 0x6000;
+
+// ID, opcode, version, net, bind index, names, switches, priority, command
+const ADDRESS_LEN: usize = 107;
+
+/// A change of a switch of a node, requested through `ArtAddress`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum SwitchChange {
+    /// Keep the current setting.
+    #[default]
+    Keep,
+    /// Return to the node's default setting.
+    Reset,
+    /// Change the setting.
+    Set(u8),
+}
+
+impl SwitchChange {
+    fn from_u8(value: u8) -> SwitchChange {
+        match value {
+            0x00 => SwitchChange::Reset,
+            v if v & 0x80 != 0 => SwitchChange::Set(v & 0x7f),
+            _ => SwitchChange::Keep,
+        }
+    }
+
+    fn as_u8(&self) -> u8 {
+        match *self {
+            SwitchChange::Keep => 0x7f,
+            SwitchChange::Reset => 0x00,
+            SwitchChange::Set(v) => 0x80 | v,
+        }
+    }
+}
+
+/// A command sent along with an `ArtAddress`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum AddressCommand {
+    /// No action.
+    #[default]
+    None,
+    /// Stop merging sources on all outputs.
+    CancelMerge,
+    /// Show the node's status on its indicators.
+    LedNormal,
+    /// Turn the indicators off.
+    LedMute,
+    /// Flash the indicators, to find the node.
+    LedLocate,
+    /// Clear the receive error flags.
+    ResetRxFlags,
+    /// Merge sources of output 0 to 3 latest-takes-precedence.
+    MergeLtp(u8),
+    /// Merge sources of output 0 to 3 highest-takes-precedence.
+    MergeHtp(u8),
+    /// Clear the channels of output 0 to 3.
+    ClearOutput(u8),
+    /// Any other command.
+    Other(u8),
+}
+
+impl AddressCommand {
+    fn from_u8(value: u8) -> AddressCommand {
+        match value {
+            0x00 => AddressCommand::None,
+            0x01 => AddressCommand::CancelMerge,
+            0x02 => AddressCommand::LedNormal,
+            0x03 => AddressCommand::LedMute,
+            0x04 => AddressCommand::LedLocate,
+            0x05 => AddressCommand::ResetRxFlags,
+            0x10..=0x13 => AddressCommand::MergeLtp(value & 0x03),
+            0x50..=0x53 => AddressCommand::MergeHtp(value & 0x03),
+            0x90..=0x93 => AddressCommand::ClearOutput(value & 0x03),
+            v => AddressCommand::Other(v),
+        }
+    }
+
+    fn as_u8(&self) -> u8 {
+        match *self {
+            AddressCommand::None => 0x00,
+            AddressCommand::CancelMerge => 0x01,
+            AddressCommand::LedNormal => 0x02,
+            AddressCommand::LedMute => 0x03,
+            AddressCommand::LedLocate => 0x04,
+            AddressCommand::ResetRxFlags => 0x05,
+            AddressCommand::MergeLtp(port) => 0x10 | (port & 0x03),
+            AddressCommand::MergeHtp(port) => 0x50 | (port & 0x03),
+            AddressCommand::ClearOutput(port) => 0x90 | (port & 0x03),
+            AddressCommand::Other(v) => v,
+        }
+    }
+}
+
+/// A decoded `ArtAddress` packet, changing the configuration of a node.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ArtAddress {
+    /// Identifies the group of four ports addressed, starting at 1. Zero
+    /// addresses the first group as well.
+    pub bind_index: u8,
+    /// New short name, up to 17 characters.
+    pub short_name: Option<String>,
+    /// New long name, up to 63 characters.
+    pub long_name: Option<String>,
+    /// New net of all ports.
+    pub net: SwitchChange,
+    /// New sub-net of all ports.
+    pub subnet: SwitchChange,
+    /// New universe of each input.
+    pub inputs: [SwitchChange; 4],
+    /// New universe of each output.
+    pub outputs: [SwitchChange; 4],
+    /// Action to take.
+    pub command: AddressCommand,
+}
+
+/// Encodes an `ArtAddress` packet into `buf`.
+///
+/// Returns the length of the packet; `buf` must hold at least 107 bytes.
+pub fn encode_address(buf: &mut [u8], address: &ArtAddress) -> usize {
+    write_header(buf, OP_ADDRESS);
+    buf[10..12].copy_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+    buf[12] = address.net.as_u8();
+    buf[13] = address.bind_index;
+    write_c_string(&mut buf[14..32], address.short_name.as_deref().unwrap_or(""));
+    write_c_string(&mut buf[32..96], address.long_name.as_deref().unwrap_or(""));
+    for i in 0..4 {
+        buf[96 + i] = address.inputs[i].as_u8();
+        buf[100 + i] = address.outputs[i].as_u8();
+    }
+    buf[104] = address.subnet.as_u8();
+    // keep the sACN priority
+    buf[105] = 0xff;
+    buf[106] = address.command.as_u8();
+
+    ADDRESS_LEN
+}
+
+/// Decodes an `ArtAddress` packet.
+///
+/// Returns `None` if the packet is not a valid `ArtAddress` packet.
+pub fn decode_address(packet: &[u8]) -> Option<ArtAddress> {
+    if opcode(packet) != Some(OP_ADDRESS) || packet.len() < ADDRESS_LEN {
+        return None;
+    }
+
+    // empty names are left unchanged
+    let name = |field: &[u8]| Some(c_string(field)).filter(|s| !s.is_empty());
+    let mut inputs = [SwitchChange::Keep; 4];
+    let mut outputs = [SwitchChange::Keep; 4];
+    for i in 0..4 {
+        inputs[i] = SwitchChange::from_u8(packet[96 + i]);
+        outputs[i] = SwitchChange::from_u8(packet[100 + i]);
+    }
+
+    Some(ArtAddress {
+        bind_index: packet[13],
+        short_name: name(&packet[14..32]),
+        long_name: name(&packet[32..96]),
+        net: SwitchChange::from_u8(packet[12]),
+        subnet: SwitchChange::from_u8(packet[104]),
+        inputs,
+        outputs,
+        command: AddressCommand::from_u8(packet[106]),
+    })
+}
+
+/// State of a node's indicators.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Indicators {
+    /// Showing the node's status.
+    #[default]
+    Normal,
+    /// Turned off.
+    Mute,
+    /// Flashing, to find the node.
+    Locate,
+}
+
+/// The configuration of a node that consoles can change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeConfig {
+    /// Short name, up to 17 characters.
+    pub short_name: String,
+    /// Long name, up to 63 characters.
+    pub long_name: String,
+    /// Port-addresses of the node's DMX outputs.
+    pub outputs: Vec<PortAddress>,
+    /// Port-addresses of the node's DMX inputs.
+    pub inputs: Vec<PortAddress>,
+}
+
+impl NodeConfig {
+    /// Create a configuration without any ports.
+    #[inline]
+    pub fn new(short_name: &str, long_name: &str) -> NodeConfig {
+        NodeConfig {
+            short_name: short_name.to_owned(),
+            long_name: long_name.to_owned(),
+            outputs: Vec::new(),
+            inputs: Vec::new(),
+        }
+    }
+}
+
+type Callback = Box<dyn FnMut(&NodeConfig, Indicators) + Send>;
+
+/// The Art-Net node side of an application.
+///
+/// Answers `ArtPoll` packets, so consoles find the node and its ports, and
+/// applies the changes of `ArtAddress` packets: consoles can rename the
+/// node, move its ports to other port-addresses and switch its indicators,
+/// e.g. to locate it. Ports are reported in groups of four, each group
+/// under its own bind index.
+///
+/// A port-address reset through `ArtAddress` returns to the one the node
+/// was created with. Commands other than those switching the indicators
+/// are up to the application, which is notified of changes through
+/// `on_change`.
+///
+/// Like `ArtRdmGateway`, the node does not own a socket; packets are handed
+/// to `handle`. An `ArtNetReceiver` does so itself once given a node
+/// through `set_node`.
+///
+/// ## Example
+///
+/// ```no_run
+/// use dmx::artnet::{ArtNetNode, ArtNetReceiver, NodeConfig, PortAddress};
+///
+/// let mut config = NodeConfig::new("stage left", "dmx-rs stage left");
+/// config.outputs.push(PortAddress::new(0, 0, 1).unwrap());
+///
+/// let mut node = ArtNetNode::new(config);
+/// node.on_change(|config, indicators| {
+///     println!("now {} at {:?}, {:?}", config.short_name, config.outputs, indicators);
+/// });
+///
+/// let mut receiver = ArtNetReceiver::new(&[]).unwrap();
+/// receiver.set_node(node);
+///
+/// loop {
+///     let frame = receiver.recv_frame().unwrap();
+/// }
+/// ```
+pub struct ArtNetNode {
+    config: NodeConfig,
+    defaults: NodeConfig,
+    indicators: Indicators,
+    ip: Option<Ipv4Addr>,
+    esta_manufacturer: u16,
+    oem: u16,
+    callback: Option<Callback>,
+}
+
+impl ArtNetNode {
+    /// Create a node with the given initial configuration.
+    pub fn new(config: NodeConfig) -> ArtNetNode {
+        ArtNetNode {
+            defaults: config.clone(),
+            config,
+            indicators: Indicators::Normal,
+            ip: None,
+            esta_manufacturer: 0,
+            oem: 0,
+            callback: None,
+        }
+    }
+
+    /// Returns the current configuration.
+    #[inline]
+    pub fn config(&self) -> &NodeConfig {
+        &self.config
+    }
+
+    /// Returns the current state of the indicators.
+    #[inline]
+    pub fn indicators(&self) -> Indicators {
+        self.indicators
+    }
+
+    /// Sets the IP address reported to consoles.
+    ///
+    /// If `None`, which is the default, the address of the interface facing
+    /// the console is reported.
+    #[inline]
+    pub fn set_ip(&mut self, ip: Option<Ipv4Addr>) {
+        self.ip = ip;
+    }
+
+    /// Sets the ESTA manufacturer code and Art-Net OEM code reported.
+    ///
+    /// Both default to zero.
+    #[inline]
+    pub fn set_oem(&mut self, esta_manufacturer: u16, oem: u16) {
+        self.esta_manufacturer = esta_manufacturer;
+        self.oem = oem;
+    }
+
+    /// Calls `callback` whenever the configuration or the indicators are
+    /// changed through `ArtAddress`.
+    pub fn on_change<C>(&mut self, callback: C)
+    where
+        C: FnMut(&NodeConfig, Indicators) + Send + 'static,
+    {
+        self.callback = Some(Box::new(callback));
+    }
+
+    /// Applies the changes of an `ArtAddress` packet.
+    ///
+    /// Returns whether anything changed, in which case the callback set
+    /// through `on_change` has been called.
+    pub fn apply(&mut self, address: &ArtAddress) -> bool {
+        let before = (self.config.clone(), self.indicators);
+        let group = usize::from(address.bind_index.max(1) - 1) * 4;
+
+        if let Some(ref name) = address.short_name {
+            self.config.short_name = name.chars().take(17).collect();
+        }
+        if let Some(ref name) = address.long_name {
+            self.config.long_name = name.chars().take(63).collect();
+        }
+
+        let defaults = &self.defaults;
+        program(&mut self.config.outputs, &defaults.outputs, group, address, &address.outputs);
+        program(&mut self.config.inputs, &defaults.inputs, group, address, &address.inputs);
+
+        self.indicators = match address.command {
+            AddressCommand::LedNormal => Indicators::Normal,
+            AddressCommand::LedMute => Indicators::Mute,
+            AddressCommand::LedLocate => Indicators::Locate,
+            _ => self.indicators,
+        };
+
+        let changed = before != (self.config.clone(), self.indicators);
+        if changed {
+            if let Some(ref mut callback) = self.callback {
+                callback(&self.config, self.indicators);
+            }
+        }
+
+        changed
+    }
+
+    /// Returns the replies to an `ArtPoll`, one per group of four ports.
+    ///
+    /// `ip` is reported as the node's address, unless set through `set_ip`.
+    pub fn poll_replies(&self, ip: Ipv4Addr) -> Vec<ArtNode> {
+        let groups = self.config.outputs.len().max(self.config.inputs.len()).div_ceil(4);
+        let group = |ports: &[PortAddress], n: usize| {
+            ports.iter().skip(n * 4).take(4).copied().collect()
+        };
+
+        (0..groups.max(1))
+            .map(|n| ArtNode {
+                ip: self.ip.unwrap_or(ip),
+                short_name: self.config.short_name.clone(),
+                long_name: self.config.long_name.clone(),
+                esta_manufacturer: self.esta_manufacturer,
+                oem: self.oem,
+                bind_index: n as u8 + 1,
+                outputs: group(&self.config.outputs, n),
+                inputs: group(&self.config.inputs, n),
+            })
+            .collect()
+    }
+
+    /// Handles a packet received from `source`, replying through `socket`.
+    ///
+    /// Answers `ArtPoll` packets and applies `ArtAddress` packets, replying
+    /// with the resulting configuration. Returns whether the packet was one
+    /// of those; other packets are left to the caller.
+    pub fn handle(
+        &mut self,
+        socket: &UdpSocket,
+        packet: &[u8],
+        source: SocketAddr,
+    ) -> Result<bool> {
+        match opcode(packet) {
+            Some(OP_POLL) => (),
+            Some(OP_ADDRESS) => match decode_address(packet) {
+                Some(address) => {
+                    self.apply(&address);
+                }
+                None => return Ok(false),
+            },
+            _ => return Ok(false),
+        }
+
+        let ip = self.ip.unwrap_or_else(|| local_ip(source.ip()));
+        let indicators = match self.indicators {
+            Indicators::Normal => 0xc0,
+            Indicators::Mute => 0x80,
+            Indicators::Locate => 0x40,
+        };
+        let status = (STATUS_DEFAULT & !0xc0) | indicators;
+
+        let mut buf = [0; POLL_REPLY_LEN];
+        for node in self.poll_replies(ip) {
+            let len = write_poll_reply(&mut buf, &node, status);
+            socket.send_to(&buf[..len], source)?;
+        }
+
+        Ok(true)
+    }
+}
+
+impl fmt::Debug for ArtNetNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ArtNetNode")
+            .field("config", &self.config)
+            .field("indicators", &self.indicators)
+            .field("ip", &self.ip)
+            .field("esta_manufacturer", &self.esta_manufacturer)
+            .field("oem", &self.oem)
+            .finish()
+    }
+}
+
+/// Applies the switch changes of an `ArtAddress` to a group of four ports.
+fn program(
+    ports: &mut [PortAddress],
+    defaults: &[PortAddress],
+    group: usize,
+    address: &ArtAddress,
+    universes: &[SwitchChange; 4],
+) {
+    let pick = |change, current, default| match change {
+        SwitchChange::Keep => current,
+        SwitchChange::Reset => default,
+        SwitchChange::Set(v) => v,
+    };
+
+    for (i, port) in ports.iter_mut().enumerate().skip(group).take(4) {
+        let default = defaults.get(i).copied().unwrap_or_default();
+        let net = pick(address.net, port.net(), default.net());
+        let subnet = pick(address.subnet, port.subnet(), default.subnet());
+        let universe = pick(universes[i - group], port.universe(), default.universe());
+
+        if let Some(new) = PortAddress::new(net, subnet & 0x0f, universe & 0x0f) {
+            *port = new;
+        }
+    }
+}
+
+/// Returns the address of the interface packets to `peer` are sent from.
+///
+/// Connecting a UDP socket sends nothing, but lets the operating system
+/// pick the route. Falls back to the unspecified address.
+fn local_ip(peer: IpAddr) -> Ipv4Addr {
+    UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| {
+            socket.connect((peer, 9))?;
+            socket.local_addr()
+        })
+        .ok()
+        .and_then(|addr| match addr.ip() {
+            IpAddr::V4(ip) => Some(ip),
+            IpAddr::V6(_) => None,
+        })
+        .unwrap_or(Ipv4Addr::UNSPECIFIED)
+}
@@ -0,0 +1,251 @@
+//! Node discovery.
+
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::{io, time};
+
+use super::{opcode, write_header, PortAddress, ARTNET_PORT, PROTOCOL_VERSION};
+
+/// Opcode of `ArtPoll` packets.
+pub const OP_POLL: u16 = 0x2000;
+
+/// Opcode of `ArtPollReply` packets.
+pub const OP_POLL_REPLY: u16 = 0x2100;
+
+// ID, opcode, version, flags, diagnostics priority
+const POLL_LEN: usize = 14;
+
+// replies of nodes implementing older revisions end after the switches
+const MIN_POLL_REPLY_LEN: usize = 197;
+
+// replies of nodes implementing revision 4
+pub(crate) const POLL_REPLY_LEN: usize = 239;
+
+// port type flags
+const PORT_OUTPUT: u8 = 0x80;
+const PORT_INPUT: u8 = 0x40;
+
+// indicators normal, port-addresses set through the network
+pub(crate) const STATUS_DEFAULT: u8 = 0xe0;
+
+// supports 15-bit port-addresses
+const STATUS2_PORT_ADDRESS_15: u8 = 0x08;
+
+/// A node that replied to an `ArtPoll`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArtNode {
+    /// IP address of the node.
+    pub ip: Ipv4Addr,
+    /// Short name, up to 17 characters.
+    pub short_name: String,
+    /// Long name, up to 63 characters.
+    pub long_name: String,
+    /// ESTA manufacturer code, zero if not reported.
+    pub esta_manufacturer: u16,
+    /// Product code assigned by Art-Net to the manufacturer.
+    pub oem: u16,
+    /// Identifies the reply among several sent by the same node, if it has
+    /// more than four ports. One for the first or only reply.
+    pub bind_index: u8,
+    /// Port-addresses of the node's DMX outputs.
+    pub outputs: Vec<PortAddress>,
+    /// Port-addresses of the node's DMX inputs.
+    pub inputs: Vec<PortAddress>,
+}
+
+impl ArtNode {
+    /// Returns the address to send to the node, see `ArtNetTransmitter::new`.
+    #[inline]
+    pub fn socket_addr(&self) -> SocketAddr {
+        (self.ip, ARTNET_PORT).into()
+    }
+}
+
+/// Encodes an `ArtPoll` packet into `buf`.
+///
+/// Returns the length of the packet; `buf` must hold at least 14 bytes.
+pub fn encode_poll(buf: &mut [u8]) -> usize {
+    write_header(buf, OP_POLL);
+    buf[10..12].copy_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+    // no reply on change, diagnostics disabled
+    buf[12] = 0;
+    buf[13] = 0;
+
+    POLL_LEN
+}
+
+/// Encodes an `ArtPollReply` packet describing `node` into `buf`.
+///
+/// A reply lists up to four ports, nodes with more send one reply per four
+/// ports, each with its own `bind_index`. All ports of a reply share the net
+/// and sub-net of the first output, or the first input if there are no
+/// outputs. Returns the length of the packet; `buf` must hold at least 239
+/// bytes.
+#[inline]
+pub fn encode_poll_reply(buf: &mut [u8], node: &ArtNode) -> usize {
+    write_poll_reply(buf, node, STATUS_DEFAULT)
+}
+
+/// Encodes an `ArtPollReply` packet with the given `Status1` field.
+pub(crate) fn write_poll_reply(buf: &mut [u8], node: &ArtNode, status: u8) -> usize {
+    for b in &mut buf[..POLL_REPLY_LEN] {
+        *b = 0;
+    }
+
+    let first = node.outputs.first().or_else(|| node.inputs.first());
+    let (net, subnet) = first.map_or((0, 0), |a| (a.net(), a.subnet()));
+    let ports = node.outputs.len().max(node.inputs.len()).min(4);
+
+    write_header(buf, OP_POLL_REPLY);
+    buf[10..14].copy_from_slice(&node.ip.octets());
+    buf[14..16].copy_from_slice(&ARTNET_PORT.to_le_bytes());
+    buf[18] = net;
+    buf[19] = subnet;
+    buf[20..22].copy_from_slice(&node.oem.to_be_bytes());
+    buf[23] = status;
+    buf[24..26].copy_from_slice(&node.esta_manufacturer.to_le_bytes());
+    write_c_string(&mut buf[26..44], &node.short_name);
+    write_c_string(&mut buf[44..108], &node.long_name);
+    buf[172..174].copy_from_slice(&(ports as u16).to_be_bytes());
+
+    for (i, address) in node.outputs.iter().take(4).enumerate() {
+        buf[174 + i] |= PORT_OUTPUT;
+        buf[190 + i] = address.universe();
+    }
+    for (i, address) in node.inputs.iter().take(4).enumerate() {
+        buf[174 + i] |= PORT_INPUT;
+        buf[186 + i] = address.universe();
+    }
+
+    buf[207..211].copy_from_slice(&node.ip.octets());
+    buf[211] = node.bind_index;
+    buf[212] = STATUS2_PORT_ADDRESS_15;
+
+    POLL_REPLY_LEN
+}
+
+/// Decodes an `ArtPollReply` packet.
+///
+/// Returns `None` if the packet is not a valid `ArtPollReply`.
+pub fn decode_poll_reply(packet: &[u8]) -> Option<ArtNode> {
+    if opcode(packet) != Some(OP_POLL_REPLY) || packet.len() < MIN_POLL_REPLY_LEN {
+        return None;
+    }
+
+    let ip = Ipv4Addr::new(packet[10], packet[11], packet[12], packet[13]);
+    let net = packet[18] & 0x7f;
+    let subnet = packet[19] & 0x0f;
+    let ports = usize::from(packet[173]).min(4);
+
+    let mut outputs = Vec::new();
+    let mut inputs = Vec::new();
+
+    for i in 0..ports {
+        let port_type = packet[174 + i];
+        let address = |switch: u8| PortAddress::new(net, subnet, switch & 0x0f);
+
+        if port_type & PORT_OUTPUT != 0 {
+            outputs.extend(address(packet[190 + i]));
+        }
+        if port_type & PORT_INPUT != 0 {
+            inputs.extend(address(packet[186 + i]));
+        }
+    }
+
+    Some(ArtNode {
+        ip,
+        short_name: c_string(&packet[26..44]),
+        long_name: c_string(&packet[44..108]),
+        esta_manufacturer: u16::from_le_bytes([packet[24], packet[25]]),
+        oem: u16::from_be_bytes([packet[20], packet[21]]),
+        bind_index: packet.get(211).copied().filter(|&n| n != 0).unwrap_or(1),
+        outputs,
+        inputs,
+    })
+}
+
+/// Discovers nodes on the local network.
+///
+/// Broadcasts an `ArtPoll` and collects replies until `timeout` has passed.
+/// As nodes reply to the Art-Net port, this fails if another socket on this
+/// host is bound to it already. Nodes are returned in the order their
+/// replies arrived, nodes replying more than once are only listed once.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use dmx::artnet::{self, ArtNetTransmitter};
+///
+/// let mut transmitters = Vec::new();
+/// for node in artnet::discover(Duration::from_secs(3)).unwrap() {
+///     for &address in &node.outputs {
+///         transmitters.push(ArtNetTransmitter::new(node.socket_addr(), address).unwrap());
+///     }
+/// }
+/// ```
+#[inline]
+pub fn discover(timeout: time::Duration) -> io::Result<Vec<ArtNode>> {
+    discover_on(Ipv4Addr::BROADCAST, timeout)
+}
+
+/// Discovers nodes, sending the `ArtPoll` to a specific address.
+///
+/// `target` usually is the directed broadcast address of a network
+/// interface, such as `10.255.255.255`, or the IP of a single node. See
+/// `discover` for details.
+pub fn discover_on(target: Ipv4Addr, timeout: time::Duration) -> io::Result<Vec<ArtNode>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, ARTNET_PORT))?;
+    socket.set_broadcast(true)?;
+
+    let mut buf = [0; 1024];
+    let len = encode_poll(&mut buf);
+    socket.send_to(&buf[..len], (target, ARTNET_PORT))?;
+
+    let deadline = time::Instant::now() + timeout;
+    let mut nodes: Vec<ArtNode> = Vec::new();
+
+    loop {
+        let now = time::Instant::now();
+        if now >= deadline {
+            break;
+        }
+        socket.set_read_timeout(Some(deadline - now))?;
+
+        let len = match socket.recv(&mut buf) {
+            Ok(len) => len,
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                break
+            }
+            Err(e) => return Err(e),
+        };
+
+        // our own poll is received as well, but is not a reply
+        if let Some(node) = decode_poll_reply(&buf[..len]) {
+            if !nodes
+                .iter()
+                .any(|n| n.ip == node.ip && n.bind_index == node.bind_index)
+            {
+                nodes.push(node);
+            }
+        }
+    }
+
+    Ok(nodes)
+}
+
+/// Writes a fixed-length, NUL-terminated string field.
+///
+/// Truncates `s` to leave room for the terminator.
+pub(crate) fn write_c_string(field: &mut [u8], s: &str) {
+    let len = s.len().min(field.len() - 1);
+    field[..len].copy_from_slice(&s.as_bytes()[..len]);
+    for b in &mut field[len..] {
+        *b = 0;
+    }
+}
+
+/// Converts a fixed-length, NUL-terminated string field.
+pub(crate) fn c_string(field: &[u8]) -> String {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..len]).into_owned()
+}
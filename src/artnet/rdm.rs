@@ -0,0 +1,382 @@
+//! RDM over Art-Net.
+
+use std::net::{SocketAddr, UdpSocket};
+
+use super::{opcode, write_header, PortAddress, PROTOCOL_VERSION};
+use crate::rdm::{CommandClass, RdmController, RdmRequest, Uid, MAX_PACKET_LEN, SC_RDM};
+use crate::{DmxTransceiver, Result};
+
+/// Opcode of `ArtTodRequest` packets.
+pub const OP_TOD_REQUEST: u16 = 0x8000;
+
+/// Opcode of `ArtTodData` packets.
+pub const OP_TOD_DATA: u16 = 0x8100;
+
+/// Opcode of `ArtTodControl` packets.
+pub const OP_TOD_CONTROL: u16 = 0x8200;
+
+/// Opcode of `ArtRdm` packets.
+pub const OP_RDM: u16 = 0x8300;
+
+/// Maximum number of UIDs in a single `ArtTodData` packet.
+pub const MAX_TOD_UIDS: usize = 200;
+
+// ID, opcode, version, spare, net, command, address count or address
+const TOD_HEADER_LEN: usize = 24;
+
+// ID, opcode, version, RDM version, port, spare, bind index, net, command,
+// address, UID total, block, UID count
+const TOD_DATA_HEADER_LEN: usize = 28;
+
+// ID, opcode, version, RDM version, spare, net, command, address
+const RDM_HEADER_LEN: usize = 24;
+
+const RDM_VERSION: u8 = 0x01;
+
+// ArtTodControl command flushing the table of devices
+const ATC_FLUSH: u8 = 0x01;
+
+/// A decoded `ArtTodData` packet, listing devices behind a port.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArtTodData {
+    /// Port-address of the port the devices are attached to.
+    pub address: PortAddress,
+    /// Physical port of the node, starting at 1.
+    pub port: u8,
+    /// Identifies the node among several sharing an IP address.
+    pub bind_index: u8,
+    /// Total number of devices behind the port.
+    pub uid_total: u16,
+    /// Index of this packet, if the devices do not fit into a single one.
+    pub block: u8,
+    /// Devices listed in this packet.
+    pub uids: Vec<Uid>,
+}
+
+/// A decoded `ArtRdm` packet.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ArtRdm<'a> {
+    /// Port-address the packet is sent to or from.
+    pub address: PortAddress,
+    /// RDM packet, without its start code.
+    pub data: &'a [u8],
+}
+
+/// Decodes an `ArtTodRequest` packet.
+///
+/// Returns the port-addresses whose devices are requested, or `None` if the
+/// packet is not a valid `ArtTodRequest`.
+pub fn decode_tod_request(packet: &[u8]) -> Option<Vec<PortAddress>> {
+    if opcode(packet) != Some(OP_TOD_REQUEST) || packet.len() < TOD_HEADER_LEN {
+        return None;
+    }
+
+    let net = packet[21] & 0x7f;
+    let count = usize::from(packet[23]).min(32);
+    let addresses = packet[TOD_HEADER_LEN..].iter().take(count);
+
+    Some(
+        addresses
+            .filter_map(|&sub_uni| PortAddress::new(net, sub_uni >> 4, sub_uni & 0x0f))
+            .collect(),
+    )
+}
+
+/// Encodes an `ArtTodData` packet into `buf`.
+///
+/// Lists up to 200 of `uids` as block `block` of the port's devices, with
+/// `uid_total` devices in total. Returns the length of the packet; `buf`
+/// must hold at least 1228 bytes.
+pub fn encode_tod_data(
+    buf: &mut [u8],
+    address: PortAddress,
+    uid_total: u16,
+    block: u8,
+    uids: &[Uid],
+) -> usize {
+    let count = uids.len().min(MAX_TOD_UIDS);
+
+    write_header(buf, OP_TOD_DATA);
+    buf[10..12].copy_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+    buf[12] = RDM_VERSION;
+    buf[13] = 1;
+    for b in &mut buf[14..20] {
+        *b = 0;
+    }
+    buf[20] = 1;
+    buf[21] = address.net();
+    // full table of devices
+    buf[22] = 0x00;
+    buf[23] = address.sub_uni();
+    buf[24..26].copy_from_slice(&uid_total.to_be_bytes());
+    buf[26] = block;
+    buf[27] = count as u8;
+
+    for (i, uid) in uids[..count].iter().enumerate() {
+        let start = TOD_DATA_HEADER_LEN + i * 6;
+        buf[start..(start + 6)].copy_from_slice(&uid.to_bytes());
+    }
+
+    TOD_DATA_HEADER_LEN + count * 6
+}
+
+/// Decodes an `ArtTodData` packet.
+///
+/// Returns `None` if the packet is not a valid `ArtTodData` packet.
+pub fn decode_tod_data(packet: &[u8]) -> Option<ArtTodData> {
+    if opcode(packet) != Some(OP_TOD_DATA) || packet.len() < TOD_DATA_HEADER_LEN {
+        return None;
+    }
+
+    let sub_uni = packet[23];
+    let address = PortAddress::new(packet[21] & 0x7f, sub_uni >> 4, sub_uni & 0x0f)?;
+    let count = usize::from(packet[27]);
+    let uids = packet[TOD_DATA_HEADER_LEN..].chunks_exact(6).take(count);
+
+    Some(ArtTodData {
+        address,
+        port: packet[13],
+        bind_index: packet[20],
+        uid_total: u16::from_be_bytes([packet[24], packet[25]]),
+        block: packet[26],
+        uids: uids
+            .map(|b| Uid::from_bytes([b[0], b[1], b[2], b[3], b[4], b[5]]))
+            .collect(),
+    })
+}
+
+/// Decodes an `ArtTodControl` packet with a flush command.
+///
+/// Returns the port-address whose devices are to be discovered again, or
+/// `None` for other packets.
+fn decode_tod_flush(packet: &[u8]) -> Option<PortAddress> {
+    if opcode(packet) != Some(OP_TOD_CONTROL) || packet.len() < TOD_HEADER_LEN {
+        return None;
+    }
+
+    if packet[22] != ATC_FLUSH {
+        return None;
+    }
+
+    let sub_uni = packet[23];
+    PortAddress::new(packet[21] & 0x7f, sub_uni >> 4, sub_uni & 0x0f)
+}
+
+/// Encodes an `ArtRdm` packet into `buf`.
+///
+/// `data` is the RDM packet without its start code, e.g. `&rdm[1..len]`
+/// after `RdmRequest::encode` or `RdmResponse::encode` wrote `len` bytes to
+/// `rdm`. Returns the length of the packet; `buf` must hold at least
+/// `24 + data.len()` bytes.
+pub fn encode_rdm(buf: &mut [u8], address: PortAddress, data: &[u8]) -> usize {
+    write_header(buf, OP_RDM);
+    buf[10..12].copy_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+    buf[12] = RDM_VERSION;
+    for b in &mut buf[13..21] {
+        *b = 0;
+    }
+    buf[21] = address.net();
+    // process the packet
+    buf[22] = 0x00;
+    buf[23] = address.sub_uni();
+    buf[RDM_HEADER_LEN..(RDM_HEADER_LEN + data.len())].copy_from_slice(data);
+
+    RDM_HEADER_LEN + data.len()
+}
+
+/// Decodes an `ArtRdm` packet.
+///
+/// Returns `None` if the packet is not a valid `ArtRdm` packet.
+pub fn decode_rdm(packet: &[u8]) -> Option<ArtRdm<'_>> {
+    if opcode(packet) != Some(OP_RDM) || packet.len() < RDM_HEADER_LEN {
+        return None;
+    }
+
+    let sub_uni = packet[23];
+
+    Some(ArtRdm {
+        address: PortAddress::new(packet[21] & 0x7f, sub_uni >> 4, sub_uni & 0x0f)?,
+        data: &packet[RDM_HEADER_LEN..],
+    })
+}
+
+/// Makes the RDM devices on a DMX line accessible over Art-Net.
+///
+/// Answers `ArtTodRequest` packets for its port-address with the devices
+/// found by the last discovery, and discovers again when receiving an
+/// `ArtTodControl` flush. `ArtRdm` packets are passed on to the line through
+/// `RdmController::forward`, responses are sent back to the console in
+/// another `ArtRdm`. Discovery requests are not forwarded, as the protocol
+/// leaves discovery to the node.
+///
+/// The gateway does not own a socket, so it can share the one receiving
+/// `ArtDmx` packets for the line: packets are handed to `handle`, which
+/// ignores those unrelated to RDM.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::net::UdpSocket;
+/// use dmx::{DmxPort, DmxTransmitter};
+/// use dmx::artnet::{self, ArtRdmGateway, PortAddress, ARTNET_PORT};
+/// use dmx::rdm::{RdmController, Uid};
+///
+/// let port = DmxPort::open("/dev/ttyUSB0").unwrap();
+/// let controller = RdmController::new(port, Uid::new(0x7ff0, 1));
+/// let address = PortAddress::new(0, 0, 1).unwrap();
+///
+/// let mut gateway = ArtRdmGateway::new(controller, address);
+/// gateway.discover().unwrap();
+///
+/// let socket = UdpSocket::bind(("0.0.0.0", ARTNET_PORT)).unwrap();
+/// let mut buf = [0; 1024];
+///
+/// loop {
+///     let (len, source) = socket.recv_from(&mut buf).unwrap();
+///     if gateway.handle(&socket, &buf[..len], source).unwrap() {
+///         continue;
+///     }
+///
+///     match artnet::decode_dmx(&buf[..len]) {
+///         Some(dmx) if dmx.address == address => {
+///             let port = gateway.controller_mut().get_mut();
+///             port.send_dmx_packet(dmx.channels).unwrap();
+///         }
+///         _ => (),
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ArtRdmGateway<T> {
+    controller: RdmController<T>,
+    address: PortAddress,
+    tod: Vec<Uid>,
+}
+
+impl<T: DmxTransceiver> ArtRdmGateway<T> {
+    /// Create a gateway for the line of `controller`, at `address`.
+    ///
+    /// No devices are known until `discover` is called.
+    #[inline]
+    pub fn new(controller: RdmController<T>, address: PortAddress) -> ArtRdmGateway<T> {
+        ArtRdmGateway {
+            controller,
+            address,
+            tod: Vec::new(),
+        }
+    }
+
+    /// Returns the port-address of the line.
+    #[inline]
+    pub fn address(&self) -> PortAddress {
+        self.address
+    }
+
+    /// Returns the devices found by the last discovery.
+    #[inline]
+    pub fn tod(&self) -> &[Uid] {
+        &self.tod
+    }
+
+    /// Discovers the devices on the line.
+    pub fn discover(&mut self) -> Result<&[Uid]> {
+        self.tod = self.controller.discover()?;
+        Ok(&self.tod)
+    }
+
+    /// Returns a mutable reference to the controller, e.g. to send DMX
+    /// data between requests.
+    #[inline]
+    pub fn controller_mut(&mut self) -> &mut RdmController<T> {
+        &mut self.controller
+    }
+
+    /// Returns the controller.
+    #[inline]
+    pub fn into_inner(self) -> RdmController<T> {
+        self.controller
+    }
+
+    /// Handles a packet received from `source`, replying through `socket`.
+    ///
+    /// Returns whether the packet was an RDM packet for the gateway's
+    /// port-address. Other packets are left to the caller.
+    pub fn handle(
+        &mut self,
+        socket: &UdpSocket,
+        packet: &[u8],
+        source: SocketAddr,
+    ) -> Result<bool> {
+        match opcode(packet) {
+            Some(OP_TOD_REQUEST) => match decode_tod_request(packet) {
+                Some(ref addresses) if addresses.contains(&self.address) => {
+                    self.send_tod(socket, source)?;
+                    Ok(true)
+                }
+                _ => Ok(false),
+            },
+            Some(OP_TOD_CONTROL) => match decode_tod_flush(packet) {
+                Some(address) if address == self.address => {
+                    self.discover()?;
+                    self.send_tod(socket, source)?;
+                    Ok(true)
+                }
+                _ => Ok(false),
+            },
+            Some(OP_RDM) => match decode_rdm(packet) {
+                Some(rdm) if rdm.address == self.address => {
+                    self.forward(socket, rdm.data, source)?;
+                    Ok(true)
+                }
+                _ => Ok(false),
+            },
+            _ => Ok(false),
+        }
+    }
+
+    /// Sends the devices found, in as many packets as needed.
+    fn send_tod(&self, socket: &UdpSocket, target: SocketAddr) -> Result<()> {
+        let mut buf = [0; TOD_DATA_HEADER_LEN + MAX_TOD_UIDS * 6];
+        let total = self.tod.len().min(usize::from(u16::MAX)) as u16;
+
+        // an empty table is sent as well, so the console knows the port
+        let mut blocks = self.tod.chunks(MAX_TOD_UIDS).peekable();
+        if blocks.peek().is_none() {
+            let len = encode_tod_data(&mut buf, self.address, 0, 0, &[]);
+            socket.send_to(&buf[..len], target)?;
+        }
+
+        for (block, uids) in blocks.enumerate() {
+            let len = encode_tod_data(&mut buf, self.address, total, block as u8, uids);
+            socket.send_to(&buf[..len], target)?;
+        }
+
+        Ok(())
+    }
+
+    /// Passes a request on to the line and its response back to `target`.
+    fn forward(&mut self, socket: &UdpSocket, data: &[u8], target: SocketAddr) -> Result<()> {
+        let mut packet = [0; MAX_PACKET_LEN];
+        if data.len() >= MAX_PACKET_LEN {
+            return Ok(());
+        }
+        packet[0] = SC_RDM;
+        packet[1..=data.len()].copy_from_slice(data);
+
+        let request = match RdmRequest::decode(&packet[..=data.len()]) {
+            Some(request) if request.command_class != CommandClass::Discovery => request,
+            _ => return Ok(()),
+        };
+
+        if let Some(response) = self.controller.forward(&request)? {
+            let mut rdm = [0; MAX_PACKET_LEN];
+            let len = response.encode(&mut rdm);
+
+            let mut buf = [0; RDM_HEADER_LEN + MAX_PACKET_LEN];
+            let len = encode_rdm(&mut buf, self.address, &rdm[1..len]);
+            socket.send_to(&buf[..len], target)?;
+        }
+
+        Ok(())
+    }
+}
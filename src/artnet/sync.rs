@@ -0,0 +1,199 @@
+//! Synchronized output of several universes.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+
+use super::{
+    encode_dmx, next_sequence, write_header, PortAddress, ARTNET_PORT, DMX_HEADER_LEN,
+    PROTOCOL_VERSION,
+};
+use crate::{Result, SocketOptions};
+
+/// Opcode of `ArtSync` packets.
+pub const OP_SYNC: u16 = 0x5200;
+
+// ID, opcode, version, two auxiliary bytes
+const SYNC_LEN: usize = 14;
+
+/// Encodes an `ArtSync` packet into `buf`.
+///
+/// Returns the length of the packet; `buf` must hold at least 14 bytes.
+pub fn encode_sync(buf: &mut [u8]) -> usize {
+    write_header(buf, OP_SYNC);
+    buf[10..12].copy_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+    buf[12] = 0;
+    buf[13] = 0;
+
+    SYNC_LEN
+}
+
+fn broadcast() -> SocketAddr {
+    (Ipv4Addr::BROADCAST, ARTNET_PORT).into()
+}
+
+#[derive(Clone, Debug)]
+struct Universe {
+    destinations: Vec<SocketAddr>,
+    sequence: u8,
+}
+
+/// Sends several Art-Net universes, synchronized through `ArtSync`.
+///
+/// Each frame is sent with `send_frame`, as one `ArtDmx` packet per universe
+/// followed by an `ArtSync`. Nodes supporting synchronization hold back the
+/// data received until the `ArtSync` arrives and then output all universes
+/// at once, so surfaces spanning several universes update without tearing.
+/// Nodes without support ignore it.
+///
+/// Universes are broadcast by default, the `ArtSync` always is unless
+/// another target is set through `set_sync_target`. A universe may also be
+/// sent to several nodes, unicast to each of them, through
+/// `set_destinations`.
+///
+/// ## Example
+///
+/// ```no_run
+/// use dmx::artnet::{ArtNetSource, PortAddress};
+///
+/// let (left, right) = (PortAddress::new(0, 0, 0).unwrap(), PortAddress::new(0, 0, 1).unwrap());
+///
+/// let mut source = ArtNetSource::new().unwrap();
+/// source.set_destination(left, "10.0.0.20:6454".parse().unwrap());
+/// source.set_destination(right, "10.0.0.21:6454".parse().unwrap());
+///
+/// source.send_frame(&[(left, &[0xff; 512][..]), (right, &[0x80; 512][..])]).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ArtNetSource {
+    socket: UdpSocket,
+    universes: BTreeMap<PortAddress, Universe>,
+    sync_target: Option<SocketAddr>,
+    physical: u8,
+    buf: [u8; DMX_HEADER_LEN + 512],
+}
+
+impl ArtNetSource {
+    /// Create a source broadcasting all universes and `ArtSync` packets.
+    #[inline]
+    pub fn new() -> io::Result<ArtNetSource> {
+        ArtNetSource::with_options(&SocketOptions::default())
+    }
+
+    /// Create a source sending through a specific interface.
+    ///
+    /// See `SocketOptions` for how packets are kept on that interface.
+    pub fn with_options(options: &SocketOptions) -> io::Result<ArtNetSource> {
+        let socket = options.bind()?;
+
+        Ok(ArtNetSource {
+            socket,
+            universes: BTreeMap::new(),
+            sync_target: Some(broadcast()),
+            physical: 0,
+            buf: [0; DMX_HEADER_LEN + 512],
+        })
+    }
+
+    fn universe_mut(&mut self, address: PortAddress) -> &mut Universe {
+        self.universes.entry(address).or_insert_with(|| Universe {
+            destinations: vec![broadcast()],
+            sequence: 1,
+        })
+    }
+
+    /// Sends a universe to a node instead of broadcasting it.
+    #[inline]
+    pub fn set_destination(&mut self, address: PortAddress, destination: SocketAddr) {
+        self.set_destinations(address, &[destination]);
+    }
+
+    /// Sends a universe to several nodes, one packet to each of them.
+    ///
+    /// An empty list makes the universe broadcast again.
+    pub fn set_destinations(&mut self, address: PortAddress, destinations: &[SocketAddr]) {
+        let destinations = if destinations.is_empty() {
+            vec![broadcast()]
+        } else {
+            destinations.to_vec()
+        };
+
+        self.universe_mut(address).destinations = destinations;
+    }
+
+    /// Returns where a universe is sent to.
+    pub fn destinations(&self, address: PortAddress) -> Vec<SocketAddr> {
+        match self.universes.get(&address) {
+            Some(universe) => universe.destinations.clone(),
+            None => vec![broadcast()],
+        }
+    }
+
+    /// Sets where `ArtSync` packets are sent to, `None` to not send any.
+    ///
+    /// The standard asks for `ArtSync` to be broadcast, but when all
+    /// universes go to the same node, it may be sent to that node directly.
+    #[inline]
+    pub fn set_sync_target(&mut self, target: Option<SocketAddr>) {
+        self.sync_target = target;
+    }
+
+    /// Returns where `ArtSync` packets are sent to.
+    #[inline]
+    pub fn sync_target(&self) -> Option<SocketAddr> {
+        self.sync_target
+    }
+
+    /// Sets the physical input port reported in packets.
+    ///
+    /// This is informational only and defaults to zero.
+    #[inline]
+    pub fn set_physical(&mut self, physical: u8) {
+        self.physical = physical;
+    }
+
+    /// Sends channel data for a universe as an `ArtDmx` packet, without
+    /// synchronization.
+    ///
+    /// Once a node has received an `ArtSync`, it holds back data sent this
+    /// way until the next one, for a few seconds at most.
+    pub fn send(&mut self, address: PortAddress, channels: &[u8]) -> Result<()> {
+        let physical = self.physical;
+        let sequence = {
+            let universe = self.universe_mut(address);
+            let sequence = universe.sequence;
+            universe.sequence = next_sequence(sequence);
+            sequence
+        };
+
+        let len = encode_dmx(&mut self.buf, address, sequence, physical, channels);
+        for &destination in &self.universes[&address].destinations {
+            self.socket.send_to(&self.buf[..len], destination)?;
+        }
+        Ok(())
+    }
+
+    /// Sends an `ArtSync` packet, making nodes output the data received
+    /// since the last one.
+    ///
+    /// Does nothing if no sync target is set.
+    pub fn send_sync(&mut self) -> Result<()> {
+        if let Some(target) = self.sync_target {
+            let len = encode_sync(&mut self.buf);
+            self.socket.send_to(&self.buf[..len], target)?;
+        }
+        Ok(())
+    }
+
+    /// Sends a frame of several universes, followed by an `ArtSync`.
+    ///
+    /// Stops at the first universe that fails to send, without sending the
+    /// `ArtSync`, so nodes keep outputting the previous frame.
+    pub fn send_frame(&mut self, universes: &[(PortAddress, &[u8])]) -> Result<()> {
+        for &(address, channels) in universes {
+            self.send(address, channels)?;
+        }
+
+        self.send_sync()
+    }
+}
@@ -0,0 +1,286 @@
+//! Asynchronous serial port DMX transmission.
+
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::{io, time};
+
+use serial2::{self, Settings};
+use tokio::io::unix::AsyncFd;
+
+use crate::serial::{
+    apply_settings, baud_error, break_duration, dmx_settings, is_unsupported, output_queue_len,
+    select_break_settings,
+};
+use crate::timing::DmxTiming;
+use crate::{AsyncDmxTransmitter, BreakMethod, DmxPort, Error, Result};
+
+// transmission time of a single byte at 250,000 baud, 8N2
+const BYTE_DURATION: time::Duration = time::Duration::from_micros(44);
+
+/// A serial port with asynchronous DMX support.
+///
+/// Works like `DmxPort`, but waits using `tokio::time::sleep` and writes
+/// through the tokio reactor instead of blocking the current thread. Note
+/// that tokio timers have a resolution of one millisecond, so breaks and
+/// marks-after-break will usually be longer than configured.
+#[derive(Debug)]
+pub struct AsyncDmxPort {
+    fd: AsyncFd<serial2::SerialPort>,
+    break_settings: Settings,
+    // baud rate of the break settings
+    break_rate: u32,
+    dmx_settings: Settings,
+    break_method: BreakMethod,
+    timing: DmxTiming,
+    // set while the port is configured for break transmission
+    in_break_mode: bool,
+    last_break: Option<tokio::time::Instant>,
+    // number of the current frame, for tracing
+    sequence: u64,
+}
+
+impl AsyncDmxPort {
+    /// Opens a serial device for asynchronous DMX transmission.
+    ///
+    /// Other options can be set through `DmxPort::builder` and
+    /// `DmxPortBuilder::open_async`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if not called from within a tokio runtime.
+    #[inline]
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<AsyncDmxPort> {
+        DmxPort::builder(path).open_async()
+    }
+
+    /// Create an asynchronous DMX port from an already opened serial port.
+    ///
+    /// # Panics
+    ///
+    /// Panics if not called from within a tokio runtime.
+    #[inline]
+    pub fn from_serial_port(port: serial2::SerialPort) -> Result<AsyncDmxPort> {
+        AsyncDmxPort::with_options(port, BreakMethod::default(), DmxTiming::default())
+    }
+
+    pub(crate) fn with_options(
+        mut port: serial2::SerialPort,
+        break_method: BreakMethod,
+        timing: DmxTiming,
+    ) -> Result<AsyncDmxPort> {
+        timing.validate()?;
+        let current = port.get_configuration()?;
+        let dmx_settings = dmx_settings(current.clone())?;
+        let (break_settings, break_rate) =
+            select_break_settings(&mut port, current, timing.break_us)?;
+
+        apply_settings(&mut port, &dmx_settings).map_err(baud_error)?;
+
+        // serial2 opens devices in non-blocking mode already
+        Ok(AsyncDmxPort {
+            fd: AsyncFd::new(port)?,
+            break_settings,
+            break_rate,
+            dmx_settings,
+            break_method,
+            timing,
+            in_break_mode: false,
+            last_break: None,
+            sequence: 0,
+        })
+    }
+
+    /// Returns the current timing parameters.
+    #[inline]
+    pub fn timing(&self) -> DmxTiming {
+        self.timing
+    }
+
+    /// Sets the timing parameters used for all following packets.
+    ///
+    /// Fails with `Error::InvalidTiming` if the timing is not within the
+    /// limits of the standard, see `DmxTiming::validate`.
+    pub fn set_timing(&mut self, timing: DmxTiming) -> Result<()> {
+        timing.validate()?;
+
+        let (settings, rate) =
+            select_break_settings(self.fd.get_mut(), self.dmx_settings.clone(), timing.break_us)?;
+        self.break_settings = settings;
+        self.break_rate = rate;
+        self.timing = timing;
+
+        // force reconfiguration on next data transmission
+        self.in_break_mode = true;
+        Ok(())
+    }
+
+    /// Returns the break method in use.
+    ///
+    /// May differ from the requested method if a fallback occurred.
+    #[inline]
+    pub fn break_method(&self) -> BreakMethod {
+        self.break_method
+    }
+
+    /// Returns the underlying serial port.
+    #[inline]
+    pub fn into_inner(self) -> serial2::SerialPort {
+        self.fd.into_inner()
+    }
+
+    fn enter_dmx_mode(&mut self) -> Result<()> {
+        if self.in_break_mode {
+            apply_settings(self.fd.get_mut(), &self.dmx_settings).map_err(baud_error)?;
+            self.in_break_mode = false;
+        }
+        Ok(())
+    }
+
+    async fn write_all(&self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            let mut guard = self.fd.writable().await?;
+
+            match guard.try_io(|fd| {
+                let rv = unsafe {
+                    ::libc::write(fd.as_raw_fd(), data.as_ptr() as *const _, data.len())
+                };
+
+                if rv < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(rv as usize)
+            }) {
+                Ok(written) => data = &data[written?..],
+                // spurious readiness, wait again
+                Err(_) => continue,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Waits until all pending output has been transmitted.
+    ///
+    /// `tcdrain` would block, so the output queue is polled instead.
+    async fn drain(&self) -> io::Result<()> {
+        #[cfg(feature = "tracing")]
+        let start = time::Instant::now();
+
+        loop {
+            let pending = output_queue_len(self.fd.get_ref())?;
+
+            if pending == 0 {
+                trace_event!(
+                    trace,
+                    elapsed_us = start.elapsed().as_micros() as u64,
+                    "output drained"
+                );
+                return Ok(());
+            }
+
+            tokio::time::sleep(BYTE_DURATION * pending as u32).await;
+        }
+    }
+
+    /// Sends a break byte at a lower baud rate, returning the duration of
+    /// the break it generates.
+    async fn send_baud_rate_break(&mut self) -> io::Result<time::Duration> {
+        apply_settings(self.fd.get_mut(), &self.break_settings)?;
+        self.in_break_mode = true;
+        self.write_all(&[0x00]).await?;
+
+        Ok(break_duration(self.break_rate))
+    }
+
+    /// Asserts the break condition, returning its duration.
+    async fn send_ioctl_break(&mut self) -> io::Result<time::Duration> {
+        // the break condition would otherwise cut off any pending data
+        self.drain().await?;
+
+        let start = time::Instant::now();
+        self.fd.get_ref().set_break(true)?;
+        tokio::time::sleep(self.timing.break_duration()).await;
+        self.fd.get_ref().set_break(false)?;
+
+        Ok(start.elapsed())
+    }
+
+    /// Sends a break, followed by a packet.
+    async fn send_frame(&mut self, data: &[u8]) -> Result<()> {
+        // honor the minimum break-to-break time
+        #[cfg(feature = "tracing")]
+        let previous = self.last_break;
+        if let Some(last_break) = self.last_break {
+            tokio::time::sleep_until(last_break + self.timing.inter_frame_duration()).await;
+        }
+
+        let start = tokio::time::Instant::now();
+        self.last_break = Some(start);
+        self.send_break().await?;
+        tokio::time::sleep(match self.break_method {
+            // the break byte is still being transmitted at this point
+            BreakMethod::BaudRate => {
+                break_duration(self.break_rate) + self.timing.mab_duration()
+            }
+            BreakMethod::Ioctl => self.timing.mab_duration(),
+        })
+        .await;
+
+        let result = self.send_raw_data(data).await;
+        #[cfg(feature = "tracing")]
+        match result {
+            Ok(()) => tracing::trace!(
+                len = data.len(),
+                interval_us = previous.map(|p| (start - p).as_micros() as u64),
+                "frame sent"
+            ),
+            Err(ref e) => tracing::warn!(error = %e, "frame failed"),
+        }
+
+        result
+    }
+}
+
+impl AsyncDmxTransmitter for AsyncDmxPort {
+    async fn send_break(&mut self) -> Result<()> {
+        let _duration = match self.break_method {
+            BreakMethod::BaudRate => self.send_baud_rate_break().await,
+            BreakMethod::Ioctl => match self.send_ioctl_break().await {
+                Err(ref e) if is_unsupported(e) => {
+                    self.break_method = BreakMethod::BaudRate;
+                    self.send_baud_rate_break().await
+                }
+                rv => rv,
+            },
+        }
+        .map_err(Error::BreakFailed)?;
+
+        trace_event!(
+            trace,
+            method = ?self.break_method,
+            duration_us = _duration.as_micros() as u64,
+            "break sent"
+        );
+        Ok(())
+    }
+
+    async fn send_raw_data(&mut self, data: &[u8]) -> Result<()> {
+        self.enter_dmx_mode()?;
+        self.write_all(data).await?;
+
+        trace_event!(trace, len = data.len(), "data written");
+        Ok(())
+    }
+
+    async fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<()> {
+        self.sequence = self.sequence.wrapping_add(1);
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!("dmx_frame", sequence = self.sequence);
+
+        let frame = self.send_frame(data);
+        #[cfg(feature = "tracing")]
+        let frame = tracing::Instrument::instrument(frame, span);
+
+        frame.await
+    }
+}
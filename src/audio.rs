@@ -0,0 +1,550 @@
+//! Audio-reactive effects.
+//!
+//! An `AudioAnalyzer` measures the loudness of audio samples fed to it and
+//! detects beats, in the form of sudden rises of energy in the bass range.
+//! With the `audio` feature, an `AudioInput` captures audio from a sound
+//! card and feeds it to an analyzer.
+//!
+//! The analysis is shared through an `AudioSource`, which modulates the
+//! effects of an `EffectEngine`:
+//!
+//! * `FollowLevel` scales the output of another effect by the loudness,
+//! * `BeatFlash` flashes all channels of a range on every beat.
+//!
+//! Unlike other effects, these depend on the audio as it is playing rather
+//! than on the time they have been running, so they cannot be seeked.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "audio")]
+//! # fn main() {
+//! use std::{thread, time};
+//! use dmx::{DmxAddress, DmxTransmitter, DmxUniverse};
+//! use dmx::audio::{AudioInput, BeatFlash, FollowLevel};
+//! use dmx::effects::{EffectEngine, Rainbow};
+//!
+//! let input = AudioInput::open_default().unwrap();
+//!
+//! let mut engine = EffectEngine::new();
+//! // eight RGB pars pulsing with the music, a strobe flashing on beats
+//! let rainbow = FollowLevel::new(input.source(), Rainbow::new(0.1));
+//! engine.add(DmxAddress::new(1).unwrap(), 24, rainbow);
+//! engine.add(DmxAddress::new(25).unwrap(), 1, BeatFlash::new(input.source()));
+//!
+//! let mut dmx_port = dmx::open_serial("/dev/ttyUSB0").unwrap();
+//! let mut universe = DmxUniverse::new();
+//! let period = time::Duration::from_millis(25);
+//!
+//! loop {
+//!     engine.tick(period, &mut universe);
+//!     dmx_port.send_universe(&universe).unwrap();
+//!     thread::sleep(period);
+//! }
+//! # }
+//! # #[cfg(not(feature = "audio"))]
+//! # fn main() {}
+//! ```
+
+use std::f32::consts::TAU;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::{fmt, time};
+
+use crate::effects::Effect;
+
+/// Number of analysis windows per second, each a little over 20ms.
+const WINDOWS_PER_SECOND: u32 = 43;
+
+/// Number of windows whose energy is averaged to detect beats, a second.
+const HISTORY: usize = WINDOWS_PER_SECOND as usize;
+
+/// Cutoff frequency of the low-pass filter applied before detecting beats,
+/// in Hz.
+const BASS_CUTOFF: f32 = 150.0;
+
+/// Shortest time between beats, limiting detection to 300 beats per minute.
+const MIN_BEAT_INTERVAL: time::Duration = time::Duration::from_millis(200);
+
+/// RMS below which audio is considered silent, neither raising the level
+/// nor triggering beats.
+const NOISE_FLOOR: f32 = 0.005;
+
+/// Time for the level to fall to a tenth when audio becomes quieter.
+const LEVEL_RELEASE: f32 = 0.3;
+
+/// Time for the loudest RMS measured, to which levels are relative, to fall
+/// to a tenth.
+const PEAK_RELEASE: f32 = 10.0;
+
+/// Results of an `AudioAnalyzer`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AudioAnalysis {
+    /// Loudness from 0 to 1, relative to the loudest audio of the last few
+    /// seconds.
+    ///
+    /// Rises instantly and falls off smoothly.
+    pub level: f32,
+    /// Root mean square of the samples of the last window, from 0 to 1.
+    pub rms: f32,
+    /// Number of beats detected so far.
+    pub beats: u64,
+    /// Time of the last beat.
+    pub last_beat: Option<time::Instant>,
+}
+
+impl AudioAnalysis {
+    /// Returns the time since the last beat, if any.
+    #[inline]
+    pub fn since_beat(&self, now: time::Instant) -> Option<time::Duration> {
+        self.last_beat.map(|beat| now.saturating_duration_since(beat))
+    }
+
+    /// Returns an envelope starting at 1 on every beat and falling to 0
+    /// over `decay`, e.g. to modulate parameters of other effects.
+    pub fn beat_envelope(&self, now: time::Instant, decay: time::Duration) -> f32 {
+        match self.since_beat(now) {
+            Some(t) if t < decay => 1.0 - t.as_secs_f32() / decay.as_secs_f32(),
+            _ => 0.0,
+        }
+    }
+}
+
+impl Default for AudioAnalysis {
+    fn default() -> AudioAnalysis {
+        AudioAnalysis {
+            level: 0.0,
+            rms: 0.0,
+            beats: 0,
+            last_beat: None,
+        }
+    }
+}
+
+/// Measures loudness and detects beats.
+///
+/// Samples are analyzed in windows of a little over 20ms. A beat is
+/// detected when the energy of a window in the bass range exceeds the
+/// average of the last second by the sensitivity.
+#[derive(Clone, Debug)]
+pub struct AudioAnalyzer {
+    channels: usize,
+    window: usize,
+    // low-pass filter coefficient and state
+    alpha: f32,
+    bass: f32,
+    // sums of the current window
+    count: usize,
+    sum: f32,
+    bass_sum: f32,
+    history: Vec<f32>,
+    next: usize,
+    sensitivity: f32,
+    peak: f32,
+    // release factors per window
+    level_release: f32,
+    peak_release: f32,
+    analysis: AudioAnalysis,
+}
+
+/// Returns the factor by which a value is multiplied every window to fall to
+/// a tenth over `seconds`.
+fn release(seconds: f32) -> f32 {
+    0.1f32.powf(1.0 / (seconds * WINDOWS_PER_SECOND as f32))
+}
+
+impl AudioAnalyzer {
+    /// Create an analyzer of `channels` interleaved channels, sampled at
+    /// `sample_rate` Hz.
+    ///
+    /// Channels are mixed down before analyzing them.
+    pub fn new(sample_rate: u32, channels: u16) -> AudioAnalyzer {
+        let sample_rate = sample_rate.max(WINDOWS_PER_SECOND);
+
+        AudioAnalyzer {
+            channels: usize::from(channels.max(1)),
+            window: (sample_rate / WINDOWS_PER_SECOND) as usize,
+            alpha: 1.0 - (-TAU * BASS_CUTOFF / sample_rate as f32).exp(),
+            bass: 0.0,
+            count: 0,
+            sum: 0.0,
+            bass_sum: 0.0,
+            history: Vec::with_capacity(HISTORY),
+            next: 0,
+            sensitivity: 1.5,
+            peak: NOISE_FLOOR,
+            level_release: release(LEVEL_RELEASE),
+            peak_release: release(PEAK_RELEASE),
+            analysis: AudioAnalysis::default(),
+        }
+    }
+
+    /// Returns the factor by which the energy of a beat exceeds the average.
+    #[inline]
+    pub fn sensitivity(&self) -> f32 {
+        self.sensitivity
+    }
+
+    /// Set the factor by which the energy of a beat exceeds the average.
+    ///
+    /// Lower values detect more beats; the default of 1.5 suits most music
+    /// with a pronounced kick drum.
+    #[inline]
+    pub fn set_sensitivity(&mut self, sensitivity: f32) {
+        self.sensitivity = sensitivity;
+    }
+
+    /// Returns the results of the last complete window.
+    #[inline]
+    pub fn analysis(&self) -> AudioAnalysis {
+        self.analysis
+    }
+
+    /// Analyzes interleaved samples from -1 to 1, received at `now`.
+    ///
+    /// Beats detected are timestamped with `now`, so samples should be fed
+    /// as they arrive.
+    pub fn feed(&mut self, samples: &[f32], now: time::Instant) {
+        for frame in samples.chunks(self.channels) {
+            let x = frame.iter().sum::<f32>() / frame.len() as f32;
+            self.bass += self.alpha * (x - self.bass);
+
+            self.sum += x * x;
+            self.bass_sum += self.bass * self.bass;
+            self.count += 1;
+
+            if self.count == self.window {
+                self.end_window(now);
+            }
+        }
+    }
+
+    fn end_window(&mut self, now: time::Instant) {
+        let rms = (self.sum / self.count as f32).sqrt().min(1.0);
+        let energy = self.bass_sum / self.count as f32;
+        self.count = 0;
+        self.sum = 0.0;
+        self.bass_sum = 0.0;
+
+        self.peak = (self.peak * self.peak_release).max(rms).max(NOISE_FLOOR);
+        let level = if rms > NOISE_FLOOR { rms / self.peak } else { 0.0 };
+
+        let analysis = &mut self.analysis;
+        analysis.rms = rms;
+        analysis.level = (analysis.level * self.level_release).max(level);
+
+        // only detect beats once the average covers a full second
+        if self.history.len() == HISTORY {
+            let average = self.history.iter().sum::<f32>() / HISTORY as f32;
+            let ready = analysis.since_beat(now).is_none_or(|t| t >= MIN_BEAT_INTERVAL);
+
+            if ready && rms > NOISE_FLOOR && energy > average * self.sensitivity {
+                analysis.beats += 1;
+                analysis.last_beat = Some(now);
+            }
+
+            self.history[self.next] = energy;
+            self.next = (self.next + 1) % HISTORY;
+        } else {
+            self.history.push(energy);
+        }
+    }
+}
+
+/// An `AudioAnalyzer` shared between the thread feeding it and effects.
+///
+/// Clones share the same analyzer.
+#[derive(Clone)]
+pub struct AudioSource {
+    analyzer: Arc<Mutex<AudioAnalyzer>>,
+}
+
+impl AudioSource {
+    /// Create a source sharing `analyzer`.
+    #[inline]
+    pub fn new(analyzer: AudioAnalyzer) -> AudioSource {
+        AudioSource {
+            analyzer: Arc::new(Mutex::new(analyzer)),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, AudioAnalyzer> {
+        // the analyzer stays consistent even if a thread panicked
+        self.analyzer.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Analyzes interleaved samples received just now, see
+    /// `AudioAnalyzer::feed`.
+    #[inline]
+    pub fn feed(&self, samples: &[f32]) {
+        self.lock().feed(samples, time::Instant::now());
+    }
+
+    /// Returns the current results.
+    #[inline]
+    pub fn analysis(&self) -> AudioAnalysis {
+        self.lock().analysis()
+    }
+
+    /// Set the sensitivity of beat detection, see
+    /// `AudioAnalyzer::set_sensitivity`.
+    #[inline]
+    pub fn set_sensitivity(&self, sensitivity: f32) {
+        self.lock().set_sensitivity(sensitivity);
+    }
+}
+
+impl fmt::Debug for AudioSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AudioSource")
+            .field("analysis", &self.analysis())
+            .finish()
+    }
+}
+
+/// Scales the output of an effect by the loudness of the audio.
+///
+/// ```
+/// use std::time;
+/// use dmx::audio::{AudioAnalyzer, AudioSource, FollowLevel};
+/// use dmx::effects::{Effect, SineWave};
+///
+/// let source = AudioSource::new(AudioAnalyzer::new(48_000, 2));
+/// let mut effect = FollowLevel::new(source, SineWave::new(1.0));
+/// effect.floor = 0.2;
+///
+/// // silence dims to the floor
+/// let mut channels = [0; 1];
+/// effect.render(time::Duration::from_millis(500), &mut channels);
+/// assert_eq!(channels, [51]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct FollowLevel<E> {
+    /// Analysis followed.
+    pub source: AudioSource,
+    /// Effect whose output is scaled.
+    pub effect: E,
+    /// Fraction of its output the effect keeps during silence, from 0 to 1.
+    pub floor: f32,
+}
+
+impl<E> FollowLevel<E> {
+    /// Create an effect following the level of `source`, going dark during
+    /// silence.
+    #[inline]
+    pub fn new(source: AudioSource, effect: E) -> FollowLevel<E> {
+        FollowLevel {
+            source,
+            effect,
+            floor: 0.0,
+        }
+    }
+}
+
+impl<E: Effect> Effect for FollowLevel<E> {
+    fn render(&self, t: time::Duration, channels: &mut [u8]) {
+        self.effect.render(t, channels);
+
+        let floor = self.floor.clamp(0.0, 1.0);
+        let factor = floor + (1.0 - floor) * self.source.analysis().level;
+        for v in channels {
+            *v = (f32::from(*v) * factor).round() as u8;
+        }
+    }
+}
+
+/// Flashes all channels of a range on every beat.
+#[derive(Clone, Debug)]
+pub struct BeatFlash {
+    /// Analysis whose beats trigger flashes.
+    pub source: AudioSource,
+    /// Time channels stay at `level` after a beat.
+    pub hold: time::Duration,
+    /// Time channels fade out over after `hold`.
+    pub decay: time::Duration,
+    /// Value of the channels during a flash; they are zero otherwise.
+    pub level: u8,
+}
+
+impl BeatFlash {
+    /// Create a flash at full for 50ms, without fading out.
+    #[inline]
+    pub fn new(source: AudioSource) -> BeatFlash {
+        BeatFlash {
+            source,
+            hold: time::Duration::from_millis(50),
+            decay: time::Duration::ZERO,
+            level: 0xff,
+        }
+    }
+}
+
+impl Effect for BeatFlash {
+    fn render(&self, _t: time::Duration, channels: &mut [u8]) {
+        let analysis = self.source.analysis();
+        let now = time::Instant::now();
+
+        let value = match analysis.since_beat(now) {
+            Some(t) if t < self.hold => 1.0,
+            Some(t) if t - self.hold < self.decay => {
+                1.0 - (t - self.hold).as_secs_f32() / self.decay.as_secs_f32()
+            }
+            _ => 0.0,
+        };
+        for v in channels {
+            *v = (f32::from(self.level) * value).round() as u8;
+        }
+    }
+}
+
+/// Captures audio from a sound card into an `AudioSource`.
+///
+/// Samples are analyzed on a thread of the audio backend as they arrive.
+/// Dropping the input stops capturing. Errors of the backend after opening
+/// are ignored; the analysis stops changing if the device disappears.
+#[cfg(feature = "audio")]
+pub struct AudioInput {
+    // kept for capturing to continue
+    _stream: cpal::Stream,
+    source: AudioSource,
+    device: String,
+}
+
+#[cfg(feature = "audio")]
+impl AudioInput {
+    /// Captures audio from the default input device of the system.
+    pub fn open_default() -> crate::Result<AudioInput> {
+        use cpal::traits::HostTrait;
+
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or(crate::Error::InvalidParameter("no audio input device"))?;
+
+        AudioInput::with_device(device)
+    }
+
+    /// Captures audio from the first input device whose name contains
+    /// `device`.
+    ///
+    /// Fails with `Error::InvalidParameter` if there is no such device. See
+    /// `AudioInput::devices` for the names of all devices.
+    pub fn open(device: &str) -> crate::Result<AudioInput> {
+        use cpal::traits::{DeviceTrait, HostTrait};
+
+        let found = cpal::default_host()
+            .input_devices()
+            .map_err(audio_error)?
+            .find(|d| d.name().is_ok_and(|name| name.contains(device)))
+            .ok_or(crate::Error::InvalidParameter("no such audio input device"))?;
+
+        AudioInput::with_device(found)
+    }
+
+    /// Returns the names of all input devices.
+    pub fn devices() -> crate::Result<Vec<String>> {
+        use cpal::traits::{DeviceTrait, HostTrait};
+
+        let devices = cpal::default_host().input_devices().map_err(audio_error)?;
+        Ok(devices.filter_map(|d| d.name().ok()).collect())
+    }
+
+    fn with_device(device: cpal::Device) -> crate::Result<AudioInput> {
+        use cpal::traits::{DeviceTrait, StreamTrait};
+        use cpal::SampleFormat;
+
+        let name = device.name().map_err(audio_error)?;
+        let supported = device.default_input_config().map_err(audio_error)?;
+        let format = supported.sample_format();
+        let config = supported.config();
+
+        let source = AudioSource::new(AudioAnalyzer::new(config.sample_rate.0, config.channels));
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, source.clone()),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, source.clone()),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, source.clone()),
+            SampleFormat::I32 => build_stream::<i32>(&device, &config, source.clone()),
+            _ => return Err(crate::Error::Unsupported("audio sample format")),
+        }?;
+        stream.play().map_err(audio_error)?;
+
+        Ok(AudioInput {
+            _stream: stream,
+            source,
+            device: name,
+        })
+    }
+
+    /// Returns the source analyzing the captured audio.
+    #[inline]
+    pub fn source(&self) -> AudioSource {
+        self.source.clone()
+    }
+
+    /// Returns the current results, see `AudioSource::analysis`.
+    #[inline]
+    pub fn analysis(&self) -> AudioAnalysis {
+        self.source.analysis()
+    }
+
+    /// Returns the name of the device being captured.
+    #[inline]
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    /// Stops capturing.
+    #[inline]
+    pub fn close(self) {}
+}
+
+#[cfg(feature = "audio")]
+impl fmt::Debug for AudioInput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AudioInput")
+            .field("device", &self.device)
+            .field("analysis", &self.analysis())
+            .finish()
+    }
+}
+
+#[cfg(feature = "audio")]
+fn audio_error<E: fmt::Display>(e: E) -> crate::Error {
+    crate::Error::Io(std::io::Error::other(e.to_string()))
+}
+
+#[cfg(feature = "audio")]
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    source: AudioSource,
+) -> crate::Result<cpal::Stream>
+where
+    T: cpal::SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    use cpal::traits::DeviceTrait;
+
+    // converted in chunks of whole frames, to avoid allocating on the audio
+    // thread
+    let mut buffer = [0.0; 512];
+    let chunk_len = buffer.len() - buffer.len() % usize::from(config.channels.max(1));
+
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                let now = time::Instant::now();
+                let mut analyzer = source.lock();
+
+                for chunk in data.chunks(chunk_len) {
+                    let samples = &mut buffer[..chunk.len()];
+                    for (x, s) in samples.iter_mut().zip(chunk) {
+                        *x = s.to_sample::<f32>();
+                    }
+                    analyzer.feed(samples, now);
+                }
+            },
+            |_| (),
+            None,
+        )
+        .map_err(audio_error)
+}
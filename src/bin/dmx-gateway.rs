@@ -0,0 +1,292 @@
+//! Art-Net and sACN to serial DMX gateway.
+//!
+//! Receives universes from the network and sends them out through serial
+//! ports. Reads its configuration from the file given as the only argument,
+//! `/etc/dmx-gateway.toml` by default:
+//!
+//! ```toml
+//! # frame rate of the serial outputs, defaults to 40
+//! fps = 40
+//! # name reported to Art-Net consoles, defaults to "dmx-gateway"
+//! name = "dmx-gateway"
+//!
+//! [[output]]
+//! port = "/dev/ttyAMA0"
+//! protocol = "sacn"
+//! universe = 1
+//!
+//! [[output]]
+//! port = "/dev/ttyUSB0"
+//! protocol = "artnet"
+//! # 15-bit port-address, combining net, sub-net and universe
+//! universe = 0
+//!
+//! # move channels 1-24 to 101-124 at 80%, dropping all others
+//! [[output.remap]]
+//! from = 1
+//! to = 101
+//! # defaults to 1
+//! count = 24
+//! # defaults to 1.0
+//! scale = 0.8
+//! ```
+//!
+//! A universe may be sent through several ports. Outputs with a `remap`
+//! only send the channels mapped, all others are set to zero.
+//!
+//! The gateway shows up on Art-Net consoles as a node with a port for each
+//! Art-Net universe received. Consoles can rename it and move its ports to
+//! other port-addresses through `ArtAddress`; such changes last until the
+//! gateway is restarted.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::{env, fs, process, thread};
+
+use dmx::artnet::{ArtNetNode, ArtNetReceiver, Indicators, NodeConfig, PortAddress};
+use dmx::sacn::SacnReceiver;
+use dmx::{ChannelRemap, DmxAddress, DmxOutputManager, SharedUniverse};
+use serde::Deserialize;
+
+const DEFAULT_CONFIG: &str = "/etc/dmx-gateway.toml";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default = "default_fps")]
+    fps: f32,
+    #[serde(default = "default_name")]
+    name: String,
+    output: Vec<OutputConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OutputConfig {
+    port: String,
+    protocol: Protocol,
+    universe: u16,
+    #[serde(default)]
+    remap: Vec<RemapConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RemapConfig {
+    from: u16,
+    to: u16,
+    #[serde(default = "default_count")]
+    count: usize,
+    #[serde(default = "default_scale")]
+    scale: f32,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+enum Protocol {
+    Artnet,
+    Sacn,
+}
+
+fn default_fps() -> f32 {
+    40.0
+}
+
+fn default_name() -> String {
+    "dmx-gateway".to_owned()
+}
+
+fn default_count() -> usize {
+    1
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+/// A universe fed by a received universe, through a remap if configured.
+struct Target {
+    universe: SharedUniverse,
+    remap: Option<ChannelRemap>,
+}
+
+fn remap(config: &[RemapConfig]) -> Result<Option<ChannelRemap>, Box<dyn Error>> {
+    if config.is_empty() {
+        return Ok(None);
+    }
+
+    let mut remap = ChannelRemap::new();
+    for entry in config {
+        let address = |n| DmxAddress::new(n).ok_or_else(|| format!("invalid channel {}", n));
+        remap.map_scaled(address(entry.from)?, address(entry.to)?, entry.count, entry.scale)?;
+    }
+    Ok(Some(remap))
+}
+
+fn main() {
+    let path = env::args_os()
+        .nth(1)
+        .map_or_else(|| PathBuf::from(DEFAULT_CONFIG), PathBuf::from);
+
+    if let Err(e) = run(path) {
+        eprintln!("dmx-gateway: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(path: PathBuf) -> Result<(), Box<dyn Error>> {
+    let config = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let config: Config = toml::from_str(&config)?;
+
+    if config.output.is_empty() {
+        return Err("no outputs configured".into());
+    }
+    if config.fps.is_nan() || config.fps <= 0.0 {
+        return Err("frame rate must be positive".into());
+    }
+
+    let mut outputs = DmxOutputManager::new();
+    let mut artnet = BTreeMap::new();
+    let mut sacn = BTreeMap::new();
+
+    // the manager numbers universes regardless of their protocol, assign a
+    // number to each distinct universe received, and one to each remapped
+    // output of its own
+    let mut numbers = BTreeMap::new();
+    let mut next = 0;
+
+    for output in &config.output {
+        let remap = remap(&output.remap).map_err(|e| format!("{}: {}", output.port, e))?;
+        let (number, new) = match remap {
+            Some(_) => (next, true),
+            None => match numbers.get(&(output.protocol, output.universe)) {
+                Some(&n) => (n, false),
+                None => {
+                    numbers.insert((output.protocol, output.universe), next);
+                    (next, true)
+                }
+            },
+        };
+        if new {
+            next += 1;
+        }
+
+        let port = dmx::open_serial(&output.port).map_err(|e| format!("{}: {}", output.port, e))?;
+        let universe = outputs.add_output(number, Box::new(port));
+        if !new {
+            continue;
+        }
+
+        let target = Target { universe, remap };
+        match output.protocol {
+            Protocol::Artnet => {
+                let address = PortAddress::from_u16(output.universe)
+                    .ok_or_else(|| format!("invalid Art-Net port-address {}", output.universe))?;
+                artnet.entry(address).or_insert_with(Vec::new).push(target);
+            }
+            Protocol::Sacn => {
+                sacn.entry(output.universe).or_insert_with(Vec::new).push(target);
+            }
+        }
+    }
+
+    if !artnet.is_empty() {
+        let addresses: Vec<_> = artnet.keys().copied().collect();
+        let mut receiver = ArtNetReceiver::new(&addresses)?;
+        let artnet = Arc::new(Mutex::new(artnet));
+
+        let mut node_config = NodeConfig::new(&config.name, &config.name);
+        node_config.outputs = addresses;
+        let mut node = ArtNetNode::new(node_config);
+        node.on_change(reconfigure(Arc::clone(&artnet), node.config().outputs.clone()));
+        receiver.set_node(node);
+
+        thread::spawn(move || {
+            let rv = receiver.run(|frame| {
+                let artnet = artnet.lock().unwrap_or_else(|e| e.into_inner());
+                for target in artnet.get(&frame.address).into_iter().flatten() {
+                    store(target, &frame.channels);
+                }
+            });
+
+            if let Err(e) = rv {
+                eprintln!("dmx-gateway: Art-Net: {}", e);
+                process::exit(1);
+            }
+        });
+    }
+
+    if !sacn.is_empty() {
+        let universes: Vec<_> = sacn.keys().copied().collect();
+        let mut receiver = SacnReceiver::new(&universes)?;
+
+        thread::spawn(move || loop {
+            match receiver.recv() {
+                Ok(n) => {
+                    if let (Some(targets), Some(merged)) = (sacn.get(&n), receiver.universe(n)) {
+                        for target in targets {
+                            store(target, merged.channels());
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("dmx-gateway: sACN: {}", e);
+                    process::exit(1);
+                }
+            }
+        });
+    }
+
+    let stop = AtomicBool::new(false);
+    outputs.run(config.fps, &stop)?;
+
+    Ok(())
+}
+
+/// Returns the callback moving the targets of Art-Net ports changed by a
+/// console to their new port-addresses.
+fn reconfigure(
+    artnet: Arc<Mutex<BTreeMap<PortAddress, Vec<Target>>>>,
+    mut ports: Vec<PortAddress>,
+) -> impl FnMut(&NodeConfig, Indicators) + Send + 'static {
+    move |config, indicators| {
+        let mut artnet = artnet.lock().unwrap_or_else(|e| e.into_inner());
+
+        // take all targets first, ports may have swapped port-addresses
+        let moved: Vec<_> = ports
+            .iter()
+            .zip(&config.outputs)
+            .filter(|(old, new)| old != new)
+            .filter_map(|(old, &new)| Some((new, artnet.remove(old)?)))
+            .collect();
+        for (address, targets) in moved {
+            artnet.entry(address).or_default().extend(targets);
+        }
+        ports = config.outputs.clone();
+
+        let addresses: Vec<_> = ports.iter().map(PortAddress::to_string).collect();
+        eprintln!(
+            "dmx-gateway: Art-Net: now \"{}\" at {}, indicators {:?}",
+            config.short_name,
+            addresses.join(", "),
+            indicators
+        );
+    }
+}
+
+/// Replaces the channels of a universe, zeroing those not received.
+fn store(target: &Target, channels: &[u8]) {
+    target.universe.update(|u| match target.remap {
+        Some(ref remap) => remap.apply(channels, u),
+        None => {
+            let (received, rest) = u.channels_mut().split_at_mut(channels.len().min(512));
+            received.copy_from_slice(&channels[..received.len()]);
+            for v in rest {
+                *v = 0;
+            }
+        }
+    });
+}
@@ -0,0 +1,174 @@
+//! Sends test patterns through a serial port.
+//!
+//! ```text
+//! dmx-send /dev/ttyAMA0 --channel 1=255 --channel 2=128
+//! dmx-send /dev/ttyUSB0 --all 64 --fps 30
+//! dmx-send /dev/ttyUSB0 --chase --channels 24
+//! dmx-send /dev/ttyUSB0 --probe
+//! ```
+//!
+//! Runs until interrupted, unless probing.
+
+use std::{env, process, thread, time};
+
+use dmx::{DmxAddress, DmxTransmitter};
+
+const USAGE: &str = "\
+usage: dmx-send PORT [OPTIONS]
+
+Sends DMX continuously until interrupted.
+
+options:
+    -c, --channel N=VALUE  set channel N (1-512) to VALUE (0-255)
+    -a, --all VALUE        set all channels to VALUE
+    -r, --ramp             fade all channels up from 0 to 255, repeatedly
+    -C, --chase            set one channel after another to full
+    -n, --channels COUNT   number of channels, 512 by default
+    -f, --fps FPS          frames per second, 40 by default
+    -p, --probe            check whether the port can send DMX, then exit
+    -h, --help             show this help";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Pattern {
+    Static,
+    Ramp,
+    Chase,
+}
+
+#[derive(Debug)]
+struct Options {
+    port: String,
+    channels: [u8; 512],
+    count: usize,
+    pattern: Pattern,
+    fps: f32,
+    probe: bool,
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("dmx-send: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if options.probe {
+        match dmx::probe_port(&options.port) {
+            Ok(report) => {
+                print!("{}", report);
+                if report.support == dmx::Support::Unsupported {
+                    process::exit(1);
+                }
+            }
+            Err(e) => {
+                eprintln!("dmx-send: {}: {}", options.port, e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Err(e) = run(&options) {
+        eprintln!("dmx-send: {}: {}", options.port, e);
+        process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> dmx::Result<()> {
+    let mut dmx_port = dmx::open_serial(&options.port)?;
+
+    let period = time::Duration::from_secs_f32(1.0 / options.fps);
+    let mut data = options.channels;
+    let mut next = time::Instant::now();
+
+    for frame in 0usize.. {
+        match options.pattern {
+            Pattern::Static => (),
+            Pattern::Ramp => data = [frame as u8; 512],
+            Pattern::Chase => {
+                data = [0; 512];
+                data[frame % options.count] = 0xff;
+            }
+        }
+
+        dmx_port.send_dmx_packet(&data[..options.count])?;
+
+        next += period;
+        let now = time::Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        } else {
+            next = now;
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses the command line, returning `None` if help was requested.
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        port: String::new(),
+        channels: [0; 512],
+        count: 512,
+        pattern: Pattern::Static,
+        fps: 40.0,
+        probe: false,
+    };
+    let mut port = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} requires a value", name));
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-c" | "--channel" => {
+                let spec = value(&arg)?;
+                let (n, v) = spec
+                    .split_once('=')
+                    .ok_or_else(|| format!("invalid channel {:?}, expected N=VALUE", spec))?;
+                let n = n
+                    .parse()
+                    .ok()
+                    .and_then(DmxAddress::new)
+                    .ok_or_else(|| format!("invalid channel number {:?}", n))?;
+
+                options.channels[n.index()] = parse_value(v)?;
+            }
+            "-a" | "--all" => options.channels = [parse_value(&value(&arg)?)?; 512],
+            "-r" | "--ramp" => options.pattern = Pattern::Ramp,
+            "-C" | "--chase" => options.pattern = Pattern::Chase,
+            "-n" | "--channels" => {
+                let count = value(&arg)?;
+                options.count = match count.parse() {
+                    Ok(n) if (1..=512).contains(&n) => n,
+                    _ => return Err(format!("invalid channel count {:?}", count)),
+                };
+            }
+            "-f" | "--fps" => {
+                let fps = value(&arg)?;
+                options.fps = match fps.parse::<f32>() {
+                    Ok(f) if f > 0.0 && f.is_finite() => f,
+                    _ => return Err(format!("invalid frame rate {:?}", fps)),
+                };
+            }
+            "-p" | "--probe" => options.probe = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ if port.is_none() => port = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+
+    options.port = port.ok_or("missing port")?;
+    Ok(Some(options))
+}
+
+fn parse_value(v: &str) -> Result<u8, String> {
+    v.parse().map_err(|_| format!("invalid value {:?}, expected 0-255", v))
+}
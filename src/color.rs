@@ -0,0 +1,238 @@
+//! Colors.
+
+use crate::address::DmxAddress;
+use crate::universe::DmxUniverse;
+
+/// An RGB color.
+///
+/// Conversions to other color systems are provided, to address fixtures
+/// using different ones uniformly.
+///
+/// ```
+/// use dmx::{Color, ColorOrder, DmxAddress, DmxUniverse};
+///
+/// let orange = Color::from_hsv(30.0, 1.0, 1.0);
+/// assert_eq!(orange, Color::new(0xff, 0x80, 0x00));
+///
+/// // an RGBW par at channel 1 and a CMY mover at channel 5
+/// let mut universe = DmxUniverse::new();
+/// orange.write_to(&mut universe, DmxAddress::new(1).unwrap(), ColorOrder::Rgbw);
+/// orange.write_to(&mut universe, DmxAddress::new(5).unwrap(), ColorOrder::Cmy);
+///
+/// assert_eq!(universe.channels()[..7], [0xff, 0x80, 0x00, 0x00, 0x00, 0x7f, 0xff]);
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Color {
+    /// Red component.
+    pub red: u8,
+    /// Green component.
+    pub green: u8,
+    /// Blue component.
+    pub blue: u8,
+}
+
+impl Color {
+    /// All components off.
+    pub const BLACK: Color = Color::new(0, 0, 0);
+    /// All components at full.
+    pub const WHITE: Color = Color::new(0xff, 0xff, 0xff);
+    /// Red at full.
+    pub const RED: Color = Color::new(0xff, 0, 0);
+    /// Green at full.
+    pub const GREEN: Color = Color::new(0, 0xff, 0);
+    /// Blue at full.
+    pub const BLUE: Color = Color::new(0, 0, 0xff);
+
+    /// Create a color from its components.
+    #[inline]
+    pub const fn new(red: u8, green: u8, blue: u8) -> Color {
+        Color { red, green, blue }
+    }
+
+    /// Create a color from red, green, blue and white components.
+    ///
+    /// White is added to the other components, saturating at full.
+    #[inline]
+    pub fn from_rgbw(red: u8, green: u8, blue: u8, white: u8) -> Color {
+        Color::new(
+            red.saturating_add(white),
+            green.saturating_add(white),
+            blue.saturating_add(white),
+        )
+    }
+
+    /// Converts the color to red, green, blue and white components.
+    ///
+    /// The white component is the lowest of the three, which is subtracted
+    /// from the others, so white LEDs take over as much as possible.
+    #[inline]
+    pub fn to_rgbw(self) -> [u8; 4] {
+        let white = self.red.min(self.green).min(self.blue);
+
+        [self.red - white, self.green - white, self.blue - white, white]
+    }
+
+    /// Create a color from cyan, magenta and yellow components, as used by
+    /// fixtures mixing colors by subtractive filters.
+    #[inline]
+    pub fn from_cmy(cyan: u8, magenta: u8, yellow: u8) -> Color {
+        Color::new(0xff - cyan, 0xff - magenta, 0xff - yellow)
+    }
+
+    /// Converts the color to cyan, magenta and yellow components.
+    #[inline]
+    pub fn to_cmy(self) -> [u8; 3] {
+        [0xff - self.red, 0xff - self.green, 0xff - self.blue]
+    }
+
+    /// Create a color from hue, in degrees, saturation and value.
+    ///
+    /// Hues outside of 0 to 360 degrees wrap around, saturation and value
+    /// are clamped to the range of 0 to 1.
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Color {
+        let s = saturation.clamp(0.0, 1.0);
+        let v = value.clamp(0.0, 1.0);
+
+        // the fraction of a full turn, without relying on `floor` from std
+        let turns = hue / 360.0;
+        let mut whole = turns as i32 as f32;
+        if whole > turns {
+            whole -= 1.0;
+        }
+
+        let sector = (turns - whole) * 6.0;
+        let index = (sector as u8).min(5);
+        let f = sector - f32::from(index);
+        let (p, q, t) = (v * (1.0 - s), v * (1.0 - s * f), v * (1.0 - s * (1.0 - f)));
+
+        let (red, green, blue) = match index {
+            0 => (v, t, p),
+            1 => (q, v, p),
+            2 => (p, v, t),
+            3 => (p, q, v),
+            4 => (t, p, v),
+            _ => (v, p, q),
+        };
+
+        Color::new(component(red), component(green), component(blue))
+    }
+
+    /// Converts the color to hue, in degrees from 0 to 360, saturation and
+    /// value.
+    ///
+    /// The hue of grays is zero.
+    pub fn to_hsv(self) -> (f32, f32, f32) {
+        let (r, g, b) = (
+            f32::from(self.red) / 255.0,
+            f32::from(self.green) / 255.0,
+            f32::from(self.blue) / 255.0,
+        );
+
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+
+        if delta == 0.0 {
+            return (0.0, 0.0, max);
+        }
+
+        let sector = if max == r {
+            (g - b) / delta
+        } else if max == g {
+            (b - r) / delta + 2.0
+        } else {
+            (r - g) / delta + 4.0
+        };
+
+        let hue = sector * 60.0;
+        let hue = if hue < 0.0 { hue + 360.0 } else { hue };
+
+        (hue, delta / max, max)
+    }
+
+    /// Writes the color into consecutive channels of `universe`, starting at
+    /// channel `base`, in the order the fixture expects.
+    ///
+    /// Channels beyond 512 are ignored. Cancels fades in progress on all
+    /// channels set.
+    #[inline]
+    pub fn write_to(self, universe: &mut DmxUniverse, base: DmxAddress, order: ColorOrder) {
+        let mut values = [0; 4];
+        order.write(self, &mut values);
+        universe.set_range(base, &values[..order.channels()]);
+    }
+}
+
+/// Converts a component from 0 to 1 to a channel value.
+#[inline]
+fn component(x: f32) -> u8 {
+    // values are never negative, so truncating after adding 0.5 rounds
+    (x * 255.0 + 0.5) as u8
+}
+
+/// Order in which a fixture or pixel expects its color channels.
+///
+/// Orders including a white channel extract the white component from the
+/// color, see `Color::to_rgbw`. Fixtures mixing colors subtractively receive
+/// the color converted to CMY.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColorOrder {
+    /// Red, green, blue.
+    #[default]
+    Rgb,
+    /// Red, blue, green.
+    Rbg,
+    /// Green, red, blue, common on WS2812 pixels.
+    Grb,
+    /// Green, blue, red.
+    Gbr,
+    /// Blue, red, green.
+    Brg,
+    /// Blue, green, red.
+    Bgr,
+    /// Red, green, blue, white.
+    Rgbw,
+    /// Green, red, blue, white, common on SK6812 pixels.
+    Grbw,
+    /// Cyan, magenta, yellow.
+    Cmy,
+}
+
+impl ColorOrder {
+    /// Returns the number of channels per color.
+    #[inline]
+    pub fn channels(self) -> usize {
+        match self {
+            ColorOrder::Rgbw | ColorOrder::Grbw => 4,
+            _ => 3,
+        }
+    }
+
+    /// Writes `color` into `out` in this order.
+    ///
+    /// Writes `channels()` values, or fewer if `out` is shorter.
+    pub fn write(self, color: Color, out: &mut [u8]) {
+        let Color { red, green, blue } = color;
+
+        let values = match self {
+            ColorOrder::Rgb => [red, green, blue, 0],
+            ColorOrder::Rbg => [red, blue, green, 0],
+            ColorOrder::Grb => [green, red, blue, 0],
+            ColorOrder::Gbr => [green, blue, red, 0],
+            ColorOrder::Brg => [blue, red, green, 0],
+            ColorOrder::Bgr => [blue, green, red, 0],
+            ColorOrder::Rgbw => color.to_rgbw(),
+            ColorOrder::Grbw => {
+                let [red, green, blue, white] = color.to_rgbw();
+                [green, red, blue, white]
+            }
+            ColorOrder::Cmy => {
+                let [cyan, magenta, yellow] = color.to_cmy();
+                [cyan, magenta, yellow, 0]
+            }
+        };
+
+        for (v, &c) in out.iter_mut().zip(&values[..self.channels()]) {
+            *v = c;
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Dimmer curves.
+
+use crate::address::DmxAddress;
+use crate::packet::MAX_CHANNELS;
+use crate::universe::DmxUniverse;
+
+/// Response curve of a dimmer channel.
+///
+/// Maps the intensity an application asks for onto the value sent. LEDs
+/// react nearly linearly to their DMX value, which the eye perceives as far
+/// too bright at low levels; a square-law curve makes fades look even.
+///
+/// Curves are lookup tables of 256 entries, built-in ones are provided as
+/// constants.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DimmerCurve {
+    table: [u8; 256],
+}
+
+impl DimmerCurve {
+    /// Values are sent unchanged.
+    pub const LINEAR: DimmerCurve = DimmerCurve::build(Shape::Linear);
+
+    /// Values are squared, giving finer control at low levels.
+    pub const SQUARE: DimmerCurve = DimmerCurve::build(Shape::Square);
+
+    /// Values follow an S-shaped curve, flattening both ends.
+    pub const S_CURVE: DimmerCurve = DimmerCurve::build(Shape::SCurve);
+
+    /// Create a curve from a lookup table, indexed by the requested value.
+    #[inline]
+    pub const fn from_table(table: [u8; 256]) -> DimmerCurve {
+        DimmerCurve { table }
+    }
+
+    /// Returns the lookup table of the curve.
+    #[inline]
+    pub fn table(&self) -> &[u8; 256] {
+        &self.table
+    }
+
+    /// Returns whether the curve leaves all values unchanged.
+    #[inline]
+    pub fn is_linear(&self) -> bool {
+        *self == DimmerCurve::LINEAR
+    }
+
+    /// Maps `value` onto the curve.
+    #[inline]
+    pub fn apply(&self, value: u8) -> u8 {
+        self.table[usize::from(value)]
+    }
+
+    /// Maps `values` onto the curve and writes them into consecutive channels
+    /// of `universe`, starting at channel `start`.
+    ///
+    /// See `DmxUniverse::set_range`.
+    pub fn write_to(&self, universe: &mut DmxUniverse, start: DmxAddress, values: &[u8]) {
+        let mut buf = [0; MAX_CHANNELS];
+        let len = values.len().min(MAX_CHANNELS - start.index());
+
+        for (out, &v) in buf.iter_mut().zip(&values[..len]) {
+            *out = self.apply(v);
+        }
+
+        universe.set_range(start, &buf[..len]);
+    }
+
+    const fn build(shape: Shape) -> DimmerCurve {
+        let mut table = [0; 256];
+
+        let mut i = 0;
+        while i < 256 {
+            let x = i as u32;
+
+            // results never exceed 255, rounded to the nearest integer
+            table[i] = match shape {
+                Shape::Linear => x,
+                Shape::Square => (x * x + 127) / 255,
+                Shape::SCurve => (x * x * (3 * 255 - 2 * x) + 32512) / 65025,
+            } as u8;
+            i += 1;
+        }
+
+        DimmerCurve { table }
+    }
+}
+
+impl Default for DimmerCurve {
+    #[inline]
+    fn default() -> DimmerCurve {
+        DimmerCurve::LINEAR
+    }
+}
+
+/// Shapes of the built-in curves.
+#[derive(Copy, Clone)]
+enum Shape {
+    Linear,
+    Square,
+    SCurve,
+}
+
+/// Dimmer curves assigned to the channels of a universe.
+///
+/// Applied to a copy of the universe right before sending it, so the
+/// universe itself keeps the intensities the application set and fades
+/// progress along the curve.
+///
+/// ## Example
+///
+/// ```
+/// use dmx::{CurveMap, DimmerCurve, DmxAddress, DmxUniverse};
+///
+/// // four cheap LED pars at channels 1 to 4
+/// let mut curves = CurveMap::new();
+/// curves.set_range(DmxAddress::MIN, 4, DimmerCurve::SQUARE);
+///
+/// let mut universe = DmxUniverse::new();
+/// universe.set_range(DmxAddress::MIN, &[0x80, 0x80, 0xff, 0x00, 0x80]);
+///
+/// let output = curves.apply(&universe);
+/// assert_eq!(output.channels()[..5], [0x40, 0x40, 0xff, 0x00, 0x80]);
+/// ```
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CurveMap {
+    // curves referenced by the channels
+    tables: Vec<DimmerCurve>,
+    // index into `tables` of each channel, `NO_CURVE` if linear
+    assigned: [u16; MAX_CHANNELS],
+}
+
+#[cfg(feature = "std")]
+const NO_CURVE: u16 = u16::MAX;
+
+#[cfg(feature = "std")]
+impl Default for CurveMap {
+    #[inline]
+    fn default() -> CurveMap {
+        CurveMap {
+            tables: Vec::new(),
+            assigned: [NO_CURVE; MAX_CHANNELS],
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl CurveMap {
+    /// Create a map with all channels linear.
+    #[inline]
+    pub fn new() -> CurveMap {
+        CurveMap::default()
+    }
+
+    /// Assigns a curve to channel `n`.
+    #[inline]
+    pub fn set(&mut self, n: DmxAddress, curve: DimmerCurve) {
+        self.set_range(n, 1, curve)
+    }
+
+    /// Assigns a curve to `count` consecutive channels, starting at channel
+    /// `start`.
+    ///
+    /// Channels beyond 512 are ignored.
+    pub fn set_range(&mut self, start: DmxAddress, count: usize, curve: DimmerCurve) {
+        let index = if curve.is_linear() {
+            NO_CURVE
+        } else {
+            match self.tables.iter().position(|t| *t == curve) {
+                Some(n) => n as u16,
+                None => {
+                    self.tables.push(curve);
+                    (self.tables.len() - 1) as u16
+                }
+            }
+        };
+
+        for a in self.assigned[start.index()..].iter_mut().take(count) {
+            *a = index;
+        }
+
+        self.remove_unused();
+    }
+
+    /// Maps the value of channel `n` onto its curve.
+    #[inline]
+    pub fn apply_channel(&self, n: DmxAddress, value: u8) -> u8 {
+        match self.assigned[n.index()] {
+            NO_CURVE => value,
+            index => self.tables[usize::from(index)].apply(value),
+        }
+    }
+
+    /// Returns a copy of `universe` with all curves applied.
+    ///
+    /// Fades in progress are not copied.
+    pub fn apply(&self, universe: &DmxUniverse) -> DmxUniverse {
+        let mut output = DmxUniverse::new();
+        let channels = output.channels_mut();
+        channels.copy_from_slice(universe.channels());
+
+        for (v, &index) in channels.iter_mut().zip(&self.assigned) {
+            if index != NO_CURVE {
+                *v = self.tables[usize::from(index)].apply(*v);
+            }
+        }
+
+        output
+    }
+
+    /// Drops curves no channel refers to anymore.
+    fn remove_unused(&mut self) {
+        let mut n = 0;
+
+        while n < self.tables.len() {
+            if self.assigned.contains(&(n as u16)) {
+                n += 1;
+                continue;
+            }
+
+            self.tables.swap_remove(n);
+            let moved = self.tables.len() as u16;
+            for a in self.assigned.iter_mut().filter(|a| **a == moved) {
+                *a = n as u16;
+            }
+        }
+    }
+}
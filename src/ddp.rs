@@ -0,0 +1,377 @@
+//! DDP support.
+//!
+//! The [Distributed Display Protocol](http://www.3waylabs.com/ddp/) sends
+//! pixel data over UDP and is understood by many WiFi pixel controllers,
+//! such as those running WLED or ESPixelStick. Instead of universes, data is
+//! addressed by its byte offset into the controller's pixel buffer, so a
+//! single controller takes any number of pixels. Data that does not fit into
+//! a packet is split across several, the last one carrying the *push* flag
+//! on which the controller displays what it received.
+//!
+//! Strips driven through DDP take their pixels straight from the frame
+//! buffer of a `PixelMap`, reserved through `PixelMap::reserve`.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use dmx::{Color, ColorOrder, DmxAddress};
+//! use dmx::ddp::DdpTransmitter;
+//! use dmx::pixels::PixelMap;
+//!
+//! // a strip on universe 1, and another one on a WLED controller
+//! let mut map = PixelMap::new();
+//! map.add_strip(1, DmxAddress::MIN, 150, ColorOrder::Grb);
+//! let wled = map.reserve(600);
+//!
+//! let mut frame = vec![Color::BLACK; map.pixel_count()];
+//! frame[wled.start] = Color::RED;
+//!
+//! let mut controller = DdpTransmitter::new([10, 0, 0, 40].into()).unwrap();
+//! controller.send_frame(&frame[wled]).unwrap();
+//! ```
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::{cmp, io};
+
+use crate::color::{Color, ColorOrder};
+use crate::{DmxTransmitter, Error, Result};
+
+/// UDP port used by DDP.
+pub const DDP_PORT: u16 = 4048;
+
+/// Length of the header of a DDP packet without timecode.
+pub const HEADER_LEN: usize = 10;
+
+/// Maximum number of data bytes sent in a single packet.
+///
+/// Holds 480 RGB or 360 RGBW pixels and keeps packets within the MTU of
+/// common networks.
+pub const MAX_DATA_LEN: usize = 1440;
+
+/// Flag: protocol version 1, set in every packet.
+pub const FLAG_VERSION_1: u8 = 0x40;
+
+/// Flag: display the data received.
+pub const FLAG_PUSH: u8 = 0x01;
+
+/// Data type of 8-bit RGB pixels.
+pub const TYPE_RGB8: u8 = 0x0b;
+
+/// Data type of 8-bit RGBW pixels.
+pub const TYPE_RGBW8: u8 = 0x1b;
+
+/// Destination ID of a controller's default output.
+pub const ID_DEFAULT: u8 = 0x01;
+
+/// Writes the header of a packet carrying `len` bytes into `buf`.
+fn write_header(buf: &mut [u8], flags: u8, sequence: u8, ty: u8, offset: u32, len: usize) {
+    buf[0] = FLAG_VERSION_1 | flags;
+    buf[1] = sequence & 0x0f;
+    buf[2] = ty;
+    buf[3] = ID_DEFAULT;
+    buf[4..8].copy_from_slice(&offset.to_be_bytes());
+    buf[8..10].copy_from_slice(&(len as u16).to_be_bytes());
+}
+
+/// Encodes a DDP data packet into `buf`.
+///
+/// `data` is written at byte `offset` of the controller's buffer and is
+/// truncated to 1440 bytes. Sequence numbers range from 1 to 15, zero if
+/// not used. Returns the length of the packet; `buf` must hold at least
+/// 1450 bytes.
+pub fn encode_data(
+    buf: &mut [u8],
+    sequence: u8,
+    data_type: u8,
+    offset: u32,
+    data: &[u8],
+    push: bool,
+) -> usize {
+    let count = cmp::min(data.len(), MAX_DATA_LEN);
+    let flags = if push { FLAG_PUSH } else { 0 };
+
+    write_header(buf, flags, sequence, data_type, offset, count);
+    buf[HEADER_LEN..(HEADER_LEN + count)].copy_from_slice(&data[..count]);
+
+    HEADER_LEN + count
+}
+
+/// DDP transmitter.
+///
+/// Sends pixel data to a controller via UDP. Pixels are written in the
+/// color order set through `set_order`, RGB by default, which is what most
+/// controllers expect, as they reorder colors for their strips themselves.
+///
+/// As a `DmxTransmitter`, channel data is written at the start of the
+/// controller's buffer and displayed at once. Breaks are not supported and
+/// only complete packets with the default start code can be sent.
+#[derive(Debug)]
+pub struct DdpTransmitter {
+    socket: UdpSocket,
+    target: SocketAddr,
+    order: ColorOrder,
+    // next sequence number, zero if sequencing is disabled
+    sequence: u8,
+    buf: [u8; HEADER_LEN + MAX_DATA_LEN],
+}
+
+impl DdpTransmitter {
+    /// Create a transmitter sending to the controller at `ip`.
+    #[inline]
+    pub fn new(ip: IpAddr) -> io::Result<DdpTransmitter> {
+        DdpTransmitter::with_target(SocketAddr::new(ip, DDP_PORT))
+    }
+
+    /// Create a transmitter sending to a specific socket address.
+    ///
+    /// Only needed if the controller listens on a port other than 4048.
+    /// Broadcast addresses are allowed.
+    pub fn with_target(target: SocketAddr) -> io::Result<DdpTransmitter> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+
+        Ok(DdpTransmitter {
+            socket,
+            target,
+            order: ColorOrder::Rgb,
+            sequence: 1,
+            buf: [0; HEADER_LEN + MAX_DATA_LEN],
+        })
+    }
+
+    /// Returns the target socket address.
+    #[inline]
+    pub fn target(&self) -> SocketAddr {
+        self.target
+    }
+
+    /// Returns the color order pixels are written in.
+    #[inline]
+    pub fn order(&self) -> ColorOrder {
+        self.order
+    }
+
+    /// Sets the color order pixels are written in.
+    ///
+    /// RGBW orders send four channels per pixel, with the RGBW data type.
+    #[inline]
+    pub fn set_order(&mut self, order: ColorOrder) {
+        self.order = order;
+    }
+
+    /// Enables or disables sequence numbers.
+    ///
+    /// Sequence numbers let controllers detect lost packets and are enabled
+    /// by default.
+    #[inline]
+    pub fn set_sequence_enabled(&mut self, enabled: bool) {
+        self.sequence = if enabled { 1 } else { 0 };
+    }
+
+    fn data_type(&self) -> u8 {
+        match self.order.channels() {
+            4 => TYPE_RGBW8,
+            _ => TYPE_RGB8,
+        }
+    }
+
+    /// Sends the packet of `len` data bytes in the buffer.
+    fn send_packet(&mut self, len: usize) -> Result<()> {
+        self.socket.send_to(&self.buf[..(HEADER_LEN + len)], self.target)?;
+
+        // sequence numbers wrap from 15 to 1, zero means disabled
+        self.sequence = match self.sequence {
+            0 => 0,
+            15 => 1,
+            n => n + 1,
+        };
+
+        Ok(())
+    }
+
+    /// Sends raw data, written at byte `offset` of the controller's buffer.
+    ///
+    /// Data longer than 1440 bytes is split across several packets. If
+    /// `push` is set, the last one makes the controller display the data
+    /// received so far.
+    pub fn send(&mut self, offset: u32, data: &[u8], push: bool) -> Result<()> {
+        if data.is_empty() {
+            return if push { self.push() } else { Ok(()) };
+        }
+
+        let data_type = self.data_type();
+        let mut chunks = data.chunks(MAX_DATA_LEN).peekable();
+        let mut offset = offset;
+
+        while let Some(chunk) = chunks.next() {
+            let push = push && chunks.peek().is_none();
+            let len = encode_data(&mut self.buf, self.sequence, data_type, offset, chunk, push);
+            self.send_packet(len - HEADER_LEN)?;
+            offset = offset.wrapping_add(chunk.len() as u32);
+        }
+
+        Ok(())
+    }
+
+    /// Sends pixels, starting at pixel `start` of the controller.
+    ///
+    /// See `send` for the meaning of `push`.
+    pub fn send_pixels(&mut self, start: usize, pixels: &[Color], push: bool) -> Result<()> {
+        if pixels.is_empty() {
+            return if push { self.push() } else { Ok(()) };
+        }
+
+        let width = self.order.channels();
+        let data_type = self.data_type();
+        let mut chunks = pixels.chunks(MAX_DATA_LEN / width).peekable();
+        let mut offset = (start * width) as u32;
+
+        while let Some(chunk) = chunks.next() {
+            let flags = if push && chunks.peek().is_none() { FLAG_PUSH } else { 0 };
+            let len = chunk.len() * width;
+
+            write_header(&mut self.buf, flags, self.sequence, data_type, offset, len);
+            let data = &mut self.buf[HEADER_LEN..(HEADER_LEN + len)];
+            for (&color, out) in chunk.iter().zip(data.chunks_mut(width)) {
+                self.order.write(color, out);
+            }
+
+            self.send_packet(len)?;
+            offset = offset.wrapping_add(len as u32);
+        }
+
+        Ok(())
+    }
+
+    /// Sends all pixels of a controller and displays them.
+    #[inline]
+    pub fn send_frame(&mut self, frame: &[Color]) -> Result<()> {
+        self.send_pixels(0, frame, true)
+    }
+
+    /// Makes the controller display the data received so far.
+    ///
+    /// Used after sending data without `push`, e.g. to several
+    /// controllers that should switch at the same time.
+    pub fn push(&mut self) -> Result<()> {
+        let data_type = self.data_type();
+        write_header(&mut self.buf, FLAG_PUSH, self.sequence, data_type, 0, 0);
+        self.send_packet(0)
+    }
+}
+
+impl DmxTransmitter for DdpTransmitter {
+    type Error = Error;
+
+    #[inline]
+    fn send_break(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn send_raw_data(&mut self, _data: &[u8]) -> Result<()> {
+        Err(Error::Unsupported("DDP can only transmit complete packets"))
+    }
+
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<()> {
+        match data.first() {
+            Some(&0x00) => self.send(0, &data[1..], true),
+            Some(&code) => Err(Error::UnsupportedStartCode(code)),
+            None => Err(Error::EmptyPacket),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a transmitter sending to a local socket
+    fn loopback() -> (DdpTransmitter, UdpSocket) {
+        let receiver = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let transmitter = DdpTransmitter::with_target(receiver.local_addr().unwrap()).unwrap();
+        (transmitter, receiver)
+    }
+
+    fn recv(receiver: &UdpSocket) -> Vec<u8> {
+        let mut buf = [0; HEADER_LEN + MAX_DATA_LEN + 1];
+        let len = receiver.recv(&mut buf).unwrap();
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn data_packet_layout() {
+        let mut buf = [0; HEADER_LEN + MAX_DATA_LEN];
+        let len = encode_data(&mut buf, 0x13, TYPE_RGB8, 0x0102_0304, &[0xff, 0x80, 0x40], true);
+        assert_eq!(len, 13);
+
+        // flags, sequence, data type, destination ID, offset and length
+        // high byte first, data
+        let expected = [
+            0x41, 0x03, 0x0b, 0x01, 0x01, 0x02, 0x03, 0x04, 0x00, 0x03, 0xff, 0x80, 0x40,
+        ];
+        assert_eq!(buf[..len], expected);
+
+        let len = encode_data(&mut buf, 0, TYPE_RGBW8, 0, &[], false);
+        assert_eq!(buf[..len], [0x40, 0x00, 0x1b, 0x01, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn data_is_truncated_to_1440_bytes() {
+        let mut buf = [0; HEADER_LEN + MAX_DATA_LEN];
+        let len = encode_data(&mut buf, 1, TYPE_RGB8, 0, &[0x55; 2000], false);
+
+        assert_eq!(len, HEADER_LEN + MAX_DATA_LEN);
+        assert_eq!(buf[8..10], [0x05, 0xa0]);
+    }
+
+    #[test]
+    fn long_data_is_split_and_pushed_once() {
+        let (mut transmitter, receiver) = loopback();
+        transmitter.send(3, &[0x55; 2000], true).unwrap();
+
+        let first = recv(&receiver);
+        assert_eq!(first.len(), HEADER_LEN + MAX_DATA_LEN);
+        assert_eq!(first[..10], [0x40, 0x01, 0x0b, 0x01, 0, 0, 0, 3, 0x05, 0xa0]);
+
+        let second = recv(&receiver);
+        assert_eq!(second.len(), HEADER_LEN + 560);
+        assert_eq!(second[..10], [0x41, 0x02, 0x0b, 0x01, 0, 0, 0x05, 0xa3, 0x02, 0x30]);
+    }
+
+    #[test]
+    fn pixels_are_written_in_color_order() {
+        let (mut transmitter, receiver) = loopback();
+        transmitter.set_order(ColorOrder::Grb);
+        transmitter.send_pixels(2, &[Color::new(0xff, 0x80, 0x40)], false).unwrap();
+
+        // offset in bytes, three per pixel
+        assert_eq!(recv(&receiver), [0x40, 0x01, 0x0b, 0x01, 0, 0, 0, 6, 0, 3, 0x80, 0xff, 0x40]);
+
+        transmitter.set_order(ColorOrder::Rgbw);
+        transmitter.send_pixels(2, &[Color::RED], true).unwrap();
+
+        let packet = recv(&receiver);
+        assert_eq!(packet, [0x41, 0x02, 0x1b, 0x01, 0, 0, 0, 8, 0, 4, 0xff, 0, 0, 0]);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_to_one() {
+        let (mut transmitter, receiver) = loopback();
+
+        let sequences: Vec<u8> = (0..17)
+            .map(|_| {
+                transmitter.push().unwrap();
+                recv(&receiver)[1]
+            })
+            .collect();
+        let expected: Vec<u8> = (1..=15).chain(1..=2).collect();
+        assert_eq!(sequences, expected);
+
+        transmitter.set_sequence_enabled(false);
+        transmitter.push().unwrap();
+        transmitter.push().unwrap();
+        assert_eq!(recv(&receiver)[1], 0);
+        assert_eq!(recv(&receiver)[1], 0);
+    }
+}
@@ -0,0 +1,196 @@
+//! Serial device enumeration.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// USB vendor ID of FTDI, whose chips most DMX interfaces are built on.
+const VID_FTDI: u16 = 0x0403;
+
+/// What kind of interface a serial device probably is.
+///
+/// Ordered from most to least likely to be a DMX interface.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DeviceKind {
+    /// An Enttec DMX USB Pro, see `enttec::EnttecPro`.
+    EnttecPro,
+    /// A DMXKing USB interface, see the `dmxking` module.
+    DmxKing,
+    /// Another FTDI-based adapter, such as the Enttec Open DMX USB or a
+    /// USB-RS485 cable, see `open_serial`.
+    Ftdi,
+    /// A USB serial adapter of another make.
+    UsbSerial,
+    /// A UART built into the host, such as `/dev/ttyS0` or `/dev/ttyAMA0`.
+    Uart,
+    /// Anything else, or a device that could not be identified.
+    Unknown,
+}
+
+/// Identification of a USB serial device.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UsbInfo {
+    /// Vendor ID.
+    pub vendor_id: u16,
+    /// Product ID.
+    pub product_id: u16,
+    /// Manufacturer string.
+    pub manufacturer: Option<String>,
+    /// Product string.
+    pub product: Option<String>,
+    /// Serial number string.
+    pub serial_number: Option<String>,
+}
+
+/// A serial device found by `enumerate`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SerialDevice {
+    /// Path of the device, to be passed to `open_serial` or the `open`
+    /// function of the device's kind.
+    pub path: PathBuf,
+    /// What the device probably is.
+    pub kind: DeviceKind,
+    /// USB identification, if the device is attached through USB and the
+    /// platform reports it.
+    pub usb: Option<UsbInfo>,
+}
+
+/// Lists serial devices, likely DMX interfaces first.
+///
+/// USB devices are identified on Linux only, through sysfs. On other
+/// platforms, all devices are reported as `DeviceKind::Unknown`, except for
+/// USB serial adapters recognized by their name on macOS.
+///
+/// ```no_run
+/// use dmx::DeviceKind;
+///
+/// for device in dmx::enumerate().unwrap() {
+///     println!("{} ({:?})", device.path.display(), device.kind);
+/// }
+///
+/// let devices = dmx::enumerate().unwrap();
+/// let ftdi = devices.iter().find(|d| d.kind == DeviceKind::Ftdi).unwrap();
+/// let port = dmx::open_serial(&ftdi.path).unwrap();
+/// ```
+pub fn enumerate() -> io::Result<Vec<SerialDevice>> {
+    let mut devices: Vec<SerialDevice> = serial2::SerialPort::available_ports()?
+        .into_iter()
+        .map(|path| {
+            let usb = usb_info(&path);
+            let kind = match usb {
+                Some(ref usb) => usb_kind(usb),
+                None => path_kind(&path),
+            };
+
+            SerialDevice { path, kind, usb }
+        })
+        .collect();
+
+    devices.sort_by(|a, b| a.kind.cmp(&b.kind).then_with(|| a.path.cmp(&b.path)));
+    Ok(devices)
+}
+
+fn usb_kind(usb: &UsbInfo) -> DeviceKind {
+    let contains = |field: &Option<String>, s: &str| {
+        field.as_ref().is_some_and(|f| f.to_lowercase().contains(s))
+    };
+
+    // DMXKing interfaces use FTDI chips, but their own strings
+    if contains(&usb.manufacturer, "dmxking") {
+        DeviceKind::DmxKing
+    } else if usb.vendor_id != VID_FTDI {
+        DeviceKind::UsbSerial
+    } else if contains(&usb.product, "dmx usb pro") {
+        DeviceKind::EnttecPro
+    } else {
+        DeviceKind::Ftdi
+    }
+}
+
+fn path_kind(path: &Path) -> DeviceKind {
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name,
+        None => return DeviceKind::Unknown,
+    };
+
+    if cfg!(target_os = "macos") && name.starts_with("cu.usbserial") {
+        DeviceKind::UsbSerial
+    } else if cfg!(target_os = "linux")
+        && ["ttyS", "ttyAMA", "ttyO", "ttySAC", "ttyTHS", "ttymxc"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+    {
+        DeviceKind::Uart
+    } else {
+        DeviceKind::Unknown
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn usb_info(path: &Path) -> Option<UsbInfo> {
+    use std::fs;
+
+    let name = path.file_name()?;
+    let mut dir = fs::canonicalize(Path::new("/sys/class/tty").join(name).join("device")).ok()?;
+
+    // the USB device is a few levels above the tty or its interface
+    for _ in 0..4 {
+        let read = |attribute: &str| {
+            fs::read_to_string(dir.join(attribute))
+                .ok()
+                .map(|s| s.trim().to_owned())
+        };
+        let id = |attribute: &str| u16::from_str_radix(&read(attribute)?, 16).ok();
+
+        if let (Some(vendor_id), Some(product_id)) = (id("idVendor"), id("idProduct")) {
+            return Some(UsbInfo {
+                vendor_id,
+                product_id,
+                manufacturer: read("manufacturer"),
+                product: read("product"),
+                serial_number: read("serial"),
+            });
+        }
+
+        if !dir.pop() {
+            break;
+        }
+    }
+
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn usb_info(_path: &Path) -> Option<UsbInfo> {
+    None
+}
+
+/// Returns whether `path` is the mini UART of a Raspberry Pi.
+///
+/// Follows symlinks such as `/dev/serial0`, then checks the device tree node
+/// and driver of the tty in sysfs.
+#[cfg(target_os = "linux")]
+pub(crate) fn is_mini_uart(path: &Path) -> bool {
+    use std::fs;
+
+    const AUX_UART: &str = "bcm2835-aux-uart";
+
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let device = match path.file_name() {
+        Some(name) => Path::new("/sys/class/tty").join(name).join("device"),
+        None => return false,
+    };
+
+    // a list of NUL-terminated strings, e.g. "brcm,bcm2835-aux-uart"
+    let compatible = fs::read(device.join("of_node/compatible")).unwrap_or_default();
+    let driver = fs::read_link(device.join("driver")).unwrap_or_default();
+
+    compatible
+        .split(|&b| b == 0)
+        .any(|c| c.ends_with(AUX_UART.as_bytes()))
+        || driver.file_name().is_some_and(|n| n == AUX_UART)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn is_mini_uart(_path: &Path) -> bool {
+    false
+}
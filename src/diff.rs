@@ -0,0 +1,196 @@
+//! Differences between universes.
+
+use std::iter::FromIterator;
+
+use crate::address::DmxAddress;
+use crate::universe::DmxUniverse;
+
+/// A channel changed between two universes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelChange {
+    /// The channel changed.
+    pub channel: DmxAddress,
+    /// Value before the change.
+    pub old: u8,
+    /// Value after the change.
+    pub new: u8,
+}
+
+/// The channels changed between two universes.
+///
+/// Holds the old and new values of each channel changed, ordered by channel,
+/// so diffs can be applied as well as reverted. Sending diffs instead of
+/// whole universes keeps network traffic low when only a few channels
+/// change, and a stack of diffs makes for an undo history. With the `serde`
+/// feature, diffs are serialized as a sequence of changes.
+///
+/// ```
+/// use dmx::{DmxAddress, DmxUniverse, UniverseDiff};
+///
+/// let before = DmxUniverse::new();
+/// let mut after = before.clone();
+/// after.set(DmxAddress::new(3).unwrap(), 0xff);
+///
+/// let diff = UniverseDiff::between(&before, &after);
+/// assert_eq!(diff.len(), 1);
+///
+/// let mut remote = before.clone();
+/// diff.apply(&mut remote);
+/// assert_eq!(remote, after);
+///
+/// diff.revert(&mut remote);
+/// assert_eq!(remote, before);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "Vec<ChannelChange>", into = "Vec<ChannelChange>")
+)]
+pub struct UniverseDiff {
+    changes: Vec<ChannelChange>,
+}
+
+impl UniverseDiff {
+    /// Create a diff without any changes.
+    #[inline]
+    pub fn new() -> UniverseDiff {
+        UniverseDiff::default()
+    }
+
+    /// Computes the changes turning `old` into `new`.
+    ///
+    /// Only channel values are compared, fades in progress are ignored.
+    pub fn between(old: &DmxUniverse, new: &DmxUniverse) -> UniverseDiff {
+        let changes = old
+            .channels()
+            .iter()
+            .zip(new.channels())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .filter_map(|(i, (&old, &new))| {
+                Some(ChannelChange {
+                    channel: DmxAddress::from_index(i)?,
+                    old,
+                    new,
+                })
+            })
+            .collect();
+
+        UniverseDiff { changes }
+    }
+
+    /// Returns the changes, ordered by channel.
+    #[inline]
+    pub fn changes(&self) -> &[ChannelChange] {
+        &self.changes
+    }
+
+    /// Returns the number of channels changed.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Returns whether no channels changed.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Records a change of `channel` from `old` to `new`.
+    ///
+    /// Merges with an earlier change of the same channel, keeping its old
+    /// value; the change is dropped if the channel ends up at its old value.
+    pub fn push(&mut self, channel: DmxAddress, old: u8, new: u8) {
+        match self.changes.binary_search_by_key(&channel, |c| c.channel) {
+            Ok(i) if self.changes[i].old == new => {
+                self.changes.remove(i);
+            }
+            Ok(i) => self.changes[i].new = new,
+            Err(_) if old == new => (),
+            Err(i) => self.changes.insert(i, ChannelChange { channel, old, new }),
+        }
+    }
+
+    /// Appends the changes of a later diff, as if both were applied in turn.
+    ///
+    /// Coalesces a series of edits into a single undo step.
+    pub fn extend(&mut self, later: &UniverseDiff) {
+        for change in &later.changes {
+            self.push(change.channel, change.old, change.new);
+        }
+    }
+
+    /// Returns the diff undoing this one.
+    pub fn inverse(&self) -> UniverseDiff {
+        let changes = self
+            .changes
+            .iter()
+            .map(|c| ChannelChange {
+                channel: c.channel,
+                old: c.new,
+                new: c.old,
+            })
+            .collect();
+
+        UniverseDiff { changes }
+    }
+
+    /// Sets the changed channels of `universe` to their new values.
+    ///
+    /// Channels are set regardless of their current values, which may
+    /// differ from the old values when applying to another universe. Cancels
+    /// fades in progress on the channels set.
+    pub fn apply(&self, universe: &mut DmxUniverse) {
+        for change in &self.changes {
+            universe.set(change.channel, change.new);
+        }
+    }
+
+    /// Sets the changed channels of `universe` back to their old values.
+    ///
+    /// See `apply`.
+    pub fn revert(&self, universe: &mut DmxUniverse) {
+        for change in &self.changes {
+            universe.set(change.channel, change.old);
+        }
+    }
+}
+
+/// Collects changes in any order, merging those of the same channel as
+/// `push` does.
+impl FromIterator<ChannelChange> for UniverseDiff {
+    fn from_iter<I: IntoIterator<Item = ChannelChange>>(changes: I) -> UniverseDiff {
+        let mut diff = UniverseDiff::new();
+        for change in changes {
+            diff.push(change.channel, change.old, change.new);
+        }
+        diff
+    }
+}
+
+impl From<Vec<ChannelChange>> for UniverseDiff {
+    #[inline]
+    fn from(changes: Vec<ChannelChange>) -> UniverseDiff {
+        changes.into_iter().collect()
+    }
+}
+
+impl From<UniverseDiff> for Vec<ChannelChange> {
+    #[inline]
+    fn from(diff: UniverseDiff) -> Vec<ChannelChange> {
+        diff.changes
+    }
+}
+
+impl<'a> IntoIterator for &'a UniverseDiff {
+    type Item = &'a ChannelChange;
+    type IntoIter = std::slice::Iter<'a, ChannelChange>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.iter()
+    }
+}
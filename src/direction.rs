@@ -0,0 +1,87 @@
+//! Transceiver direction control.
+//!
+//! Half-duplex RS485 transceivers have a driver-enable (DE) pin, which must
+//! be asserted while transmitting and released to receive, e.g. for RDM
+//! responses. If it is not handled by the UART or kernel (see
+//! `DmxPortBuilder::enable_rs485`), a `DirectionControl` can be attached to
+//! a port through `DmxPort::set_direction_control`.
+//!
+//! With the `gpio-cdev` feature, pins exported through the Linux GPIO
+//! character device can be used, see `GpioDirection`.
+
+use std::io;
+
+/// Switches a transceiver between transmitting and receiving.
+///
+/// Implemented for closures taking the new direction as well.
+pub trait DirectionControl {
+    /// Enables the driver if `transmit` is `true`, otherwise releases the
+    /// line.
+    fn set_transmit(&mut self, transmit: bool) -> io::Result<()>;
+}
+
+impl<F> DirectionControl for F
+where
+    F: FnMut(bool) -> io::Result<()>,
+{
+    #[inline]
+    fn set_transmit(&mut self, transmit: bool) -> io::Result<()> {
+        self(transmit)
+    }
+}
+
+/// Direction control through a GPIO line.
+///
+/// The line is driven high while transmitting. Requires the `gpio-cdev`
+/// feature and is only available on Linux.
+#[cfg(all(target_os = "linux", feature = "gpio-cdev"))]
+#[derive(Debug)]
+pub struct GpioDirection {
+    handle: gpio_cdev::LineHandle,
+}
+
+#[cfg(all(target_os = "linux", feature = "gpio-cdev"))]
+impl GpioDirection {
+    /// Requests line `line` of a GPIO chip, e.g. `/dev/gpiochip0`.
+    ///
+    /// The line is configured as an output and starts out low, i.e.
+    /// receiving.
+    pub fn new<P: AsRef<std::path::Path>>(chip: P, line: u32) -> io::Result<GpioDirection> {
+        let mut chip = gpio_cdev::Chip::new(chip).map_err(gpio_error)?;
+        let handle = chip
+            .get_line(line)
+            .and_then(|line| line.request(gpio_cdev::LineRequestFlags::OUTPUT, 0, "dmx"))
+            .map_err(gpio_error)?;
+
+        Ok(GpioDirection { handle })
+    }
+
+    /// Create a direction control from an already requested output line.
+    ///
+    /// Lines requested with `LineRequestFlags::ACTIVE_LOW` are driven low
+    /// while transmitting.
+    #[inline]
+    pub fn from_handle(handle: gpio_cdev::LineHandle) -> GpioDirection {
+        GpioDirection { handle }
+    }
+
+    /// Returns the line handle.
+    #[inline]
+    pub fn into_inner(self) -> gpio_cdev::LineHandle {
+        self.handle
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "gpio-cdev"))]
+impl DirectionControl for GpioDirection {
+    #[inline]
+    fn set_transmit(&mut self, transmit: bool) -> io::Result<()> {
+        self.handle.set_value(u8::from(transmit)).map_err(gpio_error)
+    }
+}
+
+/// Converts a GPIO error into an I/O error.
+#[cfg(all(target_os = "linux", feature = "gpio-cdev"))]
+pub(crate) fn gpio_error(e: gpio_cdev::Error) -> io::Error {
+    io::Error::other(e)
+}
@@ -0,0 +1,124 @@
+//! DMXKing device support.
+//!
+//! The USB interfaces of DMXKing, such as the ultraDMX MAX and the
+//! two-port ultraDMX Pro, speak the protocol of the Enttec DMX USB Pro, see
+//! the `enttec` module. They extend it with a DMX output label for each port,
+//! starting at 100 for the first port, and identify themselves through the
+//! manufacturer and device labels.
+//!
+//! The eDMX network units are Art-Net nodes, found with `discover` and driven
+//! by an `ArtNetTransmitter` for each of their outputs.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use dmx::{dmxking, DmxOutputManager};
+//!
+//! let mut manager = DmxOutputManager::new();
+//!
+//! // both ports of an ultraDMX Pro, as universes 1 and 2
+//! for (n, port) in dmxking::open("/dev/ttyUSB0", 2).unwrap().into_iter().enumerate() {
+//!     manager.add_output(n as u16 + 1, Box::new(port));
+//! }
+//!
+//! // every output of every eDMX unit, from universe 3 on
+//! let mut universe = 3;
+//! for node in dmxking::discover(Duration::from_secs(3)).unwrap() {
+//!     for transmitter in dmxking::transmitters(&node).unwrap() {
+//!         manager.add_output(universe, Box::new(transmitter));
+//!         universe += 1;
+//!     }
+//! }
+//! ```
+
+use std::path::Path;
+use std::{io, time};
+
+use crate::artnet::{self, ArtNetTransmitter, ArtNode};
+use crate::enttec::{EnttecPro, EnttecProPort, PortLabels};
+use crate::{Error, Result};
+
+/// ESTA manufacturer ID of DMXKing.
+pub const ESTA_ID: u16 = 0x6a6b;
+
+/// Message label: output-only send DMX packet to the first port. Further
+/// ports follow in order.
+pub const LABEL_SEND_DMX_PORT_A: u8 = 100;
+
+/// Highest number of DMX ports of a USB interface.
+pub const MAX_PORTS: u8 = 2;
+
+/// Returns the labels of a port of a USB interface, numbered from 0.
+///
+/// RDM requests and received data always go through the first port, as the
+/// protocol only extends DMX output to further ports. Returns `None` if
+/// `port` is out of range.
+#[inline]
+pub fn port_labels(port: u8) -> Option<PortLabels> {
+    if port >= MAX_PORTS {
+        return None;
+    }
+
+    Some(PortLabels {
+        send_dmx: LABEL_SEND_DMX_PORT_A + port,
+        ..PortLabels::PORT_1
+    })
+}
+
+/// Returns the device name if the widget is made by DMXKing.
+///
+/// Widgets not supporting the manufacturer label, such as those made by
+/// Enttec, yield `Ok(None)`.
+pub fn identify(widget: &mut EnttecPro) -> Result<Option<String>> {
+    match widget.manufacturer() {
+        Ok((ESTA_ID, _)) => Ok(Some(widget.device()?.1)),
+        Ok(_) | Err(Error::Timeout) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Opens a USB interface and returns a handle for each of its first
+/// `ports` ports.
+///
+/// The ultraDMX MAX has a single port, the ultraDMX Pro two. Fails with
+/// `Error::InvalidParameter` if `ports` is zero or exceeds `MAX_PORTS`,
+/// and with `Error::Unsupported` if the widget is not made by DMXKing.
+pub fn open<P: AsRef<Path>>(path: P, ports: u8) -> Result<Vec<EnttecProPort>> {
+    if ports == 0 || ports > MAX_PORTS {
+        return Err(Error::InvalidParameter("invalid number of ports"));
+    }
+
+    let mut widget = EnttecPro::open(path)?;
+    if identify(&mut widget)?.is_none() {
+        return Err(Error::Unsupported("not a DMXKing interface"));
+    }
+
+    let labels: Vec<PortLabels> = (0..ports).filter_map(port_labels).collect();
+    Ok(widget.into_ports(&labels))
+}
+
+/// Discovers eDMX units on the local network.
+///
+/// Polls for Art-Net nodes, see `artnet::discover`, and returns those made
+/// by DMXKing.
+pub fn discover(timeout: time::Duration) -> io::Result<Vec<ArtNode>> {
+    let mut nodes = artnet::discover(timeout)?;
+    nodes.retain(is_edmx);
+
+    Ok(nodes)
+}
+
+/// Returns whether an Art-Net node is made by DMXKing.
+#[inline]
+pub fn is_edmx(node: &ArtNode) -> bool {
+    node.esta_manufacturer == ESTA_ID
+}
+
+/// Create a transmitter for each DMX output of a node, in port order.
+pub fn transmitters(node: &ArtNode) -> io::Result<Vec<ArtNetTransmitter>> {
+    node.outputs
+        .iter()
+        .map(|&address| ArtNetTransmitter::new(node.socket_addr(), address))
+        .collect()
+}
@@ -0,0 +1,604 @@
+//! Generated effects.
+//!
+//! An `Effect` computes channel values from the time since it started,
+//! without keeping any state, so effects can be rendered standalone, seeked
+//! and restarted at will. The built-in effects are
+//!
+//! * `Chase`, lighting a block of fixtures that moves along a range,
+//! * `Strobe`, flashing all channels of a range,
+//! * `Rainbow`, cycling RGB fixtures through all hues,
+//! * `SineWave`, fading channels up and down smoothly,
+//! * `Random`, setting channels to random values.
+//!
+//! Effects operating on fixtures rather than single channels have a `width`,
+//! the number of consecutive channels of each fixture, which all receive the
+//! same value. Periodic effects have a `phase`, a fraction of a full cycle by
+//! which they are shifted, to run several instances out of step.
+//!
+//! An `EffectEngine` renders any number of effects into channel ranges of a
+//! universe. By default, later effects overwrite the output of earlier ones
+//! where their ranges overlap; a `BlendMode` combines them instead, e.g. to
+//! multiply a chase with a sine wave. Each effect added to the engine runs at
+//! its own speed, size and time offset, which can be changed through an
+//! `EffectHandle` while the effect is running.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use std::{thread, time};
+//! use dmx::{DmxAddress, DmxTransmitter, DmxUniverse};
+//! use dmx::effects::{BlendMode, Chase, EffectEngine, Rainbow, SineWave};
+//!
+//! let mut engine = EffectEngine::new();
+//! // eight RGB pars at channel 1, four dimmers at channel 25
+//! engine.add(DmxAddress::new(1).unwrap(), 24, Rainbow::new(0.2));
+//! let chase = engine.add(DmxAddress::new(25).unwrap(), 4, Chase::new(4.0));
+//! // breathing on top of the chase
+//! let wave = engine.add(DmxAddress::new(25).unwrap(), 4, SineWave::new(0.5));
+//! engine.set_blend(wave, BlendMode::Multiply);
+//!
+//! // e.g. adjusted from a fader on another thread
+//! let handle = engine.handle(chase).unwrap();
+//! handle.set_speed(2.0);
+//!
+//! let mut dmx_port = dmx::open_serial("/dev/ttyS1").unwrap();
+//! let mut universe = DmxUniverse::new();
+//! let period = time::Duration::from_millis(25);
+//!
+//! loop {
+//!     engine.tick(period, &mut universe);
+//!     dmx_port.send_universe(&universe).unwrap();
+//!     thread::sleep(period);
+//! }
+//! ```
+
+use std::f64::consts::TAU;
+use std::sync::{Arc, Mutex};
+use std::{fmt, time};
+
+use crate::address::DmxAddress;
+use crate::color::{Color, ColorOrder};
+use crate::packet::MAX_CHANNELS;
+use crate::universe::DmxUniverse;
+
+/// An effect generating channel values.
+pub trait Effect {
+    /// Writes the values of the effect at time `t` since it started into
+    /// `channels`.
+    fn render(&self, t: time::Duration, channels: &mut [u8]);
+}
+
+impl<E: Effect + ?Sized> Effect for Box<E> {
+    #[inline]
+    fn render(&self, t: time::Duration, channels: &mut [u8]) {
+        (**self).render(t, channels)
+    }
+}
+
+/// Returns the position within the current cycle, from 0 to 1.
+#[inline]
+fn cycle(t: time::Duration, speed: f32, phase: f32) -> f64 {
+    (t.as_secs_f64() * f64::from(speed) + f64::from(phase)).rem_euclid(1.0)
+}
+
+/// Scales `x`, from 0 to 1, onto the range from `min` to `max`.
+#[inline]
+fn scale(min: u8, max: u8, x: f64) -> u8 {
+    let v = f64::from(min) + (f64::from(max) - f64::from(min)) * x;
+    v.round().clamp(0.0, 255.0) as u8
+}
+
+/// Number of fixtures of `width` channels in `channels`.
+#[inline]
+fn fixtures(channels: &[u8], width: usize) -> usize {
+    channels.len().div_ceil(width)
+}
+
+/// Moves a block of lit fixtures along a range, wrapping around at its end.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Chase {
+    /// Steps per second, negative to run backwards.
+    pub speed: f32,
+    /// Number of fixtures lit at once.
+    pub size: usize,
+    /// Channels per fixture.
+    pub width: usize,
+    /// Fraction of a full cycle by which the chase is shifted.
+    pub phase: f32,
+    /// Value of lit channels; all others are set to zero.
+    pub level: u8,
+}
+
+impl Chase {
+    /// Create a chase lighting one channel at a time, at full.
+    #[inline]
+    pub fn new(speed: f32) -> Chase {
+        Chase {
+            speed,
+            size: 1,
+            width: 1,
+            phase: 0.0,
+            level: 0xff,
+        }
+    }
+}
+
+impl Effect for Chase {
+    fn render(&self, t: time::Duration, channels: &mut [u8]) {
+        let width = self.width.max(1);
+        let count = fixtures(channels, width);
+        if count == 0 {
+            return;
+        }
+
+        // a full cycle takes one step per fixture
+        let speed = self.speed / count as f32;
+        let step = (cycle(t, speed, self.phase) * count as f64) as usize;
+
+        for (i, fixture) in channels.chunks_mut(width).enumerate() {
+            let lit = (i + count - step % count) % count < self.size;
+            for v in fixture {
+                *v = if lit { self.level } else { 0 };
+            }
+        }
+    }
+}
+
+/// Flashes all channels of a range.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Strobe {
+    /// Flashes per second.
+    pub rate: f32,
+    /// Fraction of each period the channels are lit, from 0 to 1.
+    pub duty: f32,
+    /// Fraction of a period by which the flashes are shifted.
+    pub phase: f32,
+    /// Value of the channels while lit; they are zero otherwise.
+    pub level: u8,
+}
+
+impl Strobe {
+    /// Create a strobe flashing at full, lit for a tenth of each period.
+    #[inline]
+    pub fn new(rate: f32) -> Strobe {
+        Strobe {
+            rate,
+            duty: 0.1,
+            phase: 0.0,
+            level: 0xff,
+        }
+    }
+}
+
+impl Effect for Strobe {
+    fn render(&self, t: time::Duration, channels: &mut [u8]) {
+        let lit = cycle(t, self.rate, self.phase) < f64::from(self.duty);
+
+        for v in channels {
+            *v = if lit { self.level } else { 0 };
+        }
+    }
+}
+
+/// Cycles RGB fixtures through all hues.
+///
+/// Renders three channels per fixture, in red, green, blue order.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rainbow {
+    /// Full hue cycles per second.
+    pub speed: f32,
+    /// Number of hue cycles spread across the range; zero shows the same
+    /// hue on all fixtures.
+    pub spread: f32,
+    /// Fraction of a full cycle by which the hues are shifted.
+    pub phase: f32,
+    /// Saturation, from 0 to 1.
+    pub saturation: f32,
+    /// Brightness, from 0 to 1.
+    pub brightness: f32,
+}
+
+impl Rainbow {
+    /// Create a rainbow of fully saturated colors, spread once across the
+    /// range.
+    #[inline]
+    pub fn new(speed: f32) -> Rainbow {
+        Rainbow {
+            speed,
+            spread: 1.0,
+            phase: 0.0,
+            saturation: 1.0,
+            brightness: 1.0,
+        }
+    }
+}
+
+impl Effect for Rainbow {
+    fn render(&self, t: time::Duration, channels: &mut [u8]) {
+        let count = fixtures(channels, 3);
+        let offset = cycle(t, self.speed, self.phase);
+
+        for (i, fixture) in channels.chunks_mut(3).enumerate() {
+            let hue = offset + f64::from(self.spread) * i as f64 / count as f64;
+            let color = Color::from_hsv(
+                (hue.rem_euclid(1.0) * 360.0) as f32,
+                self.saturation,
+                self.brightness,
+            );
+
+            ColorOrder::Rgb.write(color, fixture);
+        }
+    }
+}
+
+/// Fades channels up and down along a sine wave.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SineWave {
+    /// Full waves per second.
+    pub speed: f32,
+    /// Number of waves spread across the range; zero fades all fixtures in
+    /// unison.
+    pub spread: f32,
+    /// Channels per fixture.
+    pub width: usize,
+    /// Fraction of a full wave by which the wave is shifted.
+    pub phase: f32,
+    /// Value at the bottom of the wave.
+    pub min: u8,
+    /// Value at the top of the wave.
+    pub max: u8,
+}
+
+impl SineWave {
+    /// Create a wave between zero and full, spread once across the range.
+    #[inline]
+    pub fn new(speed: f32) -> SineWave {
+        SineWave {
+            speed,
+            spread: 1.0,
+            width: 1,
+            phase: 0.0,
+            min: 0,
+            max: 0xff,
+        }
+    }
+}
+
+impl Effect for SineWave {
+    fn render(&self, t: time::Duration, channels: &mut [u8]) {
+        let width = self.width.max(1);
+        let count = fixtures(channels, width);
+        let offset = cycle(t, self.speed, self.phase);
+
+        for (i, fixture) in channels.chunks_mut(width).enumerate() {
+            // fixtures further along the range lag behind
+            let x = offset - f64::from(self.spread) * i as f64 / count as f64;
+            let value = scale(self.min, self.max, 0.5 - 0.5 * (x * TAU).cos());
+
+            for v in fixture {
+                *v = value;
+            }
+        }
+    }
+}
+
+/// Sets channels to random values at a fixed rate.
+///
+/// Values are derived from the seed and the time, so rendering the same
+/// point in time always yields the same values.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Random {
+    /// Changes per second.
+    pub speed: f32,
+    /// Channels per fixture.
+    pub width: usize,
+    /// Lowest value generated.
+    pub min: u8,
+    /// Highest value generated.
+    pub max: u8,
+    /// Seed, instances with different seeds generate different values.
+    pub seed: u64,
+}
+
+impl Random {
+    /// Create an effect generating values across the full range.
+    #[inline]
+    pub fn new(speed: f32) -> Random {
+        Random {
+            speed,
+            width: 1,
+            min: 0,
+            max: 0xff,
+            seed: 0,
+        }
+    }
+}
+
+impl Effect for Random {
+    fn render(&self, t: time::Duration, channels: &mut [u8]) {
+        let step = (t.as_secs_f64() * f64::from(self.speed)).max(0.0) as u64;
+        let (min, max) = (self.min.min(self.max), self.min.max(self.max));
+        let range = u64::from(max - min) + 1;
+
+        for (i, fixture) in channels.chunks_mut(self.width.max(1)).enumerate() {
+            let value = min + (mix(self.seed, step, i as u64) % range) as u8;
+
+            for v in fixture {
+                *v = value;
+            }
+        }
+    }
+}
+
+/// Hashes its inputs into a pseudo-random number (SplitMix64).
+fn mix(seed: u64, step: u64, index: u64) -> u64 {
+    let mut x = seed
+        ^ step.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ index.wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Identifies an effect added to an `EffectEngine`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EffectId(usize);
+
+/// How the output of an effect is combined with the channels below it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BlendMode {
+    /// Highest takes precedence, the higher of both values is output.
+    Htp,
+    /// Latest takes precedence, the effect overwrites the channels.
+    #[default]
+    Ltp,
+    /// Both values are added, saturating at full.
+    Add,
+    /// Both values are multiplied, as fractions of full; the effect acts as
+    /// a mask on the channels.
+    Multiply,
+}
+
+impl BlendMode {
+    /// Combines the value `below` an effect with its value `over` it.
+    #[inline]
+    pub fn blend(self, below: u8, over: u8) -> u8 {
+        match self {
+            BlendMode::Htp => below.max(over),
+            BlendMode::Ltp => over,
+            BlendMode::Add => below.saturating_add(over),
+            BlendMode::Multiply => ((u16::from(below) * u16::from(over) + 127) / 255) as u8,
+        }
+    }
+}
+
+/// Parameters of an effect running in an `EffectEngine`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EffectParams {
+    /// Factor by which the effect runs faster than the engine, zero freezes
+    /// it; negative speeds are treated as zero.
+    pub speed: f32,
+    /// Factor by which the values generated are scaled, from 0 to 1.
+    pub size: f32,
+    /// Time by which the effect runs ahead of its own clock.
+    pub offset: time::Duration,
+}
+
+impl Default for EffectParams {
+    #[inline]
+    fn default() -> EffectParams {
+        EffectParams {
+            speed: 1.0,
+            size: 1.0,
+            offset: time::Duration::ZERO,
+        }
+    }
+}
+
+/// Changes the parameters of an effect while it is running.
+///
+/// Returned by `EffectEngine::handle`. Handles can be cloned and sent to
+/// other threads; changes apply from the next time the engine renders.
+/// Changing the speed does not restart the effect, it continues from where
+/// it is at the new speed. Handles of removed effects have no effect.
+#[derive(Clone, Debug)]
+pub struct EffectHandle {
+    params: Arc<Mutex<EffectParams>>,
+}
+
+impl EffectHandle {
+    /// Returns the current parameters.
+    #[inline]
+    pub fn params(&self) -> EffectParams {
+        *self.params.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sets all parameters at once.
+    #[inline]
+    pub fn set_params(&self, params: EffectParams) {
+        *self.params.lock().unwrap_or_else(|e| e.into_inner()) = params;
+    }
+
+    /// Sets the factor by which the effect runs faster than the engine.
+    #[inline]
+    pub fn set_speed(&self, speed: f32) {
+        self.params.lock().unwrap_or_else(|e| e.into_inner()).speed = speed;
+    }
+
+    /// Sets the factor by which the values generated are scaled.
+    #[inline]
+    pub fn set_size(&self, size: f32) {
+        self.params.lock().unwrap_or_else(|e| e.into_inner()).size = size;
+    }
+
+    /// Sets the time by which the effect runs ahead of its own clock.
+    #[inline]
+    pub fn set_offset(&self, offset: time::Duration) {
+        self.params.lock().unwrap_or_else(|e| e.into_inner()).offset = offset;
+    }
+}
+
+struct Layer {
+    start: DmxAddress,
+    count: usize,
+    blend: BlendMode,
+    params: Arc<Mutex<EffectParams>>,
+    // time of the effect's own clock, advancing at its speed
+    elapsed: time::Duration,
+    effect: Box<dyn Effect + Send>,
+}
+
+/// Renders effects into a universe.
+///
+/// Effects are rendered in the order they were added, each blended with the
+/// output of the effects before it. Every effect has a clock of its own,
+/// advancing with the engine at the effect's speed, so effects added together
+/// stay in step as long as their speeds are the same.
+#[derive(Default)]
+pub struct EffectEngine {
+    layers: Vec<Option<Layer>>,
+    elapsed: time::Duration,
+}
+
+impl EffectEngine {
+    /// Create an engine without any effects.
+    #[inline]
+    pub fn new() -> EffectEngine {
+        EffectEngine::default()
+    }
+
+    /// Adds an effect rendering into `count` consecutive channels, starting
+    /// at channel `start`.
+    ///
+    /// Channels beyond 512 are ignored.
+    pub fn add<E: Effect + Send + 'static>(
+        &mut self,
+        start: DmxAddress,
+        count: usize,
+        effect: E,
+    ) -> EffectId {
+        let layer = Layer {
+            start,
+            count,
+            blend: BlendMode::default(),
+            params: Arc::new(Mutex::new(EffectParams::default())),
+            elapsed: self.elapsed,
+            effect: Box::new(effect),
+        };
+
+        // reuse the slot of a removed effect
+        match self.layers.iter().position(Option::is_none) {
+            Some(n) => {
+                self.layers[n] = Some(layer);
+                EffectId(n)
+            }
+            None => {
+                self.layers.push(Some(layer));
+                EffectId(self.layers.len() - 1)
+            }
+        }
+    }
+
+    /// Removes an effect.
+    ///
+    /// Its channels keep their last values. The id may be reused by effects
+    /// added later.
+    #[inline]
+    pub fn remove(&mut self, effect: EffectId) {
+        if let Some(slot) = self.layers.get_mut(effect.0) {
+            *slot = None;
+        }
+    }
+
+    /// Removes all effects.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.layers.clear();
+    }
+
+    /// Returns a handle to change the parameters of an effect while it is
+    /// running, or `None` if it was removed.
+    #[inline]
+    pub fn handle(&self, effect: EffectId) -> Option<EffectHandle> {
+        let layer = self.layers.get(effect.0)?.as_ref()?;
+
+        Some(EffectHandle {
+            params: layer.params.clone(),
+        })
+    }
+
+    /// Sets how an effect is combined with the channels below it.
+    #[inline]
+    pub fn set_blend(&mut self, effect: EffectId, blend: BlendMode) {
+        if let Some(Some(layer)) = self.layers.get_mut(effect.0) {
+            layer.blend = blend;
+        }
+    }
+
+    /// Returns the time the engine has been running.
+    #[inline]
+    pub fn elapsed(&self) -> time::Duration {
+        self.elapsed
+    }
+
+    /// Sets the time the engine has been running, e.g. to restart effects.
+    ///
+    /// The clocks of all effects are set to the same time, regardless of
+    /// their speeds.
+    pub fn set_elapsed(&mut self, elapsed: time::Duration) {
+        self.elapsed = elapsed;
+        for layer in self.layers.iter_mut().flatten() {
+            layer.elapsed = elapsed;
+        }
+    }
+
+    /// Advances all effects by `dt` and renders them into `universe`.
+    pub fn tick(&mut self, dt: time::Duration, universe: &mut DmxUniverse) {
+        self.elapsed += dt;
+        for layer in self.layers.iter_mut().flatten() {
+            let speed = layer.params.lock().unwrap_or_else(|e| e.into_inner()).speed;
+            layer.elapsed += dt.mul_f32(speed.max(0.0));
+        }
+
+        self.render(universe);
+    }
+
+    /// Renders all effects at the current time into `universe`.
+    pub fn render(&self, universe: &mut DmxUniverse) {
+        let channels = universe.channels_mut();
+        let mut buffer = [0; MAX_CHANNELS];
+
+        for layer in self.layers.iter().flatten() {
+            let params = *layer.params.lock().unwrap_or_else(|e| e.into_inner());
+            let range = &mut channels[layer.start.index()..];
+            let len = range.len().min(layer.count);
+            let output = &mut buffer[..len];
+
+            output.copy_from_slice(&range[..len]);
+            layer.effect.render(layer.elapsed + params.offset, output);
+
+            let size = params.size.clamp(0.0, 1.0);
+            for (v, &value) in range.iter_mut().zip(output.iter()) {
+                let value = (f32::from(value) * size).round() as u8;
+                *v = layer.blend.blend(*v, value);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for EffectEngine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EffectEngine")
+            .field("effects", &self.layers.iter().flatten().count())
+            .field("elapsed", &self.elapsed)
+            .finish()
+    }
+}
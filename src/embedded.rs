@@ -0,0 +1,117 @@
+//! Transmission through `embedded-hal` peripherals.
+//!
+//! Allows sending DMX from microcontrollers, using any UART implementing
+//! `embedded_hal::serial::Write` and a microsecond delay. The UART must be
+//! configured for 250,000 baud, 8 data bits and 2 stop bits by the caller.
+//!
+//! Breaks cannot be generated through the serial traits. Instead, a callback
+//! is invoked to hold the line low, which usually means setting the UART's
+//! send-break bit or temporarily switching the TX pin to a low GPIO output.
+//!
+//! Requires the `embedded-hal` feature, which is available without `std`.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! use dmx::DmxTransmitter;
+//! use dmx::embedded::EmbeddedTransmitter;
+//!
+//! let mut dmx = EmbeddedTransmitter::new(uart, delay, |uart: &mut Uart, on| {
+//!     uart.set_break(on);
+//!     Ok(())
+//! });
+//!
+//! dmx.send_dmx_packet(&[0xff, 0x00, 0x80]).unwrap();
+//! ```
+
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::serial::Write;
+
+use crate::timing::DmxTiming;
+use crate::DmxTransmitter;
+
+/// A DMX transmitter driving an `embedded-hal` UART.
+///
+/// `S` is the UART, `D` the delay provider and `L` the line-control callback,
+/// which is called with `true` to start a break and `false` to end it.
+///
+/// There is no clock to measure the time between packets, so the caller must
+/// take care not to send packets more often than every 1204 microseconds.
+pub struct EmbeddedTransmitter<S, D, L> {
+    serial: S,
+    delay: D,
+    line_control: L,
+    timing: DmxTiming,
+}
+
+impl<S, D, L> EmbeddedTransmitter<S, D, L>
+where
+    S: Write<u8>,
+    D: DelayUs<u32>,
+    L: FnMut(&mut S, bool) -> Result<(), S::Error>,
+{
+    /// Create a new transmitter using the default timing.
+    #[inline]
+    pub fn new(serial: S, delay: D, line_control: L) -> EmbeddedTransmitter<S, D, L> {
+        EmbeddedTransmitter {
+            serial,
+            delay,
+            line_control,
+            timing: DmxTiming::default(),
+        }
+    }
+
+    /// Returns the current timing parameters.
+    #[inline]
+    pub fn timing(&self) -> DmxTiming {
+        self.timing
+    }
+
+    /// Sets the timing parameters.
+    ///
+    /// Only break and mark-after-break durations are used.
+    #[inline]
+    pub fn set_timing(&mut self, timing: DmxTiming) -> Result<(), crate::TimingError> {
+        timing.validate()?;
+        self.timing = timing;
+        Ok(())
+    }
+
+    /// Returns the UART, delay provider and line-control callback.
+    #[inline]
+    pub fn into_inner(self) -> (S, D, L) {
+        (self.serial, self.delay, self.line_control)
+    }
+}
+
+impl<S, D, L> DmxTransmitter for EmbeddedTransmitter<S, D, L>
+where
+    S: Write<u8>,
+    D: DelayUs<u32>,
+    L: FnMut(&mut S, bool) -> Result<(), S::Error>,
+{
+    type Error = S::Error;
+
+    fn send_break(&mut self) -> Result<(), S::Error> {
+        // the break condition would otherwise cut off any pending data
+        nb::block!(self.serial.flush())?;
+
+        (self.line_control)(&mut self.serial, true)?;
+        self.delay.delay_us(self.timing.break_us);
+        (self.line_control)(&mut self.serial, false)
+    }
+
+    fn send_raw_data(&mut self, data: &[u8]) -> Result<(), S::Error> {
+        for &byte in data {
+            nb::block!(self.serial.write(byte))?;
+        }
+
+        Ok(())
+    }
+
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<(), S::Error> {
+        self.send_break()?;
+        self.delay.delay_us(self.timing.mab_us);
+        self.send_raw_data(data)
+    }
+}
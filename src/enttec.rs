@@ -0,0 +1,713 @@
+//! Enttec DMX USB Pro support.
+//!
+//! The DMX USB Pro does not expose a raw UART. Instead, the host exchanges
+//! framed messages with the widget's microcontroller over an FTDI virtual
+//! serial port, which in turn generates the DMX signal including all timing.
+//!
+//! Each message is framed as follows:
+//!
+//! ```text
+//! 0x7E | label | length LSB | length MSB | data... | 0xE7
+//! ```
+//!
+//! RDM requests are sent through dedicated labels, after which the widget
+//! turns the line around, see `DmxTransceiver`.
+//!
+//! The DMX USB Pro Mk2 has a second DMX port, which is disabled until an API
+//! key is sent. ENTTEC hands out keys to developers on request, along with
+//! the labels used for the second port; both are passed in a `Mk2Config` to
+//! `EnttecPro::into_mk2_ports`, which returns a handle for each port.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use dmx::DmxTransmitter;
+//! use dmx::enttec::{EnttecPro, Mk2Config, PortLabels};
+//!
+//! let config = Mk2Config {
+//!     api_key: 0x0123_4567,
+//!     set_port_assignment: 0x90,
+//!     port_2: PortLabels {
+//!         send_dmx: 0x91,
+//!         send_rdm: 0x92,
+//!         send_rdm_discovery: 0x93,
+//!         received_dmx: 0x94,
+//!     },
+//! };
+//!
+//! let widget = EnttecPro::open("/dev/ttyUSB0").unwrap();
+//! let (mut port_1, mut port_2) = widget.into_mk2_ports(&config).unwrap();
+//!
+//! port_1.send_dmx_packet(&[0xff; 16]).unwrap();
+//! port_2.send_dmx_packet(&[0x80; 16]).unwrap();
+//! ```
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::{cmp, io, time};
+
+use crate::rdm::{CommandClass, RdmRequest, PID_DISC_UNIQUE_BRANCH, SC_RDM};
+use crate::{DmxTransceiver, DmxTransmitter, Error, Result};
+
+const START_OF_MESSAGE: u8 = 0x7e;
+const END_OF_MESSAGE: u8 = 0xe7;
+
+/// Maximum length of message data.
+pub const MAX_MESSAGE_LEN: usize = 600;
+
+/// Message label: get widget parameters.
+pub const LABEL_GET_PARAMETERS: u8 = 3;
+/// Message label: set widget parameters.
+pub const LABEL_SET_PARAMETERS: u8 = 4;
+/// Message label: received DMX packet.
+pub const LABEL_RECEIVED_DMX: u8 = 5;
+/// Message label: output-only send DMX packet.
+pub const LABEL_SEND_DMX: u8 = 6;
+/// Message label: send RDM packet, then receive the response.
+pub const LABEL_SEND_RDM: u8 = 7;
+/// Message label: get widget serial number.
+pub const LABEL_GET_SERIAL: u8 = 10;
+/// Message label: send RDM discovery request, then receive the response.
+pub const LABEL_SEND_RDM_DISCOVERY: u8 = 11;
+/// Message label: set the API key of a DMX USB Pro Mk2.
+pub const LABEL_SET_API_KEY: u8 = 13;
+/// Message label: get the manufacturer's ESTA ID and name.
+pub const LABEL_GET_MANUFACTURER: u8 = 77;
+/// Message label: get the device ID and name.
+pub const LABEL_GET_DEVICE: u8 = 78;
+
+// the widget expects at least 24 channels
+const MIN_CHANNELS: usize = 24;
+
+// widget timing values are specified in units of 10.67 microseconds
+const TIME_UNIT_NS: u64 = 10_670;
+
+/// Duration to wait for replies of the widget.
+const REPLY_TIMEOUT: time::Duration = time::Duration::from_millis(500);
+
+/// Widget parameters.
+///
+/// Break and mark-after-break durations are given in the widget's native
+/// units of 10.67 microseconds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WidgetParameters {
+    /// Firmware version, read-only.
+    pub firmware_version: u16,
+    /// Break duration, 9 to 127 units.
+    pub break_time: u8,
+    /// Mark-after-break duration, 1 to 127 units.
+    pub mab_time: u8,
+    /// Packets per second, 1 to 40, or 0 to send as fast as possible.
+    pub refresh_rate: u8,
+}
+
+impl WidgetParameters {
+    /// Returns the break duration.
+    #[inline]
+    pub fn break_duration(&self) -> time::Duration {
+        time::Duration::from_nanos(u64::from(self.break_time) * TIME_UNIT_NS)
+    }
+
+    /// Returns the mark-after-break duration.
+    #[inline]
+    pub fn mab_duration(&self) -> time::Duration {
+        time::Duration::from_nanos(u64::from(self.mab_time) * TIME_UNIT_NS)
+    }
+
+    /// Sets break and mark-after-break durations, in microseconds.
+    ///
+    /// Values are rounded up to the next unit and clamped to the range
+    /// supported by the widget.
+    pub fn set_timing_us(&mut self, break_us: u32, mab_us: u32) {
+        let units = |us: u32| (u64::from(us) * 1000).div_ceil(TIME_UNIT_NS);
+
+        self.break_time = units(break_us).clamp(9, 127) as u8;
+        self.mab_time = units(mab_us).clamp(1, 127) as u8;
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(9..=127).contains(&self.break_time)
+            || !(1..=127).contains(&self.mab_time)
+            || self.refresh_rate > 40
+        {
+            return Err(Error::InvalidParameter("widget parameters out of range"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Message labels of a DMX port of a widget.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PortLabels {
+    /// Sends a DMX packet.
+    pub send_dmx: u8,
+    /// Sends an RDM packet and receives the response.
+    pub send_rdm: u8,
+    /// Sends an RDM discovery request and receives the response.
+    pub send_rdm_discovery: u8,
+    /// Carries received packets, including RDM responses.
+    pub received_dmx: u8,
+}
+
+impl PortLabels {
+    /// Labels of the first port, the only one on widgets other than the
+    /// Mk2.
+    pub const PORT_1: PortLabels = PortLabels {
+        send_dmx: LABEL_SEND_DMX,
+        send_rdm: LABEL_SEND_RDM,
+        send_rdm_discovery: LABEL_SEND_RDM_DISCOVERY,
+        received_dmx: LABEL_RECEIVED_DMX,
+    };
+}
+
+/// Configuration unlocking the second port of a DMX USB Pro Mk2.
+///
+/// The key and the labels of the second port are assigned by ENTTEC.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Mk2Config {
+    /// API key.
+    pub api_key: u32,
+    /// Label enabling ports for DMX output.
+    pub set_port_assignment: u8,
+    /// Labels of the second port.
+    pub port_2: PortLabels,
+}
+
+/// An Enttec DMX USB Pro widget.
+///
+/// Breaks are generated by the widget, so `send_break` does nothing and only
+/// complete packets can be sent. Packets starting with the RDM start code
+/// are sent as RDM requests, the response is read by `recv_raw_data`.
+#[derive(Debug)]
+pub struct EnttecPro {
+    port: serial2::SerialPort,
+}
+
+impl EnttecPro {
+    /// Opens a widget connected to a serial device.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<EnttecPro> {
+        // the baud rate is ignored by the FTDI chip's virtual COM port
+        EnttecPro::from_serial_port(serial2::SerialPort::open(path, 57_600)?)
+    }
+
+    /// Create a widget from an already opened serial port.
+    pub fn from_serial_port(mut port: serial2::SerialPort) -> io::Result<EnttecPro> {
+        port.set_read_timeout(REPLY_TIMEOUT)?;
+        Ok(EnttecPro { port })
+    }
+
+    /// Returns the underlying serial port.
+    #[inline]
+    pub fn into_inner(self) -> serial2::SerialPort {
+        self.port
+    }
+
+    /// Sends a message to the widget.
+    pub fn send_message(&mut self, label: u8, data: &[u8]) -> Result<()> {
+        if data.len() > MAX_MESSAGE_LEN {
+            return Err(Error::PacketTooLong(data.len()));
+        }
+
+        let mut buf = [0; MAX_MESSAGE_LEN + 5];
+        let len = data.len();
+
+        buf[0] = START_OF_MESSAGE;
+        buf[1] = label;
+        buf[2..4].copy_from_slice(&(len as u16).to_le_bytes());
+        buf[4..(4 + len)].copy_from_slice(data);
+        buf[4 + len] = END_OF_MESSAGE;
+
+        self.port.write_all(&buf[..(len + 5)])?;
+        Ok(())
+    }
+
+    /// Receives the next message from the widget.
+    ///
+    /// Returns the label of the message and the number of data bytes stored
+    /// in `buf`; excess data is discarded. Fails with `Error::Timeout` if the
+    /// widget does not send anything for 500 ms.
+    pub fn recv_message(&mut self, buf: &mut [u8]) -> Result<(u8, usize)> {
+        self.read_message(buf).map_err(Error::from_read)
+    }
+
+    fn read_message(&mut self, buf: &mut [u8]) -> io::Result<(u8, usize)> {
+        loop {
+            // skip everything up to the next start delimiter
+            while self.read_byte()? != START_OF_MESSAGE {}
+
+            let mut header = [0; 3];
+            self.port.read_exact(&mut header)?;
+            let label = header[0];
+            let len = usize::from(u16::from_le_bytes([header[1], header[2]]));
+
+            if len > MAX_MESSAGE_LEN {
+                continue;
+            }
+
+            let mut data = [0; MAX_MESSAGE_LEN];
+            self.port.read_exact(&mut data[..len])?;
+
+            if self.read_byte()? != END_OF_MESSAGE {
+                continue;
+            }
+
+            let count = cmp::min(len, buf.len());
+            buf[..count].copy_from_slice(&data[..count]);
+            return Ok((label, count));
+        }
+    }
+
+    /// Sends a request and waits for the reply with the same label.
+    fn request(&mut self, label: u8, data: &[u8], reply: &mut [u8]) -> Result<usize> {
+        self.send_message(label, data)?;
+
+        loop {
+            let (rlabel, len) = self.recv_message(reply)?;
+            if rlabel == label {
+                return Ok(len);
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut b = [0];
+        self.port.read_exact(&mut b)?;
+        Ok(b[0])
+    }
+
+    /// Reads the widget parameters.
+    pub fn widget_parameters(&mut self) -> Result<WidgetParameters> {
+        let mut reply = [0; MAX_MESSAGE_LEN];
+        let len = self.request(LABEL_GET_PARAMETERS, &[0, 0], &mut reply)?;
+
+        if len < 5 {
+            return Err(Error::InvalidResponse("short widget parameters reply"));
+        }
+
+        Ok(WidgetParameters {
+            firmware_version: u16::from_le_bytes([reply[0], reply[1]]),
+            break_time: reply[2],
+            mab_time: reply[3],
+            refresh_rate: reply[4],
+        })
+    }
+
+    /// Writes the widget parameters.
+    ///
+    /// The firmware version is ignored. Parameters are not persisted by the
+    /// widget across power cycles.
+    pub fn set_widget_parameters(&mut self, params: &WidgetParameters) -> Result<()> {
+        params.validate()?;
+
+        self.send_message(
+            LABEL_SET_PARAMETERS,
+            &[0, 0, params.break_time, params.mab_time, params.refresh_rate],
+        )
+    }
+
+    /// Reads the widget's serial number.
+    pub fn serial_number(&mut self) -> Result<u32> {
+        let mut reply = [0; MAX_MESSAGE_LEN];
+        let len = self.request(LABEL_GET_SERIAL, &[], &mut reply)?;
+
+        if len < 4 {
+            return Err(Error::InvalidResponse("short serial number reply"));
+        }
+
+        // the serial number is BCD encoded
+        let mut serial = 0;
+        for &b in reply[..4].iter().rev() {
+            serial = serial * 100 + u32::from(b >> 4) * 10 + u32::from(b & 0x0f);
+        }
+
+        Ok(serial)
+    }
+
+    /// Reads the ESTA ID and name of the widget's manufacturer.
+    ///
+    /// This is an extension of the protocol not supported by all widgets,
+    /// those that do not reply fail with `Error::Timeout`.
+    pub fn manufacturer(&mut self) -> Result<(u16, String)> {
+        self.identification(LABEL_GET_MANUFACTURER)
+    }
+
+    /// Reads the manufacturer-specific device ID and the name of the widget.
+    ///
+    /// See `manufacturer` for compatibility.
+    pub fn device(&mut self) -> Result<(u16, String)> {
+        self.identification(LABEL_GET_DEVICE)
+    }
+
+    fn identification(&mut self, label: u8) -> Result<(u16, String)> {
+        let mut reply = [0; MAX_MESSAGE_LEN];
+        let len = self.request(label, &[], &mut reply)?;
+
+        if len < 2 {
+            return Err(Error::InvalidResponse("short identification reply"));
+        }
+
+        let name = &reply[2..len];
+        let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+
+        Ok((
+            u16::from_le_bytes([reply[0], reply[1]]),
+            String::from_utf8_lossy(name).into_owned(),
+        ))
+    }
+
+    /// Returns a handle for each of the given ports.
+    ///
+    /// Handles share the widget and can be sent to different threads. The
+    /// widget must have the ports enabled already.
+    pub fn into_ports(self, ports: &[PortLabels]) -> Vec<EnttecProPort> {
+        let widget = Arc::new(Mutex::new(self));
+
+        ports
+            .iter()
+            .map(|&labels| EnttecProPort {
+                widget: widget.clone(),
+                labels,
+            })
+            .collect()
+    }
+
+    /// Unlocks the second port of a DMX USB Pro Mk2 and returns handles for
+    /// both ports.
+    ///
+    /// Both ports are enabled for DMX output. Handles share the widget and
+    /// can be sent to different threads.
+    pub fn into_mk2_ports(mut self, config: &Mk2Config) -> Result<(EnttecProPort, EnttecProPort)> {
+        self.send_message(LABEL_SET_API_KEY, &config.api_key.to_le_bytes())?;
+        self.send_message(config.set_port_assignment, &[1, 1])?;
+
+        let mut ports = self.into_ports(&[PortLabels::PORT_1, config.port_2]).into_iter();
+        Ok((ports.next().unwrap(), ports.next().unwrap()))
+    }
+
+    /// Sends a packet through the port with the given labels.
+    fn send_packet_to(&mut self, labels: &PortLabels, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Err(Error::EmptyPacket);
+        }
+
+        if data.len() > 513 {
+            return Err(Error::PacketTooLong(data.len()));
+        }
+
+        if data[0] == SC_RDM {
+            let discovery = RdmRequest::decode(data).is_some_and(|r| {
+                r.command_class == CommandClass::Discovery
+                    && r.parameter_id == PID_DISC_UNIQUE_BRANCH
+            });
+            let label = if discovery { labels.send_rdm_discovery } else { labels.send_rdm };
+
+            return self.send_message(label, data);
+        }
+
+        if data.len() > MIN_CHANNELS {
+            return self.send_message(labels.send_dmx, data);
+        }
+
+        // pad short packets with zeroed channels
+        let mut padded = [0; MIN_CHANNELS + 1];
+        padded[..data.len()].copy_from_slice(data);
+        self.send_message(labels.send_dmx, &padded)
+    }
+
+    /// Receives data through the port with the given labels.
+    ///
+    /// Messages for other ports arriving in the meantime are dropped.
+    fn recv_from(
+        &mut self,
+        labels: &PortLabels,
+        buf: &mut [u8],
+        timeout: time::Duration,
+    ) -> Result<usize> {
+        let deadline = time::Instant::now() + timeout;
+        let mut message = [0; MAX_MESSAGE_LEN];
+
+        let result = loop {
+            let remaining = deadline.saturating_duration_since(time::Instant::now());
+            if remaining.is_zero() {
+                break Ok(0);
+            }
+            self.port.set_read_timeout(remaining)?;
+
+            match self.recv_message(&mut message) {
+                // the first byte holds error flags of the receiver
+                Ok((label, len)) if label == labels.received_dmx && len > 0 => {
+                    let count = cmp::min(len - 1, buf.len());
+                    buf[..count].copy_from_slice(&message[1..(count + 1)]);
+                    break Ok(count);
+                }
+                Ok(_) => (),
+                Err(Error::Timeout) => break Ok(0),
+                Err(e) => break Err(e),
+            }
+        };
+
+        self.port.set_read_timeout(REPLY_TIMEOUT)?;
+        result
+    }
+}
+
+impl DmxTransmitter for EnttecPro {
+    type Error = Error;
+
+    #[inline]
+    fn send_break(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn send_raw_data(&mut self, _data: &[u8]) -> Result<()> {
+        Err(Error::Unsupported("the DMX USB Pro can only transmit complete packets"))
+    }
+
+    #[inline]
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<()> {
+        self.send_packet_to(&PortLabels::PORT_1, data)
+    }
+}
+
+impl DmxTransceiver for EnttecPro {
+    #[inline]
+    fn discard_input(&mut self) -> Result<()> {
+        self.port.discard_input_buffer()?;
+        Ok(())
+    }
+
+    #[inline]
+    fn recv_raw_data(&mut self, buf: &mut [u8], timeout: time::Duration) -> Result<usize> {
+        self.recv_from(&PortLabels::PORT_1, buf, timeout)
+    }
+}
+
+/// A DMX port of a widget with several ports, such as the DMX USB Pro Mk2.
+///
+/// Behaves like an `EnttecPro`, sending and receiving through the labels of
+/// its port. See `EnttecPro::into_mk2_ports` and `EnttecPro::into_ports`.
+#[derive(Clone, Debug)]
+pub struct EnttecProPort {
+    widget: Arc<Mutex<EnttecPro>>,
+    labels: PortLabels,
+}
+
+impl EnttecProPort {
+    /// Returns the labels of the port.
+    #[inline]
+    pub fn labels(&self) -> &PortLabels {
+        &self.labels
+    }
+
+    /// Returns the shared widget.
+    #[inline]
+    pub fn widget(&self) -> MutexGuard<'_, EnttecPro> {
+        // sending never leaves the widget in an inconsistent state
+        self.widget.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl DmxTransmitter for EnttecProPort {
+    type Error = Error;
+
+    #[inline]
+    fn send_break(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn send_raw_data(&mut self, _data: &[u8]) -> Result<()> {
+        Err(Error::Unsupported("the DMX USB Pro can only transmit complete packets"))
+    }
+
+    #[inline]
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<()> {
+        let labels = self.labels;
+        self.widget().send_packet_to(&labels, data)
+    }
+}
+
+impl DmxTransceiver for EnttecProPort {
+    #[inline]
+    fn discard_input(&mut self) -> Result<()> {
+        self.widget().discard_input()
+    }
+
+    #[inline]
+    fn recv_raw_data(&mut self, buf: &mut [u8], timeout: time::Duration) -> Result<usize> {
+        let labels = self.labels;
+        self.widget().recv_from(&labels, buf, timeout)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    use crate::rdm::{Uid, PID_DEVICE_INFO};
+
+    // a widget on one end of a pseudo terminal, the host on the other
+    fn widget() -> (EnttecPro, serial2::SerialPort) {
+        let (mut widget, mut host) = serial2::SerialPort::pair().unwrap();
+        let mut settings = host.get_configuration().unwrap();
+        settings.set_raw();
+        host.set_configuration(&settings).unwrap();
+        widget.set_read_timeout(REPLY_TIMEOUT).unwrap();
+
+        (EnttecPro::from_serial_port(host).unwrap(), widget)
+    }
+
+    fn read(widget: &mut serial2::SerialPort, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        widget.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn message_framing() {
+        let (mut pro, mut widget) = widget();
+        pro.send_message(LABEL_GET_SERIAL, &[]).unwrap();
+        pro.send_message(0x90, &[1, 2, 3]).unwrap();
+
+        // start delimiter, label, length low byte first, data, end delimiter
+        assert_eq!(read(&mut widget, 5), [0x7e, 0x0a, 0x00, 0x00, 0xe7]);
+        assert_eq!(read(&mut widget, 8), [0x7e, 0x90, 0x03, 0x00, 1, 2, 3, 0xe7]);
+
+        let err = pro.send_message(0x90, &[0; MAX_MESSAGE_LEN + 1]).unwrap_err();
+        assert!(matches!(err, Error::PacketTooLong(601)));
+    }
+
+    #[test]
+    fn dmx_packets_are_padded_to_24_channels() {
+        let (mut pro, mut widget) = widget();
+        pro.send_raw_dmx_packet(&[0x00, 0xff, 0x80]).unwrap();
+
+        let message = read(&mut widget, 30);
+        assert_eq!(message[..7], [0x7e, 0x06, 0x19, 0x00, 0x00, 0xff, 0x80]);
+        assert!(message[7..29].iter().all(|&v| v == 0));
+        assert_eq!(message[29], 0xe7);
+
+        pro.send_raw_dmx_packet(&[0x00; 513]).unwrap();
+        let message = read(&mut widget, 518);
+        assert_eq!(message[..5], [0x7e, 0x06, 0x01, 0x02, 0x00]);
+        assert_eq!(message[517], 0xe7);
+
+        let err = pro.send_raw_dmx_packet(&[0x00; 514]).unwrap_err();
+        assert!(matches!(err, Error::PacketTooLong(514)));
+        assert!(matches!(pro.send_raw_dmx_packet(&[]), Err(Error::EmptyPacket)));
+    }
+
+    #[test]
+    fn rdm_requests_use_their_own_labels() {
+        let (mut pro, mut widget) = widget();
+        let mut request = RdmRequest {
+            destination: Uid::BROADCAST,
+            source: Uid::new(0x7a70, 1),
+            transaction: 0,
+            port_id: 1,
+            sub_device: 0,
+            command_class: CommandClass::Get,
+            parameter_id: PID_DEVICE_INFO,
+            data: &[],
+        };
+
+        let mut packet = [0; 64];
+        let len = request.encode(&mut packet);
+        pro.send_raw_dmx_packet(&packet[..len]).unwrap();
+        let message = read(&mut widget, len + 5);
+        assert_eq!(message[..5], [0x7e, 0x07, len as u8, 0x00, SC_RDM]);
+        assert_eq!(message[4..(len + 4)], packet[..len]);
+
+        request.command_class = CommandClass::Discovery;
+        request.parameter_id = PID_DISC_UNIQUE_BRANCH;
+        request.data = &[0; 12];
+        let len = request.encode(&mut packet);
+        pro.send_raw_dmx_packet(&packet[..len]).unwrap();
+        assert_eq!(read(&mut widget, len + 5)[..2], [0x7e, 0x0b]);
+    }
+
+    #[test]
+    fn received_messages_are_resynchronized() {
+        let (mut pro, widget) = widget();
+
+        // noise, a message with a bad end delimiter, then a valid one
+        widget.write_all(&[0x00, 0x55]).unwrap();
+        widget.write_all(&[0x7e, 0x05, 0x01, 0x00, 0xaa, 0x00]).unwrap();
+        widget.write_all(&[0x7e, 0x05, 0x04, 0x00, 0x00, 0x00, 0xff, 0x80, 0xe7]).unwrap();
+
+        let mut buf = [0; 16];
+        assert_eq!(pro.recv_message(&mut buf).unwrap(), (LABEL_RECEIVED_DMX, 4));
+        assert_eq!(buf[..4], [0x00, 0x00, 0xff, 0x80]);
+    }
+
+    #[test]
+    fn received_dmx_skips_the_status_byte() {
+        let (mut pro, widget) = widget();
+
+        // a reply to another request is dropped
+        widget.write_all(&[0x7e, 0x0a, 0x04, 0x00, 1, 2, 3, 4, 0xe7]).unwrap();
+        widget.write_all(&[0x7e, 0x05, 0x04, 0x00, 0x00, 0x00, 0xff, 0x80, 0xe7]).unwrap();
+
+        let mut buf = [0; 16];
+        let len = pro.recv_raw_data(&mut buf, REPLY_TIMEOUT).unwrap();
+        assert_eq!(buf[..len], [0x00, 0xff, 0x80]);
+
+        // nothing received
+        let timeout = time::Duration::from_millis(50);
+        assert_eq!(pro.recv_raw_data(&mut buf, timeout).unwrap(), 0);
+    }
+
+    #[test]
+    fn widget_parameters_request_and_reply() {
+        let (mut pro, mut widget) = widget();
+        // firmware version low byte first, break, mark after break, rate
+        widget.write_all(&[0x7e, 0x03, 0x05, 0x00, 0x44, 0x01, 0x11, 0x02, 0x28, 0xe7]).unwrap();
+
+        let params = pro.widget_parameters().unwrap();
+        assert_eq!(read(&mut widget, 7), [0x7e, 0x03, 0x02, 0x00, 0x00, 0x00, 0xe7]);
+        assert_eq!(
+            params,
+            WidgetParameters {
+                firmware_version: 0x0144,
+                break_time: 17,
+                mab_time: 2,
+                refresh_rate: 40,
+            }
+        );
+
+        pro.set_widget_parameters(&params).unwrap();
+        assert_eq!(read(&mut widget, 10), [0x7e, 0x04, 0x05, 0x00, 0, 0, 17, 2, 40, 0xe7]);
+
+        let invalid = WidgetParameters { refresh_rate: 41, ..params };
+        assert!(pro.set_widget_parameters(&invalid).is_err());
+    }
+
+    #[test]
+    fn serial_numbers_are_bcd() {
+        let (mut pro, widget) = widget();
+        widget.write_all(&[0x7e, 0x0a, 0x04, 0x00, 0x78, 0x56, 0x34, 0x12, 0xe7]).unwrap();
+
+        assert_eq!(pro.serial_number().unwrap(), 12_345_678);
+    }
+
+    #[test]
+    fn timing_is_rounded_up_to_widget_units() {
+        let mut params = WidgetParameters {
+            firmware_version: 0,
+            break_time: 0,
+            mab_time: 0,
+            refresh_rate: 0,
+        };
+
+        params.set_timing_us(176, 12);
+        assert_eq!((params.break_time, params.mab_time), (17, 2));
+        assert!(params.break_duration() >= time::Duration::from_micros(176));
+
+        params.set_timing_us(50, 0);
+        assert_eq!((params.break_time, params.mab_time), (9, 1));
+        params.set_timing_us(10_000, 10_000);
+        assert_eq!((params.break_time, params.mab_time), (127, 127));
+    }
+}
@@ -0,0 +1,136 @@
+//! Errors.
+
+use std::{error, fmt, io, result};
+
+use crate::address::AddressError;
+use crate::timing::TimingError;
+
+/// Result type of most operations.
+pub type Result<T> = result::Result<T, Error>;
+
+/// Errors that can occur while sending or receiving DMX.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An error occurred on the underlying device or socket.
+    Io(io::Error),
+    /// The port does not support a baud rate required for DMX.
+    UnsupportedBaud(u32),
+    /// A packet is longer than allowed; holds the length.
+    PacketTooLong(usize),
+    /// A packet does not contain a start code.
+    EmptyPacket,
+    /// The transmitter does not support sending packets with this start code.
+    UnsupportedStartCode(u8),
+    /// Generating the break failed.
+    BreakFailed(io::Error),
+    /// A reply did not arrive in time.
+    Timeout,
+    /// Timing parameters are outside the limits of the standard.
+    InvalidTiming(TimingError),
+    /// A universe number is outside the range supported by the protocol.
+    InvalidUniverse(u16),
+    /// A parameter is out of range.
+    InvalidParameter(&'static str),
+    /// A device replied with malformed data.
+    InvalidResponse(&'static str),
+    /// An RDM request was rejected by a device; holds the reason code.
+    Nack(u16),
+    /// The operation is not supported by the transmitter.
+    Unsupported(&'static str),
+    /// The port is the mini UART of a Raspberry Pi, usually `/dev/ttyS0`,
+    /// whose baud rate follows the VPU core clock and drifts with it.
+    MiniUart,
+}
+
+impl Error {
+    /// Converts an error of a read, mapping timeouts to `Error::Timeout`.
+    pub(crate) fn from_read(e: io::Error) -> Error {
+        if e.kind() == io::ErrorKind::TimedOut {
+            return Error::Timeout;
+        }
+
+        Error::Io(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::UnsupportedBaud(rate) => write!(f, "baud rate of {} not supported", rate),
+            Error::PacketTooLong(len) => {
+                write!(f, "packet of {} bytes exceeds maximum of 513 bytes", len)
+            }
+            Error::EmptyPacket => f.write_str("packet is missing a start code"),
+            Error::UnsupportedStartCode(code) => write!(f, "start code {:#04x} not supported", code),
+            Error::BreakFailed(ref e) => write!(f, "could not send break: {}", e),
+            Error::Timeout => f.write_str("timed out waiting for reply"),
+            Error::InvalidTiming(ref e) => write!(f, "invalid timing: {}", e),
+            Error::InvalidUniverse(u) => write!(f, "universe {} out of range", u),
+            Error::InvalidParameter(msg) => write!(f, "invalid parameter: {}", msg),
+            Error::InvalidResponse(msg) => write!(f, "invalid response: {}", msg),
+            Error::Nack(reason) => write!(f, "request not acknowledged, reason {:#06x}", reason),
+            Error::Unsupported(msg) => write!(f, "not supported: {}", msg),
+            Error::MiniUart => f.write_str(
+                "port is the Raspberry Pi's mini UART, whose baud rate changes with the core \
+                 clock; connect the transceiver to the PL011 UART (/dev/ttyAMA0, e.g. after \
+                 `dtoverlay=disable-bt` in config.txt), or fix the core clock and use \
+                 `DmxPortBuilder::allow_mini_uart`",
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) | Error::BreakFailed(ref e) => Some(e),
+            Error::InvalidTiming(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    #[inline]
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<TimingError> for Error {
+    #[inline]
+    fn from(e: TimingError) -> Error {
+        Error::InvalidTiming(e)
+    }
+}
+
+impl From<AddressError> for Error {
+    #[inline]
+    fn from(_: AddressError) -> Error {
+        Error::InvalidParameter("channel out of range 1-512")
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        let kind = match e {
+            Error::Io(e) => return e,
+            Error::Timeout => io::ErrorKind::TimedOut,
+            Error::Unsupported(_)
+            | Error::UnsupportedBaud(_)
+            | Error::UnsupportedStartCode(_)
+            | Error::MiniUart => io::ErrorKind::Unsupported,
+            Error::PacketTooLong(_)
+            | Error::EmptyPacket
+            | Error::InvalidTiming(_)
+            | Error::InvalidUniverse(_)
+            | Error::InvalidParameter(_) => io::ErrorKind::InvalidInput,
+            Error::InvalidResponse(_) => io::ErrorKind::InvalidData,
+            Error::BreakFailed(_) | Error::Nack(_) => io::ErrorKind::Other,
+        };
+
+        io::Error::new(kind, e)
+    }
+}
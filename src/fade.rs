@@ -0,0 +1,92 @@
+//! Channel fades.
+
+use core::time;
+
+use crate::universe::lerp;
+
+/// Interpolation curve of a fade.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Easing {
+    /// Constant rate of change.
+    #[default]
+    Linear,
+    /// Starts slowly, then speeds up.
+    EaseIn,
+    /// Starts quickly, then slows down.
+    EaseOut,
+    /// Starts and ends slowly.
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps the linear progress `t` of a fade, from 0 to 1, onto the curve.
+    #[inline]
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// State of a fade on a single channel.
+///
+/// Durations are in microseconds; a zero duration marks an inactive fade.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct ChannelFade {
+    from: u8,
+    to: u8,
+    easing: Easing,
+    elapsed: u32,
+    duration: u32,
+}
+
+impl ChannelFade {
+    /// Create a fade, which is inactive if `duration` is zero.
+    #[inline]
+    pub(crate) fn new(from: u8, to: u8, duration: time::Duration, easing: Easing) -> ChannelFade {
+        ChannelFade {
+            from,
+            to,
+            easing,
+            elapsed: 0,
+            duration: micros(duration),
+        }
+    }
+
+    /// Returns whether the fade is still running.
+    #[inline]
+    pub(crate) fn is_active(&self) -> bool {
+        self.duration != 0
+    }
+
+    /// Advances the fade by `dt` microseconds and returns the channel value.
+    ///
+    /// The fade becomes inactive once it reaches its target.
+    #[inline]
+    pub(crate) fn advance(&mut self, dt: u32) -> u8 {
+        self.elapsed = self.elapsed.saturating_add(dt);
+
+        if self.elapsed >= self.duration {
+            self.duration = 0;
+            return self.to;
+        }
+
+        let t = self.elapsed as f32 / self.duration as f32;
+        lerp(self.from, self.to, self.easing.apply(t))
+    }
+}
+
+/// Converts a duration to microseconds, saturating at about 71 minutes.
+#[inline]
+pub(crate) fn micros(duration: time::Duration) -> u32 {
+    if duration.as_micros() > u128::from(u32::MAX) {
+        return u32::MAX;
+    }
+
+    duration.as_micros() as u32
+}
@@ -0,0 +1,241 @@
+//! C interface.
+//!
+//! Makes serial ports and refreshers available to C, and to other languages
+//! able to call C functions, such as C++ or Python through `ctypes`. The
+//! header `include/dmx.h` is generated from this module by
+//! [cbindgen](https://github.com/mozilla/cbindgen):
+//!
+//! ```text
+//! cbindgen --config cbindgen.toml --output include/dmx.h
+//! ```
+//!
+//! To link against the crate, build it as a library with the `ffi` feature,
+//! e.g. through `cargo rustc --release --features ffi --crate-type cdylib`.
+//!
+//! Functions returning `int` return 0 on success and -1 on failure,
+//! functions returning pointers return null on failure. `dmx_last_error`
+//! describes the last failure on the calling thread.
+//!
+//! ## Example
+//!
+//! ```c
+//! #include <stdio.h>
+//! #include "dmx.h"
+//!
+//! int main(void) {
+//!     DmxRefresher *refresher = dmx_refresher_start(dmx_open("/dev/ttyUSB0"), 40.0);
+//!     if (!refresher) {
+//!         fprintf(stderr, "%s\n", dmx_last_error());
+//!         return 1;
+//!     }
+//!
+//!     dmx_refresher_set_channel(refresher, 1, 255);
+//!     /* ... */
+//!     return dmx_refresher_stop(refresher);
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_float, c_int};
+use std::{io, panic, ptr, slice};
+
+use crate::address::DmxAddress;
+use crate::refresh::DmxRefresher;
+use crate::serial::{open_serial, DmxPort};
+use crate::{DmxTransmitter, Error, Result};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(e: &Error) {
+    // messages never contain NUL bytes
+    let message = CString::new(e.to_string()).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn status(result: Result<()>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            set_last_error(&e);
+            -1
+        }
+    }
+}
+
+fn null_pointer() -> Error {
+    Error::InvalidParameter("null pointer")
+}
+
+/// Returns a message describing the last failure on the calling thread.
+///
+/// Returns null if nothing failed yet. The message stays valid until the
+/// next failure on the same thread.
+#[no_mangle]
+pub extern "C" fn dmx_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Opens a serial port for sending DMX.
+///
+/// Returns null on failure.
+///
+/// # Safety
+///
+/// `path` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn dmx_open(path: *const c_char) -> *mut DmxPort {
+    if path.is_null() {
+        set_last_error(&null_pointer());
+        return ptr::null_mut();
+    }
+
+    let opened = CStr::from_ptr(path)
+        .to_str()
+        .map_err(|_| Error::InvalidParameter("path is not UTF-8"))
+        .and_then(open_serial);
+
+    match opened {
+        Ok(port) => Box::into_raw(Box::new(port)),
+        Err(e) => {
+            set_last_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Sends a packet with the default start code, followed by `len` channel
+/// values.
+///
+/// # Safety
+///
+/// `port` must be null or returned by `dmx_open`, and not be used by another
+/// thread at the same time. `data` must point to `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn dmx_send_packet(
+    port: *mut DmxPort,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    let port = match port.as_mut() {
+        Some(port) if !data.is_null() || len == 0 => port,
+        _ => return status(Err(null_pointer())),
+    };
+    let data = if len == 0 { &[] } else { slice::from_raw_parts(data, len) };
+
+    status(port.send_dmx_packet(data))
+}
+
+/// Closes a port. Does nothing if `port` is null.
+///
+/// # Safety
+///
+/// `port` must be null or returned by `dmx_open`, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn dmx_close(port: *mut DmxPort) {
+    if !port.is_null() {
+        drop(Box::from_raw(port));
+    }
+}
+
+/// Starts sending all 512 channels through `port` from a background thread,
+/// `fps` times per second.
+///
+/// Takes ownership of the port, even if starting fails. Channels start out
+/// at zero. Returns null on failure.
+///
+/// # Safety
+///
+/// `port` must be null or returned by `dmx_open`, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn dmx_refresher_start(
+    port: *mut DmxPort,
+    fps: c_float,
+) -> *mut DmxRefresher {
+    if port.is_null() {
+        set_last_error(&null_pointer());
+        return ptr::null_mut();
+    }
+    let port = Box::from_raw(port);
+
+    if fps.is_nan() || fps <= 0.0 {
+        set_last_error(&Error::InvalidParameter("frame rate must be positive"));
+        return ptr::null_mut();
+    }
+
+    Box::into_raw(Box::new(DmxRefresher::with_frame_rate(*port, fps)))
+}
+
+/// Sets channel `channel`, from 1 to 512, to `value`.
+///
+/// May be called from any thread.
+///
+/// # Safety
+///
+/// `refresher` must be null or returned by `dmx_refresher_start`, and not be
+/// stopped yet.
+#[no_mangle]
+pub unsafe extern "C" fn dmx_refresher_set_channel(
+    refresher: *const DmxRefresher,
+    channel: u16,
+    value: u8,
+) -> c_int {
+    let refresher = match refresher.as_ref() {
+        Some(refresher) => refresher,
+        None => return status(Err(null_pointer())),
+    };
+
+    let set = DmxAddress::try_from(channel).map(|n| refresher.set_channel(n, value));
+    status(set.map_err(Error::from))
+}
+
+/// Sets `len` consecutive channels, starting at channel `start`.
+///
+/// Values that would end up beyond channel 512 are ignored. May be called
+/// from any thread.
+///
+/// # Safety
+///
+/// `refresher` must be null or returned by `dmx_refresher_start`, and not be
+/// stopped yet. `values` must point to `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn dmx_refresher_set_channels(
+    refresher: *const DmxRefresher,
+    start: u16,
+    values: *const u8,
+    len: usize,
+) -> c_int {
+    let refresher = match refresher.as_ref() {
+        Some(refresher) if !values.is_null() || len == 0 => refresher,
+        _ => return status(Err(null_pointer())),
+    };
+    let values = if len == 0 { &[] } else { slice::from_raw_parts(values, len) };
+
+    let set = DmxAddress::try_from(start).map(|start| refresher.set_channels(start, values));
+    status(set.map_err(Error::from))
+}
+
+/// Stops refreshing and closes the port.
+///
+/// Fails if sending failed in the meantime, which also stopped refreshing.
+///
+/// # Safety
+///
+/// `refresher` must be null or returned by `dmx_refresher_start`, and not be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn dmx_refresher_stop(refresher: *mut DmxRefresher) -> c_int {
+    if refresher.is_null() {
+        return status(Err(null_pointer()));
+    }
+    let refresher = Box::from_raw(refresher);
+
+    // panics must not unwind into C
+    match panic::catch_unwind(panic::AssertUnwindSafe(|| refresher.stop())) {
+        Ok(result) => status(result),
+        Err(_) => status(Err(Error::Io(io::Error::other("refresh thread panicked")))),
+    }
+}
@@ -0,0 +1,702 @@
+//! Fixture profiles.
+//!
+//! A `FixtureProfile` describes a type of fixture: the channels it occupies
+//! in each of its modes, or *personalities*, and what each of them controls.
+//! A `Fixture` is a single fixture of that type, patched at a start address,
+//! which writes its attributes into a universe without the application having
+//! to know the channel layout. A `FixtureGroup` sets attributes of many
+//! fixtures at once, either to the same value or fanned out across the group.
+//!
+//! ## Example
+//!
+//! ```
+//! use dmx::{Color, DmxAddress, DmxUniverse};
+//! use dmx::fixture::{Attribute, Fixture, FixtureMode, FixtureProfile};
+//!
+//! let mut mode = FixtureMode::new("5ch");
+//! mode.push(Attribute::Intensity, 0);
+//! mode.push(Attribute::Red, 0);
+//! mode.push(Attribute::Green, 0);
+//! mode.push(Attribute::Blue, 0);
+//! mode.push(Attribute::Strobe, 0);
+//!
+//! let mut profile = FixtureProfile::new("Generic", "RGB Par");
+//! profile.add_mode(mode);
+//!
+//! let par = Fixture::new(&profile, "5ch", DmxAddress::new(11).unwrap()).unwrap();
+//!
+//! let mut universe = DmxUniverse::new();
+//! par.set_intensity(&mut universe, 0xff);
+//! par.set_color(&mut universe, Color::RED);
+//!
+//! assert_eq!(universe.channels()[10..15], [0xff, 0xff, 0x00, 0x00, 0x00]);
+//! ```
+
+use crate::address::DmxAddress;
+use crate::color::Color;
+use crate::curve::DimmerCurve;
+use crate::limits::ChannelLimits;
+use crate::packet::MAX_CHANNELS;
+use crate::universe::{lerp, DmxUniverse};
+use crate::{Error, Result};
+
+/// What a channel of a fixture controls.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Attribute {
+    /// Master intensity.
+    Intensity,
+    /// Fine byte of a 16-bit intensity.
+    IntensityFine,
+    /// Red emitter.
+    Red,
+    /// Green emitter.
+    Green,
+    /// Blue emitter.
+    Blue,
+    /// White emitter.
+    White,
+    /// Amber emitter.
+    Amber,
+    /// Ultraviolet emitter.
+    Uv,
+    /// Cyan filter.
+    Cyan,
+    /// Magenta filter.
+    Magenta,
+    /// Yellow filter.
+    Yellow,
+    /// Color temperature correction.
+    ColorTemperature,
+    /// Color wheel.
+    ColorWheel,
+    /// Pan.
+    Pan,
+    /// Fine byte of a 16-bit pan.
+    PanFine,
+    /// Tilt.
+    Tilt,
+    /// Fine byte of a 16-bit tilt.
+    TiltFine,
+    /// Speed of pan and tilt movements.
+    PanTiltSpeed,
+    /// Gobo wheel.
+    Gobo,
+    /// Gobo rotation.
+    GoboRotation,
+    /// Prism.
+    Prism,
+    /// Focus.
+    Focus,
+    /// Zoom.
+    Zoom,
+    /// Iris.
+    Iris,
+    /// Frost.
+    Frost,
+    /// Shutter and strobe.
+    Strobe,
+    /// Built-in programs or effects.
+    Effect,
+    /// Speed of built-in programs or effects.
+    EffectSpeed,
+    /// Reset, lamp and other control functions.
+    Control,
+    /// Any other function, by name.
+    Other(String),
+}
+
+/// A channel of a fixture mode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelDef {
+    /// Function of the channel.
+    pub attribute: Attribute,
+    /// Value the channel is set to by `Fixture::write_defaults`.
+    pub default: u8,
+}
+
+/// A mode, or personality, of a fixture: its channels in order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FixtureMode {
+    /// Name of the mode, such as `"8ch"` or `"Extended"`.
+    pub name: String,
+    /// Channels, starting at the fixture's start address.
+    pub channels: Vec<ChannelDef>,
+}
+
+impl FixtureMode {
+    /// Create a mode without any channels.
+    #[inline]
+    pub fn new(name: &str) -> FixtureMode {
+        FixtureMode {
+            name: name.to_owned(),
+            channels: Vec::new(),
+        }
+    }
+
+    /// Appends a channel.
+    #[inline]
+    pub fn push(&mut self, attribute: Attribute, default: u8) {
+        self.channels.push(ChannelDef { attribute, default });
+    }
+
+    /// Returns the number of channels occupied.
+    #[inline]
+    pub fn footprint(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Returns the offset of the first channel with `attribute`, from 0.
+    #[inline]
+    pub fn offset(&self, attribute: &Attribute) -> Option<usize> {
+        self.channels.iter().position(|c| c.attribute == *attribute)
+    }
+}
+
+/// A type of fixture.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FixtureProfile {
+    /// Manufacturer of the fixture.
+    pub manufacturer: String,
+    /// Model name.
+    pub model: String,
+    /// Modes the fixture can be set to.
+    pub modes: Vec<FixtureMode>,
+}
+
+impl FixtureProfile {
+    /// Create a profile without any modes.
+    #[inline]
+    pub fn new(manufacturer: &str, model: &str) -> FixtureProfile {
+        FixtureProfile {
+            manufacturer: manufacturer.to_owned(),
+            model: model.to_owned(),
+            modes: Vec::new(),
+        }
+    }
+
+    /// Adds a mode.
+    #[inline]
+    pub fn add_mode(&mut self, mode: FixtureMode) {
+        self.modes.push(mode);
+    }
+
+    /// Returns the mode named `name`.
+    #[inline]
+    pub fn mode(&self, name: &str) -> Option<&FixtureMode> {
+        self.modes.iter().find(|m| m.name == name)
+    }
+}
+
+/// A fixture patched at a start address.
+///
+/// Setters write the channels controlling an attribute and return whether
+/// the fixture has them; attributes a fixture lacks are ignored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "FixtureData")
+)]
+pub struct Fixture {
+    mode: FixtureMode,
+    address: DmxAddress,
+    curve: DimmerCurve,
+    // offset of each limited channel, with its lowest and highest value
+    limits: Vec<(usize, u8, u8)>,
+}
+
+/// Fields of a deserialized fixture, checked to fit into the universe.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct FixtureData {
+    mode: FixtureMode,
+    address: DmxAddress,
+    curve: DimmerCurve,
+    #[serde(default)]
+    limits: Vec<(usize, u8, u8)>,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<FixtureData> for Fixture {
+    type Error = Error;
+
+    fn try_from(data: FixtureData) -> Result<Fixture> {
+        let mut fixture = Fixture::with_mode(data.mode, data.address)?;
+        fixture.curve = data.curve;
+
+        if data.limits.iter().any(|&(offset, _, _)| offset >= fixture.footprint()) {
+            return Err(Error::InvalidParameter("limited channel outside of the fixture"));
+        }
+        fixture.limits = data.limits;
+
+        Ok(fixture)
+    }
+}
+
+impl Fixture {
+    /// Create a fixture of a profile, set to the mode named `mode`.
+    ///
+    /// Fails with `Error::InvalidParameter` if the profile has no such mode
+    /// or the fixture does not fit into the universe at `address`.
+    pub fn new(profile: &FixtureProfile, mode: &str, address: DmxAddress) -> Result<Fixture> {
+        let mode = profile
+            .mode(mode)
+            .ok_or(Error::InvalidParameter("unknown fixture mode"))?;
+
+        Fixture::with_mode(mode.clone(), address)
+    }
+
+    /// Create a fixture from a mode.
+    ///
+    /// Fails with `Error::InvalidParameter` if the fixture does not fit into
+    /// the universe at `address`.
+    pub fn with_mode(mode: FixtureMode, address: DmxAddress) -> Result<Fixture> {
+        if address.index() + mode.footprint() > MAX_CHANNELS {
+            return Err(Error::InvalidParameter("fixture does not fit into the universe"));
+        }
+
+        Ok(Fixture {
+            mode,
+            address,
+            curve: DimmerCurve::LINEAR,
+            limits: Vec::new(),
+        })
+    }
+
+    /// Returns the mode of the fixture.
+    #[inline]
+    pub fn mode(&self) -> &FixtureMode {
+        &self.mode
+    }
+
+    /// Returns the start address.
+    #[inline]
+    pub fn address(&self) -> DmxAddress {
+        self.address
+    }
+
+    /// Moves the fixture to another start address.
+    ///
+    /// Fails with `Error::InvalidParameter` if the fixture does not fit into
+    /// the universe at `address`.
+    pub fn set_address(&mut self, address: DmxAddress) -> Result<()> {
+        if address.index() + self.footprint() > MAX_CHANNELS {
+            return Err(Error::InvalidParameter("fixture does not fit into the universe"));
+        }
+
+        self.address = address;
+        Ok(())
+    }
+
+    /// Returns the number of channels occupied.
+    #[inline]
+    pub fn footprint(&self) -> usize {
+        self.mode.footprint()
+    }
+
+    /// Sets the curve intensities are mapped onto by `set_intensity`.
+    #[inline]
+    pub fn set_curve(&mut self, curve: DimmerCurve) {
+        self.curve = curve;
+    }
+
+    /// Limits the channel controlling `attribute` to values from `min` to
+    /// `max` when output.
+    ///
+    /// Limits are not applied by the setters of the fixture, but by the
+    /// `ChannelLimits` of its patch; see `Patch::limits`. Returns whether the
+    /// fixture has the attribute.
+    pub fn set_limit(&mut self, attribute: &Attribute, min: u8, max: u8) -> bool {
+        let offset = match self.mode.offset(attribute) {
+            Some(offset) => offset,
+            None => return false,
+        };
+        let (min, max) = (min.min(max), min.max(max));
+
+        self.limits.retain(|&(n, _, _)| n != offset);
+        self.limits.push((offset, min, max));
+        true
+    }
+
+    /// Removes the limits of the channel controlling `attribute`.
+    pub fn clear_limit(&mut self, attribute: &Attribute) {
+        if let Some(offset) = self.mode.offset(attribute) {
+            self.limits.retain(|&(n, _, _)| n != offset);
+        }
+    }
+
+    /// Returns the lowest and highest value of the channel controlling
+    /// `attribute`, if limited.
+    pub fn limit(&self, attribute: &Attribute) -> Option<(u8, u8)> {
+        let offset = self.mode.offset(attribute)?;
+        let &(_, min, max) = self.limits.iter().find(|&&(n, _, _)| n == offset)?;
+
+        Some((min, max))
+    }
+
+    /// Writes the limits of the fixture into `limits`, at its address.
+    pub fn write_limits(&self, limits: &mut ChannelLimits) {
+        for &(offset, min, max) in &self.limits {
+            if let Some(n) = DmxAddress::from_index(self.address.index() + offset) {
+                limits.set(n, min, max);
+            }
+        }
+    }
+
+    /// Returns the channel controlling `attribute`.
+    #[inline]
+    pub fn channel(&self, attribute: &Attribute) -> Option<DmxAddress> {
+        DmxAddress::from_index(self.address.index() + self.mode.offset(attribute)?)
+    }
+
+    /// Sets the channel controlling `attribute` to `value`.
+    #[inline]
+    pub fn set(&self, universe: &mut DmxUniverse, attribute: &Attribute, value: u8) -> bool {
+        match self.channel(attribute) {
+            Some(n) => {
+                universe.set(n, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets a 16-bit attribute, writing the low byte to its fine channel if
+    /// the fixture has one.
+    pub fn set_16(
+        &self,
+        universe: &mut DmxUniverse,
+        coarse: &Attribute,
+        fine: &Attribute,
+        value: u16,
+    ) -> bool {
+        let [high, low] = value.to_be_bytes();
+        self.set(universe, fine, low);
+        self.set(universe, coarse, high)
+    }
+
+    /// Sets the intensity, mapped onto the fixture's dimmer curve.
+    ///
+    /// Writes the fine channel if there is one, with the curve only applying
+    /// to the coarse channel.
+    #[inline]
+    pub fn set_intensity(&self, universe: &mut DmxUniverse, value: u8) -> bool {
+        self.set(universe, &Attribute::IntensityFine, 0);
+        self.set(universe, &Attribute::Intensity, self.curve.apply(value))
+    }
+
+    /// Sets the color.
+    ///
+    /// Fixtures with RGB emitters receive the color as is, with a white
+    /// component extracted if they have a white emitter as well; fixtures
+    /// with CMY filters receive it converted to CMY.
+    pub fn set_color(&self, universe: &mut DmxUniverse, color: Color) -> bool {
+        if self.channel(&Attribute::Red).is_some() {
+            let [red, green, blue, white] = match self.channel(&Attribute::White) {
+                Some(_) => color.to_rgbw(),
+                None => [color.red, color.green, color.blue, 0],
+            };
+
+            self.set(universe, &Attribute::Red, red);
+            self.set(universe, &Attribute::Green, green);
+            self.set(universe, &Attribute::Blue, blue);
+            self.set(universe, &Attribute::White, white);
+            true
+        } else if self.channel(&Attribute::Cyan).is_some() {
+            let [cyan, magenta, yellow] = color.to_cmy();
+
+            self.set(universe, &Attribute::Cyan, cyan);
+            self.set(universe, &Attribute::Magenta, magenta);
+            self.set(universe, &Attribute::Yellow, yellow);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Sets pan and tilt, as 16-bit values.
+    ///
+    /// Fixtures without fine channels receive the high bytes only.
+    pub fn set_pan_tilt(&self, universe: &mut DmxUniverse, pan: u16, tilt: u16) -> bool {
+        let pan = self.set_16(universe, &Attribute::Pan, &Attribute::PanFine, pan);
+        let tilt = self.set_16(universe, &Attribute::Tilt, &Attribute::TiltFine, tilt);
+
+        pan || tilt
+    }
+
+    /// Selects a gobo.
+    #[inline]
+    pub fn set_gobo(&self, universe: &mut DmxUniverse, value: u8) -> bool {
+        self.set(universe, &Attribute::Gobo, value)
+    }
+
+    /// Sets the shutter, or strobe, channel.
+    #[inline]
+    pub fn set_strobe(&self, universe: &mut DmxUniverse, value: u8) -> bool {
+        self.set(universe, &Attribute::Strobe, value)
+    }
+
+    /// Sets all channels of the fixture to their defaults.
+    pub fn write_defaults(&self, universe: &mut DmxUniverse) {
+        let defaults: Vec<u8> = self.mode.channels.iter().map(|c| c.default).collect();
+        universe.set_range(self.address, &defaults);
+    }
+}
+
+/// How values are spread across the fixtures of a `FixtureGroup`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Fan {
+    /// From the first value at the first fixture to the second value at the
+    /// last one.
+    #[default]
+    Linear,
+    /// From the first value at the last fixture to the second value at the
+    /// first one.
+    Reverse,
+    /// From the first value at the center of the group to the second value
+    /// at both ends, symmetrically.
+    Center,
+}
+
+impl Fan {
+    /// Returns the position of fixture `index` of `count` between the first
+    /// value, at zero, and the second value, at one.
+    pub fn position(self, index: usize, count: usize) -> f32 {
+        let x = if count > 1 {
+            index as f32 / (count - 1) as f32
+        } else {
+            0.0
+        };
+
+        match self {
+            Fan::Linear => x,
+            Fan::Reverse => 1.0 - x,
+            Fan::Center => (2.0 * x - 1.0).abs(),
+        }
+    }
+}
+
+/// Interpolates between 16-bit values, `t` from 0 to 1.
+#[inline]
+fn lerp_16(from: u16, to: u16, t: f32) -> u16 {
+    let t = t.clamp(0.0, 1.0);
+    (f32::from(from) + (f32::from(to) - f32::from(from)) * t).round() as u16
+}
+
+/// A group of fixtures controlled together.
+///
+/// Fixtures keep the order they were added in, which is the order values
+/// are fanned out in, e.g. from stage left to stage right. The fixtures need
+/// not be of the same type; each setter returns whether any fixture has the
+/// attribute set.
+///
+/// ```
+/// use dmx::{DmxAddress, DmxUniverse};
+/// use dmx::fixture::{Attribute, Fan, Fixture, FixtureGroup, FixtureMode};
+///
+/// let mut mode = FixtureMode::new("1ch");
+/// mode.push(Attribute::Intensity, 0);
+///
+/// let mut group = FixtureGroup::new();
+/// for n in 1..=5 {
+///     group.push(Fixture::with_mode(mode.clone(), DmxAddress::new(n).unwrap()).unwrap());
+/// }
+///
+/// let mut universe = DmxUniverse::new();
+/// group.set_intensity_fan(&mut universe, 0xff, 0x00, Fan::Center);
+///
+/// assert_eq!(universe.channels()[..5], [0x00, 0x80, 0xff, 0x80, 0x00]);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FixtureGroup {
+    fixtures: Vec<Fixture>,
+}
+
+impl FixtureGroup {
+    /// Create an empty group.
+    #[inline]
+    pub fn new() -> FixtureGroup {
+        FixtureGroup::default()
+    }
+
+    /// Adds a fixture at the end of the group.
+    #[inline]
+    pub fn push(&mut self, fixture: Fixture) {
+        self.fixtures.push(fixture);
+    }
+
+    /// Removes and returns the fixture at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    pub fn remove(&mut self, index: usize) -> Fixture {
+        self.fixtures.remove(index)
+    }
+
+    /// Returns the fixtures of the group.
+    #[inline]
+    pub fn fixtures(&self) -> &[Fixture] {
+        &self.fixtures
+    }
+
+    /// Returns the number of fixtures.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.fixtures.len()
+    }
+
+    /// Returns whether the group is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.fixtures.is_empty()
+    }
+
+    /// Calls `f` for every fixture with its position within the group, from
+    /// 0 to 1, as spread by `fan`.
+    ///
+    /// Building block for setting any attribute across the group; returns
+    /// whether `f` returned `true` for any fixture.
+    pub fn apply<F>(&self, universe: &mut DmxUniverse, fan: Fan, mut f: F) -> bool
+    where
+        F: FnMut(&Fixture, &mut DmxUniverse, f32) -> bool,
+    {
+        let count = self.fixtures.len();
+        let mut applied = false;
+
+        for (i, fixture) in self.fixtures.iter().enumerate() {
+            applied |= f(fixture, universe, fan.position(i, count));
+        }
+        applied
+    }
+
+    /// Sets `attribute` of all fixtures to `value`.
+    #[inline]
+    pub fn set(&self, universe: &mut DmxUniverse, attribute: &Attribute, value: u8) -> bool {
+        self.set_fan(universe, attribute, value, value, Fan::Linear)
+    }
+
+    /// Sets `attribute`, fanned out from `from` to `to`.
+    pub fn set_fan(
+        &self,
+        universe: &mut DmxUniverse,
+        attribute: &Attribute,
+        from: u8,
+        to: u8,
+        fan: Fan,
+    ) -> bool {
+        self.apply(universe, fan, |fixture, universe, t| {
+            fixture.set(universe, attribute, lerp(from, to, t))
+        })
+    }
+
+    /// Sets the intensity of all fixtures, see `Fixture::set_intensity`.
+    #[inline]
+    pub fn set_intensity(&self, universe: &mut DmxUniverse, value: u8) -> bool {
+        self.set_intensity_fan(universe, value, value, Fan::Linear)
+    }
+
+    /// Sets the intensity, fanned out from `from` to `to`, e.g. to stagger
+    /// the fixtures of the group.
+    pub fn set_intensity_fan(
+        &self,
+        universe: &mut DmxUniverse,
+        from: u8,
+        to: u8,
+        fan: Fan,
+    ) -> bool {
+        self.apply(universe, fan, |fixture, universe, t| {
+            fixture.set_intensity(universe, lerp(from, to, t))
+        })
+    }
+
+    /// Sets the color of all fixtures, see `Fixture::set_color`.
+    #[inline]
+    pub fn set_color(&self, universe: &mut DmxUniverse, color: Color) -> bool {
+        self.set_color_fan(universe, color, color, Fan::Linear)
+    }
+
+    /// Sets the color, blended from `from` to `to` across the group.
+    pub fn set_color_fan(
+        &self,
+        universe: &mut DmxUniverse,
+        from: Color,
+        to: Color,
+        fan: Fan,
+    ) -> bool {
+        self.apply(universe, fan, |fixture, universe, t| {
+            let color = Color::new(
+                lerp(from.red, to.red, t),
+                lerp(from.green, to.green, t),
+                lerp(from.blue, to.blue, t),
+            );
+            fixture.set_color(universe, color)
+        })
+    }
+
+    /// Sets pan and tilt of all fixtures, see `Fixture::set_pan_tilt`.
+    #[inline]
+    pub fn set_pan_tilt(&self, universe: &mut DmxUniverse, pan: u16, tilt: u16) -> bool {
+        self.set_pan_tilt_fan(universe, (pan, pan), (tilt, tilt), Fan::Linear)
+    }
+
+    /// Sets pan and tilt, fanned out between the first and second values of
+    /// `pan` and `tilt`, e.g. to spread beams across the stage.
+    pub fn set_pan_tilt_fan(
+        &self,
+        universe: &mut DmxUniverse,
+        pan: (u16, u16),
+        tilt: (u16, u16),
+        fan: Fan,
+    ) -> bool {
+        self.apply(universe, fan, |fixture, universe, t| {
+            let pan = lerp_16(pan.0, pan.1, t);
+            let tilt = lerp_16(tilt.0, tilt.1, t);
+            fixture.set_pan_tilt(universe, pan, tilt)
+        })
+    }
+
+    /// Selects a gobo on all fixtures.
+    #[inline]
+    pub fn set_gobo(&self, universe: &mut DmxUniverse, value: u8) -> bool {
+        self.set(universe, &Attribute::Gobo, value)
+    }
+
+    /// Sets the shutter, or strobe, channel of all fixtures.
+    #[inline]
+    pub fn set_strobe(&self, universe: &mut DmxUniverse, value: u8) -> bool {
+        self.set(universe, &Attribute::Strobe, value)
+    }
+
+    /// Sets all channels of all fixtures to their defaults.
+    pub fn write_defaults(&self, universe: &mut DmxUniverse) {
+        for fixture in &self.fixtures {
+            fixture.write_defaults(universe);
+        }
+    }
+}
+
+impl std::iter::FromIterator<Fixture> for FixtureGroup {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = Fixture>>(fixtures: I) -> FixtureGroup {
+        FixtureGroup {
+            fixtures: fixtures.into_iter().collect(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a FixtureGroup {
+    type Item = &'a Fixture;
+    type IntoIter = std::slice::Iter<'a, Fixture>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.fixtures.iter()
+    }
+}
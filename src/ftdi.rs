@@ -0,0 +1,191 @@
+//! Enttec Open DMX and plain FTDI interface support.
+//!
+//! The Open DMX USB and its many clones consist of nothing more than an FTDI
+//! USB-serial converter connected to an RS485 transceiver. Unlike the
+//! DMX USB Pro, the host is responsible for generating the complete DMX
+//! signal. As the FTDI virtual COM port drivers do not handle breaks well,
+//! the chip is driven through [libftdi](https://www.intra2net.com/en/developer/libftdi/)
+//! instead, which allows toggling the break condition directly.
+//!
+//! Requires the `ftdi` feature.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_int;
+use std::{io, ptr, thread};
+
+use libftdi1_sys as ffi;
+
+use crate::timing::DmxTiming;
+use crate::{DmxTransmitter, Error, Result};
+
+/// USB vendor ID of FTDI.
+pub const FTDI_VENDOR_ID: u16 = 0x0403;
+
+/// USB product ID of the FT232R, used by the Open DMX USB.
+pub const FT232R_PRODUCT_ID: u16 = 0x6001;
+
+/// Open DMX USB transmitter.
+///
+/// Drives an FTDI chip through libftdi, generating breaks by setting the
+/// line's break condition for the configured duration.
+pub struct OpenDmxTransmitter {
+    ctx: *mut ffi::ftdi_context,
+    timing: DmxTiming,
+}
+
+// the context is owned exclusively and libftdi holds no thread-local state
+unsafe impl Send for OpenDmxTransmitter {}
+
+impl OpenDmxTransmitter {
+    /// Opens the first FT232R-based interface found.
+    #[inline]
+    pub fn open() -> io::Result<OpenDmxTransmitter> {
+        OpenDmxTransmitter::open_device(FTDI_VENDOR_ID, FT232R_PRODUCT_ID, None)
+    }
+
+    /// Opens a specific FTDI device.
+    ///
+    /// If `serial` is given, only the device with a matching USB serial
+    /// number is opened.
+    pub fn open_device(
+        vendor: u16,
+        product: u16,
+        serial: Option<&str>,
+    ) -> io::Result<OpenDmxTransmitter> {
+        let serial = match serial {
+            Some(s) => {
+                Some(CString::new(s).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?)
+            }
+            None => None,
+        };
+
+        let ctx = unsafe { ffi::ftdi_new() };
+        if ctx.is_null() {
+            return Err(io::Error::other("could not allocate FTDI context"));
+        }
+
+        // from here on, the context is freed on drop
+        let mut tx = OpenDmxTransmitter {
+            ctx,
+            timing: DmxTiming::default(),
+        };
+
+        let serial_ptr = serial.as_ref().map_or(ptr::null(), |s| s.as_ptr());
+        tx.check(unsafe {
+            ffi::ftdi_usb_open_desc(
+                ctx,
+                c_int::from(vendor),
+                c_int::from(product),
+                ptr::null(),
+                serial_ptr,
+            )
+        })?;
+        tx.setup()?;
+
+        Ok(tx)
+    }
+
+    fn setup(&mut self) -> io::Result<()> {
+        let ctx = self.ctx;
+
+        unsafe {
+            self.check(ffi::ftdi_usb_reset(ctx))?;
+            self.check(ffi::ftdi_set_baudrate(ctx, 250_000))?;
+            self.check(ffi::ftdi_set_line_property(
+                ctx,
+                ffi::ftdi_bits_type::BITS_8,
+                ffi::ftdi_stopbits_type::STOP_BIT_2,
+                ffi::ftdi_parity_type::NONE,
+            ))?;
+            self.check(ffi::ftdi_setflowctrl(ctx, ffi::SIO_DISABLE_FLOW_CTRL))?;
+            // RTS drives the transceiver's enable line on some clones
+            self.check(ffi::ftdi_setrts(ctx, 0))?;
+            self.check(ffi::ftdi_tcioflush(ctx))?;
+        }
+
+        Ok(())
+    }
+
+    /// Converts a libftdi return code into a result.
+    fn check(&self, rv: c_int) -> io::Result<c_int> {
+        if rv >= 0 {
+            return Ok(rv);
+        }
+
+        let msg = unsafe { CStr::from_ptr(ffi::ftdi_get_error_string(self.ctx)) };
+        Err(io::Error::other(msg.to_string_lossy().into_owned()))
+    }
+
+    fn set_break(&mut self, on: bool) -> io::Result<()> {
+        let break_type = if on {
+            ffi::ftdi_break_type::BREAK_ON
+        } else {
+            ffi::ftdi_break_type::BREAK_OFF
+        };
+
+        self.check(unsafe {
+            ffi::ftdi_set_line_property2(
+                self.ctx,
+                ffi::ftdi_bits_type::BITS_8,
+                ffi::ftdi_stopbits_type::STOP_BIT_2,
+                ffi::ftdi_parity_type::NONE,
+                break_type,
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Returns the current timing parameters.
+    #[inline]
+    pub fn timing(&self) -> DmxTiming {
+        self.timing
+    }
+
+    /// Sets the timing parameters.
+    ///
+    /// Only break and mark-after-break durations are used; the USB latency
+    /// already exceeds the minimum break-to-break time.
+    pub fn set_timing(&mut self, timing: DmxTiming) -> Result<()> {
+        timing.validate()?;
+        self.timing = timing;
+        Ok(())
+    }
+}
+
+impl Drop for OpenDmxTransmitter {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::ftdi_usb_close(self.ctx);
+            ffi::ftdi_free(self.ctx);
+        }
+    }
+}
+
+impl DmxTransmitter for OpenDmxTransmitter {
+    type Error = Error;
+
+    fn send_break(&mut self) -> Result<()> {
+        self.set_break(true).map_err(Error::BreakFailed)?;
+        thread::sleep(self.timing.break_duration());
+        self.set_break(false).map_err(Error::BreakFailed)
+    }
+
+    fn send_raw_data(&mut self, data: &[u8]) -> Result<()> {
+        let mut remaining = data;
+
+        while !remaining.is_empty() {
+            let written = self.check(unsafe {
+                ffi::ftdi_write_data(self.ctx, remaining.as_ptr(), remaining.len() as c_int)
+            })?;
+            remaining = &remaining[written as usize..];
+        }
+
+        Ok(())
+    }
+
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<()> {
+        self.send_break()?;
+        thread::sleep(self.timing.mab_duration());
+        self.send_raw_data(data)
+    }
+}
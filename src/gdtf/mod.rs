@@ -0,0 +1,288 @@
+//! GDTF fixture import.
+//!
+//! The [General Device Type Format](https://gdtf.eu/) describes fixtures in
+//! files ending in `.gdtf`: zip archives holding an XML `description.xml`,
+//! along with images and models. `open` reads the modes of such a file into
+//! a `FixtureProfile`, with the function and default value of each channel.
+//!
+//! Only the channels of the first DMX break are imported; fixtures spanning
+//! several breaks are usually patched as separate fixtures. GDTF attributes
+//! without an equivalent `Attribute` are kept as `Attribute::Other`, fine
+//! bytes of those are named after them with `Fine` appended.
+//!
+//! ## Example
+//!
+//! ```
+//! use dmx::fixture::Attribute;
+//!
+//! let description = r#"<?xml version="1.0" encoding="UTF-8"?>
+//! <GDTF DataVersion="1.1">
+//!   <FixtureType Name="Spot 300" Manufacturer="Generic">
+//!     <DMXModes>
+//!       <DMXMode Name="Standard">
+//!         <DMXChannels>
+//!           <DMXChannel DMXBreak="1" Offset="1" InitialFunction="Base_Dimmer.Dimmer.Dimmer">
+//!             <LogicalChannel Attribute="Dimmer">
+//!               <ChannelFunction Name="Dimmer" Attribute="Dimmer" Default="0/1"/>
+//!             </LogicalChannel>
+//!           </DMXChannel>
+//!           <DMXChannel DMXBreak="1" Offset="2,3">
+//!             <LogicalChannel Attribute="Pan">
+//!               <ChannelFunction Name="Pan" Attribute="Pan" Default="32768/2"/>
+//!             </LogicalChannel>
+//!           </DMXChannel>
+//!         </DMXChannels>
+//!       </DMXMode>
+//!     </DMXModes>
+//!   </FixtureType>
+//! </GDTF>"#;
+//!
+//! let profile = dmx::gdtf::from_description(description).unwrap();
+//! let mode = profile.mode("Standard").unwrap();
+//!
+//! assert_eq!(profile.model, "Spot 300");
+//! assert_eq!(mode.footprint(), 3);
+//! assert_eq!(mode.channels[1].attribute, Attribute::Pan);
+//! assert_eq!(mode.channels[2].attribute, Attribute::PanFine);
+//! assert_eq!(mode.channels[1].default, 0x80);
+//! ```
+
+mod zip;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::fixture::{Attribute, ChannelDef, FixtureMode, FixtureProfile};
+use crate::xml::{self, Element};
+
+/// Name of the fixture description inside an archive.
+const DESCRIPTION: &str = "description.xml";
+
+/// Reads the fixture profile of a `.gdtf` file.
+///
+/// Fails with `InvalidData` if the file is not a valid GDTF archive.
+pub fn open<P: AsRef<Path>>(path: P) -> io::Result<FixtureProfile> {
+    from_archive(&fs::read(path)?)
+}
+
+/// Reads the fixture profile of a GDTF archive already in memory.
+///
+/// Fails with `InvalidData` if the data is not a valid GDTF archive.
+pub fn from_archive(archive: &[u8]) -> io::Result<FixtureProfile> {
+    let description = zip::extract(archive, DESCRIPTION)?;
+    let description = String::from_utf8(description)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "description is not UTF-8"))?;
+
+    from_description(&description)
+}
+
+/// Reads a fixture profile from the contents of a `description.xml`.
+///
+/// Fails with `InvalidData` if the document is not a valid description.
+pub fn from_description(description: &str) -> io::Result<FixtureProfile> {
+    let root = xml::parse(description)?;
+    let fixture_type = root
+        .child("FixtureType")
+        .filter(|_| root.name == "GDTF")
+        .ok_or_else(|| invalid("not a GDTF description"))?;
+
+    let mut profile = FixtureProfile::new(
+        fixture_type.attr("Manufacturer").unwrap_or_default(),
+        fixture_type.attr("Name").unwrap_or_default(),
+    );
+
+    if let Some(modes) = fixture_type.child("DMXModes") {
+        for mode in modes.children("DMXMode") {
+            profile.add_mode(read_mode(mode)?);
+        }
+    }
+
+    Ok(profile)
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_mode(element: &Element) -> io::Result<FixtureMode> {
+    let mut mode = FixtureMode::new(element.attr("Name").unwrap_or_default());
+
+    let channels = match element.child("DMXChannels") {
+        Some(channels) => channels,
+        None => return Ok(mode),
+    };
+
+    for channel in channels.children("DMXChannel") {
+        if channel.attr("DMXBreak").unwrap_or("1") != "1" {
+            continue;
+        }
+
+        // virtual channels have no offset
+        let offsets = match channel.attr("Offset") {
+            None | Some("None") | Some("") => continue,
+            Some(offsets) => offsets
+                .split(',')
+                .map(|o| match o.trim().parse::<usize>() {
+                    Ok(o) if o >= 1 => Ok(o - 1),
+                    _ => Err(invalid("invalid channel offset")),
+                })
+                .collect::<io::Result<Vec<usize>>>()?,
+        };
+
+        let name = channel_attribute(channel);
+        let value = channel_default(channel, offsets.len())?;
+
+        // the coarse byte comes first
+        for (i, &offset) in offsets.iter().enumerate() {
+            if mode.channels.len() <= offset {
+                mode.channels.resize(
+                    offset + 1,
+                    ChannelDef {
+                        attribute: Attribute::Other(String::new()),
+                        default: 0,
+                    },
+                );
+            }
+
+            let shift = 8 * (offsets.len() - 1 - i);
+            mode.channels[offset] = ChannelDef {
+                attribute: if i == 0 { attribute(name) } else { fine_attribute(name) },
+                default: value.checked_shr(shift as u32).unwrap_or(0) as u8,
+            };
+        }
+    }
+
+    Ok(mode)
+}
+
+/// Returns the GDTF attribute a channel controls.
+fn channel_attribute(channel: &Element) -> &str {
+    let logical = channel.child("LogicalChannel");
+
+    logical
+        .and_then(|l| l.attr("Attribute"))
+        .or_else(|| {
+            logical
+                .and_then(|l| l.child("ChannelFunction"))
+                .and_then(|f| f.attr("Attribute"))
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the default value of a channel, scaled to `bytes` bytes.
+///
+/// GDTF 1.0 puts the default on the channel, later versions on its initial
+/// channel function, which is the first one unless named otherwise.
+fn channel_default(channel: &Element, bytes: usize) -> io::Result<u64> {
+    if let Some(default) = channel.attr("Default") {
+        return parse_value(default, bytes);
+    }
+
+    let functions: Vec<&Element> = channel
+        .children("LogicalChannel")
+        .flat_map(|l| l.children("ChannelFunction"))
+        .collect();
+
+    // the initial function is referenced as `Geometry.LogicalChannel.Function`
+    let initial = channel
+        .attr("InitialFunction")
+        .and_then(|f| f.rsplit('.').next())
+        .and_then(|name| functions.iter().find(|f| f.attr("Name") == Some(name)));
+
+    match initial.or_else(|| functions.first()).and_then(|f| f.attr("Default")) {
+        Some(default) => parse_value(default, bytes),
+        None => Ok(0),
+    }
+}
+
+/// Parses a DMX value, written as `value/bytes`, and scales it to `bytes`
+/// bytes.
+fn parse_value(value: &str, bytes: usize) -> io::Result<u64> {
+    let (value, resolution) = match value.split_once('/') {
+        Some((value, resolution)) => (value, resolution),
+        None => (value, "1"),
+    };
+
+    let value: u64 = value.trim().parse().map_err(|_| invalid("invalid DMX value"))?;
+    let resolution: usize = resolution
+        .trim()
+        .parse()
+        .ok()
+        .filter(|r| (1..=4).contains(r))
+        .ok_or_else(|| invalid("invalid DMX value"))?;
+
+    Ok(if resolution < bytes {
+        value << (8 * (bytes - resolution).min(4))
+    } else {
+        value >> (8 * (resolution - bytes))
+    })
+}
+
+/// Maps a GDTF attribute onto the attribute of a coarse channel.
+fn attribute(name: &str) -> Attribute {
+    match name {
+        "Dimmer" => Attribute::Intensity,
+        "Pan" => Attribute::Pan,
+        "Tilt" => Attribute::Tilt,
+        "PanTiltSpeed" => Attribute::PanTiltSpeed,
+        "ColorAdd_R" | "ColorRGB_Red" => Attribute::Red,
+        "ColorAdd_G" | "ColorRGB_Green" => Attribute::Green,
+        "ColorAdd_B" | "ColorRGB_Blue" => Attribute::Blue,
+        "ColorAdd_W" => Attribute::White,
+        "ColorAdd_A" => Attribute::Amber,
+        "ColorAdd_UV" => Attribute::Uv,
+        "ColorSub_C" => Attribute::Cyan,
+        "ColorSub_M" => Attribute::Magenta,
+        "ColorSub_Y" => Attribute::Yellow,
+        "CTO" | "CTC" | "CTB" => Attribute::ColorTemperature,
+        "Zoom" => Attribute::Zoom,
+        "Iris" => Attribute::Iris,
+        "StrobeFrequency" => Attribute::Strobe,
+        _ => numbered_attribute(name).unwrap_or_else(|| Attribute::Other(name.to_owned())),
+    }
+}
+
+/// Maps GDTF attributes of numbered wheels and devices, such as `Gobo2` or
+/// `Shutter1Strobe`.
+fn numbered_attribute(name: &str) -> Option<Attribute> {
+    // splits `Gobo2PosRotate` into `Gobo` and `PosRotate`
+    let split = |prefix: &str| {
+        let rest = name.strip_prefix(prefix)?;
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            return None;
+        }
+        Some(&rest[digits..])
+    };
+
+    if let Some(rest) = split("Gobo") {
+        Some(if rest.is_empty() { Attribute::Gobo } else { Attribute::GoboRotation })
+    } else if let Some("") = split("Color") {
+        Some(Attribute::ColorWheel)
+    } else if let Some(rest) = split("Effects") {
+        Some(if rest.is_empty() { Attribute::Effect } else { Attribute::EffectSpeed })
+    } else if let Some("") = split("Prism") {
+        Some(Attribute::Prism)
+    } else if let Some("") = split("Focus") {
+        Some(Attribute::Focus)
+    } else if let Some("") = split("Frost") {
+        Some(Attribute::Frost)
+    } else if split("Shutter").is_some() {
+        Some(Attribute::Strobe)
+    } else if name.contains("Control") || name.contains("Reset") {
+        Some(Attribute::Control)
+    } else {
+        None
+    }
+}
+
+/// Maps a GDTF attribute onto the attribute of its fine channels.
+fn fine_attribute(name: &str) -> Attribute {
+    match name {
+        "Dimmer" => Attribute::IntensityFine,
+        "Pan" => Attribute::PanFine,
+        "Tilt" => Attribute::TiltFine,
+        _ => Attribute::Other(format!("{}Fine", name)),
+    }
+}
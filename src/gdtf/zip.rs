@@ -0,0 +1,409 @@
+//! Reading files from zip archives.
+//!
+//! Supports stored and deflated entries, which covers all GDTF files.
+
+use std::io;
+
+const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+const CENTRAL_SIGNATURE: u32 = 0x0201_4b50;
+const LOCAL_SIGNATURE: u32 = 0x0403_4b50;
+
+// compression methods
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn u16_at(data: &[u8], i: usize) -> io::Result<u16> {
+    data.get(i..i + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| invalid("truncated zip archive"))
+}
+
+fn u32_at(data: &[u8], i: usize) -> io::Result<u32> {
+    data.get(i..i + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid("truncated zip archive"))
+}
+
+/// Extracts the file `name` from a zip archive.
+///
+/// Fails with `NotFound` if the archive does not contain it.
+pub(super) fn extract(archive: &[u8], name: &str) -> io::Result<Vec<u8>> {
+    // the end of central directory record is followed by a comment of up to
+    // 64 KiB
+    let eocd = (0..archive.len().saturating_sub(21))
+        .rev()
+        .take(65536 + 22)
+        .find(|&i| u32_at(archive, i).ok() == Some(EOCD_SIGNATURE))
+        .ok_or_else(|| invalid("not a zip archive"))?;
+
+    let entries = u16_at(archive, eocd + 10)?;
+    let mut pos = u32_at(archive, eocd + 16)? as usize;
+
+    for _ in 0..entries {
+        if u32_at(archive, pos)? != CENTRAL_SIGNATURE {
+            return Err(invalid("corrupt zip central directory"));
+        }
+
+        let method = u16_at(archive, pos + 10)?;
+        let compressed = u32_at(archive, pos + 20)? as usize;
+        let size = u32_at(archive, pos + 24)? as usize;
+        let name_len = usize::from(u16_at(archive, pos + 28)?);
+        let extra_len = usize::from(u16_at(archive, pos + 30)?);
+        let comment_len = usize::from(u16_at(archive, pos + 32)?);
+        let offset = u32_at(archive, pos + 42)? as usize;
+        let entry_name = archive
+            .get(pos + 46..pos + 46 + name_len)
+            .ok_or_else(|| invalid("truncated zip archive"))?;
+
+        pos += 46 + name_len + extra_len + comment_len;
+
+        if entry_name != name.as_bytes() {
+            continue;
+        }
+
+        if u32_at(archive, offset)? != LOCAL_SIGNATURE {
+            return Err(invalid("corrupt zip entry"));
+        }
+        let start = offset
+            + 30
+            + usize::from(u16_at(archive, offset + 26)?)
+            + usize::from(u16_at(archive, offset + 28)?);
+        let data = archive
+            .get(start..start + compressed)
+            .ok_or_else(|| invalid("truncated zip archive"))?;
+
+        return match method {
+            STORED => Ok(data.to_vec()),
+            DEFLATED => inflate(data, size),
+            _ => Err(invalid("unsupported zip compression method")),
+        };
+    }
+
+    Err(io::Error::new(io::ErrorKind::NotFound, "file not found in archive"))
+}
+
+// base values and extra bits of length and distance codes
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// order in which code length code lengths are stored
+const CODE_LENGTH_ORDER: [usize; 19] =
+    [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// A canonical Huffman code.
+struct Huffman {
+    // number of codes of each length
+    counts: [u16; 16],
+    // symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0; 16];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0; 16];
+        for len in 1..16 {
+            offsets[len] = offsets[len - 1] + counts[len - 1];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[usize::from(offsets[usize::from(len)])] = symbol as u16;
+                offsets[usize::from(len)] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u32,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| invalid("truncated deflate stream"))?;
+            self.buf |= u32::from(byte) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+
+        let value = self.buf & ((1 << n) - 1);
+        self.buf >>= n;
+        self.count -= n;
+
+        Ok(value)
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> io::Result<u16> {
+        // codes are stored most significant bit first
+        let (mut code, mut first, mut index) = (0, 0, 0);
+
+        for len in 1..16 {
+            code |= self.bits(1)? as i32;
+            let count = i32::from(huffman.counts[len]);
+
+            if code - first < count {
+                return Ok(huffman.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(invalid("invalid deflate code"))
+    }
+}
+
+/// Decompresses a raw deflate stream of `size` bytes.
+///
+/// Fails if the stream does not decompress to exactly `size` bytes, without
+/// ever holding more than that, as the size is read from the archive and
+/// must not be trusted.
+fn inflate(data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut bits = Bits {
+        data,
+        pos: 0,
+        buf: 0,
+        count: 0,
+    };
+
+    loop {
+        let last = bits.bits(1)? == 1;
+
+        match bits.bits(2)? {
+            0 => {
+                // stored blocks start at a byte boundary
+                bits.buf = 0;
+                bits.count = 0;
+
+                let len = bits.bits(16)? as usize;
+                if bits.bits(16)? as usize != !len & 0xffff {
+                    return Err(invalid("corrupt stored block"));
+                }
+
+                let block = data
+                    .get(bits.pos..bits.pos + len)
+                    .ok_or_else(|| invalid("truncated deflate stream"))?;
+                if out.len() + len > size {
+                    return Err(too_large());
+                }
+                out.extend_from_slice(block);
+                bits.pos += len;
+            }
+            1 => {
+                let mut lengths = [0; 288];
+                for (i, len) in lengths.iter_mut().enumerate() {
+                    *len = match i {
+                        0..=143 => 8,
+                        144..=255 => 9,
+                        256..=279 => 7,
+                        _ => 8,
+                    };
+                }
+
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut bits, &mut out, size, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut out, size, &literals, &distances)?;
+            }
+            _ => return Err(invalid("invalid deflate block type")),
+        }
+
+        if last {
+            if out.len() != size {
+                return Err(invalid("deflate stream shorter than declared"));
+            }
+            return Ok(out);
+        }
+    }
+}
+
+/// Reads the codes of a block compressed with dynamic Huffman codes.
+fn dynamic_codes(bits: &mut Bits<'_>) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = bits.bits(5)? as usize + 257;
+    let distance_count = bits.bits(5)? as usize + 1;
+    let code_count = bits.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_count] {
+        code_lengths[i] = bits.bits(3)? as u8;
+    }
+    let code = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![0; literal_count + distance_count];
+    let mut i = 0;
+
+    while i < lengths.len() {
+        let symbol = bits.decode(&code)?;
+
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths[..i]
+                    .last()
+                    .ok_or_else(|| invalid("invalid code length repeat"))?;
+                (previous, 3 + bits.bits(2)? as usize)
+            }
+            17 => (0, 3 + bits.bits(3)? as usize),
+            _ => (0, 11 + bits.bits(7)? as usize),
+        };
+
+        if i + repeat > lengths.len() {
+            return Err(invalid("invalid code length repeat"));
+        }
+        for len in &mut lengths[i..i + repeat] {
+            *len = value;
+        }
+        i += repeat;
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn too_large() -> io::Error {
+    invalid("deflate stream larger than declared")
+}
+
+/// Decompresses the data of a Huffman-coded block, to at most `size` bytes
+/// in total.
+fn inflate_block(
+    bits: &mut Bits<'_>,
+    out: &mut Vec<u8>,
+    size: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = usize::from(bits.decode(literals)?);
+
+        if symbol < 256 {
+            if out.len() == size {
+                return Err(too_large());
+            }
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let i = symbol - 257;
+        if i >= LENGTH_BASE.len() {
+            return Err(invalid("invalid deflate length code"));
+        }
+        let len = usize::from(LENGTH_BASE[i]) + bits.bits(u32::from(LENGTH_EXTRA[i]))? as usize;
+
+        let d = usize::from(bits.decode(distances)?);
+        if d >= DIST_BASE.len() {
+            return Err(invalid("invalid deflate distance code"));
+        }
+        let dist = usize::from(DIST_BASE[d]) + bits.bits(u32::from(DIST_EXTRA[d]))? as usize;
+
+        if dist > out.len() {
+            return Err(invalid("invalid deflate distance"));
+        }
+        if out.len() + len > size {
+            return Err(too_large());
+        }
+
+        // the copy may overlap the bytes it produces
+        let start = out.len() - dist;
+        for j in 0..len {
+            out.push(out[start + j]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "hello, hello, hello!", compressed with fixed Huffman codes
+    const FIXED: [u8; 12] = [
+        0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0xc8, 0x40, 0xa2, 0x14, 0x01,
+    ];
+
+    // "stored", in a single stored block
+    const STORED_BLOCK: [u8; 11] = [
+        0x01, 0x06, 0x00, 0xf9, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64,
+    ];
+
+    #[test]
+    fn inflates_fixed_codes() {
+        assert_eq!(inflate(&FIXED, 20).unwrap(), b"hello, hello, hello!");
+    }
+
+    #[test]
+    fn inflates_stored_blocks() {
+        assert_eq!(inflate(&STORED_BLOCK, 6).unwrap(), b"stored");
+    }
+
+    #[test]
+    fn rejects_output_beyond_the_declared_size() {
+        assert!(inflate(&FIXED, 19).is_err());
+        assert!(inflate(&FIXED, 5).is_err());
+        assert!(inflate(&STORED_BLOCK, 5).is_err());
+    }
+
+    #[test]
+    fn rejects_output_short_of_the_declared_size() {
+        // must fail without allocating the declared size up front
+        assert!(inflate(&FIXED, u32::MAX as usize).is_err());
+        assert!(inflate(&STORED_BLOCK, 7).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_streams() {
+        assert!(inflate(&FIXED[..6], 20).is_err());
+        assert!(inflate(&STORED_BLOCK[..8], 6).is_err());
+        assert!(inflate(&[], 0).is_err());
+    }
+
+    #[test]
+    fn rejects_what_is_not_an_archive() {
+        assert_eq!(
+            extract(b"not a zip archive at all", "description.xml").unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
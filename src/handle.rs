@@ -0,0 +1,162 @@
+//! Command interface to an output thread.
+
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::{thread, time};
+
+use crate::address::DmxAddress;
+use crate::universe::DmxUniverse;
+use crate::{DmxTransmitter, Error, Result};
+
+/// A command for the output thread of a `DmxHandle`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DmxCommand {
+    /// Sets a channel to a value.
+    SetChannel(DmxAddress, u8),
+    /// Sets consecutive channels, see `DmxUniverse::set_range`.
+    SetRange(DmxAddress, Vec<u8>),
+    /// Sets all channels to zero.
+    Blackout,
+    /// Changes the frame rate; values that are not positive are ignored.
+    SetFps(f32),
+    /// Sends a final blackout frame and stops the thread.
+    Shutdown,
+}
+
+/// Handle to a thread owning a transmitter.
+///
+/// The thread keeps the universe and sends it at a fixed frame rate,
+/// applying commands received from any number of handles in between. Unlike
+/// `DmxRefresher`, there is no shared state; handles only send commands
+/// through a channel, so the thread never waits for other threads.
+///
+/// The thread exits after a `DmxCommand::Shutdown` or once all handles are
+/// dropped, sending a blackout frame first. An error while sending stops it
+/// right away; the error is returned by joining the thread.
+///
+/// ```
+/// use dmx::testing::MockTransmitter;
+/// use dmx::{DmxAddress, DmxHandle};
+///
+/// let transmitter = MockTransmitter::new();
+/// let (handle, thread) = DmxHandle::spawn(transmitter.clone(), 40.0);
+///
+/// let other = handle.clone();
+/// std::thread::spawn(move || other.set_range(DmxAddress::MIN, &[0xff, 0x80]))
+///     .join()
+///     .unwrap();
+///
+/// handle.set_channel(DmxAddress::MAX, 0x10);
+/// handle.shutdown();
+/// thread.join().unwrap().unwrap();
+///
+/// // the last frame is a blackout
+/// transmitter.assert_channel(DmxAddress::MIN, 0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct DmxHandle {
+    sender: mpsc::Sender<DmxCommand>,
+}
+
+impl DmxHandle {
+    /// Spawns an output thread sending at `fps` frames per second.
+    ///
+    /// Returns a handle to the thread and its join handle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fps` is not a positive number.
+    pub fn spawn<T>(mut transmitter: T, fps: f32) -> (DmxHandle, thread::JoinHandle<Result<()>>)
+    where
+        T: DmxTransmitter<Error = Error> + Send + 'static,
+    {
+        assert!(fps > 0.0, "frame rate must be positive");
+
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || run(&mut transmitter, fps, &receiver));
+
+        (DmxHandle { sender }, thread)
+    }
+
+    /// Sends a command to the output thread.
+    ///
+    /// Returns `false` if the thread has exited.
+    #[inline]
+    pub fn send(&self, command: DmxCommand) -> bool {
+        self.sender.send(command).is_ok()
+    }
+
+    /// Sets channel `n` to `value`.
+    #[inline]
+    pub fn set_channel(&self, n: DmxAddress, value: u8) -> bool {
+        self.send(DmxCommand::SetChannel(n, value))
+    }
+
+    /// Sets consecutive channels, starting at channel `start`.
+    #[inline]
+    pub fn set_range(&self, start: DmxAddress, values: &[u8]) -> bool {
+        self.send(DmxCommand::SetRange(start, values.to_vec()))
+    }
+
+    /// Sets all channels to zero.
+    #[inline]
+    pub fn blackout(&self) -> bool {
+        self.send(DmxCommand::Blackout)
+    }
+
+    /// Changes the frame rate.
+    #[inline]
+    pub fn set_fps(&self, fps: f32) -> bool {
+        self.send(DmxCommand::SetFps(fps))
+    }
+
+    /// Stops the output thread after sending a blackout frame.
+    ///
+    /// Commands sent before are still applied, later ones are discarded.
+    #[inline]
+    pub fn shutdown(&self) -> bool {
+        self.send(DmxCommand::Shutdown)
+    }
+}
+
+fn run<T>(transmitter: &mut T, fps: f32, receiver: &mpsc::Receiver<DmxCommand>) -> Result<()>
+where
+    T: DmxTransmitter<Error = Error>,
+{
+    let mut universe = DmxUniverse::new();
+    let mut period = time::Duration::from_secs_f32(1.0 / fps);
+    let mut next = time::Instant::now();
+
+    loop {
+        // apply commands until the next frame is due
+        while let Some(timeout) = next.checked_duration_since(time::Instant::now()) {
+            let command = match receiver.recv_timeout(timeout) {
+                Ok(command) => command,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => DmxCommand::Shutdown,
+            };
+
+            match command {
+                DmxCommand::SetChannel(n, value) => universe.set(n, value),
+                DmxCommand::SetRange(start, values) => universe.set_range(start, &values),
+                DmxCommand::Blackout => universe.blackout(),
+                DmxCommand::SetFps(fps) if fps > 0.0 => {
+                    period = time::Duration::from_secs_f32(1.0 / fps);
+                }
+                DmxCommand::SetFps(_) => (),
+                DmxCommand::Shutdown => {
+                    universe.blackout();
+                    return transmitter.send_universe(&universe);
+                }
+            }
+        }
+
+        transmitter.send_universe(&universe)?;
+
+        next += period;
+        let now = time::Instant::now();
+        if next < now {
+            // fell behind, do not try to catch up with a burst of frames
+            next = now;
+        }
+    }
+}
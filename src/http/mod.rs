@@ -0,0 +1,606 @@
+//! HTTP control.
+//!
+//! An `HttpServer` exposes shared universes and scenes through a small JSON
+//! API, e.g. for home automation systems:
+//!
+//! ```text
+//! GET  /universes                   [1, 2]
+//! GET  /universes/{n}/channels      [255, 128, 0, ...]
+//! PUT  /universes/{n}/channels      [255, 128] or {"start": 10, "values": [255, 128]}
+//! GET  /universes/{n}/channels/{c}  {"value": 255}
+//! PUT  /universes/{n}/channels/{c}  {"value": 255} or 255
+//! GET  /scenes                      ["cold", "warm"]
+//! POST /scenes/{name}               activates the scene
+//! GET  /universes/{n}/stream        WebSocket, with the `websocket` feature
+//! ```
+//!
+//! Universes are numbered as they were added to the server, channels from 1
+//! to 512. Bulk updates set consecutive channels, starting at `start` or
+//! channel 1; values beyond channel 512 are ignored. Activating a scene sets
+//! all channels of its universe at once.
+//!
+//! With the `websocket` feature, universes can also be streamed to browsers,
+//! e.g. to build live visualizers and faders. Clients connecting to
+//! `/universes/{n}/stream` receive binary messages with all 512 channel
+//! values whenever they changed, checked at 40 frames per second. The
+//! universe may be one a receive loop writes incoming frames into as well.
+//! Clients update channels by sending binary messages, starting with the
+//! first channel as a big-endian 16-bit number followed by the values, or
+//! text messages with the JSON accepted by bulk updates. Malformed updates
+//! are ignored.
+//!
+//! Updates are answered with `204 No Content`, errors with a status code and
+//! a body such as `{"error": "no such universe"}`. There is no
+//! authentication, so the server should only be reachable from trusted
+//! networks.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use dmx::DmxRefresher;
+//! use dmx::http::HttpServer;
+//! use dmx::scenes::Scene;
+//!
+//! let refresher = DmxRefresher::new(dmx::open_serial("/dev/ttyUSB0").unwrap());
+//!
+//! let mut evening = Scene::new();
+//! evening.set_range(dmx::DmxAddress::MIN, &[0xff, 0x60, 0x10]);
+//!
+//! let mut server = HttpServer::bind(("0.0.0.0", 8080)).unwrap();
+//! server.add_universe(1, &refresher.handle());
+//! server.add_scene("evening", &refresher.handle(), evening);
+//!
+//! server.run().unwrap();
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::{fmt, io, thread, time};
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::address::DmxAddress;
+use crate::refresh::SharedUniverse;
+use crate::scenes::Scene;
+use crate::{Error, Result};
+
+#[cfg(feature = "websocket")]
+mod websocket;
+
+/// Duration after which idle connections are closed.
+const IDLE_TIMEOUT: time::Duration = time::Duration::from_secs(30);
+
+// limits on requests, generous for the JSON of 512 channels
+const MAX_LINE_LEN: usize = 8192;
+const MAX_HEADERS: usize = 64;
+const MAX_BODY_LEN: usize = 65_536;
+
+/// HTTP server controlling shared universes.
+///
+/// Every connection is served by a thread of its own, and kept alive between
+/// requests unless the client asks otherwise.
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use std::net::TcpStream;
+/// use dmx::http::HttpServer;
+/// use dmx::SharedUniverse;
+///
+/// let universe = SharedUniverse::new();
+/// let mut server = HttpServer::bind("127.0.0.1:0").unwrap();
+/// server.add_universe(1, &universe);
+///
+/// let addr = server.local_addr().unwrap();
+/// std::thread::spawn(move || server.run());
+///
+/// let mut conn = TcpStream::connect(addr).unwrap();
+/// conn.write_all(
+///     b"PUT /universes/1/channels/5 HTTP/1.1\r\n\
+///       Content-Length: 3\r\n\
+///       Connection: close\r\n\
+///       \r\n\
+///       128",
+/// )
+/// .unwrap();
+///
+/// let mut response = String::new();
+/// conn.read_to_string(&mut response).unwrap();
+///
+/// assert!(response.starts_with("HTTP/1.1 204"));
+/// assert_eq!(universe.snapshot()[4], 128);
+/// ```
+pub struct HttpServer {
+    listener: TcpListener,
+    // shared with the threads serving connections
+    routes: Arc<Routes>,
+}
+
+#[derive(Clone, Default)]
+struct Routes {
+    universes: BTreeMap<u32, SharedUniverse>,
+    scenes: BTreeMap<String, (SharedUniverse, Scene)>,
+}
+
+impl HttpServer {
+    /// Create a server listening on `addr`.
+    #[inline]
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<HttpServer> {
+        Ok(HttpServer::with_listener(TcpListener::bind(addr)?))
+    }
+
+    /// Create a server from a bound listener.
+    pub fn with_listener(listener: TcpListener) -> HttpServer {
+        HttpServer {
+            listener,
+            routes: Arc::default(),
+        }
+    }
+
+    /// Makes `universe` available as universe `n`.
+    ///
+    /// Replaces a universe added with the same number before.
+    pub fn add_universe(&mut self, n: u32, universe: &SharedUniverse) {
+        Arc::make_mut(&mut self.routes)
+            .universes
+            .insert(n, universe.clone());
+    }
+
+    /// Adds a scene, activated by setting it on `universe`.
+    ///
+    /// Replaces a scene added with the same name before.
+    pub fn add_scene(&mut self, name: &str, universe: &SharedUniverse, scene: Scene) {
+        Arc::make_mut(&mut self.routes)
+            .scenes
+            .insert(name.to_owned(), (universe.clone(), scene));
+    }
+
+    /// Returns the address the server is listening on.
+    #[inline]
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts connections and serves them.
+    ///
+    /// Only returns if accepting connections fails.
+    pub fn run(&self) -> Result<()> {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                // the client gave up before it was accepted
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionAborted => continue,
+                Err(e) => return Err(Error::Io(e)),
+            };
+
+            let routes = Arc::clone(&self.routes);
+            thread::spawn(move || {
+                // errors only end this connection
+                let _ = serve(stream, &routes);
+            });
+        }
+    }
+}
+
+impl fmt::Debug for HttpServer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HttpServer")
+            .field("listener", &self.listener)
+            .field("universes", &self.routes.universes.keys())
+            .field("scenes", &self.routes.scenes.keys())
+            .finish()
+    }
+}
+
+/// Body of a bulk update.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ChannelsBody {
+    Values(Vec<u8>),
+    Range { start: Option<u16>, values: Vec<u8> },
+}
+
+impl ChannelsBody {
+    /// Returns the first channel and the values to set.
+    ///
+    /// Returns `None` if the first channel is out of range.
+    fn into_range(self) -> Option<(DmxAddress, Vec<u8>)> {
+        let (start, values) = match self {
+            ChannelsBody::Values(values) => (None, values),
+            ChannelsBody::Range { start, values } => (start, values),
+        };
+
+        Some((DmxAddress::new(start.unwrap_or(1))?, values))
+    }
+}
+
+/// Body of a channel update.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ValueBody {
+    Value(u8),
+    Object { value: u8 },
+}
+
+type Reply = std::result::Result<Response, Response>;
+
+impl Routes {
+    fn respond(&self, method: &str, path: &str, body: &[u8]) -> Response {
+        self.route(method, path, body).unwrap_or_else(|e| e)
+    }
+
+    fn route(&self, method: &str, path: &str, body: &[u8]) -> Reply {
+        // the query is ignored
+        let path = path.split('?').next().unwrap_or_default();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        match segments[..] {
+            ["universes"] => {
+                allow(method, "GET")?;
+                Ok(Response::json(json!(self.universes.keys().collect::<Vec<_>>())))
+            }
+            ["universes", n, "channels"] => {
+                let universe = self.universe(n)?;
+
+                match method {
+                    "GET" => Ok(Response::json(json!(&universe.snapshot()[..]))),
+                    "PUT" => {
+                        let (start, values) = parse::<ChannelsBody>(body)?
+                            .into_range()
+                            .ok_or_else(|| Response::error(400, "invalid start channel"))?;
+
+                        universe.set_channels(start, &values);
+                        Ok(Response::no_content())
+                    }
+                    _ => Err(Response::method_not_allowed("GET, PUT")),
+                }
+            }
+            ["universes", n, "channels", c] => {
+                let universe = self.universe(n)?;
+                let channel = c
+                    .parse()
+                    .ok()
+                    .and_then(DmxAddress::new)
+                    .ok_or_else(|| Response::error(404, "no such channel"))?;
+
+                match method {
+                    "GET" => {
+                        let value = universe.snapshot()[channel.index()];
+                        Ok(Response::json(json!({ "value": value })))
+                    }
+                    "PUT" => {
+                        let value = match parse(body)? {
+                            ValueBody::Value(value) | ValueBody::Object { value } => value,
+                        };
+
+                        universe.set_channel(channel, value);
+                        Ok(Response::no_content())
+                    }
+                    _ => Err(Response::method_not_allowed("GET, PUT")),
+                }
+            }
+            ["scenes"] => {
+                allow(method, "GET")?;
+                Ok(Response::json(json!(self.scenes.keys().collect::<Vec<_>>())))
+            }
+            ["scenes", name] => {
+                let name = percent_decode(name)
+                    .ok_or_else(|| Response::error(400, "malformed scene name"))?;
+                let (universe, scene) = self
+                    .scenes
+                    .get(&name)
+                    .ok_or_else(|| Response::error(404, "no such scene"))?;
+                allow(method, "POST")?;
+
+                universe.set_channels(DmxAddress::MIN, scene.universe().channels());
+                Ok(Response::no_content())
+            }
+            #[cfg(feature = "websocket")]
+            ["universes", _, "stream"] => Err(Response::error(426, "expected a WebSocket upgrade")),
+            _ => Err(Response::error(404, "not found")),
+        }
+    }
+
+    /// Returns the universe to stream to a WebSocket client.
+    #[cfg(feature = "websocket")]
+    fn stream(&self, method: &str, path: &str) -> std::result::Result<&SharedUniverse, Response> {
+        let path = path.split('?').next().unwrap_or_default();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        match segments[..] {
+            ["universes", n, "stream"] => {
+                let universe = self.universe(n)?;
+                allow(method, "GET")?;
+                Ok(universe)
+            }
+            _ => Err(Response::error(404, "not found")),
+        }
+    }
+
+    fn universe(&self, n: &str) -> std::result::Result<&SharedUniverse, Response> {
+        n.parse()
+            .ok()
+            .and_then(|n| self.universes.get(&n))
+            .ok_or_else(|| Response::error(404, "no such universe"))
+    }
+}
+
+fn allow(method: &str, allowed: &'static str) -> std::result::Result<(), Response> {
+    if method != allowed {
+        return Err(Response::method_not_allowed(allowed));
+    }
+
+    Ok(())
+}
+
+fn parse<'a, T: Deserialize<'a>>(body: &'a [u8]) -> std::result::Result<T, Response> {
+    serde_json::from_slice(body)
+        .map_err(|e| Response::error(400, &format!("invalid request body: {}", e)))
+}
+
+/// Decodes `%XX` escapes in a path segment.
+///
+/// Returns `None` if an escape is malformed or the result is not UTF-8.
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut iter = segment.bytes();
+
+    while let Some(b) = iter.next() {
+        if b != b'%' {
+            bytes.push(b);
+            continue;
+        }
+
+        let high = char::from(iter.next()?).to_digit(16)?;
+        let low = char::from(iter.next()?).to_digit(16)?;
+        bytes.push((high << 4 | low) as u8);
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    // JSON, empty for no content
+    body: String,
+    allow: Option<&'static str>,
+}
+
+impl Response {
+    fn json(value: serde_json::Value) -> Response {
+        Response {
+            status: 200,
+            body: value.to_string(),
+            allow: None,
+        }
+    }
+
+    fn no_content() -> Response {
+        Response {
+            status: 204,
+            body: String::new(),
+            allow: None,
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response {
+            status,
+            body: json!({ "error": message }).to_string(),
+            allow: None,
+        }
+    }
+
+    fn method_not_allowed(allow: &'static str) -> Response {
+        Response {
+            allow: Some(allow),
+            ..Response::error(405, "method not allowed")
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        426 => "Upgrade Required",
+        431 => "Request Header Fields Too Large",
+        501 => "Not Implemented",
+        _ => "",
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+    keep_alive: bool,
+    // key of a WebSocket handshake
+    #[cfg(feature = "websocket")]
+    websocket_key: Option<String>,
+}
+
+/// Serves requests on a connection until it is closed.
+fn serve(stream: TcpStream, routes: &Routes) -> io::Result<()> {
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    loop {
+        let (response, keep_alive) = match read_request(&mut reader, &mut writer)? {
+            #[cfg(feature = "websocket")]
+            Some(Ok(Request {
+                method,
+                path,
+                websocket_key: Some(key),
+                ..
+            })) => match routes.stream(&method, &path) {
+                Ok(universe) => return websocket::stream(reader, writer, &key, universe),
+                Err(response) => (response, false),
+            },
+            Some(Ok(request)) => (
+                routes.respond(&request.method, &request.path, &request.body),
+                request.keep_alive,
+            ),
+            // the rest of a rejected request cannot be skipped reliably
+            Some(Err(response)) => (response, false),
+            None => return Ok(()),
+        };
+
+        write_response(&mut writer, &response, keep_alive)?;
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+/// Reads a request, or a response rejecting it.
+///
+/// Returns `None` if the client closed the connection before sending another
+/// request.
+fn read_request<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+) -> io::Result<Option<std::result::Result<Request, Response>>> {
+    let reject = |status, message| Ok(Some(Err(Response::error(status, message))));
+
+    let mut line = String::new();
+    if !read_line(reader, &mut line)? {
+        return Ok(None);
+    }
+
+    // request line, e.g. "GET /universes HTTP/1.1"
+    let mut parts = line.split(' ');
+    let (method, path, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(path), Some(version), None) if version.starts_with("HTTP/1.") => {
+            (method.to_owned(), path.to_owned(), version.to_owned())
+        }
+        _ => return reject(400, "malformed request line"),
+    };
+
+    let mut keep_alive = version == "HTTP/1.1";
+    let mut content_length = 0;
+    let mut expect_continue = false;
+    #[cfg(feature = "websocket")]
+    let (mut upgrade, mut websocket_key) = (false, None);
+
+    for n in 0.. {
+        if !read_line(reader, &mut line)? {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if line.is_empty() {
+            break;
+        }
+        if n == MAX_HEADERS {
+            return reject(431, "too many headers");
+        }
+
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name, value.trim()),
+            None => return reject(400, "malformed header"),
+        };
+
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = match value.parse::<usize>() {
+                Ok(len) => len,
+                Err(_) => return reject(400, "malformed content length"),
+            };
+        } else if name.eq_ignore_ascii_case("connection") {
+            if value.eq_ignore_ascii_case("close") {
+                keep_alive = false;
+            } else if value.eq_ignore_ascii_case("keep-alive") {
+                keep_alive = true;
+            }
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            return reject(501, "transfer encodings are not supported");
+        } else if name.eq_ignore_ascii_case("expect") {
+            expect_continue = value.eq_ignore_ascii_case("100-continue");
+        }
+
+        #[cfg(feature = "websocket")]
+        if name.eq_ignore_ascii_case("upgrade") {
+            upgrade = value.eq_ignore_ascii_case("websocket");
+        } else if name.eq_ignore_ascii_case("sec-websocket-key") {
+            websocket_key = Some(value.to_owned());
+        }
+    }
+
+    if content_length > MAX_BODY_LEN {
+        return reject(413, "request body too large");
+    }
+
+    // curl waits for this before sending larger bodies
+    if expect_continue && content_length > 0 {
+        writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Some(Ok(Request {
+        method,
+        path,
+        body,
+        keep_alive,
+        #[cfg(feature = "websocket")]
+        websocket_key: websocket_key.filter(|_| upgrade),
+    })))
+}
+
+/// Reads a line into `line`, without its line ending.
+///
+/// Returns `false` at the end of the stream. Fails if the line is longer than
+/// `MAX_LINE_LEN`.
+fn read_line<R: BufRead>(reader: &mut R, line: &mut String) -> io::Result<bool> {
+    line.clear();
+    if (&mut *reader).take(MAX_LINE_LEN as u64).read_line(line)? == 0 {
+        return Ok(false);
+    }
+    if !line.ends_with('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+    }
+
+    let len = line.trim_end_matches(['\r', '\n']).len();
+    line.truncate(len);
+    Ok(true)
+}
+
+fn write_response<W: Write>(
+    writer: &mut W,
+    response: &Response,
+    keep_alive: bool,
+) -> io::Result<()> {
+    let mut buf = String::with_capacity(128 + response.body.len());
+
+    // writing into a string cannot fail
+    let _ = write!(buf, "HTTP/1.1 {} {}\r\n", response.status, reason(response.status));
+    if response.status != 204 {
+        let _ = write!(
+            buf,
+            "Content-Type: application/json\r\nContent-Length: {}\r\n",
+            response.body.len()
+        );
+    }
+    if let Some(allow) = response.allow {
+        let _ = write!(buf, "Allow: {}\r\n", allow);
+    }
+    if !keep_alive {
+        buf.push_str("Connection: close\r\n");
+    }
+    buf.push_str("\r\n");
+    buf.push_str(&response.body);
+
+    // a single write, so the response goes out in as few segments as possible
+    writer.write_all(buf.as_bytes())?;
+    writer.flush()
+}
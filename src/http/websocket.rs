@@ -0,0 +1,305 @@
+//! Streaming universes over WebSockets.
+//!
+//! Implements the server side of RFC 6455, without extensions.
+
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::{thread, time};
+
+use super::ChannelsBody;
+use crate::address::DmxAddress;
+use crate::refresh::SharedUniverse;
+use crate::serialize;
+
+/// Appended to the key of a handshake before hashing.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Interval at which universes are checked for changes.
+const STREAM_INTERVAL: time::Duration = time::Duration::from_millis(25);
+
+// largest message accepted, across all of its frames
+const MAX_MESSAGE_LEN: usize = 65_536;
+
+// opcodes
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Completes the handshake, then streams `universe` until the connection is
+/// closed.
+pub(super) fn stream(
+    mut reader: BufReader<TcpStream>,
+    stream: TcpStream,
+    key: &str,
+    universe: &SharedUniverse,
+) -> io::Result<()> {
+    let accept = accept_key(key);
+
+    let mut response = b"HTTP/1.1 101 Switching Protocols\r\n\
+                         Upgrade: websocket\r\n\
+                         Connection: Upgrade\r\n\
+                         Sec-WebSocket-Accept: "
+        .to_vec();
+    response.extend_from_slice(&accept);
+    response.extend_from_slice(b"\r\n\r\n");
+    (&stream).write_all(&response)?;
+
+    // clients that only watch never send anything
+    stream.set_read_timeout(None)?;
+
+    // written to by both threads, for frames and pongs
+    let writer = Mutex::new(stream);
+    let done = AtomicBool::new(false);
+
+    thread::scope(|s| {
+        s.spawn(|| push_frames(&writer, universe, &done));
+
+        let rv = receive_updates(&mut reader, &writer, universe);
+        done.store(true, Ordering::Relaxed);
+        rv
+    })
+}
+
+/// Returns the `Sec-WebSocket-Accept` value answering a handshake `key`.
+fn accept_key(key: &str) -> [u8; 28] {
+    let mut accept = [0; 28];
+    serialize::encode(&sha1(format!("{}{}", key, GUID).as_bytes()), &mut accept);
+    accept
+}
+
+/// Sends the channels of `universe` whenever they changed, until `done` is
+/// set.
+fn push_frames(writer: &Mutex<TcpStream>, universe: &SharedUniverse, done: &AtomicBool) {
+    let mut last = None;
+
+    while !done.load(Ordering::Relaxed) {
+        let channels = universe.snapshot();
+
+        if last != Some(channels) {
+            if send(writer, BINARY, &channels).is_err() {
+                // also ends the receiving side
+                let _ = lock(writer).shutdown(Shutdown::Both);
+                return;
+            }
+            last = Some(channels);
+        }
+
+        thread::sleep(STREAM_INTERVAL);
+    }
+}
+
+/// Applies channel updates sent by the client, until it closes the
+/// connection.
+fn receive_updates<R: Read>(
+    reader: &mut R,
+    writer: &Mutex<TcpStream>,
+    universe: &SharedUniverse,
+) -> io::Result<()> {
+    loop {
+        match read_message(reader, writer)? {
+            Message::Binary(data) => {
+                if let [high, low, ref values @ ..] = data[..] {
+                    if let Some(start) = DmxAddress::new(u16::from_be_bytes([high, low])) {
+                        universe.set_channels(start, values);
+                    }
+                }
+            }
+            Message::Text(text) => {
+                let range = serde_json::from_str::<ChannelsBody>(&text)
+                    .ok()
+                    .and_then(ChannelsBody::into_range);
+
+                if let Some((start, values)) = range {
+                    universe.set_channels(start, &values);
+                }
+            }
+            Message::Close => return send(writer, CLOSE, &[]),
+        }
+    }
+}
+
+enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Close,
+}
+
+/// Reads the next message, answering pings in between.
+fn read_message<R: Read>(reader: &mut R, writer: &Mutex<TcpStream>) -> io::Result<Message> {
+    // opcode and payload of a fragmented message
+    let mut partial: Option<(u8, Vec<u8>)> = None;
+
+    loop {
+        let (fin, opcode, payload) = read_frame(reader)?;
+
+        match opcode {
+            PING => {
+                send(writer, PONG, &payload)?;
+                continue;
+            }
+            PONG => continue,
+            CLOSE => return Ok(Message::Close),
+            TEXT | BINARY if partial.is_none() => partial = Some((opcode, payload)),
+            CONTINUATION => match partial {
+                Some((_, ref mut data)) if data.len() + payload.len() <= MAX_MESSAGE_LEN => {
+                    data.extend_from_slice(&payload);
+                }
+                Some(_) => return Err(invalid("WebSocket message too large")),
+                None => return Err(invalid("unexpected WebSocket continuation frame")),
+            },
+            _ => return Err(invalid("unexpected WebSocket frame")),
+        }
+
+        if fin {
+            // a data frame was stored above
+            return match partial.take() {
+                Some((TEXT, data)) => String::from_utf8(data)
+                    .map(Message::Text)
+                    .map_err(|_| invalid("WebSocket text message is not UTF-8")),
+                Some((_, data)) => Ok(Message::Binary(data)),
+                None => unreachable!(),
+            };
+        }
+    }
+}
+
+/// Reads a frame, returning whether it is final, its opcode and its
+/// unmasked payload.
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<(bool, u8, Vec<u8>)> {
+    let mut head = [0; 2];
+    reader.read_exact(&mut head)?;
+
+    let fin = head[0] & 0x80 != 0;
+    let opcode = head[0] & 0x0f;
+    if head[1] & 0x80 == 0 {
+        return Err(invalid("unmasked WebSocket frame"));
+    }
+
+    let len = match head[1] & 0x7f {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u64::from(u16::from_be_bytes(len))
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => u64::from(len),
+    };
+    if len > MAX_MESSAGE_LEN as u64 {
+        return Err(invalid("WebSocket message too large"));
+    }
+
+    let mut mask = [0; 4];
+    reader.read_exact(&mut mask)?;
+
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (b, m) in payload.iter_mut().zip(mask.iter().cycle()) {
+        *b ^= m;
+    }
+
+    Ok((fin, opcode, payload))
+}
+
+/// Sends a single, final frame.
+fn send(writer: &Mutex<TcpStream>, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= 0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+
+    lock(writer).write_all(&frame)
+}
+
+fn lock(writer: &Mutex<TcpStream>) -> MutexGuard<'_, TcpStream> {
+    // a stream stays usable even if a panic occurred while writing to it
+    writer.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Computes the SHA-1 digest of `data`, as needed for the handshake.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476, 0xc3d2_e1f0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0; 80];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::accept_key;
+
+    #[test]
+    fn accept_key_matches_rfc_sample() {
+        // RFC 6455, section 1.3
+        assert_eq!(&accept_key("dGhlIHNhbXBsZSBub25jZQ=="), b"s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+}
@@ -0,0 +1,318 @@
+//! KiNet support.
+//!
+//! KiNet is the UDP protocol of Color Kinetics power supplies and the LED
+//! fixtures attached to them. Power supplies are addressed by IP; those with
+//! several outputs additionally take a port number.
+//!
+//! Two packet formats are in use:
+//!
+//! * `DMXOUT`, from the first version of the protocol, carries a single
+//!   universe and is understood by all power supplies.
+//! * `PORTOUT`, from the second version, addresses one of up to 16 ports of
+//!   a power supply.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use dmx::DmxTransmitter;
+//! use dmx::kinet::{KinetOutput, KinetTransmitter};
+//!
+//! let output = KinetOutput::port_out(2).unwrap();
+//! let mut supply = KinetTransmitter::new([10, 0, 0, 30].into(), output).unwrap();
+//!
+//! supply.send_dmx_packet(&[0xff, 0x00, 0x80]).unwrap();
+//! ```
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::{cmp, io};
+
+use crate::{DmxTransmitter, Error, Result};
+
+/// UDP port used by KiNet.
+pub const KINET_PORT: u16 = 6038;
+
+/// Magic number, present at the start of every KiNet packet.
+pub const MAGIC: u32 = 0x4adc_0104;
+
+/// Packet type of `DMXOUT` packets.
+pub const TYPE_DMX_OUT: u16 = 0x0101;
+
+/// Packet type of `PORTOUT` packets.
+pub const TYPE_PORT_OUT: u16 = 0x0108;
+
+/// Highest port number of `PORTOUT` packets.
+pub const MAX_PORT: u8 = 16;
+
+// magic, version, type, sequence, port, padding, flags, universe, start code
+const DMX_OUT_HEADER_LEN: usize = 21;
+
+// magic, version, type, sequence, universe, port, padding, flags, length,
+// start code
+const PORT_OUT_HEADER_LEN: usize = 24;
+
+// KiNet universes are always sent to the power supply's wildcard universe
+const UNIVERSE_ALL: u32 = 0xffff_ffff;
+
+/// Output of a power supply data is sent to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum KinetOutput {
+    /// A single output, addressed by `DMXOUT` packets.
+    DmxOut,
+    /// A numbered port, addressed by `PORTOUT` packets.
+    PortOut(u8),
+}
+
+impl KinetOutput {
+    /// Create an output addressing a port.
+    ///
+    /// Returns `None` if `port` is not in the range of 1 to 16.
+    #[inline]
+    pub fn port_out(port: u8) -> Option<KinetOutput> {
+        if port == 0 || port > MAX_PORT {
+            return None;
+        }
+
+        Some(KinetOutput::PortOut(port))
+    }
+}
+
+/// Writes the common header of all KiNet packets into `buf`.
+fn write_header(buf: &mut [u8], version: u16, ty: u16, sequence: u32) {
+    buf[..4].copy_from_slice(&MAGIC.to_le_bytes());
+    buf[4..6].copy_from_slice(&version.to_le_bytes());
+    buf[6..8].copy_from_slice(&ty.to_le_bytes());
+    buf[8..12].copy_from_slice(&sequence.to_le_bytes());
+}
+
+/// Encodes a `DMXOUT` packet into `buf`.
+///
+/// `channels` is truncated to 512 channels. Returns the length of the packet;
+/// `buf` must hold at least 533 bytes.
+pub fn encode_dmx_out(buf: &mut [u8], sequence: u32, channels: &[u8]) -> usize {
+    let count = cmp::min(channels.len(), 512);
+
+    write_header(buf, 1, TYPE_DMX_OUT, sequence);
+    // port, padding and flags
+    buf[12..16].copy_from_slice(&[0; 4]);
+    buf[16..20].copy_from_slice(&UNIVERSE_ALL.to_le_bytes());
+    buf[20] = 0x00;
+
+    buf[DMX_OUT_HEADER_LEN..(DMX_OUT_HEADER_LEN + count)].copy_from_slice(&channels[..count]);
+
+    DMX_OUT_HEADER_LEN + count
+}
+
+/// Encodes a `PORTOUT` packet into `buf`.
+///
+/// `channels` is truncated to 512 channels. Returns the length of the packet;
+/// `buf` must hold at least 536 bytes.
+pub fn encode_port_out(buf: &mut [u8], sequence: u32, port: u8, channels: &[u8]) -> usize {
+    let count = cmp::min(channels.len(), 512);
+
+    write_header(buf, 2, TYPE_PORT_OUT, sequence);
+    buf[12..16].copy_from_slice(&UNIVERSE_ALL.to_le_bytes());
+    buf[16] = port;
+    // padding and flags
+    buf[17..20].copy_from_slice(&[0; 3]);
+    buf[20..22].copy_from_slice(&(count as u16).to_le_bytes());
+    // the start code is sent as a 16-bit value
+    buf[22..24].copy_from_slice(&[0; 2]);
+
+    buf[PORT_OUT_HEADER_LEN..(PORT_OUT_HEADER_LEN + count)].copy_from_slice(&channels[..count]);
+
+    PORT_OUT_HEADER_LEN + count
+}
+
+/// KiNet transmitter.
+///
+/// Sends packets to a power supply via UDP. As KiNet has no notion of breaks,
+/// `send_break` does nothing and only complete packets with the default start
+/// code can be sent.
+#[derive(Debug)]
+pub struct KinetTransmitter {
+    socket: UdpSocket,
+    target: SocketAddr,
+    output: KinetOutput,
+    sequence: u32,
+    buf: [u8; PORT_OUT_HEADER_LEN + 512],
+}
+
+impl KinetTransmitter {
+    /// Create a transmitter sending to the power supply at `ip`.
+    #[inline]
+    pub fn new(ip: IpAddr, output: KinetOutput) -> io::Result<KinetTransmitter> {
+        KinetTransmitter::with_target(SocketAddr::new(ip, KINET_PORT), output)
+    }
+
+    /// Create a transmitter sending to a specific socket address.
+    ///
+    /// Only needed if the power supply listens on a port other than 6038.
+    pub fn with_target(target: SocketAddr, output: KinetOutput) -> io::Result<KinetTransmitter> {
+        if let KinetOutput::PortOut(port) = output {
+            if KinetOutput::port_out(port).is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "port out of range 1-16",
+                ));
+            }
+        }
+
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+
+        Ok(KinetTransmitter {
+            socket,
+            target,
+            output,
+            sequence: 0,
+            buf: [0; PORT_OUT_HEADER_LEN + 512],
+        })
+    }
+
+    /// Returns the output packets are sent to.
+    #[inline]
+    pub fn output(&self) -> KinetOutput {
+        self.output
+    }
+
+    /// Returns the target socket address.
+    #[inline]
+    pub fn target(&self) -> SocketAddr {
+        self.target
+    }
+
+    /// Enables or disables sequence numbers.
+    ///
+    /// Power supplies do not require sequence numbers, which are disabled
+    /// by default.
+    #[inline]
+    pub fn set_sequence_enabled(&mut self, enabled: bool) {
+        self.sequence = if enabled { 1 } else { 0 };
+    }
+
+    /// Sends channel data to the output.
+    pub fn send_channels(&mut self, channels: &[u8]) -> Result<()> {
+        let len = match self.output {
+            KinetOutput::DmxOut => encode_dmx_out(&mut self.buf, self.sequence, channels),
+            KinetOutput::PortOut(port) => {
+                encode_port_out(&mut self.buf, self.sequence, port, channels)
+            }
+        };
+        self.socket.send_to(&self.buf[..len], self.target)?;
+
+        // sequence numbers wrap to 1, zero means disabled
+        if self.sequence != 0 {
+            self.sequence = self.sequence.checked_add(1).unwrap_or(1);
+        }
+
+        Ok(())
+    }
+}
+
+impl DmxTransmitter for KinetTransmitter {
+    type Error = Error;
+
+    #[inline]
+    fn send_break(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn send_raw_data(&mut self, _data: &[u8]) -> Result<()> {
+        Err(Error::Unsupported("KiNet can only transmit complete packets"))
+    }
+
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<()> {
+        match data.first() {
+            Some(&0x00) => self.send_channels(&data[1..]),
+            Some(&code) => Err(Error::UnsupportedStartCode(code)),
+            None => Err(Error::EmptyPacket),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dmx_out_layout() {
+        let mut buf = [0; PORT_OUT_HEADER_LEN + 512];
+        let len = encode_dmx_out(&mut buf, 0x0102_0304, &[0xff, 0x80, 0x40]);
+        assert_eq!(len, 24);
+
+        // magic, version, type and sequence, all little endian, port,
+        // padding, flags, universe, start code, channels
+        let expected = [
+            0x04, 0x01, 0xdc, 0x4a, 0x01, 0x00, 0x01, 0x01, 0x04, 0x03, 0x02, 0x01, 0x00, 0x00,
+            0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0xff, 0x80, 0x40,
+        ];
+        assert_eq!(buf[..len], expected);
+    }
+
+    #[test]
+    fn port_out_layout() {
+        let mut buf = [0; PORT_OUT_HEADER_LEN + 512];
+        let len = encode_port_out(&mut buf, 1, 16, &[0xff, 0x80, 0x40]);
+        assert_eq!(len, 27);
+
+        // magic, version, type, sequence, universe, port, padding, flags,
+        // length, 16-bit start code, channels
+        let expected = [
+            0x04, 0x01, 0xdc, 0x4a, 0x02, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x00, 0xff, 0xff,
+            0xff, 0xff, 0x10, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0xff, 0x80, 0x40,
+        ];
+        assert_eq!(buf[..len], expected);
+    }
+
+    #[test]
+    fn channels_are_truncated_to_512() {
+        let mut buf = [0; PORT_OUT_HEADER_LEN + 512];
+        let channels = [0x55; 600];
+
+        assert_eq!(encode_dmx_out(&mut buf, 0, &channels), DMX_OUT_HEADER_LEN + 512);
+        assert_eq!(encode_port_out(&mut buf, 0, 1, &channels), PORT_OUT_HEADER_LEN + 512);
+        assert_eq!(buf[20..22], [0x00, 0x02]);
+    }
+
+    #[test]
+    fn ports_range_from_1_to_16() {
+        assert_eq!(KinetOutput::port_out(0), None);
+        assert_eq!(KinetOutput::port_out(1), Some(KinetOutput::PortOut(1)));
+        assert_eq!(KinetOutput::port_out(16), Some(KinetOutput::PortOut(16)));
+        assert_eq!(KinetOutput::port_out(17), None);
+
+        let target = (Ipv4Addr::LOCALHOST, KINET_PORT).into();
+        let err = KinetTransmitter::with_target(target, KinetOutput::PortOut(17)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_to_one() {
+        let receiver = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let target = receiver.local_addr().unwrap();
+        let mut supply = KinetTransmitter::with_target(target, KinetOutput::DmxOut).unwrap();
+
+        let sequence = || {
+            let mut buf = [0; 64];
+            receiver.recv(&mut buf).unwrap();
+            u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]])
+        };
+
+        // disabled by default
+        supply.send_channels(&[0xff]).unwrap();
+        assert_eq!(sequence(), 0);
+        supply.send_channels(&[0xff]).unwrap();
+        assert_eq!(sequence(), 0);
+
+        supply.set_sequence_enabled(true);
+        supply.send_channels(&[0xff]).unwrap();
+        assert_eq!(sequence(), 1);
+
+        supply.sequence = u32::MAX;
+        supply.send_channels(&[0xff]).unwrap();
+        supply.send_channels(&[0xff]).unwrap();
+        assert_eq!(sequence(), u32::MAX);
+        assert_eq!(sequence(), 1);
+    }
+}
@@ -42,7 +42,8 @@
 //! The refresh rate depends on the number of channels transmitted. For the
 //! full 512 channels, the maximum achievable standard-compliant refresh rate
 //! is about 44 frames per second. If less than 512 channels are sent inside
-//! a packet, higher refresh rate are possible.
+//! a packet, higher refresh rate are possible, see
+//! `DmxPort::set_channel_count`.
 //!
 //! It should be noted that there is a minimum time between breaks (and
 //! therefore DMX packets) of 1204 microseconds, theoretically capping the
@@ -59,16 +60,116 @@
 //!
 //! # Implementations
 //!
-//! Currently, only an implementation using Linux serial devices is available.
-//! Connecting a UART to an RS485 transceiver is enough to get this working.
-//! The implementation is not 100% optimal for DMX: As most Linux kernels
+//! The main implementation uses serial devices on Linux, macOS and Windows
+//! (`COM` ports). Connecting a UART to an RS485 transceiver, or using a USB-RS485
+//! adapter, is enough to get this working. Serial devices which are likely
+//! DMX interfaces are listed by `enumerate`, `ReconnectingTransmitter` reopens
+//! devices after they were unplugged.
+//! The implementation is not 100% optimal for DMX: As most desktop kernels
 //! are not real-time capable, perfectly stable frame rates are not always
 //! achievable. However, the DMX protocol is fairly tolerant of loose timing.
+//! On Linux, the thread of a `DmxRefresher` can be given real-time priority,
+//! see `ThreadPriority`.
 //!
 //! The UARTs must support non-standard baudrates and reasonably fast baud-rate
 //! switching. Sending a break is done by switch to a slow baud-rate, sending
 //! a single `0x00` byte, then waiting a bit and switching back to 250,000
-//! baud.
+//! baud. Drivers that support it can instead assert the break condition
+//! directly, see `BreakMethod` and `DmxPort::builder`. On a Raspberry Pi, the
+//! PL011 UART has to be used instead of the mini UART, whose baud rate
+//! follows the core clock, see `DmxPortBuilder::allow_mini_uart`.
+//! Whether a port is able to send DMX at all is checked by `probe_port`,
+//! which measures the breaks it sends.
+//!
+//! DMX can also be sent over the network, see the `artnet` and `sacn` modules,
+//! or to Color Kinetics power supplies, see the `kinet` module, and pixel
+//! data to WiFi pixel controllers, see the `ddp` module. On machines with
+//! several network interfaces, the Art-Net and sACN senders take
+//! `SocketOptions` choosing the one to send from. USB interfaces that
+//! generate the DMX signal themselves are supported as well, see the
+//! `enttec` module, and those of DMXKing as well as their eDMX network units,
+//! see the `dmxking` module. Plain FTDI-based interfaces, such as the Open DMX
+//! USB, are available through the `ftdi` module if the `ftdi` feature is
+//! enabled, the Anyma uDMX through the `udmx` module with the `udmx` feature.
+//! With the `ola` feature, the `ola` module sends DMX through a running Open
+//! Lighting Architecture daemon, making all of its devices available.
+//!
+//! Channels can be controlled remotely as well: with the `osc` feature, the
+//! `osc` module maps OSC messages, e.g. from TouchOSC or QLab, onto shared
+//! universes. With the `midi` feature, the `midi` module does the same for
+//! notes and controllers of MIDI devices. The `http` feature adds an HTTP
+//! server with a JSON API for channels and scenes, see the `http` module.
+//! The `websocket` feature lets it stream universes to browsers as well.
+//! With the `mqtt` feature, the `mqtt` module controls channels and scenes
+//! through an MQTT broker and publishes their state, e.g. for Home Assistant.
+//!
+//! Without the default `std` feature, the crate is `no_std` and only provides
+//! packets, universes and timing. Microcontrollers can send DMX through any
+//! `embedded-hal` UART with the `embedded-hal` feature, see the `embedded`
+//! module.
+//!
+//! Alternate start codes are selected through `StartCode`. System information
+//! packets, allowing receivers to verify the data they got, are built and
+//! parsed by the `sip` module.
+//!
+//! Applications can be tested without hardware using the transmitter of the
+//! `testing` module. Wiring can be checked with the `dmx-send` binary, which
+//! sends fixed values or test patterns through a serial port. The `gateway`
+//! feature builds `dmx-gateway`, a binary forwarding Art-Net and sACN
+//! universes to serial ports as configured in a TOML file, remapping their
+//! channels through a `ChannelRemap` where needed.
+//!
+//! With the `tokio` feature, `AsyncDmxPort` sends DMX through the tokio
+//! runtime, see `AsyncDmxTransmitter`. With the `tracing` feature, serial
+//! ports emit `tracing` events for every break, write, drain and completed
+//! frame, which carry the frame's sequence number and timing. With the `ffi`
+//! feature, ports and refreshers can be used from C through the functions of
+//! the `ffi` module, which are declared in `include/dmx.h`. Python bindings
+//! live in the separate `python` crate, built with maturin.
+//!
+//! With the `serde` feature, packets, universes, universe diffs, scenes, cue
+//! lists, effects and patches implement `Serialize` and `Deserialize`.
+//! Channel data is stored as bytes, or as base64 strings in human-readable
+//! formats such as JSON. The `show` feature saves all of them together, along
+//! with the outputs of a rig, to a single TOML file through the `show`
+//! module.
+//!
+//! Rigs with several universes can drive all of their outputs from a single
+//! loop through `DmxOutputManager`, whose network outputs a `FrameScheduler`
+//! paces so they do not overrun their nodes. Several inputs are combined into
+//! one universe by the `merge` module. Tracking cue lists with crossfades, wait
+//! and follow times are provided by the `scenes` module, chases, strobes and
+//! other generated effects by the `effects` module, which the `audio` module
+//! makes follow the level and beats of music, captured from a sound card with
+//! the `audio` feature. `Color` converts between RGB, RGBW, CMY and HSV,
+//! fixtures are controlled by attribute rather than by channel, one at a time
+//! or fanned out across groups, through the `fixture` module, whose profiles
+//! can be imported from GDTF files with the `gdtf` feature or from QLC+
+//! fixture definitions with the `qlcplus` feature, which also imports the
+//! scenes and chasers of QLC+ workspaces, or from the Open Fixture Library
+//! with the `ofl` feature, and assigned addresses without overlaps by the
+//! `patch` module. LED strips
+//! spanning several universes are addressed through the `pixels` module.
+//! Dimmers with a poor low-end response are corrected by a `DimmerCurve`,
+//! `Masters` provide a grandmaster, blackout and submasters for groups of
+//! channels, `ChannelLimits` keep channels within ranges configured per
+//! fixture, and a `Park` holds channels such as house lights at fixed values
+//! regardless of scenes, effects and masters. Frames sent or received can be
+//! captured to a file and replayed later using the `record` module. The
+//! `timecode` module fires cues and replays recordings in sync with MIDI
+//! Timecode.
+//! A `TrackedUniverse` records whether it changed since it was last sent, so
+//! the frame rate can be lowered while nothing changes. A `UniverseDiff`
+//! holds the channels changed between two universes, e.g. to synchronize
+//! them over a network or to undo edits.
+//! Several threads can drive an output without sharing the transmitter
+//! through the commands of a `DmxHandle`.
+//!
+//! Remote device management (RDM) is available on ports implementing
+//! `DmxTransceiver`, see the `rdm` module. Half-duplex transceivers need their
+//! direction switched between sending and receiving, see the `direction`
+//! module. The `sniffer` module analyzes the timing of a monitored line,
+//! e.g. through a GPIO pin.
 //!
 //! ## Example
 //!
@@ -88,74 +189,249 @@
 //!        dmx_port.send_dmx_packet(data).unwrap();
 //!
 //!        // repeat about every 51 ms. for more accurate frame timings,
-//!        // consider using the ticktock crate.
+//!        // consider using `DmxTransmitter::run_refresh_loop`.
 //!        thread::sleep(time::Duration::new(0, 50_000_000));
 //!    }
 //! ```
 
-use std::ffi::OsStr;
-
-extern crate dmx_serial as serial;
-
-#[macro_use]
-extern crate lazy_static;
-
-use std::{cmp, thread, time};
-
-// The ideal baudrate for sending a break is 45,455 baud.
-// At this rate, sendin an 8-bit 0x00 will take the recommended 176 us
-// The following stop bit would take a reasonable 22 us.
-//
-// However, this non-standard baud rate is not supported. The closest common
-// baud rates are 57,600 bit/s and 38,400 bit/s. The former is chose here,
-// resulting in the following timings:
-//
-// BREAK:                 138 us    (spec minimum is 92 uS)
-// actual BREAK
-// MARK-AFTER-BREAK:      17 us    (spec minimum is  8 uS)
-
-const BREAK_SETTINGS: serial::PortSettings = serial::PortSettings {
-    baud_rate: serial::Baud57600,
-    char_size: serial::Bits7,
-    parity: serial::ParityNone,
-    stop_bits: serial::Stop1,
-    flow_control: serial::FlowNone,
-};
-
-const DMX_SETTINGS: serial::PortSettings = serial::PortSettings {
-    // DMX calls for 250_000 baud
-    baud_rate: serial::BaudOther(250_000),
-    char_size: serial::Bits8,
-    parity: serial::ParityNone,
-    stop_bits: serial::Stop2,
-    flow_control: serial::FlowNone,
-};
-
-lazy_static! {
-    // break will take at least 138 uS, followed by MAB of at least 8 uS
-    // we use a sleep + discard instead of using the kernel's builtin flushing
-    // functions, as they are much too slow
-    static ref SERIAL_TOTAL_BREAK: time::Duration = time::Duration::new(0, 136_000);
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "embedded-hal")]
+extern crate embedded_hal;
+#[cfg(all(target_os = "linux", feature = "gpio-cdev"))]
+extern crate gpio_cdev;
+#[cfg(feature = "ftdi")]
+extern crate libftdi1_sys;
+#[cfg(all(unix, feature = "std"))]
+extern crate libc;
+#[cfg(feature = "embedded-hal")]
+extern crate nb;
+#[cfg(feature = "udmx")]
+extern crate rusb;
+#[cfg(feature = "std")]
+extern crate serial2;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+
+use core::cmp;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+#[cfg(feature = "std")]
+use std::sync::atomic::AtomicBool;
+#[cfg(feature = "std")]
+use std::time;
+
+/// Emits a `tracing` event, if the `tracing` feature is enabled.
+#[cfg(feature = "std")]
+macro_rules! trace_event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+    };
 }
 
+mod address;
+#[cfg(feature = "std")]
+pub mod artnet;
+#[cfg(all(unix, feature = "tokio"))]
+mod async_serial;
+#[cfg(feature = "std")]
+pub mod audio;
+mod color;
+mod curve;
+#[cfg(feature = "std")]
+pub mod ddp;
+#[cfg(feature = "std")]
+mod devices;
+#[cfg(feature = "std")]
+mod diff;
+#[cfg(feature = "std")]
+pub mod direction;
+#[cfg(feature = "std")]
+pub mod dmxking;
+#[cfg(feature = "std")]
+pub mod effects;
+#[cfg(feature = "embedded-hal")]
+pub mod embedded;
+#[cfg(feature = "std")]
+pub mod enttec;
+#[cfg(feature = "std")]
+mod error;
+mod fade;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod fixture;
+#[cfg(feature = "ftdi")]
+pub mod ftdi;
+#[cfg(feature = "gdtf")]
+pub mod gdtf;
+#[cfg(feature = "std")]
+mod handle;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "std")]
+pub mod kinet;
+#[cfg(feature = "std")]
+mod limits;
+#[cfg(feature = "std")]
+mod master;
+#[cfg(feature = "std")]
+pub mod merge;
+#[cfg(feature = "midi")]
+pub mod midi;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "std")]
+mod net;
+#[cfg(feature = "ofl")]
+pub mod ofl;
+#[cfg(feature = "ola")]
+pub mod ola;
+#[cfg(feature = "osc")]
+pub mod osc;
+#[cfg(feature = "std")]
+mod output;
+mod packet;
+#[cfg(feature = "std")]
+mod park;
+#[cfg(feature = "std")]
+pub mod patch;
+#[cfg(feature = "std")]
+pub mod pixels;
+#[cfg(feature = "std")]
+mod probe;
+#[cfg(feature = "qlcplus")]
+pub mod qlcplus;
+#[cfg(feature = "std")]
+pub mod rdm;
+#[cfg(feature = "std")]
+pub mod record;
+#[cfg(all(unix, feature = "std"))]
+mod receiver;
+#[cfg(feature = "std")]
+mod reconnect;
+#[cfg(feature = "std")]
+mod refresh;
+#[cfg(feature = "std")]
+mod remap;
+#[cfg(feature = "std")]
+pub mod sacn;
+#[cfg(feature = "std")]
+pub mod scenes;
+#[cfg(feature = "std")]
+mod schedule;
+#[cfg(feature = "std")]
+mod serial;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "show")]
+pub mod show;
+pub mod sip;
+#[cfg(feature = "std")]
+pub mod sniffer;
+#[cfg(feature = "std")]
+mod stats;
+#[cfg(feature = "std")]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod timecode;
+mod timing;
+#[cfg(feature = "udmx")]
+pub mod udmx;
+mod universe;
+#[cfg(any(feature = "gdtf", feature = "qlcplus"))]
+mod xml;
+
+pub use address::{AddressError, DmxAddress};
+#[cfg(all(unix, feature = "tokio"))]
+pub use async_serial::AsyncDmxPort;
+pub use color::{Color, ColorOrder};
+#[cfg(feature = "std")]
+pub use curve::CurveMap;
+pub use curve::DimmerCurve;
+#[cfg(feature = "std")]
+pub use devices::{enumerate, DeviceKind, SerialDevice, UsbInfo};
+#[cfg(feature = "std")]
+pub use diff::{ChannelChange, UniverseDiff};
+#[cfg(feature = "std")]
+pub use direction::DirectionControl;
+#[cfg(feature = "std")]
+pub use error::{Error, Result};
+pub use fade::Easing;
+#[cfg(feature = "std")]
+pub use handle::{DmxCommand, DmxHandle};
+#[cfg(feature = "std")]
+pub use limits::ChannelLimits;
+#[cfg(feature = "std")]
+pub use master::Masters;
+#[cfg(feature = "std")]
+pub use net::SocketOptions;
+#[cfg(feature = "std")]
+pub use output::{BoxedTransmitter, DmxOutputManager};
+pub use packet::{DmxPacket, StartCode};
+#[cfg(feature = "std")]
+pub use park::Park;
+#[cfg(feature = "std")]
+pub use probe::{probe_port, ProbeProblem, ProbeReport, Support};
+#[cfg(all(unix, feature = "std"))]
+pub use receiver::{open_serial_receiver, SerialReceiver};
+#[cfg(feature = "std")]
+pub use reconnect::{ConnectionState, ReconnectingTransmitter};
+#[cfg(feature = "std")]
+pub use refresh::{DmxRefresher, FrameInfo, RefreshHandle, SharedUniverse, ThreadPriority};
+#[cfg(feature = "std")]
+pub use remap::ChannelRemap;
+#[cfg(feature = "std")]
+pub use schedule::FrameScheduler;
+#[cfg(feature = "std")]
+pub use serial::{open_serial, BreakMethod, DmxPort, DmxPortBuilder};
+#[cfg(feature = "std")]
+pub use stats::Stats;
+pub use timing::{DmxTiming, TimingError};
+pub use universe::{Channel16, DmxUniverse, TrackedUniverse};
+
 /// A DMX transmitter.
 ///
 /// Usually there is one transmitter on a bus, the master. Transmitters send
 /// DMX data.
+///
+/// The trait is object safe, so backends can be selected at runtime, e.g.
+/// from a configuration file, as a `BoxedTransmitter`. Boxed transmitters
+/// implement the trait as well and are accepted wherever a transmitter is:
+///
+/// ```no_run
+/// use dmx::{BoxedTransmitter, DmxRefresher, DmxTransmitter};
+/// use dmx::artnet::{ArtNetTransmitter, PortAddress};
+///
+/// # let config = "serial";
+/// let transmitter: BoxedTransmitter = match config {
+///     "serial" => dmx::open_serial("/dev/ttyUSB0").unwrap().boxed(),
+///     _ => ArtNetTransmitter::broadcast(PortAddress::default()).unwrap().boxed(),
+/// };
+///
+/// let refresher = DmxRefresher::new(transmitter);
+/// ```
 pub trait DmxTransmitter {
+    /// Error returned when sending fails, `dmx::Error` for all transmitters
+    /// backed by the operating system.
+    type Error;
+
     /// Send a single break.
     ///
     /// Sends a break and returns as soon as possible afterwards. A caller is
     /// itself responsible for waiting an appropriate amount of time before
     /// sending data.
-    fn send_break(&mut self) -> serial::Result<()>;
+    fn send_break(&mut self) -> core::result::Result<(), Self::Error>;
 
     /// Send raw data.
     ///
     /// Sends out bytes at the appropriate bitrate for DMX. Does **not** send
     /// a break first. Returns after the data is buffered, which might be
     /// before transmitting is complete.
-    fn send_raw_data(&mut self, data: &[u8]) -> serial::Result<()>;
+    fn send_raw_data(&mut self, data: &[u8]) -> core::result::Result<(), Self::Error>;
 
     /// Blocking send a full DMX packet.
     ///
@@ -167,8 +443,8 @@ pub trait DmxTransmitter {
     /// This will create an additional stack copy of `channels`; see
     /// `send_dmx_alt_packet` for details.
     #[inline(always)]
-    fn send_dmx_packet(&mut self, channels: &[u8]) -> serial::Result<()> {
-        self.send_dmx_alt_packet(channels, 0x00)
+    fn send_dmx_packet(&mut self, channels: &[u8]) -> core::result::Result<(), Self::Error> {
+        self.send_dmx_alt_packet(channels, StartCode::Null)
     }
 
     /// Blocking send a full DMX packet with a non-standard start code.
@@ -176,58 +452,356 @@ pub trait DmxTransmitter {
     /// Prepends an arbitrary start code `start` to a packet using a buffer
     /// on the stack (no allocations are made, but the cost of an extra copy
     /// is incurred). If required, this can be avoided by using
-    /// `send_raw_dmx_packet`.
+    /// `send_raw_dmx_packet`. `DmxPort` overrides this to write the start
+    /// code and channels without copying them.
     ///
-    /// Like `send_dmx_packet` will send a break first and returns after
-    /// buffering.
+    /// Only as many channels as given are sent, up to 512; any beyond are
+    /// dropped. Like `send_dmx_packet` will send a break first and returns
+    /// after buffering.
     #[inline]
-    fn send_dmx_alt_packet(&mut self, channels: &[u8], start: u8) -> serial::Result<()> {
-        let mut prefixed = [0; 513];
-        let dlen = cmp::min(channels.len(), 512);
-
-        // prepare prefixed packet
-        prefixed[0] = start;
-        prefixed[1..(dlen + 1)].clone_from_slice(channels);
+    fn send_dmx_alt_packet(
+        &mut self,
+        channels: &[u8],
+        start: StartCode,
+    ) -> core::result::Result<(), Self::Error> {
+        let mut buf = [0; 513];
+        let packet = prefix_start_code(&mut buf, channels, start);
 
-        self.send_raw_dmx_packet(&prefixed)
+        self.send_raw_dmx_packet(packet)
     }
 
     /// Blocking send a DMX packet including start code.
     ///
     /// Sends a break, followed by the specified data. Returns after buffering.
-    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> serial::Result<()>;
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> core::result::Result<(), Self::Error>;
+
+    /// Blocking send a `DmxPacket`.
+    ///
+    /// Sends a break, followed by the packet's start code and channels. As the
+    /// packet already contains its start code, no extra copy is made.
+    #[inline]
+    fn send_packet(&mut self, packet: &DmxPacket) -> core::result::Result<(), Self::Error> {
+        self.send_raw_dmx_packet(packet)
+    }
+
+    /// Blocking send an ASCII text packet.
+    ///
+    /// See `DmxPacket::text` for the meaning of the arguments.
+    #[inline]
+    fn send_text_packet(
+        &mut self,
+        page: u8,
+        chars_per_line: u8,
+        text: &str,
+    ) -> core::result::Result<(), Self::Error> {
+        self.send_packet(&DmxPacket::text(page, chars_per_line, text))
+    }
+
+    /// Blocking send all channels of a universe.
+    ///
+    /// Sends a full 512-channel packet with the default start code. See
+    /// `send_dmx_packet` for details.
+    #[inline]
+    fn send_universe(&mut self, universe: &DmxUniverse) -> core::result::Result<(), Self::Error> {
+        self.send_dmx_packet(universe.channels())
+    }
+
+    /// Continuously send a shared universe at a fixed frame rate.
+    ///
+    /// Each frame is scheduled relative to the deadline of the previous one
+    /// instead of sleeping a fixed period, so time spent transmitting and
+    /// oversleeping does not lower the actual frame rate. If sending falls
+    /// behind, frames are not sent back-to-back to catch up.
+    ///
+    /// Returns once `stop` is set, or on the first error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fps` is not a positive number.
+    #[cfg(feature = "std")]
+    fn run_refresh_loop(
+        &mut self,
+        universe: &SharedUniverse,
+        fps: f32,
+        stop: &AtomicBool,
+    ) -> core::result::Result<(), Self::Error> {
+        refresh::run_at_frame_rate(fps, stop, || self.send_dmx_packet(&universe.snapshot()))
+    }
+
+    /// Moves the transmitter into a `BoxedTransmitter`.
+    #[cfg(feature = "std")]
+    #[inline]
+    fn boxed(self) -> BoxedTransmitter
+    where
+        Self: DmxTransmitter<Error = Error> + Sized + Send + 'static,
+    {
+        Box::new(self)
+    }
 }
 
+/// Writes `start` followed by `channels` into `buf`, returning the packet.
+///
+/// Channels beyond 512 are dropped, shorter packets are sent as they are.
+#[inline]
+fn prefix_start_code<'a>(buf: &'a mut [u8; 513], channels: &[u8], start: StartCode) -> &'a [u8] {
+    let len = cmp::min(channels.len(), 512);
 
-impl<T: serial::SerialPort> DmxTransmitter for T {
-    #[inline(always)]
-    fn send_break(&mut self) -> serial::Result<()> {
-        self.configure(&BREAK_SETTINGS)?;
-        self.write(&[0x00])?;
-        Ok(())
+    buf[0] = start.as_u8();
+    buf[1..(len + 1)].copy_from_slice(&channels[..len]);
+    &buf[..(len + 1)]
+}
+
+#[cfg(feature = "std")]
+impl<T: DmxTransmitter + ?Sized> DmxTransmitter for Box<T> {
+    type Error = T::Error;
+
+    #[inline]
+    fn send_break(&mut self) -> core::result::Result<(), T::Error> {
+        (**self).send_break()
     }
 
-    #[inline(always)]
-    fn send_raw_data(&mut self, data: &[u8]) -> serial::Result<()> {
-        self.configure(&DMX_SETTINGS)?;
-        self.write(data)?;
-        Ok(())
+    #[inline]
+    fn send_raw_data(&mut self, data: &[u8]) -> core::result::Result<(), T::Error> {
+        (**self).send_raw_data(data)
+    }
+
+    #[inline]
+    fn send_dmx_packet(&mut self, channels: &[u8]) -> core::result::Result<(), T::Error> {
+        (**self).send_dmx_packet(channels)
     }
 
     #[inline]
-    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> serial::Result<()> {
-        // make a copy to ensure no pointer dereferencing overhead later
-        let min_brk = *SERIAL_TOTAL_BREAK;
+    fn send_dmx_alt_packet(
+        &mut self,
+        channels: &[u8],
+        start: StartCode,
+    ) -> core::result::Result<(), T::Error> {
+        (**self).send_dmx_alt_packet(channels, start)
+    }
 
-        self.send_break()?;
-        thread::sleep(min_brk);
-        self.send_raw_data(data)?;
+    #[inline]
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> core::result::Result<(), T::Error> {
+        (**self).send_raw_dmx_packet(data)
+    }
 
-        Ok(())
+    #[inline]
+    fn send_packet(&mut self, packet: &DmxPacket) -> core::result::Result<(), T::Error> {
+        (**self).send_packet(packet)
+    }
+
+    #[inline]
+    fn send_universe(&mut self, universe: &DmxUniverse) -> core::result::Result<(), T::Error> {
+        (**self).send_universe(universe)
+    }
+
+    #[inline]
+    fn run_refresh_loop(
+        &mut self,
+        universe: &SharedUniverse,
+        fps: f32,
+        stop: &AtomicBool,
+    ) -> core::result::Result<(), T::Error> {
+        (**self).run_refresh_loop(universe, fps, stop)
     }
 }
 
-/// Opens a serial device with DMX support.
-pub fn open_serial<T: AsRef<OsStr> + ?Sized>(port: &T) -> serial::Result<serial::SystemPort> {
-    serial::open(port)
+/// An asynchronous DMX transmitter.
+///
+/// Mirrors `DmxTransmitter`, but waits for breaks and buffer space without
+/// blocking the calling thread. Requires the `tokio` feature.
+#[cfg(feature = "tokio")]
+pub trait AsyncDmxTransmitter: Send {
+    /// Send a single break.
+    ///
+    /// See `DmxTransmitter::send_break`.
+    fn send_break(&mut self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Send raw data.
+    ///
+    /// See `DmxTransmitter::send_raw_data`.
+    fn send_raw_data(&mut self, data: &[u8]) -> impl Future<Output = Result<()>> + Send;
+
+    /// Send a full DMX packet.
+    ///
+    /// See `DmxTransmitter::send_dmx_packet`.
+    #[inline]
+    fn send_dmx_packet(&mut self, channels: &[u8]) -> impl Future<Output = Result<()>> + Send {
+        self.send_dmx_alt_packet(channels, StartCode::Null)
+    }
+
+    /// Send a full DMX packet with a non-standard start code.
+    ///
+    /// See `DmxTransmitter::send_dmx_alt_packet`.
+    fn send_dmx_alt_packet(
+        &mut self,
+        channels: &[u8],
+        start: StartCode,
+    ) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            let mut buf = [0; 513];
+            let packet = prefix_start_code(&mut buf, channels, start);
+
+            self.send_raw_dmx_packet(packet).await
+        }
+    }
+
+    /// Send a DMX packet including start code.
+    ///
+    /// See `DmxTransmitter::send_raw_dmx_packet`.
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> impl Future<Output = Result<()>> + Send;
+
+    /// Send a `DmxPacket`.
+    ///
+    /// See `DmxTransmitter::send_packet`.
+    #[inline]
+    fn send_packet(&mut self, packet: &DmxPacket) -> impl Future<Output = Result<()>> + Send {
+        self.send_raw_dmx_packet(packet)
+    }
+
+    /// Send an ASCII text packet.
+    ///
+    /// See `DmxTransmitter::send_text_packet`.
+    fn send_text_packet(
+        &mut self,
+        page: u8,
+        chars_per_line: u8,
+        text: &str,
+    ) -> impl Future<Output = Result<()>> + Send {
+        let packet = DmxPacket::text(page, chars_per_line, text);
+        async move { self.send_packet(&packet).await }
+    }
+
+    /// Send all channels of a universe.
+    ///
+    /// See `DmxTransmitter::send_universe`.
+    #[inline]
+    fn send_universe(
+        &mut self,
+        universe: &DmxUniverse,
+    ) -> impl Future<Output = Result<()>> + Send {
+        self.send_dmx_packet(universe.channels())
+    }
+}
+
+/// A DMX transmitter that can also receive on the same line.
+///
+/// Required for RDM, where responders reply to requests of the controller
+/// right after receiving them. See the `rdm` module.
+#[cfg(feature = "std")]
+pub trait DmxTransceiver: DmxTransmitter<Error = Error> {
+    /// Discard any received data not read yet.
+    fn discard_input(&mut self) -> Result<()>;
+
+    /// Blocking receive raw data.
+    ///
+    /// Waits for all pending output to be transmitted, then up to `timeout`
+    /// for data to arrive. Reading stops once the line goes idle again or
+    /// `buf` is full. Returns the number of bytes received, `0` if nothing
+    /// arrived in time.
+    ///
+    /// Breaks may show up as `0x00` bytes in the received data.
+    fn recv_raw_data(&mut self, buf: &mut [u8], timeout: time::Duration) -> Result<usize>;
+}
+
+/// A DMX receiver.
+///
+/// Receivers listen on the bus and reassemble the packets sent by the
+/// transmitter, usually for monitoring or merging purposes.
+#[cfg(feature = "std")]
+pub trait DmxReceiver {
+    /// Blocking receive a full DMX packet into a buffer.
+    ///
+    /// Waits for a break (unless the previous call already ended on one) and
+    /// stores the start code and all following channels into `buf`, until
+    /// the next break is detected or the line goes idle. Returns the number
+    /// of bytes written, including the start code.
+    ///
+    /// Data that does not fit into `buf` is discarded; a buffer of 513 bytes
+    /// is always large enough.
+    fn recv_dmx_packet_into(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+    /// Blocking receive a full DMX packet.
+    ///
+    /// Like `recv_dmx_packet_into`, but returns a newly allocated packet
+    /// including the start code.
+    #[inline]
+    fn recv_dmx_packet(&mut self) -> Result<Vec<u8>> {
+        let mut buf = [0; 513];
+        let len = self.recv_dmx_packet_into(&mut buf)?;
+
+        Ok(buf[..len].to_vec())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_code_is_prepended_to_the_channels() {
+        let mut buf = [0xaa; 513];
+
+        assert_eq!(prefix_start_code(&mut buf, &[], StartCode::Null), [0x00]);
+        assert_eq!(prefix_start_code(&mut buf, &[0xff, 0x80], StartCode::Text), [0x17, 0xff, 0x80]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn alt_packets_are_sent_at_their_length() {
+        let mut transmitter = testing::MockTransmitter::new();
+
+        for &count in &[0, 24, 512, 600] {
+            let channels = vec![0x55; count];
+            transmitter.send_dmx_alt_packet(&channels, StartCode::Text).unwrap();
+
+            let packet = transmitter.last_packet().unwrap();
+            assert_eq!(packet.start_code(), Some(0x17));
+            assert_eq!(packet.channels(), &channels[..count.min(512)]);
+        }
+        assert_eq!(transmitter.packet_count(), 4);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_alt_packets_are_sent_at_their_length() {
+        use std::future;
+        use std::pin::pin;
+        use std::task::{Context, Poll, Waker};
+
+        // records packets, completing every send at once
+        struct Recorder(Vec<Vec<u8>>);
+
+        impl AsyncDmxTransmitter for Recorder {
+            fn send_break(&mut self) -> impl Future<Output = Result<()>> + Send {
+                future::ready(Ok(()))
+            }
+
+            fn send_raw_data(&mut self, _data: &[u8]) -> impl Future<Output = Result<()>> + Send {
+                future::ready(Ok(()))
+            }
+
+            fn send_raw_dmx_packet(
+                &mut self,
+                data: &[u8],
+            ) -> impl Future<Output = Result<()>> + Send {
+                self.0.push(data.to_vec());
+                future::ready(Ok(()))
+            }
+        }
+
+        let mut transmitter = Recorder(Vec::new());
+        for &count in &[0, 24, 512, 600] {
+            let channels = vec![0x55; count];
+            {
+                let send = pin!(transmitter.send_dmx_alt_packet(&channels, StartCode::Text));
+                let mut cx = Context::from_waker(Waker::noop());
+                assert!(matches!(send.poll(&mut cx), Poll::Ready(Ok(()))));
+            }
+
+            let packet = transmitter.0.last().unwrap();
+            assert_eq!(packet[0], 0x17);
+            assert_eq!(packet[1..], channels[..count.min(512)]);
+        }
+    }
 }
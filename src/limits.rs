@@ -0,0 +1,115 @@
+//! Output limits.
+
+use crate::address::DmxAddress;
+use crate::packet::MAX_CHANNELS;
+use crate::universe::DmxUniverse;
+
+/// Lowest and highest values each channel may be output at.
+///
+/// Values outside of a channel's range are clamped into it, e.g. to cap a
+/// fog machine at 80% or to keep a mover's tilt away from the rigging.
+/// Channels are unlimited by default. Limits are usually taken from the
+/// fixtures of a patch, see `Patch::limits`.
+///
+/// Like `Masters`, limits are applied to a copy of the universe right before
+/// sending it; a `DmxOutputManager` applies them to every frame it sends
+/// through `DmxOutputManager::set_limits`.
+///
+/// ## Example
+///
+/// ```
+/// use dmx::{ChannelLimits, DmxAddress, DmxUniverse};
+///
+/// let fog = DmxAddress::new(1).unwrap();
+///
+/// let mut limits = ChannelLimits::new();
+/// limits.set(fog, 0, 0xcc);
+///
+/// let mut universe = DmxUniverse::new();
+/// universe.fill(0xff);
+/// assert_eq!(limits.apply(&universe).channels()[..2], [0xcc, 0xff]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelLimits {
+    min: [u8; MAX_CHANNELS],
+    max: [u8; MAX_CHANNELS],
+}
+
+impl Default for ChannelLimits {
+    #[inline]
+    fn default() -> ChannelLimits {
+        ChannelLimits {
+            min: [0; MAX_CHANNELS],
+            max: [0xff; MAX_CHANNELS],
+        }
+    }
+}
+
+impl ChannelLimits {
+    /// Create limits leaving all channels unlimited.
+    #[inline]
+    pub fn new() -> ChannelLimits {
+        ChannelLimits::default()
+    }
+
+    /// Limits channel `n` to values from `min` to `max`.
+    ///
+    /// The bounds are swapped if `min` is higher than `max`.
+    #[inline]
+    pub fn set(&mut self, n: DmxAddress, min: u8, max: u8) {
+        self.set_range(n, 1, min, max);
+    }
+
+    /// Limits `count` consecutive channels, starting at channel `start`, to
+    /// values from `min` to `max`.
+    ///
+    /// Channels beyond 512 are ignored.
+    pub fn set_range(&mut self, start: DmxAddress, count: usize, min: u8, max: u8) {
+        let (min, max) = (min.min(max), min.max(max));
+        let end = MAX_CHANNELS.min(start.index().saturating_add(count));
+
+        for i in start.index()..end {
+            self.min[i] = min;
+            self.max[i] = max;
+        }
+    }
+
+    /// Removes the limits of channel `n`.
+    #[inline]
+    pub fn clear(&mut self, n: DmxAddress) {
+        self.set(n, 0, 0xff);
+    }
+
+    /// Returns the lowest and highest value of channel `n`.
+    #[inline]
+    pub fn get(&self, n: DmxAddress) -> (u8, u8) {
+        (self.min[n.index()], self.max[n.index()])
+    }
+
+    /// Returns whether no channel is limited.
+    pub fn is_empty(&self) -> bool {
+        self.min.iter().all(|&v| v == 0) && self.max.iter().all(|&v| v == 0xff)
+    }
+
+    /// Clamps `channels`, the values of the universe from channel 1 on, into
+    /// their limits.
+    pub fn limit(&self, channels: &mut [u8]) {
+        let limits = self.min.iter().zip(&self.max);
+
+        for (v, (&min, &max)) in channels.iter_mut().zip(limits) {
+            *v = (*v).clamp(min, max);
+        }
+    }
+
+    /// Returns a copy of `universe` with all limits applied.
+    ///
+    /// Fades in progress are not copied.
+    pub fn apply(&self, universe: &DmxUniverse) -> DmxUniverse {
+        let mut output = DmxUniverse::new();
+        let channels = output.channels_mut();
+        channels.copy_from_slice(universe.channels());
+
+        self.limit(channels);
+        output
+    }
+}
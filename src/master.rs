@@ -0,0 +1,189 @@
+//! Master faders.
+
+use crate::address::DmxAddress;
+use crate::packet::MAX_CHANNELS;
+use crate::universe::DmxUniverse;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Group {
+    name: String,
+    members: [bool; MAX_CHANNELS],
+    level: u8,
+}
+
+/// Master faders scaling the channels of a universe.
+///
+/// The grandmaster scales all mastered channels, submasters scale the
+/// channels of named groups. Levels multiply, a channel in a group at half
+/// with the grandmaster at half is output at a quarter of its value. All
+/// channels are mastered by default; channels controlling positions, colors
+/// or other attributes should usually be excluded.
+///
+/// Like a `CurveMap`, masters are applied to a copy of the universe right
+/// before sending it.
+///
+/// ## Example
+///
+/// ```
+/// use dmx::{DmxAddress, DmxUniverse, Masters};
+///
+/// let mut masters = Masters::new();
+/// masters.add_group("front", DmxAddress::MIN, 2);
+/// masters.set_level("front", 0x80);
+///
+/// let mut universe = DmxUniverse::new();
+/// universe.fill(0xff);
+/// assert_eq!(masters.apply(&universe).channels()[..3], [0x80, 0x80, 0xff]);
+///
+/// masters.set_blackout(true);
+/// assert_eq!(masters.apply(&universe).channels()[..3], [0x00, 0x00, 0x00]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Masters {
+    grandmaster: u8,
+    blackout: bool,
+    mastered: [bool; MAX_CHANNELS],
+    groups: Vec<Group>,
+}
+
+impl Default for Masters {
+    #[inline]
+    fn default() -> Masters {
+        Masters {
+            grandmaster: 0xff,
+            blackout: false,
+            mastered: [true; MAX_CHANNELS],
+            groups: Vec::new(),
+        }
+    }
+}
+
+impl Masters {
+    /// Create masters with the grandmaster at full and no groups.
+    #[inline]
+    pub fn new() -> Masters {
+        Masters::default()
+    }
+
+    /// Returns the level of the grandmaster.
+    #[inline]
+    pub fn grandmaster(&self) -> u8 {
+        self.grandmaster
+    }
+
+    /// Sets the level of the grandmaster.
+    #[inline]
+    pub fn set_grandmaster(&mut self, level: u8) {
+        self.grandmaster = level;
+    }
+
+    /// Returns whether blackout is active.
+    #[inline]
+    pub fn is_blackout(&self) -> bool {
+        self.blackout
+    }
+
+    /// Activates or releases blackout, setting all mastered channels to zero
+    /// regardless of the grandmaster's level.
+    #[inline]
+    pub fn set_blackout(&mut self, blackout: bool) {
+        self.blackout = blackout;
+    }
+
+    /// Sets whether `count` consecutive channels, starting at channel
+    /// `start`, are scaled by the grandmaster.
+    ///
+    /// Channels beyond 512 are ignored. Submasters are not affected.
+    pub fn set_mastered(&mut self, start: DmxAddress, count: usize, mastered: bool) {
+        for m in self.mastered[start.index()..].iter_mut().take(count) {
+            *m = mastered;
+        }
+    }
+
+    /// Adds `count` consecutive channels, starting at channel `start`, to a
+    /// group.
+    ///
+    /// The group is created at full if it does not exist yet. Channels
+    /// beyond 512 are ignored.
+    pub fn add_group(&mut self, name: &str, start: DmxAddress, count: usize) {
+        let n = match self.groups.iter().position(|g| g.name == name) {
+            Some(n) => n,
+            None => {
+                self.groups.push(Group {
+                    name: name.to_owned(),
+                    members: [false; MAX_CHANNELS],
+                    level: 0xff,
+                });
+                self.groups.len() - 1
+            }
+        };
+
+        for m in self.groups[n].members[start.index()..].iter_mut().take(count) {
+            *m = true;
+        }
+    }
+
+    /// Removes a group.
+    ///
+    /// Returns whether the group existed.
+    pub fn remove_group(&mut self, name: &str) -> bool {
+        let count = self.groups.len();
+        self.groups.retain(|g| g.name != name);
+
+        self.groups.len() != count
+    }
+
+    /// Returns the names of all groups, in the order they were added.
+    #[inline]
+    pub fn groups(&self) -> impl Iterator<Item = &str> + '_ {
+        self.groups.iter().map(|g| g.name.as_str())
+    }
+
+    /// Returns the submaster level of a group.
+    #[inline]
+    pub fn level(&self, name: &str) -> Option<u8> {
+        self.groups.iter().find(|g| g.name == name).map(|g| g.level)
+    }
+
+    /// Sets the submaster level of a group.
+    ///
+    /// Returns whether the group exists.
+    pub fn set_level(&mut self, name: &str, level: u8) -> bool {
+        match self.groups.iter_mut().find(|g| g.name == name) {
+            Some(group) => {
+                group.level = level;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns a copy of `universe` with all masters applied.
+    ///
+    /// Fades in progress are not copied.
+    pub fn apply(&self, universe: &DmxUniverse) -> DmxUniverse {
+        let grandmaster = if self.blackout { 0 } else { self.grandmaster };
+
+        let mut output = DmxUniverse::new();
+        let channels = output.channels_mut();
+        channels.copy_from_slice(universe.channels());
+
+        for (i, v) in channels.iter_mut().enumerate() {
+            if self.mastered[i] {
+                *v = scale(*v, grandmaster);
+            }
+
+            for group in self.groups.iter().filter(|g| g.members[i]) {
+                *v = scale(*v, group.level);
+            }
+        }
+
+        output
+    }
+}
+
+/// Scales `value` by `level`, full leaving it unchanged.
+#[inline]
+fn scale(value: u8, level: u8) -> u8 {
+    ((u32::from(value) * u32::from(level) + 127) / 255) as u8
+}
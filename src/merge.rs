@@ -0,0 +1,338 @@
+//! Merging of several inputs.
+//!
+//! A `DmxMerger` combines the universes of any number of inputs, such as a
+//! console received through sACN, a backup desk on a serial port and local
+//! programming, into a single output universe. Every channel is merged using
+//! one of two policies:
+//!
+//! * *Highest takes precedence* (HTP), the highest value of all inputs is
+//!   output. Usually used for intensities.
+//! * *Latest takes precedence* (LTP), the value of the input that changed the
+//!   channel last is output. Usually used for positions, colors and other
+//!   attributes.
+//!
+//! ## Example
+//!
+//! ```
+//! use dmx::DmxAddress;
+//! use dmx::merge::{DmxMerger, MergeMode};
+//!
+//! let pan = DmxAddress::new(2).unwrap();
+//!
+//! let mut merger = DmxMerger::new();
+//! merger.set_mode(pan, MergeMode::Ltp);
+//!
+//! let console = merger.add_input();
+//! let backup = merger.add_input();
+//!
+//! merger.update(console, &[0x80, 0x10]);
+//! merger.update(backup, &[0x40, 0x20]);
+//! merger.update(console, &[0xff, 0x30]);
+//!
+//! // the dimmer is HTP, pan follows the last change
+//! assert_eq!(merger.output().channels()[..2], [0xff, 0x30]);
+//! ```
+
+use crate::address::DmxAddress;
+use crate::packet::MAX_CHANNELS;
+use crate::universe::DmxUniverse;
+
+/// Policy used to merge a channel.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MergeMode {
+    /// Highest takes precedence.
+    #[default]
+    Htp,
+    /// Latest takes precedence.
+    Ltp,
+}
+
+/// Identifies an input of a `DmxMerger`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InputId(usize);
+
+#[derive(Clone, Debug)]
+struct Input {
+    channels: [u8; MAX_CHANNELS],
+    // stamp of the last change of each channel, zero if never set
+    changed: [u64; MAX_CHANNELS],
+    active: bool,
+}
+
+/// Merges several inputs into one universe.
+///
+/// Channels default to HTP. Inputs only contribute once they have been
+/// updated and stop doing so when released, e.g. when a network source is
+/// lost. An input's first update counts as a change of all of its channels,
+/// so an input coming online takes over LTP channels.
+#[derive(Clone, Debug)]
+pub struct DmxMerger {
+    modes: [MergeMode; MAX_CHANNELS],
+    inputs: Vec<Option<Input>>,
+    output: DmxUniverse,
+    // incremented with every update, to order changes
+    stamp: u64,
+}
+
+impl Default for DmxMerger {
+    #[inline]
+    fn default() -> DmxMerger {
+        DmxMerger {
+            modes: [MergeMode::Htp; MAX_CHANNELS],
+            inputs: Vec::new(),
+            output: DmxUniverse::new(),
+            stamp: 0,
+        }
+    }
+}
+
+impl DmxMerger {
+    /// Create a merger without any inputs.
+    #[inline]
+    pub fn new() -> DmxMerger {
+        DmxMerger::default()
+    }
+
+    /// Returns the policy used to merge channel `n`.
+    #[inline]
+    pub fn mode(&self, n: DmxAddress) -> MergeMode {
+        self.modes[n.index()]
+    }
+
+    /// Sets the policy used to merge channel `n`.
+    #[inline]
+    pub fn set_mode(&mut self, n: DmxAddress, mode: MergeMode) {
+        self.modes[n.index()] = mode;
+        self.merge();
+    }
+
+    /// Sets the policy of `count` consecutive channels, starting at channel
+    /// `start`.
+    ///
+    /// Channels beyond 512 are ignored.
+    pub fn set_mode_range(&mut self, start: DmxAddress, count: usize, mode: MergeMode) {
+        for m in self.modes[start.index()..].iter_mut().take(count) {
+            *m = mode;
+        }
+        self.merge();
+    }
+
+    /// Adds an input.
+    ///
+    /// The input does not contribute to the output until it is updated.
+    pub fn add_input(&mut self) -> InputId {
+        let input = Input {
+            channels: [0; MAX_CHANNELS],
+            changed: [0; MAX_CHANNELS],
+            active: false,
+        };
+
+        // reuse the slot of a removed input
+        match self.inputs.iter().position(Option::is_none) {
+            Some(n) => {
+                self.inputs[n] = Some(input);
+                InputId(n)
+            }
+            None => {
+                self.inputs.push(Some(input));
+                InputId(self.inputs.len() - 1)
+            }
+        }
+    }
+
+    /// Removes an input.
+    ///
+    /// LTP channels it controlled fall back to the input that changed them
+    /// before. The id may be reused by inputs added later.
+    pub fn remove_input(&mut self, input: InputId) {
+        if let Some(slot) = self.inputs.get_mut(input.0) {
+            *slot = None;
+        }
+        self.merge();
+    }
+
+    /// Updates the channels of an input.
+    ///
+    /// Channels beyond the length of `channels` are set to zero. Does nothing
+    /// if the input has been removed.
+    pub fn update(&mut self, input: InputId, channels: &[u8]) {
+        self.stamp += 1;
+        let stamp = self.stamp;
+
+        let input = match self.inputs.get_mut(input.0) {
+            Some(Some(input)) => input,
+            _ => return,
+        };
+
+        let first = !input.active;
+        input.active = true;
+
+        for (i, (current, changed)) in input
+            .channels
+            .iter_mut()
+            .zip(input.changed.iter_mut())
+            .enumerate()
+        {
+            let value = channels.get(i).copied().unwrap_or(0);
+
+            if first || *current != value {
+                *current = value;
+                *changed = stamp;
+            }
+        }
+
+        self.merge();
+    }
+
+    /// Releases an input, which stops contributing to the output until it is
+    /// updated again.
+    pub fn release(&mut self, input: InputId) {
+        if let Some(Some(input)) = self.inputs.get_mut(input.0) {
+            input.active = false;
+        }
+        self.merge();
+    }
+
+    /// Returns the merged output.
+    #[inline]
+    pub fn output(&self) -> &DmxUniverse {
+        &self.output
+    }
+
+    fn merge(&mut self) {
+        let inputs: Vec<&Input> = self.inputs.iter().flatten().filter(|i| i.active).collect();
+
+        // without any inputs, the last output is held
+        if inputs.is_empty() {
+            return;
+        }
+
+        let channels = self.output.channels_mut().iter_mut().zip(&self.modes);
+
+        for (i, (out, mode)) in channels.enumerate() {
+            *out = match mode {
+                MergeMode::Htp => inputs.iter().map(|input| input.channels[i]).max(),
+                MergeMode::Ltp => inputs
+                    .iter()
+                    .max_by_key(|input| input.changed[i])
+                    .map(|input| input.channels[i]),
+            }
+            .unwrap_or(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(n: u16) -> DmxAddress {
+        DmxAddress::new(n).unwrap()
+    }
+
+    fn output(merger: &DmxMerger) -> &[u8] {
+        &merger.output().channels()[..3]
+    }
+
+    #[test]
+    fn htp_outputs_the_highest_value() {
+        let mut merger = DmxMerger::new();
+        let a = merger.add_input();
+        let b = merger.add_input();
+        let c = merger.add_input();
+
+        merger.update(a, &[0x10, 0xff, 0x00]);
+        merger.update(b, &[0x80, 0x20]);
+        merger.update(c, &[0x40, 0x00, 0x01]);
+        assert_eq!(output(&merger), [0x80, 0xff, 0x01]);
+
+        // lowering the highest value falls back to the next one
+        merger.update(b, &[0x00, 0x20]);
+        assert_eq!(output(&merger), [0x40, 0xff, 0x01]);
+    }
+
+    #[test]
+    fn ltp_outputs_the_latest_change() {
+        let mut merger = DmxMerger::new();
+        merger.set_mode_range(DmxAddress::MIN, 3, MergeMode::Ltp);
+        let a = merger.add_input();
+        let b = merger.add_input();
+
+        merger.update(a, &[0xff, 0xff, 0xff]);
+        merger.update(b, &[0x10, 0x20, 0x30]);
+        assert_eq!(output(&merger), [0x10, 0x20, 0x30]);
+
+        // only the channels that changed are taken over, resending the same
+        // values changes nothing
+        merger.update(a, &[0xff, 0x80, 0xff]);
+        assert_eq!(output(&merger), [0x10, 0x80, 0x30]);
+        merger.update(b, &[0x10, 0x20, 0x30]);
+        assert_eq!(output(&merger), [0x10, 0x80, 0x30]);
+
+        merger.update(b, &[0x10, 0x20, 0x00]);
+        assert_eq!(output(&merger), [0x10, 0x80, 0x00]);
+    }
+
+    #[test]
+    fn modes_apply_per_channel() {
+        let mut merger = DmxMerger::new();
+        merger.set_mode(addr(2), MergeMode::Ltp);
+        assert_eq!(merger.mode(addr(1)), MergeMode::Htp);
+        assert_eq!(merger.mode(addr(2)), MergeMode::Ltp);
+
+        let a = merger.add_input();
+        let b = merger.add_input();
+        merger.update(a, &[0xff, 0xff, 0xff]);
+        merger.update(b, &[0x10, 0x10, 0x10]);
+        assert_eq!(output(&merger), [0xff, 0x10, 0xff]);
+
+        // changing the mode merges again
+        merger.set_mode(addr(3), MergeMode::Ltp);
+        assert_eq!(output(&merger), [0xff, 0x10, 0x10]);
+        merger.set_mode(addr(2), MergeMode::Htp);
+        assert_eq!(output(&merger), [0xff, 0xff, 0x10]);
+    }
+
+    #[test]
+    fn removed_inputs_stop_contributing() {
+        let mut merger = DmxMerger::new();
+        merger.set_mode(addr(2), MergeMode::Ltp);
+        let a = merger.add_input();
+        let b = merger.add_input();
+
+        merger.update(a, &[0x10, 0x10]);
+        merger.update(b, &[0x80, 0x80]);
+        assert_eq!(output(&merger), [0x80, 0x80, 0]);
+
+        // the LTP channel falls back to the previous change
+        merger.remove_input(b);
+        assert_eq!(output(&merger), [0x10, 0x10, 0]);
+
+        // removed inputs ignore updates, their id is reused
+        merger.update(b, &[0xff, 0xff]);
+        assert_eq!(output(&merger), [0x10, 0x10, 0]);
+        assert_eq!(merger.add_input(), b);
+        assert_eq!(output(&merger), [0x10, 0x10, 0]);
+    }
+
+    #[test]
+    fn released_inputs_take_over_when_updated_again() {
+        let mut merger = DmxMerger::new();
+        merger.set_mode(addr(2), MergeMode::Ltp);
+        let a = merger.add_input();
+        let b = merger.add_input();
+
+        merger.update(a, &[0x10, 0x10]);
+        merger.update(b, &[0x80, 0x80]);
+        merger.release(b);
+        assert_eq!(output(&merger), [0x10, 0x10, 0]);
+
+        merger.update(b, &[0x80, 0x80]);
+        assert_eq!(output(&merger), [0x80, 0x80, 0]);
+
+        // the last output is held once nothing is left
+        merger.release(a);
+        merger.release(b);
+        assert_eq!(output(&merger), [0x80, 0x80, 0]);
+    }
+}
@@ -0,0 +1,284 @@
+//! MIDI control.
+//!
+//! Maps notes and control changes of MIDI controllers onto DMX channels.
+//! A `MidiMapping` holds the table of which note or controller sets which
+//! channel; a `MidiDmxBridge` connects it to a MIDI input port through
+//! [midir](https://docs.rs/midir) and writes into a shared universe whenever
+//! a message arrives.
+//!
+//! MIDI values range from 0 to 127 and are scaled to the full range of a
+//! channel. Notes set their channels to their velocity and back to zero
+//! once released. MIDI channels are numbered from 0 to 15 here, so channel
+//! 1 of a controller's display is 0.
+//!
+//! On Linux, midir requires the ALSA development files to build.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use dmx::{DmxAddress, DmxRefresher};
+//! use dmx::midi::{MidiDmxBridge, MidiMapping};
+//!
+//! let refresher = DmxRefresher::new(dmx::open_serial("/dev/ttyUSB0").unwrap());
+//!
+//! // faders 1 to 8 of a controller sending CC 0 to 7 on any channel
+//! let mut mapping = MidiMapping::new();
+//! for n in 0..8 {
+//!     mapping.map_control(None, n, DmxAddress::new(u16::from(n) + 1).unwrap());
+//! }
+//! // middle C flashes channel 9
+//! mapping.map_note(Some(0), 60, DmxAddress::new(9).unwrap());
+//!
+//! let _bridge = MidiDmxBridge::connect("nanoKONTROL", mapping, &refresher.handle()).unwrap();
+//! std::thread::park();
+//! ```
+
+use std::{fmt, io};
+
+use crate::address::DmxAddress;
+use crate::refresh::SharedUniverse;
+use crate::{Error, Result};
+
+// status bytes, without the channel
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xb0;
+
+/// Client name reported to the MIDI system.
+pub(crate) const CLIENT_NAME: &str = "dmx";
+
+/// A MIDI message that can be mapped onto a channel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MidiSource {
+    /// A note, set to its velocity while held.
+    Note {
+        /// MIDI channel from 0 to 15, or `None` for any channel.
+        channel: Option<u8>,
+        /// Note number, 60 being middle C.
+        note: u8,
+    },
+    /// A controller, set by control change messages.
+    Control {
+        /// MIDI channel from 0 to 15, or `None` for any channel.
+        channel: Option<u8>,
+        /// Controller number.
+        controller: u8,
+    },
+}
+
+impl MidiSource {
+    /// Returns whether `event` comes from this source.
+    fn matches(&self, event: &MidiSource) -> bool {
+        let same_channel = |mapped: Option<u8>, actual| mapped.is_none() || mapped == actual;
+
+        match (*self, *event) {
+            (
+                MidiSource::Note { channel, note },
+                MidiSource::Note {
+                    channel: actual,
+                    note: n,
+                },
+            ) => note == n && same_channel(channel, actual),
+            (
+                MidiSource::Control {
+                    channel,
+                    controller,
+                },
+                MidiSource::Control {
+                    channel: actual,
+                    controller: c,
+                },
+            ) => controller == c && same_channel(channel, actual),
+            _ => false,
+        }
+    }
+}
+
+/// Decodes a MIDI message into its source and DMX value.
+///
+/// Returns `None` for messages other than notes and control changes.
+///
+/// ```
+/// use dmx::midi::{self, MidiSource};
+///
+/// let source = MidiSource::Note { channel: Some(0), note: 60 };
+/// assert_eq!(midi::decode(&[0x90, 60, 127]), Some((source, 255)));
+///
+/// // note on with a velocity of zero releases the note
+/// assert_eq!(midi::decode(&[0x90, 60, 0]), Some((source, 0)));
+/// ```
+pub fn decode(message: &[u8]) -> Option<(MidiSource, u8)> {
+    let (status, data1, data2) = match *message {
+        [status, data1, data2, ..] => (status, data1 & 0x7f, data2 & 0x7f),
+        _ => return None,
+    };
+    let channel = Some(status & 0x0f);
+
+    match status & 0xf0 {
+        NOTE_OFF => Some((MidiSource::Note { channel, note: data1 }, 0)),
+        NOTE_ON => Some((MidiSource::Note { channel, note: data1 }, scale(data2))),
+        CONTROL_CHANGE => Some((
+            MidiSource::Control {
+                channel,
+                controller: data1,
+            },
+            scale(data2),
+        )),
+        _ => None,
+    }
+}
+
+/// Scales a 7-bit MIDI value to a channel value.
+#[inline]
+fn scale(value: u8) -> u8 {
+    ((u16::from(value) * 255 + 63) / 127) as u8
+}
+
+/// Table of MIDI sources and the channels they set.
+///
+/// A source may set several channels, and several sources the same channel.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MidiMapping {
+    entries: Vec<(MidiSource, DmxAddress)>,
+}
+
+impl MidiMapping {
+    /// Create an empty mapping.
+    #[inline]
+    pub fn new() -> MidiMapping {
+        MidiMapping::default()
+    }
+
+    /// Maps a source onto a channel.
+    #[inline]
+    pub fn map(&mut self, source: MidiSource, address: DmxAddress) {
+        self.entries.push((source, address));
+    }
+
+    /// Maps a note onto a channel.
+    #[inline]
+    pub fn map_note(&mut self, channel: Option<u8>, note: u8, address: DmxAddress) {
+        self.map(MidiSource::Note { channel, note }, address)
+    }
+
+    /// Maps a controller onto a channel.
+    #[inline]
+    pub fn map_control(&mut self, channel: Option<u8>, controller: u8, address: DmxAddress) {
+        self.map(
+            MidiSource::Control {
+                channel,
+                controller,
+            },
+            address,
+        )
+    }
+
+    /// Returns all entries, in the order they were added.
+    #[inline]
+    pub fn entries(&self) -> &[(MidiSource, DmxAddress)] {
+        &self.entries
+    }
+
+    /// Applies a MIDI message to `universe`, returning whether any channel
+    /// is mapped to it.
+    pub fn apply(&self, message: &[u8], universe: &SharedUniverse) -> bool {
+        let (event, value) = match decode(message) {
+            Some(decoded) => decoded,
+            None => return false,
+        };
+
+        if !self.entries.iter().any(|(source, _)| source.matches(&event)) {
+            return false;
+        }
+
+        universe.update(|u| {
+            for (source, address) in &self.entries {
+                if source.matches(&event) {
+                    u.set(*address, value);
+                }
+            }
+        });
+        true
+    }
+}
+
+pub(crate) fn midi_error<E: fmt::Display>(e: E) -> Error {
+    Error::Io(io::Error::other(e.to_string()))
+}
+
+/// Connection from a MIDI input port to a universe.
+///
+/// Messages are applied from a thread of the MIDI backend as they arrive.
+/// Dropping the bridge closes the connection.
+pub struct MidiDmxBridge {
+    connection: midir::MidiInputConnection<()>,
+    port: String,
+}
+
+impl MidiDmxBridge {
+    /// Returns the names of all MIDI input ports.
+    pub fn ports() -> Result<Vec<String>> {
+        let input = midir::MidiInput::new(CLIENT_NAME).map_err(midi_error)?;
+
+        input
+            .ports()
+            .iter()
+            .map(|port| input.port_name(port).map_err(midi_error))
+            .collect()
+    }
+
+    /// Connects to the first input port whose name contains `port`.
+    ///
+    /// Fails with `Error::InvalidParameter` if there is no such port.
+    pub fn connect(
+        port: &str,
+        mapping: MidiMapping,
+        universe: &SharedUniverse,
+    ) -> Result<MidiDmxBridge> {
+        let input = midir::MidiInput::new(CLIENT_NAME).map_err(midi_error)?;
+
+        let (found, name) = input
+            .ports()
+            .into_iter()
+            .filter_map(|p| input.port_name(&p).ok().map(|name| (p, name)))
+            .find(|(_, name)| name.contains(port))
+            .ok_or(Error::InvalidParameter("no such MIDI input port"))?;
+
+        let universe = universe.clone();
+        let connection = input
+            .connect(
+                &found,
+                CLIENT_NAME,
+                move |_, message, _| {
+                    mapping.apply(message, &universe);
+                },
+                (),
+            )
+            .map_err(|e| midi_error(e.kind()))?;
+
+        Ok(MidiDmxBridge {
+            connection,
+            port: name,
+        })
+    }
+
+    /// Returns the name of the connected port.
+    #[inline]
+    pub fn port(&self) -> &str {
+        &self.port
+    }
+
+    /// Closes the connection.
+    #[inline]
+    pub fn close(self) {
+        self.connection.close();
+    }
+}
+
+impl fmt::Debug for MidiDmxBridge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MidiDmxBridge")
+            .field("port", &self.port)
+            .finish()
+    }
+}
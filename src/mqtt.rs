@@ -0,0 +1,794 @@
+//! MQTT control.
+//!
+//! An `MqttDmxBridge` connects to an MQTT broker, such as the one of a Home
+//! Assistant installation, and maps topics below a prefix, `dmx` by default,
+//! onto shared universes and scenes:
+//!
+//! ```text
+//! dmx/universe/{n}/channel/{c}        sets channel c of universe n, e.g. to "255"
+//! dmx/universe/{n}/channel/{c}/state  current value, published by the bridge
+//! dmx/scene/activate                  activates the scene named in the payload
+//! dmx/scene/active                    last activated scene, published by the bridge
+//! dmx/status                          "online", or "offline" once disconnected
+//! ```
+//!
+//! Everything the bridge publishes is retained by the broker, so clients
+//! connecting later see the current state right away. Changes are published
+//! no matter how they were made, e.g. through a `Playback` writing into the
+//! universe. Commands may be retained as well; the broker then hands them to
+//! the bridge again when it reconnects, restoring the last values set.
+//!
+//! MQTT 3.1.1 is spoken without TLS. The bridge subscribes and publishes at
+//! QoS 0; messages the broker delivers at QoS 1 or 2 regardless are
+//! acknowledged as their level requires, and applied as soon as they arrive.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use dmx::DmxRefresher;
+//! use dmx::mqtt::MqttDmxBridge;
+//!
+//! let refresher = DmxRefresher::new(dmx::open_serial("/dev/ttyUSB0").unwrap());
+//!
+//! let mut bridge = MqttDmxBridge::builder("living-room-dmx")
+//!     .credentials("dmx", "secret")
+//!     .connect(("homeassistant.local", dmx::mqtt::MQTT_PORT))
+//!     .unwrap();
+//! bridge.add_universe(1, &refresher.handle()).unwrap();
+//!
+//! bridge.run().unwrap();
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::{fmt, str, time};
+
+use crate::address::DmxAddress;
+use crate::packet::MAX_CHANNELS;
+use crate::refresh::SharedUniverse;
+use crate::scenes::Scene;
+use crate::{Error, Result};
+
+/// TCP port of MQTT brokers.
+pub const MQTT_PORT: u16 = 1883;
+
+/// Prefix of all topics, unless set otherwise.
+pub const DEFAULT_PREFIX: &str = "dmx";
+
+/// Duration to wait for the broker to accept the connection.
+const REPLY_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+/// Interval at which universes are checked for changes.
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
+// largest packet accepted from the broker
+const MAX_PACKET_LEN: usize = 65_536;
+
+// packet types
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const PUBACK: u8 = 4;
+const PUBREC: u8 = 5;
+const PUBREL: u8 = 6;
+const PUBCOMP: u8 = 7;
+const SUBSCRIBE: u8 = 8;
+const SUBACK: u8 = 9;
+const PINGREQ: u8 = 12;
+const DISCONNECT: u8 = 14;
+
+// connect flags
+const USER_NAME: u8 = 0x80;
+const PASSWORD: u8 = 0x40;
+const WILL_RETAIN: u8 = 0x20;
+const WILL: u8 = 0x04;
+const CLEAN_SESSION: u8 = 0x02;
+
+/// Builder for an `MqttDmxBridge`.
+#[derive(Clone)]
+pub struct MqttDmxBridgeBuilder {
+    client_id: String,
+    prefix: String,
+    credentials: Option<(String, String)>,
+    keep_alive: time::Duration,
+}
+
+impl MqttDmxBridgeBuilder {
+    /// Sets the prefix of all topics.
+    #[inline]
+    pub fn prefix(mut self, prefix: &str) -> MqttDmxBridgeBuilder {
+        self.prefix = prefix.trim_end_matches('/').to_owned();
+        self
+    }
+
+    /// Sets the user name and password to log in with.
+    #[inline]
+    pub fn credentials(mut self, user: &str, password: &str) -> MqttDmxBridgeBuilder {
+        self.credentials = Some((user.to_owned(), password.to_owned()));
+        self
+    }
+
+    /// Sets the keep alive interval, 30 seconds by default.
+    ///
+    /// The broker considers the bridge gone, and publishes its status as
+    /// offline, if it does not hear from it for one and a half intervals.
+    /// Zero disables keep alive.
+    #[inline]
+    pub fn keep_alive(mut self, keep_alive: time::Duration) -> MqttDmxBridgeBuilder {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Connects to the broker at `addr`.
+    ///
+    /// Fails with `Error::InvalidResponse` if the broker refuses the
+    /// connection.
+    pub fn connect<A: ToSocketAddrs>(self, addr: A) -> Result<MqttDmxBridge> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+
+        let keep_alive = self.keep_alive.as_secs().min(u64::from(u16::MAX)) as u16;
+        let mut conn = Connection {
+            stream,
+            buf: Vec::new(),
+            last_sent: time::Instant::now(),
+            next_id: 1,
+        };
+        let status = format!("{}/status", self.prefix);
+
+        let credentials = self.credentials.as_ref();
+        let body = encode_connect(&self.client_id, keep_alive, &status, credentials);
+        conn.send(CONNECT << 4, &body)?;
+
+        let (header, body) = loop {
+            if let Some(packet) = conn.next_packet()? {
+                break packet;
+            }
+            conn.fill()?;
+        };
+        if header >> 4 != CONNACK || body.len() != 2 {
+            return Err(Error::InvalidResponse("expected MQTT CONNACK"));
+        }
+        match body[1] {
+            0 => (),
+            1 => return Err(Error::InvalidResponse("MQTT protocol version not supported")),
+            2 => return Err(Error::InvalidResponse("MQTT client identifier rejected")),
+            4 => return Err(Error::InvalidResponse("bad MQTT user name or password")),
+            5 => return Err(Error::InvalidResponse("not authorized by MQTT broker")),
+            _ => return Err(Error::InvalidResponse("MQTT broker refused connection")),
+        }
+
+        conn.stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        conn.publish(&status, b"online")?;
+        conn.subscribe(&format!("{}/scene/activate", self.prefix))?;
+
+        Ok(MqttDmxBridge {
+            conn,
+            prefix: self.prefix,
+            keep_alive: time::Duration::from_secs(u64::from(keep_alive)),
+            universes: BTreeMap::new(),
+            scenes: BTreeMap::new(),
+            active_scene: None,
+        })
+    }
+}
+
+impl fmt::Debug for MqttDmxBridgeBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // leaves out the password
+        f.debug_struct("MqttDmxBridgeBuilder")
+            .field("client_id", &self.client_id)
+            .field("prefix", &self.prefix)
+            .field("user", &self.credentials.as_ref().map(|(user, _)| user))
+            .field("keep_alive", &self.keep_alive)
+            .finish()
+    }
+}
+
+/// A connection to an MQTT broker controlling shared universes.
+///
+/// Created through `MqttDmxBridge::builder`. Messages are only handled, and
+/// changes only published, while `poll` or `run` is being called.
+pub struct MqttDmxBridge {
+    conn: Connection,
+    prefix: String,
+    keep_alive: time::Duration,
+    universes: BTreeMap<u32, Output>,
+    scenes: BTreeMap<String, (SharedUniverse, Scene)>,
+    // name, and whether it was published yet
+    active_scene: Option<(String, bool)>,
+}
+
+struct Output {
+    universe: SharedUniverse,
+    // channel values last published, if any
+    published: Option<[u8; MAX_CHANNELS]>,
+}
+
+impl MqttDmxBridge {
+    /// Create a builder for a bridge identifying itself as `client_id`.
+    pub fn builder(client_id: &str) -> MqttDmxBridgeBuilder {
+        MqttDmxBridgeBuilder {
+            client_id: client_id.to_owned(),
+            prefix: DEFAULT_PREFIX.to_owned(),
+            credentials: None,
+            keep_alive: time::Duration::from_secs(30),
+        }
+    }
+
+    /// Returns the prefix of all topics.
+    #[inline]
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Makes `universe` available as universe `n`.
+    ///
+    /// Subscribes to its channels and publishes all of their values with the
+    /// next call to `poll`. Replaces a universe added with the same number
+    /// before.
+    pub fn add_universe(&mut self, n: u32, universe: &SharedUniverse) -> Result<()> {
+        self.conn
+            .subscribe(&format!("{}/universe/{}/channel/+", self.prefix, n))?;
+
+        self.universes.insert(
+            n,
+            Output {
+                universe: universe.clone(),
+                published: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Adds a scene, activated by setting it on `universe`.
+    ///
+    /// Replaces a scene added with the same name before.
+    pub fn add_scene(&mut self, name: &str, universe: &SharedUniverse, scene: Scene) {
+        self.scenes
+            .insert(name.to_owned(), (universe.clone(), scene));
+    }
+
+    /// Applies a message, returning whether it was a valid command.
+    ///
+    /// Channel values are decimal numbers from 0 to 255, scenes are
+    /// activated by name.
+    pub fn apply(&mut self, topic: &str, payload: &[u8]) -> bool {
+        let path = match topic
+            .strip_prefix(self.prefix.as_str())
+            .and_then(|path| path.strip_prefix('/'))
+        {
+            Some(path) => path,
+            None => return false,
+        };
+        let payload = match str::from_utf8(payload) {
+            Ok(payload) => payload.trim(),
+            Err(_) => return false,
+        };
+
+        let segments: Vec<&str> = path.split('/').collect();
+        match segments[..] {
+            ["universe", n, "channel", c] => {
+                let output = n.parse().ok().and_then(|n| self.universes.get(&n));
+                let channel = c.parse().ok().and_then(DmxAddress::new);
+
+                match (output, channel, payload.parse::<u8>()) {
+                    (Some(output), Some(channel), Ok(value)) => {
+                        output.universe.set_channel(channel, value);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            ["scene", "activate"] => match self.scenes.get(payload) {
+                Some((universe, scene)) => {
+                    universe.set_channels(DmxAddress::MIN, scene.universe().channels());
+                    self.active_scene = Some((payload.to_owned(), false));
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Handles messages arriving within a short interval, then publishes
+    /// changes.
+    ///
+    /// Also keeps the connection alive, so it needs to be called regularly.
+    pub fn poll(&mut self) -> Result<()> {
+        match self.conn.fill() {
+            Ok(()) | Err(Error::Timeout) => (),
+            Err(e) => return Err(e),
+        }
+
+        while let Some((header, body)) = self.conn.next_packet()? {
+            self.handle(header, &body)?;
+        }
+
+        self.publish_changes()?;
+
+        if !self.keep_alive.is_zero() && self.conn.last_sent.elapsed() >= self.keep_alive / 2 {
+            self.conn.send(PINGREQ << 4, &[])?;
+        }
+
+        Ok(())
+    }
+
+    /// Handles messages and publishes changes.
+    ///
+    /// Only returns if the connection fails.
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            self.poll()?;
+        }
+    }
+
+    /// Publishes the status as offline and disconnects.
+    pub fn disconnect(mut self) -> Result<()> {
+        let status = format!("{}/status", self.prefix);
+        self.conn.publish(&status, b"offline")?;
+        self.conn.send(DISCONNECT << 4, &[])
+    }
+
+    fn handle(&mut self, header: u8, body: &[u8]) -> Result<()> {
+        match header >> 4 {
+            PUBLISH => {
+                let publish = decode_publish(header, body)?;
+
+                // messages are only delivered with QoS 1 or 2 if the broker
+                // ignored the QoS of the subscription. applying a command
+                // twice does no harm, so QoS 2 messages are applied right
+                // away instead of once released
+                match publish.id {
+                    Some(id) if publish.qos == 1 => self.conn.send(PUBACK << 4, &id.to_be_bytes())?,
+                    Some(id) => self.conn.send(PUBREC << 4, &id.to_be_bytes())?,
+                    None => (),
+                }
+
+                self.apply(publish.topic, publish.payload);
+            }
+            PUBREL => {
+                let id = body
+                    .get(..2)
+                    .ok_or(Error::InvalidResponse("malformed MQTT PUBREL"))?;
+                self.conn.send(PUBCOMP << 4, id)?;
+            }
+            SUBACK if body.get(2..).is_some_and(|codes| codes.contains(&0x80)) => {
+                return Err(Error::InvalidResponse("MQTT subscription rejected"));
+            }
+            // acknowledgements and ping responses
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn publish_changes(&mut self) -> Result<()> {
+        for (n, output) in &mut self.universes {
+            let channels = output.universe.snapshot();
+
+            for (i, &value) in channels.iter().enumerate() {
+                if output.published.as_ref().is_none_or(|p| p[i] != value) {
+                    let topic = format!("{}/universe/{}/channel/{}/state", self.prefix, n, i + 1);
+                    self.conn.publish(&topic, value.to_string().as_bytes())?;
+                }
+            }
+            output.published = Some(channels);
+        }
+
+        if let Some((ref name, ref mut published)) = self.active_scene {
+            if !*published {
+                let topic = format!("{}/scene/active", self.prefix);
+                self.conn.publish(&topic, name.as_bytes())?;
+                *published = true;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for MqttDmxBridge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MqttDmxBridge")
+            .field("stream", &self.conn.stream)
+            .field("prefix", &self.prefix)
+            .field("universes", &self.universes.keys())
+            .field("scenes", &self.scenes.keys())
+            .finish()
+    }
+}
+
+struct Connection {
+    stream: TcpStream,
+    // received data not yet handled
+    buf: Vec<u8>,
+    last_sent: time::Instant,
+    next_id: u16,
+}
+
+impl Connection {
+    fn send(&mut self, header: u8, body: &[u8]) -> Result<()> {
+        self.stream.write_all(&encode_packet(header, body))?;
+        self.last_sent = time::Instant::now();
+        Ok(())
+    }
+
+    /// Publishes a retained message.
+    fn publish(&mut self, topic: &str, payload: &[u8]) -> Result<()> {
+        let mut body = Vec::with_capacity(topic.len() + payload.len() + 2);
+        put_bytes(&mut body, topic.as_bytes());
+        body.extend_from_slice(payload);
+
+        // retain flag
+        self.send(PUBLISH << 4 | 0x01, &body)
+    }
+
+    fn subscribe(&mut self, filter: &str) -> Result<()> {
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+
+        self.send(SUBSCRIBE << 4 | 0x02, &encode_subscribe(id, filter))
+    }
+
+    /// Reads whatever data is available, waiting up to the read timeout.
+    fn fill(&mut self) -> Result<()> {
+        let mut chunk = [0; 4096];
+
+        match self.stream.read(&mut chunk) {
+            Ok(0) => Err(Error::Io(io::ErrorKind::UnexpectedEof.into())),
+            Ok(len) => {
+                self.buf.extend_from_slice(&chunk[..len]);
+                Ok(())
+            }
+            // read timeouts show as either, depending on the platform
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                Err(Error::Timeout)
+            }
+            Err(e) => Err(Error::Io(e)),
+        }
+    }
+
+    /// Removes the first complete packet from the buffer, returning its
+    /// first byte and its body.
+    fn next_packet(&mut self) -> Result<Option<(u8, Vec<u8>)>> {
+        let (start, len) = match decode_length(&self.buf)? {
+            Some(body) => body,
+            None => return Ok(None),
+        };
+        if self.buf.len() < start + len {
+            return Ok(None);
+        }
+
+        let header = self.buf[0];
+        let body = self.buf[start..(start + len)].to_vec();
+        self.buf.drain(..(start + len));
+        Ok(Some((header, body)))
+    }
+}
+
+/// Encodes a packet with the first byte `header`.
+fn encode_packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(body.len() + 5);
+    packet.push(header);
+
+    // remaining length, seven bits at a time
+    let mut len = body.len();
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            packet.push(byte);
+            break;
+        }
+        packet.push(byte | 0x80);
+    }
+    packet.extend_from_slice(body);
+
+    packet
+}
+
+/// Decodes the remaining length of the packet at the start of `buf`.
+///
+/// Returns the offset and length of the body, or `None` if the length is
+/// incomplete; the body itself may not have arrived yet.
+fn decode_length(buf: &[u8]) -> Result<Option<(usize, usize)>> {
+    let mut len = 0;
+
+    for i in 0..4 {
+        let byte = match buf.get(1 + i) {
+            Some(&byte) => byte,
+            None => return Ok(None),
+        };
+        len |= usize::from(byte & 0x7f) << (7 * i);
+
+        if byte & 0x80 == 0 {
+            if len > MAX_PACKET_LEN {
+                return Err(Error::InvalidResponse("MQTT packet too large"));
+            }
+
+            return Ok(Some((2 + i, len)));
+        }
+    }
+
+    Err(Error::InvalidResponse("malformed MQTT packet length"))
+}
+
+/// Encodes the body of a CONNECT packet, with a retained will publishing
+/// `offline` to `status`.
+fn encode_connect(
+    client_id: &str,
+    keep_alive: u16,
+    status: &str,
+    credentials: Option<&(String, String)>,
+) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_bytes(&mut body, b"MQTT");
+    // protocol level of 3.1.1
+    body.push(4);
+    let mut flags = CLEAN_SESSION | WILL | WILL_RETAIN;
+    if credentials.is_some() {
+        flags |= USER_NAME | PASSWORD;
+    }
+    body.push(flags);
+    body.extend_from_slice(&keep_alive.to_be_bytes());
+    put_bytes(&mut body, client_id.as_bytes());
+    put_bytes(&mut body, status.as_bytes());
+    put_bytes(&mut body, b"offline");
+    if let Some((user, password)) = credentials {
+        put_bytes(&mut body, user.as_bytes());
+        put_bytes(&mut body, password.as_bytes());
+    }
+
+    body
+}
+
+/// Encodes the body of a SUBSCRIBE packet for a single filter.
+fn encode_subscribe(id: u16, filter: &str) -> Vec<u8> {
+    let mut body = id.to_be_bytes().to_vec();
+    put_bytes(&mut body, filter.as_bytes());
+    // maximum QoS
+    body.push(0);
+
+    body
+}
+
+/// A received PUBLISH packet.
+#[derive(Debug, PartialEq, Eq)]
+struct Publish<'a> {
+    topic: &'a str,
+    qos: u8,
+    // packet identifier, present at QoS 1 and 2
+    id: Option<u16>,
+    payload: &'a [u8],
+}
+
+/// Decodes the body of a PUBLISH packet with the first byte `header`.
+fn decode_publish(header: u8, body: &[u8]) -> Result<Publish<'_>> {
+    let malformed = || Error::InvalidResponse("malformed MQTT PUBLISH");
+
+    let qos = (header >> 1) & 0x03;
+    if qos > 2 {
+        return Err(malformed());
+    }
+
+    let len = match *body {
+        [high, low, ..] => usize::from(u16::from_be_bytes([high, low])),
+        _ => return Err(malformed()),
+    };
+    let topic = body
+        .get(2..2 + len)
+        .and_then(|topic| str::from_utf8(topic).ok())
+        .ok_or_else(malformed)?;
+    let mut payload = &body[2 + len..];
+
+    let id = if qos > 0 {
+        let id = payload.get(..2).ok_or_else(malformed)?;
+        let id = u16::from_be_bytes([id[0], id[1]]);
+        payload = &payload[2..];
+        Some(id)
+    } else {
+        None
+    };
+
+    Ok(Publish {
+        topic,
+        qos,
+        id,
+        payload,
+    })
+}
+
+/// Appends `bytes` with their length, as MQTT encodes strings.
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remaining_length_boundaries() {
+        let cases: [(usize, &[u8]); 6] = [
+            (0, &[0x00]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (16_383, &[0xff, 0x7f]),
+            (16_384, &[0x80, 0x80, 0x01]),
+            (65_536, &[0x80, 0x80, 0x04]),
+        ];
+
+        for &(len, encoded) in &cases {
+            let body = vec![0x55; len];
+            let packet = encode_packet(PUBLISH << 4, &body);
+
+            assert_eq!(packet[0], 0x30);
+            assert_eq!(packet[1..(1 + encoded.len())], *encoded, "{}", len);
+            assert_eq!(packet.len(), 1 + encoded.len() + len);
+            assert_eq!(decode_length(&packet).unwrap(), Some((1 + encoded.len(), len)));
+        }
+    }
+
+    #[test]
+    fn incomplete_and_invalid_lengths() {
+        assert_eq!(decode_length(&[0x30]).unwrap(), None);
+        assert_eq!(decode_length(&[0x30, 0x80]).unwrap(), None);
+        // the body is not needed to decode the length
+        assert_eq!(decode_length(&[0x30, 0x05]).unwrap(), Some((2, 5)));
+
+        assert!(decode_length(&[0x30, 0x80, 0x80, 0x80, 0x80, 0x01]).is_err());
+        assert!(decode_length(&[0x30, 0x81, 0x80, 0x04]).is_err());
+    }
+
+    #[test]
+    fn connect_layout() {
+        let credentials = ("dmx".to_owned(), "pw".to_owned());
+        let body = encode_connect("id", 30, "dmx/status", Some(&credentials));
+
+        let mut expected = vec![0x00, 0x04];
+        expected.extend_from_slice(b"MQTT");
+        // protocol level, flags, keep alive
+        expected.extend_from_slice(&[0x04, 0xe6, 0x00, 0x1e]);
+        // client ID, will topic and message, user name, password
+        expected.extend_from_slice(b"\x00\x02id");
+        expected.extend_from_slice(b"\x00\x0admx/status");
+        expected.extend_from_slice(b"\x00\x07offline");
+        expected.extend_from_slice(b"\x00\x03dmx");
+        expected.extend_from_slice(b"\x00\x02pw");
+        assert_eq!(body, expected);
+
+        let body = encode_connect("id", 0, "dmx/status", None);
+        assert_eq!(body[7..10], [0x26, 0x00, 0x00]);
+        assert_eq!(body.len(), 10 + 4 + 12 + 9);
+    }
+
+    #[test]
+    fn subscribe_layout() {
+        let body = encode_subscribe(0x0102, "dmx/scene/activate");
+
+        let mut expected = vec![0x01, 0x02, 0x00, 0x12];
+        expected.extend_from_slice(b"dmx/scene/activate");
+        expected.push(0x00);
+        assert_eq!(body, expected);
+
+        let packet = encode_packet(SUBSCRIBE << 4 | 0x02, &body);
+        assert_eq!(packet[..2], [0x82, 23]);
+    }
+
+    #[test]
+    fn publish_without_packet_id() {
+        let body = b"\x00\x18dmx/universe/1/channel/5255";
+        let publish = decode_publish(PUBLISH << 4 | 0x01, body).unwrap();
+
+        assert_eq!(
+            publish,
+            Publish {
+                topic: "dmx/universe/1/channel/5",
+                qos: 0,
+                id: None,
+                payload: b"255",
+            }
+        );
+    }
+
+    #[test]
+    fn publish_with_packet_id() {
+        let body = b"\x00\x03a/b\x12\x34on";
+
+        for &qos in &[1u8, 2] {
+            let publish = decode_publish(PUBLISH << 4 | qos << 1, body).unwrap();
+            assert_eq!(publish.topic, "a/b");
+            assert_eq!(publish.qos, qos);
+            assert_eq!(publish.id, Some(0x1234));
+            assert_eq!(publish.payload, b"on");
+        }
+
+        // the packet ID is missing
+        assert!(decode_publish(PUBLISH << 4 | 0x02, b"\x00\x03a/b\x12").is_err());
+        // QoS 3 is reserved
+        assert!(decode_publish(PUBLISH << 4 | 0x06, body).is_err());
+    }
+
+    #[test]
+    fn truncated_topics_are_rejected() {
+        assert!(decode_publish(PUBLISH << 4, b"").is_err());
+        assert!(decode_publish(PUBLISH << 4, b"\x00").is_err());
+        assert!(decode_publish(PUBLISH << 4, b"\x00\x05a/b").is_err());
+        // topics must be UTF-8
+        assert!(decode_publish(PUBLISH << 4, b"\x00\x02\xff\xfe").is_err());
+        // an empty payload is fine
+        assert_eq!(decode_publish(PUBLISH << 4, b"\x00\x03a/b").unwrap().payload, b"");
+    }
+
+    // reads the next packet from a client, returning its first byte and body
+    fn read_packet(stream: &mut TcpStream, buf: &mut Vec<u8>) -> (u8, Vec<u8>) {
+        loop {
+            if let Some((start, len)) = decode_length(buf).unwrap() {
+                if buf.len() >= start + len {
+                    let packet = (buf[0], buf[start..(start + len)].to_vec());
+                    buf.drain(..(start + len));
+                    return packet;
+                }
+            }
+
+            let mut chunk = [0; 1024];
+            let len = stream.read(&mut chunk).unwrap();
+            assert!(len > 0, "connection closed");
+            buf.extend_from_slice(&chunk[..len]);
+        }
+    }
+
+    #[test]
+    fn messages_are_acknowledged_by_qos() {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let broker = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.set_read_timeout(Some(REPLY_TIMEOUT)).unwrap();
+            let mut buf = Vec::new();
+
+            assert_eq!(read_packet(&mut stream, &mut buf).0, CONNECT << 4);
+            stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+
+            // QoS 1, then QoS 2 followed by its release
+            let mut publish = b"\x00\x03a/b\x00\x01".to_vec();
+            stream.write_all(&encode_packet(PUBLISH << 4 | 0x02, &publish)).unwrap();
+            publish[6] = 0x02;
+            stream.write_all(&encode_packet(PUBLISH << 4 | 0x04, &publish)).unwrap();
+            stream.write_all(&[0x62, 0x02, 0x00, 0x02]).unwrap();
+
+            let mut replies = Vec::new();
+            while replies.last().is_none_or(|&(header, _)| header >> 4 != PUBCOMP) {
+                let (header, body) = read_packet(&mut stream, &mut buf);
+                // leaves out its own publications and subscriptions
+                if header >> 4 != PUBLISH && header >> 4 != SUBSCRIBE {
+                    replies.push((header, body));
+                }
+            }
+            // kept open until the bridge is done polling
+            (replies, stream)
+        });
+
+        let builder = MqttDmxBridge::builder("test").keep_alive(time::Duration::ZERO);
+        let mut bridge = builder.connect(addr).unwrap();
+        while !broker.is_finished() {
+            bridge.poll().unwrap();
+        }
+
+        let (replies, _stream) = broker.join().unwrap();
+        let expected = [
+            (PUBACK << 4, vec![0x00, 0x01]),
+            (PUBREC << 4, vec![0x00, 0x02]),
+            (PUBCOMP << 4, vec![0x00, 0x02]),
+        ];
+        assert_eq!(replies, expected);
+    }
+}
@@ -0,0 +1,99 @@
+//! Socket options of network senders.
+
+use std::io;
+use std::net::{Ipv4Addr, UdpSocket};
+
+/// Socket options of network senders.
+///
+/// On machines with several network interfaces, such as a show machine
+/// with its lighting network on a separate VLAN, the operating system picks
+/// the interface packets leave through from its routing table, which may
+/// not be the one the fixtures are on. Setting `interface` binds the socket
+/// of a sender to the IPv4 address of the right one instead.
+///
+/// Unicast and directed broadcast packets then leave through that
+/// interface. On Unix, multicast packets do as well; elsewhere, the routing
+/// table still decides. The limited broadcast address `255.255.255.255` is
+/// always sent through the default interface, so broadcasting senders should
+/// target the directed broadcast address of the network instead, e.g.
+/// `10.255.255.255`.
+///
+/// ## Example
+///
+/// ```no_run
+/// use dmx::SocketOptions;
+/// use dmx::sacn::{self, SacnSource};
+///
+/// let options = SocketOptions {
+///     interface: Some([10, 0, 0, 1].into()),
+///     multicast_ttl: Some(4),
+///     ..SocketOptions::default()
+/// };
+///
+/// let cid = sacn::generate_cid();
+/// let mut source = SacnSource::with_options("dmx-rs example", cid, &options).unwrap();
+/// source.send(1, &[0xff; 512]).unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// Address of the interface to send from, `None` to let the operating
+    /// system choose.
+    pub interface: Option<Ipv4Addr>,
+    /// Time to live of multicast packets, `None` for the system default,
+    /// usually 1, which keeps packets within the local network.
+    pub multicast_ttl: Option<u32>,
+    /// Whether multicast packets are looped back to receivers on this host,
+    /// `None` for the system default, usually enabled.
+    pub multicast_loop: Option<bool>,
+}
+
+impl SocketOptions {
+    /// Binds a socket to an ephemeral port, with the options applied.
+    pub(crate) fn bind(&self) -> io::Result<UdpSocket> {
+        let socket = UdpSocket::bind((self.interface.unwrap_or(Ipv4Addr::UNSPECIFIED), 0))?;
+        socket.set_broadcast(true)?;
+
+        if let Some(interface) = self.interface {
+            set_multicast_interface(&socket, interface)?;
+        }
+        if let Some(ttl) = self.multicast_ttl {
+            socket.set_multicast_ttl_v4(ttl)?;
+        }
+        if let Some(enabled) = self.multicast_loop {
+            socket.set_multicast_loop_v4(enabled)?;
+        }
+
+        Ok(socket)
+    }
+}
+
+#[cfg(unix)]
+fn set_multicast_interface(socket: &UdpSocket, interface: Ipv4Addr) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    use std::{mem, ptr};
+
+    let addr = ::libc::in_addr {
+        s_addr: u32::from(interface).to_be(),
+    };
+
+    // std offers no way to set IP_MULTICAST_IF
+    let rv = unsafe {
+        ::libc::setsockopt(
+            socket.as_raw_fd(),
+            ::libc::IPPROTO_IP,
+            ::libc::IP_MULTICAST_IF,
+            ptr::addr_of!(addr).cast(),
+            mem::size_of::<::libc::in_addr>() as ::libc::socklen_t,
+        )
+    };
+
+    if rv < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_multicast_interface(_socket: &UdpSocket, _interface: Ipv4Addr) -> io::Result<()> {
+    Ok(())
+}
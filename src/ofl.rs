@@ -0,0 +1,231 @@
+//! Open Fixture Library import.
+//!
+//! The [Open Fixture Library](https://open-fixture-library.org/) collects
+//! community-maintained fixture definitions as JSON files, one per fixture,
+//! in a directory per manufacturer. `open` reads such a file into a
+//! `FixtureProfile`, with the function and default value of each channel in
+//! each mode.
+//!
+//! Functions are taken from the type of a channel's capability, or of its
+//! first capability if it has several, refined by the channel's name for
+//! wheels. Fine channels of intensity, pan and tilt map onto their fine
+//! attributes. Channels without an equivalent `Attribute`, including fine
+//! channels of other functions, are kept as `Attribute::Other`, named after
+//! the channel. Matrix fixtures, whose modes refer to channels per pixel, are
+//! not supported.
+//!
+//! ## Example
+//!
+//! ```
+//! use dmx::fixture::Attribute;
+//!
+//! let definition = r#"{
+//!   "name": "Spot 60",
+//!   "availableChannels": {
+//!     "Dimmer": {
+//!       "fineChannelAliases": ["Dimmer fine"],
+//!       "capability": { "type": "Intensity" }
+//!     },
+//!     "Pan": { "defaultValue": "50%", "capability": { "type": "Pan" } },
+//!     "Gobo Wheel": {
+//!       "capabilities": [
+//!         { "dmxRange": [0, 127], "type": "WheelSlot", "slotNumber": 1 },
+//!         { "dmxRange": [128, 255], "type": "WheelShake", "slotNumber": 2 }
+//!       ]
+//!     }
+//!   },
+//!   "modes": [
+//!     { "name": "4-channel", "channels": ["Dimmer", "Dimmer fine", "Pan", "Gobo Wheel"] }
+//!   ]
+//! }"#;
+//!
+//! let profile = dmx::ofl::from_definition("generic", definition).unwrap();
+//! let mode = profile.mode("4-channel").unwrap();
+//!
+//! assert_eq!(profile.model, "Spot 60");
+//! assert_eq!(mode.channels[1].attribute, Attribute::IntensityFine);
+//! assert_eq!(mode.channels[2].default, 128);
+//! assert_eq!(mode.channels[3].attribute, Attribute::Gobo);
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::fixture::{Attribute, ChannelDef, FixtureMode, FixtureProfile};
+
+/// Reads the fixture profile of an OFL fixture file.
+///
+/// The manufacturer is taken from the name of the directory holding the
+/// file, which is the manufacturer's key in the library, e.g. `cameo` for
+/// `fixtures/cameo/flat-par-can-rgb-10-ir.json`. Fails with `InvalidData` if
+/// the file is not a valid fixture definition.
+pub fn open<P: AsRef<Path>>(path: P) -> io::Result<FixtureProfile> {
+    let path = path.as_ref();
+    let definition = fs::read_to_string(path)?;
+    let manufacturer = path
+        .parent()
+        .and_then(Path::file_name)
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+
+    from_definition(manufacturer, &definition)
+}
+
+/// Reads a fixture profile from the contents of an OFL fixture file.
+///
+/// OFL stores manufacturers separately from their fixtures, so the
+/// manufacturer is given as `manufacturer`. Fails with `InvalidData` if the
+/// document is not a valid fixture definition.
+pub fn from_definition(manufacturer: &str, definition: &str) -> io::Result<FixtureProfile> {
+    let root: Value = serde_json::from_str(definition)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let model = root
+        .get("name")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("not an OFL fixture definition"))?;
+    let mut profile = FixtureProfile::new(manufacturer, model);
+
+    let empty = Map::new();
+    let available = match root.get("availableChannels") {
+        Some(channels) => channels.as_object().ok_or_else(|| invalid("invalid channels"))?,
+        None => &empty,
+    };
+
+    // channels by name, including their fine channels
+    let mut channels = HashMap::new();
+    for (name, channel) in available {
+        let def = read_channel(name, channel)?;
+
+        let aliases = channel.get("fineChannelAliases").and_then(Value::as_array);
+        for alias in aliases.into_iter().flatten() {
+            let alias = alias.as_str().ok_or_else(|| invalid("invalid fine channel"))?;
+            let attribute = match def.attribute {
+                Attribute::Intensity => Attribute::IntensityFine,
+                Attribute::Pan => Attribute::PanFine,
+                Attribute::Tilt => Attribute::TiltFine,
+                _ => Attribute::Other(alias.to_owned()),
+            };
+
+            channels.insert(alias, ChannelDef { attribute, default: 0 });
+        }
+        channels.insert(name.as_str(), def);
+    }
+
+    let modes = root.get("modes").and_then(Value::as_array);
+    for element in modes.ok_or_else(|| invalid("fixture without modes"))? {
+        let name = element.get("name").and_then(Value::as_str).unwrap_or_default();
+        let mut mode = FixtureMode::new(name);
+
+        let list = element.get("channels").and_then(Value::as_array);
+        for channel in list.into_iter().flatten() {
+            let def = match *channel {
+                // unused channels
+                Value::Null => ChannelDef {
+                    attribute: Attribute::Other(String::new()),
+                    default: 0,
+                },
+                Value::String(ref name) => channels
+                    .get(name.as_str())
+                    .cloned()
+                    .ok_or_else(|| invalid("mode refers to an unknown channel"))?,
+                _ => return Err(invalid("matrix channels are not supported")),
+            };
+
+            mode.channels.push(def);
+        }
+
+        profile.add_mode(mode);
+    }
+
+    Ok(profile)
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_channel(name: &str, channel: &Value) -> io::Result<ChannelDef> {
+    let default = match channel.get("defaultValue") {
+        None => 0,
+        Some(Value::Number(n)) => n
+            .as_u64()
+            .filter(|&n| n <= 0xff)
+            .ok_or_else(|| invalid("invalid default value"))? as u8,
+        Some(Value::String(s)) => s
+            .strip_suffix('%')
+            .and_then(|p| p.trim().parse::<f64>().ok())
+            .filter(|p| (0.0..=100.0).contains(p))
+            .map(|p| (p * 255.0 / 100.0).round() as u8)
+            .ok_or_else(|| invalid("invalid default value"))?,
+        Some(_) => return Err(invalid("invalid default value")),
+    };
+
+    let capability = match channel.get("capability") {
+        Some(capability) => Some(capability),
+        None => channel
+            .get("capabilities")
+            .and_then(Value::as_array)
+            .and_then(|c| c.first()),
+    };
+    let capability = capability.ok_or_else(|| invalid("channel without capabilities"))?;
+    let kind = capability.get("type").and_then(Value::as_str).unwrap_or_default();
+
+    // wheels are named after themselves, the channel or both
+    let wheel = capability
+        .get("wheel")
+        .and_then(Value::as_str)
+        .unwrap_or(name)
+        .to_lowercase();
+    let color = capability.get("color").and_then(Value::as_str);
+
+    Ok(ChannelDef {
+        attribute: capability_attribute(kind, color, &wheel)
+            .unwrap_or_else(|| Attribute::Other(name.to_owned())),
+        default,
+    })
+}
+
+/// Maps the type of a capability onto an attribute.
+fn capability_attribute(kind: &str, color: Option<&str>, wheel: &str) -> Option<Attribute> {
+    let gobo = wheel.contains("gobo");
+    let color_wheel = wheel.contains("color") || wheel.contains("colour");
+
+    Some(match kind {
+        "Intensity" => Attribute::Intensity,
+        "ColorIntensity" => match color? {
+            "Red" => Attribute::Red,
+            "Green" => Attribute::Green,
+            "Blue" => Attribute::Blue,
+            "White" => Attribute::White,
+            "Amber" => Attribute::Amber,
+            "UV" => Attribute::Uv,
+            "Cyan" => Attribute::Cyan,
+            "Magenta" => Attribute::Magenta,
+            "Yellow" => Attribute::Yellow,
+            _ => return None,
+        },
+        "ColorPreset" => Attribute::ColorWheel,
+        "ColorTemperature" => Attribute::ColorTemperature,
+        "Pan" => Attribute::Pan,
+        "Tilt" => Attribute::Tilt,
+        "PanTiltSpeed" => Attribute::PanTiltSpeed,
+        "WheelSlot" | "WheelShake" if gobo => Attribute::Gobo,
+        "WheelSlot" | "WheelShake" if color_wheel => Attribute::ColorWheel,
+        "WheelRotation" | "WheelSlotRotation" if gobo => Attribute::GoboRotation,
+        "Prism" | "PrismRotation" => Attribute::Prism,
+        "Focus" => Attribute::Focus,
+        "Zoom" => Attribute::Zoom,
+        "Iris" => Attribute::Iris,
+        "Frost" => Attribute::Frost,
+        "ShutterStrobe" | "StrobeSpeed" | "StrobeDuration" => Attribute::Strobe,
+        "Effect" => Attribute::Effect,
+        "EffectSpeed" | "EffectDuration" => Attribute::EffectSpeed,
+        "Maintenance" => Attribute::Control,
+        _ => return None,
+    })
+}
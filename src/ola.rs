@@ -0,0 +1,238 @@
+//! Open Lighting Architecture support.
+//!
+//! [OLA](https://www.openlighting.org/ola/) runs a daemon, `olad`, that
+//! drives a wide range of interfaces and network protocols. Its built-in web
+//! server accepts DMX data for a universe through a simple HTTP interface:
+//!
+//! ```text
+//! POST /set_dmx
+//!
+//! u=<universe>&d=<value>,<value>,...
+//! ```
+//!
+//! `OlaTransmitter` sends universes this way, keeping the connection to the
+//! daemon open between packets. The universe must be patched to an output
+//! port in OLA for the data to go anywhere.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use dmx::DmxTransmitter;
+//! use dmx::ola::OlaTransmitter;
+//!
+//! let mut olad = OlaTransmitter::new(1).unwrap();
+//!
+//! olad.send_dmx_packet(&[0xff, 0x00, 0x80]).unwrap();
+//! ```
+
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::{cmp, io, time};
+
+use crate::{DmxTransmitter, Error, Result};
+
+/// TCP port of the web server of `olad`.
+pub const OLA_HTTP_PORT: u16 = 9090;
+
+/// Duration to wait for the daemon to reply.
+const REPLY_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+/// OLA transmitter.
+///
+/// Sends DMX data to a universe of a running `olad` instance. As OLA
+/// generates the DMX signal itself, `send_break` does nothing and only
+/// complete packets with the default start code can be sent.
+#[derive(Debug)]
+pub struct OlaTransmitter {
+    target: SocketAddr,
+    universe: u32,
+    conn: Option<BufReader<TcpStream>>,
+    // request body, reused between packets
+    body: String,
+}
+
+impl OlaTransmitter {
+    /// Create a transmitter sending to a daemon running on the local host.
+    #[inline]
+    pub fn new(universe: u32) -> io::Result<OlaTransmitter> {
+        OlaTransmitter::with_address((Ipv4Addr::LOCALHOST, OLA_HTTP_PORT), universe)
+    }
+
+    /// Create a transmitter sending to a daemon at a specific address.
+    ///
+    /// `target` usually is the daemon's IP address with port 9090. The
+    /// connection is established right away, to fail early if the daemon is
+    /// not running.
+    pub fn with_address<A: ToSocketAddrs>(target: A, universe: u32) -> io::Result<OlaTransmitter> {
+        let target = target
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no target address"))?;
+
+        let conn = connect(target)?;
+
+        Ok(OlaTransmitter {
+            target,
+            universe,
+            conn: Some(conn),
+            body: String::with_capacity(2048),
+        })
+    }
+
+    /// Returns the universe packets are sent to.
+    #[inline]
+    pub fn universe(&self) -> u32 {
+        self.universe
+    }
+
+    /// Sets the universe packets are sent to.
+    #[inline]
+    pub fn set_universe(&mut self, universe: u32) {
+        self.universe = universe;
+    }
+
+    /// Returns the address of the daemon.
+    #[inline]
+    pub fn target(&self) -> SocketAddr {
+        self.target
+    }
+
+    /// Sends channel data to the universe.
+    ///
+    /// At most 512 channels are sent. If the daemon closed the connection
+    /// since the last packet, it is reestablished once.
+    pub fn send_channels(&mut self, channels: &[u8]) -> Result<()> {
+        self.body.clear();
+        // writing into a string cannot fail
+        let _ = write!(self.body, "u={}&d=", self.universe);
+        for (i, value) in channels[..cmp::min(channels.len(), 512)].iter().enumerate() {
+            if i > 0 {
+                self.body.push(',');
+            }
+            let _ = write!(self.body, "{}", value);
+        }
+
+        // a kept-alive connection may have been closed by the daemon in the
+        // meantime, which only shows once a request fails
+        let reused = self.conn.is_some();
+        match self.request() {
+            Err(Error::Io(_)) if reused => self.request(),
+            rv => rv,
+        }
+    }
+
+    fn request(&mut self) -> Result<()> {
+        let mut conn = match self.conn.take() {
+            Some(conn) => conn,
+            None => connect(self.target)?,
+        };
+
+        write!(
+            conn.get_mut(),
+            "POST /set_dmx HTTP/1.1\r\n\
+             Host: {}\r\n\
+             Content-Type: application/x-www-form-urlencoded\r\n\
+             Content-Length: {}\r\n\
+             \r\n\
+             {}",
+            self.target,
+            self.body.len(),
+            self.body
+        )?;
+
+        let keep_alive = read_response(&mut conn)?;
+        if keep_alive {
+            self.conn = Some(conn);
+        }
+
+        Ok(())
+    }
+}
+
+impl DmxTransmitter for OlaTransmitter {
+    type Error = Error;
+
+    #[inline]
+    fn send_break(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn send_raw_data(&mut self, _data: &[u8]) -> Result<()> {
+        Err(Error::Unsupported("OLA can only transmit complete packets"))
+    }
+
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<()> {
+        match data.first() {
+            Some(&0x00) => self.send_channels(&data[1..]),
+            Some(&code) => Err(Error::UnsupportedStartCode(code)),
+            None => Err(Error::EmptyPacket),
+        }
+    }
+}
+
+fn connect(target: SocketAddr) -> io::Result<BufReader<TcpStream>> {
+    let stream = TcpStream::connect_timeout(&target, REPLY_TIMEOUT)?;
+    stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+
+    Ok(BufReader::new(stream))
+}
+
+/// Reads an HTTP response, discarding its body.
+///
+/// Returns whether the connection can be reused, or an error if the daemon
+/// rejected the request.
+fn read_response(conn: &mut BufReader<TcpStream>) -> Result<bool> {
+    let mut line = String::new();
+    if conn.read_line(&mut line).map_err(Error::from_read)? == 0 {
+        return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+    }
+
+    // status line, e.g. "HTTP/1.1 200 OK"
+    let status = match line.split(' ').nth(1).and_then(|s| s.parse::<u16>().ok()) {
+        Some(status) => status,
+        None => return Err(Error::InvalidResponse("malformed HTTP status line")),
+    };
+
+    let mut content_length = None;
+    let mut keep_alive = line.starts_with("HTTP/1.1");
+
+    loop {
+        line.clear();
+        if conn.read_line(&mut line).map_err(Error::from_read)? == 0 {
+            return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse::<u64>().ok();
+            } else if name.eq_ignore_ascii_case("connection") {
+                keep_alive = !value.eq_ignore_ascii_case("close");
+            }
+        }
+    }
+
+    let discarded = match content_length {
+        Some(len) => io::copy(&mut conn.take(len), &mut io::sink()),
+        None => {
+            // the body extends until the connection is closed
+            keep_alive = false;
+            io::copy(conn, &mut io::sink())
+        }
+    };
+    discarded.map_err(Error::from_read)?;
+
+    if status != 200 {
+        return Err(Error::InvalidResponse("olad rejected DMX data"));
+    }
+
+    Ok(keep_alive)
+}
@@ -0,0 +1,490 @@
+//! OSC control.
+//!
+//! [Open Sound Control](https://opensoundcontrol.stanford.edu/) is sent by
+//! control surface apps such as TouchOSC and by show control software such
+//! as QLab. An `OscDmxBridge` receives OSC messages over UDP and writes their
+//! arguments into shared universes, according to address patterns:
+//!
+//! * `/dmx/1/{channel}` takes the channel from the address, so `/dmx/1/12`
+//!   sets channel 12. The placeholder may be part of a longer address
+//!   segment, as in `/1/fader{channel}`.
+//! * Addresses without a placeholder are mapped to a fixed channel, see
+//!   `OscDmxBridge::map_channel`.
+//!
+//! Floats are expected in the range of 0 to 1, as sent by faders, integers
+//! in the range of 0 to 255. Further arguments set the following channels,
+//! e.g. `/dmx/1/10 1.0 0.5 0.0` sets the channels 10 to 12. Bundles are
+//! applied right away, regardless of their time tag.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use dmx::{DmxAddress, DmxRefresher};
+//! use dmx::osc::OscDmxBridge;
+//!
+//! let refresher = DmxRefresher::new(dmx::open_serial("/dev/ttyAMA0").unwrap());
+//!
+//! let mut bridge = OscDmxBridge::bind(("0.0.0.0", 8000)).unwrap();
+//! bridge.map("/dmx/1/{channel}", &refresher.handle()).unwrap();
+//! bridge.map_channel("/1/fader1", &refresher.handle(), DmxAddress::MIN).unwrap();
+//!
+//! bridge.run().unwrap();
+//! ```
+
+use std::convert::TryFrom;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::{fmt, io, str};
+
+use crate::address::DmxAddress;
+use crate::refresh::SharedUniverse;
+use crate::{Error, Result};
+
+/// Placeholder for the channel number in address patterns.
+pub const CHANNEL_PLACEHOLDER: &str = "{channel}";
+
+// largest datagram accepted
+const MAX_PACKET_LEN: usize = 65_507;
+
+// bundles nested deeper than this are ignored
+const MAX_BUNDLE_DEPTH: usize = 8;
+
+/// An argument of an OSC message.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscArg {
+    /// 32-bit integer, also used for characters, colors and MIDI messages.
+    Int(i32),
+    /// 64-bit integer.
+    Long(i64),
+    /// 32-bit float.
+    Float(f32),
+    /// 64-bit float.
+    Double(f64),
+    /// String or symbol.
+    String(String),
+    /// Binary data.
+    Blob(Vec<u8>),
+    /// True or false.
+    Bool(bool),
+    /// Nil.
+    Nil,
+    /// Impulse, also known as bang.
+    Impulse,
+}
+
+impl OscArg {
+    /// Converts the argument to a channel value.
+    ///
+    /// Floats from 0 to 1 are scaled to the full range, integers are taken
+    /// as they are. Values out of range are clamped. Returns `None` for
+    /// arguments without a numeric value.
+    pub fn to_dmx(&self) -> Option<u8> {
+        let scale = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+        match *self {
+            OscArg::Int(v) => Some(v.clamp(0, 255) as u8),
+            OscArg::Long(v) => Some(v.clamp(0, 255) as u8),
+            OscArg::Float(v) => Some(scale(f64::from(v))),
+            OscArg::Double(v) => Some(scale(v)),
+            OscArg::Bool(v) => Some(if v { 255 } else { 0 }),
+            _ => None,
+        }
+    }
+}
+
+/// An OSC message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OscMessage {
+    /// Address the message is sent to, such as `/1/fader1`.
+    pub address: String,
+    /// Arguments.
+    pub args: Vec<OscArg>,
+}
+
+/// Decodes an OSC packet, a message or a bundle.
+///
+/// The messages of bundles are returned in order, nested bundles are
+/// flattened. Returns `None` if the packet is malformed.
+///
+/// ```
+/// use dmx::osc::{self, OscArg};
+///
+/// let packet = b"/dmx/1/5\0\0\0\0,f\0\0\x3f\x80\0\0";
+/// let messages = osc::decode(packet).unwrap();
+///
+/// assert_eq!(messages[0].address, "/dmx/1/5");
+/// assert_eq!(messages[0].args, [OscArg::Float(1.0)]);
+/// ```
+pub fn decode(packet: &[u8]) -> Option<Vec<OscMessage>> {
+    let mut messages = Vec::new();
+    decode_into(packet, 0, &mut messages)?;
+
+    Some(messages)
+}
+
+fn decode_into(packet: &[u8], depth: usize, messages: &mut Vec<OscMessage>) -> Option<()> {
+    let mut reader = Reader(packet);
+
+    if packet.starts_with(b"#bundle\0") {
+        if depth >= MAX_BUNDLE_DEPTH {
+            return None;
+        }
+
+        // identifier and time tag
+        reader.take(16)?;
+        while !reader.0.is_empty() {
+            let len = usize::try_from(reader.i32()?).ok()?;
+            decode_into(reader.take(len)?, depth + 1, messages)?;
+        }
+
+        return Some(());
+    }
+
+    let address = reader.string()?;
+    if !address.starts_with('/') {
+        return None;
+    }
+
+    // very old implementations omit the type tags of messages without
+    // arguments
+    let tags = if reader.0.is_empty() {
+        ""
+    } else {
+        reader.string()?.strip_prefix(',')?
+    };
+
+    let mut args = Vec::with_capacity(tags.len());
+    for tag in tags.bytes() {
+        args.push(match tag {
+            b'i' | b'c' | b'r' | b'm' => OscArg::Int(reader.i32()?),
+            b'h' => OscArg::Long(reader.i64()?),
+            b'f' => OscArg::Float(f32::from_bits(reader.i32()? as u32)),
+            b'd' => OscArg::Double(f64::from_bits(reader.i64()? as u64)),
+            b's' | b'S' => OscArg::String(reader.string()?.to_owned()),
+            b'b' => {
+                let len = usize::try_from(reader.i32()?).ok()?;
+                let blob = reader.take(len)?.to_vec();
+                reader.take(padding(len))?;
+                OscArg::Blob(blob)
+            }
+            b'T' => OscArg::Bool(true),
+            b'F' => OscArg::Bool(false),
+            b'N' => OscArg::Nil,
+            b'I' => OscArg::Impulse,
+            // arrays are flattened
+            b'[' | b']' => continue,
+            // time tags carry no channel value
+            b't' => {
+                reader.take(8)?;
+                continue;
+            }
+            _ => return None,
+        });
+    }
+
+    messages.push(OscMessage {
+        address: address.to_owned(),
+        args,
+    });
+    Some(())
+}
+
+/// Returns the number of bytes padding `len` to a multiple of four.
+#[inline]
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if n > self.0.len() {
+            return None;
+        }
+
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        let bytes = self.take(4)?;
+        Some(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        let high = self.i32()? as u32;
+        let low = self.i32()? as u32;
+        Some((u64::from(high) << 32 | u64::from(low)) as i64)
+    }
+
+    /// Reads a string, NUL-terminated and padded to four bytes.
+    fn string(&mut self) -> Option<&'a str> {
+        let len = self.0.iter().position(|&b| b == 0)?;
+        let s = str::from_utf8(&self.0[..len]).ok()?;
+
+        self.take(len + 1 + padding(len + 1))?;
+        Some(s)
+    }
+}
+
+/// A segment of an address pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Channel { prefix: String, suffix: String },
+}
+
+#[derive(Clone, Debug)]
+struct Route {
+    segments: Vec<Segment>,
+    universe: SharedUniverse,
+    // channel of patterns without placeholder
+    channel: Option<DmxAddress>,
+}
+
+impl Route {
+    /// Returns the channel addressed by `address`, if it matches.
+    fn matches(&self, address: &str) -> Option<DmxAddress> {
+        let mut parts = address.strip_prefix('/')?.split('/');
+        let mut channel = self.channel;
+
+        for segment in &self.segments {
+            let part = parts.next()?;
+
+            match *segment {
+                Segment::Literal(ref literal) => {
+                    if part != literal {
+                        return None;
+                    }
+                }
+                Segment::Channel {
+                    ref prefix,
+                    ref suffix,
+                } => {
+                    let n = part.strip_prefix(prefix.as_str())?.strip_suffix(suffix.as_str())?;
+                    channel = DmxAddress::new(n.parse().ok()?);
+                }
+            }
+        }
+
+        if parts.next().is_some() {
+            return None;
+        }
+
+        channel
+    }
+}
+
+/// Parses an address pattern, returning whether it contains a placeholder.
+fn parse_pattern(pattern: &str) -> Result<(Vec<Segment>, bool)> {
+    let mut segments = Vec::new();
+    let mut placeholder = false;
+
+    let parts = pattern
+        .strip_prefix('/')
+        .ok_or(Error::InvalidParameter("invalid OSC address pattern"))?;
+
+    for part in parts.split('/') {
+        if part.is_empty() {
+            return Err(Error::InvalidParameter("invalid OSC address pattern"));
+        }
+
+        segments.push(match part.find(CHANNEL_PLACEHOLDER) {
+            Some(n) if !placeholder => {
+                placeholder = true;
+                Segment::Channel {
+                    prefix: part[..n].to_owned(),
+                    suffix: part[(n + CHANNEL_PLACEHOLDER.len())..].to_owned(),
+                }
+            }
+            Some(_) => return Err(Error::InvalidParameter("more than one channel placeholder")),
+            None => Segment::Literal(part.to_owned()),
+        });
+    }
+
+    Ok((segments, placeholder))
+}
+
+/// Bridge from OSC messages to DMX channels.
+///
+/// Receives OSC packets on a UDP socket and applies every message matching
+/// one of its routes to the route's universe, see the module documentation.
+/// Messages matching several routes are applied to all of them; messages
+/// matching none are ignored.
+///
+/// ```
+/// use dmx::osc::{OscArg, OscDmxBridge, OscMessage};
+/// use dmx::{DmxAddress, SharedUniverse};
+///
+/// let universe = SharedUniverse::new();
+/// let mut bridge = OscDmxBridge::bind("127.0.0.1:0").unwrap();
+/// bridge.map("/1/fader{channel}", &universe).unwrap();
+///
+/// bridge.apply(&OscMessage {
+///     address: "/1/fader3".to_owned(),
+///     args: vec![OscArg::Float(0.5), OscArg::Int(255)],
+/// });
+///
+/// assert_eq!(universe.snapshot()[2..4], [128, 255]);
+/// ```
+pub struct OscDmxBridge {
+    socket: UdpSocket,
+    routes: Vec<Route>,
+    buf: Vec<u8>,
+}
+
+impl OscDmxBridge {
+    /// Create a bridge listening on `addr`.
+    #[inline]
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<OscDmxBridge> {
+        Ok(OscDmxBridge::with_socket(UdpSocket::bind(addr)?))
+    }
+
+    /// Create a bridge from a bound socket.
+    pub fn with_socket(socket: UdpSocket) -> OscDmxBridge {
+        OscDmxBridge {
+            socket,
+            routes: Vec::new(),
+            buf: vec![0; MAX_PACKET_LEN],
+        }
+    }
+
+    /// Maps addresses matching `pattern` onto the channels of `universe`.
+    ///
+    /// The pattern must contain the `{channel}` placeholder, which matches
+    /// channel numbers from 1 to 512. Fails with `Error::InvalidParameter`
+    /// if the pattern is malformed.
+    pub fn map(&mut self, pattern: &str, universe: &SharedUniverse) -> Result<()> {
+        let (segments, placeholder) = parse_pattern(pattern)?;
+        if !placeholder {
+            return Err(Error::InvalidParameter("missing channel placeholder"));
+        }
+
+        self.routes.push(Route {
+            segments,
+            universe: universe.clone(),
+            channel: None,
+        });
+        Ok(())
+    }
+
+    /// Maps a single address onto `channel` of `universe`.
+    ///
+    /// Fails with `Error::InvalidParameter` if the address is malformed or
+    /// contains a placeholder.
+    pub fn map_channel(
+        &mut self,
+        address: &str,
+        universe: &SharedUniverse,
+        channel: DmxAddress,
+    ) -> Result<()> {
+        let (segments, placeholder) = parse_pattern(address)?;
+        if placeholder {
+            return Err(Error::InvalidParameter("unexpected channel placeholder"));
+        }
+
+        self.routes.push(Route {
+            segments,
+            universe: universe.clone(),
+            channel: Some(channel),
+        });
+        Ok(())
+    }
+
+    /// Removes all routes.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.routes.clear();
+    }
+
+    /// Returns the underlying socket, e.g. to set a read timeout.
+    #[inline]
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Applies a message to all matching routes, returning whether there
+    /// were any.
+    ///
+    /// Arguments without a numeric value, such as strings, are skipped.
+    pub fn apply(&self, message: &OscMessage) -> bool {
+        let values: Vec<u8> = message.args.iter().filter_map(OscArg::to_dmx).collect();
+        let mut matched = false;
+
+        for route in &self.routes {
+            if let Some(channel) = route.matches(&message.address) {
+                route.universe.set_channels(channel, &values);
+                matched = true;
+            }
+        }
+
+        matched
+    }
+
+    /// Blocking receive a packet and apply its messages.
+    ///
+    /// Returns the sender and the number of messages that matched a route.
+    /// Malformed packets are ignored.
+    pub fn recv(&mut self) -> Result<(SocketAddr, usize)> {
+        loop {
+            let (len, source) = self.socket.recv_from(&mut self.buf).map_err(Error::from_read)?;
+
+            if let Some(messages) = decode(&self.buf[..len]) {
+                let matched = messages.iter().filter(|m| self.apply(m)).count();
+                return Ok((source, matched));
+            }
+        }
+    }
+
+    /// Receives and applies messages.
+    ///
+    /// Only returns if receiving fails.
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            self.recv()?;
+        }
+    }
+}
+
+impl fmt::Debug for OscDmxBridge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OscDmxBridge")
+            .field("socket", &self.socket)
+            .field("routes", &self.routes.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_messages_without_type_tags() {
+        let messages = decode(b"/go\0").unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].address, "/go");
+        assert!(messages[0].args.is_empty());
+    }
+
+    #[test]
+    fn decodes_bundles_holding_messages_without_type_tags() {
+        let mut packet = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+        packet.extend_from_slice(&[0, 0, 0, 4]);
+        packet.extend_from_slice(b"/go\0");
+        packet.extend_from_slice(&[0, 0, 0, 20]);
+        packet.extend_from_slice(b"/dmx/1/5\0\0\0\0,i\0\0\0\0\0\x80");
+
+        let messages = decode(&packet).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].address, "/go");
+        assert_eq!(messages[1].args, [OscArg::Int(0x80)]);
+    }
+
+    #[test]
+    fn rejects_type_tags_without_a_comma() {
+        assert!(decode(b"/go\0i\0\0\0\0\0\0\x01").is_none());
+    }
+}
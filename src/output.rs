@@ -0,0 +1,283 @@
+//! Multi-universe output.
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::atomic::AtomicBool;
+use std::{fmt, time};
+
+use crate::limits::ChannelLimits;
+use crate::packet::MAX_CHANNELS;
+use crate::refresh::{run_at_frame_rate, SharedUniverse};
+use crate::schedule::FrameScheduler;
+use crate::serial::sleep_until;
+use crate::{DmxTransmitter, Error, Result};
+
+/// A transmitter selected at runtime, e.g. owned by a `DmxOutputManager`.
+///
+/// Created through `DmxTransmitter::boxed`.
+pub type BoxedTransmitter = Box<dyn DmxTransmitter<Error = Error> + Send>;
+
+struct Output {
+    universe: u16,
+    // where a network output sends to, for rate limiting
+    destination: Option<IpAddr>,
+    transmitter: BoxedTransmitter,
+    // when a frame was last sent by `send_paced`
+    last_sent: Option<time::Instant>,
+}
+
+/// Sends several universes through several transmitters.
+///
+/// Each output is assigned a universe number; several outputs may send the
+/// same universe, for example a serial port and an Art-Net node mirroring
+/// it. Universes are created when the first output is assigned to them and
+/// are changed through `SharedUniverse` handles.
+///
+/// Network outputs may be paced by a `FrameScheduler`, which spreads them
+/// over the frame period and limits the rate of packets per destination.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::sync::atomic::AtomicBool;
+/// use dmx::{DmxAddress, DmxOutputManager};
+///
+/// let mut outputs = DmxOutputManager::new();
+/// outputs.add_output(1, Box::new(dmx::open_serial("/dev/ttyUSB0").unwrap()));
+/// outputs.add_output(2, Box::new(dmx::open_serial("/dev/ttyUSB1").unwrap()));
+///
+/// outputs.universe(2).unwrap().set_channel(DmxAddress::MIN, 0xff);
+///
+/// let stop = AtomicBool::new(false);
+/// outputs.run(40.0, &stop).unwrap();
+/// ```
+#[derive(Default)]
+pub struct DmxOutputManager {
+    universes: BTreeMap<u16, SharedUniverse>,
+    limits: BTreeMap<u16, ChannelLimits>,
+    outputs: Vec<Output>,
+    scheduler: Option<FrameScheduler>,
+}
+
+impl DmxOutputManager {
+    /// Create a manager without any outputs.
+    #[inline]
+    pub fn new() -> DmxOutputManager {
+        DmxOutputManager::default()
+    }
+
+    /// Adds an output sending `universe`.
+    ///
+    /// Returns the handle of the universe, which is created if no other
+    /// output sends it yet.
+    pub fn add_output(&mut self, universe: u16, transmitter: BoxedTransmitter) -> SharedUniverse {
+        self.push_output(universe, None, transmitter)
+    }
+
+    /// Adds a network output sending `universe` to `destination`.
+    ///
+    /// Works like `add_output`, but subjects the output to the rate limit
+    /// of its destination, if a scheduler is set. `destination` is the
+    /// address of the node, or of the multicast group sent to.
+    pub fn add_network_output(
+        &mut self,
+        universe: u16,
+        destination: IpAddr,
+        transmitter: BoxedTransmitter,
+    ) -> SharedUniverse {
+        self.push_output(universe, Some(destination), transmitter)
+    }
+
+    fn push_output(
+        &mut self,
+        universe: u16,
+        destination: Option<IpAddr>,
+        transmitter: BoxedTransmitter,
+    ) -> SharedUniverse {
+        self.outputs.push(Output {
+            universe,
+            destination,
+            transmitter,
+            last_sent: None,
+        });
+
+        self.universes.entry(universe).or_default().clone()
+    }
+
+    /// Removes all outputs sending `universe` and returns them.
+    ///
+    /// Existing handles of the universe remain valid, but are no longer
+    /// sent.
+    pub fn remove_universe(&mut self, universe: u16) -> Vec<BoxedTransmitter> {
+        self.universes.remove(&universe);
+
+        let (removed, kept) = self
+            .outputs
+            .drain(..)
+            .partition(|output| output.universe == universe);
+        self.outputs = kept;
+
+        removed.into_iter().map(|output| output.transmitter).collect()
+    }
+
+    /// Sets the limits applied to every frame of `universe` sent, e.g.
+    /// those of `Patch::limits`.
+    ///
+    /// Values in the universe itself are not changed.
+    #[inline]
+    pub fn set_limits(&mut self, universe: u16, limits: ChannelLimits) {
+        self.limits.insert(universe, limits);
+    }
+
+    /// Removes the limits of `universe`.
+    #[inline]
+    pub fn clear_limits(&mut self, universe: u16) {
+        self.limits.remove(&universe);
+    }
+
+    /// Sets the scheduler pacing outputs in `run` and `send_paced`, `None`
+    /// to send all outputs at once.
+    #[inline]
+    pub fn set_scheduler(&mut self, scheduler: Option<FrameScheduler>) {
+        self.scheduler = scheduler;
+    }
+
+    /// Returns the scheduler pacing outputs.
+    #[inline]
+    pub fn scheduler_mut(&mut self) -> Option<&mut FrameScheduler> {
+        self.scheduler.as_mut()
+    }
+
+    /// Returns the handle of a universe, if any output sends it.
+    #[inline]
+    pub fn universe(&self, universe: u16) -> Option<SharedUniverse> {
+        self.universes.get(&universe).cloned()
+    }
+
+    /// Returns the numbers of all universes sent, in ascending order.
+    #[inline]
+    pub fn universes(&self) -> impl Iterator<Item = u16> + '_ {
+        self.universes.keys().copied()
+    }
+
+    /// Returns the number of outputs.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Returns whether there are no outputs.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    /// Sends every universe through all of its outputs.
+    ///
+    /// A failing output does not keep the others from sending; the first
+    /// error that occurred is returned after all outputs have been tried.
+    pub fn send_all(&mut self) -> Result<()> {
+        let frames = self.frames();
+        let mut rv = Ok(());
+
+        for output in &mut self.outputs {
+            let result = output.transmitter.send_dmx_packet(&frames[&output.universe]);
+
+            if rv.is_ok() {
+                rv = result;
+            }
+        }
+
+        rv
+    }
+
+    /// Sends every universe through all of its outputs, paced by the
+    /// scheduler over a frame `period`.
+    ///
+    /// Returns once all outputs have been sent or skipped, usually shortly
+    /// before the period has passed. Without a scheduler, works like
+    /// `send_all`.
+    pub fn send_paced(&mut self, period: time::Duration) -> Result<()> {
+        let mut scheduler = match self.scheduler.take() {
+            Some(scheduler) => scheduler,
+            None => return self.send_all(),
+        };
+
+        let frames = self.frames();
+        let start = time::Instant::now();
+        let deadline = start + period;
+
+        // outputs waiting the longest go first, so that those skipped take
+        // turns with the others sharing their destination
+        let mut order: Vec<usize> = (0..self.outputs.len()).collect();
+        order.sort_by_key(|&i| self.outputs[i].last_sent);
+        let count = order.len();
+        let mut rv = Ok(());
+
+        for (slot, i) in order.into_iter().enumerate() {
+            sleep_until(scheduler.slot(start, period, slot, count));
+
+            let output = &mut self.outputs[i];
+            if let Some(destination) = output.destination {
+                match scheduler.reserve(destination, deadline) {
+                    Some(at) => sleep_until(at),
+                    None => continue,
+                }
+            }
+
+            output.last_sent = Some(time::Instant::now());
+            let result = output.transmitter.send_dmx_packet(&frames[&output.universe]);
+
+            if rv.is_ok() {
+                rv = result;
+            }
+        }
+
+        self.scheduler = Some(scheduler);
+        rv
+    }
+
+    /// Takes snapshots of all universes, with their limits applied.
+    ///
+    /// All snapshots are taken first, so the universes are sent
+    /// consistently.
+    fn frames(&self) -> BTreeMap<u16, [u8; MAX_CHANNELS]> {
+        self.universes
+            .iter()
+            .map(|(&n, universe)| {
+                let mut frame = universe.snapshot();
+                if let Some(limits) = self.limits.get(&n) {
+                    limits.limit(&mut frame);
+                }
+                (n, frame)
+            })
+            .collect()
+    }
+
+    /// Continuously sends all universes at a fixed frame rate.
+    ///
+    /// Works like `DmxTransmitter::run_refresh_loop`, calling `send_paced`
+    /// every frame, which is `send_all` unless a scheduler is set. Returns
+    /// once `stop` is set, or when sending fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fps` is not a positive number.
+    pub fn run(&mut self, fps: f32, stop: &AtomicBool) -> Result<()> {
+        assert!(fps > 0.0, "frame rate must be positive");
+
+        let period = time::Duration::from_secs_f32(1.0 / fps);
+        run_at_frame_rate(fps, stop, || self.send_paced(period))
+    }
+}
+
+impl fmt::Debug for DmxOutputManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DmxOutputManager")
+            .field(
+                "outputs",
+                &self.outputs.iter().map(|o| o.universe).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
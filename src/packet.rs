@@ -0,0 +1,295 @@
+//! DMX packets.
+
+use core::{cmp, fmt, hash, ops};
+
+use crate::address::DmxAddress;
+
+/// Maximum number of channels inside a single packet.
+pub const MAX_CHANNELS: usize = 512;
+
+/// The start code of a packet, identifying the kind of data it carries.
+///
+/// Codes without a dedicated variant are represented by `Custom`; codes are
+/// compared by value, so `Custom(0x00)` equals `Null`.
+#[derive(Copy, Clone, Debug, Default)]
+pub enum StartCode {
+    /// Regular dimmer data, `0x00`.
+    #[default]
+    Null,
+    /// ASCII text, `0x17`.
+    Text,
+    /// Remote device management, `0xCC`.
+    Rdm,
+    /// System information packet, `0xCF`.
+    Sip,
+    /// Any other start code, e.g. a manufacturer-specific one.
+    Custom(u8),
+}
+
+impl StartCode {
+    /// Converts a raw start code.
+    ///
+    /// Never returns `Custom` for codes with a dedicated variant.
+    #[inline]
+    pub fn from_u8(code: u8) -> StartCode {
+        match code {
+            0x00 => StartCode::Null,
+            0x17 => StartCode::Text,
+            0xcc => StartCode::Rdm,
+            0xcf => StartCode::Sip,
+            code => StartCode::Custom(code),
+        }
+    }
+
+    /// Returns the raw start code.
+    #[inline]
+    pub fn as_u8(self) -> u8 {
+        match self {
+            StartCode::Null => 0x00,
+            StartCode::Text => 0x17,
+            StartCode::Rdm => 0xcc,
+            StartCode::Sip => 0xcf,
+            StartCode::Custom(code) => code,
+        }
+    }
+}
+
+impl From<u8> for StartCode {
+    #[inline]
+    fn from(code: u8) -> StartCode {
+        StartCode::from_u8(code)
+    }
+}
+
+impl From<StartCode> for u8 {
+    #[inline]
+    fn from(code: StartCode) -> u8 {
+        code.as_u8()
+    }
+}
+
+impl PartialEq for StartCode {
+    #[inline]
+    fn eq(&self, other: &StartCode) -> bool {
+        self.as_u8() == other.as_u8()
+    }
+}
+
+impl Eq for StartCode {}
+
+impl hash::Hash for StartCode {
+    #[inline]
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.as_u8().hash(state)
+    }
+}
+
+/// A DMX packet.
+///
+/// Holds a start code and up to 512 channels in a fixed-size buffer, avoiding
+/// any allocations. Channels are addressed the DMX way, starting at 1, see
+/// `DmxAddress`.
+///
+/// Dereferences to the raw packet data, including the start code, making it
+/// suitable for `DmxTransmitter::send_raw_dmx_packet`.
+#[derive(Clone)]
+pub struct DmxPacket {
+    data: [u8; MAX_CHANNELS + 1],
+    // length including the start code
+    len: usize,
+}
+
+impl DmxPacket {
+    /// Create a new packet.
+    ///
+    /// The packet has the default start code of `0x00` and all 512 channels
+    /// set to zero.
+    #[inline]
+    pub fn new() -> DmxPacket {
+        DmxPacket::with_start_code(StartCode::Null)
+    }
+
+    /// Create a new packet with a non-standard start code.
+    ///
+    /// All 512 channels will be set to zero.
+    #[inline]
+    pub fn with_start_code(start: StartCode) -> DmxPacket {
+        let mut data = [0; MAX_CHANNELS + 1];
+        data[0] = start.as_u8();
+
+        DmxPacket {
+            data,
+            len: MAX_CHANNELS + 1,
+        }
+    }
+
+    /// Create a new packet from channel data.
+    ///
+    /// Uses the default start code. Any channels beyond 512 are ignored.
+    pub fn from_channels(channels: &[u8]) -> DmxPacket {
+        let mut packet = DmxPacket::new();
+        packet.set_channels(channels);
+        packet
+    }
+
+    /// Create a new packet from raw data, including start code.
+    ///
+    /// Returns `None` if `data` is empty or longer than 513 bytes.
+    pub fn from_raw(data: &[u8]) -> Option<DmxPacket> {
+        if data.is_empty() || data.len() > MAX_CHANNELS + 1 {
+            return None;
+        }
+
+        let mut packet = DmxPacket::new();
+        packet.data[..data.len()].copy_from_slice(data);
+        packet.len = data.len();
+        Some(packet)
+    }
+
+    /// Create an ASCII text packet.
+    ///
+    /// The packet holds the page number, the number of characters per line
+    /// used to format the text, and the text itself, truncated to 510
+    /// characters. Characters outside of ASCII are replaced by `?`.
+    pub fn text(page: u8, chars_per_line: u8, text: &str) -> DmxPacket {
+        let mut packet = DmxPacket::with_start_code(StartCode::Text);
+        packet.data[1] = page;
+        packet.data[2] = chars_per_line;
+
+        let mut len = 3;
+        for (c, v) in text.chars().zip(packet.data[3..].iter_mut()) {
+            *v = if c.is_ascii() { c as u8 } else { b'?' };
+            len += 1;
+        }
+
+        packet.len = len;
+        packet
+    }
+
+    /// Returns the start code.
+    #[inline]
+    pub fn start_code(&self) -> StartCode {
+        StartCode::from_u8(self.data[0])
+    }
+
+    /// Sets the start code.
+    #[inline]
+    pub fn set_start_code(&mut self, start: StartCode) {
+        self.data[0] = start.as_u8();
+    }
+
+    /// Returns the value of channel `n`.
+    ///
+    /// Returns `None` if the channel is not part of the packet.
+    #[inline]
+    pub fn channel(&self, n: DmxAddress) -> Option<u8> {
+        let n = usize::from(n);
+
+        if n >= self.len {
+            return None;
+        }
+
+        Some(self.data[n])
+    }
+
+    /// Sets channel `n` to `value`.
+    ///
+    /// If the packet is shorter than `n` channels, it is extended, with all
+    /// new channels set to zero.
+    #[inline]
+    pub fn set_channel(&mut self, n: DmxAddress, value: u8) {
+        let n = usize::from(n);
+
+        if n >= self.len {
+            self.set_channel_count(n);
+        }
+
+        self.data[n] = value;
+    }
+
+    /// Replaces all channels.
+    ///
+    /// The packet length is adjusted to the number of channels passed in.
+    /// Any channels beyond 512 are ignored.
+    pub fn set_channels(&mut self, channels: &[u8]) {
+        let count = cmp::min(channels.len(), MAX_CHANNELS);
+
+        self.data[1..(count + 1)].copy_from_slice(&channels[..count]);
+        self.len = count + 1;
+    }
+
+    /// Returns the channel data, excluding the start code.
+    #[inline]
+    pub fn channels(&self) -> &[u8] {
+        &self.data[1..self.len]
+    }
+
+    /// Returns the channel data mutably, excluding the start code.
+    #[inline]
+    pub fn channels_mut(&mut self) -> &mut [u8] {
+        &mut self.data[1..self.len]
+    }
+
+    /// Returns the number of channels inside the packet.
+    #[inline]
+    pub fn channel_count(&self) -> usize {
+        self.len - 1
+    }
+
+    /// Sets the number of channels inside the packet.
+    ///
+    /// Shortening a packet allows for higher refresh rates. Channels added by
+    /// lengthening a packet are set to zero. Counts above 512 are clamped.
+    pub fn set_channel_count(&mut self, count: usize) {
+        let count = cmp::min(count, MAX_CHANNELS);
+
+        if count + 1 > self.len {
+            for v in &mut self.data[self.len..(count + 1)] {
+                *v = 0;
+            }
+        }
+
+        self.len = count + 1;
+    }
+}
+
+impl Default for DmxPacket {
+    #[inline]
+    fn default() -> DmxPacket {
+        DmxPacket::new()
+    }
+}
+
+impl ops::Deref for DmxPacket {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl AsRef<[u8]> for DmxPacket {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl PartialEq for DmxPacket {
+    #[inline]
+    fn eq(&self, other: &DmxPacket) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for DmxPacket {}
+
+impl fmt::Debug for DmxPacket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DmxPacket")
+            .field("start_code", &self.start_code())
+            .field("channels", &self.channels())
+            .finish()
+    }
+}
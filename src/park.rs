@@ -0,0 +1,125 @@
+//! Parked channels.
+
+use crate::address::DmxAddress;
+use crate::merge::MergeMode;
+use crate::packet::MAX_CHANNELS;
+use crate::universe::DmxUniverse;
+
+/// Channels parked at fixed values, overriding everything else.
+///
+/// A parked channel keeps its value through scene changes, effects and
+/// blackout, e.g. for house lights or the safety channel of a smoke machine.
+/// Channels are parked either at an exact value, or HTP, at a minimum the
+/// channel may still rise above.
+///
+/// Parking is meant as the final stage before output: like `Masters`, it is
+/// applied to a copy of the universe right before sending it, after all
+/// masters and curves.
+///
+/// ## Example
+///
+/// ```
+/// use dmx::{DmxAddress, DmxUniverse, Masters, Park};
+///
+/// let (house, smoke) = (DmxAddress::new(1).unwrap(), DmxAddress::new(2).unwrap());
+///
+/// let mut park = Park::new();
+/// park.park_htp(house, 0x80);
+/// park.park(smoke, 0x00);
+///
+/// let mut masters = Masters::new();
+/// masters.set_blackout(true);
+///
+/// let mut universe = DmxUniverse::new();
+/// universe.fill(0xff);
+/// let output = park.apply(&masters.apply(&universe));
+/// assert_eq!(output.channels()[..3], [0x80, 0x00, 0x00]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Park {
+    parked: [Option<(MergeMode, u8)>; MAX_CHANNELS],
+}
+
+impl Default for Park {
+    #[inline]
+    fn default() -> Park {
+        Park {
+            parked: [None; MAX_CHANNELS],
+        }
+    }
+}
+
+impl Park {
+    /// Create a park without any channels parked.
+    #[inline]
+    pub fn new() -> Park {
+        Park::default()
+    }
+
+    /// Parks channel `n` at exactly `value`.
+    #[inline]
+    pub fn park(&mut self, n: DmxAddress, value: u8) {
+        self.parked[n.index()] = Some((MergeMode::Ltp, value));
+    }
+
+    /// Parks channel `n` at a minimum of `value`, outputting its own value
+    /// where that is higher.
+    #[inline]
+    pub fn park_htp(&mut self, n: DmxAddress, value: u8) {
+        self.parked[n.index()] = Some((MergeMode::Htp, value));
+    }
+
+    /// Releases channel `n`.
+    ///
+    /// Returns whether the channel was parked.
+    #[inline]
+    pub fn unpark(&mut self, n: DmxAddress) -> bool {
+        self.parked[n.index()].take().is_some()
+    }
+
+    /// Releases all channels.
+    #[inline]
+    pub fn unpark_all(&mut self) {
+        self.parked = [None; MAX_CHANNELS];
+    }
+
+    /// Returns the value channel `n` is parked at, and whether it is parked
+    /// HTP.
+    #[inline]
+    pub fn parked(&self, n: DmxAddress) -> Option<(MergeMode, u8)> {
+        self.parked[n.index()]
+    }
+
+    /// Returns an iterator over all parked channels, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (DmxAddress, MergeMode, u8)> + '_ {
+        self.parked.iter().enumerate().filter_map(|(i, parked)| {
+            let (mode, value) = (*parked)?;
+            Some((DmxAddress::from_index(i)?, mode, value))
+        })
+    }
+
+    /// Returns whether no channels are parked.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.parked.iter().all(Option::is_none)
+    }
+
+    /// Returns a copy of `universe` with all parked channels applied.
+    ///
+    /// Fades in progress are not copied.
+    pub fn apply(&self, universe: &DmxUniverse) -> DmxUniverse {
+        let mut output = DmxUniverse::new();
+        let channels = output.channels_mut();
+        channels.copy_from_slice(universe.channels());
+
+        for (v, parked) in channels.iter_mut().zip(&self.parked) {
+            match *parked {
+                Some((MergeMode::Htp, value)) => *v = (*v).max(value),
+                Some((MergeMode::Ltp, value)) => *v = value,
+                None => (),
+            }
+        }
+
+        output
+    }
+}
@@ -0,0 +1,335 @@
+//! Patching fixtures.
+//!
+//! A `Patch` keeps track of which fixture sits at which address of which
+//! universe. Fixtures are only patched if their channels are free, so two
+//! fixtures never end up controlling the same channels by accident.
+//!
+//! ## Example
+//!
+//! ```
+//! use dmx::DmxAddress;
+//! use dmx::fixture::{Attribute, Fixture, FixtureMode};
+//! use dmx::patch::Patch;
+//!
+//! let mut mode = FixtureMode::new("3ch");
+//! mode.push(Attribute::Red, 0);
+//! mode.push(Attribute::Green, 0);
+//! mode.push(Attribute::Blue, 0);
+//!
+//! let par = |n| Fixture::with_mode(mode.clone(), DmxAddress::new(n).unwrap()).unwrap();
+//!
+//! let mut patch = Patch::new();
+//! let first = patch.add(1, par(1)).unwrap();
+//! patch.add(1, par(4)).unwrap();
+//!
+//! // channels 3 to 5 are taken
+//! let conflict = patch.add(1, par(3)).unwrap_err();
+//! assert_eq!(conflict.fixtures[0], first);
+//!
+//! assert_eq!(patch.find_free(1, 3), DmxAddress::new(7));
+//! assert_eq!(patch.fixtures(1).count(), 2);
+//! ```
+
+use std::{error, fmt, result};
+
+use crate::address::DmxAddress;
+use crate::fixture::Fixture;
+use crate::limits::ChannelLimits;
+use crate::packet::MAX_CHANNELS;
+
+/// Identifies a fixture added to a `Patch`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FixtureId(usize);
+
+impl fmt::Display for FixtureId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A fixture overlaps fixtures already patched.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatchConflict {
+    /// Universe of the fixtures.
+    pub universe: u16,
+    /// Patched fixtures sharing channels with the new one, by address.
+    pub fixtures: Vec<FixtureId>,
+}
+
+impl fmt::Display for PatchConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "channels already used in universe {} by fixture", self.universe)?;
+
+        for (i, id) in self.fixtures.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, id)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl error::Error for PatchConflict {}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Patched {
+    universe: u16,
+    fixture: Fixture,
+}
+
+/// Fixtures patched into universes.
+#[derive(Clone, Debug, Default)]
+pub struct Patch {
+    fixtures: Vec<Option<Patched>>,
+}
+
+impl Patch {
+    /// Create an empty patch.
+    #[inline]
+    pub fn new() -> Patch {
+        Patch::default()
+    }
+
+    /// Patches a fixture into a universe, at the fixture's start address.
+    ///
+    /// Fails if any of its channels are used by another fixture already.
+    pub fn add(
+        &mut self,
+        universe: u16,
+        fixture: Fixture,
+    ) -> result::Result<FixtureId, PatchConflict> {
+        self.check(universe, fixture.address(), fixture.footprint(), None)?;
+
+        let patched = Patched { universe, fixture };
+
+        // reuse the slot of a removed fixture
+        match self.fixtures.iter().position(Option::is_none) {
+            Some(n) => {
+                self.fixtures[n] = Some(patched);
+                Ok(FixtureId(n))
+            }
+            None => {
+                self.fixtures.push(Some(patched));
+                Ok(FixtureId(self.fixtures.len() - 1))
+            }
+        }
+    }
+
+    /// Removes a fixture and returns it.
+    ///
+    /// The id may be reused by fixtures added later.
+    #[inline]
+    pub fn remove(&mut self, id: FixtureId) -> Option<Fixture> {
+        self.fixtures.get_mut(id.0)?.take().map(|p| p.fixture)
+    }
+
+    /// Moves a patched fixture to another universe and start address.
+    ///
+    /// Fails if any of its channels would be used by another fixture, the
+    /// fixture is left where it was then. Returns `Ok(false)` if there is no
+    /// such fixture or it does not fit into the universe at `address`.
+    pub fn repatch(
+        &mut self,
+        id: FixtureId,
+        universe: u16,
+        address: DmxAddress,
+    ) -> result::Result<bool, PatchConflict> {
+        let footprint = match self.get(id) {
+            Some(fixture) => fixture.footprint(),
+            None => return Ok(false),
+        };
+
+        self.check(universe, address, footprint, Some(id))?;
+
+        let patched = self.fixtures[id.0].as_mut().unwrap();
+        if patched.fixture.set_address(address).is_err() {
+            return Ok(false);
+        }
+        patched.universe = universe;
+
+        Ok(true)
+    }
+
+    /// Returns a patched fixture.
+    #[inline]
+    pub fn get(&self, id: FixtureId) -> Option<&Fixture> {
+        self.patched(id).map(|p| &p.fixture)
+    }
+
+    /// Returns a patched fixture for changing its curve.
+    ///
+    /// Use `repatch` to change its address.
+    #[inline]
+    pub fn get_mut(&mut self, id: FixtureId) -> Option<&mut Fixture> {
+        match self.fixtures.get_mut(id.0) {
+            Some(Some(p)) => Some(&mut p.fixture),
+            _ => None,
+        }
+    }
+
+    /// Returns the universe a fixture is patched into.
+    #[inline]
+    pub fn universe_of(&self, id: FixtureId) -> Option<u16> {
+        self.patched(id).map(|p| p.universe)
+    }
+
+    /// Returns the number of patched fixtures.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.fixtures.iter().flatten().count()
+    }
+
+    /// Returns whether no fixtures are patched.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns all patched fixtures, with their universe, in the order of
+    /// their ids.
+    pub fn iter(&self) -> impl Iterator<Item = (FixtureId, u16, &Fixture)> + '_ {
+        self.fixtures
+            .iter()
+            .enumerate()
+            .filter_map(|(n, p)| p.as_ref().map(|p| (FixtureId(n), p.universe, &p.fixture)))
+    }
+
+    /// Returns the fixtures patched into `universe`, by address.
+    pub fn fixtures(&self, universe: u16) -> impl Iterator<Item = (FixtureId, &Fixture)> + '_ {
+        let mut fixtures: Vec<(FixtureId, &Fixture)> = self
+            .iter()
+            .filter(|&(_, u, _)| u == universe)
+            .map(|(id, _, fixture)| (id, fixture))
+            .collect();
+        fixtures.sort_by_key(|(_, fixture)| fixture.address());
+
+        fixtures.into_iter()
+    }
+
+    /// Returns the universes fixtures are patched into, in ascending order.
+    pub fn universes(&self) -> Vec<u16> {
+        let mut universes: Vec<u16> = self.iter().map(|(_, u, _)| u).collect();
+        universes.sort_unstable();
+        universes.dedup();
+
+        universes
+    }
+
+    /// Returns the fixtures using any of `count` consecutive channels,
+    /// starting at channel `start`, by address.
+    pub fn conflicts(&self, universe: u16, start: DmxAddress, count: usize) -> Vec<FixtureId> {
+        let end = start.index() + count;
+
+        self.fixtures(universe)
+            .filter(|(_, f)| {
+                f.address().index() < end && start.index() < f.address().index() + f.footprint()
+            })
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Returns the output limits of the fixtures patched into `universe`.
+    ///
+    /// ```
+    /// use dmx::{DmxAddress, DmxOutputManager};
+    /// use dmx::fixture::{Attribute, Fixture, FixtureMode};
+    /// use dmx::patch::Patch;
+    ///
+    /// let mut mode = FixtureMode::new("2ch");
+    /// mode.push(Attribute::Pan, 0x80);
+    /// mode.push(Attribute::Tilt, 0x80);
+    ///
+    /// let mut mover = Fixture::with_mode(mode, DmxAddress::new(1).unwrap()).unwrap();
+    /// mover.set_limit(&Attribute::Tilt, 0x20, 0xe0);
+    ///
+    /// let mut patch = Patch::new();
+    /// patch.add(1, mover).unwrap();
+    ///
+    /// let limits = patch.limits(1);
+    /// assert_eq!(limits.get(DmxAddress::new(2).unwrap()), (0x20, 0xe0));
+    ///
+    /// let mut outputs = DmxOutputManager::new();
+    /// outputs.set_limits(1, limits);
+    /// ```
+    pub fn limits(&self, universe: u16) -> ChannelLimits {
+        let mut limits = ChannelLimits::new();
+        for (_, fixture) in self.fixtures(universe) {
+            fixture.write_limits(&mut limits);
+        }
+        limits
+    }
+
+    /// Returns the lowest address at which `count` consecutive channels are
+    /// free.
+    pub fn find_free(&self, universe: u16, count: usize) -> Option<DmxAddress> {
+        let mut start = 0;
+
+        for (_, fixture) in self.fixtures(universe) {
+            if fixture.address().index() >= start + count {
+                break;
+            }
+            start = start.max(fixture.address().index() + fixture.footprint());
+        }
+
+        if start + count > MAX_CHANNELS {
+            return None;
+        }
+
+        DmxAddress::from_index(start)
+    }
+
+    fn patched(&self, id: FixtureId) -> Option<&Patched> {
+        self.fixtures.get(id.0)?.as_ref()
+    }
+
+    /// Fails if any of the channels are used by a fixture other than
+    /// `ignore`.
+    fn check(
+        &self,
+        universe: u16,
+        start: DmxAddress,
+        count: usize,
+        ignore: Option<FixtureId>,
+    ) -> result::Result<(), PatchConflict> {
+        let mut fixtures = self.conflicts(universe, start, count);
+        fixtures.retain(|&id| Some(id) != ignore);
+
+        if fixtures.is_empty() {
+            return Ok(());
+        }
+
+        Err(PatchConflict { universe, fixtures })
+    }
+}
+
+/// Serialized as a sequence of fixtures by id, with gaps of removed fixtures
+/// as `None`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Patch {
+    #[inline]
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> result::Result<S::Ok, S::Error> {
+        self.fixtures.serialize(serializer)
+    }
+}
+
+/// Fails if fixtures overlap, keeping the ids of all fixtures.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Patch {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> result::Result<Patch, D::Error> {
+        let fixtures: Vec<Option<Patched>> = serde::Deserialize::deserialize(deserializer)?;
+        let mut patch = Patch::new();
+
+        for patched in fixtures {
+            if let Some(ref p) = patched {
+                let (address, footprint) = (p.fixture.address(), p.fixture.footprint());
+                patch
+                    .check(p.universe, address, footprint, None)
+                    .map_err(serde::de::Error::custom)?;
+            }
+            patch.fixtures.push(patched);
+        }
+
+        Ok(patch)
+    }
+}
@@ -0,0 +1,188 @@
+//! Pixel mapping.
+//!
+//! LED strips with individually addressable pixels occupy three or four
+//! channels per pixel, so a single universe holds at most 170 RGB or 128
+//! RGBW pixels. Longer strips, usually driven through Art-Net or sACN, span
+//! several consecutive universes.
+//!
+//! A `PixelMap` describes where the pixels of one or more strips are
+//! addressed. Applications render their effects into a frame buffer, a slice
+//! of `Color`s holding all pixels of all strips in the order they were added
+//! to the map, which the map then writes into the universes. Strips on
+//! controllers addressed by pixel rather than by universe, such as those
+//! speaking DDP, are given a range of the frame buffer through `reserve`.
+//!
+//! ## Example
+//!
+//! ```
+//! use std::collections::BTreeMap;
+//! use dmx::{Color, ColorOrder, DmxAddress};
+//! use dmx::pixels::PixelMap;
+//!
+//! // a strip of 300 pixels, starting at universe 1, channel 1
+//! let mut map = PixelMap::new();
+//! let strip = map.add_strip(1, DmxAddress::MIN, 300, ColorOrder::Grb);
+//!
+//! let mut frame = vec![Color::BLACK; map.pixel_count()];
+//! frame[strip.start + 170] = Color::RED;
+//!
+//! let mut universes = BTreeMap::new();
+//! map.render(&frame, &mut universes);
+//!
+//! // the 171st pixel is the first one of the second universe
+//! assert_eq!(universes[&2].channels()[..3], [0x00, 0xff, 0x00]);
+//! ```
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use crate::address::DmxAddress;
+use crate::color::{Color, ColorOrder};
+use crate::output::DmxOutputManager;
+use crate::packet::MAX_CHANNELS;
+use crate::universe::DmxUniverse;
+
+/// Maximum number of RGB pixels in a universe.
+pub const PIXELS_PER_UNIVERSE: usize = MAX_CHANNELS / 3;
+
+/// The part of a strip within a single universe.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Segment {
+    universe: u16,
+    // index of the first channel
+    offset: usize,
+    // index of the first pixel in the frame buffer
+    first: usize,
+    pixels: usize,
+    order: ColorOrder,
+}
+
+/// Maps the pixels of a frame buffer to channels of universes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PixelMap {
+    segments: Vec<Segment>,
+    pixels: usize,
+}
+
+impl PixelMap {
+    /// Create an empty map.
+    #[inline]
+    pub fn new() -> PixelMap {
+        PixelMap::default()
+    }
+
+    /// Adds a strip of `pixels` pixels, starting at channel `start` of
+    /// `universe`.
+    ///
+    /// Pixels that do not fit into the rest of the universe continue at
+    /// channel 1 of the next universe; pixels are never split across
+    /// universes. Returns the range the strip's pixels occupy in the frame
+    /// buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the strip extends beyond universe 65535.
+    pub fn add_strip(
+        &mut self,
+        universe: u16,
+        start: DmxAddress,
+        pixels: usize,
+        order: ColorOrder,
+    ) -> Range<usize> {
+        let first = self.pixels;
+        let width = order.channels();
+
+        let mut universe = universe;
+        let mut offset = start.index();
+        let mut remaining = pixels;
+
+        while remaining > 0 {
+            let fit = ((MAX_CHANNELS - offset) / width).min(remaining);
+
+            if fit > 0 {
+                self.segments.push(Segment {
+                    universe,
+                    offset,
+                    first: first + pixels - remaining,
+                    pixels: fit,
+                    order,
+                });
+                remaining -= fit;
+            }
+
+            if remaining > 0 {
+                universe = universe.checked_add(1).expect("strip extends beyond universe 65535");
+                offset = 0;
+            }
+        }
+
+        self.pixels += pixels;
+        first..self.pixels
+    }
+
+    /// Reserves `pixels` pixels of the frame buffer, without mapping them to
+    /// any universe.
+    ///
+    /// For strips not addressed through universes, such as those of a DDP
+    /// controller, which are sent their range of the frame buffer directly.
+    /// Returns the range reserved.
+    #[inline]
+    pub fn reserve(&mut self, pixels: usize) -> Range<usize> {
+        let first = self.pixels;
+        self.pixels += pixels;
+        first..self.pixels
+    }
+
+    /// Returns the number of pixels of all strips.
+    #[inline]
+    pub fn pixel_count(&self) -> usize {
+        self.pixels
+    }
+
+    /// Returns the numbers of all universes pixels are mapped to, in
+    /// ascending order.
+    pub fn universes(&self) -> Vec<u16> {
+        let mut universes: Vec<u16> = self.segments.iter().map(|s| s.universe).collect();
+        universes.sort_unstable();
+        universes.dedup();
+        universes
+    }
+
+    /// Writes the pixels mapped to `universe` into `out`.
+    ///
+    /// Pixels missing from `frame` are written as black. Channels no pixel
+    /// is mapped to are left untouched.
+    pub fn render_universe(&self, frame: &[Color], universe: u16, out: &mut DmxUniverse) {
+        let channels = out.channels_mut();
+
+        for segment in self.segments.iter().filter(|s| s.universe == universe) {
+            let width = segment.order.channels();
+            let range = &mut channels[segment.offset..(segment.offset + segment.pixels * width)];
+
+            for (i, pixel) in range.chunks_mut(width).enumerate() {
+                let color = frame.get(segment.first + i).copied().unwrap_or_default();
+                segment.order.write(color, pixel);
+            }
+        }
+    }
+
+    /// Writes all pixels into `universes`, adding universes missing from it.
+    pub fn render(&self, frame: &[Color], universes: &mut BTreeMap<u16, DmxUniverse>) {
+        for universe in self.universes() {
+            self.render_universe(frame, universe, universes.entry(universe).or_default());
+        }
+    }
+
+    /// Writes all pixels into the universes of a `DmxOutputManager`.
+    ///
+    /// Pixels mapped to universes the manager does not send are skipped.
+    /// Each universe is updated at once, so no frame is sent with only part
+    /// of the pixels changed.
+    pub fn render_to(&self, frame: &[Color], outputs: &DmxOutputManager) {
+        for universe in self.universes() {
+            if let Some(handle) = outputs.universe(universe) {
+                handle.update(|out| self.render_universe(frame, universe, out));
+            }
+        }
+    }
+}
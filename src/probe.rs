@@ -0,0 +1,282 @@
+//! Timing self-test of serial ports.
+
+use std::ffi::OsStr;
+use std::path::Path;
+use std::{fmt, time};
+
+use crate::devices;
+use crate::serial::{
+    apply_settings, break_baud_rate, break_settings, dmx_settings, sleep_until, DMX_BAUD_RATE,
+};
+use crate::timing::{DmxTiming, MIN_BREAK_US};
+use crate::{Error, Result};
+
+/// Number of breaks sent while probing.
+const PROBE_ROUNDS: u32 = 16;
+
+/// Time the port needs to switch from the break rate back to 250,000 baud
+/// beyond which it is considered slow.
+const SLOW_RECONFIGURATION: time::Duration = time::Duration::from_millis(1);
+
+fn deviation(rate: u32) -> u32 {
+    rate.abs_diff(DMX_BAUD_RATE)
+}
+
+/// How well a port is suited for sending DMX.
+///
+/// Ordered from best to worst.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Support {
+    /// No problems were found.
+    Supported,
+    /// DMX can be sent, but timing may be off for some receivers.
+    Marginal,
+    /// The port cannot send valid DMX.
+    Unsupported,
+}
+
+/// A problem found by `probe_port`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ProbeProblem {
+    /// The port is the mini UART of a Raspberry Pi, see `Error::MiniUart`.
+    MiniUart,
+    /// The driver rejected 250,000 baud.
+    BaudRateRejected,
+    /// The driver set a baud rate too far from 250,000; holds the rate.
+    BaudRateDeviates(u32),
+    /// The driver rejected the baud rate used for breaks; holds the rate.
+    BreakRateRejected(u32),
+    /// Output was reported as drained before a break could have been sent.
+    ///
+    /// Common with USB adapters, which buffer data on the device. The baud
+    /// rate may be switched back before the break is complete, shortening
+    /// it, so `BreakMethod::Ioctl` should be preferred if available.
+    BreakNotDrained,
+    /// Switching back to 250,000 baud after a break took longer than a
+    /// millisecond; holds the longest time measured.
+    ///
+    /// The mark-after-break is stretched accordingly, which is allowed, but
+    /// lowers the frame rate.
+    SlowReconfiguration(time::Duration),
+}
+
+impl ProbeProblem {
+    /// Returns how severe the problem is.
+    ///
+    /// Baud rates deviating by more than 2% are outside of the standard's
+    /// tolerance, smaller deviations of more than 1% are marginal.
+    pub fn support(&self) -> Support {
+        match *self {
+            ProbeProblem::MiniUart
+            | ProbeProblem::BaudRateRejected
+            | ProbeProblem::BreakRateRejected(_) => Support::Unsupported,
+            ProbeProblem::BaudRateDeviates(rate) if deviation(rate) * 50 > DMX_BAUD_RATE => {
+                Support::Unsupported
+            }
+            _ => Support::Marginal,
+        }
+    }
+}
+
+impl fmt::Display for ProbeProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProbeProblem::MiniUart => f.write_str(
+                "port is the Raspberry Pi's mini UART, use the PL011 UART (/dev/ttyAMA0) instead",
+            ),
+            ProbeProblem::BaudRateRejected => f.write_str("driver does not support 250,000 baud"),
+            ProbeProblem::BaudRateDeviates(rate) => {
+                write!(f, "driver set {} baud instead of 250,000", rate)
+            }
+            ProbeProblem::BreakRateRejected(rate) => {
+                write!(f, "driver does not support {} baud, needed for breaks", rate)
+            }
+            ProbeProblem::BreakNotDrained => {
+                f.write_str("breaks may be cut short, the driver does not wait for output")
+            }
+            ProbeProblem::SlowReconfiguration(duration) => write!(
+                f,
+                "switching baud rates takes up to {} us, lowering the frame rate",
+                duration.as_micros()
+            ),
+        }
+    }
+}
+
+/// Results of `probe_port`.
+///
+/// Durations are measured on the host: a break lasts from writing the break
+/// byte until the driver reports it as sent, the mark-after-break from then
+/// until the port is back at 250,000 baud. They are only as accurate as the
+/// driver's reporting, but usually reveal broken setups.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProbeReport {
+    /// Overall result, the most severe of all problems.
+    pub support: Support,
+    /// Problems found, if any.
+    pub problems: Vec<ProbeProblem>,
+    /// Baud rate reported by the driver after requesting 250,000 baud, if
+    /// the platform reports it.
+    pub baud_rate: Option<u32>,
+    /// Baud rate used for breaks.
+    pub break_rate: u32,
+    /// Whether the driver can assert the break condition directly, see
+    /// `BreakMethod::Ioctl`.
+    pub ioctl_break: bool,
+    /// Shortest and longest break measured.
+    pub break_range: Option<(time::Duration, time::Duration)>,
+    /// Shortest and longest mark-after-break measured.
+    pub mab_range: Option<(time::Duration, time::Duration)>,
+    /// Mean time of a complete round trip, from reconfiguring the port for
+    /// the break until it is back at 250,000 baud.
+    pub round_trip: Option<time::Duration>,
+}
+
+impl ProbeReport {
+    fn add(&mut self, problem: ProbeProblem) {
+        self.support = self.support.max(problem.support());
+        self.problems.push(problem);
+    }
+}
+
+impl fmt::Display for ProbeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let support = match self.support {
+            Support::Supported => "supported",
+            Support::Marginal => "marginal",
+            Support::Unsupported => "unsupported",
+        };
+        writeln!(f, "support: {}", support)?;
+
+        if let Some(rate) = self.baud_rate {
+            writeln!(f, "baud rate: {}", rate)?;
+        }
+        writeln!(f, "break baud rate: {}", self.break_rate)?;
+        writeln!(f, "ioctl breaks: {}", if self.ioctl_break { "yes" } else { "no" })?;
+
+        let range = |(min, max): (time::Duration, time::Duration)| {
+            format!("{}-{} us", min.as_micros(), max.as_micros())
+        };
+        if let Some(r) = self.break_range {
+            writeln!(f, "break: {}", range(r))?;
+        }
+        if let Some(r) = self.mab_range {
+            writeln!(f, "mark-after-break: {}", range(r))?;
+        }
+        if let Some(r) = self.round_trip {
+            writeln!(f, "round trip: {} us", r.as_micros())?;
+        }
+
+        for problem in &self.problems {
+            writeln!(f, "problem: {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks whether a serial port is able to send DMX.
+///
+/// Configures the port for 250,000 baud and sends a series of breaks as
+/// `DmxPort` would with the default timing, measuring how long they take.
+/// Only fails if the port cannot be opened or configured at all; everything
+/// else is part of the report. Connected receivers see a few empty frames.
+///
+/// ```no_run
+/// let report = dmx::probe_port("/dev/ttyUSB0").unwrap();
+/// print!("{}", report);
+/// ```
+pub fn probe_port<T: AsRef<OsStr> + ?Sized>(port: &T) -> Result<ProbeReport> {
+    let path = Path::new(port);
+    let mut port = serial2::SerialPort::open(path, serial2::KeepSettings)?;
+    let current = port.get_configuration()?;
+
+    let timing = DmxTiming::default();
+    let break_rate = break_baud_rate(timing.break_us);
+
+    let mut report = ProbeReport {
+        support: Support::Supported,
+        problems: Vec::new(),
+        baud_rate: None,
+        break_rate,
+        ioctl_break: false,
+        break_range: None,
+        mab_range: None,
+        round_trip: None,
+    };
+    if devices::is_mini_uart(path) {
+        report.add(ProbeProblem::MiniUart);
+    }
+
+    let dmx = dmx_settings(current.clone())?;
+    if apply_settings(&mut port, &dmx).is_err() {
+        report.add(ProbeProblem::BaudRateRejected);
+        return Ok(report);
+    }
+
+    report.baud_rate = port.get_configuration()?.get_baud_rate().ok();
+    match report.baud_rate {
+        Some(rate) if deviation(rate) * 100 > DMX_BAUD_RATE => {
+            report.add(ProbeProblem::BaudRateDeviates(rate));
+        }
+        _ => (),
+    }
+
+    report.ioctl_break = port.set_break(true).and_then(|_| port.set_break(false)).is_ok();
+
+    let breaking = match break_settings(current, break_rate) {
+        Ok(settings) if apply_settings(&mut port, &settings).is_ok() => settings,
+        _ => {
+            report.add(ProbeProblem::BreakRateRejected(break_rate));
+            return Ok(report);
+        }
+    };
+
+    let mut breaks = Vec::new();
+    let mut mabs = Vec::new();
+    let mut round_trips = time::Duration::ZERO;
+
+    for _ in 0..PROBE_ROUNDS {
+        let start = time::Instant::now();
+        apply_settings(&mut port, &breaking).map_err(Error::BreakFailed)?;
+
+        let written = time::Instant::now();
+        port.write_all(&[0x00])?;
+        port.flush()?;
+        let drained = time::Instant::now();
+
+        apply_settings(&mut port, &dmx)?;
+        let reconfigured = time::Instant::now();
+
+        // a start code, to end the frame
+        port.write_all(&[0x00])?;
+        port.flush()?;
+
+        breaks.push(drained - written);
+        mabs.push(reconfigured - drained);
+        round_trips += reconfigured - start;
+
+        sleep_until(start + timing.inter_frame_duration());
+    }
+
+    let range = |durations: &[time::Duration]| {
+        let min = durations.iter().min()?;
+        let max = durations.iter().max()?;
+        Some((*min, *max))
+    };
+    report.break_range = range(&breaks);
+    report.mab_range = range(&mabs);
+    report.round_trip = Some(round_trips / PROBE_ROUNDS);
+
+    if let Some((min, _)) = report.break_range {
+        if min < time::Duration::from_micros(MIN_BREAK_US.into()) {
+            report.add(ProbeProblem::BreakNotDrained);
+        }
+    }
+    if let Some((_, max)) = report.mab_range {
+        if max > SLOW_RECONFIGURATION {
+            report.add(ProbeProblem::SlowReconfiguration(max));
+        }
+    }
+
+    Ok(report)
+}
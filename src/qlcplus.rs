@@ -0,0 +1,426 @@
+//! QLC+ fixture definition and workspace import.
+//!
+//! [QLC+](https://www.qlcplus.org/) ships a library of several thousand
+//! community-maintained fixture definitions, one XML file ending in `.qxf`
+//! per fixture. `open` reads such a file into a `FixtureProfile`, with the
+//! function and default value of each channel in each mode.
+//!
+//! Shows programmed in QLC+ are saved as workspaces, XML files ending in
+//! `.qxw`. `open_workspace` converts the scenes and chasers of a workspace
+//! into `Scene`s and `CueList`s, see `Workspace`.
+//!
+//! Functions are taken from the channel's preset if it has one, from its
+//! group and color otherwise. Channels without an equivalent `Attribute` are
+//! kept as `Attribute::Other`, named after the channel.
+//!
+//! ## Example
+//!
+//! ```
+//! use dmx::fixture::Attribute;
+//!
+//! let definition = r#"<?xml version="1.0" encoding="UTF-8"?>
+//! <!DOCTYPE FixtureDefinition>
+//! <FixtureDefinition xmlns="http://www.qlcplus.org/FixtureDefinition">
+//!  <Manufacturer>Generic</Manufacturer>
+//!  <Model>RGB Par</Model>
+//!  <Channel Name="Red" Preset="IntensityRed"/>
+//!  <Channel Name="Green" Preset="IntensityGreen"/>
+//!  <Channel Name="Blue">
+//!   <Group Byte="0">Intensity</Group>
+//!   <Colour>Blue</Colour>
+//!  </Channel>
+//!  <Channel Name="Macros" Default="10">
+//!   <Group Byte="0">Effect</Group>
+//!  </Channel>
+//!  <Mode Name="4 Channel">
+//!   <Channel Number="0">Red</Channel>
+//!   <Channel Number="1">Green</Channel>
+//!   <Channel Number="2">Blue</Channel>
+//!   <Channel Number="3">Macros</Channel>
+//!  </Mode>
+//! </FixtureDefinition>"#;
+//!
+//! let profile = dmx::qlcplus::from_definition(definition).unwrap();
+//! let mode = profile.mode("4 Channel").unwrap();
+//!
+//! assert_eq!(profile.model, "RGB Par");
+//! assert_eq!(mode.channels[2].attribute, Attribute::Blue);
+//! assert_eq!(mode.channels[3].attribute, Attribute::Effect);
+//! assert_eq!(mode.channels[3].default, 10);
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::address::DmxAddress;
+use crate::fixture::{Attribute, ChannelDef, FixtureMode, FixtureProfile};
+use crate::scenes::{Cue, CueList, Scene};
+use crate::xml::{self, Element};
+
+/// Reads the fixture profile of a `.qxf` file.
+///
+/// Fails with `InvalidData` if the file is not a valid fixture definition.
+pub fn open<P: AsRef<Path>>(path: P) -> io::Result<FixtureProfile> {
+    let definition = fs::read_to_string(path)?;
+
+    from_definition(&definition)
+}
+
+/// Reads a fixture profile from the contents of a `.qxf` file.
+///
+/// Fails with `InvalidData` if the document is not a valid fixture
+/// definition.
+pub fn from_definition(definition: &str) -> io::Result<FixtureProfile> {
+    let root = xml::parse(definition)?;
+    if root.name != "FixtureDefinition" {
+        return Err(invalid("not a QLC+ fixture definition"));
+    }
+
+    let text = |name| root.child(name).map(|e| e.text.trim()).unwrap_or_default();
+    let mut profile = FixtureProfile::new(text("Manufacturer"), text("Model"));
+
+    let channels: HashMap<&str, ChannelDef> = root
+        .children("Channel")
+        .map(|c| Ok((c.attr("Name").unwrap_or_default(), read_channel(c)?)))
+        .collect::<io::Result<_>>()?;
+
+    for element in root.children("Mode") {
+        let mut mode = FixtureMode::new(element.attr("Name").unwrap_or_default());
+
+        for channel in element.children("Channel") {
+            let number: usize = channel
+                .attr("Number")
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(|| invalid("invalid channel number"))?;
+            let def = channels
+                .get(channel.text.trim())
+                .ok_or_else(|| invalid("mode refers to an unknown channel"))?;
+
+            if mode.channels.len() <= number {
+                mode.channels.resize(
+                    number + 1,
+                    ChannelDef {
+                        attribute: Attribute::Other(String::new()),
+                        default: 0,
+                    },
+                );
+            }
+            mode.channels[number] = def.clone();
+        }
+
+        profile.add_mode(mode);
+    }
+
+    Ok(profile)
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_channel(channel: &Element) -> io::Result<ChannelDef> {
+    let name = channel.attr("Name").unwrap_or_default();
+
+    let default = match channel.attr("Default") {
+        Some(default) => default.trim().parse().map_err(|_| invalid("invalid default value"))?,
+        None => 0,
+    };
+
+    let attribute = match channel.attr("Preset") {
+        Some(preset) => preset_attribute(preset),
+        None => {
+            let group = channel.child("Group");
+            let fine = group.and_then(|g| g.attr("Byte")).is_some_and(|b| b.trim() != "0");
+            let colour = channel.child("Colour").map(|c| c.text.trim());
+
+            group_attribute(group.map(|g| g.text.trim()).unwrap_or_default(), colour, fine)
+        }
+    };
+
+    Ok(ChannelDef {
+        attribute: attribute.unwrap_or_else(|| Attribute::Other(name.to_owned())),
+        default,
+    })
+}
+
+/// Maps a channel preset onto an attribute.
+fn preset_attribute(preset: &str) -> Option<Attribute> {
+    Some(match preset {
+        "IntensityMasterDimmer" | "IntensityDimmer" => Attribute::Intensity,
+        "IntensityMasterDimmerFine" | "IntensityDimmerFine" => Attribute::IntensityFine,
+        "IntensityRed" => Attribute::Red,
+        "IntensityGreen" => Attribute::Green,
+        "IntensityBlue" => Attribute::Blue,
+        "IntensityWhite" => Attribute::White,
+        "IntensityAmber" => Attribute::Amber,
+        "IntensityUV" => Attribute::Uv,
+        "IntensityCyan" => Attribute::Cyan,
+        "IntensityMagenta" => Attribute::Magenta,
+        "IntensityYellow" => Attribute::Yellow,
+        "PositionPan" => Attribute::Pan,
+        "PositionPanFine" => Attribute::PanFine,
+        "PositionTilt" => Attribute::Tilt,
+        "PositionTiltFine" => Attribute::TiltFine,
+        "SpeedPanTiltSlowFast" | "SpeedPanTiltFastSlow" => Attribute::PanTiltSpeed,
+        "ColorWheel" => Attribute::ColorWheel,
+        "ColorCTOMixer" | "ColorCTCMixer" | "ColorCTBMixer" => Attribute::ColorTemperature,
+        "GoboWheel" => Attribute::Gobo,
+        "GoboIndex" => Attribute::GoboRotation,
+        "ShutterStrobeSlowFast" | "ShutterStrobeFastSlow" => Attribute::Strobe,
+        "ShutterIrisMinToMax" | "ShutterIrisMaxToMin" => Attribute::Iris,
+        "BeamFocusNearFar" | "BeamFocusFarNear" => Attribute::Focus,
+        "BeamZoomSmallBig" | "BeamZoomBigSmall" => Attribute::Zoom,
+        "PrismRotationSlowFast" | "PrismRotationFastSlow" => Attribute::Prism,
+        _ => return None,
+    })
+}
+
+/// Maps the group of a channel, and the color of intensity channels, onto an
+/// attribute.
+fn group_attribute(group: &str, colour: Option<&str>, fine: bool) -> Option<Attribute> {
+    Some(match (group, fine) {
+        ("Intensity", false) => match colour {
+            None | Some("") => Attribute::Intensity,
+            Some("Red") => Attribute::Red,
+            Some("Green") => Attribute::Green,
+            Some("Blue") => Attribute::Blue,
+            Some("White") => Attribute::White,
+            Some("Amber") => Attribute::Amber,
+            Some("UV") => Attribute::Uv,
+            Some("Cyan") => Attribute::Cyan,
+            Some("Magenta") => Attribute::Magenta,
+            Some("Yellow") => Attribute::Yellow,
+            Some(_) => return None,
+        },
+        ("Intensity", true) if colour.is_none_or(str::is_empty) => Attribute::IntensityFine,
+        ("Pan", false) => Attribute::Pan,
+        ("Pan", true) => Attribute::PanFine,
+        ("Tilt", false) => Attribute::Tilt,
+        ("Tilt", true) => Attribute::TiltFine,
+        ("Colour", false) => Attribute::ColorWheel,
+        ("Gobo", false) => Attribute::Gobo,
+        ("Prism", false) => Attribute::Prism,
+        ("Shutter", false) => Attribute::Strobe,
+        ("Effect", false) => Attribute::Effect,
+        ("Maintenance", false) => Attribute::Control,
+        _ => return None,
+    })
+}
+
+/// Time QLC+ stores for steps that are held until triggered manually, in
+/// milliseconds; higher times are special values as well.
+const INFINITE: u32 = 0xffff_fffe;
+
+/// The scenes and chasers of a QLC+ workspace, for a single universe.
+///
+/// Scene values are placed at the addresses of the fixtures they belong to;
+/// channels a scene does not set are zero. Each step of a chaser becomes a
+/// cue fading in to the step's scene, following on after the step's
+/// duration unless it is held until triggered. Chasers running backwards
+/// have their steps reversed. Steps of other functions, such as nested
+/// chasers or EFX, are skipped, as are the run order and fade out times.
+/// Functions sharing a name replace those before them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Workspace {
+    /// Scenes, by name.
+    pub scenes: BTreeMap<String, Scene>,
+    /// Chasers, as cue lists, by name.
+    pub chasers: BTreeMap<String, CueList>,
+}
+
+/// Reads the scenes and chasers of a `.qxw` file, for `universe`.
+///
+/// Universes are numbered from 0, as in the file; the first universe in
+/// QLC+ is 0. Fails with `InvalidData` if the file is not a valid workspace.
+pub fn open_workspace<P: AsRef<Path>>(path: P, universe: u32) -> io::Result<Workspace> {
+    let document = fs::read_to_string(path)?;
+
+    from_workspace(&document, universe)
+}
+
+/// Reads the scenes and chasers of the contents of a `.qxw` file.
+///
+/// See `open_workspace`.
+///
+/// ```
+/// let document = r#"<?xml version="1.0" encoding="UTF-8"?>
+/// <!DOCTYPE Workspace>
+/// <Workspace xmlns="http://www.qlcplus.org/Workspace">
+///  <Engine>
+///   <Fixture>
+///    <ID>0</ID>
+///    <Universe>0</Universe>
+///    <Address>10</Address>
+///    <Channels>3</Channels>
+///   </Fixture>
+///   <Function ID="0" Type="Scene" Name="Red">
+///    <Speed FadeIn="1000" FadeOut="0" Duration="0"/>
+///    <FixtureVal ID="0">0,255,1,0,2,0</FixtureVal>
+///   </Function>
+///   <Function ID="1" Type="Chaser" Name="Flash">
+///    <Speed FadeIn="0" FadeOut="0" Duration="500"/>
+///    <SpeedModes FadeIn="Default" FadeOut="Default" Duration="Common"/>
+///    <Step Number="0">0</Step>
+///   </Function>
+///  </Engine>
+/// </Workspace>"#;
+///
+/// let workspace = dmx::qlcplus::from_workspace(document, 0).unwrap();
+/// let red = &workspace.scenes["Red"];
+/// assert_eq!(red.universe().channels()[10..13], [0xff, 0x00, 0x00]);
+///
+/// let step = workspace.chasers["Flash"].get(0).unwrap();
+/// assert_eq!(step.fade.as_millis(), 1000);
+/// assert_eq!(step.follow.unwrap().as_millis(), 500);
+/// ```
+pub fn from_workspace(document: &str, universe: u32) -> io::Result<Workspace> {
+    let root = xml::parse(document)?;
+    if root.name != "Workspace" {
+        return Err(invalid("not a QLC+ workspace"));
+    }
+    let engine = root
+        .child("Engine")
+        .ok_or_else(|| invalid("workspace without an engine"))?;
+
+    // start addresses of the fixtures in the universe, by id
+    let mut fixtures = HashMap::new();
+    for fixture in engine.children("Fixture") {
+        let number = |name| -> io::Result<u32> {
+            let text = fixture.child(name).map(|e| e.text.trim()).unwrap_or_default();
+            text.parse().map_err(|_| invalid("invalid fixture"))
+        };
+
+        if number("Universe")? == universe {
+            fixtures.insert(number("ID")?, number("Address")? as usize);
+        }
+    }
+
+    let mut workspace = Workspace::default();
+    let mut scenes = HashMap::new();
+
+    for function in engine.children("Function") {
+        if function.attr("Type") != Some("Scene") {
+            continue;
+        }
+
+        let mut scene = Scene::new();
+        for values in function.children("FixtureVal") {
+            let start = match values.attr("ID").and_then(|id| id.parse().ok()) {
+                Some(id) => fixtures.get(&id),
+                None => return Err(invalid("invalid fixture id")),
+            };
+            let start = match start {
+                Some(&start) => start,
+                None => continue,
+            };
+
+            let numbers = values
+                .text
+                .split(',')
+                .filter(|n| !n.trim().is_empty())
+                .map(|n| n.trim().parse::<usize>().map_err(|_| invalid("invalid scene value")))
+                .collect::<io::Result<Vec<_>>>()?;
+
+            for pair in numbers.chunks_exact(2) {
+                let value = u8::try_from(pair[1]).map_err(|_| invalid("invalid scene value"))?;
+                if let Some(n) = DmxAddress::from_index(start + pair[0]) {
+                    scene.set(n, value);
+                }
+            }
+        }
+
+        let speed = read_speed(function.child("Speed"))?;
+        scenes.insert(function.attr("ID").unwrap_or_default(), (scene.clone(), speed));
+        workspace
+            .scenes
+            .insert(function.attr("Name").unwrap_or_default().to_owned(), scene);
+    }
+
+    for function in engine.children("Function") {
+        if function.attr("Type") != Some("Chaser") {
+            continue;
+        }
+
+        let common = read_speed(function.child("Speed"))?;
+        let modes = function.child("SpeedModes");
+        let mode = |name| modes.and_then(|m| m.attr(name)).unwrap_or("Default");
+
+        let mut steps = function
+            .children("Step")
+            .map(|step| {
+                let number: u32 = step.attr("Number").and_then(|n| n.parse().ok()).unwrap_or(0);
+                Ok((number, step, read_speed(Some(step))?))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        steps.sort_by_key(|&(number, _, _)| number);
+        if function.child("Direction").map(|d| d.text.trim()) == Some("Backward") {
+            steps.reverse();
+        }
+
+        let mut cues = CueList::new();
+        for (_, step, own) in steps {
+            let (scene, default) = match scenes.get(step.text.trim()) {
+                Some(scene) => scene,
+                None => continue,
+            };
+
+            let pick = |mode| match mode {
+                "Common" => common,
+                "PerStep" => own,
+                _ => *default,
+            };
+            let fade_in = pick(mode("FadeIn")).fade_in;
+            let duration = pick(mode("Duration")).duration;
+
+            let mut cue = Cue::new(scene.clone(), millis(fade_in));
+            cue.follow = duration.filter(|&d| d < INFINITE).map(millis);
+            cues.push(cue);
+        }
+
+        workspace
+            .chasers
+            .insert(function.attr("Name").unwrap_or_default().to_owned(), cues);
+    }
+
+    Ok(workspace)
+}
+
+/// Fade in time and duration of a function or chaser step, in milliseconds.
+#[derive(Copy, Clone, Debug, Default)]
+struct Speed {
+    fade_in: u32,
+    duration: Option<u32>,
+}
+
+fn read_speed(element: Option<&Element>) -> io::Result<Speed> {
+    let element = match element {
+        Some(element) => element,
+        None => return Ok(Speed::default()),
+    };
+    let time = |name| -> io::Result<Option<u32>> {
+        match element.attr(name) {
+            Some(t) => t.trim().parse().map(Some).map_err(|_| invalid("invalid time")),
+            None => Ok(None),
+        }
+    };
+
+    let fade_in = time("FadeIn")?.unwrap_or(0);
+    // steps store the time held after fading in, functions the sum of both
+    let duration = match (time("Duration")?, time("Hold")?) {
+        (Some(duration), _) => Some(duration),
+        (None, Some(hold)) if hold >= INFINITE => Some(hold),
+        (None, Some(hold)) => Some(fade_in.saturating_add(hold)),
+        (None, None) => None,
+    };
+
+    Ok(Speed { fade_in, duration })
+}
+
+#[inline]
+fn millis(ms: u32) -> Duration {
+    Duration::from_millis(ms.into())
+}
@@ -0,0 +1,393 @@
+//! RDM controller.
+
+use std::time;
+
+use super::{
+    decode_discovery_response, decode_label, encode_label, CommandClass, DeviceInfo, Personality,
+    PersonalityDescription, RdmRequest, RdmResponse, ResponseType, Uid, MAX_PACKET_LEN,
+    PID_DEVICE_INFO, PID_DEVICE_LABEL, PID_DISC_MUTE, PID_DISC_UNIQUE_BRANCH, PID_DISC_UN_MUTE,
+    PID_DMX_PERSONALITY, PID_DMX_PERSONALITY_DESCRIPTION, PID_DMX_START_ADDRESS,
+    PID_IDENTIFY_DEVICE, PID_SOFTWARE_VERSION_LABEL, SC_RDM,
+};
+use crate::{DmxTransceiver, Error, Result};
+
+/// Default time to wait for a response.
+///
+/// Responders must reply within 2 ms, the rest allows for operating system
+/// and USB latencies.
+pub const DEFAULT_RESPONSE_TIMEOUT: time::Duration = time::Duration::from_millis(30);
+
+/// Result of a discovery unique branch request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DiscoveryResponse {
+    /// No unmuted device within the branch.
+    None,
+    /// A single device responded.
+    Device(Uid),
+    /// Data was received, but could not be decoded. Usually caused by
+    /// several devices responding at once.
+    Collision,
+}
+
+/// An RDM controller.
+///
+/// Sends requests through a `DmxTransceiver` and waits for the responses.
+/// Regular DMX packets can still be sent through `get_mut` in between.
+#[derive(Debug)]
+pub struct RdmController<T> {
+    port: T,
+    uid: Uid,
+    transaction: u8,
+    timeout: time::Duration,
+}
+
+impl<T: DmxTransceiver> RdmController<T> {
+    /// Create a controller sending requests with the given UID.
+    #[inline]
+    pub fn new(port: T, uid: Uid) -> RdmController<T> {
+        RdmController {
+            port,
+            uid,
+            transaction: 0,
+            timeout: DEFAULT_RESPONSE_TIMEOUT,
+        }
+    }
+
+    /// Returns the UID of the controller.
+    #[inline]
+    pub fn uid(&self) -> Uid {
+        self.uid
+    }
+
+    /// Sets the time to wait for responses.
+    #[inline]
+    pub fn set_response_timeout(&mut self, timeout: time::Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns a mutable reference to the underlying port.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.port
+    }
+
+    /// Returns the underlying port.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.port
+    }
+
+    /// Sends a request without waiting for a response.
+    fn transmit(
+        &mut self,
+        destination: Uid,
+        sub_device: u16,
+        command_class: CommandClass,
+        parameter_id: u16,
+        data: &[u8],
+    ) -> Result<u8> {
+        let transaction = self.transaction;
+        self.transaction = self.transaction.wrapping_add(1);
+
+        let request = RdmRequest {
+            destination,
+            source: self.uid,
+            transaction,
+            port_id: 1,
+            sub_device,
+            command_class,
+            parameter_id,
+            data,
+        };
+        self.transmit_request(&request)?;
+
+        Ok(transaction)
+    }
+
+    fn transmit_request(&mut self, request: &RdmRequest<'_>) -> Result<()> {
+        let mut buf = [0; MAX_PACKET_LEN];
+        let len = request.encode(&mut buf);
+
+        // drop stale data, so it is not mistaken for the response
+        self.port.discard_input()?;
+        self.port.send_raw_dmx_packet(&buf[..len])?;
+
+        Ok(())
+    }
+
+    /// Waits for the response to a request sent.
+    fn receive(&mut self, transaction: u8, destination: Uid) -> Result<Option<RdmResponse>> {
+        let mut buf = [0; MAX_PACKET_LEN * 2];
+        let len = self.port.recv_raw_data(&mut buf, self.timeout)?;
+
+        // the response may be preceded by a break or line noise
+        Ok(buf[..len]
+            .iter()
+            .enumerate()
+            .filter(|&(_, &b)| b == SC_RDM)
+            .filter_map(|(i, _)| RdmResponse::decode(&buf[i..len]))
+            .find(|r| r.transaction == transaction && r.source == destination))
+    }
+
+    /// Sends a request and waits for the response.
+    ///
+    /// Returns `None` if the device did not respond in time. Requests to
+    /// broadcast addresses are never answered, so no response is waited for.
+    pub fn send_request(
+        &mut self,
+        destination: Uid,
+        sub_device: u16,
+        command_class: CommandClass,
+        parameter_id: u16,
+        data: &[u8],
+    ) -> Result<Option<RdmResponse>> {
+        let transaction =
+            self.transmit(destination, sub_device, command_class, parameter_id, data)?;
+
+        if destination.is_broadcast() {
+            return Ok(None);
+        }
+
+        self.receive(transaction, destination)
+    }
+
+    /// Sends a request of another controller and waits for the response.
+    ///
+    /// Unlike `send_request`, the request is sent unchanged, including its
+    /// source UID and transaction number, so the response can be passed on
+    /// to the controller it is meant for. Used to proxy requests received
+    /// over the network, see `artnet::ArtRdmGateway`.
+    pub fn forward(&mut self, request: &RdmRequest<'_>) -> Result<Option<RdmResponse>> {
+        self.transmit_request(request)?;
+
+        if request.destination.is_broadcast() {
+            return Ok(None);
+        }
+
+        self.receive(request.transaction, request.destination)
+    }
+
+    /// Reads a parameter of a device's root.
+    ///
+    /// Fails with `Error::Timeout` if the device does not respond.
+    #[inline]
+    pub fn get(&mut self, destination: Uid, parameter_id: u16, data: &[u8]) -> Result<RdmResponse> {
+        self.send_request(destination, 0, CommandClass::Get, parameter_id, data)?
+            .ok_or(Error::Timeout)
+    }
+
+    /// Changes a parameter of a device's root.
+    ///
+    /// Fails with `Error::Timeout` if the device does not respond.
+    #[inline]
+    pub fn set(&mut self, destination: Uid, parameter_id: u16, data: &[u8]) -> Result<RdmResponse> {
+        self.send_request(destination, 0, CommandClass::Set, parameter_id, data)?
+            .ok_or(Error::Timeout)
+    }
+
+    /// Reads the device information of a device.
+    pub fn device_info(&mut self, uid: Uid) -> Result<DeviceInfo> {
+        let response = acknowledged(self.get(uid, PID_DEVICE_INFO, &[])?)?;
+
+        DeviceInfo::from_bytes(response.data())
+            .ok_or(Error::InvalidResponse("short device info response"))
+    }
+
+    /// Reads the DMX start address of a device.
+    ///
+    /// Returns `None` for devices without any DMX channels.
+    pub fn start_address(&mut self, uid: Uid) -> Result<Option<u16>> {
+        let response = acknowledged(self.get(uid, PID_DMX_START_ADDRESS, &[])?)?;
+
+        match *response.data() {
+            [high, low, ..] => {
+                let address = u16::from_be_bytes([high, low]);
+                Ok(Some(address).filter(|&a| a != 0xffff))
+            }
+            _ => Err(Error::InvalidResponse("short start address response")),
+        }
+    }
+
+    /// Changes the DMX start address of a device.
+    ///
+    /// Fails with `Error::InvalidParameter` unless `address` is in the range
+    /// of 1 to 512.
+    pub fn set_start_address(&mut self, uid: Uid, address: u16) -> Result<()> {
+        if !(1..=512).contains(&address) {
+            return Err(Error::InvalidParameter("start address out of range 1-512"));
+        }
+
+        self.set_acknowledged(uid, PID_DMX_START_ADDRESS, &address.to_be_bytes())
+    }
+
+    /// Returns whether a device is identifying itself.
+    pub fn identify(&mut self, uid: Uid) -> Result<bool> {
+        let response = acknowledged(self.get(uid, PID_IDENTIFY_DEVICE, &[])?)?;
+
+        match response.data().first() {
+            Some(&on) => Ok(on != 0),
+            None => Err(Error::InvalidResponse("short identify response")),
+        }
+    }
+
+    /// Makes a device identify itself, usually by flashing a light, or
+    /// stop doing so.
+    pub fn set_identify(&mut self, uid: Uid, on: bool) -> Result<()> {
+        self.set_acknowledged(uid, PID_IDENTIFY_DEVICE, &[u8::from(on)])
+    }
+
+    /// Reads the user-assigned label of a device.
+    pub fn device_label(&mut self, uid: Uid) -> Result<String> {
+        let response = acknowledged(self.get(uid, PID_DEVICE_LABEL, &[])?)?;
+
+        Ok(decode_label(response.data()))
+    }
+
+    /// Changes the label of a device, truncated to 32 bytes.
+    pub fn set_device_label(&mut self, uid: Uid, label: &str) -> Result<()> {
+        self.set_acknowledged(uid, PID_DEVICE_LABEL, encode_label(label))
+    }
+
+    /// Reads the description of the software version of a device.
+    pub fn software_version_label(&mut self, uid: Uid) -> Result<String> {
+        let response = acknowledged(self.get(uid, PID_SOFTWARE_VERSION_LABEL, &[])?)?;
+
+        Ok(decode_label(response.data()))
+    }
+
+    /// Reads the current personality of a device.
+    pub fn personality(&mut self, uid: Uid) -> Result<Personality> {
+        let response = acknowledged(self.get(uid, PID_DMX_PERSONALITY, &[])?)?;
+
+        Personality::from_bytes(response.data())
+            .ok_or(Error::InvalidResponse("short personality response"))
+    }
+
+    /// Changes the personality of a device, starting at 1.
+    pub fn set_personality(&mut self, uid: Uid, personality: u8) -> Result<()> {
+        self.set_acknowledged(uid, PID_DMX_PERSONALITY, &[personality])
+    }
+
+    /// Reads the description of a personality of a device, starting at 1.
+    pub fn personality_description(
+        &mut self,
+        uid: Uid,
+        personality: u8,
+    ) -> Result<PersonalityDescription> {
+        let response =
+            acknowledged(self.get(uid, PID_DMX_PERSONALITY_DESCRIPTION, &[personality])?)?;
+
+        PersonalityDescription::from_bytes(response.data())
+            .ok_or(Error::InvalidResponse("short personality description response"))
+    }
+
+    /// Changes a parameter of a device's root, failing unless the request is
+    /// acknowledged.
+    ///
+    /// Broadcasts are not answered, so they succeed once sent.
+    fn set_acknowledged(&mut self, uid: Uid, parameter_id: u16, data: &[u8]) -> Result<()> {
+        match self.send_request(uid, 0, CommandClass::Set, parameter_id, data)? {
+            Some(response) => acknowledged(response).map(|_| ()),
+            None if uid.is_broadcast() => Ok(()),
+            None => Err(Error::Timeout),
+        }
+    }
+
+    /// Mutes a device, excluding it from further discovery.
+    ///
+    /// Returns whether the device acknowledged; broadcasts are never
+    /// acknowledged.
+    pub fn mute(&mut self, uid: Uid) -> Result<bool> {
+        let response = self.send_request(uid, 0, CommandClass::Discovery, PID_DISC_MUTE, &[])?;
+
+        Ok(response.is_some_and(|r| r.response_type == ResponseType::Ack))
+    }
+
+    /// Unmutes a device, making it take part in discovery again.
+    ///
+    /// Returns whether the device acknowledged; broadcasts are never
+    /// acknowledged.
+    pub fn unmute(&mut self, uid: Uid) -> Result<bool> {
+        let response = self.send_request(uid, 0, CommandClass::Discovery, PID_DISC_UN_MUTE, &[])?;
+
+        Ok(response.is_some_and(|r| r.response_type == ResponseType::Ack))
+    }
+
+    /// Asks all unmuted devices with a UID between `lower` and `upper`
+    /// (inclusive) to identify themselves.
+    pub fn unique_branch(&mut self, lower: Uid, upper: Uid) -> Result<DiscoveryResponse> {
+        let mut data = [0; 12];
+        data[..6].copy_from_slice(&lower.to_bytes());
+        data[6..].copy_from_slice(&upper.to_bytes());
+
+        self.transmit(
+            Uid::BROADCAST,
+            0,
+            CommandClass::Discovery,
+            PID_DISC_UNIQUE_BRANCH,
+            &data,
+        )?;
+
+        let mut buf = [0; MAX_PACKET_LEN];
+        let len = self.port.recv_raw_data(&mut buf, self.timeout)?;
+
+        if len == 0 {
+            return Ok(DiscoveryResponse::None);
+        }
+
+        Ok(decode_discovery_response(&buf[..len])
+            .map_or(DiscoveryResponse::Collision, DiscoveryResponse::Device))
+    }
+
+    /// Discovers all devices on the line.
+    ///
+    /// Unmutes all devices, then narrows down the UID space through a binary
+    /// search, muting every device found. Returns the UIDs of all devices
+    /// that acknowledged muting.
+    pub fn discover(&mut self) -> Result<Vec<Uid>> {
+        self.unmute(Uid::BROADCAST)?;
+
+        let mut found = Vec::new();
+        let mut branches = vec![(0, Uid::BROADCAST.as_u64())];
+
+        while let Some((lower, upper)) = branches.pop() {
+            loop {
+                let uid = match self.unique_branch(Uid(lower), Uid(upper))? {
+                    DiscoveryResponse::None => break,
+                    DiscoveryResponse::Device(uid) => Some(uid),
+                    DiscoveryResponse::Collision => None,
+                };
+
+                // a muted device no longer responds, so there may be more
+                // devices in the same branch
+                if let Some(uid) = uid {
+                    if self.mute(uid)? {
+                        if !found.contains(&uid) {
+                            found.push(uid);
+                        }
+                        continue;
+                    }
+                }
+
+                // collision, or a garbled response that happened to decode
+                if lower != upper {
+                    let mid = lower + (upper - lower) / 2;
+                    branches.push((mid + 1, upper));
+                    branches.push((lower, mid));
+                }
+                break;
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+/// Fails unless a response acknowledges the request.
+fn acknowledged(response: RdmResponse) -> Result<RdmResponse> {
+    match response.response_type {
+        ResponseType::Ack => Ok(response),
+        ResponseType::NackReason => Err(Error::Nack(response.nack_reason().unwrap_or(0))),
+        _ => Err(Error::Unsupported("deferred RDM responses")),
+    }
+}
@@ -0,0 +1,975 @@
+//! Remote Device Management (RDM) support.
+//!
+//! [RDM](https://tsp.esta.org/tsp/documents/docs/ANSI-ESTA_E1-20_2010.pdf)
+//! (ANSI E1.20) extends DMX with a bidirectional protocol, allowing a
+//! controller to discover devices on the line and query or change their
+//! settings. Requests and responses are sent as DMX packets with the start
+//! code `0xCC`, in between regular DMX packets. Every device is identified by
+//! a 48-bit unique ID, consisting of a manufacturer and a device ID.
+//!
+//! Sending requests requires a port that can also receive, see
+//! `DmxTransceiver`. The line driver must be turned around quickly after
+//! sending and the port must not read back its own transmission.
+//!
+//! `RdmResponder` emulates a device, answering the requests of a controller.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use dmx::DmxPort;
+//! use dmx::rdm::{RdmController, Uid};
+//!
+//! let port = DmxPort::open("/dev/ttyUSB0").unwrap();
+//! let mut controller = RdmController::new(port, Uid::new(0x7ff0, 1));
+//!
+//! for uid in controller.discover().unwrap() {
+//!     let info = controller.device_info(uid).unwrap();
+//!     println!("{}: {} channels at {}", uid, info.footprint, info.start_address);
+//! }
+//!
+//! // move the first device to channel 101 and make it flash
+//! let uid = controller.discover().unwrap()[0];
+//! controller.set_start_address(uid, 101).unwrap();
+//! controller.set_identify(uid, true).unwrap();
+//! ```
+
+use std::{fmt, time};
+
+mod controller;
+mod responder;
+
+pub use self::controller::{DiscoveryResponse, RdmController, DEFAULT_RESPONSE_TIMEOUT};
+pub use self::responder::{RdmReply, RdmResponder};
+
+/// DMX start code of RDM packets.
+pub const SC_RDM: u8 = 0xcc;
+
+/// Sub-start code of RDM packets.
+pub const SC_SUB_MESSAGE: u8 = 0x01;
+
+/// Maximum length of parameter data.
+pub const MAX_PARAMETER_DATA_LEN: usize = 231;
+
+// start codes, message length, UIDs, transaction number, port ID/response
+// type, message count, sub-device, command class, parameter ID and length
+const HEADER_LEN: usize = 24;
+
+/// Maximum length of an RDM packet, including start code and checksum.
+pub const MAX_PACKET_LEN: usize = HEADER_LEN + MAX_PARAMETER_DATA_LEN + 2;
+
+/// Parameter ID: discovery unique branch.
+pub const PID_DISC_UNIQUE_BRANCH: u16 = 0x0001;
+/// Parameter ID: discovery mute.
+pub const PID_DISC_MUTE: u16 = 0x0002;
+/// Parameter ID: discovery unmute.
+pub const PID_DISC_UN_MUTE: u16 = 0x0003;
+/// Parameter ID: device info.
+pub const PID_DEVICE_INFO: u16 = 0x0060;
+/// Parameter ID: device label.
+pub const PID_DEVICE_LABEL: u16 = 0x0082;
+/// Parameter ID: software version label.
+pub const PID_SOFTWARE_VERSION_LABEL: u16 = 0x00c0;
+/// Parameter ID: DMX512 personality.
+pub const PID_DMX_PERSONALITY: u16 = 0x00e0;
+/// Parameter ID: DMX512 personality description.
+pub const PID_DMX_PERSONALITY_DESCRIPTION: u16 = 0x00e1;
+/// Parameter ID: DMX512 start address.
+pub const PID_DMX_START_ADDRESS: u16 = 0x00f0;
+/// Parameter ID: identify device.
+pub const PID_IDENTIFY_DEVICE: u16 = 0x1000;
+
+/// NACK reason: the parameter is not supported.
+pub const NR_UNKNOWN_PID: u16 = 0x0000;
+/// NACK reason: the parameter data is malformed.
+pub const NR_FORMAT_ERROR: u16 = 0x0001;
+/// NACK reason: the parameter cannot be read or changed.
+pub const NR_UNSUPPORTED_COMMAND_CLASS: u16 = 0x0005;
+/// NACK reason: the parameter data is out of range.
+pub const NR_DATA_OUT_OF_RANGE: u16 = 0x0006;
+/// NACK reason: the addressed sub-device does not exist.
+pub const NR_SUB_DEVICE_OUT_OF_RANGE: u16 = 0x0009;
+
+/// Maximum length of labels, such as the device label.
+pub const MAX_LABEL_LEN: usize = 32;
+
+// separates the preamble from the encoded UID in discovery responses
+const DISCOVERY_SEPARATOR: u8 = 0xaa;
+
+/// A device's unique ID.
+///
+/// Consists of a 16-bit ESTA manufacturer ID and a 32-bit device ID.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Uid(u64);
+
+impl Uid {
+    /// Address of all devices.
+    pub const BROADCAST: Uid = Uid(0xffff_ffff_ffff);
+
+    /// Create a UID from manufacturer and device ID.
+    #[inline]
+    pub fn new(manufacturer: u16, device: u32) -> Uid {
+        Uid(u64::from(manufacturer) << 32 | u64::from(device))
+    }
+
+    /// Create a UID addressing all devices of a manufacturer.
+    #[inline]
+    pub fn manufacturer_broadcast(manufacturer: u16) -> Uid {
+        Uid::new(manufacturer, 0xffff_ffff)
+    }
+
+    /// Create a UID from its 48-bit numeric representation.
+    ///
+    /// Returns `None` if `value` exceeds 48 bits.
+    #[inline]
+    pub fn from_u64(value: u64) -> Option<Uid> {
+        if value > Uid::BROADCAST.0 {
+            return None;
+        }
+
+        Some(Uid(value))
+    }
+
+    /// Returns the 48-bit numeric representation.
+    #[inline]
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+
+    /// Returns the manufacturer ID.
+    #[inline]
+    pub fn manufacturer(&self) -> u16 {
+        (self.0 >> 32) as u16
+    }
+
+    /// Returns the device ID.
+    #[inline]
+    pub fn device(&self) -> u32 {
+        self.0 as u32
+    }
+
+    /// Returns whether the UID addresses more than one device.
+    #[inline]
+    pub fn is_broadcast(&self) -> bool {
+        self.device() == 0xffff_ffff
+    }
+
+    /// Create a UID from its wire format.
+    #[inline]
+    pub fn from_bytes(bytes: [u8; 6]) -> Uid {
+        let mut buf = [0; 8];
+        buf[2..].copy_from_slice(&bytes);
+        Uid(u64::from_be_bytes(buf))
+    }
+
+    /// Returns the wire format of the UID.
+    #[inline]
+    pub fn to_bytes(&self) -> [u8; 6] {
+        let mut bytes = [0; 6];
+        bytes.copy_from_slice(&self.0.to_be_bytes()[2..]);
+        bytes
+    }
+}
+
+impl fmt::Display for Uid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04x}:{:08x}", self.manufacturer(), self.device())
+    }
+}
+
+/// Command class of an RDM message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommandClass {
+    /// Discovery request.
+    Discovery,
+    /// Response to a discovery request.
+    DiscoveryResponse,
+    /// Request to read a parameter.
+    Get,
+    /// Response to a get request.
+    GetResponse,
+    /// Request to change a parameter.
+    Set,
+    /// Response to a set request.
+    SetResponse,
+}
+
+impl CommandClass {
+    /// Create a command class from its numeric value.
+    pub fn from_u8(value: u8) -> Option<CommandClass> {
+        Some(match value {
+            0x10 => CommandClass::Discovery,
+            0x11 => CommandClass::DiscoveryResponse,
+            0x20 => CommandClass::Get,
+            0x21 => CommandClass::GetResponse,
+            0x30 => CommandClass::Set,
+            0x31 => CommandClass::SetResponse,
+            _ => return None,
+        })
+    }
+
+    /// Returns the numeric value.
+    pub fn as_u8(&self) -> u8 {
+        match *self {
+            CommandClass::Discovery => 0x10,
+            CommandClass::DiscoveryResponse => 0x11,
+            CommandClass::Get => 0x20,
+            CommandClass::GetResponse => 0x21,
+            CommandClass::Set => 0x30,
+            CommandClass::SetResponse => 0x31,
+        }
+    }
+}
+
+/// Type of an RDM response.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResponseType {
+    /// The request was processed.
+    Ack,
+    /// The request will be processed later, see `RdmResponse::ack_timer`.
+    AckTimer,
+    /// The request was rejected, see `RdmResponse::nack_reason`.
+    NackReason,
+    /// More data is available than fits into a single response.
+    AckOverflow,
+}
+
+impl ResponseType {
+    /// Create a response type from its numeric value.
+    pub fn from_u8(value: u8) -> Option<ResponseType> {
+        Some(match value {
+            0x00 => ResponseType::Ack,
+            0x01 => ResponseType::AckTimer,
+            0x02 => ResponseType::NackReason,
+            0x03 => ResponseType::AckOverflow,
+            _ => return None,
+        })
+    }
+
+    /// Returns the numeric value.
+    pub fn as_u8(&self) -> u8 {
+        match *self {
+            ResponseType::Ack => 0x00,
+            ResponseType::AckTimer => 0x01,
+            ResponseType::NackReason => 0x02,
+            ResponseType::AckOverflow => 0x03,
+        }
+    }
+}
+
+/// Calculates the checksum of an RDM packet.
+///
+/// `data` must include everything from the start code up to the end of the
+/// parameter data.
+pub fn checksum(data: &[u8]) -> u16 {
+    data.iter()
+        .fold(0u16, |sum, &b| sum.wrapping_add(u16::from(b)))
+}
+
+/// An RDM request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RdmRequest<'a> {
+    /// Addressed device.
+    pub destination: Uid,
+    /// UID of the controller.
+    pub source: Uid,
+    /// Transaction number, echoed in the response.
+    pub transaction: u8,
+    /// Port of the controller, starting at 1.
+    pub port_id: u8,
+    /// Addressed sub-device, `0` for the root device.
+    pub sub_device: u16,
+    /// Whether to discover, read or change a parameter.
+    pub command_class: CommandClass,
+    /// Parameter ID.
+    pub parameter_id: u16,
+    /// Parameter data.
+    pub data: &'a [u8],
+}
+
+impl<'a> RdmRequest<'a> {
+    /// Decodes a request packet, starting at the start code.
+    ///
+    /// Trailing data after the checksum is ignored. Returns `None` if the
+    /// packet is malformed, truncated or its checksum does not match.
+    pub fn decode(packet: &'a [u8]) -> Option<RdmRequest<'a>> {
+        let len = validate(packet)?;
+
+        let mut uid = [0; 6];
+        uid.copy_from_slice(&packet[3..9]);
+        let destination = Uid::from_bytes(uid);
+        uid.copy_from_slice(&packet[9..15]);
+        let source = Uid::from_bytes(uid);
+
+        Some(RdmRequest {
+            destination,
+            source,
+            transaction: packet[15],
+            port_id: packet[16],
+            sub_device: u16::from_be_bytes([packet[18], packet[19]]),
+            command_class: CommandClass::from_u8(packet[20])?,
+            parameter_id: u16::from_be_bytes([packet[21], packet[22]]),
+            data: &packet[HEADER_LEN..len],
+        })
+    }
+
+    /// Encodes the request into `buf`, including start code and checksum.
+    ///
+    /// Returns the length of the packet, which can be sent using
+    /// `DmxTransmitter::send_raw_dmx_packet`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameter data exceeds 231 bytes or `buf` is too short
+    /// for the packet; a buffer of `MAX_PACKET_LEN` bytes is always large
+    /// enough.
+    pub fn encode(&self, buf: &mut [u8]) -> usize {
+        assert!(
+            self.data.len() <= MAX_PARAMETER_DATA_LEN,
+            "parameter data exceeds {} bytes",
+            MAX_PARAMETER_DATA_LEN
+        );
+
+        let len = HEADER_LEN + self.data.len();
+
+        buf[0] = SC_RDM;
+        buf[1] = SC_SUB_MESSAGE;
+        buf[2] = len as u8;
+        buf[3..9].copy_from_slice(&self.destination.to_bytes());
+        buf[9..15].copy_from_slice(&self.source.to_bytes());
+        buf[15] = self.transaction;
+        buf[16] = self.port_id;
+        // message count is always zero in requests
+        buf[17] = 0;
+        buf[18..20].copy_from_slice(&self.sub_device.to_be_bytes());
+        buf[20] = self.command_class.as_u8();
+        buf[21..23].copy_from_slice(&self.parameter_id.to_be_bytes());
+        buf[23] = self.data.len() as u8;
+        buf[HEADER_LEN..len].copy_from_slice(self.data);
+
+        let sum = checksum(&buf[..len]);
+        buf[len..(len + 2)].copy_from_slice(&sum.to_be_bytes());
+
+        len + 2
+    }
+}
+
+/// An RDM response.
+#[derive(Clone)]
+pub struct RdmResponse {
+    /// UID of the controller.
+    pub destination: Uid,
+    /// Responding device.
+    pub source: Uid,
+    /// Transaction number of the request.
+    pub transaction: u8,
+    /// Whether the request was processed.
+    pub response_type: ResponseType,
+    /// Number of queued messages available from the device.
+    pub message_count: u8,
+    /// Responding sub-device.
+    pub sub_device: u16,
+    /// Command class, matching the request.
+    pub command_class: CommandClass,
+    /// Parameter ID.
+    pub parameter_id: u16,
+    data: [u8; MAX_PARAMETER_DATA_LEN],
+    data_len: usize,
+}
+
+impl RdmResponse {
+    /// Create a response to a request.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameter data exceeds 231 bytes.
+    pub fn new(request: &RdmRequest<'_>, response_type: ResponseType, data: &[u8]) -> RdmResponse {
+        assert!(
+            data.len() <= MAX_PARAMETER_DATA_LEN,
+            "parameter data exceeds {} bytes",
+            MAX_PARAMETER_DATA_LEN
+        );
+
+        let command_class = match request.command_class {
+            CommandClass::Discovery => CommandClass::DiscoveryResponse,
+            CommandClass::Get => CommandClass::GetResponse,
+            CommandClass::Set => CommandClass::SetResponse,
+            class => class,
+        };
+
+        let mut buf = [0; MAX_PARAMETER_DATA_LEN];
+        buf[..data.len()].copy_from_slice(data);
+
+        RdmResponse {
+            destination: request.source,
+            source: request.destination,
+            transaction: request.transaction,
+            response_type,
+            message_count: 0,
+            sub_device: request.sub_device,
+            command_class,
+            parameter_id: request.parameter_id,
+            data: buf,
+            data_len: data.len(),
+        }
+    }
+
+    /// Create a response rejecting a request.
+    #[inline]
+    pub fn nack(request: &RdmRequest<'_>, reason: u16) -> RdmResponse {
+        RdmResponse::new(request, ResponseType::NackReason, &reason.to_be_bytes())
+    }
+
+    /// Decodes a response packet, starting at the start code.
+    ///
+    /// Trailing data after the checksum is ignored. Returns `None` if the
+    /// packet is malformed, truncated or its checksum does not match.
+    pub fn decode(packet: &[u8]) -> Option<RdmResponse> {
+        let len = validate(packet)?;
+        let data_len = len - HEADER_LEN;
+
+        let mut uid = [0; 6];
+        uid.copy_from_slice(&packet[3..9]);
+        let destination = Uid::from_bytes(uid);
+        uid.copy_from_slice(&packet[9..15]);
+        let source = Uid::from_bytes(uid);
+
+        let mut data = [0; MAX_PARAMETER_DATA_LEN];
+        data[..data_len].copy_from_slice(&packet[HEADER_LEN..len]);
+
+        Some(RdmResponse {
+            destination,
+            source,
+            transaction: packet[15],
+            response_type: ResponseType::from_u8(packet[16])?,
+            message_count: packet[17],
+            sub_device: u16::from_be_bytes([packet[18], packet[19]]),
+            command_class: CommandClass::from_u8(packet[20])?,
+            parameter_id: u16::from_be_bytes([packet[21], packet[22]]),
+            data,
+            data_len,
+        })
+    }
+
+    /// Returns the parameter data.
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.data_len]
+    }
+
+    /// Encodes the response into `buf`, including start code and checksum.
+    ///
+    /// Returns the length of the packet, which can be sent using
+    /// `DmxTransmitter::send_raw_dmx_packet`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buf` is too short for the packet; a buffer of
+    /// `MAX_PACKET_LEN` bytes is always large enough.
+    pub fn encode(&self, buf: &mut [u8]) -> usize {
+        let len = HEADER_LEN + self.data_len;
+
+        buf[0] = SC_RDM;
+        buf[1] = SC_SUB_MESSAGE;
+        buf[2] = len as u8;
+        buf[3..9].copy_from_slice(&self.destination.to_bytes());
+        buf[9..15].copy_from_slice(&self.source.to_bytes());
+        buf[15] = self.transaction;
+        buf[16] = self.response_type.as_u8();
+        buf[17] = self.message_count;
+        buf[18..20].copy_from_slice(&self.sub_device.to_be_bytes());
+        buf[20] = self.command_class.as_u8();
+        buf[21..23].copy_from_slice(&self.parameter_id.to_be_bytes());
+        buf[23] = self.data_len as u8;
+        buf[HEADER_LEN..len].copy_from_slice(self.data());
+
+        let sum = checksum(&buf[..len]);
+        buf[len..(len + 2)].copy_from_slice(&sum.to_be_bytes());
+
+        len + 2
+    }
+
+    /// Returns the reason code if the request was rejected.
+    pub fn nack_reason(&self) -> Option<u16> {
+        if self.response_type != ResponseType::NackReason || self.data_len < 2 {
+            return None;
+        }
+
+        Some(u16::from_be_bytes([self.data[0], self.data[1]]))
+    }
+
+    /// Returns the time after which to ask for the result of a request that
+    /// is processed later.
+    pub fn ack_timer(&self) -> Option<time::Duration> {
+        if self.response_type != ResponseType::AckTimer || self.data_len < 2 {
+            return None;
+        }
+
+        // given in units of 100 ms
+        let units = u16::from_be_bytes([self.data[0], self.data[1]]);
+        Some(time::Duration::from_millis(u64::from(units) * 100))
+    }
+}
+
+impl fmt::Debug for RdmResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RdmResponse")
+            .field("destination", &self.destination)
+            .field("source", &self.source)
+            .field("transaction", &self.transaction)
+            .field("response_type", &self.response_type)
+            .field("message_count", &self.message_count)
+            .field("sub_device", &self.sub_device)
+            .field("command_class", &self.command_class)
+            .field("parameter_id", &self.parameter_id)
+            .field("data", &self.data())
+            .finish()
+    }
+}
+
+/// Checks the framing and checksum of a packet.
+///
+/// Returns the length of the packet without checksum.
+fn validate(packet: &[u8]) -> Option<usize> {
+    if packet.len() < HEADER_LEN + 2 || packet[0] != SC_RDM || packet[1] != SC_SUB_MESSAGE {
+        return None;
+    }
+
+    let len = usize::from(packet[2]);
+    let data_len = usize::from(packet[23]);
+    if len != HEADER_LEN + data_len || packet.len() < len + 2 {
+        return None;
+    }
+
+    if checksum(&packet[..len]) != u16::from_be_bytes([packet[len], packet[len + 1]]) {
+        return None;
+    }
+
+    Some(len)
+}
+
+/// Length of a discovery unique branch response, including the preamble.
+pub const DISCOVERY_RESPONSE_LEN: usize = 24;
+
+/// Encodes a response to a discovery unique branch request.
+///
+/// The response is sent without a break, see `decode_discovery_response`.
+pub fn encode_discovery_response(uid: Uid) -> [u8; DISCOVERY_RESPONSE_LEN] {
+    let mut response = [0xfe; DISCOVERY_RESPONSE_LEN];
+    response[7] = DISCOVERY_SEPARATOR;
+
+    let uid = uid.to_bytes();
+    for (i, &b) in uid.iter().enumerate() {
+        response[8 + 2 * i] = b | 0xaa;
+        response[9 + 2 * i] = b | 0x55;
+    }
+
+    let sum = checksum(&response[8..20]).to_be_bytes();
+    for (i, &b) in sum.iter().enumerate() {
+        response[20 + 2 * i] = b | 0xaa;
+        response[21 + 2 * i] = b | 0x55;
+    }
+
+    response
+}
+
+/// Decodes a response to a discovery unique branch request.
+///
+/// Responses are sent without a break, consisting of an optional preamble,
+/// a separator and the encoded UID and checksum. Returns `None` if no valid
+/// response is found in `data`, which usually means that several devices
+/// responded at once.
+pub fn decode_discovery_response(data: &[u8]) -> Option<Uid> {
+    data.iter()
+        .enumerate()
+        .filter(|&(_, &b)| b == DISCOVERY_SEPARATOR)
+        .filter_map(|(i, _)| decode_euid(&data[(i + 1)..]))
+        .next()
+}
+
+/// Decodes an encoded UID and its checksum.
+///
+/// Each byte is transmitted twice, once or-ed with `0xAA` and once with
+/// `0x55`.
+fn decode_euid(euid: &[u8]) -> Option<Uid> {
+    if euid.len() < 16 {
+        return None;
+    }
+
+    let mut decoded = [0; 8];
+    for (i, pair) in euid[..16].chunks(2).enumerate() {
+        if pair[0] & 0xaa != 0xaa || pair[1] & 0x55 != 0x55 {
+            return None;
+        }
+
+        decoded[i] = pair[0] & pair[1];
+    }
+
+    if checksum(&euid[..12]) != u16::from_be_bytes([decoded[6], decoded[7]]) {
+        return None;
+    }
+
+    let mut uid = [0; 6];
+    uid.copy_from_slice(&decoded[..6]);
+    Some(Uid::from_bytes(uid))
+}
+
+/// Device information, as returned by `PID_DEVICE_INFO`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// Supported RDM protocol version, `0x0100` for E1.20.
+    pub protocol_version: u16,
+    /// Manufacturer-specific device model.
+    pub model_id: u16,
+    /// Product category, as defined in E1.20 table A-5.
+    pub product_category: u16,
+    /// Manufacturer-specific software version.
+    pub software_version: u32,
+    /// Number of DMX channels used by the device.
+    pub footprint: u16,
+    /// Current personality, starting at 1.
+    pub personality: u8,
+    /// Number of available personalities.
+    pub personality_count: u8,
+    /// First DMX channel used, `0xFFFF` if the footprint is zero.
+    pub start_address: u16,
+    /// Number of sub-devices.
+    pub sub_device_count: u16,
+    /// Number of sensors.
+    pub sensor_count: u8,
+}
+
+impl DeviceInfo {
+    /// Length of the parameter data.
+    pub const LEN: usize = 19;
+
+    /// Decodes device information from parameter data.
+    ///
+    /// Returns `None` if `data` is too short.
+    pub fn from_bytes(data: &[u8]) -> Option<DeviceInfo> {
+        if data.len() < DeviceInfo::LEN {
+            return None;
+        }
+
+        let u16_at = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+
+        Some(DeviceInfo {
+            protocol_version: u16_at(0),
+            model_id: u16_at(2),
+            product_category: u16_at(4),
+            software_version: u32::from_be_bytes([data[6], data[7], data[8], data[9]]),
+            footprint: u16_at(10),
+            personality: data[12],
+            personality_count: data[13],
+            start_address: u16_at(14),
+            sub_device_count: u16_at(16),
+            sensor_count: data[18],
+        })
+    }
+
+    /// Encodes device information into parameter data.
+    pub fn to_bytes(&self) -> [u8; DeviceInfo::LEN] {
+        let mut data = [0; DeviceInfo::LEN];
+
+        data[0..2].copy_from_slice(&self.protocol_version.to_be_bytes());
+        data[2..4].copy_from_slice(&self.model_id.to_be_bytes());
+        data[4..6].copy_from_slice(&self.product_category.to_be_bytes());
+        data[6..10].copy_from_slice(&self.software_version.to_be_bytes());
+        data[10..12].copy_from_slice(&self.footprint.to_be_bytes());
+        data[12] = self.personality;
+        data[13] = self.personality_count;
+        data[14..16].copy_from_slice(&self.start_address.to_be_bytes());
+        data[16..18].copy_from_slice(&self.sub_device_count.to_be_bytes());
+        data[18] = self.sensor_count;
+
+        data
+    }
+}
+
+/// Current personality of a device, as returned by `PID_DMX_PERSONALITY`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Personality {
+    /// Current personality, starting at 1.
+    pub current: u8,
+    /// Number of available personalities.
+    pub count: u8,
+}
+
+impl Personality {
+    /// Length of the parameter data.
+    pub const LEN: usize = 2;
+
+    /// Decodes the personality from parameter data.
+    ///
+    /// Returns `None` if `data` is too short.
+    #[inline]
+    pub fn from_bytes(data: &[u8]) -> Option<Personality> {
+        match *data {
+            [current, count, ..] => Some(Personality { current, count }),
+            _ => None,
+        }
+    }
+
+    /// Encodes the personality into parameter data.
+    #[inline]
+    pub fn to_bytes(&self) -> [u8; Personality::LEN] {
+        [self.current, self.count]
+    }
+}
+
+/// Description of a personality, as returned by
+/// `PID_DMX_PERSONALITY_DESCRIPTION`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PersonalityDescription {
+    /// Number of the personality, starting at 1.
+    pub personality: u8,
+    /// Number of DMX channels used in this personality.
+    pub footprint: u16,
+    /// Description, such as `"8ch RGBW"`.
+    pub description: String,
+}
+
+impl PersonalityDescription {
+    /// Decodes a personality description from parameter data.
+    ///
+    /// Returns `None` if `data` is too short.
+    pub fn from_bytes(data: &[u8]) -> Option<PersonalityDescription> {
+        if data.len() < 3 {
+            return None;
+        }
+
+        Some(PersonalityDescription {
+            personality: data[0],
+            footprint: u16::from_be_bytes([data[1], data[2]]),
+            description: decode_label(&data[3..]),
+        })
+    }
+
+    /// Encodes the description into parameter data.
+    ///
+    /// Descriptions longer than 32 bytes are truncated.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![self.personality];
+        data.extend_from_slice(&self.footprint.to_be_bytes());
+        data.extend_from_slice(encode_label(&self.description));
+
+        data
+    }
+}
+
+/// Decodes a label, such as the device label, from parameter data.
+///
+/// Labels are at most 32 bytes of ASCII, though some devices pad them with
+/// NUL bytes; those and invalid characters are dropped or replaced.
+pub fn decode_label(data: &[u8]) -> String {
+    let data = &data[..data.len().min(MAX_LABEL_LEN)];
+    let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+
+    String::from_utf8_lossy(&data[..len]).into_owned()
+}
+
+/// Encodes a label into parameter data, truncating it to 32 bytes.
+pub fn encode_label(label: &str) -> &[u8] {
+    let mut len = label.len().min(MAX_LABEL_LEN);
+
+    // do not split characters
+    while !label.is_char_boundary(len) {
+        len -= 1;
+    }
+
+    &label.as_bytes()[..len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTROLLER: Uid = Uid(0x7a70_0000_0001);
+    const DEVICE: Uid = Uid(0x4744_1234_5678);
+
+    fn get_device_info() -> RdmRequest<'static> {
+        RdmRequest {
+            destination: DEVICE,
+            source: CONTROLLER,
+            transaction: 1,
+            port_id: 1,
+            sub_device: 0,
+            command_class: CommandClass::Get,
+            parameter_id: PID_DEVICE_INFO,
+            data: &[],
+        }
+    }
+
+    #[test]
+    fn checksum_wraps_around() {
+        assert_eq!(checksum(&[]), 0);
+        assert_eq!(checksum(&[0xcc, 0x01, 0x18]), 0xe5);
+        assert_eq!(checksum(&[0xff; 300]), 0x2ad4);
+    }
+
+    #[test]
+    fn request_layout() {
+        let mut buf = [0; MAX_PACKET_LEN];
+        let len = get_device_info().encode(&mut buf);
+
+        // start code, sub start code, message length, destination, source,
+        // transaction, port, message count, sub-device, command class,
+        // parameter ID, data length, checksum
+        let expected = [
+            0xcc, 0x01, 0x18, 0x47, 0x44, 0x12, 0x34, 0x56, 0x78, 0x7a, 0x70, 0x00, 0x00, 0x00,
+            0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x20, 0x00, 0x60, 0x00, 0x03, 0xf1,
+        ];
+        assert_eq!(buf[..len], expected);
+        assert_eq!(RdmRequest::decode(&buf[..len]), Some(get_device_info()));
+    }
+
+    #[test]
+    fn response_layout() {
+        let request = RdmRequest {
+            command_class: CommandClass::Set,
+            parameter_id: PID_DMX_START_ADDRESS,
+            ..get_device_info()
+        };
+        let response = RdmResponse::new(&request, ResponseType::Ack, &42u16.to_be_bytes());
+
+        let mut buf = [0; MAX_PACKET_LEN];
+        let len = response.encode(&mut buf);
+
+        // addresses swapped, response type in place of the port
+        let expected = [
+            0xcc, 0x01, 0x1a, 0x7a, 0x70, 0x00, 0x00, 0x00, 0x01, 0x47, 0x44, 0x12, 0x34, 0x56,
+            0x78, 0x01, 0x00, 0x00, 0x00, 0x00, 0x31, 0x00, 0xf0, 0x02, 0x00, 0x2a, 0x04, 0xbf,
+        ];
+        assert_eq!(buf[..len], expected);
+
+        let decoded = RdmResponse::decode(&buf[..len]).unwrap();
+        assert_eq!(decoded.destination, CONTROLLER);
+        assert_eq!(decoded.source, DEVICE);
+        assert_eq!(decoded.transaction, 1);
+        assert_eq!(decoded.response_type, ResponseType::Ack);
+        assert_eq!(decoded.command_class, CommandClass::SetResponse);
+        assert_eq!(decoded.parameter_id, PID_DMX_START_ADDRESS);
+        assert_eq!(decoded.data(), [0x00, 0x2a]);
+    }
+
+    #[test]
+    fn request_round_trip_with_parameter_data() {
+        let data = [0x55; MAX_PARAMETER_DATA_LEN];
+        let request = RdmRequest {
+            sub_device: 0x0200,
+            command_class: CommandClass::Set,
+            parameter_id: PID_DEVICE_LABEL,
+            data: &data,
+            ..get_device_info()
+        };
+
+        let mut buf = [0; MAX_PACKET_LEN];
+        let len = request.encode(&mut buf);
+        assert_eq!(len, MAX_PACKET_LEN);
+        assert_eq!(buf[2], 255);
+
+        // trailing data after the checksum is ignored
+        let mut packet = buf.to_vec();
+        packet.extend_from_slice(&[0xff; 4]);
+        assert_eq!(RdmRequest::decode(&packet), Some(request));
+    }
+
+    #[test]
+    fn nack_and_ack_timer_responses() {
+        let nack = RdmResponse::nack(&get_device_info(), NR_UNKNOWN_PID);
+        assert_eq!(nack.response_type, ResponseType::NackReason);
+        assert_eq!(nack.nack_reason(), Some(NR_UNKNOWN_PID));
+        assert_eq!(nack.ack_timer(), None);
+
+        let timer = RdmResponse::new(&get_device_info(), ResponseType::AckTimer, &[0x00, 0x0f]);
+        assert_eq!(timer.ack_timer(), Some(time::Duration::from_millis(1500)));
+        assert_eq!(timer.nack_reason(), None);
+    }
+
+    #[test]
+    fn invalid_packets_are_rejected() {
+        let mut buf = [0; MAX_PACKET_LEN];
+        let len = get_device_info().encode(&mut buf);
+        let packet = &buf[..len];
+
+        assert!(RdmRequest::decode(&packet[..len - 1]).is_none());
+
+        let corrupt = |i: usize, value: u8| {
+            let mut packet = packet.to_vec();
+            packet[i] = value;
+            RdmRequest::decode(&packet).is_some()
+        };
+        // start code, sub start code, message length, data length
+        assert!(!corrupt(0, 0x00));
+        assert!(!corrupt(1, 0x02));
+        assert!(!corrupt(2, 0x19));
+        assert!(!corrupt(23, 0x01));
+        // payload and checksum
+        assert!(!corrupt(15, 0x02));
+        assert!(!corrupt(25, 0xf2));
+    }
+
+    #[test]
+    fn invalid_command_classes_are_rejected() {
+        let mut buf = [0; MAX_PACKET_LEN];
+        let len = get_device_info().encode(&mut buf);
+        buf[20] = 0x40;
+        let sum = checksum(&buf[..len - 2]);
+        buf[(len - 2)..len].copy_from_slice(&sum.to_be_bytes());
+
+        assert!(RdmRequest::decode(&buf[..len]).is_none());
+    }
+
+    #[test]
+    fn discovery_response_layout() {
+        let uid = Uid::new(0x1234, 0x5678_9abc);
+        let response = encode_discovery_response(uid);
+
+        // preamble, separator, each byte or-ed with 0xaa and 0x55
+        let expected = [
+            0xfe, 0xfe, 0xfe, 0xfe, 0xfe, 0xfe, 0xfe, 0xaa, 0xba, 0x57, 0xbe, 0x75, 0xfe, 0x57,
+            0xfa, 0x7d, 0xba, 0xdf, 0xbe, 0xfd, 0xaa, 0x5d, 0xee, 0x75,
+        ];
+        assert_eq!(response, expected);
+        assert_eq!(decode_discovery_response(&response), Some(uid));
+    }
+
+    #[test]
+    fn discovery_responses_without_preamble_are_decoded() {
+        let uid = Uid::new(0x4744, 0x0000_0001);
+        let response = encode_discovery_response(uid);
+
+        assert_eq!(decode_discovery_response(&response[7..]), Some(uid));
+        assert_eq!(decode_discovery_response(&response[8..]), None);
+    }
+
+    #[test]
+    fn collided_discovery_responses_are_rejected() {
+        let first = encode_discovery_response(Uid::new(0x1234, 0x5678_9abc));
+        let second = encode_discovery_response(Uid::new(0x4744, 0x0000_0001));
+
+        // the line idles high, so colliding responses are and-ed
+        let collision: Vec<u8> = first.iter().zip(&second).map(|(a, b)| a & b).collect();
+        assert_eq!(decode_discovery_response(&collision), None);
+        assert_eq!(decode_discovery_response(&first[..23]), None);
+    }
+
+    #[test]
+    fn uid_bytes_are_big_endian() {
+        let uid = Uid::new(0x4744, 0x1234_5678);
+        assert_eq!(uid.to_bytes(), [0x47, 0x44, 0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(Uid::from_bytes(uid.to_bytes()), uid);
+        assert_eq!(uid.to_string(), "4744:12345678");
+        assert!(Uid::manufacturer_broadcast(0x4744).is_broadcast());
+        assert!(!uid.is_broadcast());
+    }
+
+    #[test]
+    fn labels_are_truncated() {
+        assert_eq!(decode_label(b"Wash\0\0\0\0"), "Wash");
+        assert_eq!(decode_label(&[b'a'; 40]).len(), MAX_LABEL_LEN);
+
+        let label = "\u{e9}".repeat(20);
+        assert_eq!(encode_label(&label).len(), 32);
+        assert_eq!(encode_label("\u{20ac}".repeat(11).as_str()).len(), 30);
+    }
+}
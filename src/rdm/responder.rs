@@ -0,0 +1,327 @@
+//! RDM responder.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time;
+
+use super::{
+    decode_label, encode_discovery_response, encode_label, CommandClass, DeviceInfo,
+    PersonalityDescription, RdmRequest, RdmResponse, ResponseType, Uid,
+    DISCOVERY_RESPONSE_LEN, MAX_PACKET_LEN, NR_DATA_OUT_OF_RANGE, NR_FORMAT_ERROR,
+    NR_SUB_DEVICE_OUT_OF_RANGE, NR_UNKNOWN_PID, NR_UNSUPPORTED_COMMAND_CLASS, PID_DEVICE_INFO,
+    PID_DEVICE_LABEL, PID_DISC_MUTE, PID_DISC_UNIQUE_BRANCH, PID_DISC_UN_MUTE,
+    PID_DMX_PERSONALITY, PID_DMX_PERSONALITY_DESCRIPTION, PID_DMX_START_ADDRESS,
+    PID_IDENTIFY_DEVICE, PID_SOFTWARE_VERSION_LABEL, SC_RDM,
+};
+use crate::{DmxTransceiver, DmxTransmitter, Error, Result};
+
+// time to wait for requests before checking whether to stop
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
+/// Reply of a responder to a request.
+#[derive(Clone, Debug)]
+pub enum RdmReply {
+    /// A response, sent after a break.
+    Response(Box<RdmResponse>),
+    /// A response to a discovery unique branch request, sent without a
+    /// break.
+    Discovery([u8; DISCOVERY_RESPONSE_LEN]),
+}
+
+impl RdmReply {
+    /// Sends the reply.
+    pub fn send<T: DmxTransmitter<Error = Error>>(&self, port: &mut T) -> Result<()> {
+        match *self {
+            RdmReply::Response(ref response) => {
+                let mut buf = [0; MAX_PACKET_LEN];
+                let len = response.encode(&mut buf);
+                port.send_raw_dmx_packet(&buf[..len])
+            }
+            RdmReply::Discovery(ref data) => port.send_raw_data(data),
+        }
+    }
+}
+
+/// A virtual RDM device.
+///
+/// Answers discovery requests and reads and changes of the device info,
+/// labels, start address, identify state and personalities, rejecting
+/// requests for any other parameter. Useful for testing controllers without
+/// physical RDM fixtures, either on a real line through `run` or through
+/// `testing::MockRdmBus`.
+///
+/// ## Example
+///
+/// ```
+/// use dmx::rdm::{DeviceInfo, RdmController, RdmResponder, Uid};
+/// use dmx::testing::MockRdmBus;
+///
+/// let info = DeviceInfo {
+///     protocol_version: 0x0100,
+///     model_id: 1,
+///     product_category: 0x0101,
+///     software_version: 1,
+///     footprint: 4,
+///     personality: 1,
+///     personality_count: 1,
+///     start_address: 1,
+///     sub_device_count: 0,
+///     sensor_count: 0,
+/// };
+///
+/// let mut bus = MockRdmBus::new();
+/// bus.add(RdmResponder::new(Uid::new(0x7ff0, 100), info));
+/// bus.add(RdmResponder::new(Uid::new(0x7ff0, 200), info));
+///
+/// let mut controller = RdmController::new(bus, Uid::new(0x7ff0, 1));
+/// let devices = controller.discover().unwrap();
+/// assert_eq!(devices.len(), 2);
+///
+/// controller.set_start_address(devices[1], 101).unwrap();
+/// assert_eq!(controller.start_address(devices[1]).unwrap(), Some(101));
+/// ```
+#[derive(Clone, Debug)]
+pub struct RdmResponder {
+    uid: Uid,
+    info: DeviceInfo,
+    label: String,
+    software_version_label: String,
+    personalities: Vec<PersonalityDescription>,
+    identify: bool,
+    muted: bool,
+}
+
+impl RdmResponder {
+    /// Create a responder with the given UID and device info.
+    ///
+    /// The personality and footprint are taken from `info` until
+    /// personalities are added.
+    #[inline]
+    pub fn new(uid: Uid, info: DeviceInfo) -> RdmResponder {
+        RdmResponder {
+            uid,
+            info,
+            label: String::new(),
+            software_version_label: String::new(),
+            personalities: Vec::new(),
+            identify: false,
+            muted: false,
+        }
+    }
+
+    /// Returns the UID of the device.
+    #[inline]
+    pub fn uid(&self) -> Uid {
+        self.uid
+    }
+
+    /// Returns the device info, as reported to controllers.
+    #[inline]
+    pub fn device_info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    /// Returns the DMX start address.
+    #[inline]
+    pub fn start_address(&self) -> u16 {
+        self.info.start_address
+    }
+
+    /// Returns the device label.
+    #[inline]
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Sets the device label, truncated to 32 bytes.
+    #[inline]
+    pub fn set_label(&mut self, label: &str) {
+        self.label = decode_label(encode_label(label));
+    }
+
+    /// Sets the software version label, truncated to 32 bytes.
+    #[inline]
+    pub fn set_software_version_label(&mut self, label: &str) {
+        self.software_version_label = decode_label(encode_label(label));
+    }
+
+    /// Adds a personality with the given footprint.
+    ///
+    /// The first personality added becomes the current one.
+    pub fn add_personality(&mut self, footprint: u16, description: &str) {
+        self.personalities.push(PersonalityDescription {
+            personality: self.personalities.len() as u8 + 1,
+            footprint,
+            description: decode_label(encode_label(description)),
+        });
+
+        self.info.personality_count = self.personalities.len() as u8;
+        if self.personalities.len() == 1 {
+            self.info.personality = 1;
+            self.info.footprint = footprint;
+        }
+    }
+
+    /// Returns whether a controller asked the device to identify itself.
+    #[inline]
+    pub fn is_identifying(&self) -> bool {
+        self.identify
+    }
+
+    /// Returns whether the device is muted, not taking part in discovery.
+    #[inline]
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Processes a request.
+    ///
+    /// Returns the reply to send, if any. Requests addressed to other
+    /// devices are ignored, broadcasts are processed but not replied to,
+    /// except for discovery.
+    pub fn handle(&mut self, request: &RdmRequest<'_>) -> Option<RdmReply> {
+        let destination = request.destination;
+        let broadcast = destination == Uid::BROADCAST
+            || destination == Uid::manufacturer_broadcast(self.uid.manufacturer());
+
+        if destination != self.uid && !broadcast {
+            return None;
+        }
+
+        if request.command_class == CommandClass::Discovery
+            && request.parameter_id == PID_DISC_UNIQUE_BRANCH
+        {
+            return self.unique_branch(request.data);
+        }
+
+        let response = self.respond(request);
+
+        if broadcast {
+            return None;
+        }
+        response.map(|r| RdmReply::Response(Box::new(r)))
+    }
+
+    /// Answers requests arriving on `port` until `stop` is set, or on the
+    /// first error.
+    pub fn run<T: DmxTransceiver>(&mut self, port: &mut T, stop: &AtomicBool) -> Result<()> {
+        let mut buf = [0; MAX_PACKET_LEN * 2];
+
+        while !stop.load(Ordering::Relaxed) {
+            let len = port.recv_raw_data(&mut buf, POLL_INTERVAL)?;
+
+            // requests may be preceded by a break or line noise
+            let reply = buf[..len]
+                .iter()
+                .enumerate()
+                .filter(|&(_, &b)| b == SC_RDM)
+                .filter_map(|(i, _)| RdmRequest::decode(&buf[i..len]))
+                .find_map(|request| self.handle(&request));
+
+            if let Some(reply) = reply {
+                reply.send(port)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unique_branch(&self, data: &[u8]) -> Option<RdmReply> {
+        if self.muted || data.len() < 12 {
+            return None;
+        }
+
+        let mut bytes = [0; 6];
+        bytes.copy_from_slice(&data[..6]);
+        let lower = Uid::from_bytes(bytes);
+        bytes.copy_from_slice(&data[6..12]);
+        let upper = Uid::from_bytes(bytes);
+
+        if self.uid < lower || self.uid > upper {
+            return None;
+        }
+
+        Some(RdmReply::Discovery(encode_discovery_response(self.uid)))
+    }
+
+    fn respond(&mut self, request: &RdmRequest<'_>) -> Option<RdmResponse> {
+        let ack = |data: &[u8]| Some(RdmResponse::new(request, ResponseType::Ack, data));
+        let nack = |reason| Some(RdmResponse::nack(request, reason));
+
+        let data = request.data;
+
+        match request.command_class {
+            CommandClass::Discovery => {
+                match request.parameter_id {
+                    PID_DISC_MUTE => self.muted = true,
+                    PID_DISC_UN_MUTE => self.muted = false,
+                    _ => return None,
+                }
+
+                // control field, no flags set
+                return ack(&[0, 0]);
+            }
+            CommandClass::Get | CommandClass::Set => (),
+            _ => return None,
+        }
+
+        if request.sub_device != 0 {
+            return nack(NR_SUB_DEVICE_OUT_OF_RANGE);
+        }
+        let get = request.command_class == CommandClass::Get;
+
+        match request.parameter_id {
+            PID_DEVICE_INFO if get => ack(&self.info.to_bytes()),
+            PID_DEVICE_LABEL if get => ack(self.label.as_bytes()),
+            PID_DEVICE_LABEL => {
+                self.label = decode_label(data);
+                ack(&[])
+            }
+            PID_SOFTWARE_VERSION_LABEL if get => ack(self.software_version_label.as_bytes()),
+            PID_DMX_START_ADDRESS if get => ack(&self.info.start_address.to_be_bytes()),
+            PID_DMX_START_ADDRESS => match *data {
+                [high, low] => match u16::from_be_bytes([high, low]) {
+                    address @ 1..=512 => {
+                        self.info.start_address = address;
+                        ack(&[])
+                    }
+                    _ => nack(NR_DATA_OUT_OF_RANGE),
+                },
+                _ => nack(NR_FORMAT_ERROR),
+            },
+            PID_IDENTIFY_DEVICE if get => ack(&[u8::from(self.identify)]),
+            PID_IDENTIFY_DEVICE => match *data {
+                [on @ 0..=1] => {
+                    self.identify = on == 1;
+                    ack(&[])
+                }
+                [_] => nack(NR_DATA_OUT_OF_RANGE),
+                _ => nack(NR_FORMAT_ERROR),
+            },
+            PID_DMX_PERSONALITY if get => {
+                ack(&[self.info.personality, self.info.personality_count])
+            }
+            PID_DMX_PERSONALITY => match *data {
+                [n] => match self.personalities.get(usize::from(n).wrapping_sub(1)) {
+                    Some(personality) => {
+                        self.info.personality = n;
+                        self.info.footprint = personality.footprint;
+                        ack(&[])
+                    }
+                    None => nack(NR_DATA_OUT_OF_RANGE),
+                },
+                _ => nack(NR_FORMAT_ERROR),
+            },
+            PID_DMX_PERSONALITY_DESCRIPTION if get => match *data {
+                [n] => match self.personalities.get(usize::from(n).wrapping_sub(1)) {
+                    Some(personality) => ack(&personality.to_bytes()),
+                    None => nack(NR_DATA_OUT_OF_RANGE),
+                },
+                _ => nack(NR_FORMAT_ERROR),
+            },
+            PID_DEVICE_INFO
+            | PID_SOFTWARE_VERSION_LABEL
+            | PID_DMX_PERSONALITY_DESCRIPTION => nack(NR_UNSUPPORTED_COMMAND_CLASS),
+            _ => nack(NR_UNKNOWN_PID),
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! Serial DMX reception.
+//!
+//! A break cannot be read like a regular byte. Instead, the TTY is put into
+//! parity-marking mode (`PARMRK`), causing the kernel to report a break as the
+//! three-byte sequence `0xFF 0x00 0x00` in the input stream. Any other framing
+//! or parity error is reported as `0xFF 0x00 <byte>`, while a literal `0xFF`
+//! byte is escaped as `0xFF 0xFF`.
+
+use std::ffi::OsStr;
+use std::path::Path;
+use std::{io, time};
+
+use crate::devices;
+use crate::serial::{baud_error, dmx_settings};
+use crate::stats::Stats;
+use crate::{DmxReceiver, Error, Result};
+
+// idle time after which a partially received packet is considered complete
+const IDLE_TIMEOUT: time::Duration = time::Duration::from_millis(100);
+
+/// A single decoded item of the marked input stream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Symbol {
+    /// A regular data byte.
+    Data(u8),
+    /// A break condition.
+    Break,
+    /// A byte received with a framing or parity error.
+    Error,
+}
+
+/// DMX receiver on top of a serial port.
+///
+/// Wraps a serial port, which is configured for 250,000 baud reception with
+/// break detection upon creation.
+#[derive(Debug)]
+pub struct SerialReceiver {
+    port: serial2::SerialPort,
+    buf: [u8; 256],
+    pos: usize,
+    len: usize,
+    // set if the last packet ended on a break, i.e. the next byte is the
+    // start code of a new packet
+    synced: bool,
+    stats: Stats,
+}
+
+impl SerialReceiver {
+    /// Create a new receiver from a serial port.
+    pub fn new(mut port: serial2::SerialPort) -> Result<SerialReceiver> {
+        let mut settings = dmx_settings(port.get_configuration()?)?;
+        enable_break_marking(settings.as_termios_mut());
+        port.set_configuration(&settings).map_err(baud_error)?;
+        port.set_read_timeout(IDLE_TIMEOUT)?;
+
+        Ok(SerialReceiver {
+            port,
+            buf: [0; 256],
+            pos: 0,
+            len: 0,
+            synced: false,
+            stats: Stats::new(),
+        })
+    }
+
+    /// Returns statistics of the packets received since creating the
+    /// receiver or the last `reset_stats`.
+    ///
+    /// Packets discarded because of framing errors are counted as errors,
+    /// timeouts are not.
+    #[inline]
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Clears the statistics.
+    #[inline]
+    pub fn reset_stats(&mut self) {
+        self.stats.reset();
+    }
+
+    /// Returns the underlying serial port.
+    pub fn into_inner(self) -> serial2::SerialPort {
+        self.port
+    }
+
+    /// Waits for a break, unless already positioned right after one.
+    fn sync(&mut self) -> io::Result<()> {
+        while !self.synced {
+            if self.next_symbol()? == Symbol::Break {
+                self.synced = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the next raw byte, refilling the internal buffer if required.
+    fn next_byte(&mut self) -> io::Result<u8> {
+        while self.pos == self.len {
+            self.len = self.port.read(&mut self.buf)?;
+            self.pos = 0;
+        }
+
+        let b = self.buf[self.pos];
+        self.pos += 1;
+        Ok(b)
+    }
+
+    /// Reads the next symbol from the marked input stream.
+    fn next_symbol(&mut self) -> io::Result<Symbol> {
+        match self.next_byte()? {
+            0xFF => match self.next_byte()? {
+                0xFF => Ok(Symbol::Data(0xFF)),
+                _ => match self.next_byte()? {
+                    0x00 => Ok(Symbol::Break),
+                    _ => Ok(Symbol::Error),
+                },
+            },
+            b => Ok(Symbol::Data(b)),
+        }
+    }
+}
+
+impl DmxReceiver for SerialReceiver {
+    fn recv_dmx_packet_into(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.sync().map_err(Error::from_read)?;
+        let start = time::Instant::now();
+
+        match self.recv_packet(buf) {
+            Ok(len) => {
+                self.stats.record_frame(start, len);
+                Ok(len)
+            }
+            Err(Error::Timeout) => Err(Error::Timeout),
+            Err(e) => {
+                self.stats.record_error();
+                Err(e)
+            }
+        }
+    }
+}
+
+impl SerialReceiver {
+    /// Receives a packet, right after a break.
+    fn recv_packet(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut len = 0;
+        let mut received = 0;
+
+        loop {
+            let sym = match self.next_symbol() {
+                Ok(sym) => sym,
+                // an idle line after a partial packet ends the packet
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut && received > 0 => {
+                    self.synced = false;
+                    return Ok(len);
+                }
+                Err(e) => {
+                    self.synced = false;
+                    return Err(Error::from_read(e));
+                }
+            };
+
+            match sym {
+                Symbol::Data(b) => {
+                    if len < buf.len() {
+                        buf[len] = b;
+                        len += 1;
+                    }
+                    received += 1;
+
+                    // start code and 512 channels are the maximum packet size
+                    if received == 513 {
+                        self.synced = false;
+                        return Ok(len);
+                    }
+                }
+                // a break completes the current packet and starts the next
+                Symbol::Break if received > 0 => return Ok(len),
+                // a break right after another is a stray line condition
+                Symbol::Break => (),
+                // corrupted slot, discard the packet and resynchronize
+                Symbol::Error => {
+                    self.stats.record_error();
+                    len = 0;
+                    received = 0;
+                    self.synced = false;
+                    self.sync().map_err(Error::from_read)?;
+                }
+            }
+        }
+    }
+}
+
+/// Enables break and error marking in TTY settings.
+fn enable_break_marking(tio: &mut serial2::os::unix::RawTermios) {
+    tio.c_iflag &= !(libc::IGNBRK | libc::BRKINT | libc::IGNPAR | libc::ISTRIP);
+    tio.c_iflag |= libc::PARMRK | libc::INPCK;
+}
+
+/// Opens a serial device for DMX reception.
+///
+/// Fails with `Error::MiniUart` for the mini UART of a Raspberry Pi, which
+/// cannot detect breaks.
+pub fn open_serial_receiver<T: AsRef<OsStr> + ?Sized>(port: &T) -> Result<SerialReceiver> {
+    let path = Path::new(port);
+    if devices::is_mini_uart(path) {
+        return Err(Error::MiniUart);
+    }
+
+    SerialReceiver::new(serial2::SerialPort::open(path, serial2::KeepSettings)?)
+}
@@ -0,0 +1,236 @@
+//! Reconnecting after device failures.
+
+use std::ffi::OsString;
+use std::{fmt, time};
+
+use crate::serial::{open_serial, DmxPort};
+use crate::{DmxTransmitter, Error, Result};
+
+// delay before the first attempt to reopen a device, doubling up to the
+// maximum on every failed attempt
+const INITIAL_BACKOFF: time::Duration = time::Duration::from_millis(100);
+const MAX_BACKOFF: time::Duration = time::Duration::from_secs(5);
+
+/// Connection state of a `ReconnectingTransmitter`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ConnectionState {
+    /// The device is open.
+    Connected,
+    /// The device failed or could not be opened.
+    Disconnected,
+}
+
+type Open<T> = Box<dyn FnMut() -> Result<T> + Send>;
+type Callback = Box<dyn FnMut(ConnectionState) + Send>;
+
+/// A transmitter reopening its device after I/O errors.
+///
+/// When sending fails with `Error::Io` or `Error::BreakFailed`, e.g.
+/// because a USB adapter was unplugged, the device is closed and sending
+/// succeeds without doing anything, until the device is reopened. Attempts
+/// to reopen it are made while sending, with an increasing delay between
+/// them, or explicitly through `reconnect`, which also retransmits the last
+/// packet. Other errors are returned as usual.
+///
+/// ```no_run
+/// use dmx::{ConnectionState, DmxRefresher, ReconnectingTransmitter};
+///
+/// let mut transmitter = ReconnectingTransmitter::serial("/dev/ttyUSB0");
+/// transmitter.on_state_change(|state| {
+///     if state == ConnectionState::Disconnected {
+///         eprintln!("DMX interface lost");
+///     }
+/// });
+///
+/// let refresher = DmxRefresher::new(transmitter);
+/// ```
+pub struct ReconnectingTransmitter<T> {
+    open: Open<T>,
+    transmitter: Option<T>,
+    callback: Option<Callback>,
+    last_packet: Vec<u8>,
+    last_error: Option<Error>,
+    initial_backoff: time::Duration,
+    backoff: time::Duration,
+    max_backoff: time::Duration,
+    next_attempt: time::Instant,
+}
+
+impl<T: DmxTransmitter<Error = Error>> ReconnectingTransmitter<T> {
+    /// Create a transmitter opening its device through `open`.
+    ///
+    /// The device is opened right away. If that fails, the transmitter
+    /// starts out disconnected and retries while sending.
+    pub fn new<F>(open: F) -> ReconnectingTransmitter<T>
+    where
+        F: FnMut() -> Result<T> + Send + 'static,
+    {
+        let mut transmitter = ReconnectingTransmitter {
+            open: Box::new(open),
+            transmitter: None,
+            callback: None,
+            last_packet: Vec::new(),
+            last_error: None,
+            initial_backoff: INITIAL_BACKOFF,
+            backoff: INITIAL_BACKOFF,
+            max_backoff: MAX_BACKOFF,
+            next_attempt: time::Instant::now(),
+        };
+
+        transmitter.try_open();
+        transmitter
+    }
+
+    /// Returns the transmitter, if the device is open.
+    #[inline]
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.transmitter.as_mut()
+    }
+
+    /// Returns whether the device is open.
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        self.transmitter.is_some()
+    }
+
+    /// Returns the error that caused the last disconnect or failed attempt
+    /// to reopen the device.
+    #[inline]
+    pub fn last_error(&self) -> Option<&Error> {
+        self.last_error.as_ref()
+    }
+
+    /// Calls `callback` whenever the device is closed after an error or
+    /// reopened.
+    pub fn on_state_change<C>(&mut self, callback: C)
+    where
+        C: FnMut(ConnectionState) + Send + 'static,
+    {
+        self.callback = Some(Box::new(callback));
+    }
+
+    /// Sets the delay before the first attempt to reopen the device, and the
+    /// maximum it is doubled up to after failed attempts.
+    ///
+    /// Defaults to 100 milliseconds and five seconds.
+    #[inline]
+    pub fn set_backoff(&mut self, initial: time::Duration, max: time::Duration) {
+        self.initial_backoff = initial;
+        self.backoff = initial;
+        self.max_backoff = max.max(initial);
+    }
+
+    /// Tries to reopen the device, unless it is open already or the delay
+    /// since the last attempt has not passed yet, then retransmits the last
+    /// packet.
+    ///
+    /// Sending reopens the device as well; applications that only send
+    /// changes can call this periodically to restore output without waiting
+    /// for the next change. Returns whether the device is open.
+    pub fn reconnect(&mut self) -> bool {
+        if self.transmitter.is_some() {
+            return true;
+        }
+
+        if self.try_open() && !self.last_packet.is_empty() {
+            let packet = std::mem::take(&mut self.last_packet);
+            // a failure closes the device again
+            let _ = self.send_raw_dmx_packet(&packet);
+        }
+
+        self.transmitter.is_some()
+    }
+
+    fn try_open(&mut self) -> bool {
+        if self.transmitter.is_some() {
+            return true;
+        }
+
+        let now = time::Instant::now();
+        if now < self.next_attempt {
+            return false;
+        }
+
+        match (self.open)() {
+            Ok(transmitter) => {
+                self.transmitter = Some(transmitter);
+                self.backoff = self.initial_backoff;
+                self.notify(ConnectionState::Connected);
+                true
+            }
+            Err(e) => {
+                self.last_error = Some(e);
+                self.next_attempt = now + self.backoff;
+                self.backoff = (self.backoff * 2).min(self.max_backoff);
+                false
+            }
+        }
+    }
+
+    /// Runs `f` on the transmitter, closing it on I/O errors.
+    fn with_transmitter<F>(&mut self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut T) -> Result<()>,
+    {
+        if !self.try_open() {
+            return Ok(());
+        }
+
+        match f(self.transmitter.as_mut().unwrap()) {
+            Err(e @ Error::Io(_)) | Err(e @ Error::BreakFailed(_)) => {
+                self.transmitter = None;
+                self.last_error = Some(e);
+                self.next_attempt = time::Instant::now() + self.backoff;
+                self.notify(ConnectionState::Disconnected);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    fn notify(&mut self, state: ConnectionState) {
+        if let Some(ref mut callback) = self.callback {
+            callback(state);
+        }
+    }
+}
+
+impl ReconnectingTransmitter<DmxPort> {
+    /// Create a transmitter for a serial device, see `open_serial`.
+    pub fn serial<P: Into<OsString>>(port: P) -> ReconnectingTransmitter<DmxPort> {
+        let port = port.into();
+        ReconnectingTransmitter::new(move || open_serial(&port))
+    }
+}
+
+impl<T: DmxTransmitter<Error = Error>> DmxTransmitter for ReconnectingTransmitter<T> {
+    type Error = Error;
+
+    #[inline]
+    fn send_break(&mut self) -> Result<()> {
+        self.with_transmitter(|t| t.send_break())
+    }
+
+    #[inline]
+    fn send_raw_data(&mut self, data: &[u8]) -> Result<()> {
+        self.with_transmitter(|t| t.send_raw_data(data))
+    }
+
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<()> {
+        self.last_packet.clear();
+        self.last_packet.extend_from_slice(data);
+
+        self.with_transmitter(|t| t.send_raw_dmx_packet(data))
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ReconnectingTransmitter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReconnectingTransmitter")
+            .field("transmitter", &self.transmitter)
+            .field("last_error", &self.last_error)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .finish()
+    }
+}
@@ -0,0 +1,732 @@
+//! Recording DMX frames.
+//!
+//! A `DmxRecorder` wraps a transmitter or receiver and writes every frame
+//! passing through it to a file, along with the time it was sent or
+//! received. Recordings are read back using a `FrameReader`, or replayed
+//! through a transmitter by a `DmxPlayer`.
+//!
+//! ## Format
+//!
+//! Recordings start with the six bytes `DMXREC`, followed by the format
+//! version as a little-endian `u16`, currently 1. Frames follow, each one
+//! consisting of
+//!
+//! * the time since the recording started, in microseconds, as a
+//!   little-endian `u64`,
+//! * the length of the frame as a little-endian `u16`,
+//! * the frame itself, starting with its start code.
+//!
+//! ## Example
+//!
+//! ```
+//! use dmx::DmxTransmitter;
+//! use dmx::record::{DmxRecorder, FrameReader};
+//! use dmx::testing::MockTransmitter;
+//!
+//! let mut recorder = DmxRecorder::new(MockTransmitter::new(), Vec::new()).unwrap();
+//! recorder.send_dmx_packet(&[0xff, 0x80]).unwrap();
+//! recorder.send_dmx_packet(&[0x00, 0x40]).unwrap();
+//! let (_, recording) = recorder.finish().unwrap();
+//!
+//! let frames: Vec<_> = FrameReader::new(&recording[..])
+//!     .unwrap()
+//!     .collect::<Result<_, _>>()
+//!     .unwrap();
+//!
+//! assert_eq!(frames.len(), 2);
+//! assert_eq!(frames[1].channels()[..2], [0x00, 0x40]);
+//! assert!(frames[0].timestamp <= frames[1].timestamp);
+//! ```
+
+use core::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{thread, time};
+
+use crate::refresh::DEFAULT_FRAME_RATE;
+use crate::{prefix_start_code, DmxReceiver, DmxTransmitter, Result, StartCode};
+
+/// Identifies a recording, followed by the format version.
+pub const MAGIC: [u8; 6] = *b"DMXREC";
+
+/// Version of the recording format written.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// A frame read from a recording.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedFrame {
+    /// Time since the recording started.
+    pub timestamp: time::Duration,
+    /// Frame data, including the start code.
+    pub data: Vec<u8>,
+}
+
+impl RecordedFrame {
+    /// Returns the start code, if the frame is not empty.
+    #[inline]
+    pub fn start_code(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Returns the channels, i.e. all data after the start code.
+    #[inline]
+    pub fn channels(&self) -> &[u8] {
+        self.data.get(1..).unwrap_or(&[])
+    }
+}
+
+/// Writes frames in the recording format.
+#[derive(Debug)]
+pub struct FrameWriter<W> {
+    writer: W,
+    started: time::Instant,
+}
+
+impl<W: Write> FrameWriter<W> {
+    /// Starts a recording, writing its header to `writer`.
+    ///
+    /// Timestamps are relative to the time this is called.
+    pub fn new(mut writer: W) -> io::Result<FrameWriter<W>> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+
+        Ok(FrameWriter {
+            writer,
+            started: time::Instant::now(),
+        })
+    }
+
+    /// Returns the time since the recording started.
+    #[inline]
+    pub fn elapsed(&self) -> time::Duration {
+        self.started.elapsed()
+    }
+
+    /// Writes a frame, timestamped with the current time.
+    #[inline]
+    pub fn write_frame(&mut self, data: &[u8]) -> io::Result<()> {
+        let timestamp = self.elapsed();
+        self.write_frame_at(timestamp, data)
+    }
+
+    /// Writes a frame with an explicit timestamp.
+    ///
+    /// Fails with `InvalidInput` if `data` is longer than 65535 bytes.
+    pub fn write_frame_at(&mut self, timestamp: time::Duration, data: &[u8]) -> io::Result<()> {
+        let len = u16::try_from(data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too long"))?;
+        let micros = u64::try_from(timestamp.as_micros()).unwrap_or(u64::MAX);
+
+        let mut header = [0; 10];
+        header[..8].copy_from_slice(&micros.to_le_bytes());
+        header[8..].copy_from_slice(&len.to_le_bytes());
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(data)
+    }
+
+    /// Flushes the underlying writer.
+    #[inline]
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Flushes and returns the underlying writer.
+    #[inline]
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads frames from a recording.
+///
+/// Iterating yields all frames in the order they were recorded. A recording
+/// ending in the middle of a frame yields an `UnexpectedEof` error last.
+#[derive(Debug)]
+pub struct FrameReader<R> {
+    reader: R,
+}
+
+impl FrameReader<BufReader<File>> {
+    /// Opens a recording file.
+    #[inline]
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<FrameReader<BufReader<File>>> {
+        FrameReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Reads the header of a recording.
+    ///
+    /// Fails with `InvalidData` if `reader` does not contain a recording, or
+    /// one in a format version that is not supported.
+    pub fn new(mut reader: R) -> io::Result<FrameReader<R>> {
+        let mut header = [0; 8];
+        reader.read_exact(&mut header)?;
+
+        if header[..6] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a DMX recording"));
+        }
+
+        if u16::from_le_bytes([header[6], header[7]]) != FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unsupported recording format version",
+            ));
+        }
+
+        Ok(FrameReader { reader })
+    }
+
+    /// Reads the next frame.
+    ///
+    /// Returns `None` at the end of the recording.
+    pub fn read_frame(&mut self) -> io::Result<Option<RecordedFrame>> {
+        let mut header = [0; 10];
+
+        // the recording may only end in between frames
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.reader.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut micros = [0; 8];
+        micros.copy_from_slice(&header[..8]);
+        let len = u16::from_le_bytes([header[8], header[9]]);
+
+        let mut data = vec![0; usize::from(len)];
+        self.reader.read_exact(&mut data)?;
+
+        Ok(Some(RecordedFrame {
+            timestamp: time::Duration::from_micros(u64::from_le_bytes(micros)),
+            data,
+        }))
+    }
+
+    /// Returns the underlying reader.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = io::Result<RecordedFrame>;
+
+    #[inline]
+    fn next(&mut self) -> Option<io::Result<RecordedFrame>> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Records all frames sent through a transmitter, or received by a receiver.
+///
+/// Frames are recorded after they have been sent or received successfully.
+/// Frames sent as a break followed by raw data are collected until the next
+/// break, timestamped with the time of their break; call `flush` or `finish`
+/// to make sure the last one is written. Raw data sent without a break
+/// before it is collected the same way, timestamped with the time the first
+/// of it was sent.
+///
+/// ## Example
+///
+/// ```no_run
+/// use dmx::DmxReceiver;
+/// use dmx::record::DmxRecorder;
+///
+/// let input = dmx::open_serial_receiver("/dev/ttyUSB0").unwrap();
+/// let mut recorder = DmxRecorder::create(input, "console.dmxrec").unwrap();
+///
+/// loop {
+///     recorder.recv_dmx_packet().unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DmxRecorder<T, W = BufWriter<File>> {
+    inner: T,
+    writer: FrameWriter<W>,
+    // frame assembled from a break and raw data, with the time of its break
+    pending: Option<(time::Duration, Vec<u8>)>,
+}
+
+impl<T> DmxRecorder<T> {
+    /// Create a recorder writing to a newly created file.
+    ///
+    /// An existing file is truncated.
+    #[inline]
+    pub fn create<P: AsRef<Path>>(inner: T, path: P) -> io::Result<DmxRecorder<T>> {
+        DmxRecorder::new(inner, BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<T, W: Write> DmxRecorder<T, W> {
+    /// Create a recorder writing to `writer`.
+    ///
+    /// Writes the header of the recording immediately, timestamps are
+    /// relative to the time this is called.
+    #[inline]
+    pub fn new(inner: T, writer: W) -> io::Result<DmxRecorder<T, W>> {
+        Ok(DmxRecorder {
+            inner,
+            writer: FrameWriter::new(writer)?,
+            pending: None,
+        })
+    }
+
+    /// Returns the wrapped transmitter or receiver.
+    #[inline]
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the wrapped transmitter or receiver mutably.
+    ///
+    /// Frames sent or received directly are not recorded.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Writes any pending frame and flushes the recording.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_pending()?;
+        self.writer.flush()
+    }
+
+    /// Finishes the recording, returning the wrapped transmitter or receiver
+    /// and the writer.
+    pub fn finish(mut self) -> io::Result<(T, W)> {
+        self.write_pending()?;
+        let writer = self.writer.into_inner()?;
+
+        Ok((self.inner, writer))
+    }
+
+    fn write_pending(&mut self) -> io::Result<()> {
+        match self.pending.take() {
+            Some((timestamp, data)) => self.writer.write_frame_at(timestamp, &data),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T, W> DmxTransmitter for DmxRecorder<T, W>
+where
+    T: DmxTransmitter,
+    T::Error: From<io::Error>,
+    W: Write,
+{
+    type Error = T::Error;
+
+    fn send_break(&mut self) -> core::result::Result<(), T::Error> {
+        self.inner.send_break()?;
+        self.write_pending()?;
+        self.pending = Some((self.writer.elapsed(), Vec::new()));
+
+        Ok(())
+    }
+
+    fn send_raw_data(&mut self, data: &[u8]) -> core::result::Result<(), T::Error> {
+        self.inner.send_raw_data(data)?;
+
+        // appended to the frame of the last break, data sent without one
+        // starts a frame that is continued up to the next break
+        let timestamp = self.writer.elapsed();
+        self.pending
+            .get_or_insert_with(|| (timestamp, Vec::new()))
+            .1
+            .extend_from_slice(data);
+
+        Ok(())
+    }
+
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> core::result::Result<(), T::Error> {
+        self.inner.send_raw_dmx_packet(data)?;
+        self.write_pending()?;
+        self.writer.write_frame(data)?;
+
+        Ok(())
+    }
+
+    fn send_dmx_alt_packet(
+        &mut self,
+        channels: &[u8],
+        start: StartCode,
+    ) -> core::result::Result<(), T::Error> {
+        self.inner.send_dmx_alt_packet(channels, start)?;
+        self.write_pending()?;
+
+        let mut buf = [0; 513];
+        self.writer.write_frame(prefix_start_code(&mut buf, channels, start))?;
+
+        Ok(())
+    }
+}
+
+impl<T: DmxReceiver, W: Write> DmxReceiver for DmxRecorder<T, W> {
+    fn recv_dmx_packet_into(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.inner.recv_dmx_packet_into(buf)?;
+        self.writer.write_frame(&buf[..len])?;
+
+        Ok(len)
+    }
+}
+
+// longest time `DmxPlayer::run` sleeps before checking whether to stop
+const MAX_PLAYER_SLEEP: time::Duration = time::Duration::from_millis(100);
+
+/// Replays a recording through a transmitter.
+///
+/// Frames are sent at the same intervals they were recorded at, optionally
+/// sped up or slowed down. Playback starts at the first frame of the
+/// recording. When looping, the last frame is held for as long as the
+/// interval preceding it before playback restarts.
+///
+/// The player is driven either by calling `advance` regularly, e.g. from an
+/// existing show loop, or by `run`, which sleeps until each frame is due.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::sync::atomic::AtomicBool;
+/// use dmx::record::DmxPlayer;
+///
+/// let mut player = DmxPlayer::open("console.dmxrec").unwrap();
+/// player.set_looping(true);
+///
+/// let mut dmx_port = dmx::open_serial("/dev/ttyUSB0").unwrap();
+/// player.run(&mut dmx_port, &AtomicBool::new(false)).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct DmxPlayer {
+    // timestamps relative to the first frame
+    frames: Vec<RecordedFrame>,
+    length: time::Duration,
+    position: time::Duration,
+    // index of the next frame to send
+    next: usize,
+    speed: f64,
+    looping: bool,
+    paused: bool,
+}
+
+impl DmxPlayer {
+    /// Create a player for frames read from a recording.
+    ///
+    /// Frames must be ordered by their timestamp.
+    pub fn new(mut frames: Vec<RecordedFrame>) -> DmxPlayer {
+        let start = frames.first().map_or(time::Duration::ZERO, |f| f.timestamp);
+        for frame in &mut frames {
+            frame.timestamp = frame.timestamp.saturating_sub(start);
+        }
+
+        // the last frame is held for the interval preceding it, or a regular
+        // frame period if there is none
+        let length = match frames.len() {
+            0 => time::Duration::ZERO,
+            n => {
+                let last = frames[n - 1].timestamp;
+                let hold = match n {
+                    1 => time::Duration::ZERO,
+                    _ => last.saturating_sub(frames[n - 2].timestamp),
+                };
+
+                if hold.is_zero() {
+                    last + time::Duration::from_secs_f32(1.0 / DEFAULT_FRAME_RATE)
+                } else {
+                    last + hold
+                }
+            }
+        };
+
+        DmxPlayer {
+            frames,
+            length,
+            position: time::Duration::ZERO,
+            next: 0,
+            speed: 1.0,
+            looping: false,
+            paused: false,
+        }
+    }
+
+    /// Create a player for all frames of a recording.
+    pub fn load<R: Read>(reader: FrameReader<R>) -> io::Result<DmxPlayer> {
+        Ok(DmxPlayer::new(reader.collect::<io::Result<_>>()?))
+    }
+
+    /// Create a player for a recording file.
+    #[inline]
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<DmxPlayer> {
+        DmxPlayer::load(FrameReader::open(path)?)
+    }
+
+    /// Returns the frames played.
+    ///
+    /// Timestamps are relative to the first frame.
+    #[inline]
+    pub fn frames(&self) -> &[RecordedFrame] {
+        &self.frames
+    }
+
+    /// Returns the length of the recording, including the time the last
+    /// frame is held when looping.
+    #[inline]
+    pub fn duration(&self) -> time::Duration {
+        self.length
+    }
+
+    /// Returns the current position within the recording.
+    #[inline]
+    pub fn position(&self) -> time::Duration {
+        self.position
+    }
+
+    /// Moves playback to `position`.
+    ///
+    /// The frame current at the new position is sent again by the next call
+    /// to `advance`, so the output reflects the new position right away.
+    /// Positions beyond the end of the recording are clamped.
+    pub fn seek(&mut self, position: time::Duration) {
+        self.position = position.min(self.length);
+        self.next = self
+            .frames
+            .partition_point(|f| f.timestamp <= self.position)
+            .saturating_sub(1);
+    }
+
+    /// Returns the playback speed.
+    #[inline]
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Sets the playback speed, 1.0 being the original speed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `speed` is not a positive number.
+    #[inline]
+    pub fn set_speed(&mut self, speed: f64) {
+        assert!(speed > 0.0 && speed.is_finite(), "speed must be positive");
+        self.speed = speed;
+    }
+
+    /// Returns whether playback restarts at the end of the recording.
+    #[inline]
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Sets whether playback restarts at the end of the recording.
+    #[inline]
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    /// Pauses playback, holding the current position.
+    ///
+    /// No frames are sent while paused; transmitters that need continuous
+    /// refreshing should be refreshed by other means in the meantime.
+    #[inline]
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Resumes paused playback.
+    #[inline]
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Returns whether playback is paused.
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Returns whether all frames have been sent and playback is not
+    /// looping.
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.next >= self.frames.len()
+    }
+
+    /// Returns the time until the next frame is due, at the current speed.
+    ///
+    /// Returns `None` if paused or finished.
+    pub fn time_to_next_frame(&self) -> Option<time::Duration> {
+        if self.paused || self.frames.is_empty() {
+            return None;
+        }
+
+        let due = match self.frames.get(self.next) {
+            Some(frame) => frame.timestamp,
+            // the first frame, after restarting
+            None if self.looping => self.length,
+            None => return None,
+        };
+
+        Some(due.saturating_sub(self.position).div_f64(self.speed))
+    }
+
+    /// Advances playback by `dt`, sending all frames that became due.
+    ///
+    /// `dt` is scaled by the playback speed. Does nothing if paused. When
+    /// looping, entire repetitions passed within `dt` are skipped.
+    pub fn advance<T: DmxTransmitter>(
+        &mut self,
+        dt: time::Duration,
+        transmitter: &mut T,
+    ) -> core::result::Result<(), T::Error> {
+        if self.paused || self.frames.is_empty() {
+            return Ok(());
+        }
+
+        let mut target = self.position + dt.mul_f64(self.speed);
+
+        loop {
+            while let Some(frame) = self.frames.get(self.next) {
+                if frame.timestamp > target {
+                    break;
+                }
+                transmitter.send_raw_dmx_packet(&frame.data)?;
+                self.next += 1;
+            }
+
+            if !self.looping || target < self.length {
+                self.position = target.min(self.length);
+                return Ok(());
+            }
+
+            // restart, without replaying repetitions that were missed
+            let rest = (target - self.length).as_nanos() % self.length.as_nanos();
+            target = time::Duration::from_nanos(rest as u64);
+            self.position = time::Duration::ZERO;
+            self.next = 0;
+        }
+    }
+
+    /// Plays the recording, sleeping until each frame is due.
+    ///
+    /// Returns once playback is finished, which never happens when looping,
+    /// or `stop` is set.
+    pub fn run<T: DmxTransmitter>(
+        &mut self,
+        transmitter: &mut T,
+        stop: &AtomicBool,
+    ) -> core::result::Result<(), T::Error> {
+        let mut last = time::Instant::now();
+
+        while !stop.load(Ordering::Relaxed) {
+            let now = time::Instant::now();
+            self.advance(now - last, transmitter)?;
+            last = now;
+
+            if self.is_finished() {
+                break;
+            }
+
+            let wait = self
+                .time_to_next_frame()
+                .map_or(MAX_PLAYER_SLEEP, |t| t.min(MAX_PLAYER_SLEEP));
+            thread::sleep(wait);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTransmitter;
+
+    fn frames(recording: &[u8]) -> Vec<Vec<u8>> {
+        FrameReader::new(recording)
+            .unwrap()
+            .map(|frame| frame.unwrap().data)
+            .collect()
+    }
+
+    #[test]
+    fn raw_data_is_collected_up_to_the_next_break() {
+        let mut recorder = DmxRecorder::new(MockTransmitter::new(), Vec::new()).unwrap();
+
+        // data before the first break, then two frames sent in pieces
+        recorder.send_raw_data(&[0x00, 0x01]).unwrap();
+        recorder.send_raw_data(&[0x02]).unwrap();
+        recorder.send_break().unwrap();
+        recorder.send_raw_data(&[0x00, 0xff]).unwrap();
+        recorder.send_raw_data(&[0x80]).unwrap();
+        recorder.send_break().unwrap();
+        recorder.send_raw_data(&[0x17]).unwrap();
+        let (_, recording) = recorder.finish().unwrap();
+
+        let expected = [vec![0x00, 0x01, 0x02], vec![0x00, 0xff, 0x80], vec![0x17]];
+        assert_eq!(frames(&recording), expected);
+    }
+
+    #[test]
+    fn complete_packets_end_the_pending_frame() {
+        let mut recorder = DmxRecorder::new(MockTransmitter::new(), Vec::new()).unwrap();
+
+        recorder.send_break().unwrap();
+        recorder.send_raw_data(&[0x00, 0xff]).unwrap();
+        recorder.send_raw_dmx_packet(&[0x00, 0x80]).unwrap();
+        recorder.send_raw_data(&[0x00, 0x40]).unwrap();
+        let (_, recording) = recorder.finish().unwrap();
+
+        let expected = [vec![0x00, 0xff], vec![0x00, 0x80], vec![0x00, 0x40]];
+        assert_eq!(frames(&recording), expected);
+    }
+
+    // counts alternate start code packets, which it handles itself
+    #[derive(Default)]
+    struct AltCounter(usize);
+
+    impl DmxTransmitter for AltCounter {
+        type Error = crate::Error;
+
+        fn send_break(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn send_raw_data(&mut self, _: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        fn send_raw_dmx_packet(&mut self, _: &[u8]) -> Result<()> {
+            unreachable!("packets are sent through send_dmx_alt_packet")
+        }
+
+        fn send_dmx_alt_packet(&mut self, _: &[u8], _: StartCode) -> Result<()> {
+            self.0 += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn alt_packets_are_forwarded_and_recorded_with_their_start_code() {
+        let mut recorder = DmxRecorder::new(AltCounter::default(), Vec::new()).unwrap();
+
+        recorder.send_dmx_alt_packet(&[0xff, 0x80], StartCode::Text).unwrap();
+        recorder.send_dmx_packet(&[0x40]).unwrap();
+        recorder.send_dmx_alt_packet(&[0x55; 600], StartCode::Null).unwrap();
+        let (counter, recording) = recorder.finish().unwrap();
+
+        assert_eq!(counter.0, 3);
+        let frames = frames(&recording);
+        assert_eq!(frames[..2], [vec![0x17, 0xff, 0x80], vec![0x00, 0x40]]);
+        assert_eq!(frames[2].len(), 513);
+    }
+}
@@ -0,0 +1,538 @@
+//! Continuous background transmission.
+
+use std::sync::atomic::{self, AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::{fmt, hint, io, panic, thread, time};
+
+use crate::address::DmxAddress;
+use crate::packet::MAX_CHANNELS;
+use crate::universe::{Channel16, DmxUniverse};
+use crate::{DmxTransmitter, Error, Result};
+
+/// Default refresh rate in frames per second.
+pub const DEFAULT_FRAME_RATE: f32 = 40.0;
+
+/// Scheduling priority of a refresh thread.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ThreadPriority {
+    /// The default priority of new threads.
+    #[default]
+    Normal,
+    /// Real-time `SCHED_FIFO` scheduling, on Linux only.
+    ///
+    /// The thread then preempts all threads with normal priority, lowering
+    /// the jitter of frame timings considerably on loaded systems.
+    Realtime {
+        /// Priority from 1 to 99, higher values take precedence.
+        priority: u8,
+        /// Locks the memory of the whole process (`mlockall`), so the thread
+        /// is never delayed by page faults.
+        lock_memory: bool,
+    },
+}
+
+impl ThreadPriority {
+    /// Applies the priority to the calling thread.
+    ///
+    /// Returns `Ok(false)` if real-time scheduling is not supported on this
+    /// platform or the process lacks the privileges, usually
+    /// `CAP_SYS_NICE` or an `rtprio` limit, leaving the thread as it is.
+    /// Failing to lock memory is not fatal either and yields `Ok(false)`,
+    /// with the priority applied nonetheless.
+    pub fn apply(self) -> io::Result<bool> {
+        match self {
+            ThreadPriority::Normal => Ok(true),
+            ThreadPriority::Realtime {
+                priority,
+                lock_memory,
+            } => set_realtime(priority, lock_memory),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_realtime(priority: u8, lock_memory: bool) -> io::Result<bool> {
+    let param = libc::sched_param {
+        sched_priority: libc::c_int::from(priority.clamp(1, 99)),
+    };
+
+    // pthread functions return the error instead of setting errno
+    match unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) } {
+        0 => (),
+        libc::EPERM => return Ok(false),
+        e => return Err(io::Error::from_raw_os_error(e)),
+    }
+
+    if lock_memory && unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } != 0 {
+        let e = io::Error::last_os_error();
+
+        return match e.raw_os_error() {
+            Some(libc::EPERM) | Some(libc::ENOMEM) => Ok(false),
+            _ => Err(e),
+        };
+    }
+
+    Ok(true)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_realtime(_priority: u8, _lock_memory: bool) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// A frame of a `DmxRefresher`, as passed to its hooks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// Number of the frame, counting from zero since the refresher started.
+    pub number: u64,
+    /// Time at which sending the frame started, before calling the
+    /// `on_before_frame` hook.
+    pub time: time::Instant,
+}
+
+type Hook = Box<dyn FnMut(&FrameInfo) + Send>;
+
+/// Hooks of a refresher, shared with its thread.
+#[derive(Default)]
+struct Hooks {
+    before: Option<Hook>,
+    after: Option<Hook>,
+}
+
+/// Background refresher.
+///
+/// DMX fixtures expect a continuous stream of packets and may switch off once
+/// packets stop arriving. A refresher takes ownership of a transmitter and
+/// spawns a thread that retransmits the current universe at a fixed rate.
+///
+/// The universe is changed through `SharedUniverse` handles, which can be
+/// cloned cheaply and shared between threads. Dropping the refresher stops
+/// the background thread.
+///
+/// Hooks called from the thread before and after every frame allow
+/// per-frame animation or synchronization, see `on_before_frame`.
+pub struct DmxRefresher {
+    handle: SharedUniverse,
+    channel_count: Arc<AtomicUsize>,
+    hooks: Arc<Mutex<Hooks>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<Result<()>>>,
+}
+
+impl DmxRefresher {
+    /// Start refreshing at the default rate of 40 frames per second.
+    #[inline]
+    pub fn new<T>(transmitter: T) -> DmxRefresher
+    where
+        T: DmxTransmitter<Error = Error> + Send + 'static,
+    {
+        DmxRefresher::with_frame_rate(transmitter, DEFAULT_FRAME_RATE)
+    }
+
+    /// Start refreshing at a fixed frame rate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fps` is not a positive number.
+    #[inline]
+    pub fn with_frame_rate<T>(transmitter: T, fps: f32) -> DmxRefresher
+    where
+        T: DmxTransmitter<Error = Error> + Send + 'static,
+    {
+        DmxRefresher::with_priority(transmitter, fps, ThreadPriority::Normal)
+    }
+
+    /// Start refreshing at a fixed frame rate, from a thread with the given
+    /// priority.
+    ///
+    /// If the priority cannot be applied, the thread falls back to the
+    /// default priority; use `ThreadPriority::apply` to check beforehand.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fps` is not a positive number.
+    ///
+    /// ```no_run
+    /// use dmx::{DmxRefresher, ThreadPriority};
+    ///
+    /// let port = dmx::open_serial("/dev/ttyS1").unwrap();
+    /// let priority = ThreadPriority::Realtime {
+    ///     priority: 50,
+    ///     lock_memory: true,
+    /// };
+    ///
+    /// let refresher = DmxRefresher::with_priority(port, 40.0, priority);
+    /// ```
+    pub fn with_priority<T>(mut transmitter: T, fps: f32, priority: ThreadPriority) -> DmxRefresher
+    where
+        T: DmxTransmitter<Error = Error> + Send + 'static,
+    {
+        assert!(fps > 0.0, "frame rate must be positive");
+
+        let handle = SharedUniverse::new();
+        let channel_count = Arc::new(AtomicUsize::new(MAX_CHANNELS));
+        let hooks = Arc::new(Mutex::new(Hooks::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let handle = handle.clone();
+            let channel_count = channel_count.clone();
+            let hooks = hooks.clone();
+            let stop = stop.clone();
+
+            thread::spawn(move || {
+                // running at normal priority beats not running at all
+                if !priority.apply().unwrap_or(false) {
+                    trace_event!(warn, ?priority, "could not apply thread priority");
+                }
+
+                let mut number = 0;
+                run_at_frame_rate(fps, &stop, || {
+                    let frame = FrameInfo {
+                        number,
+                        time: time::Instant::now(),
+                    };
+                    number += 1;
+
+                    call_hook(&hooks, &frame, |hooks| &mut hooks.before);
+                    let count = channel_count.load(Ordering::Relaxed);
+                    transmitter.send_dmx_packet(&handle.snapshot()[..count])?;
+                    call_hook(&hooks, &frame, |hooks| &mut hooks.after);
+                    Ok(())
+                })
+            })
+        };
+
+        DmxRefresher {
+            handle,
+            channel_count,
+            hooks,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns a new handle to the refreshed universe.
+    #[inline]
+    pub fn handle(&self) -> SharedUniverse {
+        self.handle.clone()
+    }
+
+    /// Sets channel `n` to `value`.
+    ///
+    /// See `SharedUniverse::set_channel`.
+    #[inline]
+    pub fn set_channel(&self, n: DmxAddress, value: u8) {
+        self.handle.set_channel(n, value)
+    }
+
+    /// Sets consecutive channels, starting at channel `start`.
+    ///
+    /// See `SharedUniverse::set_channels`.
+    #[inline]
+    pub fn set_channels(&self, start: DmxAddress, values: &[u8]) {
+        self.handle.set_channels(start, values)
+    }
+
+    /// Returns the number of channels sent per frame.
+    #[inline]
+    pub fn channel_count(&self) -> usize {
+        self.channel_count.load(Ordering::Relaxed)
+    }
+
+    /// Sends only the first `n` channels with the following frames.
+    ///
+    /// `n` is clamped to the range from 1 to 512. Sending fewer channels
+    /// allows higher frame rates on small rigs, as long as the transmitter
+    /// does not pad packets to full length; a `DmxPort` needs its own count
+    /// lowered as well, see `DmxPort::set_channel_count`. Ports enforce the
+    /// minimum break-to-break time, which caps the frame rate at about 830
+    /// frames per second regardless of `fps`.
+    ///
+    /// ```no_run
+    /// use dmx::DmxRefresher;
+    ///
+    /// let port = dmx::DmxPort::builder("/dev/ttyUSB0").channel_count(24).open().unwrap();
+    /// let refresher = DmxRefresher::with_frame_rate(port, 200.0);
+    /// refresher.set_channel_count(24);
+    /// ```
+    #[inline]
+    pub fn set_channel_count(&self, n: usize) {
+        self.channel_count.store(n.clamp(1, MAX_CHANNELS), Ordering::Relaxed);
+    }
+
+    /// Sets a hook called right before each frame is sent.
+    ///
+    /// Changes made to the universe by the hook are part of the frame, which
+    /// makes it suitable for animation. Replaces the previous hook. Hooks
+    /// delay the frame they are called for, and must not set hooks
+    /// themselves.
+    ///
+    /// ```no_run
+    /// use dmx::{DmxAddress, DmxRefresher};
+    ///
+    /// let refresher = DmxRefresher::new(dmx::open_serial("/dev/ttyS1").unwrap());
+    /// let universe = refresher.handle();
+    /// let dimmer = DmxAddress::new(1).unwrap();
+    ///
+    /// // a sawtooth, rising by one step per frame
+    /// refresher.on_before_frame(move |frame| universe.set_channel(dimmer, frame.number as u8));
+    /// ```
+    pub fn on_before_frame<F>(&self, hook: F)
+    where
+        F: FnMut(&FrameInfo) + Send + 'static,
+    {
+        lock_hooks(&self.hooks).before = Some(Box::new(hook));
+    }
+
+    /// Sets a hook called after each frame was sent successfully.
+    ///
+    /// Replaces the previous hook. See `on_before_frame`.
+    pub fn on_after_frame<F>(&self, hook: F)
+    where
+        F: FnMut(&FrameInfo) + Send + 'static,
+    {
+        lock_hooks(&self.hooks).after = Some(Box::new(hook));
+    }
+
+    /// Removes both hooks.
+    pub fn clear_hooks(&self) {
+        *lock_hooks(&self.hooks) = Hooks::default();
+    }
+
+    /// Returns whether the background thread is still transmitting.
+    ///
+    /// The thread exits early if an error occurs while sending, which can be
+    /// retrieved through `stop`.
+    #[inline]
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|t| !t.is_finished())
+    }
+
+    /// Stop refreshing.
+    ///
+    /// Waits for the background thread to finish and returns the error that
+    /// caused it to exit prematurely, if any.
+    pub fn stop(mut self) -> Result<()> {
+        match self.shutdown() {
+            Ok(rv) => rv,
+            Err(e) => panic::resume_unwind(e),
+        }
+    }
+
+    fn shutdown(&mut self) -> thread::Result<Result<()>> {
+        self.stop.store(true, Ordering::Relaxed);
+
+        match self.thread.take() {
+            Some(thread) => thread.join(),
+            None => Ok(Ok(())),
+        }
+    }
+}
+
+impl Drop for DmxRefresher {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+fn lock_hooks(hooks: &Mutex<Hooks>) -> MutexGuard<'_, Hooks> {
+    // hooks stay usable even if one of them panicked
+    hooks.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Calls the hook selected by `select`, if it is set.
+fn call_hook<S>(hooks: &Mutex<Hooks>, frame: &FrameInfo, select: S)
+where
+    S: FnOnce(&mut Hooks) -> &mut Option<Hook>,
+{
+    if let Some(hook) = select(&mut lock_hooks(hooks)) {
+        hook(frame);
+    }
+}
+
+/// Calls `f` at a fixed frame rate until `stop` is set or `f` fails.
+///
+/// Schedules by deadline, to avoid accumulating drift.
+pub(crate) fn run_at_frame_rate<E, F>(
+    fps: f32,
+    stop: &AtomicBool,
+    mut f: F,
+) -> std::result::Result<(), E>
+where
+    F: FnMut() -> std::result::Result<(), E>,
+{
+    assert!(fps > 0.0, "frame rate must be positive");
+
+    let period = time::Duration::from_secs_f32(1.0 / fps);
+    let mut next = time::Instant::now();
+
+    while !stop.load(Ordering::Relaxed) {
+        f()?;
+
+        next += period;
+        let now = time::Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        } else {
+            // fell behind, do not try to catch up with a burst of frames
+            next = now;
+        }
+    }
+
+    Ok(())
+}
+
+/// A universe shared between threads.
+///
+/// Clones refer to the same universe. Used by refresh loops, see
+/// `DmxRefresher` and `DmxTransmitter::run_refresh_loop`; changes become
+/// visible with the next frame sent.
+///
+/// Changes are made under a lock, then published to a copy of the channels
+/// protected by a sequence lock. Refresh loops read that copy through
+/// `snapshot`, which never blocks on the lock, so threads updating the
+/// universe neither wait for frames being sent nor delay them.
+#[derive(Clone, Default)]
+pub struct SharedUniverse {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    universe: Mutex<DmxUniverse>,
+    channels: Snapshot,
+}
+
+/// Handle to the universe of a `DmxRefresher`.
+pub type RefreshHandle = SharedUniverse;
+
+impl SharedUniverse {
+    /// Create a new shared universe with all channels set to zero.
+    #[inline]
+    pub fn new() -> SharedUniverse {
+        SharedUniverse::default()
+    }
+
+    /// Sets channel `n` to `value`.
+    #[inline]
+    pub fn set_channel(&self, n: DmxAddress, value: u8) {
+        self.update(|u| u.set(n, value))
+    }
+
+    /// Sets consecutive channels, starting at channel `start`.
+    ///
+    /// See `DmxUniverse::set_range`.
+    #[inline]
+    pub fn set_channels(&self, start: DmxAddress, values: &[u8]) {
+        self.update(|u| u.set_range(start, values))
+    }
+
+    /// Sets a 16-bit channel to `value`.
+    ///
+    /// See `DmxUniverse::set_channel_16`.
+    #[inline]
+    pub fn set_channel_16(&self, channel: Channel16, value: u16) {
+        self.update(|u| u.set_channel_16(channel, value))
+    }
+
+    /// Modifies the universe in place.
+    ///
+    /// All changes made by `f` are sent out in the same frame.
+    pub fn update<F: FnOnce(&mut DmxUniverse)>(&self, f: F) {
+        let mut universe = self.lock();
+        f(&mut universe);
+
+        // still holding the lock, so there is only ever one writer
+        self.inner.channels.publish(universe.channels());
+    }
+
+    /// Returns a copy of the current universe.
+    ///
+    /// Unlike `snapshot`, this includes fades in progress, but has to wait
+    /// for concurrent updates.
+    #[inline]
+    pub fn universe(&self) -> DmxUniverse {
+        self.lock().clone()
+    }
+
+    /// Returns a consistent copy of all channel values.
+    ///
+    /// Does not take the lock; if an update is being published at the same
+    /// time, the copy is retried until it is not torn.
+    #[inline]
+    pub fn snapshot(&self) -> [u8; MAX_CHANNELS] {
+        self.inner.channels.read()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DmxUniverse> {
+        // the universe is valid at all times, even if a panic occurred while
+        // it was being modified
+        self.inner.universe.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for SharedUniverse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedUniverse")
+            .field("channels", &&self.snapshot()[..])
+            .finish()
+    }
+}
+
+const WORD_SIZE: usize = 4;
+
+/// Channel values behind a sequence lock, for a single writer.
+///
+/// The sequence number is odd while the values are being written. Readers
+/// retry if it was odd or changed while they were reading.
+struct Snapshot {
+    sequence: AtomicUsize,
+    words: [AtomicU32; MAX_CHANNELS / WORD_SIZE],
+}
+
+impl Snapshot {
+    /// Stores new channel values. Must not be called concurrently.
+    fn publish(&self, channels: &[u8]) {
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        self.sequence.store(sequence.wrapping_add(1), Ordering::Relaxed);
+        atomic::fence(Ordering::Release);
+
+        for (word, bytes) in self.words.iter().zip(channels.chunks_exact(WORD_SIZE)) {
+            let value = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            word.store(value, Ordering::Relaxed);
+        }
+
+        self.sequence.store(sequence.wrapping_add(2), Ordering::Release);
+    }
+
+    fn read(&self) -> [u8; MAX_CHANNELS] {
+        let mut channels = [0; MAX_CHANNELS];
+
+        loop {
+            let before = self.sequence.load(Ordering::Acquire);
+
+            if before & 1 == 0 {
+                for (word, bytes) in self.words.iter().zip(channels.chunks_exact_mut(WORD_SIZE)) {
+                    bytes.copy_from_slice(&word.load(Ordering::Relaxed).to_ne_bytes());
+                }
+
+                atomic::fence(Ordering::Acquire);
+                if self.sequence.load(Ordering::Relaxed) == before {
+                    return channels;
+                }
+            }
+
+            hint::spin_loop();
+        }
+    }
+}
+
+impl Default for Snapshot {
+    fn default() -> Snapshot {
+        Snapshot {
+            sequence: AtomicUsize::new(0),
+            words: std::array::from_fn(|_| AtomicU32::new(0)),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Remapping of received channels.
+
+use crate::address::DmxAddress;
+use crate::packet::MAX_CHANNELS;
+use crate::universe::DmxUniverse;
+use crate::{Error, Result};
+
+/// Moves, drops and scales channels on their way from a receiver to a
+/// transmitter.
+///
+/// Maps each output channel to at most one input channel, optionally scaling
+/// its value. Output channels not mapped are set to zero, so a remap starting
+/// out empty drops everything not mapped explicitly, while one starting out
+/// as `identity` passes everything through. Handles fixtures with fixed
+/// addresses that cannot be rewired, e.g. in a gateway.
+///
+/// ## Example
+///
+/// ```
+/// use dmx::{ChannelRemap, DmxAddress, DmxUniverse};
+///
+/// // move channels 1-24 to 101-124, at 80%
+/// let mut remap = ChannelRemap::new();
+/// remap
+///     .map_scaled(DmxAddress::new(1).unwrap(), DmxAddress::new(101).unwrap(), 24, 0.8)
+///     .unwrap();
+///
+/// let mut output = DmxUniverse::new();
+/// remap.apply(&[0xff; 512], &mut output);
+/// assert_eq!(output.get(DmxAddress::new(101).unwrap()), 0xcc);
+/// assert_eq!(output.get(DmxAddress::new(1).unwrap()), 0x00);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelRemap {
+    // input channel index and scale of each output channel
+    sources: [Option<(u16, f32)>; MAX_CHANNELS],
+}
+
+impl Default for ChannelRemap {
+    #[inline]
+    fn default() -> ChannelRemap {
+        ChannelRemap {
+            sources: [None; MAX_CHANNELS],
+        }
+    }
+}
+
+impl ChannelRemap {
+    /// Create a remap dropping all channels.
+    #[inline]
+    pub fn new() -> ChannelRemap {
+        ChannelRemap::default()
+    }
+
+    /// Create a remap passing all channels through unchanged.
+    pub fn identity() -> ChannelRemap {
+        let mut remap = ChannelRemap::new();
+        for (i, source) in remap.sources.iter_mut().enumerate() {
+            *source = Some((i as u16, 1.0));
+        }
+        remap
+    }
+
+    /// Maps `count` consecutive input channels, starting at channel `from`,
+    /// onto the output channels starting at channel `to`.
+    ///
+    /// Replaces earlier mappings of the output channels. Fails with
+    /// `Error::InvalidParameter` if either range extends beyond channel 512.
+    #[inline]
+    pub fn map(&mut self, from: DmxAddress, to: DmxAddress, count: usize) -> Result<()> {
+        self.map_scaled(from, to, count, 1.0)
+    }
+
+    /// Maps channels like `map`, multiplying their values by `scale`.
+    ///
+    /// Values are rounded and limited to full, a negative or NaN `scale`
+    /// sets the channels to zero.
+    pub fn map_scaled(
+        &mut self,
+        from: DmxAddress,
+        to: DmxAddress,
+        count: usize,
+        scale: f32,
+    ) -> Result<()> {
+        if from.index() + count > MAX_CHANNELS || to.index() + count > MAX_CHANNELS {
+            return Err(Error::InvalidParameter("remapped channels beyond 512"));
+        }
+
+        let scale = if scale.is_nan() { 0.0 } else { scale.max(0.0) };
+        let outputs = &mut self.sources[to.index()..to.index() + count];
+        for (i, source) in outputs.iter_mut().enumerate() {
+            *source = Some(((from.index() + i) as u16, scale));
+        }
+        Ok(())
+    }
+
+    /// Drops `count` consecutive output channels, starting at channel
+    /// `start`, setting them to zero.
+    ///
+    /// Channels beyond 512 are ignored.
+    pub fn drop_range(&mut self, start: DmxAddress, count: usize) {
+        for source in self.sources[start.index()..].iter_mut().take(count) {
+            *source = None;
+        }
+    }
+
+    /// Returns the input channel output channel `n` is mapped to, and its
+    /// scale.
+    #[inline]
+    pub fn source(&self, n: DmxAddress) -> Option<(DmxAddress, f32)> {
+        let (index, scale) = self.sources[n.index()]?;
+        Some((DmxAddress::from_index(index.into())?, scale))
+    }
+
+    /// Writes the remapped channels of `input` into `output`.
+    ///
+    /// Every channel of `output` is written; channels mapped to input
+    /// channels beyond the end of `input` are set to zero, like those not
+    /// mapped at all.
+    pub fn apply(&self, input: &[u8], output: &mut DmxUniverse) {
+        let channels = output.channels_mut();
+
+        for (v, source) in channels.iter_mut().zip(&self.sources) {
+            *v = match *source {
+                Some((index, scale)) => match input.get(usize::from(index)) {
+                    Some(&value) if scale == 1.0 => value,
+                    Some(&value) => (f32::from(value) * scale).round().min(255.0) as u8,
+                    None => 0,
+                },
+                None => 0,
+            };
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! Universe discovery.
+
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::{cmp, io, str, time};
+
+use super::{
+    multicast_address, write_flags_length, write_root_layer, write_source_name, Cid,
+    ACN_PACKET_IDENTIFIER, FRAMING_OFFSET, SACN_PORT, VECTOR_ROOT_E131_EXTENDED,
+};
+use crate::{Error, Result};
+
+/// Universe discovery packets are sent to.
+///
+/// Outside of the range of universes carrying data, so it never collides
+/// with one.
+pub const DISCOVERY_UNIVERSE: u16 = 64214;
+
+/// Interval at which sources advertise their universes.
+pub const DISCOVERY_INTERVAL: time::Duration = time::Duration::from_secs(10);
+
+/// Maximum number of universes listed in a single discovery packet.
+pub const MAX_DISCOVERY_UNIVERSES: usize = 512;
+
+const VECTOR_E131_EXTENDED_DISCOVERY: u32 = 0x0000_0002;
+const VECTOR_UNIVERSE_DISCOVERY_UNIVERSE_LIST: u32 = 0x0000_0001;
+
+// offset of the universe discovery layer
+const DISCOVERY_OFFSET: usize = 112;
+pub(crate) const DISCOVERY_HEADER_LEN: usize = 120;
+
+/// A page of a source's universe list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveryPage<'a> {
+    /// CID of the source.
+    pub cid: Cid,
+    /// Human readable source name, up to 63 bytes.
+    pub source_name: &'a str,
+    /// Index of this page, starting at zero.
+    pub page: u8,
+    /// Index of the last page of the list.
+    pub last_page: u8,
+    /// Universes listed on this page, in ascending order.
+    pub universes: Vec<u16>,
+}
+
+/// Encodes a universe discovery packet into `buf`.
+///
+/// Lists up to 512 of `universes`, which must be sorted in ascending order,
+/// as page `page` out of `last_page + 1`. Returns the packet length; `buf`
+/// must hold at least 1144 bytes.
+pub fn encode_discovery(
+    buf: &mut [u8],
+    cid: &Cid,
+    source_name: &str,
+    page: u8,
+    last_page: u8,
+    universes: &[u16],
+) -> usize {
+    let count = cmp::min(universes.len(), MAX_DISCOVERY_UNIVERSES);
+    let len = DISCOVERY_HEADER_LEN + count * 2;
+
+    write_root_layer(buf, VECTOR_ROOT_E131_EXTENDED, cid, len);
+
+    write_flags_length(buf, FRAMING_OFFSET, len);
+    buf[40..44].copy_from_slice(&VECTOR_E131_EXTENDED_DISCOVERY.to_be_bytes());
+    write_source_name(&mut buf[44..108], source_name);
+    for v in &mut buf[108..112] {
+        *v = 0;
+    }
+
+    write_flags_length(buf, DISCOVERY_OFFSET, len);
+    buf[114..118].copy_from_slice(&VECTOR_UNIVERSE_DISCOVERY_UNIVERSE_LIST.to_be_bytes());
+    buf[118] = page;
+    buf[119] = last_page;
+    for (i, universe) in universes[..count].iter().enumerate() {
+        let start = DISCOVERY_HEADER_LEN + i * 2;
+        buf[start..(start + 2)].copy_from_slice(&universe.to_be_bytes());
+    }
+
+    len
+}
+
+/// Decodes a universe discovery packet.
+///
+/// Returns `None` if the packet is not a valid universe discovery packet.
+pub fn decode_discovery(packet: &[u8]) -> Option<DiscoveryPage<'_>> {
+    if packet.len() < DISCOVERY_HEADER_LEN || &packet[4..16] != ACN_PACKET_IDENTIFIER {
+        return None;
+    }
+
+    let u16_at = |i: usize| u16::from_be_bytes([packet[i], packet[i + 1]]);
+    let u32_at = |i: usize| u32::from(u16_at(i)) << 16 | u32::from(u16_at(i + 2));
+
+    if u32_at(18) != VECTOR_ROOT_E131_EXTENDED
+        || u32_at(FRAMING_OFFSET + 2) != VECTOR_E131_EXTENDED_DISCOVERY
+        || u32_at(DISCOVERY_OFFSET + 2) != VECTOR_UNIVERSE_DISCOVERY_UNIVERSE_LIST
+    {
+        return None;
+    }
+
+    // the length of the discovery layer tells the number of universes
+    let layer_len = usize::from(u16_at(DISCOVERY_OFFSET) & 0x0fff);
+    let end = cmp::min(DISCOVERY_OFFSET + layer_len, packet.len());
+    if end < DISCOVERY_HEADER_LEN {
+        return None;
+    }
+
+    let name = &packet[44..108];
+    let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+
+    let mut cid = [0; 16];
+    cid.copy_from_slice(&packet[22..38]);
+
+    Some(DiscoveryPage {
+        cid,
+        source_name: str::from_utf8(&name[..name_len]).unwrap_or(""),
+        page: packet[118],
+        last_page: packet[119],
+        universes: packet[DISCOVERY_HEADER_LEN..end]
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect(),
+    })
+}
+
+/// A source found through universe discovery.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredSource {
+    /// CID of the source.
+    pub cid: Cid,
+    /// Human readable source name.
+    pub name: String,
+    /// Universes the source is sending to, in ascending order.
+    pub universes: Vec<u16>,
+}
+
+/// Pages of a source's list received so far.
+#[derive(Clone, Debug)]
+struct PendingList {
+    name: String,
+    pages: BTreeMap<u8, Vec<u16>>,
+    last_seen: time::Instant,
+    // last complete list
+    universes: Option<Vec<u16>>,
+}
+
+/// Receives the universe lists advertised by sources.
+///
+/// Joins the multicast group of `DISCOVERY_UNIVERSE` and collects the pages
+/// of each source's list. Sources are forgotten if they do not advertise
+/// their universes again within three times `DISCOVERY_INTERVAL`.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use dmx::sacn;
+///
+/// for source in sacn::discover(Duration::from_secs(11)).unwrap() {
+///     println!("{}: {:?}", source.name, source.universes);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DiscoveryReceiver {
+    socket: UdpSocket,
+    sources: BTreeMap<Cid, PendingList>,
+    buf: [u8; DISCOVERY_HEADER_LEN + MAX_DISCOVERY_UNIVERSES * 2],
+}
+
+impl DiscoveryReceiver {
+    /// Create a receiver listening on the sACN port of all interfaces.
+    #[inline]
+    pub fn new() -> Result<DiscoveryReceiver> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, SACN_PORT))?;
+
+        DiscoveryReceiver::with_socket(socket)
+    }
+
+    /// Create a receiver from a socket bound to the sACN port.
+    ///
+    /// Joins the discovery multicast group on all interfaces.
+    pub fn with_socket(socket: UdpSocket) -> Result<DiscoveryReceiver> {
+        socket.join_multicast_v4(
+            &multicast_address(DISCOVERY_UNIVERSE),
+            &Ipv4Addr::UNSPECIFIED,
+        )?;
+
+        Ok(DiscoveryReceiver {
+            socket,
+            sources: BTreeMap::new(),
+            buf: [0; DISCOVERY_HEADER_LEN + MAX_DISCOVERY_UNIVERSES * 2],
+        })
+    }
+
+    /// Returns the underlying socket, e.g. to set a read timeout.
+    #[inline]
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Blocking receive the next complete universe list.
+    ///
+    /// Returns the source once all pages of its list have arrived.
+    pub fn recv(&mut self) -> Result<DiscoveredSource> {
+        loop {
+            let len = self.socket.recv(&mut self.buf).map_err(Error::from_read)?;
+
+            if let Some(source) = self.handle_packet(len) {
+                return Ok(source);
+            }
+        }
+    }
+
+    /// Processes a received packet.
+    ///
+    /// Returns the source whose list was completed, if any.
+    fn handle_packet(&mut self, len: usize) -> Option<DiscoveredSource> {
+        let page = decode_discovery(&self.buf[..len])?;
+        let now = time::Instant::now();
+
+        let list = self.sources.entry(page.cid).or_insert_with(|| PendingList {
+            name: String::new(),
+            pages: BTreeMap::new(),
+            last_seen: now,
+            universes: None,
+        });
+
+        // the first page starts another round
+        if page.page == 0 {
+            list.pages.clear();
+        }
+        list.name = page.source_name.to_owned();
+        list.last_seen = now;
+        list.pages.insert(page.page, page.universes);
+
+        if list.pages.len() != usize::from(page.last_page) + 1 {
+            return None;
+        }
+
+        let universes: Vec<u16> = list.pages.values().flatten().copied().collect();
+        list.pages.clear();
+        list.universes = Some(universes.clone());
+
+        Some(DiscoveredSource {
+            cid: page.cid,
+            name: list.name.clone(),
+            universes,
+        })
+    }
+
+    /// Returns the sources whose lists were received, ordered by CID.
+    pub fn sources(&self) -> Vec<DiscoveredSource> {
+        let now = time::Instant::now();
+
+        self.sources
+            .iter()
+            .filter(|(_, list)| now.duration_since(list.last_seen) < DISCOVERY_INTERVAL * 3)
+            .filter_map(|(&cid, list)| {
+                Some(DiscoveredSource {
+                    cid,
+                    name: list.name.clone(),
+                    universes: list.universes.clone()?,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns all universes advertised by any source, in ascending order.
+    pub fn universes(&self) -> Vec<u16> {
+        let mut universes: Vec<u16> = self
+            .sources()
+            .into_iter()
+            .flat_map(|source| source.universes)
+            .collect();
+
+        universes.sort_unstable();
+        universes.dedup();
+        universes
+    }
+}
+
+/// Discovers sources on the local network, with the universes they send to.
+///
+/// Collects universe lists until `timeout` has passed. As sources only
+/// advertise their universes every `DISCOVERY_INTERVAL`, the timeout should
+/// be slightly longer than that to find all of them. Fails if another
+/// socket on this host is bound to the sACN port already.
+pub fn discover(timeout: time::Duration) -> Result<Vec<DiscoveredSource>> {
+    let mut receiver = DiscoveryReceiver::new()?;
+    let deadline = time::Instant::now() + timeout;
+
+    loop {
+        let now = time::Instant::now();
+        if now >= deadline {
+            break;
+        }
+        receiver.socket.set_read_timeout(Some(deadline - now))?;
+
+        let len = match receiver.socket.recv(&mut receiver.buf) {
+            Ok(len) => len,
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                break
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        receiver.handle_packet(len);
+    }
+
+    Ok(receiver.sources())
+}
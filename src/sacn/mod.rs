@@ -0,0 +1,768 @@
+//! sACN (ANSI E1.31) support.
+//!
+//! Streaming ACN transports DMX universes over UDP, usually via multicast.
+//! Every universe from 1 to 63,999 has its own multicast group; receivers
+//! join the groups of the universes they are interested in.
+//!
+//! Sources identify themselves using a *CID*, a UUID that should stay the
+//! same for the lifetime of an application. Multiple sources can send to the
+//! same universe, receivers choose between them based on priority.
+//!
+//! Optionally, receivers can be told to hold back data until a
+//! synchronization packet arrives, allowing multiple universes to change at
+//! exactly the same time.
+//!
+//! Data is sent by a `SacnSource` and received by a `SacnReceiver`, which
+//! merges the data of all sources sending to a universe.
+//!
+//! Sources can advertise the universes they send to through universe
+//! discovery, so tools on the network can list them without listening to
+//! every universe. The lists advertised are collected by a
+//! `DiscoveryReceiver`, or once through `discover`.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use dmx::sacn::SacnSource;
+//!
+//! let mut source = SacnSource::new("dmx-rs example").unwrap();
+//! source.set_priority(2, 150).unwrap();
+//!
+//! source.send(1, &[0xff, 0x80]).unwrap();
+//! source.send(2, &[0x00, 0xff]).unwrap();
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hasher};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::{cmp, io, process, time};
+
+use crate::{DmxTransmitter, Error, Result, SocketOptions};
+
+mod discovery;
+mod receiver;
+
+use self::discovery::DISCOVERY_HEADER_LEN;
+
+pub use self::discovery::{
+    decode_discovery, discover, encode_discovery, DiscoveredSource, DiscoveryPage,
+    DiscoveryReceiver, DISCOVERY_INTERVAL, DISCOVERY_UNIVERSE, MAX_DISCOVERY_UNIVERSES,
+};
+pub use self::receiver::{decode_data, SacnReceiver, SOURCE_LOSS_TIMEOUT};
+
+/// UDP port used by sACN.
+pub const SACN_PORT: u16 = 5568;
+
+/// Lowest valid universe number.
+pub const MIN_UNIVERSE: u16 = 1;
+
+/// Highest valid universe number.
+pub const MAX_UNIVERSE: u16 = 63999;
+
+/// Default source priority.
+pub const DEFAULT_PRIORITY: u8 = 100;
+
+/// Highest valid source priority.
+pub const MAX_PRIORITY: u8 = 200;
+
+/// Identifier present in the root layer of every ACN packet.
+pub const ACN_PACKET_IDENTIFIER: &[u8; 12] = b"ASC-E1.17\0\0\0";
+
+pub(crate) const VECTOR_ROOT_E131_DATA: u32 = 0x0000_0004;
+pub(crate) const VECTOR_ROOT_E131_EXTENDED: u32 = 0x0000_0008;
+pub(crate) const VECTOR_E131_DATA_PACKET: u32 = 0x0000_0002;
+pub(crate) const VECTOR_E131_EXTENDED_SYNCHRONIZATION: u32 = 0x0000_0001;
+pub(crate) const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+
+/// Option flag: data is for visualization only.
+pub const OPTION_PREVIEW: u8 = 0x80;
+/// Option flag: the source stops sending to a universe.
+pub const OPTION_STREAM_TERMINATED: u8 = 0x40;
+/// Option flag: receivers should not wait for synchronization.
+pub const OPTION_FORCE_SYNC: u8 = 0x20;
+
+// offsets of the framing and DMP layers inside a data packet
+pub(crate) const FRAMING_OFFSET: usize = 38;
+pub(crate) const DMP_OFFSET: usize = 115;
+pub(crate) const DATA_HEADER_LEN: usize = 125;
+pub(crate) const SYNC_PACKET_LEN: usize = 49;
+
+/// Number of times a stream termination is announced.
+const TERMINATION_REPEATS: usize = 3;
+
+/// A component identifier (CID).
+pub type Cid = [u8; 16];
+
+/// Generates a new random CID.
+///
+/// The result is a version 4 UUID. Sources should generate a CID once and
+/// persist it, where possible.
+pub fn generate_cid() -> Cid {
+    let mut cid = [0; 16];
+
+    for (i, chunk) in cid.chunks_mut(8).enumerate() {
+        // RandomState is seeded randomly, mix in time and process for good
+        // measure
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(i);
+        hasher.write_u32(process::id());
+        if let Ok(now) = time::SystemTime::now().duration_since(time::UNIX_EPOCH) {
+            hasher.write_u128(now.as_nanos());
+        }
+        chunk.copy_from_slice(&hasher.finish().to_be_bytes());
+    }
+
+    // version 4, variant 1
+    cid[6] = (cid[6] & 0x0f) | 0x40;
+    cid[8] = (cid[8] & 0x3f) | 0x80;
+    cid
+}
+
+/// Returns the multicast group of a universe.
+#[inline]
+pub fn multicast_address(universe: u16) -> Ipv4Addr {
+    Ipv4Addr::new(239, 255, (universe >> 8) as u8, universe as u8)
+}
+
+/// Returns whether `universe` is a valid universe number.
+#[inline]
+pub fn is_valid_universe(universe: u16) -> bool {
+    (MIN_UNIVERSE..=MAX_UNIVERSE).contains(&universe)
+}
+
+/// Encodes a flags-and-length field, covering `buf[offset..len]`.
+#[inline]
+fn write_flags_length(buf: &mut [u8], offset: usize, len: usize) {
+    let v = 0x7000 | (len - offset) as u16;
+    buf[offset..(offset + 2)].copy_from_slice(&v.to_be_bytes());
+}
+
+/// Writes the root layer of a packet of length `len`.
+pub(crate) fn write_root_layer(buf: &mut [u8], vector: u32, cid: &Cid, len: usize) {
+    buf[0..2].copy_from_slice(&0x0010u16.to_be_bytes());
+    buf[2..4].copy_from_slice(&0x0000u16.to_be_bytes());
+    buf[4..16].copy_from_slice(ACN_PACKET_IDENTIFIER);
+    write_flags_length(buf, 16, len);
+    buf[18..22].copy_from_slice(&vector.to_be_bytes());
+    buf[22..38].copy_from_slice(cid);
+}
+
+/// Writes a source name as a null-terminated, 64-byte field.
+///
+/// Names longer than 63 bytes are truncated without splitting characters.
+pub(crate) fn write_source_name(buf: &mut [u8], name: &str) {
+    let mut len = cmp::min(name.len(), 63);
+
+    // do not split characters
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+
+    buf[..len].copy_from_slice(&name.as_bytes()[..len]);
+    for v in &mut buf[len..64] {
+        *v = 0;
+    }
+}
+
+/// Header fields of an E1.31 data packet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataHeader<'a> {
+    /// CID of the source.
+    pub cid: Cid,
+    /// Human readable source name, up to 63 bytes.
+    pub source_name: &'a str,
+    /// Priority of the source, 0 to 200.
+    pub priority: u8,
+    /// Universe used for synchronization, zero if not synchronized.
+    pub sync_address: u16,
+    /// Sequence number.
+    pub sequence: u8,
+    /// Option flags.
+    pub options: u8,
+    /// Universe the data is for.
+    pub universe: u16,
+}
+
+/// Encodes an E1.31 data packet into `buf`.
+///
+/// `data` includes the start code and is truncated to 513 bytes. Returns the
+/// packet length; `buf` must hold at least 638 bytes.
+pub fn encode_data(buf: &mut [u8], header: &DataHeader, data: &[u8]) -> usize {
+    let count = cmp::min(data.len(), 513);
+    let len = DATA_HEADER_LEN + count;
+
+    write_root_layer(buf, VECTOR_ROOT_E131_DATA, &header.cid, len);
+
+    write_flags_length(buf, FRAMING_OFFSET, len);
+    buf[40..44].copy_from_slice(&VECTOR_E131_DATA_PACKET.to_be_bytes());
+    write_source_name(&mut buf[44..108], header.source_name);
+    buf[108] = header.priority;
+    buf[109..111].copy_from_slice(&header.sync_address.to_be_bytes());
+    buf[111] = header.sequence;
+    buf[112] = header.options;
+    buf[113..115].copy_from_slice(&header.universe.to_be_bytes());
+
+    write_flags_length(buf, DMP_OFFSET, len);
+    buf[117] = VECTOR_DMP_SET_PROPERTY;
+    buf[118] = 0xa1;
+    buf[119..121].copy_from_slice(&0x0000u16.to_be_bytes());
+    buf[121..123].copy_from_slice(&0x0001u16.to_be_bytes());
+    buf[123..125].copy_from_slice(&(count as u16).to_be_bytes());
+    buf[DATA_HEADER_LEN..len].copy_from_slice(&data[..count]);
+
+    len
+}
+
+/// Encodes an E1.31 synchronization packet into `buf`.
+///
+/// Returns the packet length; `buf` must hold at least 49 bytes.
+pub fn encode_sync(buf: &mut [u8], cid: &Cid, sequence: u8, sync_address: u16) -> usize {
+    write_root_layer(buf, VECTOR_ROOT_E131_EXTENDED, cid, SYNC_PACKET_LEN);
+
+    write_flags_length(buf, FRAMING_OFFSET, SYNC_PACKET_LEN);
+    buf[40..44].copy_from_slice(&VECTOR_E131_EXTENDED_SYNCHRONIZATION.to_be_bytes());
+    buf[44] = sequence;
+    buf[45..47].copy_from_slice(&sync_address.to_be_bytes());
+    buf[47] = 0;
+    buf[48] = 0;
+
+    SYNC_PACKET_LEN
+}
+
+fn multicast_destination(universe: u16) -> SocketAddr {
+    SocketAddrV4::new(multicast_address(universe), SACN_PORT).into()
+}
+
+/// Per-universe state of a source.
+#[derive(Clone, Debug)]
+struct UniverseState {
+    priority: u8,
+    sequence: u8,
+    destinations: Vec<SocketAddr>,
+}
+
+impl UniverseState {
+    fn new(universe: u16) -> UniverseState {
+        UniverseState {
+            priority: DEFAULT_PRIORITY,
+            sequence: 0,
+            destinations: vec![multicast_destination(universe)],
+        }
+    }
+
+    fn next_sequence(&mut self) -> u8 {
+        let seq = self.sequence;
+        self.sequence = self.sequence.wrapping_add(1);
+        seq
+    }
+}
+
+/// An sACN source.
+///
+/// Sends data for any number of universes, each with its own priority and
+/// sequence numbering. By default, data is sent to the multicast group of
+/// each universe, but it may be unicast to one or more receivers instead.
+/// Synchronization and discovery packets are always sent via multicast.
+///
+/// With universe discovery enabled, the universes sent to are advertised
+/// every `DISCOVERY_INTERVAL` while data is being sent.
+#[derive(Debug)]
+pub struct SacnSource {
+    socket: UdpSocket,
+    cid: Cid,
+    name: String,
+    universes: BTreeMap<u16, UniverseState>,
+    sync_universe: u16,
+    sync_sequence: u8,
+    preview: bool,
+    discovery: bool,
+    // when the universes were last advertised
+    last_discovery: Option<time::Instant>,
+    buf: [u8; DATA_HEADER_LEN + 513],
+}
+
+impl SacnSource {
+    /// Create a new source with a random CID.
+    #[inline]
+    pub fn new(name: &str) -> io::Result<SacnSource> {
+        SacnSource::with_cid(name, generate_cid())
+    }
+
+    /// Create a new source with a fixed CID.
+    #[inline]
+    pub fn with_cid(name: &str, cid: Cid) -> io::Result<SacnSource> {
+        SacnSource::with_options(name, cid, &SocketOptions::default())
+    }
+
+    /// Create a new source sending through a specific interface.
+    ///
+    /// See `SocketOptions` for how packets are kept on that interface.
+    pub fn with_options(name: &str, cid: Cid, options: &SocketOptions) -> io::Result<SacnSource> {
+        let socket = options.bind()?;
+
+        Ok(SacnSource {
+            socket,
+            cid,
+            name: name.to_owned(),
+            universes: BTreeMap::new(),
+            sync_universe: 0,
+            sync_sequence: 0,
+            preview: false,
+            discovery: false,
+            last_discovery: None,
+            buf: [0; DATA_HEADER_LEN + 513],
+        })
+    }
+
+    /// Returns the CID of the source.
+    #[inline]
+    pub fn cid(&self) -> Cid {
+        self.cid
+    }
+
+    /// Returns the name of the source.
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns all universes the source has sent to or been configured for.
+    pub fn universes(&self) -> Vec<u16> {
+        self.universes.keys().cloned().collect()
+    }
+
+    fn universe_mut(&mut self, universe: u16) -> Result<&mut UniverseState> {
+        if !is_valid_universe(universe) {
+            return Err(Error::InvalidUniverse(universe));
+        }
+
+        Ok(self
+            .universes
+            .entry(universe)
+            .or_insert_with(|| UniverseState::new(universe)))
+    }
+
+    /// Sets the priority for a universe.
+    ///
+    /// Priorities range from 0 to 200, the default is 100.
+    pub fn set_priority(&mut self, universe: u16, priority: u8) -> Result<()> {
+        if priority > MAX_PRIORITY {
+            return Err(Error::InvalidParameter(
+                "priority must be in the range of 0 to 200",
+            ));
+        }
+
+        self.universe_mut(universe)?.priority = priority;
+        Ok(())
+    }
+
+    /// Sends data for a universe to a unicast address instead of multicast.
+    #[inline]
+    pub fn set_destination(&mut self, universe: u16, destination: SocketAddr) -> Result<()> {
+        self.set_destinations(universe, &[destination])
+    }
+
+    /// Sends data for a universe to several unicast addresses, one packet to
+    /// each of them.
+    ///
+    /// An empty list makes the universe multicast again. To multicast in
+    /// addition to unicasting, include the address of its multicast group.
+    pub fn set_destinations(&mut self, universe: u16, destinations: &[SocketAddr]) -> Result<()> {
+        let destinations = if destinations.is_empty() {
+            vec![multicast_destination(universe)]
+        } else {
+            destinations.to_vec()
+        };
+
+        self.universe_mut(universe)?.destinations = destinations;
+        Ok(())
+    }
+
+    /// Returns where data for a universe is sent to.
+    pub fn destinations(&self, universe: u16) -> Vec<SocketAddr> {
+        match self.universes.get(&universe) {
+            Some(state) => state.destinations.clone(),
+            None => vec![multicast_destination(universe)],
+        }
+    }
+
+    /// Sets the time to live of multicast packets.
+    ///
+    /// Defaults to 1, which keeps packets within the local network. Routed
+    /// multicast networks need a higher value.
+    #[inline]
+    pub fn set_multicast_ttl(&mut self, ttl: u32) -> Result<()> {
+        Ok(self.socket.set_multicast_ttl_v4(ttl)?)
+    }
+
+    /// Enables or disables looping multicast packets back to receivers on
+    /// this host.
+    ///
+    /// Usually enabled by default, which lets a visualizer run on the same
+    /// machine.
+    #[inline]
+    pub fn set_multicast_loop(&mut self, enabled: bool) -> Result<()> {
+        Ok(self.socket.set_multicast_loop_v4(enabled)?)
+    }
+
+    /// Marks all data as preview data, intended for visualizers only.
+    #[inline]
+    pub fn set_preview(&mut self, preview: bool) {
+        self.preview = preview;
+    }
+
+    /// Sets the universe used for synchronization.
+    ///
+    /// If set, receivers hold back data until `send_sync` is called. Pass
+    /// `None` to disable synchronization.
+    pub fn set_sync_universe(&mut self, universe: Option<u16>) -> Result<()> {
+        match universe {
+            Some(u) if !is_valid_universe(u) => Err(Error::InvalidUniverse(u)),
+            _ => {
+                self.sync_universe = universe.unwrap_or(0);
+                Ok(())
+            }
+        }
+    }
+
+    /// Enables or disables universe discovery.
+    ///
+    /// If enabled, the universes sent to are advertised on the first send
+    /// and every `DISCOVERY_INTERVAL` after, see `send_discovery`. Disabled
+    /// by default.
+    #[inline]
+    pub fn set_discovery_enabled(&mut self, enabled: bool) {
+        self.discovery = enabled;
+        self.last_discovery = None;
+    }
+
+    /// Advertises the universes sent to, as universe discovery packets.
+    ///
+    /// Lists all universes the source has sent to or been configured for,
+    /// on as many pages as needed. Universes terminated are no longer
+    /// listed. Sending nothing but an empty list is valid, announcing that
+    /// the source is not sending to any universe.
+    pub fn send_discovery(&mut self) -> Result<()> {
+        let universes = self.universes();
+        let pages: Vec<&[u16]> = if universes.is_empty() {
+            vec![&[]]
+        } else {
+            universes.chunks(MAX_DISCOVERY_UNIVERSES).collect()
+        };
+
+        let mut buf = [0; DISCOVERY_HEADER_LEN + MAX_DISCOVERY_UNIVERSES * 2];
+        let destination = SocketAddrV4::new(multicast_address(DISCOVERY_UNIVERSE), SACN_PORT);
+        let last_page = (pages.len() - 1) as u8;
+
+        for (page, list) in pages.into_iter().enumerate() {
+            let page = page as u8;
+            let len = encode_discovery(&mut buf, &self.cid, &self.name, page, last_page, list);
+            self.socket.send_to(&buf[..len], destination)?;
+        }
+
+        self.last_discovery = Some(time::Instant::now());
+        Ok(())
+    }
+
+    fn send_with_options(&mut self, universe: u16, data: &[u8], options: u8) -> Result<()> {
+        let sync_address = self.sync_universe;
+        let options = options | if self.preview { OPTION_PREVIEW } else { 0 };
+
+        let (priority, sequence) = {
+            let state = self.universe_mut(universe)?;
+            (state.priority, state.next_sequence())
+        };
+
+        let header = DataHeader {
+            cid: self.cid,
+            source_name: &self.name,
+            priority,
+            sync_address,
+            sequence,
+            options,
+            universe,
+        };
+        let len = encode_data(&mut self.buf, &header, data);
+
+        for &destination in &self.universes[&universe].destinations {
+            self.socket.send_to(&self.buf[..len], destination)?;
+        }
+
+        let due = self
+            .last_discovery
+            .is_none_or(|last| last.elapsed() >= DISCOVERY_INTERVAL);
+        if self.discovery && due {
+            self.send_discovery()?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends channel data with the default start code to a universe.
+    #[inline]
+    pub fn send(&mut self, universe: u16, channels: &[u8]) -> Result<()> {
+        let mut data = [0; 513];
+        let count = cmp::min(channels.len(), 512);
+        data[1..(count + 1)].copy_from_slice(&channels[..count]);
+
+        self.send_raw(universe, &data[..(count + 1)])
+    }
+
+    /// Sends raw data, including start code, to a universe.
+    #[inline]
+    pub fn send_raw(&mut self, universe: u16, data: &[u8]) -> Result<()> {
+        self.send_with_options(universe, data, 0)
+    }
+
+    /// Sends a synchronization packet.
+    ///
+    /// Does nothing if no sync universe is set.
+    pub fn send_sync(&mut self) -> Result<()> {
+        if self.sync_universe == 0 {
+            return Ok(());
+        }
+
+        let len = encode_sync(&mut self.buf, &self.cid, self.sync_sequence, self.sync_universe);
+        self.sync_sequence = self.sync_sequence.wrapping_add(1);
+
+        let destination = SocketAddrV4::new(multicast_address(self.sync_universe), SACN_PORT);
+        self.socket.send_to(&self.buf[..len], destination)?;
+        Ok(())
+    }
+
+    /// Announces that the source stops sending to a universe.
+    ///
+    /// Receivers will release the universe immediately instead of waiting for
+    /// a timeout.
+    pub fn terminate(&mut self, universe: u16) -> Result<()> {
+        for _ in 0..TERMINATION_REPEATS {
+            self.send_with_options(universe, &[0x00], OPTION_STREAM_TERMINATED)?;
+        }
+
+        self.universes.remove(&universe);
+        Ok(())
+    }
+}
+
+/// sACN transmitter for a single universe.
+///
+/// Wraps a source to implement `DmxTransmitter`. As sACN has no notion of
+/// breaks, `send_break` does nothing and only complete packets can be sent.
+/// Unlike Art-Net, alternate start codes are supported.
+#[derive(Debug)]
+pub struct SacnTransmitter {
+    source: SacnSource,
+    universe: u16,
+}
+
+impl SacnTransmitter {
+    /// Create a transmitter with a new source.
+    #[inline]
+    pub fn new(name: &str, universe: u16) -> Result<SacnTransmitter> {
+        SacnTransmitter::from_source(SacnSource::new(name)?, universe)
+    }
+
+    /// Create a transmitter from an existing source.
+    pub fn from_source(source: SacnSource, universe: u16) -> Result<SacnTransmitter> {
+        if !is_valid_universe(universe) {
+            return Err(Error::InvalidUniverse(universe));
+        }
+
+        Ok(SacnTransmitter { source, universe })
+    }
+
+    /// Returns the universe data is sent to.
+    #[inline]
+    pub fn universe(&self) -> u16 {
+        self.universe
+    }
+
+    /// Returns the underlying source.
+    #[inline]
+    pub fn source(&mut self) -> &mut SacnSource {
+        &mut self.source
+    }
+
+    /// Returns the underlying source, consuming the transmitter.
+    #[inline]
+    pub fn into_source(self) -> SacnSource {
+        self.source
+    }
+}
+
+impl DmxTransmitter for SacnTransmitter {
+    type Error = Error;
+
+    #[inline]
+    fn send_break(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn send_raw_data(&mut self, _data: &[u8]) -> Result<()> {
+        Err(Error::Unsupported("sACN can only transmit complete packets"))
+    }
+
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Err(Error::EmptyPacket);
+        }
+
+        self.source.send_raw(self.universe, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CID: Cid = [
+        0x6d, 0x7e, 0x86, 0xa5, 0x1f, 0x3c, 0x4b, 0x4d, 0x8d, 0x79, 0x1e, 0x2f, 0x3a, 0x4b, 0x5c,
+        0x6d,
+    ];
+
+    fn header(name: &str) -> DataHeader<'_> {
+        DataHeader {
+            cid: CID,
+            source_name: name,
+            priority: 150,
+            sync_address: 7000,
+            sequence: 42,
+            options: OPTION_PREVIEW,
+            universe: 0x1234,
+        }
+    }
+
+    #[test]
+    fn data_packet_layout() {
+        let mut buf = [0; 638];
+        let len = encode_data(&mut buf, &header("dmx"), &[0x00, 0xff, 0x80]);
+        assert_eq!(len, 128);
+
+        // root layer: preamble, postamble, identifier, flags and length
+        // of the remaining 112 bytes, vector, CID
+        let mut expected = vec![0x00, 0x10, 0x00, 0x00];
+        expected.extend_from_slice(b"ASC-E1.17\0\0\0");
+        expected.extend_from_slice(&[0x70, 0x70, 0x00, 0x00, 0x00, 0x04]);
+        expected.extend_from_slice(&CID);
+
+        // framing layer: 90 bytes, name padded to 64 bytes, priority, sync
+        // address, sequence, options, universe
+        expected.extend_from_slice(&[0x70, 0x5a, 0x00, 0x00, 0x00, 0x02]);
+        expected.extend_from_slice(b"dmx");
+        expected.extend_from_slice(&[0; 61]);
+        expected.extend_from_slice(&[150, 0x1b, 0x58, 42, 0x80, 0x12, 0x34]);
+
+        // DMP layer: 13 bytes, vector, address and data type, first
+        // property address, increment, property count, values
+        expected.extend_from_slice(&[0x70, 0x0d, 0x02, 0xa1, 0x00, 0x00, 0x00, 0x01]);
+        expected.extend_from_slice(&[0x00, 0x03, 0x00, 0xff, 0x80]);
+
+        assert_eq!(buf[..len], expected[..]);
+    }
+
+    #[test]
+    fn data_packet_round_trip() {
+        let mut buf = [0; 638];
+        let data: Vec<u8> = (0..=512).map(|i| i as u8).collect();
+        let len = encode_data(&mut buf, &header("round trip"), &data);
+        assert_eq!(len, 638);
+
+        let (decoded, values) = decode_data(&buf[..len]).unwrap();
+        assert_eq!(decoded, header("round trip"));
+        assert_eq!(values, &data[..]);
+    }
+
+    #[test]
+    fn data_is_truncated_to_513_slots() {
+        let mut buf = [0; 700];
+        let len = encode_data(&mut buf, &header(""), &[0x55; 600]);
+        assert_eq!(len, 638);
+        assert_eq!(buf[123..125], [0x02, 0x01]);
+    }
+
+    #[test]
+    fn source_names_are_truncated_to_63_bytes() {
+        let name = "n".repeat(80);
+        let mut buf = [0; 638];
+        let len = encode_data(&mut buf, &header(&name), &[0x00]);
+
+        assert_eq!(buf[44..107], [b'n'; 63]);
+        assert_eq!(buf[107], 0);
+        assert_eq!(decode_data(&buf[..len]).unwrap().0.source_name.len(), 63);
+    }
+
+    #[test]
+    fn source_names_are_truncated_between_characters() {
+        // 31 two-byte characters fit, the 32nd would end at byte 64
+        let name = "ü".repeat(40);
+        let mut buf = [0; 638];
+        let len = encode_data(&mut buf, &header(&name), &[0x00]);
+
+        assert_eq!(buf[106..108], [0, 0]);
+        assert_eq!(decode_data(&buf[..len]).unwrap().0.source_name, "ü".repeat(31));
+    }
+
+    #[test]
+    fn invalid_data_packets_are_rejected() {
+        let mut buf = [0; 638];
+        let len = encode_data(&mut buf, &header("dmx"), &[0x00, 0xff]);
+
+        // truncated, or with a count beyond the packet
+        assert!(decode_data(&buf[..len - 1]).is_none());
+        assert!(decode_data(&buf[..DATA_HEADER_LEN]).is_none());
+
+        let mut packet = buf[..len].to_vec();
+        packet[4] = b'X';
+        assert!(decode_data(&packet).is_none());
+
+        let mut packet = buf[..len].to_vec();
+        packet[21] = 0x08;
+        assert!(decode_data(&packet).is_none());
+
+        let mut packet = buf[..len].to_vec();
+        packet[124] = 0;
+        assert!(decode_data(&packet).is_none());
+    }
+
+    #[test]
+    fn sync_packet_layout() {
+        let mut buf = [0; 49];
+        assert_eq!(encode_sync(&mut buf, &CID, 9, 7000), 49);
+
+        let mut expected = vec![0x00, 0x10, 0x00, 0x00];
+        expected.extend_from_slice(b"ASC-E1.17\0\0\0");
+        expected.extend_from_slice(&[0x70, 0x21, 0x00, 0x00, 0x00, 0x08]);
+        expected.extend_from_slice(&CID);
+        expected.extend_from_slice(&[0x70, 0x0b, 0x00, 0x00, 0x00, 0x01]);
+        expected.extend_from_slice(&[9, 0x1b, 0x58, 0x00, 0x00]);
+
+        assert_eq!(buf[..], expected[..]);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap() {
+        let mut state = UniverseState::new(1);
+        state.sequence = 254;
+
+        let sequences: Vec<u8> = (0..4).map(|_| state.next_sequence()).collect();
+        assert_eq!(sequences, [254, 255, 0, 1]);
+    }
+
+    #[test]
+    fn multicast_addresses() {
+        assert_eq!(multicast_address(1), Ipv4Addr::new(239, 255, 0, 1));
+        assert_eq!(multicast_address(0x1234), Ipv4Addr::new(239, 255, 0x12, 0x34));
+        assert_eq!(multicast_address(MAX_UNIVERSE), Ipv4Addr::new(239, 255, 249, 255));
+    }
+
+    #[test]
+    fn generated_cids_are_version_4_uuids() {
+        let (a, b) = (generate_cid(), generate_cid());
+
+        assert_ne!(a, b);
+        assert_eq!(a[6] >> 4, 4);
+        assert_eq!(a[8] >> 6, 0b10);
+    }
+}
@@ -0,0 +1,525 @@
+//! sACN reception and merging.
+
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::{cmp, io, str, time};
+
+use super::{
+    is_valid_universe, multicast_address, Cid, DataHeader, ACN_PACKET_IDENTIFIER,
+    DATA_HEADER_LEN, DMP_OFFSET, FRAMING_OFFSET, MAX_PRIORITY, OPTION_PREVIEW,
+    OPTION_STREAM_TERMINATED, SACN_PORT, VECTOR_DMP_SET_PROPERTY, VECTOR_E131_DATA_PACKET,
+    VECTOR_ROOT_E131_DATA,
+};
+use crate::universe::DmxUniverse;
+use crate::{DmxReceiver, Error, Result};
+
+/// Time after which a source that stopped sending is considered lost.
+pub const SOURCE_LOSS_TIMEOUT: time::Duration = time::Duration::from_millis(2500);
+
+// sequence numbers up to this far behind the previous one are considered
+// late, larger jumps backwards a restarted source
+const SEQUENCE_WINDOW: i8 = 20;
+
+/// Decodes an E1.31 data packet.
+///
+/// Returns the header and the data, including the start code, or `None` if
+/// the packet is not a valid data packet.
+pub fn decode_data(packet: &[u8]) -> Option<(DataHeader<'_>, &[u8])> {
+    if packet.len() <= DATA_HEADER_LEN || &packet[4..16] != ACN_PACKET_IDENTIFIER {
+        return None;
+    }
+
+    let u16_at = |i: usize| u16::from_be_bytes([packet[i], packet[i + 1]]);
+    let u32_at = |i: usize| u32::from(u16_at(i)) << 16 | u32::from(u16_at(i + 2));
+
+    if u32_at(18) != VECTOR_ROOT_E131_DATA
+        || u32_at(FRAMING_OFFSET + 2) != VECTOR_E131_DATA_PACKET
+        || packet[DMP_OFFSET + 2] != VECTOR_DMP_SET_PROPERTY
+        || packet[DMP_OFFSET + 3] != 0xa1
+    {
+        return None;
+    }
+
+    let count = usize::from(u16_at(123));
+    if count == 0 || DATA_HEADER_LEN + count > packet.len() {
+        return None;
+    }
+
+    let name = &packet[44..108];
+    let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+
+    let mut cid = [0; 16];
+    cid.copy_from_slice(&packet[22..38]);
+
+    let header = DataHeader {
+        cid,
+        source_name: str::from_utf8(&name[..name_len]).unwrap_or(""),
+        priority: packet[108],
+        sync_address: u16_at(109),
+        sequence: packet[111],
+        options: packet[112],
+        universe: u16_at(113),
+    };
+
+    Some((header, &packet[DATA_HEADER_LEN..(DATA_HEADER_LEN + count)]))
+}
+
+/// A source sending to a universe.
+#[derive(Clone, Debug)]
+struct Source {
+    priority: u8,
+    sequence: u8,
+    last_seen: time::Instant,
+    channels: [u8; 512],
+    len: usize,
+}
+
+/// A universe being received.
+#[derive(Clone, Debug, Default)]
+struct ReceivedUniverse {
+    sources: BTreeMap<Cid, Source>,
+    merged: DmxUniverse,
+}
+
+impl ReceivedUniverse {
+    /// Recalculates the merged output.
+    ///
+    /// Only sources sharing the highest priority contribute, their channels
+    /// are merged highest-takes-precedence. If there are no sources left, the
+    /// last output is kept.
+    fn merge(&mut self) {
+        let priority = match self.sources.values().map(|s| s.priority).max() {
+            Some(priority) => priority,
+            None => return,
+        };
+
+        let merged = self.merged.channels_mut();
+        for v in merged.iter_mut() {
+            *v = 0;
+        }
+
+        for source in self.sources.values().filter(|s| s.priority == priority) {
+            for (out, &v) in merged.iter_mut().zip(&source.channels[..source.len]) {
+                *out = cmp::max(*out, v);
+            }
+        }
+    }
+
+    /// Applies a data packet received at `now`.
+    ///
+    /// Returns whether the merged output was updated.
+    fn update(&mut self, header: &DataHeader, data: &[u8], now: time::Instant) -> bool {
+        if header.options & OPTION_PREVIEW != 0 || header.priority > MAX_PRIORITY {
+            return false;
+        }
+
+        if header.options & OPTION_STREAM_TERMINATED != 0 {
+            if self.sources.remove(&header.cid).is_none() {
+                return false;
+            }
+            self.merge();
+            return true;
+        }
+
+        // only level data is merged
+        if data[0] != 0x00 {
+            return false;
+        }
+
+        let source = self.sources.entry(header.cid).or_insert_with(|| Source {
+            priority: header.priority,
+            sequence: header.sequence.wrapping_sub(1),
+            last_seen: now,
+            channels: [0; 512],
+            len: 0,
+        });
+
+        let diff = header.sequence.wrapping_sub(source.sequence) as i8;
+        if diff <= 0 && diff > -SEQUENCE_WINDOW {
+            return false;
+        }
+
+        let channels = &data[1..cmp::min(data.len(), 513)];
+        source.priority = header.priority;
+        source.sequence = header.sequence;
+        source.last_seen = now;
+        source.channels[..channels.len()].copy_from_slice(channels);
+        source.len = channels.len();
+
+        self.merge();
+        true
+    }
+
+    /// Removes sources not seen since `deadline`.
+    ///
+    /// Returns whether any source was removed.
+    fn expire(&mut self, deadline: time::Instant) -> bool {
+        let count = self.sources.len();
+        self.sources.retain(|_, s| s.last_seen > deadline);
+
+        self.sources.len() != count
+    }
+}
+
+/// An sACN receiver.
+///
+/// Joins the multicast groups of the universes listened to and merges the
+/// data of all sources sending to each of them, following the priority rules
+/// of E1.31: only the sources with the highest priority are used, several of
+/// those are merged highest-takes-precedence. Sources are dropped once they
+/// terminate their stream or stop sending for `SOURCE_LOSS_TIMEOUT`.
+///
+/// Preview data, alternate start codes and synchronization are ignored.
+///
+/// As a `DmxReceiver`, the merged output of a universe is returned every
+/// time it is updated, with the default start code; listening to a single
+/// universe forwards exactly that universe.
+///
+/// ## Example
+///
+/// ```no_run
+/// use dmx::DmxTransmitter;
+/// use dmx::sacn::SacnReceiver;
+///
+/// let mut input = SacnReceiver::new(&[1]).unwrap();
+/// let mut dmx_port = dmx::open_serial("/dev/ttyUSB0").unwrap();
+///
+/// loop {
+///     let universe = input.recv().unwrap();
+///     dmx_port.send_universe(input.universe(universe).unwrap()).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct SacnReceiver {
+    socket: UdpSocket,
+    universes: BTreeMap<u16, ReceivedUniverse>,
+    timeout: Option<time::Duration>,
+    buf: [u8; DATA_HEADER_LEN + 513],
+}
+
+impl SacnReceiver {
+    /// Create a receiver listening on the sACN port of all interfaces.
+    ///
+    /// Joins the multicast groups of `universes`.
+    pub fn new(universes: &[u16]) -> Result<SacnReceiver> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, SACN_PORT))?;
+
+        let mut receiver = SacnReceiver {
+            socket,
+            universes: BTreeMap::new(),
+            timeout: None,
+            buf: [0; DATA_HEADER_LEN + 513],
+        };
+
+        for &universe in universes {
+            receiver.listen(universe)?;
+        }
+
+        Ok(receiver)
+    }
+
+    /// Starts listening to a universe, joining its multicast group.
+    pub fn listen(&mut self, universe: u16) -> Result<()> {
+        if !is_valid_universe(universe) {
+            return Err(Error::InvalidUniverse(universe));
+        }
+
+        if !self.universes.contains_key(&universe) {
+            self.socket
+                .join_multicast_v4(&multicast_address(universe), &Ipv4Addr::UNSPECIFIED)?;
+            self.universes.insert(universe, ReceivedUniverse::default());
+        }
+
+        Ok(())
+    }
+
+    /// Stops listening to a universe, leaving its multicast group.
+    pub fn stop_listening(&mut self, universe: u16) -> Result<()> {
+        if self.universes.remove(&universe).is_some() {
+            self.socket
+                .leave_multicast_v4(&multicast_address(universe), &Ipv4Addr::UNSPECIFIED)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the maximum time `recv` waits for data.
+    ///
+    /// Waits indefinitely if `None`, which is the default.
+    #[inline]
+    pub fn set_timeout(&mut self, timeout: Option<time::Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the merged output of a universe listened to.
+    #[inline]
+    pub fn universe(&self, universe: u16) -> Option<&DmxUniverse> {
+        self.universes.get(&universe).map(|u| &u.merged)
+    }
+
+    /// Returns the number of sources currently sending to a universe.
+    #[inline]
+    pub fn source_count(&self, universe: u16) -> usize {
+        self.universes.get(&universe).map_or(0, |u| u.sources.len())
+    }
+
+    /// Blocking receive data for any of the universes listened to.
+    ///
+    /// Returns the number of the universe whose merged output was updated,
+    /// either because data arrived or a source was lost. Fails with
+    /// `Error::Timeout` if nothing happened within the timeout set.
+    pub fn recv(&mut self) -> Result<u16> {
+        let started = time::Instant::now();
+
+        loop {
+            let now = time::Instant::now();
+            if let Some(universe) = self.expire_sources(now) {
+                return Ok(universe);
+            }
+
+            // wake up in time to notice lost sources
+            let lost_in = self
+                .universes
+                .values()
+                .flat_map(|u| u.sources.values())
+                .map(|s| (s.last_seen + SOURCE_LOSS_TIMEOUT).saturating_duration_since(now))
+                .min();
+            let timeout_in = self.timeout.map(|t| (started + t).saturating_duration_since(now));
+
+            if timeout_in == Some(time::Duration::ZERO) {
+                return Err(Error::Timeout);
+            }
+
+            let wait = match (lost_in, timeout_in) {
+                (Some(a), Some(b)) => Some(cmp::min(a, b)),
+                (a, b) => a.or(b),
+            };
+            self.socket
+                .set_read_timeout(wait.map(|w| cmp::max(w, time::Duration::from_millis(1))))?;
+
+            let len = match self.socket.recv(&mut self.buf) {
+                Ok(len) => len,
+                Err(ref e)
+                    if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            if let Some(universe) = self.handle_packet(len, time::Instant::now()) {
+                return Ok(universe);
+            }
+        }
+    }
+
+    /// Processes a received packet.
+    ///
+    /// Returns the universe updated, if any.
+    fn handle_packet(&mut self, len: usize, now: time::Instant) -> Option<u16> {
+        let (header, data) = decode_data(&self.buf[..len])?;
+        let universe = self.universes.get_mut(&header.universe)?;
+
+        if universe.update(&header, data, now) {
+            Some(header.universe)
+        } else {
+            None
+        }
+    }
+
+    /// Drops lost sources.
+    ///
+    /// Returns the first universe whose sources changed.
+    fn expire_sources(&mut self, now: time::Instant) -> Option<u16> {
+        let deadline = now.checked_sub(SOURCE_LOSS_TIMEOUT)?;
+        let mut changed = None;
+
+        for (&n, universe) in &mut self.universes {
+            if universe.expire(deadline) {
+                universe.merge();
+                changed = changed.or(Some(n));
+            }
+        }
+
+        changed
+    }
+}
+
+impl DmxReceiver for SacnReceiver {
+    fn recv_dmx_packet_into(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let universe = self.recv()?;
+        let channels = self.universes[&universe].merged.channels();
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let len = cmp::min(channels.len(), buf.len() - 1);
+        buf[0] = 0x00;
+        buf[1..=len].copy_from_slice(&channels[..len]);
+
+        Ok(len + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{encode_data, DEFAULT_PRIORITY};
+    use super::*;
+
+    const A: Cid = [0xa; 16];
+    const B: Cid = [0xb; 16];
+
+    // feeds an encoded data packet to the universe, received at `now`
+    fn send(
+        universe: &mut ReceivedUniverse,
+        cid: Cid,
+        priority: u8,
+        sequence: u8,
+        data: &[u8],
+        now: time::Instant,
+    ) -> bool {
+        let header = DataHeader {
+            cid,
+            source_name: "test",
+            priority,
+            sync_address: 0,
+            sequence,
+            options: 0,
+            universe: 1,
+        };
+        let mut buf = [0; DATA_HEADER_LEN + 513];
+        let len = encode_data(&mut buf, &header, data);
+
+        let (header, data) = decode_data(&buf[..len]).unwrap();
+        universe.update(&header, data, now)
+    }
+
+    fn merged(universe: &ReceivedUniverse) -> &[u8] {
+        &universe.merged.channels()[..3]
+    }
+
+    #[test]
+    fn equal_priorities_are_merged_highest_takes_precedence() {
+        let now = time::Instant::now();
+        let mut universe = ReceivedUniverse::default();
+
+        assert!(send(&mut universe, A, DEFAULT_PRIORITY, 0, &[0, 0xff, 0x10, 0x40], now));
+        assert!(send(&mut universe, B, DEFAULT_PRIORITY, 0, &[0, 0x20, 0x80], now));
+        assert_eq!(merged(&universe), [0xff, 0x80, 0x40]);
+
+        // lowering a level lets the other source through
+        assert!(send(&mut universe, A, DEFAULT_PRIORITY, 1, &[0, 0x00, 0x10, 0x40], now));
+        assert_eq!(merged(&universe), [0x20, 0x80, 0x40]);
+    }
+
+    #[test]
+    fn the_highest_priority_wins() {
+        let now = time::Instant::now();
+        let mut universe = ReceivedUniverse::default();
+
+        send(&mut universe, A, DEFAULT_PRIORITY, 0, &[0, 0xff, 0xff, 0xff], now);
+        send(&mut universe, B, 150, 0, &[0, 0x10], now);
+        assert_eq!(merged(&universe), [0x10, 0, 0]);
+
+        // dropping below the other source hands over the whole universe
+        send(&mut universe, B, 50, 1, &[0, 0x10], now);
+        assert_eq!(merged(&universe), [0xff, 0xff, 0xff]);
+        assert_eq!(universe.sources.len(), 2);
+    }
+
+    #[test]
+    fn invalid_priorities_previews_and_alternate_start_codes_are_ignored() {
+        let now = time::Instant::now();
+        let mut universe = ReceivedUniverse::default();
+
+        assert!(!send(&mut universe, A, MAX_PRIORITY + 1, 0, &[0, 0xff], now));
+        assert!(!send(&mut universe, A, DEFAULT_PRIORITY, 0, &[0xdd, 0xff], now));
+        assert!(universe.sources.is_empty());
+
+        let header = DataHeader {
+            cid: A,
+            source_name: "",
+            priority: DEFAULT_PRIORITY,
+            sync_address: 0,
+            sequence: 0,
+            options: OPTION_PREVIEW,
+            universe: 1,
+        };
+        assert!(!universe.update(&header, &[0, 0xff], now));
+        assert_eq!(merged(&universe), [0, 0, 0]);
+    }
+
+    #[test]
+    fn late_packets_within_the_sequence_window_are_dropped() {
+        let now = time::Instant::now();
+        let mut universe = ReceivedUniverse::default();
+
+        assert!(send(&mut universe, A, DEFAULT_PRIORITY, 250, &[0, 1], now));
+        assert!(!send(&mut universe, A, DEFAULT_PRIORITY, 250, &[0, 2], now));
+        assert!(!send(&mut universe, A, DEFAULT_PRIORITY, 249, &[0, 3], now));
+
+        // sequence numbers wrap around
+        assert!(send(&mut universe, A, DEFAULT_PRIORITY, 255, &[0, 4], now));
+        assert!(send(&mut universe, A, DEFAULT_PRIORITY, 5, &[0, 5], now));
+        assert!(!send(&mut universe, A, DEFAULT_PRIORITY, 242, &[0, 6], now));
+        assert_eq!(merged(&universe), [5, 0, 0]);
+
+        // 20 behind the last one is taken as a restarted source
+        assert!(send(&mut universe, A, DEFAULT_PRIORITY, 241, &[0, 7], now));
+        assert_eq!(merged(&universe), [7, 0, 0]);
+    }
+
+    #[test]
+    fn lost_sources_are_dropped_and_the_last_look_held() {
+        let start = time::Instant::now();
+        let ms = time::Duration::from_millis;
+        let mut universe = ReceivedUniverse::default();
+
+        // drops the sources lost at `now` like the receiver does
+        let expire = |universe: &mut ReceivedUniverse, now: time::Instant| {
+            let lost = universe.expire(now - SOURCE_LOSS_TIMEOUT);
+            universe.merge();
+            lost
+        };
+
+        send(&mut universe, A, 150, 0, &[0, 0xff], start);
+        send(&mut universe, B, DEFAULT_PRIORITY, 0, &[0, 0x10, 0x20], start + ms(1000));
+        assert_eq!(merged(&universe), [0xff, 0, 0]);
+
+        assert!(!expire(&mut universe, start + SOURCE_LOSS_TIMEOUT - ms(1)));
+        assert_eq!(universe.sources.len(), 2);
+
+        // the lower priority takes over once the other source is lost
+        assert!(expire(&mut universe, start + SOURCE_LOSS_TIMEOUT + ms(1)));
+        assert_eq!(universe.sources.len(), 1);
+        assert_eq!(merged(&universe), [0x10, 0x20, 0]);
+
+        // without any sources, the output stays as it was
+        assert!(expire(&mut universe, start + SOURCE_LOSS_TIMEOUT + ms(1001)));
+        assert!(universe.sources.is_empty());
+        assert_eq!(merged(&universe), [0x10, 0x20, 0]);
+    }
+
+    #[test]
+    fn terminated_streams_are_dropped() {
+        let now = time::Instant::now();
+        let mut universe = ReceivedUniverse::default();
+
+        send(&mut universe, A, DEFAULT_PRIORITY, 0, &[0, 0xff], now);
+        send(&mut universe, B, DEFAULT_PRIORITY, 0, &[0, 0x10], now);
+
+        let header = DataHeader {
+            cid: A,
+            source_name: "",
+            priority: DEFAULT_PRIORITY,
+            sync_address: 0,
+            sequence: 1,
+            options: OPTION_STREAM_TERMINATED,
+            universe: 1,
+        };
+        assert!(universe.update(&header, &[0], now));
+        assert!(!universe.update(&header, &[0], now));
+        assert_eq!(merged(&universe), [0x10, 0, 0]);
+    }
+}
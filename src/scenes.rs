@@ -0,0 +1,785 @@
+//! Scenes, cues and playback.
+//!
+//! A `Scene` stores the values of all channels of a universe. Scenes are
+//! arranged into a `CueList`, each cue adding the time it takes to fade into
+//! its scene. A `Playback` steps through a cue list, crossfading from the
+//! current output to the next scene and writing the result into a universe
+//! every time it is advanced.
+//!
+//! Like on a theatrical console, cues may record only some channels, the
+//! others tracking their values from earlier cues, wait before fading and
+//! follow on to the next cue automatically. A playback can also preset the
+//! attributes of dark fixtures for the next cue, see
+//! `Playback::add_move_in_black`.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use std::{thread, time};
+//! use dmx::{DmxAddress, DmxTransmitter};
+//! use dmx::scenes::{Cue, CueList, Playback, Scene};
+//!
+//! let mut warm = Scene::new();
+//! warm.set_range(DmxAddress::MIN, &[0xff, 0x80, 0x00]);
+//! let mut cold = Scene::new();
+//! cold.set_range(DmxAddress::MIN, &[0x00, 0x80, 0xff]);
+//!
+//! let mut cues = CueList::new();
+//! cues.push(Cue::new(warm, time::Duration::from_secs(2)));
+//! cues.push(Cue::new(cold, time::Duration::from_secs(5)));
+//!
+//! let mut playback = Playback::new(cues);
+//! playback.go();
+//!
+//! let mut dmx_port = dmx::open_serial("/dev/ttyS1").unwrap();
+//! let mut universe = dmx::DmxUniverse::new();
+//! let period = time::Duration::from_millis(25);
+//!
+//! loop {
+//!     playback.tick(period, &mut universe);
+//!     dmx_port.send_universe(&universe).unwrap();
+//!     thread::sleep(period);
+//! }
+//! ```
+
+use std::collections::BTreeSet;
+use std::{slice, time};
+
+use crate::address::DmxAddress;
+use crate::universe::{lerp, DmxUniverse};
+
+/// A snapshot of channel values.
+///
+/// Channels are addressed starting at 1, like on `DmxUniverse`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct Scene {
+    values: DmxUniverse,
+}
+
+impl Scene {
+    /// Create a new scene with all channels set to zero.
+    #[inline]
+    pub fn new() -> Scene {
+        Scene::default()
+    }
+
+    /// Create a scene from the current values of a universe.
+    #[inline]
+    pub fn capture(universe: &DmxUniverse) -> Scene {
+        Scene {
+            values: universe.clone(),
+        }
+    }
+
+    /// Returns the value of channel `n`.
+    ///
+    /// See `DmxUniverse::get`.
+    #[inline]
+    pub fn get(&self, n: DmxAddress) -> u8 {
+        self.values.get(n)
+    }
+
+    /// Sets channel `n` to `value`.
+    ///
+    /// See `DmxUniverse::set`.
+    #[inline]
+    pub fn set(&mut self, n: DmxAddress, value: u8) {
+        self.values.set(n, value)
+    }
+
+    /// Sets consecutive channels, starting at channel `start`.
+    ///
+    /// See `DmxUniverse::set_range`.
+    #[inline]
+    pub fn set_range(&mut self, start: DmxAddress, values: &[u8]) {
+        self.values.set_range(start, values)
+    }
+
+    /// Returns the channel values as a universe.
+    #[inline]
+    pub fn universe(&self) -> &DmxUniverse {
+        &self.values
+    }
+}
+
+impl From<DmxUniverse> for Scene {
+    #[inline]
+    fn from(values: DmxUniverse) -> Scene {
+        Scene { values }
+    }
+}
+
+/// A cue, fading into a scene.
+///
+/// A cue records either all channels of its scene, which makes it a block
+/// cue, or only the channels it changes. Channels not recorded track: they
+/// keep the values of the cue recording them last, back to the last block
+/// cue. The values a cue shows this way are returned by `CueList::state`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cue {
+    /// Scene shown once the cue is complete.
+    pub scene: Scene,
+    /// Time it takes to fade from the previous output into the scene.
+    pub fade: time::Duration,
+    /// Delay from going to the cue until its fade starts.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub wait: time::Duration,
+    /// Time from going to the cue until the next cue is gone to
+    /// automatically, if any.
+    ///
+    /// Consecutive cues with follow times form a chain running on its own.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub follow: Option<time::Duration>,
+    /// Channels recorded in the cue, or `None` if all of them are.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub channels: Option<BTreeSet<DmxAddress>>,
+    /// Whether going to the cue fades channels it does not record to their
+    /// tracked values as well.
+    ///
+    /// Otherwise, such channels keep their current output when going to
+    /// the cue from the one before it, e.g. if they were still fading or the
+    /// playback was released.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub assert: bool,
+}
+
+impl Cue {
+    /// Create a new cue recording all channels.
+    #[inline]
+    pub fn new(scene: Scene, fade: time::Duration) -> Cue {
+        Cue {
+            scene,
+            fade,
+            wait: time::Duration::ZERO,
+            follow: None,
+            channels: None,
+            assert: false,
+        }
+    }
+
+    /// Create a new cue recording only `channels` of `scene`.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use dmx::DmxAddress;
+    /// use dmx::scenes::{Cue, CueList, Scene};
+    ///
+    /// let (dimmer, color) = (DmxAddress::new(1).unwrap(), DmxAddress::new(2).unwrap());
+    ///
+    /// let mut scene = Scene::new();
+    /// scene.set(dimmer, 0xff);
+    /// scene.set(color, 0x40);
+    /// let mut darker = Scene::new();
+    /// darker.set(dimmer, 0x80);
+    ///
+    /// let mut cues = CueList::new();
+    /// cues.push(Cue::new(scene, Duration::from_secs(3)));
+    /// cues.push(Cue::tracking(darker, vec![dimmer], Duration::from_secs(1)));
+    ///
+    /// // the color tracks from the first cue
+    /// let state = cues.state(1).unwrap();
+    /// assert_eq!((state.get(dimmer), state.get(color)), (0x80, 0x40));
+    /// ```
+    pub fn tracking<I>(scene: Scene, channels: I, fade: time::Duration) -> Cue
+    where
+        I: IntoIterator<Item = DmxAddress>,
+    {
+        Cue {
+            channels: Some(channels.into_iter().collect()),
+            ..Cue::new(scene, fade)
+        }
+    }
+
+    /// Returns whether the cue records channel `n`.
+    #[inline]
+    pub fn records(&self, n: DmxAddress) -> bool {
+        self.channels.as_ref().is_none_or(|channels| channels.contains(&n))
+    }
+}
+
+/// An ordered list of cues.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct CueList {
+    cues: Vec<Cue>,
+}
+
+impl CueList {
+    /// Create an empty cue list.
+    #[inline]
+    pub fn new() -> CueList {
+        CueList::default()
+    }
+
+    /// Appends a cue.
+    #[inline]
+    pub fn push(&mut self, cue: Cue) {
+        self.cues.push(cue)
+    }
+
+    /// Inserts a cue at `index`, shifting all cues after it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the number of cues.
+    #[inline]
+    pub fn insert(&mut self, index: usize, cue: Cue) {
+        self.cues.insert(index, cue)
+    }
+
+    /// Removes and returns the cue at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    pub fn remove(&mut self, index: usize) -> Cue {
+        self.cues.remove(index)
+    }
+
+    /// Returns the cue at `index`.
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&Cue> {
+        self.cues.get(index)
+    }
+
+    /// Returns the cue at `index` mutably.
+    #[inline]
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Cue> {
+        self.cues.get_mut(index)
+    }
+
+    /// Returns the number of cues.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.cues.len()
+    }
+
+    /// Returns whether the list contains no cues.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.cues.is_empty()
+    }
+
+    /// Returns an iterator over all cues.
+    #[inline]
+    pub fn iter(&self) -> slice::Iter<'_, Cue> {
+        self.cues.iter()
+    }
+
+    /// Returns the values shown by the cue at `index` once complete,
+    /// including tracked values.
+    ///
+    /// Channels tracking from before the first cue are at zero.
+    pub fn state(&self, index: usize) -> Option<DmxUniverse> {
+        let cues = self.cues.get(..=index)?;
+
+        // values before the last block cue do not matter
+        let block = cues.iter().rposition(|cue| cue.channels.is_none());
+        let (mut state, rest) = match block {
+            Some(n) => (cues[n].scene.universe().clone(), &cues[n + 1..]),
+            None => (DmxUniverse::new(), cues),
+        };
+
+        for cue in rest {
+            for &n in cue.channels.iter().flatten() {
+                state.set(n, cue.scene.get(n));
+            }
+        }
+
+        Some(state)
+    }
+
+    /// Turns the cue at `index` into a block cue, recording its tracked
+    /// values.
+    ///
+    /// The cue looks the same afterwards, but changes to earlier cues no
+    /// longer track into it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn block(&mut self, index: usize) {
+        let state = self.state(index).expect("cue index out of bounds");
+        let cue = &mut self.cues[index];
+
+        cue.scene = Scene::from(state);
+        cue.channels = None;
+    }
+}
+
+impl<'a> IntoIterator for &'a CueList {
+    type Item = &'a Cue;
+    type IntoIter = slice::Iter<'a, Cue>;
+
+    #[inline]
+    fn into_iter(self) -> slice::Iter<'a, Cue> {
+        self.cues.iter()
+    }
+}
+
+/// Plays back a cue list.
+///
+/// The output starts out dark, before the first cue. Going to a cue starts a
+/// crossfade from the current output, so cues can be triggered while another
+/// fade is still in progress without any jumps.
+///
+/// Going to the next cue through `go` only fades the channels the cue
+/// records, unless it asserts. Going to any other cue, e.g. through `back`,
+/// fades all channels to the state of that cue, including tracked values.
+#[derive(Clone, Debug)]
+pub struct Playback {
+    cues: CueList,
+    current: Option<usize>,
+    // output at the start of the fade, and its target
+    from: DmxUniverse,
+    target: DmxUniverse,
+    output: DmxUniverse,
+    // time since going to the current cue
+    elapsed: time::Duration,
+    wait: time::Duration,
+    fade: time::Duration,
+    follow: Option<time::Duration>,
+    move_in_black: Vec<MoveInBlack>,
+    // set until fixtures were moved in black for the next cue
+    moving_pending: bool,
+}
+
+/// A fixture moved in black, see `Playback::add_move_in_black`.
+#[derive(Clone, Debug)]
+struct MoveInBlack {
+    intensity: DmxAddress,
+    attributes: Vec<DmxAddress>,
+}
+
+impl Playback {
+    /// Create a new playback, positioned before the first cue.
+    #[inline]
+    pub fn new(cues: CueList) -> Playback {
+        Playback {
+            cues,
+            current: None,
+            from: DmxUniverse::new(),
+            target: DmxUniverse::new(),
+            output: DmxUniverse::new(),
+            elapsed: time::Duration::ZERO,
+            wait: time::Duration::ZERO,
+            fade: time::Duration::ZERO,
+            follow: None,
+            move_in_black: Vec::new(),
+            moving_pending: true,
+        }
+    }
+
+    /// Returns the cue list.
+    #[inline]
+    pub fn cue_list(&self) -> &CueList {
+        &self.cues
+    }
+
+    /// Returns the cue list mutably.
+    ///
+    /// Changes to the current cue take effect the next time it is gone to.
+    #[inline]
+    pub fn cue_list_mut(&mut self) -> &mut CueList {
+        &mut self.cues
+    }
+
+    /// Returns the index of the current cue.
+    #[inline]
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// Starts fading into the next cue.
+    ///
+    /// Returns `false` if there is no next cue.
+    #[inline]
+    pub fn go(&mut self) -> bool {
+        self.go_to(self.current.map_or(0, |n| n + 1))
+    }
+
+    /// Starts fading into the previous cue.
+    ///
+    /// Returns `false` if there is no previous cue.
+    #[inline]
+    pub fn back(&mut self) -> bool {
+        match self.current {
+            Some(n) if n > 0 => self.go_to(n - 1),
+            _ => false,
+        }
+    }
+
+    /// Starts the cue at `index`, fading into it once its wait time has
+    /// passed.
+    ///
+    /// Returns `false` if there is no such cue.
+    pub fn go_to(&mut self, index: usize) -> bool {
+        let cue = match self.cues.get(index) {
+            Some(cue) => cue,
+            None => return false,
+        };
+        let next = self.current.map_or(0, |n| n + 1);
+
+        let target = match cue.channels {
+            Some(ref channels) if index == next && !cue.assert => {
+                // channels not recorded hold what is on stage
+                let mut target = self.output.clone();
+                for &n in channels {
+                    target.set(n, cue.scene.get(n));
+                }
+                target
+            }
+            _ => match self.cues.state(index) {
+                Some(state) => state,
+                None => return false,
+            },
+        };
+
+        let (wait, fade) = (cue.wait, cue.fade);
+        self.follow = cue.follow;
+        self.current = Some(index);
+        self.start_fade(target, wait, fade);
+        true
+    }
+
+    /// Fades out to all channels at zero, positioning the playback before
+    /// the first cue again.
+    #[inline]
+    pub fn release(&mut self, fade: time::Duration) {
+        self.current = None;
+        self.follow = None;
+        self.start_fade(DmxUniverse::new(), time::Duration::ZERO, fade);
+    }
+
+    /// Returns whether a fade is in progress or waiting to start.
+    #[inline]
+    pub fn is_fading(&self) -> bool {
+        self.elapsed < self.wait + self.fade
+    }
+
+    /// Presets the attributes of a fixture while it is dark for the next
+    /// cue ("move in black").
+    ///
+    /// Once a cue is complete, if channel `intensity` is at zero but the
+    /// next cue lights the fixture, the `attributes` of the fixture, e.g.
+    /// its pan, tilt and color channels, are set to their values in the next
+    /// cue right away. The fixture then fades up in place instead of
+    /// visibly moving into position.
+    pub fn add_move_in_black(&mut self, intensity: DmxAddress, attributes: &[DmxAddress]) {
+        self.move_in_black.push(MoveInBlack {
+            intensity,
+            attributes: attributes.to_vec(),
+        });
+    }
+
+    /// Removes all fixtures added through `add_move_in_black`.
+    #[inline]
+    pub fn clear_move_in_black(&mut self) {
+        self.move_in_black.clear();
+    }
+
+    /// Advances the playback by `dt` and writes the current output into
+    /// `universe`.
+    ///
+    /// Follows on to the next cues every time a follow time passes.
+    pub fn tick(&mut self, dt: time::Duration, universe: &mut DmxUniverse) {
+        self.elapsed += dt;
+
+        // a chain may pass several cues within one tick, but never loops
+        for _ in 0..=self.cues.len() {
+            let follow = match self.follow {
+                Some(follow) if self.elapsed >= follow => follow,
+                _ => break,
+            };
+
+            let rest = self.elapsed - follow;
+            self.elapsed = follow;
+            self.render();
+            if !self.go() {
+                self.follow = None;
+                self.elapsed = follow + rest;
+                break;
+            }
+            self.elapsed = rest;
+        }
+
+        self.render();
+        if self.moving_pending && !self.is_fading() {
+            self.moving_pending = false;
+            self.move_fixtures_in_black();
+        }
+
+        universe.channels_mut().copy_from_slice(self.output.channels());
+    }
+
+    /// Returns the output, as of the last tick.
+    #[inline]
+    pub fn output(&self) -> &DmxUniverse {
+        &self.output
+    }
+
+    fn start_fade(&mut self, target: DmxUniverse, wait: time::Duration, fade: time::Duration) {
+        self.from = self.output.clone();
+        self.target = target;
+        self.elapsed = time::Duration::ZERO;
+        self.wait = wait;
+        self.fade = fade;
+        self.moving_pending = true;
+    }
+
+    /// Updates the output for the time elapsed.
+    fn render(&mut self) {
+        let t = match self.elapsed.checked_sub(self.wait) {
+            None => 0.0,
+            Some(_) if self.fade.is_zero() => 1.0,
+            Some(t) => t.as_secs_f32() / self.fade.as_secs_f32(),
+        };
+
+        let channels = self
+            .from
+            .channels()
+            .iter()
+            .zip(self.target.channels())
+            .zip(self.output.channels_mut());
+
+        for ((&from, &to), out) in channels {
+            *out = lerp(from, to, t);
+        }
+    }
+
+    fn move_fixtures_in_black(&mut self) {
+        if self.move_in_black.is_empty() {
+            return;
+        }
+        let next = match self.cues.state(self.current.map_or(0, |n| n + 1)) {
+            Some(next) => next,
+            None => return,
+        };
+
+        for fixture in &self.move_in_black {
+            if self.output.get(fixture.intensity) != 0 || next.get(fixture.intensity) == 0 {
+                continue;
+            }
+
+            for &n in &fixture.attributes {
+                let value = next.get(n);
+                self.from.set(n, value);
+                self.target.set(n, value);
+                self.output.set(n, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: time::Duration = time::Duration::from_secs(1);
+    const HALF: time::Duration = time::Duration::from_millis(500);
+
+    fn addr(n: u16) -> DmxAddress {
+        DmxAddress::new(n).unwrap()
+    }
+
+    fn scene(values: &[u8]) -> Scene {
+        let mut scene = Scene::new();
+        scene.set_range(DmxAddress::MIN, values);
+        scene
+    }
+
+    // advances the playback, returning the first channels of its output
+    fn tick(playback: &mut Playback, dt: time::Duration, count: usize) -> Vec<u8> {
+        let mut universe = DmxUniverse::new();
+        playback.tick(dt, &mut universe);
+        universe.channels()[..count].to_vec()
+    }
+
+    #[test]
+    fn tracking_cues_only_fade_recorded_channels() {
+        let mut cues = CueList::new();
+        cues.push(Cue::new(scene(&[200, 0x40]), time::Duration::ZERO));
+        cues.push(Cue::tracking(scene(&[0]), vec![addr(1)], SECOND));
+        let mut playback = Playback::new(cues);
+
+        playback.go();
+        assert_eq!(tick(&mut playback, time::Duration::ZERO, 2), [200, 0x40]);
+
+        playback.go();
+        assert_eq!(tick(&mut playback, HALF, 2), [100, 0x40]);
+        assert_eq!(tick(&mut playback, HALF, 2), [0, 0x40]);
+        assert_eq!(playback.cue_list().state(1).unwrap().channels()[..2], [0, 0x40]);
+    }
+
+    #[test]
+    fn tracked_channels_hold_their_output() {
+        let mut cues = CueList::new();
+        cues.push(Cue::new(scene(&[200, 200]), time::Duration::ZERO));
+        cues.push(Cue::new(scene(&[0, 0]), SECOND));
+        cues.push(Cue::tracking(scene(&[0xff]), vec![addr(1)], SECOND));
+        let mut playback = Playback::new(cues);
+
+        playback.go();
+        tick(&mut playback, time::Duration::ZERO, 2);
+        playback.go();
+        assert_eq!(tick(&mut playback, HALF, 2), [100, 100]);
+
+        // the second channel stops half way instead of fading on to zero
+        playback.go();
+        assert_eq!(tick(&mut playback, SECOND, 2), [0xff, 100]);
+    }
+
+    #[test]
+    fn tracked_channels_hold_a_released_output() {
+        let mut cues = CueList::new();
+        cues.push(Cue::tracking(scene(&[0xff]), vec![addr(1)], SECOND));
+        cues.push(Cue::new(scene(&[200, 200]), time::Duration::ZERO));
+        let mut playback = Playback::new(cues);
+
+        playback.go_to(1);
+        tick(&mut playback, time::Duration::ZERO, 2);
+        playback.release(SECOND);
+        assert_eq!(tick(&mut playback, HALF, 2), [100, 100]);
+
+        // going to the first cue from the release keeps the second channel
+        playback.go();
+        assert_eq!(tick(&mut playback, SECOND, 2), [0xff, 100]);
+    }
+
+    #[test]
+    fn asserting_cues_fade_tracked_channels() {
+        let mut cues = CueList::new();
+        cues.push(Cue::new(scene(&[200, 200]), time::Duration::ZERO));
+        cues.push(Cue::new(scene(&[0, 0]), SECOND));
+        cues.push(Cue {
+            assert: true,
+            ..Cue::tracking(scene(&[0xff]), vec![addr(1)], SECOND)
+        });
+        let mut playback = Playback::new(cues);
+
+        playback.go();
+        tick(&mut playback, time::Duration::ZERO, 2);
+        playback.go();
+        assert_eq!(tick(&mut playback, HALF, 2), [100, 100]);
+
+        // the second channel fades to the zero tracked from the cue before
+        playback.go();
+        assert_eq!(tick(&mut playback, HALF, 2), [0xb2, 50]);
+        assert_eq!(tick(&mut playback, HALF, 2), [0xff, 0]);
+    }
+
+    #[test]
+    fn going_back_fades_to_the_tracked_state() {
+        let mut cues = CueList::new();
+        cues.push(Cue::new(scene(&[200, 200]), time::Duration::ZERO));
+        cues.push(Cue::tracking(scene(&[0]), vec![addr(1)], time::Duration::ZERO));
+        cues.push(Cue::tracking(scene(&[0, 0]), vec![addr(2)], time::Duration::ZERO));
+        let mut playback = Playback::new(cues);
+
+        for _ in 0..3 {
+            playback.go();
+            tick(&mut playback, time::Duration::ZERO, 2);
+        }
+        assert_eq!(tick(&mut playback, time::Duration::ZERO, 2), [0, 0]);
+
+        assert!(playback.back());
+        assert_eq!(tick(&mut playback, time::Duration::ZERO, 2), [0, 200]);
+        assert!(playback.back());
+        assert!(!playback.back());
+        assert_eq!(tick(&mut playback, time::Duration::ZERO, 2), [200, 200]);
+    }
+
+    #[test]
+    fn fades_start_after_the_wait_time() {
+        let mut cues = CueList::new();
+        cues.push(Cue {
+            wait: HALF,
+            ..Cue::new(scene(&[200]), SECOND)
+        });
+        let mut playback = Playback::new(cues);
+
+        playback.go();
+        assert_eq!(tick(&mut playback, HALF, 1), [0]);
+        assert!(playback.is_fading());
+        assert_eq!(tick(&mut playback, HALF, 1), [100]);
+        assert_eq!(tick(&mut playback, HALF, 1), [200]);
+        assert!(!playback.is_fading());
+    }
+
+    #[test]
+    fn follow_times_chain_cues() {
+        let mut cues = CueList::new();
+        cues.push(Cue {
+            follow: Some(HALF),
+            ..Cue::new(scene(&[200]), time::Duration::ZERO)
+        });
+        cues.push(Cue {
+            wait: HALF,
+            follow: Some(SECOND * 2),
+            ..Cue::new(scene(&[0]), SECOND)
+        });
+        cues.push(Cue::new(scene(&[50]), time::Duration::ZERO));
+        let mut playback = Playback::new(cues);
+
+        playback.go();
+        assert_eq!(tick(&mut playback, time::Duration::ZERO, 1), [200]);
+        assert_eq!(playback.current(), Some(0));
+
+        // the second cue waits, then fades
+        assert_eq!(tick(&mut playback, HALF, 1), [200]);
+        assert_eq!(playback.current(), Some(1));
+        assert_eq!(tick(&mut playback, HALF, 1), [200]);
+        assert_eq!(tick(&mut playback, HALF, 1), [100]);
+
+        // the time left over from the follow carries into the last cue
+        assert_eq!(tick(&mut playback, SECOND, 1), [50]);
+        assert_eq!(playback.current(), Some(2));
+        assert!(!playback.go());
+    }
+
+    #[test]
+    fn chains_may_pass_several_cues_in_one_tick() {
+        let mut cues = CueList::new();
+        for &value in &[10, 20, 30] {
+            cues.push(Cue {
+                follow: Some(time::Duration::from_millis(10)),
+                ..Cue::new(scene(&[value]), time::Duration::ZERO)
+            });
+        }
+        let mut playback = Playback::new(cues);
+
+        playback.go();
+        assert_eq!(tick(&mut playback, time::Duration::from_millis(25), 1), [30]);
+        assert_eq!(playback.current(), Some(2));
+
+        // the last cue has nothing to follow on to
+        assert_eq!(tick(&mut playback, SECOND, 1), [30]);
+        assert_eq!(playback.current(), Some(2));
+    }
+
+    #[test]
+    fn dark_fixtures_move_in_black() {
+        let mut cues = CueList::new();
+        cues.push(Cue::new(scene(&[0xff, 0x10, 0xff, 0x10]), time::Duration::ZERO));
+        cues.push(Cue::new(scene(&[0x00, 0x10, 0x80, 0x10]), time::Duration::ZERO));
+        cues.push(Cue::new(scene(&[0xff, 0x90, 0xff, 0x90]), SECOND));
+        let mut playback = Playback::new(cues);
+        playback.add_move_in_black(addr(1), &[addr(2)]);
+        playback.add_move_in_black(addr(3), &[addr(4)]);
+
+        playback.go();
+        assert_eq!(tick(&mut playback, time::Duration::ZERO, 4), [0xff, 0x10, 0xff, 0x10]);
+
+        // the dark fixture takes its position for the next cue, the lit one
+        // stays where it is
+        playback.go();
+        assert_eq!(tick(&mut playback, time::Duration::ZERO, 4), [0x00, 0x90, 0x80, 0x10]);
+
+        playback.go();
+        assert_eq!(tick(&mut playback, HALF, 4), [0x80, 0x90, 0xc0, 0x50]);
+        assert_eq!(tick(&mut playback, HALF, 4), [0xff, 0x90, 0xff, 0x90]);
+    }
+}
@@ -0,0 +1,157 @@
+//! Pacing of network output.
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time;
+
+/// Paces the packets of network outputs.
+///
+/// Sending dozens of universes at the start of every frame makes for bursts
+/// of packets, which cheap nodes with room for only a few of them in their
+/// buffers partially drop. Set on a `DmxOutputManager` through
+/// `set_scheduler`, a scheduler spreads the outputs evenly over the frame
+/// period instead, and caps the packets per second sent to each destination
+/// of outputs added through `DmxOutputManager::add_network_output`.
+///
+/// A packet over the limit of its destination is delayed if it can still be
+/// sent within the frame, and skipped otherwise. Outputs skipped go first
+/// in the next frame, so those sharing a destination take turns.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::sync::atomic::AtomicBool;
+/// use dmx::{DmxOutputManager, DmxTransmitter, FrameScheduler};
+/// use dmx::artnet::{ArtNetTransmitter, PortAddress};
+///
+/// let node = [10, 0, 0, 20].into();
+/// let mut outputs = DmxOutputManager::new();
+/// for universe in 0..16 {
+///     let address = PortAddress::from_u16(universe).unwrap();
+///     let transmitter = ArtNetTransmitter::new((node, 6454), address).unwrap();
+///     outputs.add_network_output(universe + 1, node, transmitter.boxed());
+/// }
+///
+/// // 16 universes at 40 frames per second, but the node takes only 400
+/// // packets per second
+/// let mut scheduler = FrameScheduler::new();
+/// scheduler.set_rate_limit(node, Some(400.0));
+/// outputs.set_scheduler(Some(scheduler));
+///
+/// let stop = AtomicBool::new(false);
+/// outputs.run(40.0, &stop).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct FrameScheduler {
+    stagger: bool,
+    default_rate: Option<f32>,
+    rates: BTreeMap<IpAddr, f32>,
+    // earliest time the next packet may be sent to each destination
+    next: BTreeMap<IpAddr, time::Instant>,
+}
+
+impl FrameScheduler {
+    /// Create a scheduler staggering outputs, without rate limits.
+    #[inline]
+    pub fn new() -> FrameScheduler {
+        FrameScheduler {
+            stagger: true,
+            default_rate: None,
+            rates: BTreeMap::new(),
+            next: BTreeMap::new(),
+        }
+    }
+
+    /// Returns whether outputs are spread over the frame period.
+    #[inline]
+    pub fn stagger(&self) -> bool {
+        self.stagger
+    }
+
+    /// Enables or disables spreading outputs over the frame period.
+    ///
+    /// If disabled, outputs are sent back to back at the start of each
+    /// frame, only delayed by rate limits. Enabled by default.
+    #[inline]
+    pub fn set_stagger(&mut self, enabled: bool) {
+        self.stagger = enabled;
+    }
+
+    /// Sets the rate limit of destinations without one of their own, in
+    /// packets per second, `None` for no limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not a positive number.
+    pub fn set_default_rate_limit(&mut self, rate: Option<f32>) {
+        assert!(rate.is_none_or(|r| r > 0.0), "rate limit must be positive");
+        self.default_rate = rate;
+    }
+
+    /// Sets the rate limit of a destination, in packets per second, `None`
+    /// for the default limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not a positive number.
+    pub fn set_rate_limit(&mut self, destination: IpAddr, rate: Option<f32>) {
+        assert!(rate.is_none_or(|r| r > 0.0), "rate limit must be positive");
+        match rate {
+            Some(rate) => self.rates.insert(destination, rate),
+            None => self.rates.remove(&destination),
+        };
+    }
+
+    /// Returns the rate limit of a destination, in packets per second.
+    #[inline]
+    pub fn rate_limit(&self, destination: IpAddr) -> Option<f32> {
+        self.rates.get(&destination).copied().or(self.default_rate)
+    }
+
+    /// Returns when output `index` out of `count` of a frame is due.
+    pub(crate) fn slot(
+        &self,
+        start: time::Instant,
+        period: time::Duration,
+        index: usize,
+        count: usize,
+    ) -> time::Instant {
+        if self.stagger && count > 1 {
+            start + period * index as u32 / count as u32
+        } else {
+            start
+        }
+    }
+
+    /// Reserves the next packet to a destination.
+    ///
+    /// Returns when it may be sent, or `None` if not before `deadline`.
+    pub(crate) fn reserve(
+        &mut self,
+        destination: IpAddr,
+        deadline: time::Instant,
+    ) -> Option<time::Instant> {
+        let now = time::Instant::now();
+        let rate = match self.rate_limit(destination) {
+            Some(rate) => rate,
+            None => return Some(now),
+        };
+
+        let next = self.next.get(&destination).copied().unwrap_or(now);
+        if next > deadline {
+            return None;
+        }
+
+        let at = next.max(now);
+        let interval = time::Duration::from_secs_f32(1.0 / rate);
+        self.next.insert(destination, at + interval);
+        Some(at)
+    }
+}
+
+impl Default for FrameScheduler {
+    #[inline]
+    fn default() -> FrameScheduler {
+        FrameScheduler::new()
+    }
+}
@@ -0,0 +1,1011 @@
+//! Serial port DMX transmission.
+
+use std::ffi::OsStr;
+use std::io::IoSlice;
+use std::path::{Path, PathBuf};
+use std::{fmt, io, time};
+
+use serial2::{self, CharSize, FlowControl, Parity, Settings, StopBits};
+
+use crate::devices;
+use crate::direction::DirectionControl;
+use crate::stats::Stats;
+use crate::timing::DmxTiming;
+use crate::{DmxTransceiver, DmxTransmitter, Error, Result, StartCode};
+
+// The ideal baudrate for sending a break is 45,455 baud.
+// At this rate, sendin an 8-bit 0x00 will take the recommended 176 us
+// The following stop bit would take a reasonable 22 us.
+//
+// On Linux, such a rate is set exactly through `termios2` and `BOTHER`, if
+// the driver supports arbitrary divisors. Otherwise, the fastest common baud
+// rate that still results in a break of the configured length is used. With
+// the default timing, this is 57,600 bit/s, resulting in the following
+// timings:
+//
+// BREAK:                 138 us    (spec minimum is 92 uS)
+// actual BREAK
+// MARK-AFTER-BREAK:      17 us    (spec minimum is  8 uS)
+const BREAK_BAUD_RATES: &[u32] = &[115_200, 57_600, 38_400, 19_200, 9_600, 4_800, 2_400, 1_200];
+
+// A break is sent as a 7-bit 0x00, the start bit is low as well
+const BREAK_BITS: u64 = 8;
+
+// channels missing from short packets, sent as zero
+const PADDING: [u8; 512] = [0; 512];
+
+// DMX calls for 250_000 baud
+pub(crate) const DMX_BAUD_RATE: u32 = 250_000;
+
+// a slot is 11 bits at 250,000 baud
+const SLOT_DURATION: time::Duration = time::Duration::from_micros(44);
+
+// gap after which a reply is considered complete. RDM responders may pause
+// up to 2.1 ms between bytes, but USB adapters deliver data in chunks after
+// their latency timer (16 ms on FTDI chips) expires
+const REPLY_IDLE_TIMEOUT: time::Duration = time::Duration::from_millis(20);
+
+/// Returns the baud rate used to send a break of at least `break_us`.
+///
+/// Breaks too long for even the slowest rate are capped to its duration.
+pub(crate) fn break_baud_rate(break_us: u32) -> u32 {
+    BREAK_BAUD_RATES
+        .iter()
+        .cloned()
+        .find(|&rate| BREAK_BITS * 1_000_000 / u64::from(rate) >= u64::from(break_us))
+        .unwrap_or(BREAK_BAUD_RATES[BREAK_BAUD_RATES.len() - 1])
+}
+
+/// Returns the exact baud rate sending a break of `break_us`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn exact_break_baud_rate(break_us: u32) -> u32 {
+    (BREAK_BITS * 1_000_000 / u64::from(break_us.max(1))).max(1) as u32
+}
+
+/// Returns settings for sending breaks of `break_us`, and their baud rate.
+///
+/// On Linux, the exact rate is used if `port` accepts it, which is checked
+/// by applying the settings; the port has to be reconfigured for DMX
+/// afterwards. Elsewhere, or if the driver rounds the rate too far, the rate
+/// is chosen by `break_baud_rate`.
+pub(crate) fn select_break_settings(
+    port: &mut serial2::SerialPort,
+    settings: Settings,
+    break_us: u32,
+) -> Result<(Settings, u32)> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let rate = exact_break_baud_rate(break_us);
+
+        if let Ok(exact) = break_settings(settings.clone(), rate) {
+            if accepts_baud_rate(port, &exact, rate) {
+                return Ok((exact, rate));
+            }
+        }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let _ = port;
+
+    let rate = break_baud_rate(break_us);
+    Ok((break_settings(settings, rate)?, rate))
+}
+
+/// Applies settings and checks whether the driver achieves their baud rate.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn accepts_baud_rate(port: &mut serial2::SerialPort, settings: &Settings, rate: u32) -> bool {
+    if apply_settings(port, settings).is_err() {
+        return false;
+    }
+
+    // drivers report the rate they actually set, allow 2% of deviation
+    match port.get_configuration().and_then(|s| s.get_baud_rate()) {
+        Ok(actual) => actual.abs_diff(rate) * 50 <= rate,
+        Err(_) => false,
+    }
+}
+
+/// Returns the duration of a break sent at `rate` baud.
+pub(crate) fn break_duration(rate: u32) -> time::Duration {
+    time::Duration::from_micros(BREAK_BITS * 1_000_000 / u64::from(rate))
+}
+
+/// Method used to generate a break.
+///
+/// Defaults to `BaudRate`, except on Windows, where reconfiguring a port is
+/// slow and `Ioctl` is used instead.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum BreakMethod {
+    /// Switch to a slow baud rate and send a single `0x00` byte.
+    ///
+    /// Works on almost all UARTs, but requires two reconfigurations of the
+    /// port per packet.
+    #[cfg_attr(not(windows), default)]
+    BaudRate,
+    /// Assert the break condition on the line directly for the configured
+    /// time.
+    ///
+    /// Uses the `TIOCSBRK`/`TIOCCBRK` ioctls (`SetCommBreak`/`ClearCommBreak`
+    /// on Windows), keeping the port at 250,000 baud at all times. If the
+    /// driver does not support these, the port falls back to `BaudRate`.
+    #[cfg_attr(windows, default)]
+    Ioctl,
+}
+
+/// Returns serial port settings for sending breaks.
+pub(crate) fn break_settings(mut settings: Settings, rate: u32) -> Result<Settings> {
+    settings.set_raw();
+    settings
+        .set_baud_rate(rate)
+        .map_err(|_| Error::UnsupportedBaud(rate))?;
+    settings.set_char_size(CharSize::Bits7);
+    settings.set_parity(Parity::None);
+    settings.set_stop_bits(StopBits::One);
+    settings.set_flow_control(FlowControl::None);
+    Ok(settings)
+}
+
+/// Returns serial port settings for sending or receiving DMX data.
+pub(crate) fn dmx_settings(mut settings: Settings) -> Result<Settings> {
+    settings.set_raw();
+    settings
+        .set_baud_rate(DMX_BAUD_RATE)
+        .map_err(|_| Error::UnsupportedBaud(DMX_BAUD_RATE))?;
+    settings.set_char_size(CharSize::Bits8);
+    settings.set_parity(Parity::None);
+    settings.set_stop_bits(StopBits::Two);
+    settings.set_flow_control(FlowControl::None);
+    Ok(settings)
+}
+
+/// A serial port with DMX support.
+///
+/// Sends breaks by switching to a slower baud rate and transmitting a single
+/// `0x00` byte, then switches back to 250,000 baud for the data.
+///
+/// Other ways of generating a break can be selected through `DmxPort::builder`.
+#[derive(Debug)]
+pub struct DmxPort {
+    port: serial2::SerialPort,
+    break_settings: Settings,
+    // baud rate of the break settings
+    break_rate: u32,
+    // break time the break settings are still to be selected for
+    pending_break_us: Option<u32>,
+    dmx_settings: Settings,
+    break_method: BreakMethod,
+    timing: DmxTiming,
+    // set while the port is configured for break transmission
+    in_break_mode: bool,
+    last_break: Option<time::Instant>,
+    // estimated end of the last packet on the line
+    last_data_end: Option<time::Instant>,
+    // channels per packet sent by send_dmx_packet
+    channel_count: usize,
+    direction: Option<Direction>,
+    stats: Stats,
+    // number of the current frame, for tracing
+    sequence: u64,
+}
+
+/// A boxed direction control.
+struct Direction(Box<dyn DirectionControl + Send>);
+
+impl fmt::Debug for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("DirectionControl")
+    }
+}
+
+impl DmxPort {
+    /// Opens a serial device for DMX transmission.
+    #[inline]
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<DmxPort> {
+        DmxPort::builder(path).open()
+    }
+
+    /// Create a builder for configuring a port before opening it.
+    ///
+    /// ```no_run
+    /// use dmx::{BreakMethod, DmxPort, DmxTiming};
+    ///
+    /// let timing = DmxTiming::new(176, 16, 1204).unwrap();
+    /// let port = DmxPort::builder("/dev/ttyAMA0")
+    ///     .timing(timing)
+    ///     .break_method(BreakMethod::Ioctl)
+    ///     .open()
+    ///     .unwrap();
+    /// ```
+    #[inline]
+    pub fn builder<P: AsRef<Path>>(path: P) -> DmxPortBuilder {
+        DmxPortBuilder {
+            path: path.as_ref().to_path_buf(),
+            break_method: BreakMethod::default(),
+            timing: DmxTiming::default(),
+            channel_count: 512,
+            allow_mini_uart: false,
+            #[cfg(target_os = "linux")]
+            rs485: None,
+        }
+    }
+
+    /// Create a DMX port from an already opened serial port.
+    #[inline]
+    pub fn from_serial_port(port: serial2::SerialPort) -> Result<DmxPort> {
+        DmxPort::with_options(port, BreakMethod::default(), DmxTiming::default())
+    }
+
+    fn with_options(
+        mut port: serial2::SerialPort,
+        break_method: BreakMethod,
+        timing: DmxTiming,
+    ) -> Result<DmxPort> {
+        timing.validate()?;
+        let current = port.get_configuration()?;
+        let (break_settings, break_rate) =
+            select_break_settings(&mut port, current.clone(), timing.break_us)?;
+
+        let mut port = DmxPort {
+            break_settings,
+            break_rate,
+            pending_break_us: None,
+            dmx_settings: dmx_settings(current)?,
+            break_method,
+            timing,
+            in_break_mode: true,
+            last_break: None,
+            last_data_end: None,
+            channel_count: 512,
+            direction: None,
+            stats: Stats::new(),
+            sequence: 0,
+            port,
+        };
+        port.enter_dmx_mode()?;
+
+        Ok(port)
+    }
+
+    /// Returns the current timing parameters.
+    #[inline]
+    pub fn timing(&self) -> DmxTiming {
+        self.timing
+    }
+
+    /// Sets the timing parameters used for all following packets.
+    ///
+    /// The port is left alone until the next break, so this can be called
+    /// while a packet is still being transmitted. The baud rate for a new
+    /// break time is selected at that break, which fails with
+    /// `Error::UnsupportedBaud` if the port supports none.
+    ///
+    /// Fails with `Error::InvalidTiming` if the timing is not within the
+    /// limits of the standard, see `DmxTiming::validate`.
+    pub fn set_timing(&mut self, timing: DmxTiming) -> Result<()> {
+        timing.validate()?;
+
+        if timing.break_us != self.timing.break_us {
+            self.pending_break_us = Some(timing.break_us);
+        }
+        self.timing = timing;
+        Ok(())
+    }
+
+    /// Returns the break method in use.
+    ///
+    /// May differ from the requested method if a fallback occurred.
+    #[inline]
+    pub fn break_method(&self) -> BreakMethod {
+        self.break_method
+    }
+
+    /// Returns the number of channels sent per packet.
+    #[inline]
+    pub fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    /// Sets the number of channels sent per packet, from 1 to 512.
+    ///
+    /// `send_dmx_packet` and `send_dmx_alt_packet` only send the first `n`
+    /// channels, padding shorter packets with zeros. Smaller rigs get higher
+    /// frame rates this way: a packet of 24 channels takes about 1.3ms to
+    /// send instead of 23ms, though breaks are never sent more often than
+    /// the minimum break-to-break time of the port's timing allows, 1204
+    /// microseconds by default. Raw packets are sent as they are.
+    ///
+    /// Fails with `Error::InvalidParameter` if `n` is out of range.
+    pub fn set_channel_count(&mut self, n: usize) -> Result<()> {
+        if !(1..=512).contains(&n) {
+            return Err(Error::InvalidParameter("channel count out of range 1-512"));
+        }
+
+        self.channel_count = n;
+        Ok(())
+    }
+
+    fn enter_dmx_mode(&mut self) -> Result<()> {
+        if self.in_break_mode {
+            apply_settings(&mut self.port, &self.dmx_settings).map_err(baud_error)?;
+            self.in_break_mode = false;
+        }
+        Ok(())
+    }
+
+    /// Selects the break settings for a break time set by `set_timing`.
+    ///
+    /// May reconfigure the port, so it is only called right before a break.
+    fn update_break_settings(&mut self) -> Result<()> {
+        if let Some(break_us) = self.pending_break_us {
+            // probing leaves the port in break mode either way
+            self.in_break_mode = true;
+
+            let (settings, rate) =
+                select_break_settings(&mut self.port, self.dmx_settings.clone(), break_us)?;
+            self.break_settings = settings;
+            self.break_rate = rate;
+            self.pending_break_us = None;
+        }
+        Ok(())
+    }
+
+    /// Sends a break byte at a lower baud rate, returning when the break
+    /// started and its duration.
+    ///
+    /// The break byte is still being transmitted on return.
+    fn send_baud_rate_break(&mut self) -> io::Result<(time::Instant, time::Duration)> {
+        apply_settings(&mut self.port, &self.break_settings)?;
+        self.in_break_mode = true;
+        self.port.write_all(&[0x00])?;
+
+        Ok((
+            time::Instant::now(),
+            break_duration(self.break_rate),
+        ))
+    }
+
+    /// Asserts the break condition, returning when the break started and its
+    /// duration.
+    fn send_ioctl_break(&mut self) -> io::Result<(time::Instant, time::Duration)> {
+        // the break condition would otherwise cut off any pending data
+        self.port.flush()?;
+
+        let start = time::Instant::now();
+        self.port.set_break(true)?;
+        sleep_until(start + self.timing.break_duration());
+        self.port.set_break(false)?;
+
+        Ok((start, start.elapsed()))
+    }
+
+    /// Sends a break, returning the time at which it ends.
+    fn send_timed_break(&mut self) -> Result<time::Instant> {
+        let sent = match self.break_method {
+            BreakMethod::BaudRate => None,
+            BreakMethod::Ioctl => match self.send_ioctl_break() {
+                Err(ref e) if is_unsupported(e) => {
+                    self.break_method = BreakMethod::BaudRate;
+                    None
+                }
+                rv => Some(rv),
+            },
+        };
+        let (start, duration) = match sent {
+            Some(rv) => rv,
+            None => {
+                self.update_break_settings()?;
+                self.send_baud_rate_break()
+            }
+        }
+        .map_err(Error::BreakFailed)?;
+
+        self.stats.record_break(duration);
+        trace_event!(
+            trace,
+            method = ?self.break_method,
+            duration_us = duration.as_micros() as u64,
+            "break sent"
+        );
+        Ok(start + duration)
+    }
+
+    /// Returns the number of bytes still waiting to be transmitted.
+    ///
+    /// Uses the `TIOCOUTQ` ioctl. Some USB adapters buffer data on the device
+    /// itself, which is not accounted for.
+    #[cfg(unix)]
+    #[inline]
+    pub fn pending_output(&self) -> Result<usize> {
+        Ok(output_queue_len(&self.port)?)
+    }
+
+    /// Blocks until all buffered data has been transmitted.
+    ///
+    /// Uses `tcdrain` (`FlushFileBuffers` on Windows).
+    #[inline]
+    pub fn wait_drained(&mut self) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        let start = time::Instant::now();
+        self.port.flush()?;
+
+        trace_event!(trace, elapsed_us = start.elapsed().as_micros() as u64, "output drained");
+        Ok(())
+    }
+
+    /// Send a full DMX packet, unless the port is still busy.
+    ///
+    /// Returns `false` without sending anything if data of the previous
+    /// packet is still being transmitted or the minimum break-to-break time
+    /// has not passed yet, allowing callers to do other work instead of
+    /// blocking. Otherwise works like `send_dmx_packet`, which only blocks
+    /// for the duration of the break and mark-after-break.
+    #[cfg(unix)]
+    pub fn send_dmx_packet_nb(&mut self, channels: &[u8]) -> Result<bool> {
+        if let Some(last_break) = self.last_break {
+            if last_break.elapsed() < self.timing.inter_frame_duration() {
+                return Ok(false);
+            }
+        }
+
+        if self.pending_output()? > 0 {
+            return Ok(false);
+        }
+
+        self.send_dmx_packet(channels)?;
+        Ok(true)
+    }
+
+    /// Sets the direction control of a half-duplex transceiver.
+    ///
+    /// The driver is enabled before each break and released once the packet
+    /// has been transmitted completely, which makes sending packets block
+    /// until the output is drained. The line is released right away.
+    pub fn set_direction_control<D>(&mut self, mut control: D) -> Result<()>
+    where
+        D: DirectionControl + Send + 'static,
+    {
+        control.set_transmit(false)?;
+        self.direction = Some(Direction(Box::new(control)));
+        Ok(())
+    }
+
+    /// Removes the direction control, leaving the line in receive mode.
+    #[inline]
+    pub fn clear_direction_control(&mut self) {
+        self.direction = None;
+    }
+
+    /// Returns statistics of the packets sent since opening the port or the
+    /// last `reset_stats`.
+    ///
+    /// Breaks generated by switching baud rates are not measured, their
+    /// duration is derived from the baud rate.
+    #[inline]
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Clears the statistics.
+    #[inline]
+    pub fn reset_stats(&mut self) {
+        self.stats.reset();
+    }
+
+    /// Returns the underlying serial port.
+    #[inline]
+    pub fn into_inner(self) -> serial2::SerialPort {
+        self.port
+    }
+}
+
+/// Writes all of `bufs`, through as few `writev` calls as possible.
+fn write_all_vectored<W: io::Write>(writer: &mut W, mut bufs: &mut [IoSlice]) -> io::Result<()> {
+    IoSlice::advance_slices(&mut bufs, 0);
+
+    while !bufs.is_empty() {
+        match writer.write_vectored(bufs) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => IoSlice::advance_slices(&mut bufs, n),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Sleeps until `deadline`.
+///
+/// Uses `clock_nanosleep` with an absolute deadline, which unlike a relative
+/// sleep does not accumulate delays when interrupted by signals.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn sleep_until(deadline: time::Instant) {
+    let now = time::Instant::now();
+    if deadline <= now {
+        return;
+    }
+
+    // `Instant` is based on the monotonic clock as well
+    let mut ts = ::libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { ::libc::clock_gettime(::libc::CLOCK_MONOTONIC, &mut ts) };
+
+    let remaining = deadline - now;
+    let nanos = ts.tv_nsec as u64 + u64::from(remaining.subsec_nanos());
+    ts.tv_sec += (remaining.as_secs() + nanos / 1_000_000_000) as ::libc::time_t;
+    ts.tv_nsec = (nanos % 1_000_000_000) as _;
+
+    loop {
+        let rv = unsafe {
+            ::libc::clock_nanosleep(
+                ::libc::CLOCK_MONOTONIC,
+                ::libc::TIMER_ABSTIME,
+                &ts,
+                std::ptr::null_mut(),
+            )
+        };
+
+        if rv != ::libc::EINTR {
+            return;
+        }
+    }
+}
+
+/// Sleeps until `deadline`.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn sleep_until(deadline: time::Instant) {
+    let now = time::Instant::now();
+    if deadline > now {
+        std::thread::sleep(deadline - now);
+    }
+}
+
+/// Returns the number of bytes in the output queue of a port.
+#[cfg(unix)]
+pub(crate) fn output_queue_len(port: &serial2::SerialPort) -> io::Result<usize> {
+    use std::os::raw::c_int;
+    use std::os::unix::io::AsRawFd;
+
+    let mut pending: c_int = 0;
+    let rv = unsafe {
+        ::libc::ioctl(
+            port.as_raw_fd(),
+            ::libc::TIOCOUTQ as _,
+            &mut pending as *mut c_int,
+        )
+    };
+
+    if rv < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(pending.max(0) as usize)
+}
+
+/// Applies port settings without waiting for pending output.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn apply_settings(port: &mut serial2::SerialPort, settings: &Settings) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // serial2 drains the output queue before applying settings, which is
+    // much too slow. we take care of timing ourselves
+    let rv = unsafe { ::libc::ioctl(port.as_raw_fd(), ::libc::TCSETS2 as _, settings.as_termios()) };
+
+    if rv < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+// _IOW('T', 2, speed_t), as used by IOKit serial drivers
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const IOSSIOSPEED: ::libc::c_ulong = 0x8004_5402;
+
+/// Applies port settings without waiting for pending output.
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub(crate) fn apply_settings(port: &mut serial2::SerialPort, settings: &Settings) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = port.as_raw_fd();
+    let mut termios = *settings.as_termios();
+    let speed = termios.c_ospeed;
+
+    // termios rejects non-standard baud rates such as 250,000. a standard rate
+    // is set first, then replaced through IOSSIOSPEED
+    termios.c_ispeed = ::libc::B9600;
+    termios.c_ospeed = ::libc::B9600;
+
+    if unsafe { ::libc::tcsetattr(fd, ::libc::TCSANOW, &termios) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if unsafe { ::libc::ioctl(fd, IOSSIOSPEED, &speed) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Applies port settings.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios"
+)))]
+#[inline]
+pub(crate) fn apply_settings(port: &mut serial2::SerialPort, settings: &Settings) -> io::Result<()> {
+    port.set_configuration(settings)
+}
+
+/// Converts an error applying the DMX settings.
+///
+/// Drivers reject unsupported baud rates only once the settings are applied.
+pub(crate) fn baud_error(e: io::Error) -> Error {
+    if is_unsupported(&e) {
+        return Error::UnsupportedBaud(DMX_BAUD_RATE);
+    }
+
+    Error::Io(e)
+}
+
+/// Returns whether an error indicates that an operation is not supported by
+/// the driver.
+pub(crate) fn is_unsupported(e: &io::Error) -> bool {
+    if e.kind() == io::ErrorKind::Unsupported {
+        return true;
+    }
+
+    #[cfg(unix)]
+    {
+        if let Some(errno) = e.raw_os_error() {
+            return errno == ::libc::ENOTTY
+                || errno == ::libc::EINVAL
+                || errno == ::libc::ENOSYS
+                || errno == ::libc::EOPNOTSUPP;
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        // ERROR_INVALID_FUNCTION, ERROR_NOT_SUPPORTED, ERROR_INVALID_PARAMETER
+        if let Some(code) = e.raw_os_error() {
+            return code == 1 || code == 50 || code == 87;
+        }
+    }
+
+    false
+}
+
+impl DmxTransmitter for DmxPort {
+    type Error = Error;
+
+    #[inline]
+    fn send_break(&mut self) -> Result<()> {
+        self.send_timed_break().map(|_| ())
+    }
+
+    #[inline]
+    fn send_raw_data(&mut self, data: &[u8]) -> Result<()> {
+        self.enter_dmx_mode()?;
+        self.port.write_all(data)?;
+
+        trace_event!(trace, len = data.len(), "data written");
+        Ok(())
+    }
+
+    #[inline]
+    fn send_dmx_alt_packet(&mut self, channels: &[u8], start: StartCode) -> Result<()> {
+        let channels = &channels[..channels.len().min(self.channel_count)];
+
+        self.send_vectored_dmx_packet(&mut [
+            IoSlice::new(&[start.as_u8()]),
+            IoSlice::new(channels),
+            IoSlice::new(&PADDING[channels.len()..self.channel_count]),
+        ])
+    }
+
+    #[inline]
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<()> {
+        self.send_vectored_dmx_packet(&mut [IoSlice::new(data)])
+    }
+}
+
+impl DmxPort {
+    /// Send a DMX packet, including start code, gathered from several
+    /// buffers.
+    ///
+    /// Works like `send_raw_dmx_packet`, but writes the buffers in a single
+    /// `writev` call where possible instead of copying them into one packet
+    /// first. `send_dmx_packet` and `send_dmx_alt_packet` use this to prepend
+    /// the start code. The slices are advanced while writing.
+    ///
+    /// ```no_run
+    /// use std::io::IoSlice;
+    ///
+    /// let mut port = dmx::open_serial("/dev/ttyS1").unwrap();
+    /// let channels = [255; 512];
+    ///
+    /// port.send_vectored_dmx_packet(&mut [IoSlice::new(&[0x00]), IoSlice::new(&channels)])
+    ///     .unwrap();
+    /// ```
+    pub fn send_vectored_dmx_packet(&mut self, bufs: &mut [IoSlice]) -> Result<()> {
+        self.sequence = self.sequence.wrapping_add(1);
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("dmx_frame", sequence = self.sequence).entered();
+
+        #[cfg(feature = "tracing")]
+        let previous = self.last_break;
+        let len = bufs.iter().map(|b| b.len()).sum();
+
+        match self.send_frame(bufs) {
+            Ok(()) => {
+                if let Some(start) = self.last_break {
+                    self.stats.record_frame(start, len);
+
+                    #[cfg(feature = "tracing")]
+                    let interval = previous.map(|p| start.saturating_duration_since(p));
+                    trace_event!(
+                        trace,
+                        len,
+                        interval_us = interval.map(|i| i.as_micros() as u64),
+                        "frame sent"
+                    );
+                }
+                Ok(())
+            }
+            Err(e) => {
+                self.stats.record_error();
+                trace_event!(warn, error = %e, "frame failed");
+                Err(e)
+            }
+        }
+    }
+
+    /// Writes one slot at a time, leaving the mark time between slots after
+    /// each.
+    fn write_spaced(&mut self, bufs: &[IoSlice]) -> io::Result<()> {
+        let period = SLOT_DURATION + self.timing.mtbs_duration();
+        let mut next = time::Instant::now();
+
+        for slot in bufs.iter().flat_map(|b| b.iter()) {
+            sleep_until(next);
+            self.port.write_all(std::slice::from_ref(slot))?;
+            next += period;
+        }
+
+        Ok(())
+    }
+
+    /// Sends a break, followed by a packet.
+    fn send_frame(&mut self, bufs: &mut [IoSlice]) -> Result<()> {
+        // honor the minimum break-to-break time and mark-before-break
+        if let Some(last_break) = self.last_break {
+            sleep_until(last_break + self.timing.inter_frame_duration());
+        }
+        if let Some(data_end) = self.last_data_end {
+            sleep_until(data_end + self.timing.mbb_duration());
+        }
+
+        if let Some(Direction(ref mut control)) = self.direction {
+            control.set_transmit(true)?;
+        }
+
+        self.last_break = Some(time::Instant::now());
+        let break_end = self.send_timed_break()?;
+
+        // a break byte must be transmitted completely before switching back
+        // to the DMX baud rate. deadlines are absolute, so the time taken to
+        // reconfigure the port is part of the mark-after-break
+        sleep_until(break_end);
+        self.enter_dmx_mode()?;
+        sleep_until(break_end + self.timing.mab_duration());
+
+        let len = bufs.iter().map(|b| b.len()).sum::<usize>() as u32;
+        let written = time::Instant::now();
+        if self.timing.mtbs_us == 0 {
+            write_all_vectored(&mut self.port, bufs)?;
+        } else {
+            self.write_spaced(bufs)?;
+        }
+        trace_event!(trace, len, "data written");
+
+        // assumes the data starts going out right away, which holds unless
+        // the port buffers output on the adapter
+        let gaps = self.timing.mtbs_duration() * len.saturating_sub(1);
+        self.last_data_end = Some(written + SLOT_DURATION * len + gaps);
+
+        if self.direction.is_some() {
+            self.wait_drained()?;
+        }
+        if let Some(Direction(ref mut control)) = self.direction {
+            control.set_transmit(false)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl DmxTransceiver for DmxPort {
+    #[inline]
+    fn discard_input(&mut self) -> Result<()> {
+        self.port.discard_input_buffer()?;
+        Ok(())
+    }
+
+    fn recv_raw_data(&mut self, buf: &mut [u8], timeout: time::Duration) -> Result<usize> {
+        self.enter_dmx_mode()?;
+        self.port.flush()?;
+        self.port.set_read_timeout(timeout)?;
+
+        let mut len = 0;
+        while len < buf.len() {
+            match self.port.read(&mut buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => break,
+                Err(e) => return Err(e.into()),
+            }
+
+            self.port.set_read_timeout(REPLY_IDLE_TIMEOUT)?;
+        }
+
+        Ok(len)
+    }
+}
+
+/// Builder for DMX ports.
+///
+/// Created through `DmxPort::builder`. Options not set keep the defaults
+/// used by `open_serial`.
+#[derive(Clone, Debug)]
+pub struct DmxPortBuilder {
+    path: PathBuf,
+    break_method: BreakMethod,
+    timing: DmxTiming,
+    channel_count: usize,
+    allow_mini_uart: bool,
+    // delays before and after sending, if RS485 mode is to be enabled
+    #[cfg(target_os = "linux")]
+    rs485: Option<(time::Duration, time::Duration)>,
+}
+
+impl DmxPortBuilder {
+    /// Sets the method used to generate breaks.
+    #[inline]
+    pub fn break_method(mut self, break_method: BreakMethod) -> DmxPortBuilder {
+        self.break_method = break_method;
+        self
+    }
+
+    /// Sets the timing parameters.
+    ///
+    /// The timing is validated when opening the port.
+    #[inline]
+    pub fn timing(mut self, timing: DmxTiming) -> DmxPortBuilder {
+        self.timing = timing;
+        self
+    }
+
+    /// Sets the number of channels sent per packet, see
+    /// `DmxPort::set_channel_count`.
+    ///
+    /// The count is validated when opening the port. Ports opened through
+    /// `open_async` always send all 512 channels.
+    #[inline]
+    pub fn channel_count(mut self, n: usize) -> DmxPortBuilder {
+        self.channel_count = n;
+        self
+    }
+
+    /// Allows opening the mini UART of a Raspberry Pi.
+    ///
+    /// On a Pi 3 or 4 with Bluetooth enabled, `/dev/ttyS0` (and
+    /// `/dev/serial0`) is the mini UART, whose baud rate is derived from the
+    /// VPU core clock. As the clock scales with load, so does the baud rate,
+    /// corrupting frames, and opening such ports fails with `Error::MiniUart`
+    /// by default. The PL011 UART, `/dev/ttyAMA0`, does not have this problem
+    /// and is used for the GPIO pins after adding `dtoverlay=disable-bt` or
+    /// `dtoverlay=miniuart-bt` to `config.txt`.
+    ///
+    /// The mini UART can be used once the core clock is fixed, e.g. through
+    /// `core_freq=250` on a Pi 3, or `core_freq=500` and `core_freq_min=500`
+    /// on a Pi 4. Ports are only detected as mini UARTs on Linux.
+    #[inline]
+    pub fn allow_mini_uart(mut self, allow: bool) -> DmxPortBuilder {
+        self.allow_mini_uart = allow;
+        self
+    }
+
+    /// Enables the kernel's RS485 mode when opening the port.
+    ///
+    /// The driver then asserts RTS, which is wired to the driver-enable pin of
+    /// half-duplex transceivers on many boards, while sending and releases it
+    /// right afterwards. Disabled by default, which leaves the mode as it is.
+    ///
+    /// Uses the `TIOCSRS485` ioctl and is only available on Linux. Opening
+    /// fails with `Error::Unsupported` if the driver lacks RS485 support.
+    #[cfg(target_os = "linux")]
+    #[inline]
+    pub fn rs485(mut self, enable: bool) -> DmxPortBuilder {
+        self.rs485 = enable.then_some((time::Duration::ZERO, time::Duration::ZERO));
+        self
+    }
+
+    /// Enables the kernel's RS485 mode when opening the port, holding RTS for
+    /// the given delays.
+    ///
+    /// `delay_before` and `delay_after` are the times RTS is held before and
+    /// after transmission; drivers usually round them to whole milliseconds.
+    /// See `rs485`.
+    #[cfg(target_os = "linux")]
+    #[inline]
+    pub fn enable_rs485(
+        mut self,
+        delay_before: time::Duration,
+        delay_after: time::Duration,
+    ) -> DmxPortBuilder {
+        self.rs485 = Some((delay_before, delay_after));
+        self
+    }
+
+    /// Opens the port.
+    pub fn open(self) -> Result<DmxPort> {
+        let port = self.open_port()?;
+        let mut port = DmxPort::with_options(port, self.break_method, self.timing)?;
+        port.set_channel_count(self.channel_count)?;
+
+        Ok(port)
+    }
+
+    fn open_port(&self) -> Result<serial2::SerialPort> {
+        if !self.allow_mini_uart && devices::is_mini_uart(&self.path) {
+            return Err(Error::MiniUart);
+        }
+
+        // settings are applied afterwards, to detect unsupported baud rates
+        let port = serial2::SerialPort::open(&self.path, serial2::KeepSettings)?;
+
+        #[cfg(target_os = "linux")]
+        {
+            if let Some((delay_before, delay_after)) = self.rs485 {
+                let mut config = serial2::rs4xx::Rs485Config::new();
+                config.set_delay_before_send(delay_before);
+                config.set_delay_after_send(delay_after);
+
+                port.set_rs4xx_mode(config).map_err(|e| {
+                    if is_unsupported(&e) {
+                        Error::Unsupported("RS485 mode")
+                    } else {
+                        Error::Io(e)
+                    }
+                })?;
+            }
+        }
+
+        Ok(port)
+    }
+
+    /// Opens the port for asynchronous transmission.
+    ///
+    /// # Panics
+    ///
+    /// Panics if not called from within a tokio runtime.
+    #[cfg(all(unix, feature = "tokio"))]
+    pub fn open_async(self) -> Result<crate::AsyncDmxPort> {
+        let port = self.open_port()?;
+        crate::AsyncDmxPort::with_options(port, self.break_method, self.timing)
+    }
+}
+
+/// Opens a serial device with DMX support.
+///
+/// On macOS, the callout device should be used, e.g.
+/// `/dev/cu.usbserial-A10K3N2E`. On Windows, ports are given by name, e.g.
+/// `COM3`. Uses the default options; see `DmxPort::builder` for others.
+#[inline]
+pub fn open_serial<T: AsRef<OsStr> + ?Sized>(port: &T) -> Result<DmxPort> {
+    DmxPort::open(Path::new(port))
+}
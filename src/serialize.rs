@@ -0,0 +1,329 @@
+//! Serde support for channel data.
+//!
+//! Packets, universes and dimmer curves are serialized as bytes, or as a
+//! base64 string in human-readable formats such as JSON. Deserializing also
+//! accepts a sequence of numbers.
+
+use core::{fmt, str};
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::address::DmxAddress;
+use crate::curve::DimmerCurve;
+use crate::packet::{DmxPacket, MAX_CHANNELS};
+use crate::universe::DmxUniverse;
+
+// length of the base64 encoding of a full packet
+const MAX_ENCODED: usize = (MAX_CHANNELS + 1).div_ceil(3) * 4;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes `bytes` as padded base64 into `out`, returning the length.
+pub(crate) fn encode(bytes: &[u8], out: &mut [u8]) -> usize {
+    let mut len = 0;
+
+    for chunk in bytes.chunks(3) {
+        let word = chunk
+            .iter()
+            .chain(&[0, 0])
+            .take(3)
+            .fold(0, |word, &b| word << 8 | u32::from(b));
+
+        for i in 0..4 {
+            out[len + i] = if i <= chunk.len() {
+                ALPHABET[(word >> (18 - 6 * i) & 0x3f) as usize]
+            } else {
+                b'='
+            };
+        }
+        len += 4;
+    }
+
+    len
+}
+
+/// Decodes padded base64 into `out`, returning the length.
+///
+/// Returns `None` if `encoded` is malformed or does not fit into `out`.
+fn decode(encoded: &[u8], out: &mut [u8]) -> Option<usize> {
+    if !encoded.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let chunks = encoded.len() / 4;
+    let mut len = 0;
+
+    for (n, chunk) in encoded.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && n + 1 < chunks) {
+            return None;
+        }
+
+        let mut word = 0;
+        for &c in &chunk[..4 - padding] {
+            word = word << 6 | u32::from(sextet(c)?);
+        }
+        word <<= 6 * padding;
+
+        let count = 3 - padding;
+        if len + count > out.len() {
+            return None;
+        }
+        for (i, b) in out[len..(len + count)].iter_mut().enumerate() {
+            *b = (word >> (16 - 8 * i)) as u8;
+        }
+        len += count;
+    }
+
+    Some(len)
+}
+
+fn sextet(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+fn serialize_bytes<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    if !serializer.is_human_readable() {
+        return serializer.serialize_bytes(bytes);
+    }
+
+    let mut buf = [0; MAX_ENCODED];
+    let len = encode(bytes, &mut buf);
+
+    // the alphabet is ASCII
+    serializer.serialize_str(str::from_utf8(&buf[..len]).unwrap())
+}
+
+/// Deserializes up to `N` bytes, returning them and their number.
+fn deserialize_bytes<'de, D, const N: usize>(deserializer: D) -> Result<([u8; N], usize), D::Error>
+where
+    D: Deserializer<'de>,
+{
+    // some human-readable formats hand out strings as bytes
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(BytesVisitor::<N>)
+    } else {
+        deserializer.deserialize_bytes(BytesVisitor::<N>)
+    }
+}
+
+struct BytesVisitor<const N: usize>;
+
+impl<'de, const N: usize> Visitor<'de> for BytesVisitor<N> {
+    type Value = ([u8; N], usize);
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at most {} bytes, possibly base64-encoded", N)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        let mut buf = [0; N];
+
+        match decode(v.as_bytes(), &mut buf) {
+            Some(len) => Ok((buf, len)),
+            None => Err(E::invalid_value(de::Unexpected::Str(v), &self)),
+        }
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        if v.len() > N {
+            return Err(E::invalid_length(v.len(), &self));
+        }
+
+        let mut buf = [0; N];
+        buf[..v.len()].copy_from_slice(v);
+        Ok((buf, v.len()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut buf = [0; N];
+        let mut len = 0;
+
+        while let Some(b) = seq.next_element()? {
+            if len == N {
+                return Err(de::Error::invalid_length(len + 1, &self));
+            }
+            buf[len] = b;
+            len += 1;
+        }
+
+        Ok((buf, len))
+    }
+}
+
+/// Serialized as its raw data, including the start code.
+impl Serialize for DmxPacket {
+    #[inline]
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DmxPacket {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<DmxPacket, D::Error> {
+        let (buf, len) = deserialize_bytes::<_, { MAX_CHANNELS + 1 }>(deserializer)?;
+
+        DmxPacket::from_raw(&buf[..len]).ok_or_else(|| de::Error::custom("missing start code"))
+    }
+}
+
+/// Serialized as its channel values; fades in progress are not included.
+impl Serialize for DmxUniverse {
+    #[inline]
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(self.channels(), serializer)
+    }
+}
+
+/// Channels missing from shorter data are set to zero.
+impl<'de> Deserialize<'de> for DmxUniverse {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<DmxUniverse, D::Error> {
+        let (buf, len) = deserialize_bytes::<_, MAX_CHANNELS>(deserializer)?;
+
+        let mut universe = DmxUniverse::new();
+        universe.set_range(DmxAddress::MIN, &buf[..len]);
+        Ok(universe)
+    }
+}
+
+/// Serialized as its lookup table.
+impl Serialize for DimmerCurve {
+    #[inline]
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(self.table(), serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DimmerCurve {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<DimmerCurve, D::Error> {
+        let (table, len) = deserialize_bytes::<_, 256>(deserializer)?;
+        if len != table.len() {
+            return Err(de::Error::invalid_length(len, &"256 bytes"));
+        }
+
+        Ok(DimmerCurve::from_table(table))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(bytes: &[u8]) -> String {
+        let mut buf = [0; MAX_ENCODED];
+        let len = encode(bytes, &mut buf);
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    fn decoded(encoded: &str) -> Option<Vec<u8>> {
+        let mut buf = [0; MAX_CHANNELS + 1];
+        decode(encoded.as_bytes(), &mut buf).map(|len| buf[..len].to_vec())
+    }
+
+    #[test]
+    fn rfc_4648_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+
+        for &(plain, base64) in &vectors {
+            assert_eq!(encoded(plain.as_bytes()), base64);
+            assert_eq!(decoded(base64).unwrap(), plain.as_bytes());
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let bytes: Vec<u8> = (0..=255).cycle().take(513).map(|b: u8| b.wrapping_mul(7)).collect();
+
+        for &len in &[0, 1, 2, 3, 4, 5, 512, 513] {
+            let base64 = encoded(&bytes[..len]);
+            assert_eq!(base64.len(), len.div_ceil(3) * 4);
+            assert_eq!(decoded(&base64).unwrap(), &bytes[..len]);
+        }
+    }
+
+    #[test]
+    fn short_chunks_are_padded() {
+        assert_eq!(encoded(&[0xff]), "/w==");
+        assert_eq!(encoded(&[0xff, 0xff]), "//8=");
+        assert_eq!(encoded(&[0xff, 0xff, 0xff]), "////");
+        assert_eq!(encoded(&[0x00, 0x10, 0x83]), "ABCD");
+    }
+
+    #[test]
+    fn malformed_base64_is_rejected() {
+        // padding before the last chunk, or more than two characters of it
+        assert_eq!(decoded("Zg==Zm8="), None);
+        assert_eq!(decoded("Z==="), None);
+        assert_eq!(decoded("===="), None);
+        assert_eq!(decoded("Zm=v"), None);
+        // characters outside the alphabet
+        assert_eq!(decoded("Zm9-"), None);
+        assert_eq!(decoded("Zm9_"), None);
+        assert_eq!(decoded("Zm 9"), None);
+        // lengths that are not a multiple of 4
+        assert_eq!(decoded("Zm9"), None);
+        assert_eq!(decoded("Zm9vY"), None);
+    }
+
+    #[test]
+    fn output_overflow_is_rejected() {
+        let mut buf = [0; 2];
+        assert_eq!(decode(b"Zm8=", &mut buf), Some(2));
+        assert_eq!(decode(b"Zm9v", &mut buf), None);
+
+        let mut buf = [0; 4];
+        assert_eq!(decode(b"Zm9vYg==", &mut buf), Some(4));
+        assert_eq!(decode(b"Zm9vYmE=", &mut buf), None);
+    }
+
+    #[test]
+    fn packets_round_trip_through_json() {
+        let packet = DmxPacket::from_channels(&[0xff, 0x80, 0x00]);
+        let json = serde_json::to_string(&packet).unwrap();
+        assert_eq!(json, "\"AP+AAA==\"");
+        assert_eq!(serde_json::from_str::<DmxPacket>(&json).unwrap(), packet);
+
+        // a full packet, and one given as numbers
+        let full = DmxPacket::new();
+        let json = serde_json::to_string(&full).unwrap();
+        assert_eq!(serde_json::from_str::<DmxPacket>(&json).unwrap(), full);
+        let numbers: DmxPacket = serde_json::from_str("[0, 255, 128, 0]").unwrap();
+        assert_eq!(numbers, packet);
+
+        // the start code is required
+        assert!(serde_json::from_str::<DmxPacket>("\"\"").is_err());
+        assert!(serde_json::from_str::<DmxPacket>("\"AP+AAA\"").is_err());
+    }
+
+    #[test]
+    fn universes_round_trip_through_json() {
+        let mut universe = DmxUniverse::new();
+        universe.set_range(DmxAddress::MIN, &[0xff, 0x80]);
+        universe.set_range(DmxAddress::new(512).unwrap(), &[0x40]);
+
+        let json = serde_json::to_string(&universe).unwrap();
+        assert_eq!(json.len(), MAX_CHANNELS.div_ceil(3) * 4 + 2);
+        assert_eq!(serde_json::from_str::<DmxUniverse>(&json).unwrap(), universe);
+
+        // missing channels are zero, excess ones are rejected
+        let short: DmxUniverse = serde_json::from_str("\"/4A=\"").unwrap();
+        assert_eq!(short.channels()[..3], [0xff, 0x80, 0x00]);
+        assert!(serde_json::from_str::<DmxUniverse>(&format!("{:?}", vec![0; 513])).is_err());
+    }
+}
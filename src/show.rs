@@ -0,0 +1,333 @@
+//! Show files.
+//!
+//! A `Show` bundles everything needed to run a show: the patch, named scenes
+//! and cue lists, the effects running on each universe and the outputs the
+//! universes are sent through. Shows are saved as TOML, so they can be moved
+//! between machines, versioned and, if need be, edited by hand.
+//!
+//! Show files carry the version of the format they were written in,
+//! `SHOW_VERSION`. Files written by later, incompatible versions are
+//! rejected rather than loaded incompletely.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use dmx::DmxAddress;
+//! use dmx::effects::Chase;
+//! use dmx::scenes::{Cue, CueList, Scene};
+//! use dmx::show::{BuiltinEffect, OutputTarget, Show, ShowEffect, ShowOutput};
+//!
+//! let mut show = Show::new("Rehearsal");
+//!
+//! let mut scene = Scene::new();
+//! scene.set(DmxAddress::new(1).unwrap(), 0xff);
+//! let mut cues = CueList::new();
+//! cues.push(Cue::new(scene, Duration::from_secs(3)));
+//! show.cue_lists.insert("Main".to_owned(), cues);
+//!
+//! let chase = BuiltinEffect::Chase(Chase::new(4.0));
+//! show.effects.push(ShowEffect::new(1, DmxAddress::new(25).unwrap(), 4, chase));
+//! show.outputs.push(ShowOutput {
+//!     universe: 1,
+//!     target: OutputTarget::Serial { port: "/dev/ttyUSB0".to_owned() },
+//!     interface: None,
+//! });
+//!
+//! show.save("rehearsal.toml").unwrap();
+//!
+//! let show = Show::load("rehearsal.toml").unwrap();
+//! let mut outputs = show.open_outputs().unwrap();
+//! let mut effects = show.effect_engine(1);
+//! ```
+
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::{fs, io, result, time};
+
+use serde::{Deserialize, Serialize};
+
+use crate::address::DmxAddress;
+use crate::artnet::{ArtNetTransmitter, PortAddress};
+use crate::effects::{
+    BlendMode, Chase, Effect, EffectEngine, EffectParams, Rainbow, Random, SineWave, Strobe,
+};
+use crate::fixture::Fixture;
+use crate::output::DmxOutputManager;
+use crate::patch::Patch;
+use crate::sacn::{generate_cid, multicast_address, SacnSource, SacnTransmitter};
+use crate::scenes::{CueList, Scene};
+use crate::{open_serial, DmxTransmitter, Error, Result, SocketOptions};
+
+/// Version of the show file format written by this version of the crate.
+pub const SHOW_VERSION: u32 = 1;
+
+/// One of the built-in effects, as stored in a show.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum BuiltinEffect {
+    /// See `Chase`.
+    Chase(Chase),
+    /// See `Strobe`.
+    Strobe(Strobe),
+    /// See `Rainbow`.
+    Rainbow(Rainbow),
+    /// See `SineWave`.
+    SineWave(SineWave),
+    /// See `Random`.
+    Random(Random),
+}
+
+impl Effect for BuiltinEffect {
+    fn render(&self, t: time::Duration, channels: &mut [u8]) {
+        match *self {
+            BuiltinEffect::Chase(ref e) => e.render(t, channels),
+            BuiltinEffect::Strobe(ref e) => e.render(t, channels),
+            BuiltinEffect::Rainbow(ref e) => e.render(t, channels),
+            BuiltinEffect::SineWave(ref e) => e.render(t, channels),
+            BuiltinEffect::Random(ref e) => e.render(t, channels),
+        }
+    }
+}
+
+/// An effect assigned to channels of a universe.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ShowEffect {
+    /// Universe the effect renders into.
+    pub universe: u16,
+    /// First channel rendered into.
+    pub start: DmxAddress,
+    /// Number of channels rendered into.
+    pub count: usize,
+    /// How the effect is combined with the channels below it.
+    #[serde(default)]
+    pub blend: BlendMode,
+    /// Speed, size and offset of the effect.
+    #[serde(default)]
+    pub params: EffectParams,
+    /// The effect.
+    pub effect: BuiltinEffect,
+}
+
+impl ShowEffect {
+    /// Create an assignment with the default blend mode and parameters.
+    #[inline]
+    pub fn new(universe: u16, start: DmxAddress, count: usize, effect: BuiltinEffect) -> ShowEffect {
+        ShowEffect {
+            universe,
+            start,
+            count,
+            blend: BlendMode::default(),
+            params: EffectParams::default(),
+            effect,
+        }
+    }
+}
+
+/// Where an output of a show sends its universe.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "protocol", rename_all = "lowercase")]
+pub enum OutputTarget {
+    /// A serial port, see `open_serial`.
+    Serial {
+        /// Name of the port, e.g. `/dev/ttyUSB0`.
+        port: String,
+    },
+    /// An Art-Net node, see `ArtNetTransmitter`.
+    Artnet {
+        /// Address of the node, or a broadcast address.
+        target: String,
+        /// 15-bit port-address, combining net, sub-net and universe.
+        port_address: u16,
+    },
+    /// sACN, multicast to the universe of the same number.
+    Sacn,
+}
+
+/// An output of a show.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShowOutput {
+    /// Universe sent.
+    pub universe: u16,
+    /// Where the universe is sent to.
+    #[serde(flatten)]
+    pub target: OutputTarget,
+    /// Address of the network interface Art-Net and sACN are sent from,
+    /// see `SocketOptions`. Ignored by serial ports.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<Ipv4Addr>,
+}
+
+/// The complete state of a show.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Show {
+    /// Name of the show, also used as the sACN source name.
+    pub name: String,
+    /// Patched fixtures.
+    ///
+    /// Fixtures are stored in the order of their ids; ids of removed
+    /// fixtures are not kept, so the fixtures of a loaded show may have
+    /// lower ids than when it was saved.
+    #[serde(with = "patch_fixtures")]
+    pub patch: Patch,
+    /// Scenes, by name.
+    pub scenes: BTreeMap<String, Scene>,
+    /// Cue lists, by name.
+    pub cue_lists: BTreeMap<String, CueList>,
+    /// Effects running on the universes.
+    pub effects: Vec<ShowEffect>,
+    /// Outputs the universes are sent through.
+    pub outputs: Vec<ShowOutput>,
+}
+
+impl Show {
+    /// Create an empty show.
+    #[inline]
+    pub fn new(name: &str) -> Show {
+        Show {
+            name: name.to_owned(),
+            ..Show::default()
+        }
+    }
+
+    /// Reads a show from a file.
+    ///
+    /// Fails with `InvalidData` if the file is not a valid show file, or one
+    /// of a later version.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Show> {
+        Show::from_toml(&fs::read_to_string(path)?)
+    }
+
+    /// Writes the show to a file, replacing it if it exists.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_toml()?)
+    }
+
+    /// Reads a show from the contents of a show file.
+    ///
+    /// See `load`.
+    pub fn from_toml(document: &str) -> io::Result<Show> {
+        let table: toml::Table = toml::from_str(document).map_err(invalid)?;
+
+        match table.get("version").and_then(toml::Value::as_integer) {
+            Some(version) if version > SHOW_VERSION.into() => {
+                return Err(invalid("show file written by a later version"))
+            }
+            Some(_) => (),
+            None => return Err(invalid("not a show file")),
+        }
+
+        table.try_into().map_err(invalid)
+    }
+
+    /// Returns the contents of a show file for the show.
+    pub fn to_toml(&self) -> io::Result<String> {
+        let mut table = toml::Table::new();
+        table.insert("version".to_owned(), SHOW_VERSION.into());
+        table.extend(toml::Table::try_from(self).map_err(invalid)?);
+
+        toml::to_string(&table).map_err(invalid)
+    }
+
+    /// Creates an engine running the effects of `universe`.
+    pub fn effect_engine(&self, universe: u16) -> EffectEngine {
+        let mut engine = EffectEngine::new();
+
+        for effect in self.effects.iter().filter(|e| e.universe == universe) {
+            let id = engine.add(effect.start, effect.count, effect.effect);
+            engine.set_blend(id, effect.blend);
+            if let Some(handle) = engine.handle(id) {
+                handle.set_params(effect.params);
+            }
+        }
+
+        engine
+    }
+
+    /// Opens all outputs of the show.
+    ///
+    /// Fails if any of them cannot be opened, e.g. because a serial port
+    /// does not exist on this machine. Network outputs are added along with
+    /// their destinations, so they can be paced by a `FrameScheduler`.
+    pub fn open_outputs(&self) -> Result<DmxOutputManager> {
+        let mut outputs = DmxOutputManager::new();
+
+        for output in &self.outputs {
+            let options = SocketOptions {
+                interface: output.interface,
+                ..SocketOptions::default()
+            };
+
+            match output.target {
+                OutputTarget::Serial { ref port } => {
+                    outputs.add_output(output.universe, open_serial(port)?.boxed());
+                }
+                OutputTarget::Artnet {
+                    ref target,
+                    port_address,
+                } => {
+                    let address = PortAddress::from_u16(port_address)
+                        .ok_or(Error::InvalidParameter("invalid Art-Net port-address"))?;
+                    let node = ArtNetTransmitter::with_options(target.as_str(), address, &options)?;
+                    let destination = node.target().ip();
+                    outputs.add_network_output(output.universe, destination, node.boxed());
+                }
+                OutputTarget::Sacn => {
+                    let source = SacnSource::with_options(&self.name, generate_cid(), &options)?;
+                    let destination = multicast_address(output.universe).into();
+                    let transmitter = SacnTransmitter::from_source(source, output.universe)?;
+                    outputs.add_network_output(output.universe, destination, transmitter.boxed());
+                }
+            }
+        }
+
+        Ok(outputs)
+    }
+}
+
+fn invalid<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Stores a patch as a sequence of fixtures with their universes.
+mod patch_fixtures {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Patched<F> {
+        universe: u16,
+        fixture: F,
+    }
+
+    pub fn serialize<S>(patch: &Patch, serializer: S) -> result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(
+            patch
+                .iter()
+                .map(|(_, universe, fixture)| Patched { universe, fixture }),
+        )
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> result::Result<Patch, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let fixtures: Vec<Patched<Fixture>> = Deserialize::deserialize(deserializer)?;
+        let mut patch = Patch::new();
+
+        for p in fixtures {
+            patch
+                .add(p.universe, p.fixture)
+                .map_err(serde::de::Error::custom)?;
+        }
+
+        Ok(patch)
+    }
+}
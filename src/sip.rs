@@ -0,0 +1,320 @@
+//! System information packets.
+//!
+//! A system information packet (SIP, start code `0xCF`) follows a packet of
+//! dimmer data and carries its checksum and length, allowing receivers to
+//! verify the integrity of the link. It further identifies up to five devices
+//! the data passed through, starting with the originator.
+//!
+//! Transmitters create SIPs through a `SipGenerator`; received SIPs are
+//! parsed by `Sip::decode`.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use dmx::DmxTransmitter;
+//! use dmx::sip::SipGenerator;
+//!
+//! let mut dmx_port = dmx::open_serial("/dev/ttyS1").unwrap();
+//! let mut sip = SipGenerator::new(0x7ff0);
+//! let packet = dmx::DmxPacket::from_channels(&[0xff, 0x80]);
+//!
+//! dmx_port.send_packet(&packet).unwrap();
+//! sip.record(&packet);
+//! dmx_port.send_packet(&sip.next_packet()).unwrap();
+//! ```
+
+use crate::packet::{DmxPacket, StartCode};
+
+/// Length of a SIP, including start code and checksum.
+pub const SIP_LEN: usize = 25;
+
+// offset of the SIP checksum, which covers all bytes before it
+const CHECKSUM_OFFSET: usize = SIP_LEN - 1;
+
+/// Returns the checksum of a packet, as carried by SIPs.
+///
+/// The checksum is the sum of all bytes of the packet, including its start
+/// code, truncated to 16 bits.
+pub fn packet_checksum(packet: &[u8]) -> u16 {
+    packet
+        .iter()
+        .fold(0u16, |sum, &b| sum.wrapping_add(u16::from(b)))
+}
+
+/// The contents of a system information packet.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Sip {
+    /// Control bit field, reserved and usually zero.
+    pub control: u8,
+    /// Checksum of the preceding packet, see `packet_checksum`.
+    pub checksum: u16,
+    /// Sequence number, incremented with every SIP sent.
+    pub sequence: u8,
+    /// Universe number of the link.
+    pub universe: u8,
+    /// Number of devices the data passed through, zero at the originator.
+    pub processing_level: u8,
+    /// Software version of the device that sent the SIP.
+    pub software_version: u8,
+    /// Length of the preceding packet, including its start code.
+    pub packet_length: u16,
+    /// Number of packets sent since the previous SIP.
+    pub packet_count: u16,
+    /// ESTA manufacturer IDs of the devices the data passed through,
+    /// starting with the originator; unused entries are zero.
+    pub devices: [u16; 5],
+}
+
+impl Sip {
+    /// Encodes the SIP into `buf`, which must hold at least `SIP_LEN` bytes.
+    ///
+    /// Returns the length of the packet.
+    pub fn encode(&self, buf: &mut [u8]) -> usize {
+        buf[0] = StartCode::Sip.as_u8();
+        buf[1] = CHECKSUM_OFFSET as u8;
+        buf[2] = self.control;
+        buf[3..5].copy_from_slice(&self.checksum.to_be_bytes());
+        buf[5] = self.sequence;
+        buf[6] = self.universe;
+        buf[7] = self.processing_level;
+        buf[8] = self.software_version;
+        buf[9..11].copy_from_slice(&self.packet_length.to_be_bytes());
+        buf[11..13].copy_from_slice(&self.packet_count.to_be_bytes());
+        for (i, id) in self.devices.iter().enumerate() {
+            buf[(13 + 2 * i)..(15 + 2 * i)].copy_from_slice(&id.to_be_bytes());
+        }
+        buf[23] = 0;
+        buf[CHECKSUM_OFFSET] = sip_checksum(&buf[..CHECKSUM_OFFSET]);
+
+        SIP_LEN
+    }
+
+    /// Returns the SIP as a packet.
+    #[inline]
+    pub fn to_packet(&self) -> DmxPacket {
+        let mut buf = [0; SIP_LEN];
+        self.encode(&mut buf);
+
+        // the buffer always holds a start code and fits into a packet
+        DmxPacket::from_raw(&buf).unwrap()
+    }
+
+    /// Decodes a received SIP, including its start code.
+    ///
+    /// Returns `None` if the data is not a SIP or its checksum mismatches.
+    pub fn decode(packet: &[u8]) -> Option<Sip> {
+        if packet.len() < SIP_LEN || packet[0] != StartCode::Sip.as_u8() {
+            return None;
+        }
+
+        // the byte count allows for future extensions of the packet
+        let len = usize::from(packet[1]);
+        if len < CHECKSUM_OFFSET || len >= packet.len() {
+            return None;
+        }
+
+        if sip_checksum(&packet[..len]) != packet[len] {
+            return None;
+        }
+
+        let u16_at = |i: usize| u16::from_be_bytes([packet[i], packet[i + 1]]);
+        let mut devices = [0; 5];
+        for (i, id) in devices.iter_mut().enumerate() {
+            *id = u16_at(13 + 2 * i);
+        }
+
+        Some(Sip {
+            control: packet[2],
+            checksum: u16_at(3),
+            sequence: packet[5],
+            universe: packet[6],
+            processing_level: packet[7],
+            software_version: packet[8],
+            packet_length: u16_at(9),
+            packet_count: u16_at(11),
+            devices,
+        })
+    }
+
+    /// Returns whether the SIP describes `packet`, the packet of dimmer
+    /// data received right before it.
+    #[inline]
+    pub fn matches(&self, packet: &[u8]) -> bool {
+        usize::from(self.packet_length) == packet.len()
+            && self.checksum == packet_checksum(packet)
+    }
+}
+
+/// Generates SIPs on a transmitter.
+///
+/// Every packet of dimmer data sent is passed to `record`; `next_packet`
+/// then creates the SIP to be sent after it.
+#[derive(Clone, Debug)]
+pub struct SipGenerator {
+    sip: Sip,
+    sent: u16,
+}
+
+impl SipGenerator {
+    /// Create a generator for an originating device.
+    ///
+    /// `manufacturer_id` is the ESTA manufacturer ID of the device.
+    #[inline]
+    pub fn new(manufacturer_id: u16) -> SipGenerator {
+        let mut sip = Sip::default();
+        sip.devices[0] = manufacturer_id;
+
+        SipGenerator { sip, sent: 0 }
+    }
+
+    /// Sets the universe number reported.
+    #[inline]
+    pub fn set_universe(&mut self, universe: u8) {
+        self.sip.universe = universe;
+    }
+
+    /// Sets the software version reported.
+    #[inline]
+    pub fn set_software_version(&mut self, version: u8) {
+        self.sip.software_version = version;
+    }
+
+    /// Records a packet that has been sent.
+    ///
+    /// Only packets with the null start code are covered by SIPs, others
+    /// are ignored.
+    pub fn record(&mut self, packet: &[u8]) {
+        if packet.first() != Some(&StartCode::Null.as_u8()) {
+            return;
+        }
+
+        self.sip.checksum = packet_checksum(packet);
+        self.sip.packet_length = packet.len() as u16;
+        self.sent = self.sent.saturating_add(1);
+    }
+
+    /// Returns the SIP describing the last packet recorded.
+    ///
+    /// Advances the sequence number and restarts counting packets.
+    pub fn next_packet(&mut self) -> DmxPacket {
+        self.sip.packet_count = self.sent;
+        let packet = self.sip.to_packet();
+
+        self.sip.sequence = self.sip.sequence.wrapping_add(1);
+        self.sent = 0;
+        packet
+    }
+}
+
+/// Returns the checksum of the SIP itself, the low byte of the sum of all
+/// preceding bytes.
+#[inline]
+fn sip_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sip() -> Sip {
+        Sip {
+            control: 0,
+            checksum: 0x1234,
+            sequence: 7,
+            universe: 2,
+            processing_level: 1,
+            software_version: 3,
+            packet_length: 513,
+            packet_count: 1,
+            devices: [0x7ff0, 0x4744, 0, 0, 0],
+        }
+    }
+
+    #[test]
+    fn sip_layout() {
+        let mut buf = [0; SIP_LEN];
+        assert_eq!(sip().encode(&mut buf), SIP_LEN);
+
+        // start code, byte count, control, checksum, sequence, universe,
+        // processing level, software version, length, packet count,
+        // manufacturer IDs, reserved, SIP checksum
+        let expected = [
+            0xcf, 0x18, 0x00, 0x12, 0x34, 0x07, 0x02, 0x01, 0x03, 0x02, 0x01, 0x00, 0x01, 0x7f,
+            0xf0, 0x47, 0x44, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x38,
+        ];
+        assert_eq!(buf, expected);
+        assert_eq!(Sip::decode(&buf), Some(sip()));
+    }
+
+    #[test]
+    fn packet_checksum_wraps_around() {
+        assert_eq!(packet_checksum(&[0x00, 0xff, 0x80]), 0x017f);
+        assert_eq!(packet_checksum(&[0xff; 513]), 0xfeff);
+    }
+
+    #[test]
+    fn invalid_sips_are_rejected() {
+        let mut buf = [0; SIP_LEN];
+        sip().encode(&mut buf);
+
+        assert!(Sip::decode(&buf[..SIP_LEN - 1]).is_none());
+
+        let corrupt = |i: usize, value: u8| {
+            let mut packet = buf;
+            packet[i] = value;
+            Sip::decode(&packet).is_some()
+        };
+        assert!(!corrupt(0, 0x00));
+        assert!(!corrupt(1, 0x17));
+        assert!(!corrupt(1, 0x19));
+        assert!(!corrupt(5, 0x08));
+        assert!(!corrupt(24, 0x39));
+    }
+
+    #[test]
+    fn extended_sips_are_decoded() {
+        // a future extension, with two more bytes before the checksum
+        let mut packet = [0; SIP_LEN + 2];
+        sip().encode(&mut packet);
+        packet[1] = 0x1a;
+        packet[24] = 0xaa;
+        packet[25] = 0x55;
+        packet[26] = sip_checksum(&packet[..26]);
+
+        assert_eq!(Sip::decode(&packet), Some(sip()));
+    }
+
+    #[test]
+    fn generator_describes_the_last_packet() {
+        let mut generator = SipGenerator::new(0x7ff0);
+        generator.set_universe(1);
+
+        let packet = DmxPacket::from_channels(&[0xff, 0x80]);
+        generator.record(&packet);
+        // alternate start codes are not covered
+        generator.record(&DmxPacket::text(0, 20, "ignored"));
+        generator.record(&packet);
+
+        let first = Sip::decode(&generator.next_packet()).unwrap();
+        assert_eq!(first.sequence, 0);
+        assert_eq!(first.universe, 1);
+        assert_eq!(first.packet_count, 2);
+        assert_eq!(first.devices, [0x7ff0, 0, 0, 0, 0]);
+        assert!(first.matches(&packet));
+        assert!(!first.matches(&DmxPacket::from_channels(&[0xff, 0x81])));
+
+        let second = Sip::decode(&generator.next_packet()).unwrap();
+        assert_eq!(second.sequence, 1);
+        assert_eq!(second.packet_count, 0);
+    }
+
+    #[test]
+    fn generator_sequence_wraps_around() {
+        let mut generator = SipGenerator::new(0x7ff0);
+        generator.sip.sequence = 0xff;
+
+        assert_eq!(Sip::decode(&generator.next_packet()).unwrap().sequence, 0xff);
+        assert_eq!(Sip::decode(&generator.next_packet()).unwrap().sequence, 0);
+    }
+}
@@ -0,0 +1,520 @@
+//! Line analysis.
+//!
+//! A `Sniffer` decodes the signal of a monitored DMX line from the times at
+//! which it changes level, and collects histograms of break and
+//! mark-after-break lengths, the time between slots, frame intervals and
+//! channel counts. Edges are read from an `EdgeSource`, such as a GPIO line
+//! connected to the receive side of a transceiver with the `gpio-cdev`
+//! feature, see `GpioEdges`:
+//!
+//! ```no_run
+//! # #[cfg(all(target_os = "linux", feature = "gpio-cdev"))]
+//! # fn main() -> std::io::Result<()> {
+//! use std::time;
+//! use dmx::sniffer::{GpioEdges, Sniffer};
+//!
+//! let mut edges = GpioEdges::new("/dev/gpiochip0", 15)?;
+//! let mut sniffer = Sniffer::new();
+//!
+//! sniffer.capture(&mut edges, time::Duration::from_secs(10))?;
+//! print!("{}", sniffer.report());
+//! # Ok(())
+//! # }
+//! # #[cfg(not(all(target_os = "linux", feature = "gpio-cdev")))]
+//! # fn main() {}
+//! ```
+//!
+//! Edges have to be timestamped to within a few microseconds to decode
+//! slots; breaks and marks-after-break are much longer and measured even on
+//! busy systems. Where only a UART is available, packets received through
+//! `SerialReceiver` can be passed to `Sniffer::feed_packet`, which records
+//! frame intervals and channel counts only.
+
+use std::collections::BTreeMap;
+use std::{fmt, io, time};
+
+/// Duration of a bit at 250,000 baud, in nanoseconds.
+const BIT_NS: u64 = 4_000;
+
+/// Bits of a slot: a start bit, eight data bits and two stop bits.
+const SLOT_BITS: u8 = 11;
+
+/// Shortest break receivers have to accept, in microseconds.
+const MIN_BREAK_US: u64 = 88;
+
+/// A change of the line level.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Edge {
+    /// Time of the change, relative to any fixed point.
+    pub time: time::Duration,
+    /// Level after the change, `true` for a mark (idle line).
+    pub high: bool,
+}
+
+/// Source of timestamped edges of a DMX line.
+pub trait EdgeSource {
+    /// Waits for the next edge.
+    fn next_edge(&mut self) -> io::Result<Edge>;
+}
+
+impl<F> EdgeSource for F
+where
+    F: FnMut() -> io::Result<Edge>,
+{
+    #[inline]
+    fn next_edge(&mut self) -> io::Result<Edge> {
+        self()
+    }
+}
+
+/// Edges of a GPIO line, timestamped by the kernel.
+///
+/// Requires the `gpio-cdev` feature and is only available on Linux. The
+/// line has to be connected to the receiver output of a transceiver, whose
+/// idle level is high.
+#[cfg(all(target_os = "linux", feature = "gpio-cdev"))]
+#[derive(Debug)]
+pub struct GpioEdges {
+    handle: gpio_cdev::LineEventHandle,
+}
+
+#[cfg(all(target_os = "linux", feature = "gpio-cdev"))]
+impl GpioEdges {
+    /// Requests edge events of line `line` of a GPIO chip, e.g.
+    /// `/dev/gpiochip0`.
+    pub fn new<P: AsRef<std::path::Path>>(chip: P, line: u32) -> io::Result<GpioEdges> {
+        use gpio_cdev::{EventRequestFlags, LineRequestFlags};
+
+        let mut chip = gpio_cdev::Chip::new(chip).map_err(crate::direction::gpio_error)?;
+        let handle = chip
+            .get_line(line)
+            .and_then(|line| {
+                line.events(LineRequestFlags::INPUT, EventRequestFlags::BOTH_EDGES, "dmx")
+            })
+            .map_err(crate::direction::gpio_error)?;
+
+        Ok(GpioEdges { handle })
+    }
+
+    /// Returns the event handle.
+    #[inline]
+    pub fn into_inner(self) -> gpio_cdev::LineEventHandle {
+        self.handle
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "gpio-cdev"))]
+impl EdgeSource for GpioEdges {
+    fn next_edge(&mut self) -> io::Result<Edge> {
+        let event = self.handle.get_event().map_err(crate::direction::gpio_error)?;
+
+        Ok(Edge {
+            time: time::Duration::from_nanos(event.timestamp()),
+            high: event.event_type() == gpio_cdev::EventType::RisingEdge,
+        })
+    }
+}
+
+/// A histogram of values in fixed-width buckets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    width: u32,
+    buckets: BTreeMap<u32, u64>,
+    count: u64,
+    sum: u64,
+    min: Option<u32>,
+    max: Option<u32>,
+}
+
+impl Histogram {
+    /// Create an empty histogram with buckets `width` wide.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is zero.
+    pub fn new(width: u32) -> Histogram {
+        assert!(width > 0, "bucket width must not be zero");
+
+        Histogram {
+            width,
+            buckets: BTreeMap::new(),
+            count: 0,
+            sum: 0,
+            min: None,
+            max: None,
+        }
+    }
+
+    /// Adds a value.
+    pub fn record(&mut self, value: u32) {
+        *self.buckets.entry(value - value % self.width).or_insert(0) += 1;
+        self.count += 1;
+        self.sum += u64::from(value);
+        self.min = Some(self.min.map_or(value, |m| m.min(value)));
+        self.max = Some(self.max.map_or(value, |m| m.max(value)));
+    }
+
+    /// Removes all values.
+    pub fn clear(&mut self) {
+        *self = Histogram::new(self.width);
+    }
+
+    /// Returns the width of the buckets.
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the number of values.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the smallest value.
+    #[inline]
+    pub fn min(&self) -> Option<u32> {
+        self.min
+    }
+
+    /// Returns the largest value.
+    #[inline]
+    pub fn max(&self) -> Option<u32> {
+        self.max
+    }
+
+    /// Returns the mean of all values.
+    pub fn mean(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+
+        Some(self.sum as f64 / self.count as f64)
+    }
+
+    /// Returns the lower bound and number of values of all non-empty
+    /// buckets, in ascending order.
+    pub fn buckets(&self) -> impl Iterator<Item = (u32, u64)> + '_ {
+        self.buckets.iter().map(|(&bucket, &count)| (bucket, count))
+    }
+}
+
+/// Measurements collected by a `Sniffer`.
+///
+/// Durations are in microseconds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnifferReport {
+    /// Number of complete frames.
+    pub frames: u64,
+    /// Number of slots with a framing error; the frames containing them are
+    /// discarded.
+    pub errors: u64,
+    /// Lengths of breaks, in buckets of 4 µs.
+    pub breaks: Histogram,
+    /// Lengths of marks-after-break, in buckets of 4 µs.
+    pub marks_after_break: Histogram,
+    /// Idle times between the stop bits of a slot and the start bit of the
+    /// next, in buckets of 4 µs.
+    pub inter_slot: Histogram,
+    /// Time from one break to the next, in buckets of 1 ms.
+    pub frame_intervals: Histogram,
+    /// Number of channels per frame, excluding the start code.
+    pub channel_counts: Histogram,
+}
+
+impl SnifferReport {
+    fn new() -> SnifferReport {
+        SnifferReport {
+            frames: 0,
+            errors: 0,
+            breaks: Histogram::new(4),
+            marks_after_break: Histogram::new(4),
+            inter_slot: Histogram::new(4),
+            frame_intervals: Histogram::new(1_000),
+            channel_counts: Histogram::new(1),
+        }
+    }
+
+    /// Returns the mean frame rate, in frames per second.
+    pub fn frame_rate(&self) -> Option<f64> {
+        self.frame_intervals
+            .mean()
+            .filter(|&mean| mean > 0.0)
+            .map(|mean| 1_000_000.0 / mean)
+    }
+}
+
+impl fmt::Display for SnifferReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "frames: {}", self.frames)?;
+        writeln!(f, "errors: {}", self.errors)?;
+        if let Some(rate) = self.frame_rate() {
+            writeln!(f, "frame rate: {:.1} Hz", rate)?;
+        }
+
+        let rows = [
+            ("break", &self.breaks, " us"),
+            ("mark-after-break", &self.marks_after_break, " us"),
+            ("inter-slot", &self.inter_slot, " us"),
+            ("frame interval", &self.frame_intervals, " us"),
+            ("channels", &self.channel_counts, ""),
+        ];
+        for (name, histogram, unit) in rows {
+            if let (Some(min), Some(mean), Some(max)) =
+                (histogram.min(), histogram.mean(), histogram.max())
+            {
+                writeln!(
+                    f,
+                    "{}: min {}{unit}, mean {:.0}{unit}, max {}{unit}",
+                    name,
+                    min,
+                    mean,
+                    max,
+                    unit = unit
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A frame decoded by a `Sniffer`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// Time at which the break started, in the time base of the edges.
+    pub time: time::Duration,
+    /// Length of the break.
+    pub break_length: time::Duration,
+    /// Length of the mark-after-break.
+    pub mark_after_break: time::Duration,
+    /// Start code, followed by the channels.
+    pub data: Vec<u8>,
+}
+
+/// A slot being decoded.
+#[derive(Copy, Clone, Debug)]
+struct Slot {
+    value: u8,
+    // bits received, including the start bit
+    bits: u8,
+}
+
+/// Decoder and analyzer of a monitored DMX line.
+///
+/// Waits for the first break, then decodes frames until a framing error
+/// occurs, after which it waits for the next break.
+#[derive(Clone, Debug)]
+pub struct Sniffer {
+    report: SnifferReport,
+    // time and level of the last edge
+    last: Option<Edge>,
+    // frame being decoded, none while waiting for a break
+    frame: Option<Frame>,
+    // set until the mark-after-break of the current frame ended
+    in_mab: bool,
+    slot: Option<Slot>,
+    // idle time since the stop bits of the last slot, in nanoseconds
+    mark_ns: u64,
+    // start of the last break, for frame intervals
+    last_break: Option<time::Duration>,
+    // time of the last packet passed to `feed_packet`
+    last_packet: Option<time::Duration>,
+}
+
+impl Default for Sniffer {
+    #[inline]
+    fn default() -> Sniffer {
+        Sniffer::new()
+    }
+}
+
+impl Sniffer {
+    /// Create a sniffer without any measurements.
+    pub fn new() -> Sniffer {
+        Sniffer {
+            report: SnifferReport::new(),
+            last: None,
+            frame: None,
+            in_mab: false,
+            slot: None,
+            mark_ns: 0,
+            last_break: None,
+            last_packet: None,
+        }
+    }
+
+    /// Returns the measurements collected so far.
+    #[inline]
+    pub fn report(&self) -> &SnifferReport {
+        &self.report
+    }
+
+    /// Clears all measurements.
+    ///
+    /// A frame being decoded is still completed.
+    pub fn reset(&mut self) {
+        self.report = SnifferReport::new();
+    }
+
+    /// Reads edges from `source` until they span `duration`.
+    pub fn capture<S: EdgeSource>(
+        &mut self,
+        source: &mut S,
+        duration: time::Duration,
+    ) -> io::Result<()> {
+        let first = source.next_edge()?;
+        self.feed(first);
+
+        loop {
+            let edge = source.next_edge()?;
+            self.feed(edge);
+
+            if edge.time.saturating_sub(first.time) >= duration {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Processes an edge of the line.
+    ///
+    /// Returns the previous frame once it is complete, i.e. when the break
+    /// of the next frame has ended. Edges must be passed in order; edges
+    /// without a change of level are ignored.
+    pub fn feed(&mut self, edge: Edge) -> Option<Frame> {
+        let last = match self.last {
+            Some(last) if last.high == edge.high => return None,
+            Some(last) => last,
+            None => {
+                self.last = Some(edge);
+                return None;
+            }
+        };
+        self.last = Some(edge);
+
+        let length = edge.time.saturating_sub(last.time);
+        self.interval(last.time, last.high, length)
+    }
+
+    /// Records a packet received through a UART at `time`.
+    ///
+    /// Only frame intervals and channel counts are recorded, the timing of
+    /// breaks and slots is not visible through a UART.
+    pub fn feed_packet(&mut self, time: time::Duration, packet: &[u8]) {
+        if let Some(last) = self.last_packet {
+            self.report
+                .frame_intervals
+                .record(micros(time.saturating_sub(last)));
+        }
+        self.last_packet = Some(time);
+
+        self.report.frames += 1;
+        self.report
+            .channel_counts
+            .record(packet.len().saturating_sub(1) as u32);
+    }
+
+    /// Processes a period of the line at one level.
+    fn interval(
+        &mut self,
+        start: time::Duration,
+        high: bool,
+        length: time::Duration,
+    ) -> Option<Frame> {
+        if !high && micros(length) as u64 >= MIN_BREAK_US {
+            return self.on_break(start, length);
+        }
+
+        let frame = self.frame.as_mut()?;
+        if self.in_mab {
+            // the break ended with a rising edge, so this is the mark
+            frame.mark_after_break = length;
+            self.report.marks_after_break.record(micros(length));
+            self.in_mab = false;
+            return None;
+        }
+
+        let ns = length.as_nanos() as u64;
+        let bits = ((ns + BIT_NS / 2) / BIT_NS).max(1);
+
+        for consumed in 0..bits {
+            match self.slot {
+                // idle line after the stop bits, for the rest of the period
+                None if high => {
+                    self.mark_ns += ns.saturating_sub(consumed * BIT_NS);
+                    return None;
+                }
+                None => {
+                    if !frame.data.is_empty() {
+                        self.report.inter_slot.record((self.mark_ns / 1_000) as u32);
+                    }
+                    self.slot = Some(Slot { value: 0, bits: 1 });
+                }
+                Some(ref mut slot) if slot.bits <= 8 => {
+                    // least significant bit first
+                    slot.value |= u8::from(high) << (slot.bits - 1);
+                    slot.bits += 1;
+                }
+                Some(ref mut slot) => {
+                    if !high {
+                        // stop bits must be marks
+                        self.report.errors += 1;
+                        self.frame = None;
+                        self.slot = None;
+                        return None;
+                    }
+
+                    slot.bits += 1;
+                    if slot.bits == SLOT_BITS {
+                        if frame.data.len() < 513 {
+                            frame.data.push(slot.value);
+                        }
+                        self.slot = None;
+                        self.mark_ns = 0;
+                    }
+                }
+            }
+        }
+
+        // idle time left after the stop bits that completed a slot
+        if high && self.slot.is_none() {
+            self.mark_ns += ns.saturating_sub(bits * BIT_NS);
+        }
+        None
+    }
+
+    /// Completes the current frame and starts a new one.
+    fn on_break(&mut self, start: time::Duration, length: time::Duration) -> Option<Frame> {
+        self.report.breaks.record(micros(length));
+
+        if let Some(last) = self.last_break {
+            self.report
+                .frame_intervals
+                .record(micros(start.saturating_sub(last)));
+        }
+        self.last_break = Some(start);
+
+        let finished = self.frame.take().filter(|frame| !frame.data.is_empty());
+        if let Some(ref frame) = finished {
+            self.report.frames += 1;
+            self.report
+                .channel_counts
+                .record(frame.data.len() as u32 - 1);
+        }
+
+        self.frame = Some(Frame {
+            time: start,
+            break_length: length,
+            mark_after_break: time::Duration::ZERO,
+            data: Vec::with_capacity(513),
+        });
+        self.in_mab = true;
+        self.slot = None;
+        self.mark_ns = 0;
+
+        finished
+    }
+}
+
+fn micros(d: time::Duration) -> u32 {
+    d.as_micros().min(u128::from(u32::MAX)) as u32
+}
@@ -0,0 +1,158 @@
+//! Transmission statistics.
+
+use std::time;
+
+/// Statistics of sent or received frames.
+///
+/// Collected by `DmxPort` and `SerialReceiver`, see their `stats` methods.
+/// Intervals are measured from the start of one frame to the start of the
+/// next, so their spread is the jitter of the frame rate.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    frames: u64,
+    bytes: u64,
+    errors: u64,
+    last_frame: Option<time::Instant>,
+    intervals: u64,
+    interval_sum: time::Duration,
+    // in seconds squared, for the standard deviation
+    interval_square_sum: f64,
+    min_interval: Option<time::Duration>,
+    max_interval: Option<time::Duration>,
+    breaks: u64,
+    break_sum: time::Duration,
+    min_break: Option<time::Duration>,
+    max_break: Option<time::Duration>,
+}
+
+impl Stats {
+    /// Create empty statistics.
+    #[inline]
+    pub fn new() -> Stats {
+        Stats::default()
+    }
+
+    /// Records a frame of `len` bytes, including the start code, starting
+    /// at `start`.
+    pub fn record_frame(&mut self, start: time::Instant, len: usize) {
+        if let Some(last) = self.last_frame {
+            let interval = start.saturating_duration_since(last);
+
+            self.intervals += 1;
+            self.interval_sum += interval;
+            self.interval_square_sum += interval.as_secs_f64() * interval.as_secs_f64();
+            self.min_interval = Some(self.min_interval.map_or(interval, |m| m.min(interval)));
+            self.max_interval = Some(self.max_interval.map_or(interval, |m| m.max(interval)));
+        }
+
+        self.frames += 1;
+        self.bytes += len as u64;
+        self.last_frame = Some(start);
+    }
+
+    /// Records a break of the given, possibly estimated, duration.
+    pub fn record_break(&mut self, duration: time::Duration) {
+        self.breaks += 1;
+        self.break_sum += duration;
+        self.min_break = Some(self.min_break.map_or(duration, |m| m.min(duration)));
+        self.max_break = Some(self.max_break.map_or(duration, |m| m.max(duration)));
+    }
+
+    /// Records a failed frame.
+    #[inline]
+    pub fn record_error(&mut self) {
+        self.errors += 1;
+    }
+
+    /// Clears all statistics.
+    #[inline]
+    pub fn reset(&mut self) {
+        *self = Stats::default();
+    }
+
+    /// Returns the number of frames.
+    #[inline]
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Returns the number of bytes of all frames, including start codes.
+    #[inline]
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the number of failed frames.
+    #[inline]
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Returns the achieved frame rate, in frames per second.
+    ///
+    /// Returns `None` until two frames have been recorded.
+    pub fn frame_rate(&self) -> Option<f64> {
+        let mean = self.mean_interval()?.as_secs_f64();
+        if mean == 0.0 {
+            return None;
+        }
+
+        Some(1.0 / mean)
+    }
+
+    /// Returns the shortest interval between frames.
+    #[inline]
+    pub fn min_interval(&self) -> Option<time::Duration> {
+        self.min_interval
+    }
+
+    /// Returns the longest interval between frames.
+    #[inline]
+    pub fn max_interval(&self) -> Option<time::Duration> {
+        self.max_interval
+    }
+
+    /// Returns the mean interval between frames.
+    #[inline]
+    pub fn mean_interval(&self) -> Option<time::Duration> {
+        if self.intervals == 0 {
+            return None;
+        }
+
+        Some(mean(self.interval_sum, self.intervals))
+    }
+
+    /// Returns the standard deviation of the intervals between frames.
+    pub fn jitter(&self) -> Option<time::Duration> {
+        let mean = self.mean_interval()?.as_secs_f64();
+        let variance = self.interval_square_sum / self.intervals as f64 - mean * mean;
+
+        Some(time::Duration::from_secs_f64(variance.max(0.0).sqrt()))
+    }
+
+    /// Returns the shortest break.
+    #[inline]
+    pub fn min_break(&self) -> Option<time::Duration> {
+        self.min_break
+    }
+
+    /// Returns the longest break.
+    #[inline]
+    pub fn max_break(&self) -> Option<time::Duration> {
+        self.max_break
+    }
+
+    /// Returns the mean break duration.
+    #[inline]
+    pub fn mean_break(&self) -> Option<time::Duration> {
+        if self.breaks == 0 {
+            return None;
+        }
+
+        Some(mean(self.break_sum, self.breaks))
+    }
+}
+
+fn mean(sum: time::Duration, count: u64) -> time::Duration {
+    time::Duration::from_secs_f64(sum.as_secs_f64() / count as f64)
+}
@@ -0,0 +1,487 @@
+//! Utilities for testing applications without hardware.
+//!
+//! `MockTransmitter` records everything sent through it, for inspection by
+//! tests. `MockRdmBus` connects an RDM controller to virtual devices. On
+//! Unix, `pty_loopback` connects a real `DmxPort` to a pseudo-terminal,
+//! capturing the bytes it writes, including breaks.
+//!
+//! ## Example
+//!
+//! ```
+//! use dmx::{DmxAddress, DmxTransmitter};
+//! use dmx::testing::MockTransmitter;
+//!
+//! let mut transmitter = MockTransmitter::new();
+//! transmitter.send_dmx_packet(&[0xff, 0x80]).unwrap();
+//!
+//! assert_eq!(transmitter.packet_count(), 1);
+//! transmitter.assert_channel(DmxAddress::new(2).unwrap(), 0x80);
+//! ```
+
+#[cfg(unix)]
+use std::collections::VecDeque;
+#[cfg(unix)]
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, MutexGuard};
+#[cfg(unix)]
+use std::{cmp, io, thread};
+use std::time;
+
+use crate::address::DmxAddress;
+use crate::rdm::{RdmReply, RdmRequest, RdmResponder, Uid, MAX_PACKET_LEN};
+#[cfg(unix)]
+use crate::DmxPort;
+use crate::{DmxTransceiver, DmxTransmitter, Error, Result};
+
+/// A packet recorded by a `MockTransmitter`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedPacket {
+    /// Time the break preceding the packet was sent.
+    pub timestamp: time::Instant,
+    /// Data sent, including the start code.
+    pub data: Vec<u8>,
+}
+
+impl RecordedPacket {
+    /// Returns the start code, if any data was sent.
+    #[inline]
+    pub fn start_code(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Returns the channels, i.e. all data after the start code.
+    #[inline]
+    pub fn channels(&self) -> &[u8] {
+        self.data.get(1..).unwrap_or(&[])
+    }
+}
+
+#[derive(Debug, Default)]
+struct Recording {
+    packets: Vec<RecordedPacket>,
+    failure: Option<Error>,
+}
+
+/// A transmitter recording all packets sent.
+///
+/// Every break starts a new packet, any data sent afterwards is appended to
+/// it. Clones share their recordings, so a clone can be handed to code that
+/// takes ownership of a transmitter, such as a `DmxRefresher`, while the
+/// original is used to inspect what was sent.
+#[derive(Clone, Debug, Default)]
+pub struct MockTransmitter {
+    recording: Arc<Mutex<Recording>>,
+}
+
+impl MockTransmitter {
+    /// Create a transmitter without any recorded packets.
+    #[inline]
+    pub fn new() -> MockTransmitter {
+        MockTransmitter::default()
+    }
+
+    /// Returns all recorded packets, oldest first.
+    #[inline]
+    pub fn packets(&self) -> Vec<RecordedPacket> {
+        self.lock().packets.clone()
+    }
+
+    /// Returns the most recent packet.
+    #[inline]
+    pub fn last_packet(&self) -> Option<RecordedPacket> {
+        self.lock().packets.last().cloned()
+    }
+
+    /// Returns the number of packets recorded.
+    #[inline]
+    pub fn packet_count(&self) -> usize {
+        self.lock().packets.len()
+    }
+
+    /// Discards all recorded packets.
+    #[inline]
+    pub fn clear(&self) {
+        self.lock().packets.clear()
+    }
+
+    /// Makes the next send fail with `error`.
+    #[inline]
+    pub fn fail_next(&self, error: Error) {
+        self.lock().failure = Some(error);
+    }
+
+    /// Asserts that channel `n` has `value` in the most recent packet with the
+    /// default start code.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel has a different value, was not sent, or no
+    /// packet with the default start code was recorded.
+    pub fn assert_channel(&self, n: DmxAddress, value: u8) {
+        let recording = self.lock();
+        let packet = recording
+            .packets
+            .iter()
+            .rev()
+            .find(|p| p.start_code() == Some(0x00))
+            .expect("no packet with the default start code recorded");
+
+        match packet.channels().get(n.index()) {
+            Some(&v) => assert_eq!(v, value, "unexpected value of channel {}", n),
+            None => panic!(
+                "channel {} not sent, last packet contained {} channels",
+                n,
+                packet.channels().len()
+            ),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Recording> {
+        // a failed assertion must not poison other clones
+        self.recording.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the pending failure, if any, or the recording.
+    fn record(&self) -> Result<MutexGuard<'_, Recording>> {
+        let mut recording = self.lock();
+
+        match recording.failure.take() {
+            Some(e) => Err(e),
+            None => Ok(recording),
+        }
+    }
+}
+
+impl DmxTransmitter for MockTransmitter {
+    type Error = Error;
+
+    fn send_break(&mut self) -> Result<()> {
+        self.record()?.packets.push(RecordedPacket {
+            timestamp: time::Instant::now(),
+            data: Vec::new(),
+        });
+
+        Ok(())
+    }
+
+    fn send_raw_data(&mut self, data: &[u8]) -> Result<()> {
+        let mut recording = self.record()?;
+
+        // data sent before the first break starts a packet of its own
+        if recording.packets.is_empty() {
+            recording.packets.push(RecordedPacket {
+                timestamp: time::Instant::now(),
+                data: Vec::new(),
+            });
+        }
+
+        if let Some(packet) = recording.packets.last_mut() {
+            packet.data.extend_from_slice(data);
+        }
+
+        Ok(())
+    }
+
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<()> {
+        self.record()?.packets.push(RecordedPacket {
+            timestamp: time::Instant::now(),
+            data: data.to_vec(),
+        });
+
+        Ok(())
+    }
+}
+
+/// A line with virtual RDM devices attached.
+///
+/// Every packet sent is handed to all responders, their replies are
+/// returned by the next `recv_raw_data`. Replies of several responders to
+/// the same request collide like on a real line, so discovery has to
+/// resolve them. See `RdmResponder` for an example.
+#[derive(Clone, Debug, Default)]
+pub struct MockRdmBus {
+    responders: Vec<RdmResponder>,
+    // packet being sent, handed to the responders on the next break
+    pending: Option<Vec<u8>>,
+    received: Vec<u8>,
+}
+
+impl MockRdmBus {
+    /// Create a line without any devices.
+    #[inline]
+    pub fn new() -> MockRdmBus {
+        MockRdmBus::default()
+    }
+
+    /// Attaches a device.
+    #[inline]
+    pub fn add(&mut self, responder: RdmResponder) {
+        self.responders.push(responder);
+    }
+
+    /// Returns all devices, in the order they were attached.
+    #[inline]
+    pub fn responders(&self) -> &[RdmResponder] {
+        &self.responders
+    }
+
+    /// Returns the device with the given UID.
+    #[inline]
+    pub fn responder(&self, uid: Uid) -> Option<&RdmResponder> {
+        self.responders.iter().find(|r| r.uid() == uid)
+    }
+
+    /// Returns the device with the given UID for changing it.
+    #[inline]
+    pub fn responder_mut(&mut self, uid: Uid) -> Option<&mut RdmResponder> {
+        self.responders.iter_mut().find(|r| r.uid() == uid)
+    }
+
+    /// Hands the packet being sent to the responders.
+    fn deliver(&mut self) {
+        let packet = match self.pending.take() {
+            Some(packet) => packet,
+            None => return,
+        };
+        let request = match RdmRequest::decode(&packet) {
+            Some(request) => request,
+            None => return,
+        };
+
+        let replies: Vec<Vec<u8>> = self
+            .responders
+            .iter_mut()
+            .filter_map(|r| r.handle(&request))
+            .map(|reply| match reply {
+                RdmReply::Response(response) => {
+                    let mut buf = [0; MAX_PACKET_LEN];
+                    let len = response.encode(&mut buf);
+                    buf[..len].to_vec()
+                }
+                RdmReply::Discovery(data) => data.to_vec(),
+            })
+            .collect();
+
+        match replies.len() {
+            0 => (),
+            1 => self.received.extend_from_slice(&replies[0]),
+            // drivers fighting over the line, nothing can be decoded
+            _ => {
+                let len = replies.iter().map(Vec::len).max().unwrap_or(0);
+                self.received.resize(self.received.len() + len, 0);
+            }
+        }
+    }
+}
+
+impl DmxTransmitter for MockRdmBus {
+    type Error = Error;
+
+    fn send_break(&mut self) -> Result<()> {
+        self.deliver();
+        self.pending = Some(Vec::new());
+
+        Ok(())
+    }
+
+    fn send_raw_data(&mut self, data: &[u8]) -> Result<()> {
+        self.pending.get_or_insert_with(Vec::new).extend_from_slice(data);
+
+        Ok(())
+    }
+
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<()> {
+        self.deliver();
+        self.pending = Some(data.to_vec());
+
+        Ok(())
+    }
+}
+
+impl DmxTransceiver for MockRdmBus {
+    fn discard_input(&mut self) -> Result<()> {
+        self.deliver();
+        self.received.clear();
+
+        Ok(())
+    }
+
+    fn recv_raw_data(&mut self, buf: &mut [u8], _timeout: time::Duration) -> Result<usize> {
+        self.deliver();
+
+        let len = self.received.len().min(buf.len());
+        buf[..len].copy_from_slice(&self.received[..len]);
+        self.received.drain(..len);
+
+        Ok(len)
+    }
+}
+
+/// Creates a `DmxPort` connected to a pseudo-terminal.
+///
+/// Everything the port writes is captured on the other end. Pseudo-terminals
+/// ignore baud rates, so the break the port generates by sending `0x00` at a
+/// slow baud rate shows up as a regular `0x00` byte in front of each packet.
+/// Breaks sent through `BreakMethod::Ioctl` are not visible at all.
+///
+/// ```
+/// use std::time::Duration;
+/// use dmx::DmxTransmitter;
+///
+/// let (mut port, mut capture) = dmx::testing::pty_loopback().unwrap();
+/// port.send_dmx_packet(&[0xff, 0x80]).unwrap();
+///
+/// let frame = capture.recv_frame(512, Duration::from_secs(1)).unwrap();
+/// assert_eq!(frame.start_code, 0x00);
+/// assert_eq!(frame.channels[..2], [0xff, 0x80]);
+/// ```
+#[cfg(unix)]
+pub fn pty_loopback() -> Result<(DmxPort, PtyCapture)> {
+    let (mut master, slave) = serial2::SerialPort::pair()?;
+    master.set_read_timeout(CAPTURE_POLL_INTERVAL)?;
+
+    let (tx, rx) = mpsc::channel();
+
+    // timestamp data as soon as it arrives, regardless of when it is read
+    thread::spawn(move || {
+        let mut buf = [0; 1024];
+
+        loop {
+            match master.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => {
+                    if tx.send((time::Instant::now(), buf[..len].to_vec())).is_err() {
+                        break;
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                    // stop once the capture has been dropped
+                    if tx.send((time::Instant::now(), Vec::new())).is_err() {
+                        break;
+                    }
+                }
+                // the other end has been closed
+                Err(_) => break,
+            }
+        }
+    });
+
+    let capture = PtyCapture {
+        rx,
+        chunks: VecDeque::new(),
+    };
+
+    Ok((DmxPort::from_serial_port(slave)?, capture))
+}
+
+// interval at which the capture thread checks whether it is still needed
+#[cfg(unix)]
+const CAPTURE_POLL_INTERVAL: time::Duration = time::Duration::from_millis(50);
+
+/// A frame captured by a `PtyCapture`.
+#[cfg(unix)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapturedFrame {
+    /// Time the break byte arrived.
+    pub timestamp: time::Instant,
+    /// Start code of the frame.
+    pub start_code: u8,
+    /// Channels of the frame.
+    pub channels: Vec<u8>,
+}
+
+/// Captures the output of a `DmxPort`, see `pty_loopback`.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct PtyCapture {
+    rx: mpsc::Receiver<(time::Instant, Vec<u8>)>,
+    // data received but not read yet, with its time of arrival
+    chunks: VecDeque<(time::Instant, Vec<u8>)>,
+}
+
+#[cfg(unix)]
+impl PtyCapture {
+    /// Reads the next frame, expecting it to hold `channels` channels.
+    ///
+    /// Fails with `Error::Timeout` if the frame is not complete within
+    /// `timeout`, or with `Error::InvalidResponse` if the data does not start
+    /// with a break byte.
+    pub fn recv_frame(
+        &mut self,
+        channels: usize,
+        timeout: time::Duration,
+    ) -> Result<CapturedFrame> {
+        let deadline = time::Instant::now() + timeout;
+
+        while self.buffered() < channels + 2 {
+            if !self.fill(deadline) {
+                return Err(Error::Timeout);
+            }
+        }
+
+        let timestamp = self.chunks[0].0;
+        let data = self.take(channels + 2);
+
+        if data[0] != 0x00 {
+            return Err(Error::InvalidResponse("frame does not start with a break byte"));
+        }
+
+        Ok(CapturedFrame {
+            timestamp,
+            start_code: data[1],
+            channels: data[2..].to_vec(),
+        })
+    }
+
+    /// Reads all data that arrives until the port has been idle for
+    /// `idle`.
+    pub fn recv_raw(&mut self, idle: time::Duration) -> Vec<u8> {
+        while self.fill(time::Instant::now() + idle) {}
+
+        let len = self.buffered();
+        self.take(len)
+    }
+
+    /// Discards all data received so far.
+    pub fn clear(&mut self) {
+        while self.fill(time::Instant::now()) {}
+        self.chunks.clear();
+    }
+
+    fn buffered(&self) -> usize {
+        self.chunks.iter().map(|(_, data)| data.len()).sum()
+    }
+
+    /// Waits for more data until `deadline`, returning whether any arrived.
+    fn fill(&mut self, deadline: time::Instant) -> bool {
+        loop {
+            let timeout = deadline.saturating_duration_since(time::Instant::now());
+
+            match self.rx.recv_timeout(timeout) {
+                Ok((_, ref data)) if data.is_empty() => continue,
+                Ok(chunk) => {
+                    self.chunks.push_back(chunk);
+                    return true;
+                }
+                Err(_) => return false,
+            }
+        }
+    }
+
+    fn take(&mut self, mut len: usize) -> Vec<u8> {
+        let mut data = Vec::with_capacity(len);
+
+        while len > 0 {
+            let (_, chunk) = &mut self.chunks[0];
+            let n = cmp::min(len, chunk.len());
+
+            data.extend(chunk.drain(..n));
+            if chunk.is_empty() {
+                self.chunks.pop_front();
+            }
+            len -= n;
+        }
+
+        data
+    }
+}
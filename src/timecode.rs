@@ -0,0 +1,672 @@
+//! Timecode-synchronized playback.
+//!
+//! Shows synchronized to audio or video follow a timecode, the position
+//! within the show sent by the playing device. A `TimecodeSource` provides
+//! the current position, e.g. MIDI Timecode (MTC) decoded by an
+//! `MtcDecoder`. With the `midi` feature, an `MtcReceiver` decodes MTC
+//! arriving at a MIDI input port.
+//!
+//! A `TimecodeCues` goes to cues of a `Playback` at programmed positions, a
+//! `TimecodePlayer` keeps a `DmxPlayer` at the position of the timecode.
+//! Both follow jumps of the timecode: after a jump, the cue programmed last
+//! before the new position is gone to, and the player seeks.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "midi")]
+//! # fn main() {
+//! use std::{thread, time};
+//! use dmx::DmxTransmitter;
+//! use dmx::scenes::{CueList, Playback};
+//! use dmx::timecode::{FrameRate, MtcReceiver, Timecode, TimecodeCues};
+//!
+//! # let cue_list = CueList::new();
+//! let mtc = MtcReceiver::connect("IAC Driver").unwrap();
+//!
+//! let mut cues = TimecodeCues::new(Playback::new(cue_list));
+//! cues.add(Timecode::new(0, 0, 10, 0, FrameRate::Fps25).unwrap(), 0);
+//! cues.add(Timecode::new(0, 1, 30, 12, FrameRate::Fps25).unwrap(), 1);
+//!
+//! let mut dmx_port = dmx::open_serial("/dev/ttyUSB0").unwrap();
+//! let mut universe = dmx::DmxUniverse::new();
+//! let period = time::Duration::from_millis(25);
+//!
+//! loop {
+//!     cues.tick(&mtc, period, &mut universe);
+//!     dmx_port.send_universe(&universe).unwrap();
+//!     thread::sleep(period);
+//! }
+//! # }
+//! # #[cfg(not(feature = "midi"))]
+//! # fn main() {}
+//! ```
+
+use std::{fmt, time};
+
+use crate::record::DmxPlayer;
+use crate::scenes::Playback;
+use crate::universe::DmxUniverse;
+use crate::DmxTransmitter;
+
+/// Time after the last quarter frame message at which MTC is considered
+/// stopped.
+const MTC_TIMEOUT: time::Duration = time::Duration::from_millis(100);
+
+/// Difference between the position of a timecode source and a player beyond
+/// which the player seeks instead of catching up.
+const MAX_DRIFT: time::Duration = time::Duration::from_millis(100);
+
+/// Frame rate of a timecode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FrameRate {
+    /// 24 frames per second, used for film.
+    Fps24,
+    /// 25 frames per second, used for PAL video.
+    Fps25,
+    /// 29.97 frames per second with drop-frame counting, used for NTSC
+    /// video.
+    ///
+    /// Frame numbers 0 and 1 are skipped at the start of every minute,
+    /// except for every tenth minute, keeping the timecode in line with the
+    /// clock.
+    Fps30Drop,
+    /// 30 frames per second.
+    Fps30,
+}
+
+impl FrameRate {
+    /// Returns the number of frames in a second of timecode.
+    #[inline]
+    pub fn nominal(self) -> u8 {
+        match self {
+            FrameRate::Fps24 => 24,
+            FrameRate::Fps25 => 25,
+            FrameRate::Fps30Drop | FrameRate::Fps30 => 30,
+        }
+    }
+
+    /// Returns the actual number of frames per second.
+    #[inline]
+    pub fn fps(self) -> f64 {
+        match self {
+            FrameRate::Fps30Drop => 30_000.0 / 1_001.0,
+            rate => f64::from(rate.nominal()),
+        }
+    }
+
+    /// Returns the rate encoded in the hours of an MTC message.
+    fn from_mtc(bits: u8) -> FrameRate {
+        match bits & 0x03 {
+            0 => FrameRate::Fps24,
+            1 => FrameRate::Fps25,
+            2 => FrameRate::Fps30Drop,
+            _ => FrameRate::Fps30,
+        }
+    }
+}
+
+/// A position in hours, minutes, seconds and frames.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Timecode {
+    hours: u8,
+    minutes: u8,
+    seconds: u8,
+    frames: u8,
+    rate: FrameRate,
+}
+
+impl Timecode {
+    /// Create a timecode.
+    ///
+    /// Returns `None` if a field is out of range, including frames skipped
+    /// by drop-frame counting.
+    pub fn new(
+        hours: u8,
+        minutes: u8,
+        seconds: u8,
+        frames: u8,
+        rate: FrameRate,
+    ) -> Option<Timecode> {
+        let dropped = rate == FrameRate::Fps30Drop
+            && seconds == 0
+            && frames < 2
+            && !minutes.is_multiple_of(10);
+
+        if hours >= 24 || minutes >= 60 || seconds >= 60 || frames >= rate.nominal() || dropped {
+            return None;
+        }
+
+        Some(Timecode {
+            hours,
+            minutes,
+            seconds,
+            frames,
+            rate,
+        })
+    }
+
+    /// Returns the timecode of frame `n`, counting from 00:00:00:00.
+    ///
+    /// Wraps around after 24 hours.
+    pub fn from_frame_number(mut n: u64, rate: FrameRate) -> Timecode {
+        let nominal = u64::from(rate.nominal());
+
+        if rate == FrameRate::Fps30Drop {
+            // 17,982 frames every ten minutes, 1,798 in all but the first
+            // minute of those
+            let (tens, rest) = (n / 17_982, n % 17_982);
+            n += 18 * tens + if rest < 2 { 0 } else { 2 * ((rest - 2) / 1_798) };
+        }
+
+        Timecode {
+            hours: (n / (nominal * 3_600) % 24) as u8,
+            minutes: (n / (nominal * 60) % 60) as u8,
+            seconds: (n / nominal % 60) as u8,
+            frames: (n % nominal) as u8,
+            rate,
+        }
+    }
+
+    /// Returns the timecode current at `position`, counting from
+    /// 00:00:00:00.
+    #[inline]
+    pub fn from_duration(position: time::Duration, rate: FrameRate) -> Timecode {
+        let n = position.as_secs_f64() * rate.fps();
+        // positions are rounded down to frames, allowing for tiny errors
+        Timecode::from_frame_number((n + 1e-6) as u64, rate)
+    }
+
+    /// Returns the number of frames since 00:00:00:00.
+    pub fn frame_number(&self) -> u64 {
+        let nominal = u64::from(self.rate.nominal());
+        let minutes = u64::from(self.hours) * 60 + u64::from(self.minutes);
+        let n = (minutes * 60 + u64::from(self.seconds)) * nominal + u64::from(self.frames);
+
+        match self.rate {
+            FrameRate::Fps30Drop => n - 2 * (minutes - minutes / 10),
+            _ => n,
+        }
+    }
+
+    /// Returns the time since 00:00:00:00.
+    #[inline]
+    pub fn to_duration(&self) -> time::Duration {
+        time::Duration::from_secs_f64(self.frame_number() as f64 / self.rate.fps())
+    }
+
+    /// Returns the hours.
+    #[inline]
+    pub fn hours(&self) -> u8 {
+        self.hours
+    }
+
+    /// Returns the minutes.
+    #[inline]
+    pub fn minutes(&self) -> u8 {
+        self.minutes
+    }
+
+    /// Returns the seconds.
+    #[inline]
+    pub fn seconds(&self) -> u8 {
+        self.seconds
+    }
+
+    /// Returns the frames.
+    #[inline]
+    pub fn frames(&self) -> u8 {
+        self.frames
+    }
+
+    /// Returns the frame rate.
+    #[inline]
+    pub fn rate(&self) -> FrameRate {
+        self.rate
+    }
+}
+
+impl fmt::Display for Timecode {
+    /// Formats as `HH:MM:SS:FF`, with a semicolon before the frames for
+    /// drop-frame timecode.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let separator = if self.rate == FrameRate::Fps30Drop { ';' } else { ':' };
+
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours, self.minutes, self.seconds, separator, self.frames
+        )
+    }
+}
+
+impl From<Timecode> for time::Duration {
+    #[inline]
+    fn from(timecode: Timecode) -> time::Duration {
+        timecode.to_duration()
+    }
+}
+
+/// Provides the current position of a timecode.
+///
+/// Implemented for closures returning the position as well, which are
+/// always considered running.
+pub trait TimecodeSource {
+    /// Returns the current position, or `None` if no timecode has been
+    /// received yet.
+    fn position(&self) -> Option<time::Duration>;
+
+    /// Returns whether the timecode is advancing.
+    ///
+    /// Stopped timecode still has a position, which it was located to or
+    /// stopped at.
+    fn is_running(&self) -> bool;
+}
+
+impl<F> TimecodeSource for F
+where
+    F: Fn() -> Option<time::Duration>,
+{
+    #[inline]
+    fn position(&self) -> Option<time::Duration> {
+        self()
+    }
+
+    #[inline]
+    fn is_running(&self) -> bool {
+        self().is_some()
+    }
+}
+
+/// Decoder of MIDI Timecode.
+///
+/// Running timecode is sent as quarter frame messages, eight of which make
+/// up a complete timecode; it is considered stopped once they stop
+/// arriving. Full frame messages, sent when the timecode is located, set
+/// the position right away. Between messages, the position is advanced by
+/// the time passed.
+#[derive(Clone, Debug, Default)]
+pub struct MtcDecoder {
+    // nibbles of the timecode being assembled from quarter frames
+    pieces: [u8; 8],
+    // bit n is set once piece n was received in order
+    received: u8,
+    // last decoded timecode, when it was current and whether it is running
+    last: Option<(Timecode, time::Instant, bool)>,
+}
+
+impl MtcDecoder {
+    /// Create a decoder that has not received any timecode yet.
+    #[inline]
+    pub fn new() -> MtcDecoder {
+        MtcDecoder::default()
+    }
+
+    /// Processes a MIDI message received at `now`.
+    ///
+    /// Returns the timecode if the message completed one. Messages other
+    /// than MTC are ignored.
+    pub fn feed(&mut self, message: &[u8], now: time::Instant) -> Option<Timecode> {
+        match *message {
+            [0xf1, data, ..] => self.quarter_frame(data, now),
+            [0xf0, 0x7f, _, 0x01, 0x01, hours, minutes, seconds, frames, 0xf7] => {
+                let rate = FrameRate::from_mtc(hours >> 5);
+                let timecode = Timecode::new(hours & 0x1f, minutes, seconds, frames, rate)?;
+
+                // a locate, the timecode stands still until quarter frames
+                // arrive
+                self.received = 0;
+                self.last = Some((timecode, now, false));
+                Some(timecode)
+            }
+            _ => None,
+        }
+    }
+
+    fn quarter_frame(&mut self, data: u8, now: time::Instant) -> Option<Timecode> {
+        let (piece, value) = ((data >> 4) & 0x07, data & 0x0f);
+
+        // pieces have to arrive in order, starting with the frames
+        if piece == 0 {
+            self.received = 0;
+        }
+        if self.received != (1 << piece) - 1 {
+            self.received = 0;
+            return None;
+        }
+        self.pieces[usize::from(piece)] = value;
+        self.received |= 1 << piece;
+
+        if piece != 7 {
+            // keep running between complete timecodes
+            if let Some((_, _, ref mut running)) = self.last {
+                *running = true;
+            }
+            return None;
+        }
+        self.received = 0;
+
+        let [fl, fh, sl, sh, ml, mh, hl, hh] = self.pieces;
+        let rate = FrameRate::from_mtc(hh >> 1);
+        let timecode = Timecode::new(
+            (hh & 0x01) << 4 | hl,
+            mh << 4 | ml,
+            sh << 4 | sl,
+            fh << 4 | fl,
+            rate,
+        )?;
+
+        // the timecode was current when its first piece was sent, two
+        // frames ago
+        let n = timecode.frame_number() + 2;
+        let timecode = Timecode::from_frame_number(n, rate);
+        self.last = Some((timecode, now, true));
+        Some(timecode)
+    }
+
+    /// Returns the last timecode decoded.
+    #[inline]
+    pub fn timecode(&self) -> Option<Timecode> {
+        self.last.map(|(timecode, _, _)| timecode)
+    }
+
+    /// Returns whether quarter frames arrived recently, as of `now`.
+    pub fn is_running_at(&self, now: time::Instant) -> bool {
+        match self.last {
+            Some((_, at, running)) => running && now.saturating_duration_since(at) < MTC_TIMEOUT,
+            None => false,
+        }
+    }
+
+    /// Returns the position as of `now`, advanced by the time passed since
+    /// the last timecode while running.
+    pub fn position_at(&self, now: time::Instant) -> Option<time::Duration> {
+        let (timecode, at, _) = self.last?;
+        let position = timecode.to_duration();
+
+        if self.is_running_at(now) {
+            Some(position + now.saturating_duration_since(at))
+        } else {
+            Some(position)
+        }
+    }
+}
+
+impl TimecodeSource for MtcDecoder {
+    #[inline]
+    fn position(&self) -> Option<time::Duration> {
+        self.position_at(time::Instant::now())
+    }
+
+    #[inline]
+    fn is_running(&self) -> bool {
+        self.is_running_at(time::Instant::now())
+    }
+}
+
+/// MIDI Timecode received through a MIDI input port.
+///
+/// Requires the `midi` feature. Messages are decoded from a thread of the
+/// MIDI backend as they arrive. Dropping the receiver closes the
+/// connection.
+#[cfg(feature = "midi")]
+pub struct MtcReceiver {
+    connection: midir::MidiInputConnection<()>,
+    decoder: std::sync::Arc<std::sync::Mutex<MtcDecoder>>,
+    port: String,
+}
+
+#[cfg(feature = "midi")]
+impl MtcReceiver {
+    /// Connects to the first input port whose name contains `port`.
+    ///
+    /// Fails with `Error::InvalidParameter` if there is no such port. See
+    /// `MidiDmxBridge::ports` for the names of all ports.
+    pub fn connect(port: &str) -> crate::Result<MtcReceiver> {
+        use std::sync::{Arc, Mutex};
+
+        use crate::midi::{midi_error, CLIENT_NAME};
+        use crate::Error;
+
+        let mut input = midir::MidiInput::new(CLIENT_NAME).map_err(midi_error)?;
+        // quarter frames are time messages, full frames system exclusive
+        input.ignore(midir::Ignore::ActiveSense);
+
+        let (found, name) = input
+            .ports()
+            .into_iter()
+            .filter_map(|p| input.port_name(&p).ok().map(|name| (p, name)))
+            .find(|(_, name)| name.contains(port))
+            .ok_or(Error::InvalidParameter("no such MIDI input port"))?;
+
+        let decoder = Arc::new(Mutex::new(MtcDecoder::new()));
+        let connection = {
+            let decoder = decoder.clone();
+
+            input
+                .connect(
+                    &found,
+                    CLIENT_NAME,
+                    move |_, message, _| {
+                        let now = time::Instant::now();
+                        lock(&decoder).feed(message, now);
+                    },
+                    (),
+                )
+                .map_err(|e| midi_error(e.kind()))?
+        };
+
+        Ok(MtcReceiver {
+            connection,
+            decoder,
+            port: name,
+        })
+    }
+
+    /// Returns the last timecode received.
+    #[inline]
+    pub fn timecode(&self) -> Option<Timecode> {
+        lock(&self.decoder).timecode()
+    }
+
+    /// Returns the name of the connected port.
+    #[inline]
+    pub fn port(&self) -> &str {
+        &self.port
+    }
+
+    /// Closes the connection.
+    #[inline]
+    pub fn close(self) {
+        self.connection.close();
+    }
+}
+
+#[cfg(feature = "midi")]
+fn lock(decoder: &std::sync::Mutex<MtcDecoder>) -> std::sync::MutexGuard<'_, MtcDecoder> {
+    // the decoder stays consistent even if a panic occurred while feeding it
+    decoder.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(feature = "midi")]
+impl TimecodeSource for MtcReceiver {
+    #[inline]
+    fn position(&self) -> Option<time::Duration> {
+        lock(&self.decoder).position()
+    }
+
+    #[inline]
+    fn is_running(&self) -> bool {
+        lock(&self.decoder).is_running()
+    }
+}
+
+#[cfg(feature = "midi")]
+impl fmt::Debug for MtcReceiver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MtcReceiver")
+            .field("port", &self.port)
+            .field("timecode", &self.timecode())
+            .finish()
+    }
+}
+
+/// Goes to cues of a playback at programmed timecode positions.
+#[derive(Clone, Debug)]
+pub struct TimecodeCues {
+    playback: Playback,
+    // positions and cue indices, ordered by position
+    triggers: Vec<(time::Duration, usize)>,
+    // position as of the last tick
+    last: Option<time::Duration>,
+}
+
+impl TimecodeCues {
+    /// Create an empty program for `playback`.
+    #[inline]
+    pub fn new(playback: Playback) -> TimecodeCues {
+        TimecodeCues {
+            playback,
+            triggers: Vec::new(),
+            last: None,
+        }
+    }
+
+    /// Goes to cue `index` once the timecode reaches `at`.
+    pub fn add<P: Into<time::Duration>>(&mut self, at: P, index: usize) {
+        let at = at.into();
+        let pos = self.triggers.partition_point(|&(t, _)| t <= at);
+        self.triggers.insert(pos, (at, index));
+    }
+
+    /// Returns the programmed positions and cues, ordered by position.
+    #[inline]
+    pub fn triggers(&self) -> &[(time::Duration, usize)] {
+        &self.triggers
+    }
+
+    /// Returns the playback.
+    #[inline]
+    pub fn playback(&self) -> &Playback {
+        &self.playback
+    }
+
+    /// Returns the playback mutably, e.g. to go to cues manually.
+    #[inline]
+    pub fn playback_mut(&mut self) -> &mut Playback {
+        &mut self.playback
+    }
+
+    /// Goes to the cues reached since the last tick, then advances the
+    /// playback by `dt` and writes its output into `universe`.
+    ///
+    /// If the timecode jumped back or more than `dt` ahead, only the cue
+    /// programmed last before the new position is gone to. Returns the cue
+    /// gone to, if any.
+    pub fn tick<S: TimecodeSource + ?Sized>(
+        &mut self,
+        source: &S,
+        dt: time::Duration,
+        universe: &mut DmxUniverse,
+    ) -> Option<usize> {
+        let triggered = source.position().and_then(|position| self.follow(position, dt));
+
+        self.playback.tick(dt, universe);
+        triggered
+    }
+
+    fn follow(&mut self, position: time::Duration, dt: time::Duration) -> Option<usize> {
+        let end = self.triggers.partition_point(|&(t, _)| t <= position);
+
+        let start = match self.last.replace(position) {
+            // moving on regularly, with some slack for jitter of the source
+            Some(last) if last <= position && position - last <= dt + MAX_DRIFT => {
+                self.triggers.partition_point(|&(t, _)| t <= last)
+            }
+            // jumped, chase the cue current at the new position
+            _ => end.saturating_sub(1),
+        };
+
+        let &(_, index) = self.triggers[start.min(end)..end].last()?;
+        if self.playback.current() == Some(index) {
+            return None;
+        }
+
+        self.playback.go_to(index);
+        Some(index)
+    }
+}
+
+/// Keeps a `DmxPlayer` at the position of a timecode.
+///
+/// The recording starts at `offset`, i.e. its first frame is sent once the
+/// timecode reaches it. Playback pauses while the timecode is stopped.
+#[derive(Clone, Debug)]
+pub struct TimecodePlayer {
+    player: DmxPlayer,
+    offset: time::Duration,
+}
+
+impl TimecodePlayer {
+    /// Create a synchronized player, starting the recording at timecode
+    /// position `offset`.
+    #[inline]
+    pub fn new<P: Into<time::Duration>>(player: DmxPlayer, offset: P) -> TimecodePlayer {
+        TimecodePlayer {
+            player,
+            offset: offset.into(),
+        }
+    }
+
+    /// Returns the player.
+    #[inline]
+    pub fn player(&self) -> &DmxPlayer {
+        &self.player
+    }
+
+    /// Returns the player, releasing it from the timecode.
+    #[inline]
+    pub fn into_inner(self) -> DmxPlayer {
+        self.player
+    }
+
+    /// Moves the player to the position of `source`, sending all frames
+    /// that became due.
+    ///
+    /// Seeks if the player is off by more than 100 ms, e.g. after the
+    /// timecode jumped, sending the frame current at the new position.
+    /// Before `offset`, nothing is sent.
+    pub fn advance<S, T>(
+        &mut self,
+        source: &S,
+        transmitter: &mut T,
+    ) -> core::result::Result<(), T::Error>
+    where
+        S: TimecodeSource + ?Sized,
+        T: DmxTransmitter,
+    {
+        let target = match source.position() {
+            Some(position) if position >= self.offset => position - self.offset,
+            _ => return Ok(()),
+        };
+
+        if source.is_running() {
+            self.player.resume();
+        }
+
+        let current = self.player.position();
+        if target >= current && target - current <= MAX_DRIFT {
+            self.player
+                .advance((target - current).div_f64(self.player.speed()), transmitter)?;
+        } else if current.abs_diff(target) > MAX_DRIFT {
+            self.player.seek(target);
+            self.player.resume();
+            self.player.advance(time::Duration::ZERO, transmitter)?;
+        }
+
+        if !source.is_running() {
+            self.player.pause();
+        }
+        Ok(())
+    }
+}
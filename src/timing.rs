@@ -0,0 +1,195 @@
+//! Packet timing.
+
+use core::{fmt, time};
+
+/// Minimum break duration for transmitters, in microseconds.
+pub const MIN_BREAK_US: u32 = 92;
+
+/// Minimum mark-after-break duration for transmitters, in microseconds.
+pub const MIN_MAB_US: u32 = 12;
+
+/// Minimum time between two consecutive breaks, in microseconds.
+pub const MIN_BREAK_TO_BREAK_US: u32 = 1204;
+
+/// Upper limit of the mark time between slots and the mark-before-break, in
+/// microseconds.
+pub const MAX_MARK_US: u32 = 999_999;
+
+/// Timing parameters for DMX transmission.
+///
+/// All durations are minimums; operating system scheduling will usually
+/// cause them to be exceeded somewhat, which DMX receivers tolerate.
+///
+/// The defaults match the break produced by switching to 57,600 baud, see
+/// `BreakMethod::BaudRate`, and send slots back to back.
+///
+/// Some cheap receivers drop slots sent at full speed or miss breaks that
+/// follow the last slot too closely. They are accommodated by idling
+/// between slots and before breaks, at the expense of the frame rate. Both
+/// are honored by `DmxPort` only:
+///
+/// ```
+/// use dmx::DmxTiming;
+///
+/// let timing = DmxTiming {
+///     mtbs_us: 20,
+///     mbb_us: 100,
+///     ..DmxTiming::default()
+/// };
+/// assert!(timing.validate().is_ok());
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DmxTiming {
+    /// Duration of the break, in microseconds.
+    pub break_us: u32,
+    /// Duration of the mark-after-break, in microseconds.
+    pub mab_us: u32,
+    /// Minimum time from one break to the next, in microseconds.
+    pub inter_frame_us: u32,
+    /// Mark time between slots, the line idling after each slot, in
+    /// microseconds.
+    ///
+    /// Slots are written one at a time if non-zero, which costs a system
+    /// call per slot.
+    pub mtbs_us: u32,
+    /// Minimum mark-before-break, the line idling from the end of a packet
+    /// until the next break, in microseconds.
+    pub mbb_us: u32,
+}
+
+impl DmxTiming {
+    /// Create a new set of timing parameters.
+    ///
+    /// Slots are sent back to back, without a mark-before-break. Fails if
+    /// any parameter is below the minimum allowed by the standard.
+    pub fn new(break_us: u32, mab_us: u32, inter_frame_us: u32) -> Result<DmxTiming, TimingError> {
+        let timing = DmxTiming {
+            break_us,
+            mab_us,
+            inter_frame_us,
+            mtbs_us: 0,
+            mbb_us: 0,
+        };
+
+        timing.validate()?;
+        Ok(timing)
+    }
+
+    /// Checks the parameters against the limits of the standard.
+    pub fn validate(&self) -> Result<(), TimingError> {
+        if self.break_us < MIN_BREAK_US {
+            return Err(TimingError::BreakTooShort(self.break_us));
+        }
+
+        if self.mab_us < MIN_MAB_US {
+            return Err(TimingError::MabTooShort(self.mab_us));
+        }
+
+        if self.inter_frame_us < MIN_BREAK_TO_BREAK_US {
+            return Err(TimingError::InterFrameTooShort(self.inter_frame_us));
+        }
+
+        if self.mtbs_us > MAX_MARK_US {
+            return Err(TimingError::MtbsTooLong(self.mtbs_us));
+        }
+
+        if self.mbb_us > MAX_MARK_US {
+            return Err(TimingError::MbbTooLong(self.mbb_us));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the break duration.
+    #[inline]
+    pub fn break_duration(&self) -> time::Duration {
+        time::Duration::from_micros(self.break_us.into())
+    }
+
+    /// Returns the mark-after-break duration.
+    #[inline]
+    pub fn mab_duration(&self) -> time::Duration {
+        time::Duration::from_micros(self.mab_us.into())
+    }
+
+    /// Returns the minimum break-to-break duration.
+    #[inline]
+    pub fn inter_frame_duration(&self) -> time::Duration {
+        time::Duration::from_micros(self.inter_frame_us.into())
+    }
+
+    /// Returns the mark time between slots.
+    #[inline]
+    pub fn mtbs_duration(&self) -> time::Duration {
+        time::Duration::from_micros(self.mtbs_us.into())
+    }
+
+    /// Returns the minimum mark-before-break duration.
+    #[inline]
+    pub fn mbb_duration(&self) -> time::Duration {
+        time::Duration::from_micros(self.mbb_us.into())
+    }
+}
+
+impl Default for DmxTiming {
+    #[inline]
+    fn default() -> DmxTiming {
+        DmxTiming {
+            break_us: 138,
+            mab_us: 16,
+            inter_frame_us: MIN_BREAK_TO_BREAK_US,
+            mtbs_us: 0,
+            mbb_us: 0,
+        }
+    }
+}
+
+/// Invalid timing parameters.
+///
+/// Each variant holds the offending value, in microseconds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimingError {
+    /// The break is shorter than 92 microseconds.
+    BreakTooShort(u32),
+    /// The mark-after-break is shorter than 12 microseconds.
+    MabTooShort(u32),
+    /// The break-to-break time is shorter than 1204 microseconds.
+    InterFrameTooShort(u32),
+    /// The mark time between slots is a second or longer.
+    MtbsTooLong(u32),
+    /// The mark-before-break is a second or longer.
+    MbbTooLong(u32),
+}
+
+impl fmt::Display for TimingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimingError::BreakTooShort(v) => {
+                write!(f, "break of {} us is below minimum of {} us", v, MIN_BREAK_US)
+            }
+            TimingError::MabTooShort(v) => write!(
+                f,
+                "mark-after-break of {} us is below minimum of {} us",
+                v, MIN_MAB_US
+            ),
+            TimingError::InterFrameTooShort(v) => write!(
+                f,
+                "break-to-break time of {} us is below minimum of {} us",
+                v, MIN_BREAK_TO_BREAK_US
+            ),
+            TimingError::MtbsTooLong(v) => write!(
+                f,
+                "mark time between slots of {} us is above maximum of {} us",
+                v, MAX_MARK_US
+            ),
+            TimingError::MbbTooLong(v) => write!(
+                f,
+                "mark-before-break of {} us is above maximum of {} us",
+                v, MAX_MARK_US
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TimingError {}
@@ -0,0 +1,125 @@
+//! Anyma uDMX support.
+//!
+//! The uDMX is a minimal USB interface built around an AVR microcontroller,
+//! which keeps its own copy of the universe and continuously transmits it.
+//! Channel values are updated through USB vendor control requests.
+//!
+//! Requires the `udmx` feature.
+
+use std::{io, time};
+
+use crate::{DmxAddress, DmxTransmitter, Error, Result};
+
+/// USB vendor ID of the uDMX.
+pub const UDMX_VENDOR_ID: u16 = 0x16c0;
+
+/// USB product ID of the uDMX.
+pub const UDMX_PRODUCT_ID: u16 = 0x05dc;
+
+/// Vendor request: set a single channel.
+pub const CMD_SET_SINGLE_CHANNEL: u8 = 1;
+
+/// Vendor request: set a range of channels.
+pub const CMD_SET_CHANNEL_RANGE: u8 = 2;
+
+const TIMEOUT: time::Duration = time::Duration::from_millis(500);
+
+/// Converts a libusb error into an I/O error.
+fn usb_error(e: rusb::Error) -> io::Error {
+    let kind = match e {
+        rusb::Error::NoDevice | rusb::Error::NotFound => io::ErrorKind::NotFound,
+        rusb::Error::Access => io::ErrorKind::PermissionDenied,
+        rusb::Error::Timeout => io::ErrorKind::TimedOut,
+        rusb::Error::Busy => io::ErrorKind::ResourceBusy,
+        rusb::Error::InvalidParam => io::ErrorKind::InvalidInput,
+        rusb::Error::NotSupported => io::ErrorKind::Unsupported,
+        _ => io::ErrorKind::Other,
+    };
+
+    io::Error::new(kind, e)
+}
+
+/// A uDMX interface.
+///
+/// As the interface transmits on its own, `send_break` does nothing and only
+/// complete packets with the default start code can be sent.
+pub struct UDmx {
+    handle: rusb::DeviceHandle<rusb::GlobalContext>,
+}
+
+impl UDmx {
+    /// Opens the first uDMX found.
+    pub fn open() -> io::Result<UDmx> {
+        let handle = rusb::open_device_with_vid_pid(UDMX_VENDOR_ID, UDMX_PRODUCT_ID)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no uDMX found"))?;
+
+        Ok(UDmx { handle })
+    }
+
+    /// Create an interface from an already opened USB device.
+    #[inline]
+    pub fn from_handle(handle: rusb::DeviceHandle<rusb::GlobalContext>) -> UDmx {
+        UDmx { handle }
+    }
+
+    fn control(&self, request: u8, value: u16, index: u16, data: &[u8]) -> Result<()> {
+        let request_type = rusb::request_type(
+            rusb::Direction::Out,
+            rusb::RequestType::Vendor,
+            rusb::Recipient::Device,
+        );
+
+        self.handle
+            .write_control(request_type, request, value, index, data, TIMEOUT)
+            .map_err(usb_error)?;
+        Ok(())
+    }
+
+    /// Sets channel `n` to `value`.
+    pub fn set_channel(&mut self, n: DmxAddress, value: u8) -> Result<()> {
+        self.control(CMD_SET_SINGLE_CHANNEL, u16::from(value), n.index() as u16, &[])
+    }
+
+    /// Sets consecutive channels, starting at channel `start`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `values` extend beyond channel 512.
+    pub fn set_channels(&mut self, start: DmxAddress, values: &[u8]) -> Result<()> {
+        assert!(start.index() + values.len() <= 512, "channel range exceeds 512");
+
+        if values.is_empty() {
+            return Ok(());
+        }
+
+        self.control(
+            CMD_SET_CHANNEL_RANGE,
+            values.len() as u16,
+            start.index() as u16,
+            values,
+        )
+    }
+}
+
+impl DmxTransmitter for UDmx {
+    type Error = Error;
+
+    #[inline]
+    fn send_break(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn send_raw_data(&mut self, _data: &[u8]) -> Result<()> {
+        Err(Error::Unsupported("the uDMX can only transmit complete packets"))
+    }
+
+    fn send_raw_dmx_packet(&mut self, data: &[u8]) -> Result<()> {
+        match data.first() {
+            Some(&0x00) if data.len() <= 513 => self.set_channels(DmxAddress::MIN, &data[1..]),
+            Some(&0x00) => Err(Error::PacketTooLong(data.len())),
+            Some(&code) => Err(Error::UnsupportedStartCode(code)),
+            None => Err(Error::EmptyPacket),
+        }
+    }
+}
@@ -0,0 +1,445 @@
+//! DMX universes.
+
+use core::{cmp, fmt, ops, time};
+
+use crate::address::DmxAddress;
+use crate::fade::{micros, ChannelFade, Easing};
+use crate::packet::{DmxPacket, MAX_CHANNELS};
+
+/// A DMX universe.
+///
+/// Holds the state of all 512 channels of a DMX bus. Applications usually
+/// keep a universe around, modify it as required and periodically hand it to
+/// a transmitter using `DmxTransmitter::send_universe`.
+///
+/// Like packets, channels are addressed by `DmxAddress`, starting at 1.
+///
+/// Channels can also be faded to a new value over time, see `fade_channel`.
+/// Universes compare equal if their channel values are equal, regardless of
+/// any fades in progress.
+#[derive(Clone)]
+pub struct DmxUniverse {
+    channels: [u8; MAX_CHANNELS],
+    fades: [ChannelFade; MAX_CHANNELS],
+}
+
+impl DmxUniverse {
+    /// Create a new universe with all channels set to zero.
+    #[inline]
+    pub fn new() -> DmxUniverse {
+        DmxUniverse {
+            channels: [0; MAX_CHANNELS],
+            fades: [ChannelFade::default(); MAX_CHANNELS],
+        }
+    }
+
+    /// Returns the value of channel `n`.
+    #[inline]
+    pub fn get(&self, n: DmxAddress) -> u8 {
+        self.channels[n.index()]
+    }
+
+    /// Sets channel `n` to `value`.
+    ///
+    /// Cancels a fade in progress on the channel.
+    #[inline]
+    pub fn set(&mut self, n: DmxAddress, value: u8) {
+        let i = n.index();
+        self.channels[i] = value;
+        self.fades[i] = ChannelFade::default();
+    }
+
+    /// Sets consecutive channels, starting at channel `start`.
+    ///
+    /// Values that would end up beyond channel 512 are ignored. Cancels fades
+    /// in progress on all channels set.
+    pub fn set_range(&mut self, start: DmxAddress, values: &[u8]) {
+        let offset = start.index();
+        let count = cmp::min(values.len(), MAX_CHANNELS - offset);
+
+        self.channels[offset..(offset + count)].copy_from_slice(&values[..count]);
+        for fade in &mut self.fades[offset..(offset + count)] {
+            *fade = ChannelFade::default();
+        }
+    }
+
+    /// Sets all channels to `value`.
+    ///
+    /// Cancels all fades in progress.
+    #[inline]
+    pub fn fill(&mut self, value: u8) {
+        for v in self.channels.iter_mut() {
+            *v = value;
+        }
+        self.cancel_fades();
+    }
+
+    /// Returns the value of a 16-bit channel.
+    #[inline]
+    pub fn get_channel_16(&self, channel: Channel16) -> u16 {
+        u16::from_be_bytes([self.get(channel.coarse), self.get(channel.fine)])
+    }
+
+    /// Sets a 16-bit channel to `value`.
+    ///
+    /// The high byte is written to the coarse channel, the low byte to the
+    /// fine channel.
+    #[inline]
+    pub fn set_channel_16(&mut self, channel: Channel16, value: u16) {
+        let [coarse, fine] = value.to_be_bytes();
+        self.set(channel.coarse, coarse);
+        self.set(channel.fine, fine);
+    }
+
+    /// Fades channel `n` linearly from its current value to `target`.
+    ///
+    /// The fade progresses with every call to `tick` and completes after
+    /// `duration`. Replaces any fade in progress on the channel; a zero
+    /// duration sets the channel right away.
+    #[inline]
+    pub fn fade_channel(&mut self, n: DmxAddress, target: u8, duration: time::Duration) {
+        self.fade_channel_with(n, target, duration, Easing::Linear)
+    }
+
+    /// Fades channel `n` to `target`, following an easing curve.
+    ///
+    /// See `fade_channel`.
+    pub fn fade_channel_with(
+        &mut self,
+        n: DmxAddress,
+        target: u8,
+        duration: time::Duration,
+        easing: Easing,
+    ) {
+        let i = n.index();
+        let fade = ChannelFade::new(self.channels[i], target, duration, easing);
+
+        if !fade.is_active() {
+            self.channels[i] = target;
+        }
+        self.fades[i] = fade;
+    }
+
+    /// Advances all fades in progress by `dt`.
+    ///
+    /// Usually called once per frame, right before sending the universe.
+    #[inline]
+    pub fn tick(&mut self, dt: time::Duration) {
+        self.advance_fades(dt);
+    }
+
+    /// Advances all fades, returning whether any channel value changed.
+    fn advance_fades(&mut self, dt: time::Duration) -> bool {
+        let dt = micros(dt);
+        let mut changed = false;
+
+        for (v, fade) in self.channels.iter_mut().zip(self.fades.iter_mut()) {
+            if fade.is_active() {
+                let value = fade.advance(dt);
+                changed |= value != *v;
+                *v = value;
+            }
+        }
+
+        changed
+    }
+
+    /// Returns whether any channel is fading.
+    #[inline]
+    pub fn is_fading(&self) -> bool {
+        self.fades.iter().any(ChannelFade::is_active)
+    }
+
+    /// Stops all fades, keeping the current channel values.
+    #[inline]
+    pub fn cancel_fades(&mut self) {
+        for fade in self.fades.iter_mut() {
+            *fade = ChannelFade::default();
+        }
+    }
+
+    /// Sets all channels to zero.
+    #[inline]
+    pub fn blackout(&mut self) {
+        self.fill(0);
+    }
+
+    /// Returns all channel values.
+    #[inline]
+    pub fn channels(&self) -> &[u8] {
+        &self.channels
+    }
+
+    /// Returns all channel values mutably.
+    ///
+    /// Fades in progress continue to overwrite the channels they affect.
+    #[inline]
+    pub fn channels_mut(&mut self) -> &mut [u8] {
+        &mut self.channels
+    }
+
+    /// Create a packet with the default start code from the universe.
+    #[inline]
+    pub fn to_packet(&self) -> DmxPacket {
+        DmxPacket::from_channels(&self.channels)
+    }
+}
+
+impl Default for DmxUniverse {
+    #[inline]
+    fn default() -> DmxUniverse {
+        DmxUniverse::new()
+    }
+}
+
+impl PartialEq for DmxUniverse {
+    #[inline]
+    fn eq(&self, other: &DmxUniverse) -> bool {
+        self.channels == other.channels
+    }
+}
+
+impl Eq for DmxUniverse {}
+
+impl fmt::Debug for DmxUniverse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DmxUniverse")
+            .field("channels", &&self.channels[..])
+            .finish()
+    }
+}
+
+/// A universe tracking whether it changed since it was last sent.
+///
+/// Wraps a `DmxUniverse`, which can be read through `Deref`, and marks
+/// itself dirty whenever a change alters a channel value. Setting a channel
+/// to its current value does not count as a change.
+///
+/// Fixtures only need an occasional keep-alive frame while nothing changes,
+/// so applications can lower the frame rate when idle and send at full rate
+/// while the universe is dirty, using less CPU time and bus bandwidth.
+///
+/// ```no_run
+/// use std::time::{Duration, Instant};
+/// use dmx::{DmxAddress, DmxTransmitter, TrackedUniverse};
+///
+/// let mut port = dmx::open_serial("/dev/ttyS1").unwrap();
+/// let mut universe = TrackedUniverse::new();
+/// let mut last_sent = Instant::now();
+///
+/// loop {
+///     universe.set(DmxAddress::MIN, 0xff);
+///
+///     // one frame per second is enough to keep fixtures from timing out
+///     if universe.take_dirty() || last_sent.elapsed() >= Duration::from_secs(1) {
+///         port.send_universe(&universe).unwrap();
+///         last_sent = Instant::now();
+///     }
+///
+///     std::thread::sleep(Duration::from_millis(25));
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrackedUniverse {
+    universe: DmxUniverse,
+    dirty: bool,
+}
+
+impl TrackedUniverse {
+    /// Create a new universe with all channels set to zero.
+    ///
+    /// A new universe is dirty, as it has not been sent yet.
+    #[inline]
+    pub fn new() -> TrackedUniverse {
+        TrackedUniverse::from(DmxUniverse::new())
+    }
+
+    /// Returns whether any channel changed since the last `take_dirty`.
+    #[inline]
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Returns whether any channel changed and clears the flag.
+    ///
+    /// Usually called right before sending the universe.
+    #[inline]
+    pub fn take_dirty(&mut self) -> bool {
+        core::mem::replace(&mut self.dirty, false)
+    }
+
+    /// Marks the universe as changed, e.g. to send it again after the
+    /// transmitter was reconnected.
+    #[inline]
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Sets channel `n` to `value`.
+    ///
+    /// See `DmxUniverse::set`.
+    #[inline]
+    pub fn set(&mut self, n: DmxAddress, value: u8) {
+        self.dirty |= self.universe.get(n) != value;
+        self.universe.set(n, value);
+    }
+
+    /// Sets consecutive channels, starting at channel `start`.
+    ///
+    /// See `DmxUniverse::set_range`.
+    pub fn set_range(&mut self, start: DmxAddress, values: &[u8]) {
+        let offset = start.index();
+        let count = cmp::min(values.len(), MAX_CHANNELS - offset);
+
+        self.dirty |= self.universe.channels[offset..(offset + count)] != values[..count];
+        self.universe.set_range(start, values);
+    }
+
+    /// Sets all channels to `value`.
+    ///
+    /// See `DmxUniverse::fill`.
+    #[inline]
+    pub fn fill(&mut self, value: u8) {
+        self.dirty |= self.universe.channels.iter().any(|&v| v != value);
+        self.universe.fill(value);
+    }
+
+    /// Sets a 16-bit channel to `value`.
+    ///
+    /// See `DmxUniverse::set_channel_16`.
+    #[inline]
+    pub fn set_channel_16(&mut self, channel: Channel16, value: u16) {
+        let [coarse, fine] = value.to_be_bytes();
+        self.set(channel.coarse, coarse);
+        self.set(channel.fine, fine);
+    }
+
+    /// Fades channel `n` linearly from its current value to `target`.
+    ///
+    /// See `DmxUniverse::fade_channel`.
+    #[inline]
+    pub fn fade_channel(&mut self, n: DmxAddress, target: u8, duration: time::Duration) {
+        self.fade_channel_with(n, target, duration, Easing::Linear)
+    }
+
+    /// Fades channel `n` to `target`, following an easing curve.
+    ///
+    /// The universe becomes dirty once the fade changes the channel's value,
+    /// see `tick`.
+    pub fn fade_channel_with(
+        &mut self,
+        n: DmxAddress,
+        target: u8,
+        duration: time::Duration,
+        easing: Easing,
+    ) {
+        let previous = self.universe.get(n);
+        self.universe.fade_channel_with(n, target, duration, easing);
+        self.dirty |= self.universe.get(n) != previous;
+    }
+
+    /// Advances all fades in progress by `dt`.
+    ///
+    /// Marks the universe dirty only if a fade changed a channel value, so
+    /// slow fades do not force every frame to be sent.
+    #[inline]
+    pub fn tick(&mut self, dt: time::Duration) {
+        self.dirty |= self.universe.advance_fades(dt);
+    }
+
+    /// Sets all channels to zero.
+    #[inline]
+    pub fn blackout(&mut self) {
+        self.fill(0);
+    }
+
+    /// Modifies the underlying universe, marking it dirty if any channel
+    /// value changed.
+    pub fn update<F: FnOnce(&mut DmxUniverse)>(&mut self, f: F) {
+        let previous = self.universe.channels;
+        f(&mut self.universe);
+        self.dirty |= self.universe.channels != previous;
+    }
+
+    /// Returns the underlying universe.
+    #[inline]
+    pub fn into_inner(self) -> DmxUniverse {
+        self.universe
+    }
+}
+
+impl From<DmxUniverse> for TrackedUniverse {
+    /// Wraps a universe, marking it dirty.
+    #[inline]
+    fn from(universe: DmxUniverse) -> TrackedUniverse {
+        TrackedUniverse {
+            universe,
+            dirty: true,
+        }
+    }
+}
+
+impl ops::Deref for TrackedUniverse {
+    type Target = DmxUniverse;
+
+    #[inline]
+    fn deref(&self) -> &DmxUniverse {
+        &self.universe
+    }
+}
+
+/// A 16-bit channel.
+///
+/// Fixtures requiring more precision, e.g. for pan and tilt of moving heads,
+/// combine two channels: a *coarse* channel holding the high byte and a
+/// *fine* channel holding the low byte. Usually the fine channel directly
+/// follows the coarse channel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Channel16 {
+    coarse: DmxAddress,
+    fine: DmxAddress,
+}
+
+impl Channel16 {
+    /// Create a 16-bit channel starting at channel `coarse`, with the fine
+    /// channel right after it.
+    ///
+    /// Returns `None` if `coarse` is channel 512, which has no channel after
+    /// it.
+    #[inline]
+    pub fn new(coarse: DmxAddress) -> Option<Channel16> {
+        let fine = coarse.checked_add(1)?;
+
+        Some(Channel16 { coarse, fine })
+    }
+
+    /// Create a 16-bit channel from separate coarse and fine channels.
+    #[inline]
+    pub fn with_fine(coarse: DmxAddress, fine: DmxAddress) -> Channel16 {
+        Channel16 { coarse, fine }
+    }
+
+    /// Returns the coarse channel, holding the high byte.
+    #[inline]
+    pub fn coarse(&self) -> DmxAddress {
+        self.coarse
+    }
+
+    /// Returns the fine channel, holding the low byte.
+    #[inline]
+    pub fn fine(&self) -> DmxAddress {
+        self.fine
+    }
+}
+
+/// Interpolates linearly between two channel values.
+///
+/// `t` is clamped to the range of 0 to 1.
+#[inline]
+pub(crate) fn lerp(from: u8, to: u8, t: f32) -> u8 {
+    let t = t.clamp(0.0, 1.0);
+    let value = f32::from(from) + (f32::from(to) - f32::from(from)) * t;
+
+    // values are never negative, so truncating after adding 0.5 rounds
+    (value + 0.5) as u8
+}
@@ -0,0 +1,298 @@
+//! Minimal XML parsing, for fixture definition files.
+//!
+//! Parses a document into a tree of elements. Processing instructions,
+//! comments and doctype declarations are skipped; namespaces are not
+//! resolved, names are kept as written.
+
+use std::io;
+
+/// An element of a parsed document.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Element {
+    pub(crate) name: String,
+    pub(crate) attributes: Vec<(String, String)>,
+    pub(crate) children: Vec<Element>,
+    pub(crate) text: String,
+}
+
+impl Element {
+    /// Returns the value of an attribute.
+    pub(crate) fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the first child named `name`.
+    pub(crate) fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    /// Returns all children named `name`.
+    pub(crate) fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+}
+
+/// Parses a document, returning its root element.
+///
+/// A leading byte order mark is skipped, as written by some editors.
+pub(crate) fn parse(doc: &str) -> io::Result<Element> {
+    let doc = doc.strip_prefix('\u{feff}').unwrap_or(doc);
+    let mut parser = Parser { doc, pos: 0 };
+
+    parser.skip_misc()?;
+    let root = parser.element()?;
+    parser.skip_misc()?;
+
+    if parser.pos != doc.len() {
+        return Err(invalid("trailing data after root element"));
+    }
+
+    Ok(root)
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+struct Parser<'a> {
+    doc: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.doc[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skips past the next occurrence of `end`.
+    fn skip_past(&mut self, end: &str) -> io::Result<()> {
+        match self.rest().find(end) {
+            Some(n) => {
+                self.pos += n + end.len();
+                Ok(())
+            }
+            None => Err(invalid("unexpected end of document")),
+        }
+    }
+
+    /// Skips whitespace, comments, processing instructions and doctypes.
+    fn skip_misc(&mut self) -> io::Result<()> {
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+
+            if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<!") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> io::Result<&'a str> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || c == '/' || c == '>' || c == '=')
+            .unwrap_or(rest.len());
+
+        if len == 0 {
+            return Err(invalid("expected a name"));
+        }
+
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn element(&mut self) -> io::Result<Element> {
+        if !self.rest().starts_with('<') {
+            return Err(invalid("expected an element"));
+        }
+        self.pos += 1;
+
+        let mut element = Element {
+            name: self.name()?.to_owned(),
+            ..Element::default()
+        };
+
+        // attributes
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            }
+            if rest.starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+
+            let name = self.name()?.to_owned();
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(invalid("expected an attribute value"));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+
+            let quote = match self.rest().chars().next() {
+                Some(q @ '"') | Some(q @ '\'') => q,
+                _ => return Err(invalid("expected a quoted attribute value")),
+            };
+            self.pos += 1;
+            let len = self
+                .rest()
+                .find(quote)
+                .ok_or_else(|| invalid("unexpected end of document"))?;
+            let value = unescape(&self.rest()[..len])?;
+            self.pos += len + 1;
+
+            element.attributes.push((name, value));
+        }
+
+        // content
+        loop {
+            let rest = self.rest();
+
+            if rest.starts_with("</") {
+                self.pos += 2;
+                if self.name()? != element.name {
+                    return Err(invalid("mismatched closing tag"));
+                }
+                self.skip_whitespace();
+                if !self.rest().starts_with('>') {
+                    return Err(invalid("expected end of closing tag"));
+                }
+                self.pos += 1;
+                return Ok(element);
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += 9;
+                let len = self
+                    .rest()
+                    .find("]]>")
+                    .ok_or_else(|| invalid("unexpected end of document"))?;
+                element.text.push_str(&self.rest()[..len]);
+                self.pos += len + 3;
+            } else if rest.starts_with("<!--") || rest.starts_with("<?") {
+                self.skip_misc()?;
+            } else if rest.starts_with('<') {
+                element.children.push(self.element()?);
+            } else if rest.is_empty() {
+                return Err(invalid("unexpected end of document"));
+            } else {
+                let len = rest.find('<').unwrap_or(rest.len());
+                element.text.push_str(&unescape(&rest[..len])?);
+                self.pos += len;
+            }
+        }
+    }
+}
+
+/// Replaces entity and character references.
+fn unescape(s: &str) -> io::Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(n) = rest.find('&') {
+        out.push_str(&rest[..n]);
+        rest = &rest[n + 1..];
+
+        let end = rest.find(';').ok_or_else(|| invalid("unterminated reference"))?;
+        let reference = &rest[..end];
+        rest = &rest[end + 1..];
+
+        let c = match reference {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = if let Some(hex) = reference.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(dec) = reference.strip_prefix('#') {
+                    dec.parse().ok()
+                } else {
+                    None
+                };
+
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| invalid("unknown reference"))?
+            }
+        };
+        out.push(c);
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_elements() {
+        let root = parse(
+            r#"<?xml version="1.0"?>
+            <!DOCTYPE Root>
+            <!-- a comment -->
+            <Root Version="1">
+              <Modes>
+                <Mode Name="A"><Channel Offset="1"/><Channel Offset='2'/></Mode>
+                <!-- between modes -->
+                <Mode Name="B"/>
+              </Modes>
+            </Root>"#,
+        )
+        .unwrap();
+
+        assert_eq!(root.name, "Root");
+        assert_eq!(root.attr("Version"), Some("1"));
+
+        let modes: Vec<_> = root.child("Modes").unwrap().children("Mode").collect();
+        assert_eq!(modes.len(), 2);
+        assert_eq!(modes[1].attr("Name"), Some("B"));
+
+        let offsets: Vec<_> = modes[0].children("Channel").map(|c| c.attr("Offset")).collect();
+        assert_eq!(offsets, [Some("1"), Some("2")]);
+    }
+
+    #[test]
+    fn unescapes_text_and_attributes() {
+        let root = parse(r#"<A B="&lt;&amp;&#x41;&#66;&quot;">x &gt; y<![CDATA[ <raw> ]]></A>"#)
+            .unwrap();
+
+        assert_eq!(root.attr("B"), Some("<&AB\""));
+        assert_eq!(root.text, "x > y <raw> ");
+    }
+
+    #[test]
+    fn skips_a_byte_order_mark() {
+        let root = parse("\u{feff}<?xml version=\"1.0\"?><A/>").unwrap();
+        assert_eq!(root.name, "A");
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        assert!(parse("<A><B></A>").is_err());
+        assert!(parse("<A>").is_err());
+        assert!(parse("<A B=C/>").is_err());
+        assert!(parse("<A/><B/>").is_err());
+        assert!(parse("<A>&unknown;</A>").is_err());
+        assert!(parse("").is_err());
+    }
+}
@@ -0,0 +1,93 @@
+//! GDTF import of archives written by common zip tools.
+
+use std::io;
+
+use dmx::fixture::{Attribute, FixtureProfile};
+use dmx::gdtf;
+
+// the same description, stored, deflated, and deflated with data
+// descriptors after a byte order mark, next to a stored thumbnail
+const STORED: &[u8] = include_bytes!("fixtures/stored.gdtf");
+const DEFLATED: &[u8] = include_bytes!("fixtures/deflated.gdtf");
+const DESCRIPTOR: &[u8] = include_bytes!("fixtures/descriptor.gdtf");
+
+fn check(profile: &FixtureProfile) {
+    assert_eq!(profile.manufacturer, "Generic");
+    assert_eq!(profile.model, "Wash & Beam 19");
+    assert_eq!(profile.modes.len(), 2);
+
+    let basic = profile.mode("Basic").unwrap();
+    assert_eq!(basic.footprint(), 2);
+    assert_eq!(basic.channels[0].attribute, Attribute::Intensity);
+    assert_eq!(basic.channels[1].attribute, Attribute::Red);
+    assert_eq!(basic.channels[1].default, 0xff);
+
+    // channels of the second DMX break are left out
+    let extended = profile.mode("Extended").unwrap();
+    let attributes: Vec<_> = extended.channels.iter().map(|c| c.attribute.clone()).collect();
+    assert_eq!(
+        attributes,
+        [
+            Attribute::Pan,
+            Attribute::PanFine,
+            Attribute::Tilt,
+            Attribute::TiltFine,
+            Attribute::Intensity,
+        ]
+    );
+
+    let defaults: Vec<_> = extended.channels.iter().map(|c| c.default).collect();
+    assert_eq!(defaults, [0x80, 0x00, 0x80, 0x00, 0x00]);
+}
+
+#[test]
+fn reads_stored_entries() {
+    check(&gdtf::from_archive(STORED).unwrap());
+}
+
+#[test]
+fn reads_deflated_entries() {
+    check(&gdtf::from_archive(DEFLATED).unwrap());
+}
+
+#[test]
+fn reads_entries_with_data_descriptors() {
+    check(&gdtf::from_archive(DESCRIPTOR).unwrap());
+}
+
+#[test]
+fn reads_descriptions_with_a_byte_order_mark() {
+    let description = "\u{feff}<?xml version=\"1.0\"?>\
+        <GDTF><FixtureType Name=\"Par\" Manufacturer=\"Generic\"/></GDTF>";
+
+    assert_eq!(gdtf::from_description(description).unwrap().model, "Par");
+}
+
+#[test]
+fn rejects_truncated_archives() {
+    for len in [0, 22, DEFLATED.len() / 2, DEFLATED.len() - 1] {
+        assert!(gdtf::from_archive(&DEFLATED[..len]).is_err());
+    }
+}
+
+#[test]
+fn rejects_archives_without_a_description() {
+    // renames the description in the central directory
+    let mut archive = DEFLATED.to_vec();
+    let central = archive.windows(4).rposition(|w| w == b"PK\x01\x02").unwrap();
+    archive[central + 46] = b'D';
+
+    let e = gdtf::from_archive(&archive).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn rejects_entries_larger_than_declared() {
+    // shrinks the size of the description in the central directory
+    let mut archive = DEFLATED.to_vec();
+    let central = archive.windows(4).rposition(|w| w == b"PK\x01\x02").unwrap();
+    archive[central + 24..central + 28].copy_from_slice(&100u32.to_le_bytes());
+
+    let e = gdtf::from_archive(&archive).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+}
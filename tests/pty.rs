@@ -0,0 +1,130 @@
+//! Frames sent by a `DmxPort`, captured through a pseudo terminal.
+
+#![cfg(unix)]
+
+use std::time::Duration;
+
+use dmx::testing::pty_loopback;
+use dmx::{DmxTiming, DmxTransmitter, StartCode};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+// slack for the capture thread timestamping data late
+const TOLERANCE: Duration = Duration::from_millis(5);
+
+#[test]
+fn frames_start_with_a_break() {
+    let (mut port, mut capture) = pty_loopback().unwrap();
+    port.set_channel_count(4).unwrap();
+
+    port.send_dmx_packet(&[1, 2, 3, 4]).unwrap();
+    port.send_dmx_packet(&[5, 6, 7, 8]).unwrap();
+
+    // break byte, start code, channels, for each frame
+    let data = capture.recv_raw(Duration::from_millis(200));
+    assert_eq!(data, [0, 0, 1, 2, 3, 4, 0, 0, 5, 6, 7, 8]);
+}
+
+#[test]
+fn start_code_follows_the_break() {
+    let (mut port, mut capture) = pty_loopback().unwrap();
+    port.set_channel_count(2).unwrap();
+
+    port.send_dmx_alt_packet(&[0x41, 0x42], StartCode::Text).unwrap();
+    port.send_dmx_packet(&[0xff, 0x80]).unwrap();
+
+    let text = capture.recv_frame(2, TIMEOUT).unwrap();
+    assert_eq!(text.start_code, 0x17);
+    assert_eq!(text.channels, [0x41, 0x42]);
+
+    let dimmers = capture.recv_frame(2, TIMEOUT).unwrap();
+    assert_eq!(dimmers.start_code, 0x00);
+    assert_eq!(dimmers.channels, [0xff, 0x80]);
+}
+
+#[test]
+fn short_packets_are_padded_to_the_channel_count() {
+    let (mut port, mut capture) = pty_loopback().unwrap();
+    port.set_channel_count(24).unwrap();
+
+    port.send_dmx_packet(&[0xff, 0x80, 0x40]).unwrap();
+
+    let frame = capture.recv_frame(24, TIMEOUT).unwrap();
+    assert_eq!(frame.channels[..3], [0xff, 0x80, 0x40]);
+    assert!(frame.channels[3..].iter().all(|&v| v == 0));
+
+    // nothing is sent beyond the channel count
+    assert!(capture.recv_raw(Duration::from_millis(100)).is_empty());
+}
+
+#[test]
+fn long_packets_are_cut_at_the_channel_count() {
+    let (mut port, mut capture) = pty_loopback().unwrap();
+    port.set_channel_count(3).unwrap();
+
+    port.send_dmx_packet(&[1, 2, 3, 4, 5]).unwrap();
+
+    assert_eq!(capture.recv_raw(Duration::from_millis(200)), [0, 0, 1, 2, 3]);
+}
+
+#[test]
+fn frames_are_spaced_by_the_break_to_break_time() {
+    let (mut port, mut capture) = pty_loopback().unwrap();
+    port.set_channel_count(1).unwrap();
+    port.set_timing(DmxTiming {
+        inter_frame_us: 30_000,
+        ..DmxTiming::default()
+    })
+    .unwrap();
+
+    for value in 0..3 {
+        port.send_dmx_packet(&[value]).unwrap();
+    }
+
+    let frames: Vec<_> = (0..3).map(|_| capture.recv_frame(1, TIMEOUT).unwrap()).collect();
+    for pair in frames.windows(2) {
+        let interval = pair[1].timestamp - pair[0].timestamp;
+        assert!(interval + TOLERANCE >= Duration::from_millis(30), "{:?}", interval);
+    }
+    assert_eq!(frames[2].channels, [2]);
+}
+
+#[test]
+fn slots_are_spaced_by_the_mark_time_between_slots() {
+    let (mut port, mut capture) = pty_loopback().unwrap();
+    port.set_channel_count(10).unwrap();
+    port.set_timing(DmxTiming {
+        mtbs_us: 2_000,
+        ..DmxTiming::default()
+    })
+    .unwrap();
+
+    port.send_dmx_packet(&[0xff; 10]).unwrap();
+    port.send_dmx_packet(&[0x80; 10]).unwrap();
+
+    let first = capture.recv_frame(10, TIMEOUT).unwrap();
+    let second = capture.recv_frame(10, TIMEOUT).unwrap();
+    assert_eq!(first.channels, [0xff; 10]);
+    assert_eq!(second.channels, [0x80; 10]);
+
+    // the start code and ten channels leave ten gaps in the first frame
+    let interval = second.timestamp - first.timestamp;
+    assert!(interval + TOLERANCE >= Duration::from_millis(20), "{:?}", interval);
+}
+
+#[test]
+fn break_times_change_with_the_next_break() {
+    let (mut port, mut capture) = pty_loopback().unwrap();
+    port.set_channel_count(2).unwrap();
+
+    port.send_dmx_packet(&[1, 2]).unwrap();
+    port.set_timing(DmxTiming {
+        break_us: 400,
+        ..DmxTiming::default()
+    })
+    .unwrap();
+    assert_eq!(port.timing().break_us, 400);
+    port.send_dmx_packet(&[3, 4]).unwrap();
+
+    assert_eq!(capture.recv_raw(Duration::from_millis(200)), [0, 0, 1, 2, 0, 0, 3, 4]);
+}